@@ -0,0 +1,137 @@
+//! Encrypts and decrypts files under a passphrase, streaming them through
+//! `encryption::aead` so that files of any size fit in a fixed amount of
+//! memory.
+//!
+//!     cargo run --example file_encryption -- seal <passphrase> <input> <output>
+//!     cargo run --example file_encryption -- open <passphrase> <input> <output>
+//!
+//! The stream key is derived from the passphrase with scrypt, under a
+//! random salt written in front of the stream. Without arguments the
+//! example seals and opens a file in the temporary directory
+
+use std::env;
+use std::error;
+use std::fs::{ self, File };
+use std::io::{ self, BufReader, BufWriter, Read, Write };
+use std::process;
+
+use cryptofun::Error;
+use cryptofun::encryption::aead::{ SealingWriter, OpeningReader, KEY_LENGTH };
+use cryptofun::hash::kdf::scrypt;
+use cryptofun::utils::rng::os_rng;
+use rand::Rng;
+
+/// Length in bytes of the scrypt salt in front of the stream
+const SALT_LENGTH: usize = 16;
+
+/// Plaintext bytes in every chunk of the stream
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// scrypt costs, the interactive parameters of the scrypt paper
+const SCRYPT_N: u32 = 1 << 14;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let result = match args.len() {
+        0 => demo(),
+        4 => run(&args[0], &args[1], &args[2], &args[3]),
+        _ => {
+            eprintln!("Usage: file_encryption seal|open <passphrase> <input> <output>");
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+/// Runs one command given on the command line
+fn run(command: &str, passphrase: &str, input: &str, output: &str) -> Result<(), Box<dyn error::Error>> {
+    match command {
+        "seal" => seal_file(passphrase, input, output),
+        "open" => open_file(passphrase, input, output),
+        _ => Err(format!("Unknown command {}, expected seal or open", command).into())
+    }
+}
+
+/// Seals and opens a file in the temporary directory, checking that the
+/// round trip gives back the original
+fn demo() -> Result<(), Box<dyn error::Error>> {
+    let directory = env::temp_dir();
+    let plain = directory.join("cryptofun-example.txt");
+    let sealed = directory.join("cryptofun-example.sealed");
+    let opened = directory.join("cryptofun-example.opened");
+
+    let contents = b"Attack at dawn. ".repeat(10_000);
+    fs::write(&plain, &contents)?;
+
+    seal_file("correct horse battery staple", path(&plain)?, path(&sealed)?)?;
+    open_file("correct horse battery staple", path(&sealed)?, path(&opened)?)?;
+
+    println!("Sealed {} bytes into {} bytes", contents.len(), fs::metadata(&sealed)?.len());
+    assert_eq!(fs::read(&opened)?, contents);
+    println!("Opened {} bytes, matching the original", contents.len());
+
+    if open_file("wrong passphrase", path(&sealed)?, path(&opened)?).is_err() {
+        println!("Opening with the wrong passphrase fails");
+    }
+
+    for file in &[plain, sealed, opened] {
+        let _ = fs::remove_file(file);
+    }
+
+    Ok(())
+}
+
+/// Seals a file under a passphrase
+fn seal_file(passphrase: &str, input: &str, output: &str) -> Result<(), Box<dyn error::Error>> {
+    let mut rng = os_rng()?;
+    let mut salt = [0; SALT_LENGTH];
+    rng.fill_bytes(&mut salt);
+
+    let key = derive_key(passphrase, &salt)?;
+    let mut reader = BufReader::new(File::open(input)?);
+    let mut file = BufWriter::new(File::create(output)?);
+    file.write_all(&salt)?;
+
+    let mut writer = SealingWriter::new(file, &key, CHUNK_SIZE, &mut rng)?;
+    io::copy(&mut reader, &mut writer)?;
+    writer.finish()?.flush()?;
+
+    Ok(())
+}
+
+/// Opens a file sealed by `seal_file`. The output is removed again if
+/// the stream turns out to be forged or truncated
+fn open_file(passphrase: &str, input: &str, output: &str) -> Result<(), Box<dyn error::Error>> {
+    let mut file = BufReader::new(File::open(input)?);
+    let mut salt = [0; SALT_LENGTH];
+    file.read_exact(&mut salt)?;
+
+    let key = derive_key(passphrase, &salt)?;
+    let mut reader = OpeningReader::new(file, &key)?;
+    let mut writer = BufWriter::new(File::create(output)?);
+
+    if let Err(e) = io::copy(&mut reader, &mut writer).and_then(|_| writer.flush()) {
+        drop(writer);
+        let _ = fs::remove_file(output);
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+/// Derives the stream key from a passphrase
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Vec<u8>, Error> {
+    Ok(scrypt(passphrase.as_bytes(), salt, SCRYPT_N, SCRYPT_R, SCRYPT_P, KEY_LENGTH)?)
+}
+
+/// Path as a string, for the file functions
+fn path(path: &std::path::Path) -> Result<&str, Box<dyn error::Error>> {
+    path.to_str().ok_or_else(|| "Temporary directory is not valid UTF-8".into())
+}
@@ -0,0 +1,87 @@
+//! Simulates two parties setting up an authenticated session and talking
+//! over it.
+//!
+//!     cargo run --example handshake
+//!
+//! Alice and Bob each hold a long-term ECDSA key, and each knows the
+//! other's verification key beforehand. Every session runs a fresh X25519
+//! exchange, signed with the long-term keys, and then carries AES-GCM
+//! frames in both directions. The wire is a queue per direction, so the
+//! handshake and the frames can be printed as they pass
+
+use std::collections::VecDeque;
+
+use cryptofun::Error;
+use cryptofun::key_exchange::ecdh::ECDH;
+use cryptofun::session::Session;
+use cryptofun::signature::ecdsa::ECDSA;
+use cryptofun::utils::ecc_curves::ECPSupportedCurves;
+use cryptofun::utils::encoding::bytes_to_hex;
+use cryptofun::utils::rng::os_rng;
+
+fn main() -> Result<(), Error> {
+    let mut rng = os_rng()?;
+
+    // Long-term keys, exchanged out of band
+    let mut alice_signer = ECDSA::new(ECPSupportedCurves::BP256R1, &mut rng)?;
+    let mut bob_signer = ECDSA::new(ECPSupportedCurves::BP256R1, &mut rng)?;
+    let alice_key = alice_signer.public_key()?;
+    let bob_key = bob_signer.public_key()?;
+
+    let mut to_bob = VecDeque::new();
+    let mut to_alice = VecDeque::new();
+
+    // Alice starts the handshake with a fresh exchange key
+    let alice_kex = ECDH::new(ECPSupportedCurves::Curve25519, &mut rng)?;
+    let (pending, message) = Session::initiate(alice_kex, &mut alice_signer, &mut rng)?;
+    println!("Alice -> Bob   handshake, {} bytes", message.len());
+    to_bob.push_back(message);
+
+    // Bob checks it against Alice's key and replies with his own
+    let bob_kex = ECDH::new(ECPSupportedCurves::Curve25519, &mut rng)?;
+    let message = to_bob.pop_front().unwrap();
+    let (mut bob, reply) = Session::respond(bob_kex, &mut bob_signer, &alice_key, &message, &mut rng)?;
+    println!("Bob -> Alice   handshake reply, {} bytes", reply.len());
+    to_alice.push_back(reply);
+
+    // Alice checks the reply against Bob's key
+    let reply = to_alice.pop_front().unwrap();
+    let mut alice = pending.complete(&bob_key, &reply, &mut rng)?;
+    println!("Session established\n");
+
+    for text in &["Hi Bob", "Are we still on for tomorrow?"] {
+        let frame = alice.send(text.as_bytes())?;
+        println!("Alice -> Bob   {}", bytes_to_hex(&frame));
+        to_bob.push_back(frame);
+    }
+
+    while let Some(frame) = to_bob.pop_front() {
+        println!("Bob reads      {}", String::from_utf8_lossy(&bob.recv(&frame)?));
+    }
+
+    let frame = bob.send(b"Yes, see you at noon")?;
+    println!("Bob -> Alice   {}", bytes_to_hex(&frame));
+    to_alice.push_back(frame.clone());
+
+    while let Some(frame) = to_alice.pop_front() {
+        println!("Alice reads    {}", String::from_utf8_lossy(&alice.recv(&frame)?));
+    }
+
+    // A frame played back a second time is refused
+    match alice.recv(&frame) {
+        Err(e) => println!("\nReplayed frame rejected: {}", e),
+        Ok(_) => panic!("A replayed frame was accepted")
+    }
+
+    // As is a frame changed on the way
+    let mut tampered = alice.send(b"Bring the documents")?;
+    let last = tampered.len() - 1;
+    tampered[last] ^= 1;
+
+    match bob.recv(&tampered) {
+        Err(e) => println!("Tampered frame rejected: {}", e),
+        Ok(_) => panic!("A tampered frame was accepted")
+    }
+
+    Ok(())
+}
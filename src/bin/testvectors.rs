@@ -0,0 +1,9 @@
+//! Prints the deterministic test vectors of every enabled primitive as
+//! JSON. Redirect the output to `tests/fixtures/vectors.json` to update
+//! the committed fixture
+
+use cryptofun::testvectors;
+
+fn main() {
+    println!("{}", testvectors::to_json(&testvectors::generate()));
+}
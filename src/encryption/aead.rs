@@ -0,0 +1,747 @@
+//! Streaming authenticated encryption with the STREAM construction of
+//! Hoang, Reyhanitabar, Rogaway and Vizár. The plaintext is cut into
+//! chunks of a fixed size and each chunk is sealed with AES-256-GCM on
+//! its own, so the receiver checks and releases every chunk as it is
+//! read instead of buffering the whole message until one tag verifies.
+//!
+//! A stream starts with a header holding a version, the chunk size and a
+//! random salt. The chunk key is derived from the caller's key and the
+//! whole header with HKDF, so chunks cannot be moved between streams and
+//! the chunk size cannot be changed. Chunk nonces are counters taken from
+//! a `utils::nonce::NonceSequence`, so chunks cannot be reordered, and
+//! the final chunk sets a flag in its nonce, so a stream cut short at a
+//! chunk boundary fails to open. Only the final chunk may be shorter
+//! than the chunk size, and it may be empty.
+//!
+//! `SealingWriter` and `OpeningReader` run a stream over `std::io`
+
+use std::fmt;
+use std::error;
+use std::io::{ self, Read, Write };
+use crypto::aes::KeySize;
+use crypto::aes_gcm::AesGcm;
+use crypto::aead::{ AeadEncryptor, AeadDecryptor };
+
+use crate::hash::kdf::{ hkdf, KdfError };
+use crate::hash::crypto::HashAlgorithm;
+use crate::utils::rng::CryptoRngCore;
+use crate::utils::zeroize::zeroize_bytes;
+use crate::utils::nonce::{ NonceSequence, NonceError, NONCE_LENGTH, PREFIX_LENGTH };
+
+
+/*---- CONSTANTS ----*/
+
+/// Length in bytes of the stream key
+pub const KEY_LENGTH: usize = 32;
+
+/// Length in bytes of the GCM authentication tag on every chunk
+pub const TAG_LENGTH: usize = 16;
+
+/// Length in bytes of the stream header: version, chunk size and salt
+pub const HEADER_LENGTH: usize = 1 + 4 + SALT_LENGTH;
+
+/// Largest number of plaintext bytes in a chunk
+pub const MAX_CHUNK_SIZE: usize = 1 << 24;
+
+/// Version of the stream format, the first byte of the header
+const STREAM_VERSION: u8 = 1;
+
+/// Length in bytes of the random salt ending the header
+const SALT_LENGTH: usize = 32;
+
+/// Prefix of the chunk nonces, which need none since each stream has its own key
+const NONCE_PREFIX: [u8; PREFIX_LENGTH] = [0; PREFIX_LENGTH];
+
+/// Flag on the top bit of the counter in the nonce of the final chunk.
+/// No counter reaches it, as that would take 2^63 chunks
+const LAST_CHUNK_FLAG: u8 = 0x80;
+
+/// HKDF info string binding chunk keys to this scheme, followed by the header
+const KDF_INFO: &'static [u8] = b"cryptofun stream v1";
+
+/// Hash algorithm used by HKDF
+const KDF_HASH_ALGORITHM: HashAlgorithm = HashAlgorithm::Sha3_256;
+
+
+/*---- ENUMS ----*/
+
+/// Errors raised while sealing or opening a stream
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum StreamError {
+    InvalidKey,                 // stream key is not KEY_LENGTH bytes
+    InvalidChunkSize,           // chunk size is zero or above MAX_CHUNK_SIZE
+    MalformedHeader,            // header has the wrong length, version or chunk size
+    MalformedChunk,             // sealed chunk has the wrong length for its place in the stream
+    AuthenticationFailed,       // chunk was tampered with, reordered, taken from another stream or is not the final one
+    Truncated,                  // stream ended before its final chunk
+    Exhausted,                  // no more chunks can be sealed in this stream
+    Kdf(KdfError)               // the chunk key could not be derived
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StreamError::InvalidKey => write!(f, "Stream key must be {} bytes", KEY_LENGTH),
+            StreamError::InvalidChunkSize => write!(f, "Chunk size must be between 1 and {} bytes", MAX_CHUNK_SIZE),
+            StreamError::MalformedHeader => write!(f, "Stream header is malformed"),
+            StreamError::MalformedChunk => write!(f, "Sealed chunk has the wrong length"),
+            StreamError::AuthenticationFailed => write!(f, "Chunk failed authentication"),
+            StreamError::Truncated => write!(f, "Stream ended before its final chunk"),
+            StreamError::Exhausted => write!(f, "Stream has sealed as many chunks as it can"),
+            StreamError::Kdf(e) => write!(f, "Chunk key derivation failed: {}", e)
+        }
+    }
+}
+
+impl error::Error for StreamError {}
+
+impl From<KdfError> for StreamError {
+    fn from(error: KdfError) -> Self {
+        StreamError::Kdf(error)
+    }
+}
+
+impl From<NonceError> for StreamError {
+    fn from(_: NonceError) -> Self {
+        StreamError::Exhausted
+    }
+}
+
+
+/*---- STRUCTS ----*/
+
+/// Seals a stream chunk by chunk. Every chunk but the final one must be
+/// exactly the chunk size
+pub struct StreamSealer {
+    key: Vec<u8>,               // chunk key derived from the stream key and header
+    header: Vec<u8>,            // header to send ahead of the chunks
+    chunk_size: usize,          // plaintext bytes in every chunk but the final one
+    nonces: NonceSequence       // nonces of the chunks sealed so far
+}
+
+/// Opens a stream chunk by chunk, checking each one before returning it.
+/// Once a chunk fails, every later chunk fails as well
+pub struct StreamOpener {
+    key: Vec<u8>,               // chunk key derived from the stream key and header
+    chunk_size: usize,          // plaintext bytes in every chunk but the final one
+    nonces: NonceSequence       // nonces of the chunks opened so far
+}
+
+/// Writer sealing everything written to it into a stream on the inner
+/// writer. A full chunk is held back until more data follows it, so that
+/// `finish` always has a final chunk to seal. A writer dropped without
+/// `finish` leaves a stream that fails to open
+pub struct SealingWriter<W: Write> {
+    inner: W,                   // receiver of the header and sealed chunks
+    sealer: StreamSealer,       // stream being written
+    buffer: Vec<u8>             // plaintext not sealed yet, at most one chunk
+}
+
+/// Reader opening a stream from the inner reader. Only plaintext from
+/// chunks that verified is ever returned
+pub struct OpeningReader<R: Read> {
+    inner: R,                       // source of the sealed chunks
+    opener: Option<StreamOpener>,   // stream being read, until its final chunk is opened
+    sealed: Vec<u8>,                // sealed bytes read ahead of the next chunk
+    plaintext: Vec<u8>,             // plaintext of the last chunk opened
+    position: usize                 // bytes of that plaintext already returned
+}
+
+
+/*---- IMPLEMENTATIONS ----*/
+
+impl StreamSealer {
+
+    /// Starts a stream. Every stream draws a fresh salt, so one key can
+    /// seal many streams
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `key` - Stream key, KEY_LENGTH bytes
+    /// * `chunk_size` - Plaintext bytes in every chunk but the final one
+    /// * `rng` - Random number generator for the salt
+    pub fn new(key: &[u8], chunk_size: usize, rng: &mut impl CryptoRngCore) -> Result<Self, StreamError> {
+        if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+            return Err(StreamError::InvalidChunkSize);
+        }
+
+        let mut salt = [0; SALT_LENGTH];
+        rng.fill_bytes(&mut salt);
+
+        let mut header = Vec::with_capacity(HEADER_LENGTH);
+        header.push(STREAM_VERSION);
+        header.extend_from_slice(&(chunk_size as u32).to_be_bytes());
+        header.extend_from_slice(&salt);
+
+        Ok(StreamSealer {
+            key: chunk_key(key, &header)?,
+            header: header,
+            chunk_size: chunk_size,
+            nonces: NonceSequence::counter(NONCE_PREFIX)
+        })
+    }
+
+    /// Header to send ahead of the chunks
+    pub fn header(&self) -> &[u8] {
+        &self.header
+    }
+
+    /// Plaintext bytes in every chunk but the final one
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Seals a chunk that is not the final one
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `chunk` - Plaintext, exactly the chunk size
+    pub fn seal_chunk(&mut self, chunk: &[u8]) -> Result<Vec<u8>, StreamError> {
+        if chunk.len() != self.chunk_size {
+            return Err(StreamError::MalformedChunk);
+        }
+
+        let nonce = self.nonces.next()?;
+        Ok(seal(&self.key, &nonce, chunk))
+    }
+
+    /// Seals the final chunk, ending the stream
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `chunk` - Plaintext, at most the chunk size and possibly empty
+    pub fn seal_last(mut self, chunk: &[u8]) -> Result<Vec<u8>, StreamError> {
+        if chunk.len() > self.chunk_size {
+            return Err(StreamError::MalformedChunk);
+        }
+
+        let nonce = last_nonce(self.nonces.next()?);
+        Ok(seal(&self.key, &nonce, chunk))
+    }
+
+    /// Wipes the chunk key, after which every chunk sealed is rejected
+    pub fn clear(&mut self) -> () {
+        zeroize_bytes(&mut self.key);
+        self.nonces.exhaust();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for StreamSealer {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl StreamOpener {
+
+    /// Reads a stream header and derives the chunk key from it
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `key` - Stream key, KEY_LENGTH bytes
+    /// * `header` - Header sent ahead of the chunks
+    pub fn new(key: &[u8], header: &[u8]) -> Result<Self, StreamError> {
+        if header.len() != HEADER_LENGTH || header[0] != STREAM_VERSION {
+            return Err(StreamError::MalformedHeader);
+        }
+
+        let chunk_size = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+
+        if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+            return Err(StreamError::MalformedHeader);
+        }
+
+        Ok(StreamOpener {
+            key: chunk_key(key, header)?,
+            chunk_size: chunk_size,
+            nonces: NonceSequence::counter(NONCE_PREFIX)
+        })
+    }
+
+    /// Plaintext bytes in every chunk but the final one
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Length in bytes of every sealed chunk but the final one
+    pub fn sealed_chunk_size(&self) -> usize {
+        self.chunk_size + TAG_LENGTH
+    }
+
+    /// Opens a chunk that is not the final one
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `sealed` - Sealed chunk, exactly the sealed chunk size
+    pub fn open_chunk(&mut self, sealed: &[u8]) -> Result<Vec<u8>, StreamError> {
+        if sealed.len() != self.sealed_chunk_size() {
+            return Err(StreamError::MalformedChunk);
+        }
+
+        let nonce = self.nonces.next()?;
+        open(&self.key, &nonce, sealed)
+    }
+
+    /// Opens the final chunk, ending the stream. A chunk that was sealed
+    /// as any other than the final one fails here
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `sealed` - Sealed chunk, from the tag length up to the sealed chunk size
+    pub fn open_last(mut self, sealed: &[u8]) -> Result<Vec<u8>, StreamError> {
+        if sealed.len() < TAG_LENGTH || sealed.len() > self.sealed_chunk_size() {
+            return Err(StreamError::MalformedChunk);
+        }
+
+        let nonce = last_nonce(self.nonces.next()?);
+        open(&self.key, &nonce, sealed)
+    }
+
+    /// Wipes the chunk key, after which every chunk is rejected
+    pub fn clear(&mut self) -> () {
+        zeroize_bytes(&mut self.key);
+        self.nonces.exhaust();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for StreamOpener {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<W: Write> SealingWriter<W> {
+
+    /// Starts a stream on a writer and writes its header
+    /// 
+    /// ### Example
+    /// 
+    /// ```
+    /// use std::io::{ Read, Write };
+    /// use cryptofun::encryption::aead::{ SealingWriter, OpeningReader, KEY_LENGTH };
+    /// use cryptofun::utils::rng::seeded_rng;
+    /// 
+    /// let key = [7; KEY_LENGTH];
+    /// let mut writer = SealingWriter::new(Vec::new(), &key, 1024, &mut seeded_rng(&[0x5e, 0xa1])).unwrap();
+    /// writer.write_all(&[0x42; 3000]).unwrap();
+    /// let sealed = writer.finish().unwrap();
+    /// 
+    /// let mut opened = Vec::new();
+    /// OpeningReader::new(&sealed[..], &key).unwrap().read_to_end(&mut opened).unwrap();
+    /// 
+    /// assert_eq!(opened, vec![0x42; 3000]);
+    /// ```
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `inner` - Writer to send the stream to
+    /// * `key` - Stream key, KEY_LENGTH bytes
+    /// * `chunk_size` - Plaintext bytes in every chunk but the final one
+    /// * `rng` - Random number generator for the salt
+    pub fn new(mut inner: W, key: &[u8], chunk_size: usize, rng: &mut impl CryptoRngCore) -> io::Result<Self> {
+        let sealer = StreamSealer::new(key, chunk_size, rng).map_err(io_error)?;
+        inner.write_all(sealer.header())?;
+
+        Ok(SealingWriter {
+            inner: inner,
+            sealer: sealer,
+            buffer: Vec::with_capacity(chunk_size)
+        })
+    }
+
+    /// Seals the plaintext held back as the final chunk and hands back
+    /// the inner writer
+    pub fn finish(self) -> io::Result<W> {
+        let SealingWriter { mut inner, sealer, mut buffer } = self;
+        let sealed = sealer.seal_last(&buffer).map_err(io_error);
+        zeroize_bytes(&mut buffer);
+
+        inner.write_all(&sealed?)?;
+        inner.flush()?;
+
+        Ok(inner)
+    }
+}
+
+impl<W: Write> Write for SealingWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let chunk_size = self.sealer.chunk_size();
+
+        // The held chunk is not the final one once more data follows it
+        if self.buffer.len() == chunk_size && !data.is_empty() {
+            let sealed = self.sealer.seal_chunk(&self.buffer).map_err(io_error)?;
+            self.inner.write_all(&sealed)?;
+            zeroize_bytes(&mut self.buffer);
+            self.buffer.clear();
+        }
+
+        let taken = data.len().min(chunk_size - self.buffer.len());
+        self.buffer.extend_from_slice(&data[..taken]);
+
+        Ok(taken)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<R: Read> OpeningReader<R> {
+
+    /// Reads the stream header from a reader
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `inner` - Reader to take the stream from
+    /// * `key` - Stream key, KEY_LENGTH bytes
+    pub fn new(mut inner: R, key: &[u8]) -> io::Result<Self> {
+        let mut header = Vec::with_capacity(HEADER_LENGTH);
+        (&mut inner).take(HEADER_LENGTH as u64).read_to_end(&mut header)?;
+
+        let opener = StreamOpener::new(key, &header).map_err(io_error)?;
+
+        Ok(OpeningReader {
+            inner: inner,
+            opener: Some(opener),
+            sealed: Vec::new(),
+            plaintext: Vec::new(),
+            position: 0
+        })
+    }
+
+    /// Opens the next chunk. A chunk of the full sealed size is only known
+    /// not to be the final one once a byte after it has been read, so one
+    /// byte more than a chunk is read ahead
+    fn next_chunk(&mut self) -> io::Result<()> {
+        let sealed_size = match self.opener {
+            Some(ref opener) => opener.sealed_chunk_size(),
+            None => return Ok(())
+        };
+
+        let wanted = (sealed_size + 1 - self.sealed.len()) as u64;
+        (&mut self.inner).take(wanted).read_to_end(&mut self.sealed)?;
+
+        zeroize_bytes(&mut self.plaintext);
+        self.position = 0;
+
+        let opened = if self.sealed.len() > sealed_size {
+            let opened = self.opener.as_mut().unwrap().open_chunk(&self.sealed[..sealed_size]);
+            self.sealed.drain(..sealed_size);
+            opened
+        } else if self.sealed.is_empty() {
+            Err(StreamError::Truncated)
+        } else {
+            let opened = self.opener.take().unwrap().open_last(&self.sealed);
+            self.sealed.clear();
+            opened
+        };
+
+        self.plaintext = opened.map_err(io_error)?;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for OpeningReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.plaintext.len() && self.opener.is_some() {
+            self.next_chunk()?;
+        }
+
+        let count = buf.len().min(self.plaintext.len() - self.position);
+        buf[..count].copy_from_slice(&self.plaintext[self.position..self.position + count]);
+        self.position += count;
+
+        Ok(count)
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Derives the chunk key of a stream from the stream key and its header,
+/// with the salt in the header as the HKDF salt
+/// 
+/// ### Arguments
+/// 
+/// * `key` - Stream key
+/// * `header` - Header of the stream
+fn chunk_key(key: &[u8], header: &[u8]) -> Result<Vec<u8>, StreamError> {
+    if key.len() != KEY_LENGTH {
+        return Err(StreamError::InvalidKey);
+    }
+
+    let mut info = KDF_INFO.to_vec();
+    info.extend_from_slice(header);
+
+    Ok(hkdf(key, &header[HEADER_LENGTH - SALT_LENGTH..], &info, KEY_LENGTH, KDF_HASH_ALGORITHM)?)
+}
+
+/// Nonce of the final chunk, with the flag set on its counter
+/// 
+/// ### Arguments
+/// 
+/// * `nonce` - Next nonce of the stream
+fn last_nonce(mut nonce: [u8; NONCE_LENGTH]) -> [u8; NONCE_LENGTH] {
+    nonce[PREFIX_LENGTH] |= LAST_CHUNK_FLAG;
+    nonce
+}
+
+/// Seals a chunk into ciphertext || tag
+/// 
+/// ### Arguments
+/// 
+/// * `key` - Chunk key
+/// * `nonce` - Nonce of the chunk
+/// * `chunk` - Plaintext of the chunk
+fn seal(key: &[u8], nonce: &[u8; NONCE_LENGTH], chunk: &[u8]) -> Vec<u8> {
+    let mut sealed = vec![0; chunk.len() + TAG_LENGTH];
+    let mut tag = [0; TAG_LENGTH];
+
+    let mut encryptor = AesGcm::new(KeySize::KeySize256, key, nonce, &[]);
+    encryptor.encrypt(chunk, &mut sealed[..chunk.len()], &mut tag);
+    sealed[chunk.len()..].copy_from_slice(&tag);
+
+    sealed
+}
+
+/// Opens a chunk sealed by `seal`
+/// 
+/// ### Arguments
+/// 
+/// * `key` - Chunk key
+/// * `nonce` - Nonce the chunk should have been sealed under
+/// * `sealed` - Sealed chunk
+fn open(key: &[u8], nonce: &[u8; NONCE_LENGTH], sealed: &[u8]) -> Result<Vec<u8>, StreamError> {
+    let (ciphertext, tag) = sealed.split_at(sealed.len() - TAG_LENGTH);
+    let mut plaintext = vec![0; ciphertext.len()];
+
+    let mut decryptor = AesGcm::new(KeySize::KeySize256, key, nonce, &[]);
+
+    if !decryptor.decrypt(ciphertext, &mut plaintext, tag) {
+        zeroize_bytes(&mut plaintext);
+        return Err(StreamError::AuthenticationFailed);
+    }
+
+    Ok(plaintext)
+}
+
+/// Wraps a stream error for the `std::io` adapters
+/// 
+/// ### Arguments
+/// 
+/// * `error` - Error raised by the stream
+fn io_error(error: StreamError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod aead_test {
+
+    use std::io::{ self, Read, Write };
+    use std::sync::mpsc::{ sync_channel, Receiver, SyncSender };
+    use std::thread;
+    use rand::{ Rng, ChaChaRng, SeedableRng };
+    use crate::encryption::aead::{ StreamSealer, StreamOpener, SealingWriter, OpeningReader, StreamError, HEADER_LENGTH, TAG_LENGTH };
+
+    /// Plaintext made up on the fly, so a long stream is never held in memory
+    struct Pattern {
+        position: u64,      // bytes produced so far
+        length: u64         // bytes to produce in total
+    }
+
+    /// Writing end of a pipe between two threads
+    struct PipeWriter(SyncSender<Vec<u8>>);
+
+    /// Reading end of a pipe between two threads
+    struct PipeReader {
+        receiver: Receiver<Vec<u8>>,    // writes sent down the pipe
+        pending: Vec<u8>,               // write being read
+        position: usize                 // bytes of it already read
+    }
+
+    impl Read for Pattern {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let count = (buf.len() as u64).min(self.length - self.position) as usize;
+
+            for (i, byte) in buf[..count].iter_mut().enumerate() {
+                let position = self.position + i as u64;
+                *byte = (position ^ (position >> 8) ^ (position >> 19)) as u8;
+            }
+
+            self.position += count as u64;
+            Ok(count)
+        }
+    }
+
+    impl Write for PipeWriter {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.0.send(data.to_vec()).map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Read for PipeReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.position == self.pending.len() {
+                match self.receiver.recv() {
+                    Ok(next) => {
+                        self.pending = next;
+                        self.position = 0;
+                    },
+                    Err(_) => return Ok(0)
+                }
+            }
+
+            let count = buf.len().min(self.pending.len() - self.position);
+            buf[..count].copy_from_slice(&self.pending[self.position..self.position + count]);
+            self.position += count;
+
+            Ok(count)
+        }
+    }
+
+    fn stream_error(error: io::Error) -> StreamError {
+        error.get_ref().and_then(|inner| inner.downcast_ref::<StreamError>()).unwrap().clone()
+    }
+
+    fn sealed_stream(key: &[u8], chunks: &[&[u8]], last: &[u8], rng: &mut ChaChaRng) -> (Vec<u8>, Vec<Vec<u8>>) {
+        let mut sealer = StreamSealer::new(key, 4, rng).unwrap();
+        let header = sealer.header().to_vec();
+        let mut sealed: Vec<Vec<u8>> = chunks.iter().map(|chunk| sealer.seal_chunk(chunk).unwrap()).collect();
+        sealed.push(sealer.seal_last(last).unwrap());
+
+        (header, sealed)
+    }
+
+    fn read_stream(key: &[u8], stream: &[u8]) -> Result<Vec<u8>, StreamError> {
+        let mut reader = OpeningReader::new(stream, key).map_err(stream_error)?;
+        let mut plaintext = Vec::new();
+        reader.read_to_end(&mut plaintext).map_err(stream_error)?;
+
+        Ok(plaintext)
+    }
+
+    #[test]
+    fn large_stream_round_trips_in_constant_memory() {
+        const LENGTH: u64 = 50 * 1024 * 1024;
+        let key = [0x42; 32];
+
+        // The pipe holds at most four writes, so neither side can buffer the stream
+        let (sender, receiver) = sync_channel(4);
+
+        let sealing = thread::spawn(move || {
+            let mut rng = ChaChaRng::from_seed(&[0x73, 0x74]);
+            let mut writer = SealingWriter::new(PipeWriter(sender), &key, 64 * 1024, &mut rng).unwrap();
+
+            io::copy(&mut Pattern { position: 0, length: LENGTH }, &mut writer).unwrap();
+            writer.finish().unwrap();
+        });
+
+        let pipe = PipeReader { receiver: receiver, pending: Vec::new(), position: 0 };
+        let mut reader = OpeningReader::new(pipe, &key).unwrap();
+        let mut expected = Pattern { position: 0, length: LENGTH };
+        let mut opened = [0; 8192];
+        let mut wanted = [0; 8192];
+        let mut total = 0;
+
+        loop {
+            let count = reader.read(&mut opened).unwrap();
+
+            if count == 0 {
+                break;
+            }
+
+            expected.read_exact(&mut wanted[..count]).unwrap();
+            assert_eq!(&opened[..count], &wanted[..count]);
+            total += count as u64;
+        }
+
+        sealing.join().unwrap();
+        assert_eq!(total, LENGTH);
+    }
+
+    #[test]
+    fn swapped_chunks_are_rejected() {
+        let mut rng = ChaChaRng::from_seed(&[0x73, 0x77]);
+        let key = [7; 32];
+        let (header, sealed) = sealed_stream(&key, &[b"one ", b"two "], b"end", &mut rng);
+
+        let mut stream = header.clone();
+        for chunk in &sealed {
+            stream.extend_from_slice(chunk);
+        }
+        assert_eq!(read_stream(&key, &stream), Ok(b"one two end".to_vec()));
+
+        // Reordered within the stream
+        let mut swapped = header.clone();
+        for index in &[1, 0, 2] {
+            swapped.extend_from_slice(&sealed[*index]);
+        }
+        assert_eq!(read_stream(&key, &swapped), Err(StreamError::AuthenticationFailed));
+
+        // Substituted from another stream under the same key
+        let (_, other) = sealed_stream(&key, &[b"one ", b"two "], b"end", &mut rng);
+        let mut opener = StreamOpener::new(&key, &header).unwrap();
+        assert_eq!(opener.open_chunk(&sealed[0]), Ok(b"one ".to_vec()));
+        assert_eq!(opener.open_chunk(&other[1]), Err(StreamError::AuthenticationFailed));
+
+        // A header claiming another chunk size derives another key
+        let mut resized = stream.clone();
+        resized[4] = 3;
+        assert!(read_stream(&key, &resized).is_err());
+    }
+
+    #[test]
+    fn truncated_streams_are_rejected() {
+        let mut rng = ChaChaRng::from_seed(&[0x74, 0x72]);
+        let key = [9; 32];
+        let (header, sealed) = sealed_stream(&key, &[b"one ", b"two ", b"six "], b"", &mut rng);
+
+        // Cut at a chunk boundary, the last full chunk is read as the final one
+        let mut truncated = header.clone();
+        for chunk in &sealed[..2] {
+            truncated.extend_from_slice(chunk);
+        }
+        assert_eq!(read_stream(&key, &truncated), Err(StreamError::AuthenticationFailed));
+
+        // Cut inside a chunk
+        truncated.extend_from_slice(&sealed[2][..TAG_LENGTH]);
+        assert_eq!(read_stream(&key, &truncated), Err(StreamError::AuthenticationFailed));
+
+        // Cut before any chunk, or inside the header
+        assert_eq!(read_stream(&key, &header), Err(StreamError::Truncated));
+        assert_eq!(read_stream(&key, &header[..HEADER_LENGTH - 1]), Err(StreamError::MalformedHeader));
+
+        // Nor can a middle chunk be opened as the final one
+        let mut opener = StreamOpener::new(&key, &header).unwrap();
+        for chunk in &sealed[..2] {
+            opener.open_chunk(chunk).unwrap();
+        }
+        assert_eq!(opener.open_last(&sealed[2]), Err(StreamError::AuthenticationFailed));
+    }
+
+    #[test]
+    fn empty_plaintext_round_trips() {
+        let mut rng = ChaChaRng::from_seed(&[0x65, 0x6d]);
+        let mut key = [0; 32];
+        rng.fill_bytes(&mut key);
+
+        let writer = SealingWriter::new(Vec::new(), &key, 1024, &mut rng).unwrap();
+        let stream = writer.finish().unwrap();
+
+        assert_eq!(stream.len(), HEADER_LENGTH + TAG_LENGTH);
+        assert_eq!(read_stream(&key, &stream), Ok(Vec::new()));
+        assert_eq!(read_stream(&[1; 32], &stream), Err(StreamError::AuthenticationFailed));
+        assert_eq!(StreamSealer::new(&key, 0, &mut rng).err(), Some(StreamError::InvalidChunkSize));
+        assert_eq!(StreamSealer::new(&key[..16], 1024, &mut rng).err(), Some(StreamError::InvalidKey));
+    }
+}
\ No newline at end of file
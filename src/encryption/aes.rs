@@ -1,32 +1,207 @@
-use utils::primes;
-use rand::{ Rng, OsRng };
+use std::cmp;
+use std::fmt;
+use std::mem;
+use std::panic;
+use std::thread;
+
+use crate::utils::ct;
+use crate::hash::hmac::hmac;
+use crate::hash::kdf::hkdf;
+#[cfg(feature = "hashes-sha2")]
+use crate::hash::kdf::pbkdf2;
+use crate::hash::crypto::HashAlgorithm;
+use crate::encryption::{ CipherError, SymmetricEncryptor };
+use crate::utils::rng::{ CryptoRngCore, os_rng };
+use crate::utils::zeroize::{ zeroize_bytes, Zeroizing };
+use crate::utils::nonce::{ NonceSequence, NONCE_LENGTH };
+use crate::utils::gf::{ gf128_double, gf128_mul };
+use crate::utils::transform::{ chunk_for_threads, pkcs7_pad, pkcs7_unpad };
 use crypto::aes_gcm::AesGcm;
-use crypto::{ aes, symmetriccipher };
+use crypto::aes;
+use crypto::aessafe::{ AesSafe128Encryptor, AesSafe192Encryptor, AesSafe256Encryptor };
+use crypto::aessafe::{ AesSafe128Decryptor, AesSafe192Decryptor, AesSafe256Decryptor };
+use crypto::symmetriccipher::{ BlockEncryptor, BlockDecryptor };
 use crypto::aead::{ AeadEncryptor, AeadDecryptor };
-use crypto::symmetriccipher::{ Encryptor, Decryptor };
-use crypto::buffer::{ RefWriteBuffer, RefReadBuffer, WriteBuffer, ReadBuffer, BufferResult };
+use crypto::symmetriccipher::SynchronousStreamCipher;
+
+pub use crypto::aes::KeySize;
 
-/// AAD is an identifier value and is used in GCM mode only, thus
-/// necessitating an Option type.
+pub mod stream;
+
+/// AAD is an identifier value used by the authenticated modes. SIV
+/// takes a list of associated data components, each authenticated as a
+/// separate string, while GCM and CCM take at most one.
 /// 
 /// The "history_bytes" value is used to keep track of the number 
-/// of bytes that have been encrypted with the current key. 
+/// of bytes that have been encrypted with the current key. Once that
+/// or the number of messages reaches the `KeyBudget` for the mode,
+/// `encrypt` refuses with `KeyExhausted` until `rekey` is called.
+/// 
+/// GCM and CCM draw a fresh nonce for every message from a random
+/// `NonceSequence` and send it in front of the ciphertext, so the
+/// initialization vector is only used in Counter and CBC modes. SIV
+/// needs no nonce, deriving its IV from the key, AAD and message.
+/// 
+/// In Counter mode the low `counter_bits` of the IV count blocks and
+/// the rest is a fixed nonce. Every message starts its keystream at the
+/// IV, so only one message, or one stream from `ctr_stream`, may be
+/// encrypted under a key and IV; later ones are refused with
+/// `KeyExhausted` until `rekey`, as is a message that would run the
+/// counter past the top of its field, wrapping it or carrying into the
+/// nonce.
+/// 
+/// CBC mode chains every message from the IV as well, so messages that
+/// start alike would encrypt alike, and it too takes one message per
+/// key and IV, refusing later ones with `KeyExhausted` until `rekey`.
+/// 
+/// The key and IV are wiped when the instance is dropped, and left out
+/// of its Debug output
 pub struct AES {
     mode: AESMode,
-    pub key: Vec<u8>,
+    key: Zeroizing<Vec<u8>>,
     key_size: aes::KeySize,
-    aad: Option<Vec<u8>>,
-    pub initialization_vector: Vec<u8>,
-    history_bytes: usize // number of bytes encrypted with one key
+    aad: Vec<Vec<u8>>,
+    initialization_vector: Zeroizing<Vec<u8>>,
+    nonces: NonceSequence, // nonces of the GCM and CCM messages sealed under the key
+    budget: KeyBudget, // limits on the use of one key
+    history_bytes: usize, // number of bytes encrypted with one key
+    history_messages: u64, // number of messages encrypted with one key
+    counter_bits: usize, // low bits of the Counter mode IV that count blocks
+    counter_blocks: u128 // keystream blocks used under the Counter mode key and IV
+}
+
+/// Limits on how much may be encrypted under one key. Decryption doesn't
+/// count towards them
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct KeyBudget {
+    pub bytes: u64,     // plaintext bytes
+    pub messages: u64   // messages
 }
 
+#[derive(Debug)]
 pub enum AESMode {
     Counter,
-    GCM
+    GCM,
+    CBC,
+    CCM {
+        tag_len: usize,     // tag length in bytes, even and from 4 to 16
+        nonce_len: usize    // nonce length in bytes, from 7 to 13
+    },
+    SIV
+}
+
+/// Errors raised by AES operations, those of every symmetric cipher
+pub type AesError = CipherError;
+
+/// Incremental CMAC (NIST SP 800-38B) over AES. The last block is held
+/// back until `finalize`, since it is masked differently depending on
+/// whether it is complete
+pub struct Cmac {
+    cipher: Box<dyn BlockEncryptor>,    // AES under the MAC key
+    k1: [u8; CMAC_BLOCK_SIZE],          // subkey for a complete last block
+    k2: [u8; CMAC_BLOCK_SIZE],          // subkey for a padded last block
+    state: [u8; CMAC_BLOCK_SIZE],       // CBC-MAC chaining value
+    buffer: [u8; CMAC_BLOCK_SIZE],      // bytes not yet chained
+    buffered: usize                     // number of bytes in the buffer
+}
+
+/// Counter mode keystream that carries on across calls, so that a long
+/// message can be encrypted or decrypted a piece at a time. The pieces
+/// of output join up to exactly what one call over the whole message
+/// gives
+pub struct AesCtrStream {
+    cipher: Box<dyn SynchronousStreamCipher>    // AES-CTR from the instance key and IV
+}
+
+/// Counter mode composed with HMAC, encrypt-then-MAC. Separate encryption
+/// and MAC keys are derived from the instance key with HKDF. Every message
+/// is encrypted under a fresh IV, the 12 byte nonce from a random
+/// `NonceSequence` followed by a 32 bit block counter, and sealed as
+/// IV || ciphertext || HMAC(IV || ciphertext)
+pub struct AuthenticatedCtr {
+    key_size: aes::KeySize,     // size of the encryption key
+    encryption_key: Vec<u8>,    // AES-CTR key
+    mac_key: Vec<u8>,           // HMAC key
+    algorithm: HashAlgorithm,   // hash underneath HKDF and HMAC
+    nonces: NonceSequence       // nonces of the IVs used under the key
 }
 
+
+/*---- CONSTANTS ----*/
+
+/// Block and tag size of CMAC, in bytes
+pub const CMAC_BLOCK_SIZE: usize = 16;
+
+/// Length in bytes of the per-message IV of `AuthenticatedCtr`
+pub const AUTHENTICATED_CTR_IV_SIZE: usize = 16;
+
+/// HKDF info for the encryption and MAC keys of `AuthenticatedCtr`
+const AUTHENTICATED_CTR_INFO: &'static [u8] = b"cryptofun aes-ctr-hmac keys";
+
+/// Length in bytes of a GCM nonce, the 96 bits GCM is defined for
+pub const GCM_NONCE_SIZE: usize = NONCE_LENGTH;
+
+/// Length in bytes of the GCM authentication tag
+pub const GCM_TAG_SIZE: usize = 16;
+
+/// Blocks one GCM message may hold, its 32 bit counter starting at 2
+const GCM_MAX_BLOCKS: u64 = (1 << 32) - 2;
+
+/// Length in bytes of the semiblocks key wrap works on, and of the
+/// integrity check it adds
+pub const KEY_WRAP_BLOCK_SIZE: usize = 8;
+
+/// Default initial value of RFC 3394 2.2.3.1, checked again on unwrap
+const KEY_WRAP_IV: [u8; KEY_WRAP_BLOCK_SIZE] = [0xa6; KEY_WRAP_BLOCK_SIZE];
+
+/// Hash underneath PBKDF2 when deriving keys from passwords
+#[cfg(feature = "hashes-sha2")]
+const PASSWORD_HASH_ALGORITHM: HashAlgorithm = HashAlgorithm::Sha256;
+
+/// Length in bytes of the counter block of Counter mode
+const CTR_BLOCK_SIZE: usize = 16;
+
+/// Bits of the Counter mode IV that count blocks by default, the whole block
+const DEFAULT_COUNTER_BITS: usize = 128;
+
+/// Bits of the `AuthenticatedCtr` IV that count blocks, those after the nonce
+const AUTHENTICATED_CTR_COUNTER_BITS: usize = 32;
+
+/// Bytes run through a stream cipher at a time when working in place,
+/// rust-crypto wanting separate input and output
+const IN_PLACE_CHUNK_SIZE: usize = 512;
+
+/// Blocks to encrypt under one key by default, NIST's 2^32 block guidance
+const DEFAULT_BUDGET_BLOCKS: u64 = 1 << 32;
+
+/// Messages to encrypt under one key in the modes with random nonces,
+/// the 2^32 invocations SP 800-38D allows for GCM
+const RANDOM_NONCE_MESSAGES: u64 = 1 << 32;
+
+
 /*---- IMPLEMENTATIONS ----*/
 
+impl KeyBudget {
+
+    /// Default budget of a mode: 2^32 blocks in every mode, and 2^32
+    /// messages in GCM and CCM, whose nonces are drawn at random
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `mode` - AES block mode
+    pub fn for_mode(mode: &AESMode) -> Self {
+        let messages = match *mode {
+            AESMode::GCM | AESMode::CCM { .. } => RANDOM_NONCE_MESSAGES,
+            _ => u64::max_value()
+        };
+
+        KeyBudget {
+            bytes: DEFAULT_BUDGET_BLOCKS * CMAC_BLOCK_SIZE as u64,
+            messages: messages
+        }
+    }
+}
+
 impl AES {
 
     /// AES symmetric block cipher. This implementation is a module
@@ -35,156 +210,2574 @@ impl AES {
     /// 
     /// TODO: Inspect the TLS AES source code and see whether it improves
     /// 
+    /// ### Example
+    /// 
+    /// ```
+    /// use cryptofun::encryption::aes::{ AES, AESMode, KeySize };
+    /// use cryptofun::utils::rng::seeded_rng;
+    /// 
+    /// let mut rng = seeded_rng(&[0xae, 0x5]);
+    /// let mut aes = AES::new(KeySize::KeySize256, AESMode::Counter, None, &mut rng);
+    /// let ciphertext = aes.encrypt(b"attack at dawn").unwrap();
+    /// 
+    /// assert!(ciphertext != b"attack at dawn".to_vec());
+    /// assert_eq!(aes.decrypt(&ciphertext).unwrap(), b"attack at dawn".to_vec());
+    /// ```
+    /// 
     /// ### Arguments
     /// 
     /// * `key_size` - Cipher key size
-    /// * `mode` - AES block mode, either Counter or GCM
-    /// * `gcm_aad` - AAD for GCM mode. None for Counter
-    pub fn new(key_size: aes::KeySize, mode: AESMode, gcm_aad: Option<Vec<u8>>) -> Self {
-        let mut rng = OsRng::new().unwrap();
-        let mut key = primes::generate_random_biguint(&mut rng, &256).to_bytes_le();
-        let mut iv = primes::generate_random_biguint(&mut rng, &128).to_bytes_le();
+    /// * `mode` - AES block mode, Counter, GCM or CBC
+    /// * `gcm_aad` - AAD for GCM, CCM and SIV modes, or None. See `with_aad_components` for SIV
+    /// * `rng` - Random number generator for the key and IV
+    pub fn new(key_size: aes::KeySize, mode: AESMode, gcm_aad: Option<Vec<u8>>, rng: &mut impl CryptoRngCore) -> Self {
+        let mut key = vec![0; key_length(key_size, &mode)];
+        let mut iv = vec![0; iv_length(&mode)];
+        rng.fill_bytes(&mut key);
+        rng.fill_bytes(&mut iv);
 
         AES {
+            budget: KeyBudget::for_mode(&mode),
+            mode: mode,
+            key: Zeroizing::new(key),
+            history_bytes: 0,
+            history_messages: 0,
+            counter_bits: DEFAULT_COUNTER_BITS,
+            counter_blocks: 0,
+            key_size: key_size,
+            aad: gcm_aad.into_iter().collect(),
+            initialization_vector: Zeroizing::new(iv),
+            nonces: NonceSequence::random(rng)
+        }
+    }
+
+    /// AES instance under a key and IV supplied by the caller, such as a
+    /// key derived from a key exchange or one kept from an earlier
+    /// instance. The key must be as long as `key_size` asks for, twice
+    /// that for SIV, and the IV a full block for Counter and CBC modes or
+    /// empty for the others, or `InvalidLength` is returned. GCM and CCM
+    /// nonces are drawn from a sequence seeded from the OS generator
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `key` - Cipher key
+    /// * `iv` - Initialization vector, empty for GCM
+    /// * `key_size` - Cipher key size
+    /// * `mode` - AES block mode, Counter, GCM or CBC
+    /// * `gcm_aad` - AAD for GCM, CCM and SIV modes, or None. See `with_aad_components` for SIV
+    pub fn new_with_key(key: Vec<u8>, iv: Vec<u8>, key_size: aes::KeySize, mode: AESMode, gcm_aad: Option<Vec<u8>>) -> Result<Self, AesError> {
+        if key.len() != key_length(key_size, &mode) || iv.len() != iv_length(&mode) {
+            return Err(AesError::InvalidLength);
+        }
+
+        let mut rng = os_rng().map_err(|_| AesError::InvalidLength)?;
+
+        Ok(AES {
+            budget: KeyBudget::for_mode(&mode),
             mode: mode,
-            key: key,
+            key: Zeroizing::new(key),
             history_bytes: 0,
+            history_messages: 0,
+            counter_bits: DEFAULT_COUNTER_BITS,
+            counter_blocks: 0,
             key_size: key_size,
-            aad: gcm_aad,
-            initialization_vector: iv
+            aad: gcm_aad.into_iter().collect(),
+            initialization_vector: Zeroizing::new(iv),
+            nonces: NonceSequence::random(&mut rng)
+        })
+    }
+
+    /// AES instance under a key derived from a password with PBKDF2 over
+    /// HMAC-SHA256, rather than a key taken straight from a hash of it.
+    /// The IV of Counter and CBC modes is derived along with the key, so
+    /// the same password and salt give the same instance; the salt should
+    /// be random and stored with the ciphertext. Zero iterations are
+    /// refused with `InvalidLength`
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `password` - Password to derive the key from
+    /// * `salt` - Salt, unique per password
+    /// * `iterations` - PBKDF2 iteration count, the work factor
+    /// * `key_size` - Cipher key size
+    /// * `mode` - AES block mode
+    #[cfg(feature = "hashes-sha2")]
+    pub fn from_password(password: &[u8], salt: &[u8], iterations: u32, key_size: aes::KeySize, mode: AESMode) -> Result<Self, AesError> {
+        let key_len = key_length(key_size, &mode);
+        let mut key = pbkdf2(password, salt, iterations, key_len + iv_length(&mode), PASSWORD_HASH_ALGORITHM)
+            .map_err(|_| AesError::InvalidLength)?;
+        let iv = key.split_off(key_len);
+
+        AES::new_with_key(key, iv, key_size, mode, None)
+    }
+
+    /// Replaces the associated data with a list of components, which SIV
+    /// authenticates as separate strings. GCM and CCM take only one, and
+    /// refuse to encrypt or decrypt under more with `InvalidLength`
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `components` - Associated data components, at most 126
+    pub fn with_aad_components(mut self, components: Vec<Vec<u8>>) -> Self {
+        self.aad = components;
+        self
+    }
+
+    /// Replaces the default budget of the mode
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `budget` - Limits on the bytes and messages encrypted under one key
+    pub fn with_budget(mut self, budget: KeyBudget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Sets how the Counter mode IV splits into a fixed nonce and a block
+    /// counter, the counter taking the low bits. With a 64-bit nonce
+    /// prefix, for example, the counter has 64 bits. Widths of 0 or above
+    /// 128 are refused with `InvalidLength`. A stream from `ctr_stream`
+    /// takes the whole field, but isn't stopped at its end
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `bits` - Width of the block counter in bits, from 1 to 128
+    pub fn with_counter_bits(mut self, bits: usize) -> Result<Self, AesError> {
+        if bits == 0 || bits > 128 {
+            return Err(AesError::InvalidLength);
         }
+
+        self.counter_bits = bits;
+        Ok(self)
+    }
+
+    /// Replaces the key and IV with fresh random ones, and the GCM and
+    /// CCM nonces with a new sequence, starting the budget over. The old
+    /// key is wiped, so ciphertext under it can no longer be decrypted
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `rng` - Random number generator for the key, IV and nonces
+    pub fn rekey(&mut self, rng: &mut impl CryptoRngCore) -> () {
+        self.key = Zeroizing::new(vec![0; key_length(self.key_size, &self.mode)]);
+        self.initialization_vector = Zeroizing::new(vec![0; iv_length(&self.mode)]);
+        rng.fill_bytes(&mut self.key);
+        rng.fill_bytes(&mut self.initialization_vector);
+
+        self.nonces = NonceSequence::random(rng);
+        self.history_bytes = 0;
+        self.history_messages = 0;
+        self.counter_blocks = 0;
+    }
+
+    /// Keystream blocks used under the key and IV in Counter mode since
+    /// the last `rekey`. A stream from `ctr_stream` counts as the whole
+    /// counter field
+    pub fn counter_blocks(&self) -> u128 {
+        self.counter_blocks
+    }
+
+    /// Cipher key, empty once cleared
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// Initialization vector of Counter and CBC modes, empty for the others
+    pub fn initialization_vector(&self) -> &[u8] {
+        &self.initialization_vector
     }
 
-    /// Encrypts a block of data provided and returns the ciphertext
+    /// Encrypts a block of data provided and returns the ciphertext. In
+    /// GCM mode the output is nonce || ciphertext || tag under a nonce of
+    /// its own, and once the 2^32 random nonces NIST allows for one key
+    /// are used up, `KeyExhausted` is returned until the key is replaced.
+    /// In CBC mode the data is padded with PKCS#7, always adding at least
+    /// one byte, so the output is up to a block longer. SIV output is the
+    /// synthetic IV || ciphertext, the same every time for the same data.
+    /// A message that would take the key past its budget is refused with
+    /// `KeyExhausted`, and a GCM message longer than its counter can run
+    /// with `InvalidLength`
     /// 
     /// ### Arguments
     ///  
     /// * `data` - Data to encrypt
-    pub fn encrypt(&mut self, data: &[u8]) -> Result<Vec<u8>, symmetriccipher::SymmetricCipherError> {
+    pub fn encrypt(&mut self, data: &[u8]) -> Result<Vec<u8>, AesError> {
+        self.check_budget(data.len())?;
+
         match self.mode {
             AESMode::GCM => {
-                let mut output_buffer = self.fill_vec_to_length(data.len());
-                let mut tag = [0; 16];
-                let aad = self.aad.clone().unwrap();
+                check_gcm_length(data.len())?;
 
-                let mut encryptor = AesGcm::new(self.key_size, &[0; 32], &[0; 12], &aad);
-                encryptor.encrypt(data, &mut output_buffer, &mut tag);
+                let nonce = self.next_nonce(GCM_NONCE_SIZE)?;
+                let mut output_buffer = self.fill_vec_to_length(GCM_NONCE_SIZE + data.len() + GCM_TAG_SIZE);
+                let mut tag = [0; GCM_TAG_SIZE];
+                let aad = self.single_aad()?;
+
+                let mut encryptor = AesGcm::new(self.key_size, &self.key, &nonce, &aad);
+                encryptor.encrypt(data, &mut output_buffer[GCM_NONCE_SIZE..GCM_NONCE_SIZE + data.len()], &mut tag);
+                output_buffer[..GCM_NONCE_SIZE].copy_from_slice(&nonce);
+                output_buffer[GCM_NONCE_SIZE + data.len()..].copy_from_slice(&tag);
+
+                self.spend(data.len());
 
                 Ok(output_buffer)
             },
 
             AESMode::Counter => {
-                let mut buffer_base = [0; 4096];
-                let mut read_buffer = RefReadBuffer::new(data);
-                let mut output_buffer = RefWriteBuffer::new(&mut buffer_base);
-                let mut encryptor = aes::ctr(self.key_size, &self.key.as_slice(), &self.initialization_vector.as_slice());
+                let output_buffer = self.ctr_keystream().process(data);
+                self.spend(data.len());
+
+                Ok(output_buffer)
+            },
+
+            AESMode::CCM { tag_len, nonce_len } => {
+                check_ccm_parameters(tag_len, nonce_len, data.len())?;
+
+                let nonce = self.next_nonce(nonce_len)?;
+                let cipher = block_encryptor(&self.key)?;
+                let aad = self.single_aad()?;
+
+                let mut output_buffer = nonce.clone();
+                output_buffer.extend(ccm_seal(&*cipher, &nonce, &aad, data, tag_len));
+                self.spend(data.len());
 
-                let mut final_clone = Vec::new();
+                Ok(output_buffer)
+            },
+
+            AESMode::SIV => {
+                let output_buffer = siv_seal(self.key_size, &self.key, &self.aad, data)?;
+                self.spend(data.len());
+
+                Ok(output_buffer)
+            },
 
-                loop {
-                    let result = try!(encryptor.encrypt(&mut read_buffer, &mut output_buffer, false));
-                    final_clone.extend(output_buffer.take_read_buffer().take_remaining().iter().map(|&i| i));
+            AESMode::CBC => {
+                let cipher = block_encryptor(&self.key)?;
+                let mut padded = pkcs7_pad(data, CMAC_BLOCK_SIZE);
+                let mut output_buffer = self.fill_vec_to_length(padded.len());
+                let mut previous = self.initialization_vector.clone();
 
-                    match result {
-                        BufferResult::BufferUnderflow => break,
-                        BufferResult::BufferOverflow => { }
+                for (block, output) in padded.chunks(CMAC_BLOCK_SIZE).zip(output_buffer.chunks_mut(CMAC_BLOCK_SIZE)) {
+                    for (byte, chained) in previous.iter_mut().zip(block.iter()) {
+                        *byte ^= chained;
                     }
+
+                    cipher.encrypt_block(&previous, output);
+                    previous.copy_from_slice(output);
                 }
 
-                self.history_bytes += final_clone.len();
+                zeroize_bytes(&mut padded);
+                self.spend(data.len());
 
-                Ok(final_clone)
+                Ok(output_buffer)
             }
         }
     }
 
-    /// Decrypts a block of data and returns the plain text
+    /// Decrypts a block of data and returns the plain text. In GCM mode
+    /// the tag is checked before any plaintext is given out, and a
+    /// message that fails it, or a CCM or SIV message that fails its
+    /// check, is refused with `AuthenticationFailed`. In CBC mode every
+    /// padding byte is checked, and malformed padding is refused with
+    /// `InvalidPadding`. CBC is not authenticated, and
+    /// answering whether padding was valid can leak the plaintext, so it
+    /// is only for formats that need it, behind a MAC
     /// 
     /// ### Arguments
     ///  
     /// * `ciphertext` - Ciphertext to decrypt
-    pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, symmetriccipher::SymmetricCipherError> {
+    pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, AesError> {
+        if self.key.is_empty() {
+            return Err(AesError::InvalidLength);
+        }
+
         match self.mode {
             AESMode::GCM => {
-                let mut output_buffer = self.fill_vec_to_length(ciphertext.len());
-                let tag = [0; 16];
-                let aad = self.aad.clone().unwrap();
+                if ciphertext.len() < GCM_NONCE_SIZE + GCM_TAG_SIZE {
+                    return Err(AesError::InvalidLength);
+                }
+
+                let (nonce, sealed) = ciphertext.split_at(GCM_NONCE_SIZE);
+                let (sealed, tag) = sealed.split_at(sealed.len() - GCM_TAG_SIZE);
+                check_gcm_length(sealed.len())?;
+                let mut output_buffer = self.fill_vec_to_length(sealed.len());
+                let aad = self.single_aad()?;
 
-                let mut decryptor = AesGcm::new(self.key_size, &[0; 32], &[0; 12], &aad);
-                decryptor.decrypt(ciphertext, &mut output_buffer, &tag);
+                let mut decryptor = AesGcm::new(self.key_size, &self.key, nonce, &aad);
+
+                if !decryptor.decrypt(sealed, &mut output_buffer, tag) {
+                    zeroize_bytes(&mut output_buffer);
+                    return Err(AesError::AuthenticationFailed);
+                }
 
                 Ok(output_buffer)
             },
 
-            AESMode::Counter => {
-                let mut buffer_base = [0; 4096];
-                let mut read_buffer = RefReadBuffer::new(ciphertext);
-                let mut output_buffer = RefWriteBuffer::new(&mut buffer_base);
-                let mut decryptor = aes::ctr(self.key_size, &self.key.as_slice(), &self.initialization_vector.as_slice());
+            AESMode::Counter => Ok(self.ctr_keystream().process(ciphertext)),
+
+            AESMode::SIV => {
+                if ciphertext.len() < CMAC_BLOCK_SIZE || self.key.is_empty() {
+                    return Err(AesError::InvalidLength);
+                }
+
+                siv_open(self.key_size, &self.key, &self.aad, ciphertext)
+            },
+
+            AESMode::CCM { tag_len, nonce_len } => {
+                check_ccm_parameters(tag_len, nonce_len, 0)?;
+
+                if ciphertext.len() < nonce_len + tag_len {
+                    return Err(AesError::InvalidLength);
+                }
+
+                let (nonce, sealed) = ciphertext.split_at(nonce_len);
+                let cipher = block_encryptor(&self.key)?;
+                let aad = self.single_aad()?;
+
+                ccm_open(&*cipher, nonce, &aad, sealed, tag_len).ok_or(AesError::AuthenticationFailed)
+            },
+
+            AESMode::CBC => {
+                if ciphertext.is_empty() || ciphertext.len() % CMAC_BLOCK_SIZE != 0 {
+                    return Err(AesError::InvalidLength);
+                }
 
-                let mut final_clone = Vec::new();
+                let cipher = block_decryptor(&self.key)?;
+                let mut padded = self.fill_vec_to_length(ciphertext.len());
+                let mut previous = &self.initialization_vector[..];
 
-                loop {
-                    let result = try!(decryptor.decrypt(&mut read_buffer, &mut output_buffer, false));
-                    final_clone.extend(output_buffer.take_read_buffer().take_remaining().iter().map(|&i| i));
+                for (block, output) in ciphertext.chunks(CMAC_BLOCK_SIZE).zip(padded.chunks_mut(CMAC_BLOCK_SIZE)) {
+                    cipher.decrypt_block(block, output);
 
-                    match result {
-                        BufferResult::BufferUnderflow => break,
-                        BufferResult::BufferOverflow => { }
+                    for (byte, chained) in output.iter_mut().zip(previous.iter()) {
+                        *byte ^= chained;
                     }
+
+                    previous = block;
                 }
 
-                Ok(final_clone)
+                let unpadded = pkcs7_unpad(&padded, CMAC_BLOCK_SIZE);
+                zeroize_bytes(&mut padded);
+
+                unpadded.ok_or(AesError::InvalidPadding)
             }
         }
     }
 
-    /// Fills a vector with zeros based on the provided length.
-    /// The reason for this is that slices in Rust require a constant
-    /// value length, which is not guaranteed because of the variable 
-    /// nature of input data
+    /// Encrypts the buffer in place, leaving in it exactly what `encrypt`
+    /// would return. Counter mode overwrites the data, CBC grows it by
+    /// the padding, and GCM, CCM and SIV grow it by the nonce or
+    /// synthetic IV in front and the tag behind. Beyond that growth, no
+    /// copy of the message is made
     /// 
     /// ### Arguments
     /// 
-    /// * `length` - Length to fill up to
-    fn fill_vec_to_length(&self, length: usize) -> Vec<u8> {
-        let mut output = Vec::with_capacity(length);
+    /// * `buffer` - Plaintext, replaced by the ciphertext
+    pub fn encrypt_in_place(&mut self, buffer: &mut Vec<u8>) -> Result<(), AesError> {
+        let length = buffer.len();
+        self.check_budget(length)?;
 
-        for i in 0..length {
-            output.push(0);
+        match self.mode {
+            AESMode::GCM => {
+                check_gcm_length(length)?;
+
+                let nonce = self.next_nonce(GCM_NONCE_SIZE)?;
+                let cipher = block_encryptor(&self.key)?;
+                let aad = self.single_aad()?;
+
+                let tag = gcm_seal_in_place(&*cipher, &nonce, &aad, buffer);
+                buffer.splice(..0, nonce);
+                buffer.extend_from_slice(&tag);
+            },
+
+            AESMode::Counter => self.ctr_keystream().process_in_place(buffer),
+
+            AESMode::CCM { tag_len, nonce_len } => {
+                check_ccm_parameters(tag_len, nonce_len, length)?;
+
+                let nonce = self.next_nonce(nonce_len)?;
+                let cipher = block_encryptor(&self.key)?;
+                let aad = self.single_aad()?;
+
+                let mut tag = ccm_mac(&*cipher, &nonce, &aad, buffer, tag_len);
+                ccm_ctr(&*cipher, &nonce, &mut tag, buffer);
+                buffer.splice(..0, nonce);
+                buffer.extend_from_slice(&tag);
+            },
+
+            AESMode::SIV => {
+                let (mac_key, ctr_key) = self.key.split_at(self.key.len() / 2);
+                let v = s2v(mac_key, &self.aad, buffer)?;
+
+                siv_ctr(self.key_size, ctr_key, &v, buffer);
+                buffer.splice(..0, v.iter().cloned());
+            },
+
+            AESMode::CBC => {
+                let cipher = block_encryptor(&self.key)?;
+                let padding = CMAC_BLOCK_SIZE - length % CMAC_BLOCK_SIZE;
+                let mut input = [0; CMAC_BLOCK_SIZE];
+                let mut previous = &self.initialization_vector[..];
+
+                buffer.resize(length + padding, padding as u8);
+
+                for block in buffer.chunks_mut(CMAC_BLOCK_SIZE) {
+                    for (byte, (value, chained)) in input.iter_mut().zip(block.iter().zip(previous.iter())) {
+                        *byte = value ^ chained;
+                    }
+
+                    cipher.encrypt_block(&input, block);
+                    previous = block;
+                }
+
+                zeroize_bytes(&mut input);
+            }
         }
 
-        output
+        self.spend(length);
+        Ok(())
     }
 
-}
+    /// Decrypts the buffer in place, leaving in it exactly what `decrypt`
+    /// would return, and failing in the same cases. GCM checks the tag
+    /// before decrypting, so a forged message is left as it was. CCM, SIV
+    /// and CBC can only check after decrypting, and wipe and empty the
+    /// buffer when the check fails
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `buffer` - Ciphertext, replaced by the plaintext
+    pub fn decrypt_in_place(&self, buffer: &mut Vec<u8>) -> Result<(), AesError> {
+        if self.key.is_empty() {
+            return Err(AesError::InvalidLength);
+        }
 
+        match self.mode {
+            AESMode::GCM => {
+                if buffer.len() < GCM_NONCE_SIZE + GCM_TAG_SIZE {
+                    return Err(AesError::InvalidLength);
+                }
 
-/*----- TESTS -----*/
+                let cipher = block_encryptor(&self.key)?;
+                let aad = self.single_aad()?;
+                let tag_start = buffer.len() - GCM_TAG_SIZE;
 
-#[cfg(test)]
-mod aes_test {
+                let (nonce, sealed) = buffer.split_at_mut(GCM_NONCE_SIZE);
+                let (sealed, tag) = sealed.split_at_mut(tag_start - GCM_NONCE_SIZE);
+                check_gcm_length(sealed.len())?;
 
-    use crypto::aes::KeySize;
-    use cryptopunk::encryption::aes::{ AES, AESMode };
+                if !gcm_open_in_place(&*cipher, nonce, &aad, sealed, tag) {
+                    return Err(AesError::AuthenticationFailed);
+                }
 
-    #[test]
-    fn counter_mode_encryption() {
-        let data = b"Hello World";
-        let mut aes_cipher = AES::new(KeySize::KeySize256, AESMode::Counter, None);
+                buffer.truncate(tag_start);
+                buffer.drain(..GCM_NONCE_SIZE);
+            },
 
-        let cipher = aes_cipher.encrypt(data).ok().unwrap();
-        let plain = aes_cipher.decrypt(&cipher).ok().unwrap();
+            AESMode::Counter => self.ctr_keystream().process_in_place(buffer),
 
-        assert_eq!(plain, data.to_vec());
+            AESMode::SIV => {
+                if buffer.len() < CMAC_BLOCK_SIZE {
+                    return Err(AesError::InvalidLength);
+                }
+
+                let (mac_key, ctr_key) = self.key.split_at(self.key.len() / 2);
+                let mut v = [0; CMAC_BLOCK_SIZE];
+                v.copy_from_slice(&buffer[..CMAC_BLOCK_SIZE]);
+                buffer.drain(..CMAC_BLOCK_SIZE);
+
+                siv_ctr(self.key_size, ctr_key, &v, buffer);
+
+                match s2v(mac_key, &self.aad, buffer) {
+                    Ok(expected) if ct::ct_eq(&expected, &v) => (),
+                    Ok(_) => return Err(wipe_buffer(buffer, AesError::AuthenticationFailed)),
+                    Err(e) => return Err(wipe_buffer(buffer, e))
+                }
+            },
+
+            AESMode::CCM { tag_len, nonce_len } => {
+                check_ccm_parameters(tag_len, nonce_len, 0)?;
+
+                if buffer.len() < nonce_len + tag_len {
+                    return Err(AesError::InvalidLength);
+                }
+
+                let cipher = block_encryptor(&self.key)?;
+                let aad = self.single_aad()?;
+                let tag_start = buffer.len() - tag_len;
+                let mut tag = buffer.split_off(tag_start);
+                let nonce: Vec<u8> = buffer.drain(..nonce_len).collect();
+
+                ccm_ctr(&*cipher, &nonce, &mut tag, buffer);
+
+                if !ct::ct_eq(&ccm_mac(&*cipher, &nonce, &aad, buffer, tag_len), &tag) {
+                    return Err(wipe_buffer(buffer, AesError::AuthenticationFailed));
+                }
+            },
+
+            AESMode::CBC => {
+                if buffer.is_empty() || buffer.len() % CMAC_BLOCK_SIZE != 0 {
+                    return Err(AesError::InvalidLength);
+                }
+
+                let cipher = block_decryptor(&self.key)?;
+                let mut previous = [0; CMAC_BLOCK_SIZE];
+                let mut input = [0; CMAC_BLOCK_SIZE];
+                previous.copy_from_slice(&self.initialization_vector);
+
+                for block in buffer.chunks_mut(CMAC_BLOCK_SIZE) {
+                    input.copy_from_slice(block);
+                    cipher.decrypt_block(&input, block);
+
+                    for (byte, chained) in block.iter_mut().zip(previous.iter()) {
+                        *byte ^= chained;
+                    }
+
+                    previous = input;
+                }
+
+                let last_block = buffer.len() - CMAC_BLOCK_SIZE;
+
+                match pkcs7_unpad(&buffer[last_block..], CMAC_BLOCK_SIZE) {
+                    Some(mut unpadded) => {
+                        buffer.truncate(last_block + unpadded.len());
+                        zeroize_bytes(&mut unpadded);
+                    },
+                    None => return Err(wipe_buffer(buffer, AesError::InvalidPadding))
+                }
+            }
+        }
+
+        Ok(())
     }
 
-    #[test]
-    fn gcm_mode_encryption() {
-        let data = b"Hello World";
-        let aad = Some([3u8; 10].to_vec());
-        let mut aes_cipher = AES::new(KeySize::KeySize256, AESMode::GCM, aad);
+    /// Counter mode keystream starting from the instance IV, as `encrypt`
+    /// and `decrypt` use. The stream can run to any length, so it uses up
+    /// the key and IV, and is refused with `KeyExhausted` once they have
+    /// encrypted anything. A stream is read back by `decrypt`, or by the
+    /// stream of another instance under the same key and IV. Refused with
+    /// `InvalidLength` in other modes and once the key has been cleared
+    /// 
+    /// ### Example
+    /// 
+    /// ```
+    /// use cryptofun::encryption::aes::{ AES, AESMode, KeySize };
+    /// use cryptofun::utils::rng::seeded_rng;
+    /// 
+    /// let mut rng = seeded_rng(&[0xc7, 0x12]);
+    /// let mut aes = AES::new(KeySize::KeySize128, AESMode::Counter, None, &mut rng);
+    /// let mut stream = aes.ctr_stream().unwrap();
+    /// 
+    /// let mut ciphertext = stream.process(b"attack ");
+    /// ciphertext.extend(stream.process(b"at dawn"));
+    /// 
+    /// assert_eq!(aes.decrypt(&ciphertext).unwrap(), b"attack at dawn".to_vec());
+    /// assert!(aes.ctr_stream().is_err());
+    /// ```
+    pub fn ctr_stream(&mut self) -> Result<AesCtrStream, AesError> {
+        match self.mode {
+            AESMode::Counter if !self.key.is_empty() => (),
+            _ => return Err(AesError::InvalidLength)
+        }
 
-        let cipher = aes_cipher.encrypt(data).ok().unwrap();
+        if self.counter_blocks > 0 {
+            return Err(AesError::KeyExhausted);
+        }
+
+        self.counter_blocks = counter_capacity(&self.initialization_vector, self.counter_bits);
+        Ok(self.ctr_keystream())
+    }
+
+    /// Counter mode keystream from the IV, for a mode and key already
+    /// checked by the caller
+    fn ctr_keystream(&self) -> AesCtrStream {
+        AesCtrStream {
+            cipher: aes::ctr(self.key_size, &self.key, &self.initialization_vector)
+        }
+    }
+
+    /// Encrypts in Counter mode with the input spread over worker
+    /// threads. Each thread takes a run of whole blocks and starts its
+    /// keystream at the counter of its first block, so the output is the
+    /// same as that of `encrypt`, and `decrypt` takes it back. Refused
+    /// with `InvalidLength` in other modes
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `data` - Data to encrypt
+    /// * `num_threads` - Number of worker threads, at least one is used
+    pub fn encrypt_parallel(&mut self, data: &[u8], num_threads: usize) -> Result<Vec<u8>, AesError> {
+        self.check_budget(data.len())?;
+
+        match self.mode {
+            AESMode::Counter => (),
+            _ => return Err(AesError::InvalidLength)
+        }
+
+        let num_threads = cmp::max(num_threads, 1);
+        let blocks = (data.len() + CTR_BLOCK_SIZE - 1) / CTR_BLOCK_SIZE;
+        let blocks_per_thread = cmp::max((blocks + num_threads - 1) / num_threads, 1);
+
+        let chunks = chunk_for_threads(data, blocks_per_thread * CTR_BLOCK_SIZE).into_iter().map(Zeroizing::new);
+
+        let workers: Vec<_> = chunks.enumerate().map(|(index, mut chunk)| {
+            let key_size = self.key_size;
+            let key = Zeroizing::new(self.key.to_vec());
+            let offset = self.counter_blocks + (index * blocks_per_thread) as u128;
+            let counter = Zeroizing::new(counter_block(&self.initialization_vector, offset));
+
+            thread::spawn(move || {
+                process_in_place(&mut *aes::ctr(key_size, &key, &counter), &mut chunk);
+                chunk
+            })
+        }).collect();
+
+        let mut output = Vec::with_capacity(data.len());
+
+        for worker in workers {
+            let chunk = worker.join().unwrap_or_else(|e| panic::resume_unwind(e));
+            output.extend_from_slice(&chunk);
+        }
+
+        self.spend(data.len());
+        Ok(output)
+    }
+
+    /// Counter mode with an HMAC over each message, from keys derived
+    /// from the instance key. Refused with `InvalidLength` in other modes
+    /// and once the key has been cleared
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `algorithm` - Hash to derive the keys and build the HMAC over
+    /// * `rng` - Random number generator seeding the IV nonces
+    pub fn authenticated_ctr(&self, algorithm: HashAlgorithm, rng: &mut impl CryptoRngCore) -> Result<AuthenticatedCtr, AesError> {
+        match self.mode {
+            AESMode::Counter if !self.key.is_empty() => AuthenticatedCtr::new(self.key_size, &self.key, algorithm, rng),
+            _ => Err(AesError::InvalidLength)
+        }
+    }
+
+    /// Wipes the key and empties it, after which encryption and
+    /// decryption fail with `InvalidLength`
+    pub fn clear(&mut self) -> () {
+        zeroize_bytes(&mut self.key);
+        self.key.clear();
+    }
+
+    /// Refuses a message of the given length with `KeyExhausted` if it
+    /// would take the key past its budget or, in Counter mode, reuse
+    /// keystream or run the counter past its field, or with
+    /// `InvalidLength` if the key has been cleared
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `length` - Plaintext length in bytes
+    fn check_budget(&self, length: usize) -> Result<(), AesError> {
+        if self.key.is_empty() {
+            return Err(AesError::InvalidLength);
+        }
+
+        if self.history_messages >= self.budget.messages
+            || self.history_bytes as u64 + length as u64 > self.budget.bytes
+        {
+            return Err(AesError::KeyExhausted);
+        }
+
+        // Every Counter mode message starts at the IV, so used keystream
+        // can't be stepped past, only replaced. CBC chains from the IV
+        // too, and even an empty message fills a padding block
+        match self.mode {
+            AESMode::Counter if self.counter_blocks > 0 && length > 0 => Err(AesError::KeyExhausted),
+            AESMode::Counter if blocks_for(length) > counter_capacity(&self.initialization_vector, self.counter_bits) => Err(AesError::KeyExhausted),
+            AESMode::CBC if self.history_messages > 0 => Err(AesError::KeyExhausted),
+            _ => Ok(())
+        }
+    }
+
+    /// Counts a message of the given length against the budget
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `length` - Plaintext length in bytes
+    fn spend(&mut self, length: usize) -> () {
+        self.history_bytes += length;
+        self.history_messages += 1;
+
+        if let AESMode::Counter = self.mode {
+            self.counter_blocks += blocks_for(length);
+        }
+    }
+
+    /// Associated data components of a message encrypted through
+    /// `SymmetricEncryptor`. Counter and CBC modes take none
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `aad` - Associated data of the message, empty for none
+    fn message_aad(&self, aad: &[u8]) -> Result<Vec<Vec<u8>>, AesError> {
+        match self.mode {
+            _ if aad.is_empty() => Ok(Vec::new()),
+            AESMode::Counter | AESMode::CBC => Err(AesError::InvalidLength),
+            _ => Ok(vec![aad.to_vec()])
+        }
+    }
+
+    /// Associated data for the modes that take a single string
+    fn single_aad(&self) -> Result<Vec<u8>, AesError> {
+        match self.aad.len() {
+            0 => Ok(Vec::new()),
+            1 => Ok(self.aad[0].clone()),
+            _ => Err(AesError::InvalidLength)
+        }
+    }
+
+    /// Draws the nonce of a message from the nonce sequence, taking as
+    /// many nonces from it as the length needs
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `length` - Nonce length in bytes
+    fn next_nonce(&mut self, length: usize) -> Result<Vec<u8>, AesError> {
+        let mut nonce = Vec::with_capacity(length + NONCE_LENGTH);
+
+        while nonce.len() < length {
+            let next = self.nonces.next().map_err(|_| AesError::InvalidLength)?;
+            nonce.extend_from_slice(&next);
+        }
+
+        nonce.truncate(length);
+        Ok(nonce)
+    }
+
+    /// Fills a vector with zeros based on the provided length.
+    /// The reason for this is that slices in Rust require a constant
+    /// value length, which is not guaranteed because of the variable 
+    /// nature of input data
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `length` - Length to fill up to
+    fn fill_vec_to_length(&self, length: usize) -> Vec<u8> {
+        let mut output = Vec::with_capacity(length);
+
+        for i in 0..length {
+            output.push(0);
+        }
+
+        output
+    }
+
+}
+
+/// Shows the mode, key size and use of the key, never the key or IV
+impl fmt::Debug for AES {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AES")
+            .field("mode", &self.mode)
+            .field("key_bits", &(key_length(self.key_size, &AESMode::Counter) * 8))
+            .field("history_bytes", &self.history_bytes)
+            .field("history_messages", &self.history_messages)
+            .finish()
+    }
+}
+
+/// The associated data of each message stands in for the instance AAD
+/// while that message is encrypted or decrypted. SIV takes it as a
+/// single component
+impl SymmetricEncryptor for AES {
+    fn encrypt(&mut self, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, CipherError> {
+        let message_aad = self.message_aad(aad)?;
+        let instance_aad = mem::replace(&mut self.aad, message_aad);
+        let result = AES::encrypt(self, plaintext);
+        self.aad = instance_aad;
+
+        result
+    }
+
+    fn decrypt(&mut self, ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>, CipherError> {
+        let message_aad = self.message_aad(aad)?;
+        let instance_aad = mem::replace(&mut self.aad, message_aad);
+        let result = AES::decrypt(self, ciphertext);
+        self.aad = instance_aad;
+
+        result
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for AES {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl Cmac {
+
+    /// Creates a CMAC instance and derives its subkeys K1 = 2 L and
+    /// K2 = 4 L, where L is the encryption of the zero block
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `key` - AES key of 16, 24 or 32 bytes
+    pub fn new(key: &[u8]) -> Result<Self, AesError> {
+        let cipher = block_encryptor(key)?;
+
+        let mut l = [0; CMAC_BLOCK_SIZE];
+        cipher.encrypt_block(&[0; CMAC_BLOCK_SIZE], &mut l);
+
+        let k1 = gf128_double(&l);
+        let k2 = gf128_double(&k1);
+        zeroize_bytes(&mut l);
+
+        Ok(Cmac {
+            cipher: cipher,
+            k1: k1,
+            k2: k2,
+            state: [0; CMAC_BLOCK_SIZE],
+            buffer: [0; CMAC_BLOCK_SIZE],
+            buffered: 0
+        })
+    }
+
+    /// Feeds more of the message into the MAC
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `data` - Next part of the message
+    pub fn update(&mut self, mut data: &[u8]) -> () {
+        while !data.is_empty() {
+            // Only chain a full buffer once more data follows it
+            if self.buffered == CMAC_BLOCK_SIZE {
+                let block = self.buffer;
+                self.chain(&block);
+                self.buffered = 0;
+            }
+
+            let take = std::cmp::min(CMAC_BLOCK_SIZE - self.buffered, data.len());
+            self.buffer[self.buffered..self.buffered + take].copy_from_slice(&data[..take]);
+            self.buffered += take;
+            data = &data[take..];
+        }
+    }
+
+    /// Masks and chains the last block, returning the tag
+    pub fn finalize(mut self) -> [u8; CMAC_BLOCK_SIZE] {
+        let mut last = [0; CMAC_BLOCK_SIZE];
+        last[..self.buffered].copy_from_slice(&self.buffer[..self.buffered]);
+
+        let subkey = if self.buffered == CMAC_BLOCK_SIZE {
+            self.k1
+        } else {
+            last[self.buffered] = 0x80;
+            self.k2
+        };
+
+        for (byte, mask) in last.iter_mut().zip(subkey.iter()) {
+            *byte ^= mask;
+        }
+
+        self.chain(&last);
+        zeroize_bytes(&mut last);
+
+        self.state
+    }
+
+    /// Runs one block through the CBC-MAC chain
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `block` - Block to chain
+    fn chain(&mut self, block: &[u8; CMAC_BLOCK_SIZE]) -> () {
+        let mut input = self.state;
+
+        for (byte, value) in input.iter_mut().zip(block.iter()) {
+            *byte ^= value;
+        }
+
+        self.cipher.encrypt_block(&input, &mut self.state);
+        zeroize_bytes(&mut input);
+    }
+
+    /// Wipes the subkeys, chaining value and buffered message bytes
+    pub fn clear(&mut self) -> () {
+        zeroize_bytes(&mut self.k1);
+        zeroize_bytes(&mut self.k2);
+        zeroize_bytes(&mut self.state);
+        zeroize_bytes(&mut self.buffer);
+        self.buffered = 0;
+    }
+}
+
+impl AesCtrStream {
+
+    /// Encrypts or decrypts the next piece of the message, which may be
+    /// of any length
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `data` - Next piece of plaintext or ciphertext
+    pub fn process(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut output = vec![0; data.len()];
+        self.cipher.process(data, &mut output);
+
+        output
+    }
+
+    /// Encrypts or decrypts the next piece of the message in place
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `data` - Next piece of plaintext or ciphertext, overwritten
+    pub fn process_in_place(&mut self, data: &mut [u8]) -> () {
+        process_in_place(&mut *self.cipher, data);
+    }
+}
+
+impl AuthenticatedCtr {
+
+    /// Derives the encryption and MAC keys from a key
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `key_size` - Size of the encryption key, and of `key`
+    /// * `key` - Key to derive from
+    /// * `algorithm` - Hash to derive the keys and build the HMAC over
+    /// * `rng` - Random number generator seeding the IV nonces
+    fn new(key_size: aes::KeySize, key: &[u8], algorithm: HashAlgorithm, rng: &mut impl CryptoRngCore) -> Result<Self, AesError> {
+        let key_len = key_length(key_size, &AESMode::Counter);
+        let mac_len = algorithm.output_size();
+
+        let mut keys = hkdf(key, &[], AUTHENTICATED_CTR_INFO, key_len + mac_len, algorithm.clone())
+            .map_err(|_| AesError::InvalidLength)?;
+        let mac_key = keys.split_off(key_len);
+
+        Ok(AuthenticatedCtr {
+            key_size: key_size,
+            encryption_key: keys,
+            mac_key: mac_key,
+            algorithm: algorithm,
+            nonces: NonceSequence::random(rng)
+        })
+    }
+
+    /// Encrypts a message under a fresh IV and appends the HMAC over
+    /// the IV and ciphertext. Fails with `InvalidLength` once the IV
+    /// nonces run out
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `data` - Message to encrypt
+    pub fn encrypt(&mut self, data: &[u8]) -> Result<Vec<u8>, AesError> {
+        let nonce = self.nonces.next().map_err(|_| AesError::InvalidLength)?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.resize(AUTHENTICATED_CTR_IV_SIZE, 0);
+
+        if blocks_for(data.len()) > counter_capacity(&sealed, AUTHENTICATED_CTR_COUNTER_BITS) {
+            return Err(AesError::KeyExhausted);
+        }
+
+        let ciphertext = self.ctr(&sealed, data);
+        sealed.extend(ciphertext);
+
+        let tag = hmac(&self.mac_key, &sealed, self.algorithm.clone());
+        sealed.extend(tag);
+
+        Ok(sealed)
+    }
+
+    /// Checks the HMAC of a sealed message in constant time, and only
+    /// then decrypts it. A mismatch gives `AuthenticationFailed`, and input
+    /// too short to hold an IV and tag, or a cleared instance, gives
+    /// `InvalidLength`
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `sealed` - IV || ciphertext || HMAC
+    pub fn decrypt(&self, sealed: &[u8]) -> Result<Vec<u8>, AesError> {
+        let tag_len = self.algorithm.output_size();
+
+        if sealed.len() < AUTHENTICATED_CTR_IV_SIZE + tag_len || self.encryption_key.is_empty() {
+            return Err(AesError::InvalidLength);
+        }
+
+        let (authenticated, tag) = sealed.split_at(sealed.len() - tag_len);
+
+        if !ct::ct_eq(&hmac(&self.mac_key, authenticated, self.algorithm.clone()), tag) {
+            return Err(AesError::AuthenticationFailed);
+        }
+
+        let (iv, ciphertext) = authenticated.split_at(AUTHENTICATED_CTR_IV_SIZE);
+        Ok(self.ctr(iv, ciphertext))
+    }
+
+    /// Wipes both keys and empties them, after which encryption and
+    /// decryption fail with `InvalidLength`
+    pub fn clear(&mut self) -> () {
+        zeroize_bytes(&mut self.encryption_key);
+        zeroize_bytes(&mut self.mac_key);
+        self.encryption_key.clear();
+        self.mac_key.clear();
+        self.nonces.exhaust();
+    }
+
+    /// AES-CTR under the encryption key from the given IV
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `iv` - Initial counter block
+    /// * `data` - Message to encrypt or decrypt
+    fn ctr(&self, iv: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut output = vec![0; data.len()];
+        aes::ctr(self.key_size, &self.encryption_key, iv).process(data, &mut output);
+
+        output
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for Cmac {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for AuthenticatedCtr {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Computes the AES-CMAC tag of a message (NIST SP 800-38B)
+/// 
+/// ### Arguments
+/// 
+/// * `key` - AES key of 16, 24 or 32 bytes
+/// * `message` - Message to authenticate
+pub fn cmac(key: &[u8], message: &[u8]) -> Result<[u8; CMAC_BLOCK_SIZE], AesError> {
+    let mut mac = Cmac::new(key)?;
+    mac.update(message);
+
+    Ok(mac.finalize())
+}
+
+/// Wraps key material under a key-encryption key with the AES key wrap
+/// of RFC 3394, adding an 8 byte integrity check. The key material must
+/// be at least two semiblocks and a whole number of them
+/// 
+/// ### Arguments
+/// 
+/// * `kek` - Key-encryption key of 16, 24 or 32 bytes
+/// * `plaintext_key` - Key material to wrap
+pub fn key_wrap(kek: &[u8], plaintext_key: &[u8]) -> Result<Vec<u8>, AesError> {
+    if plaintext_key.len() < 2 * KEY_WRAP_BLOCK_SIZE || plaintext_key.len() % KEY_WRAP_BLOCK_SIZE != 0 {
+        return Err(AesError::InvalidLength);
+    }
+
+    let cipher = block_encryptor(kek)?;
+    let n = plaintext_key.len() / KEY_WRAP_BLOCK_SIZE;
+    let mut wrapped = KEY_WRAP_IV.to_vec();
+    wrapped.extend_from_slice(plaintext_key);
+
+    let mut block = [0; CMAC_BLOCK_SIZE];
+    let mut output = [0; CMAC_BLOCK_SIZE];
+
+    for j in 0..6 {
+        for i in 1..=n {
+            // B = AES(K, A | R[i]), A = MSB(B) ^ t, R[i] = LSB(B)
+            block[..KEY_WRAP_BLOCK_SIZE].copy_from_slice(&wrapped[..KEY_WRAP_BLOCK_SIZE]);
+            block[KEY_WRAP_BLOCK_SIZE..].copy_from_slice(&wrapped[i * KEY_WRAP_BLOCK_SIZE..(i + 1) * KEY_WRAP_BLOCK_SIZE]);
+            cipher.encrypt_block(&block, &mut output);
+
+            let t = ((n * j + i) as u64).to_be_bytes();
+
+            for (k, byte) in wrapped[..KEY_WRAP_BLOCK_SIZE].iter_mut().enumerate() {
+                *byte = output[k] ^ t[k];
+            }
+
+            wrapped[i * KEY_WRAP_BLOCK_SIZE..(i + 1) * KEY_WRAP_BLOCK_SIZE].copy_from_slice(&output[KEY_WRAP_BLOCK_SIZE..]);
+        }
+    }
+
+    zeroize_bytes(&mut block);
+    zeroize_bytes(&mut output);
+
+    Ok(wrapped)
+}
+
+/// Unwraps key material wrapped by `key_wrap`. If the integrity check
+/// doesn't come out as the RFC 3394 initial value, because the wrapped
+/// key was changed or the key-encryption key is wrong, nothing is
+/// returned but `InvalidPadding`
+/// 
+/// ### Arguments
+/// 
+/// * `kek` - Key-encryption key of 16, 24 or 32 bytes
+/// * `wrapped_key` - Wrapped key material
+pub fn key_unwrap(kek: &[u8], wrapped_key: &[u8]) -> Result<Vec<u8>, AesError> {
+    if wrapped_key.len() < 3 * KEY_WRAP_BLOCK_SIZE || wrapped_key.len() % KEY_WRAP_BLOCK_SIZE != 0 {
+        return Err(AesError::InvalidLength);
+    }
+
+    let cipher = block_decryptor(kek)?;
+    let n = wrapped_key.len() / KEY_WRAP_BLOCK_SIZE - 1;
+    let mut unwrapped = wrapped_key.to_vec();
+
+    let mut block = [0; CMAC_BLOCK_SIZE];
+    let mut output = [0; CMAC_BLOCK_SIZE];
+
+    for j in (0..6).rev() {
+        for i in (1..=n).rev() {
+            // B = AES-1(K, (A ^ t) | R[i]), A = MSB(B), R[i] = LSB(B)
+            let t = ((n * j + i) as u64).to_be_bytes();
+
+            for (k, byte) in block[..KEY_WRAP_BLOCK_SIZE].iter_mut().enumerate() {
+                *byte = unwrapped[k] ^ t[k];
+            }
+
+            block[KEY_WRAP_BLOCK_SIZE..].copy_from_slice(&unwrapped[i * KEY_WRAP_BLOCK_SIZE..(i + 1) * KEY_WRAP_BLOCK_SIZE]);
+            cipher.decrypt_block(&block, &mut output);
+
+            unwrapped[..KEY_WRAP_BLOCK_SIZE].copy_from_slice(&output[..KEY_WRAP_BLOCK_SIZE]);
+            unwrapped[i * KEY_WRAP_BLOCK_SIZE..(i + 1) * KEY_WRAP_BLOCK_SIZE].copy_from_slice(&output[KEY_WRAP_BLOCK_SIZE..]);
+        }
+    }
+
+    zeroize_bytes(&mut block);
+    zeroize_bytes(&mut output);
+
+    if !ct::ct_eq(&unwrapped[..KEY_WRAP_BLOCK_SIZE], &KEY_WRAP_IV) {
+        zeroize_bytes(&mut unwrapped);
+        return Err(AesError::InvalidPadding);
+    }
+
+    Ok(unwrapped.split_off(KEY_WRAP_BLOCK_SIZE))
+}
+
+/// Checks CCM tag and nonce lengths against RFC 3610, and that a
+/// message of the given length fits in the length field the nonce
+/// leaves room for
+/// 
+/// ### Arguments
+/// 
+/// * `tag_len` - Tag length in bytes
+/// * `nonce_len` - Nonce length in bytes
+/// * `data_len` - Message length in bytes
+fn check_ccm_parameters(tag_len: usize, nonce_len: usize, data_len: usize) -> Result<(), AesError> {
+    let valid_tag = tag_len >= 4 && tag_len <= 16 && tag_len % 2 == 0;
+    let valid_nonce = nonce_len >= 7 && nonce_len <= 13;
+
+    if !valid_tag || !valid_nonce || (nonce_len > 7 && (data_len as u64) >> (8 * (15 - nonce_len)) != 0) {
+        return Err(AesError::InvalidLength);
+    }
+
+    Ok(())
+}
+
+/// Encrypts and authenticates a message with CCM (RFC 3610) under one
+/// nonce, giving ciphertext || tag
+/// 
+/// ### Arguments
+/// 
+/// * `cipher` - AES under the key
+/// * `nonce` - Nonce of the message
+/// * `aad` - Associated data, authenticated but not encrypted
+/// * `data` - Message to encrypt
+/// * `tag_len` - Tag length in bytes
+fn ccm_seal(cipher: &dyn BlockEncryptor, nonce: &[u8], aad: &[u8], data: &[u8], tag_len: usize) -> Vec<u8> {
+    let mut tag = ccm_mac(cipher, nonce, aad, data, tag_len);
+    let mut sealed = data.to_vec();
+
+    ccm_ctr(cipher, nonce, &mut tag, &mut sealed);
+    sealed.extend_from_slice(&tag);
+    sealed
+}
+
+/// Opens a message sealed by `ccm_seal`. The tag is recomputed over the
+/// decrypted message and compared in constant time, and the message is
+/// only given out if it matches
+/// 
+/// ### Arguments
+/// 
+/// * `cipher` - AES under the key
+/// * `nonce` - Nonce of the message
+/// * `aad` - Associated data the message was sealed with
+/// * `sealed` - Ciphertext || tag
+/// * `tag_len` - Tag length in bytes
+fn ccm_open(cipher: &dyn BlockEncryptor, nonce: &[u8], aad: &[u8], sealed: &[u8], tag_len: usize) -> Option<Vec<u8>> {
+    let (ciphertext, received) = sealed.split_at(sealed.len() - tag_len);
+    let mut data = ciphertext.to_vec();
+    let mut tag = received.to_vec();
+
+    ccm_ctr(cipher, nonce, &mut tag, &mut data);
+
+    if !ct::ct_eq(&ccm_mac(cipher, nonce, aad, &data, tag_len), &tag) {
+        zeroize_bytes(&mut data);
+        return None;
+    }
+
+    Some(data)
+}
+
+/// CCM counter mode: the tag is masked with the keystream block of
+/// counter 0 and the message with those from counter 1 on
+/// 
+/// ### Arguments
+/// 
+/// * `cipher` - AES under the key
+/// * `nonce` - Nonce of the message
+/// * `tag` - Tag to mask
+/// * `data` - Message to encrypt or decrypt in place
+fn ccm_ctr(cipher: &dyn BlockEncryptor, nonce: &[u8], tag: &mut [u8], data: &mut [u8]) -> () {
+    let l = 15 - nonce.len();
+    let mut counter = [0; CMAC_BLOCK_SIZE];
+    let mut keystream = [0; CMAC_BLOCK_SIZE];
+
+    // A_i = flags || nonce || i, with L - 1 in the flags
+    counter[0] = (l - 1) as u8;
+    counter[1..1 + nonce.len()].copy_from_slice(nonce);
+
+    for (i, piece) in Some(tag).into_iter().chain(data.chunks_mut(CMAC_BLOCK_SIZE)).enumerate() {
+        counter[CMAC_BLOCK_SIZE - l..].copy_from_slice(&(i as u64).to_be_bytes()[8 - l..]);
+        cipher.encrypt_block(&counter, &mut keystream);
+
+        for (byte, mask) in piece.iter_mut().zip(keystream.iter()) {
+            *byte ^= mask;
+        }
+    }
+
+    zeroize_bytes(&mut keystream);
+}
+
+/// CCM authentication tag: the CBC-MAC of B_0, the encoded associated
+/// data and the message, each zero padded to whole blocks
+/// 
+/// ### Arguments
+/// 
+/// * `cipher` - AES under the key
+/// * `nonce` - Nonce of the message
+/// * `aad` - Associated data
+/// * `data` - Message
+/// * `tag_len` - Tag length in bytes
+fn ccm_mac(cipher: &dyn BlockEncryptor, nonce: &[u8], aad: &[u8], data: &[u8], tag_len: usize) -> Vec<u8> {
+    let l = 15 - nonce.len();
+
+    // B_0 = flags || nonce || l(m), flags holding Adata, (M - 2) / 2 and L - 1
+    let mut blocks = vec![((!aad.is_empty() as u8) << 6) | ((((tag_len - 2) / 2) as u8) << 3) | (l - 1) as u8];
+    blocks.extend_from_slice(nonce);
+    blocks.extend_from_slice(&(data.len() as u64).to_be_bytes()[8 - l..]);
+
+    if !aad.is_empty() {
+        if aad.len() < 0xff00 {
+            blocks.extend_from_slice(&(aad.len() as u16).to_be_bytes());
+        } else if aad.len() as u64 <= u32::max_value() as u64 {
+            blocks.extend_from_slice(&[0xff, 0xfe]);
+            blocks.extend_from_slice(&(aad.len() as u32).to_be_bytes());
+        } else {
+            blocks.extend_from_slice(&[0xff, 0xff]);
+            blocks.extend_from_slice(&(aad.len() as u64).to_be_bytes());
+        }
+
+        blocks.extend_from_slice(aad);
+        blocks.resize((blocks.len() + CMAC_BLOCK_SIZE - 1) / CMAC_BLOCK_SIZE * CMAC_BLOCK_SIZE, 0);
+    }
+
+    let mut state = [0; CMAC_BLOCK_SIZE];
+    let mut input = [0; CMAC_BLOCK_SIZE];
+
+    // The message follows the header blocks, its last block zero padded
+    for block in blocks.chunks(CMAC_BLOCK_SIZE).chain(data.chunks(CMAC_BLOCK_SIZE)) {
+        for (i, byte) in input.iter_mut().enumerate() {
+            *byte = state[i] ^ block.get(i).cloned().unwrap_or(0);
+        }
+
+        cipher.encrypt_block(&input, &mut state);
+    }
+
+    zeroize_bytes(&mut blocks);
+    zeroize_bytes(&mut input);
+
+    state[..tag_len].to_vec()
+}
+
+/// Encrypts a message with AES-SIV (RFC 5297), giving V || ciphertext
+/// 
+/// ### Arguments
+/// 
+/// * `key_size` - Size of each half of the key
+/// * `key` - S2V key || CTR key
+/// * `components` - Associated data components
+/// * `data` - Message to encrypt
+fn siv_seal(key_size: KeySize, key: &[u8], components: &[Vec<u8>], data: &[u8]) -> Result<Vec<u8>, AesError> {
+    let (mac_key, ctr_key) = key.split_at(key.len() / 2);
+    let v = s2v(mac_key, components, data)?;
+
+    let mut sealed = v.to_vec();
+    sealed.extend_from_slice(data);
+    siv_ctr(key_size, ctr_key, &v, &mut sealed[CMAC_BLOCK_SIZE..]);
+
+    Ok(sealed)
+}
+
+/// Opens a message sealed by `siv_seal`, decrypting it and checking that
+/// S2V over the result gives V back. Otherwise the message is wiped and
+/// `AuthenticationFailed` returned
+/// 
+/// ### Arguments
+/// 
+/// * `key_size` - Size of each half of the key
+/// * `key` - S2V key || CTR key
+/// * `components` - Associated data components the message was sealed with
+/// * `sealed` - V || ciphertext
+fn siv_open(key_size: KeySize, key: &[u8], components: &[Vec<u8>], sealed: &[u8]) -> Result<Vec<u8>, AesError> {
+    let (mac_key, ctr_key) = key.split_at(key.len() / 2);
+    let (v, ciphertext) = sealed.split_at(CMAC_BLOCK_SIZE);
+
+    let mut data = ciphertext.to_vec();
+    siv_ctr(key_size, ctr_key, v, &mut data);
+
+    if !ct::ct_eq(&s2v(mac_key, components, &data)?, v) {
+        zeroize_bytes(&mut data);
+        return Err(AesError::AuthenticationFailed);
+    }
+
+    Ok(data)
+}
+
+/// CTR under the counter Q, which is V with bits 63 and 31 cleared so
+/// that implementations with 64 or 32 bit counters agree
+/// 
+/// ### Arguments
+/// 
+/// * `key_size` - Size of the key
+/// * `key` - CTR key
+/// * `v` - Synthetic IV
+/// * `data` - Message to encrypt or decrypt in place
+fn siv_ctr(key_size: KeySize, key: &[u8], v: &[u8], data: &mut [u8]) -> () {
+    let mut q = v.to_vec();
+    q[8] &= 0x7f;
+    q[12] &= 0x7f;
+
+    process_in_place(&mut *aes::ctr(key_size, key, &q), data);
+}
+
+/// S2V (RFC 5297 2.4), the CMAC of a vector of strings: the associated
+/// data components followed by the message
+/// 
+/// ### Arguments
+/// 
+/// * `key` - CMAC key
+/// * `components` - Associated data components, at most 126
+/// * `data` - Message, the last string
+fn s2v(key: &[u8], components: &[Vec<u8>], data: &[u8]) -> Result<[u8; CMAC_BLOCK_SIZE], AesError> {
+    if components.len() > 126 {
+        return Err(AesError::InvalidLength);
+    }
+
+    let mut d = cmac(key, &[0; CMAC_BLOCK_SIZE])?;
+
+    for component in components {
+        let mac = cmac(key, component)?;
+        d = gf128_double(&d);
+
+        for (byte, value) in d.iter_mut().zip(mac.iter()) {
+            *byte ^= value;
+        }
+    }
+
+    // T = S_n xorend D for long messages, dbl(D) xor pad(S_n) for short
+    // ones. Only the last block of T differs from the message, so the
+    // rest is fed to CMAC as it is
+    let mut mac = Cmac::new(key)?;
+    let mut last = [0; CMAC_BLOCK_SIZE];
+
+    if data.len() >= CMAC_BLOCK_SIZE {
+        let (head, tail) = data.split_at(data.len() - CMAC_BLOCK_SIZE);
+        mac.update(head);
+        last.copy_from_slice(tail);
+
+        for (byte, value) in last.iter_mut().zip(d.iter()) {
+            *byte ^= value;
+        }
+    } else {
+        last = gf128_double(&d);
+        last[data.len()] ^= 0x80;
+
+        for (byte, value) in last.iter_mut().zip(data.iter()) {
+            *byte ^= value;
+        }
+    }
+
+    mac.update(&last);
+    zeroize_bytes(&mut last);
+    zeroize_bytes(&mut d);
+
+    Ok(mac.finalize())
+}
+
+/// Refuses GCM messages of more than `GCM_MAX_BLOCKS` blocks, beyond
+/// which the counter would wrap and reuse the keystream
+/// 
+/// ### Arguments
+/// 
+/// * `data_len` - Length of the message in bytes
+fn check_gcm_length(data_len: usize) -> Result<(), AesError> {
+    let blocks = (data_len as u64 + CMAC_BLOCK_SIZE as u64 - 1) / CMAC_BLOCK_SIZE as u64;
+
+    if blocks > GCM_MAX_BLOCKS {
+        return Err(AesError::InvalidLength);
+    }
+
+    Ok(())
+}
+
+/// GCM (SP 800-38D) under a 96 bit nonce, encrypting the message in
+/// place and returning the tag. The message must have passed
+/// `check_gcm_length`
+/// 
+/// ### Arguments
+/// 
+/// * `cipher` - AES under the key
+/// * `nonce` - Nonce of the message
+/// * `aad` - Associated data
+/// * `data` - Message, overwritten with the ciphertext
+fn gcm_seal_in_place(cipher: &dyn BlockEncryptor, nonce: &[u8], aad: &[u8], data: &mut [u8]) -> [u8; GCM_TAG_SIZE] {
+    gcm_ctr(cipher, nonce, data);
+    gcm_tag(cipher, nonce, aad, data)
+}
+
+/// Checks the tag of a GCM message in constant time, and only if it
+/// matches decrypts the message in place
+/// 
+/// ### Arguments
+/// 
+/// * `cipher` - AES under the key
+/// * `nonce` - Nonce of the message
+/// * `aad` - Associated data the message was sealed with
+/// * `data` - Ciphertext, overwritten with the message if authentic
+/// * `tag` - Received tag
+fn gcm_open_in_place(cipher: &dyn BlockEncryptor, nonce: &[u8], aad: &[u8], data: &mut [u8], tag: &[u8]) -> bool {
+    if !ct::ct_eq(&gcm_tag(cipher, nonce, aad, data), tag) {
+        return false;
+    }
+
+    gcm_ctr(cipher, nonce, data);
+    true
+}
+
+/// GCM counter mode, from inc32(J_0) with J_0 = nonce || 1, for
+/// messages that have passed `check_gcm_length`
+/// 
+/// ### Arguments
+/// 
+/// * `cipher` - AES under the key
+/// * `nonce` - Nonce of the message
+/// * `data` - Message to encrypt or decrypt in place
+fn gcm_ctr(cipher: &dyn BlockEncryptor, nonce: &[u8], data: &mut [u8]) -> () {
+    let mut counter = [0; CMAC_BLOCK_SIZE];
+    let mut keystream = [0; CMAC_BLOCK_SIZE];
+    counter[..GCM_NONCE_SIZE].copy_from_slice(nonce);
+
+    for (i, block) in data.chunks_mut(CMAC_BLOCK_SIZE).enumerate() {
+        counter[GCM_NONCE_SIZE..].copy_from_slice(&(i as u32 + 2).to_be_bytes());
+        cipher.encrypt_block(&counter, &mut keystream);
+
+        for (byte, mask) in block.iter_mut().zip(keystream.iter()) {
+            *byte ^= mask;
+        }
+    }
+
+    zeroize_bytes(&mut keystream);
+}
+
+/// GCM tag, E(J_0) xor GHASH over the associated data, the ciphertext
+/// and their lengths in bits
+/// 
+/// ### Arguments
+/// 
+/// * `cipher` - AES under the key
+/// * `nonce` - Nonce of the message
+/// * `aad` - Associated data
+/// * `ciphertext` - Ciphertext
+fn gcm_tag(cipher: &dyn BlockEncryptor, nonce: &[u8], aad: &[u8], ciphertext: &[u8]) -> [u8; GCM_TAG_SIZE] {
+    let mut h = [0; CMAC_BLOCK_SIZE];
+    let mut tag = [0; GCM_TAG_SIZE];
+    let mut j0 = [0; CMAC_BLOCK_SIZE];
+    cipher.encrypt_block(&[0; CMAC_BLOCK_SIZE], &mut h);
+
+    let mut lengths = [0; CMAC_BLOCK_SIZE];
+    lengths[..8].copy_from_slice(&(aad.len() as u64 * 8).to_be_bytes());
+    lengths[8..].copy_from_slice(&(ciphertext.len() as u64 * 8).to_be_bytes());
+
+    // Each input is zero padded to whole blocks
+    let mut y = [0; CMAC_BLOCK_SIZE];
+
+    for block in aad.chunks(CMAC_BLOCK_SIZE).chain(ciphertext.chunks(CMAC_BLOCK_SIZE)).chain(Some(&lengths[..])) {
+        for (byte, value) in y.iter_mut().zip(block.iter()) {
+            *byte ^= value;
+        }
+
+        y = gf128_mul(&y, &h);
+    }
+
+    j0[..GCM_NONCE_SIZE].copy_from_slice(nonce);
+    j0[CMAC_BLOCK_SIZE - 1] = 1;
+    cipher.encrypt_block(&j0, &mut tag);
+
+    for (byte, value) in tag.iter_mut().zip(y.iter()) {
+        *byte ^= value;
+    }
+
+    zeroize_bytes(&mut h);
+    tag
+}
+
+/// Runs a stream cipher over data in place, a chunk at a time through a
+/// scratch buffer, since rust-crypto wants separate input and output
+/// 
+/// ### Arguments
+/// 
+/// * `cipher` - Stream cipher
+/// * `data` - Data to encrypt or decrypt in place
+fn process_in_place(cipher: &mut dyn SynchronousStreamCipher, data: &mut [u8]) -> () {
+    let mut scratch = [0; IN_PLACE_CHUNK_SIZE];
+
+    for chunk in data.chunks_mut(IN_PLACE_CHUNK_SIZE) {
+        let input = &mut scratch[..chunk.len()];
+        input.copy_from_slice(chunk);
+        cipher.process(input, chunk);
+    }
+
+    zeroize_bytes(&mut scratch);
+}
+
+/// Number of blocks a Counter mode keystream can take from an IV before
+/// its counter field wraps, saturating at 2^128 - 1 for a whole-block
+/// counter starting at zero
+/// 
+/// ### Arguments
+/// 
+/// * `initial` - Counter block of the first block
+/// * `bits` - Width of the counter field, the low bits of the block
+fn counter_capacity(initial: &[u8], bits: usize) -> u128 {
+    let mut block = [0; CTR_BLOCK_SIZE];
+    block.copy_from_slice(initial);
+
+    let top = u128::max_value() >> (128 - bits);
+    let start = u128::from_be_bytes(block) & top;
+
+    (top - start).saturating_add(1)
+}
+
+/// Number of Counter mode blocks a message of the given length takes
+/// 
+/// ### Arguments
+/// 
+/// * `length` - Message length in bytes
+fn blocks_for(length: usize) -> u128 {
+    (length as u128 + CTR_BLOCK_SIZE as u128 - 1) / CTR_BLOCK_SIZE as u128
+}
+
+/// Counter block a number of blocks into a Counter mode keystream, the
+/// initial block read as a big-endian integer with the offset added
+/// modulo 2^128, as rust-crypto increments it
+/// 
+/// ### Arguments
+/// 
+/// * `initial` - Counter block of the first block
+/// * `offset` - Number of blocks to move on
+fn counter_block(initial: &[u8], offset: u128) -> Vec<u8> {
+    let mut block = [0; CTR_BLOCK_SIZE];
+    block.copy_from_slice(initial);
+
+    u128::from_be_bytes(block).wrapping_add(offset).to_be_bytes().to_vec()
+}
+
+/// Wipes and empties a buffer holding a rejected message, passing the
+/// error through
+/// 
+/// ### Arguments
+/// 
+/// * `buffer` - Buffer to wipe
+/// * `error` - Error to return
+fn wipe_buffer(buffer: &mut Vec<u8>, error: AesError) -> AesError {
+    zeroize_bytes(buffer);
+    buffer.clear();
+    error
+}
+
+/// AES block encryptor for a key of 16, 24 or 32 bytes
+/// 
+/// ### Arguments
+/// 
+/// * `key` - AES key
+fn block_encryptor(key: &[u8]) -> Result<Box<dyn BlockEncryptor>, AesError> {
+    match key.len() {
+        16 => Ok(Box::new(AesSafe128Encryptor::new(key))),
+        24 => Ok(Box::new(AesSafe192Encryptor::new(key))),
+        32 => Ok(Box::new(AesSafe256Encryptor::new(key))),
+        _ => Err(AesError::InvalidLength)
+    }
+}
+
+/// AES block decryptor for a key of 16, 24 or 32 bytes
+/// 
+/// ### Arguments
+/// 
+/// * `key` - AES key
+fn block_decryptor(key: &[u8]) -> Result<Box<dyn BlockDecryptor>, AesError> {
+    match key.len() {
+        16 => Ok(Box::new(AesSafe128Decryptor::new(key))),
+        24 => Ok(Box::new(AesSafe192Decryptor::new(key))),
+        32 => Ok(Box::new(AesSafe256Decryptor::new(key))),
+        _ => Err(AesError::InvalidLength)
+    }
+}
+
+/// Length in bytes of a key of the given size. SIV keys are two keys
+/// of the size, one for S2V and one for CTR
+/// 
+/// ### Arguments
+/// 
+/// * `key_size` - Cipher key size
+/// * `mode` - AES block mode
+fn key_length(key_size: KeySize, mode: &AESMode) -> usize {
+    let length = match key_size {
+        KeySize::KeySize128 => 16,
+        KeySize::KeySize192 => 24,
+        KeySize::KeySize256 => 32
+    };
+
+    match *mode {
+        AESMode::SIV => 2 * length,
+        _ => length
+    }
+}
+
+/// Length in bytes of the IV a mode takes. GCM takes none, as every
+/// message has a nonce of its own
+/// 
+/// ### Arguments
+/// 
+/// * `mode` - AES block mode
+fn iv_length(mode: &AESMode) -> usize {
+    match *mode {
+        AESMode::Counter | AESMode::CBC => CMAC_BLOCK_SIZE,
+        AESMode::GCM | AESMode::CCM { .. } | AESMode::SIV => 0
+    }
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod aes_test {
+
+    use std::time::Instant;
+    use rand::{ Rng, ChaChaRng, SeedableRng };
+    use crypto::aes::KeySize;
+    use crypto::aes_gcm::AesGcm;
+    use crypto::aead::{ AeadEncryptor, AeadDecryptor };
+    use crate::utils::encoding::hex_to_bytes;
+    use crate::utils::rng::os_rng;
+    use crate::utils::transform;
+    #[cfg(any(feature = "hashes-sha3", feature = "hashes-sha2"))]
+    use crate::hash::crypto::HashAlgorithm;
+    #[cfg(feature = "hashes-sha2")]
+    use crate::hash::kdf::pbkdf2;
+    use crate::encryption::{ CipherError, SymmetricEncryptor };
+    use crate::encryption::aes::{ self, AES, AESMode, AesError, Cmac, KeyBudget };
+
+    /// Example message of SP 800-38B, D.1 and D.3
+    const CMAC_MESSAGE: &'static str = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e5130c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710";
+
+    #[test]
+    fn counter_mode_encryption() {
+        let data = b"Hello World";
+        let mut aes_cipher = AES::new(KeySize::KeySize256, AESMode::Counter, None, &mut os_rng().unwrap());
+
+        let cipher = aes_cipher.encrypt(data).ok().unwrap();
+        let plain = aes_cipher.decrypt(&cipher).ok().unwrap();
+
+        assert_eq!(plain, data.to_vec());
+    }
+
+    #[test]
+    fn counter_mode_matches_sp800_38a_vectors() {
+        // F.5.1, CTR-AES128.Encrypt
+        let key = hex_to_bytes("2b7e151628aed2a6abf7158809cf4f3c").unwrap();
+        let counter = hex_to_bytes("f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff").unwrap();
+        let expected = hex_to_bytes("874d6191b620e3261bef6864990db6ce9806f66b7970fdff8617187bb9fffdff5ae4df3edbd5d35e5b4f09020db03eab1e031dda2fbe03d1792170a0f3009cee").unwrap();
+        let mut aes_cipher = AES::new_with_key(key, counter, KeySize::KeySize128, AESMode::Counter, None).unwrap();
+
+        assert_eq!(aes_cipher.encrypt(&hex_to_bytes(CMAC_MESSAGE).unwrap()).unwrap(), expected);
+    }
+
+    #[test]
+    fn parallel_counter_mode_matches_sequential() {
+        let mut rng = ChaChaRng::from_seed(&[0x70, 0x63, 0x74]);
+        let mut data = vec![0; 100_003];
+        rng.fill_bytes(&mut data);
+
+        // Both counters carry out of their low bytes after one block
+        let key = hex_to_bytes("2b7e151628aed2a6abf7158809cf4f3c").unwrap();
+        let mut carrying = vec![0; 8];
+        carrying.extend_from_slice(&[0xff; 8]);
+        let vector = hex_to_bytes("f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff").unwrap();
+        let random = AES::new(KeySize::KeySize256, AESMode::Counter, None, &mut rng);
+
+        let setups = [
+            (key.clone(), carrying, KeySize::KeySize128),
+            (key, vector, KeySize::KeySize128),
+            (random.key().to_vec(), random.initialization_vector().to_vec(), KeySize::KeySize256)
+        ];
+
+        // Each message goes through fresh instances under the same key and IV
+        let instance = |setup: &(Vec<u8>, Vec<u8>, KeySize)| {
+            AES::new_with_key(setup.0.clone(), setup.1.clone(), setup.2, AESMode::Counter, None).unwrap()
+        };
+
+        for setup in setups.iter() {
+            for &length in [0, 1, 16, 64, 1000, data.len()].iter() {
+                let sequential = instance(setup).encrypt(&data[..length]).unwrap();
+
+                for &threads in [0, 1, 3, 4, 7].iter() {
+                    let parallel = instance(setup).encrypt_parallel(&data[..length], threads).unwrap();
+
+                    assert_eq!(parallel, sequential, "{} bytes on {} threads", length, threads);
+                }
+
+                assert_eq!(instance(setup).decrypt(&sequential).unwrap(), data[..length].to_vec());
+            }
+        }
+
+        let mut gcm = AES::new(KeySize::KeySize128, AESMode::GCM, None, &mut rng);
+        assert_eq!(gcm.encrypt_parallel(&data, 4), Err(AesError::InvalidLength));
+    }
+
+    #[test]
+    fn counter_overflow_is_refused() {
+        // A 4-bit counter starting at 13 leaves blocks 13, 14 and 15
+        let mut iv = vec![0x42; 15];
+        iv.push(0x5d);
+        let mut aes_cipher = AES::new_with_key(vec![7; 16], iv.clone(), KeySize::KeySize128, AESMode::Counter, None).unwrap()
+            .with_counter_bits(4).unwrap();
+
+        assert_eq!(aes_cipher.encrypt(&[0; 49]), Err(AesError::KeyExhausted));
+        assert_eq!(aes_cipher.encrypt_in_place(&mut vec![0; 49]), Err(AesError::KeyExhausted));
+        assert_eq!(aes_cipher.encrypt_parallel(&[0; 49], 2), Err(AesError::KeyExhausted));
+        assert_eq!(aes_cipher.counter_blocks(), 0);
+
+        let ciphertext = aes_cipher.encrypt(&[0; 48]).unwrap();
+        assert_eq!(aes_cipher.decrypt(&ciphertext).unwrap(), vec![0; 48]);
+        assert_eq!(aes_cipher.counter_blocks(), 3);
+
+        // The keystream is used up, so no second message can reuse it
+        assert_eq!(aes_cipher.encrypt(&[0; 20]), Err(AesError::KeyExhausted));
+        assert_eq!(aes_cipher.encrypt_in_place(&mut vec![0; 20]), Err(AesError::KeyExhausted));
+        assert_eq!(aes_cipher.encrypt_parallel(&[0; 20], 2), Err(AesError::KeyExhausted));
+        assert!(aes_cipher.ctr_stream().is_err());
+        assert_eq!(aes_cipher.counter_blocks(), 3);
+
+        // A stream takes the whole field
+        let mut streaming = AES::new_with_key(vec![7; 16], iv.clone(), KeySize::KeySize128, AESMode::Counter, None).unwrap()
+            .with_counter_bits(4).unwrap();
+        assert_eq!(streaming.ctr_stream().unwrap().process(&[0; 48]), ciphertext);
+        assert_eq!(streaming.counter_blocks(), 3);
+        assert_eq!(streaming.encrypt(&[0; 1]), Err(AesError::KeyExhausted));
+
+        // The whole block counts by default, and wraps only at 2^128
+        let wide = || AES::new_with_key(vec![7; 16], vec![0xff; 16], KeySize::KeySize128, AESMode::Counter, None).unwrap();
+        assert!(wide().encrypt(&[0; 16]).is_ok());
+        assert_eq!(wide().encrypt(&[0; 17]), Err(AesError::KeyExhausted));
+
+        // A fresh key and IV start the counter over, with a new keystream
+        aes_cipher.rekey(&mut ChaChaRng::from_seed(&[0x63, 0x74, 0x72]));
+        assert_eq!(aes_cipher.counter_blocks(), 0);
+        assert!(aes_cipher.encrypt(&[0; 16]).unwrap() != ciphertext[..16].to_vec());
+
+        for &bits in [0, 129].iter() {
+            let aes_cipher = AES::new_with_key(vec![7; 16], iv.clone(), KeySize::KeySize128, AESMode::Counter, None).unwrap();
+            assert!(aes_cipher.with_counter_bits(bits).is_err());
+        }
+    }
+
+    #[test]
+    #[ignore] // slow: run with --ignored --release
+    fn parallel_counter_mode_is_faster() {
+        let mut aes_cipher = AES::new(KeySize::KeySize256, AESMode::Counter, None, &mut os_rng().unwrap());
+        let mut twin = AES::new_with_key(aes_cipher.key().to_vec(), aes_cipher.initialization_vector().to_vec(), KeySize::KeySize256, AESMode::Counter, None).unwrap();
+        let data = vec![0x5a; 16 * 1024 * 1024];
+
+        let start = Instant::now();
+        let sequential = aes_cipher.encrypt(&data).unwrap();
+        let sequential_time = start.elapsed();
+
+        let start = Instant::now();
+        let parallel = twin.encrypt_parallel(&data, 4).unwrap();
+        let parallel_time = start.elapsed();
+
+        assert_eq!(parallel, sequential);
+        assert!(parallel_time < sequential_time, "{:?} on 4 threads against {:?} on one", parallel_time, sequential_time);
+    }
+
+    #[test]
+    fn counter_mode_streams_large_inputs() {
+        let mut rng = ChaChaRng::from_seed(&[0x63, 0x74, 0x72]);
+        let mut aes_cipher = AES::new(KeySize::KeySize256, AESMode::Counter, None, &mut rng);
+        let mut data = vec![0; 1 << 20];
+        rng.fill_bytes(&mut data);
+
+        let ciphertext = aes_cipher.encrypt(&data).unwrap();
+        assert_eq!(ciphertext.len(), data.len());
+        assert_eq!(aes_cipher.decrypt(&ciphertext).unwrap(), data);
+
+        // Pieces that split blocks and the old 4096 byte buffer join up to the one-shot output
+        let twin = || AES::new_with_key(aes_cipher.key().to_vec(), aes_cipher.initialization_vector().to_vec(), KeySize::KeySize256, AESMode::Counter, None).unwrap();
+        let mut stream = twin().ctr_stream().unwrap();
+        let mut streamed = Vec::new();
+        let mut rest = &data[..];
+
+        for &length in [0, 1, 15, 17, 4095, 4097, 65536].iter().cycle() {
+            if rest.is_empty() {
+                break;
+            }
+
+            let (piece, remaining) = rest.split_at(length.min(rest.len()));
+            streamed.extend(stream.process(piece));
+            rest = remaining;
+        }
+
+        assert_eq!(streamed, ciphertext);
+
+        let mut opening = twin().ctr_stream().unwrap();
+        let opened: Vec<u8> = ciphertext.chunks(1000).flat_map(|piece| opening.process(piece)).collect();
+        assert_eq!(opened, data);
+
+        let mut gcm = AES::new(KeySize::KeySize256, AESMode::GCM, None, &mut rng);
+        assert!(gcm.ctr_stream().is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "hashes-sha3")]
+    fn authenticated_ctr_round_trips() {
+        let mut rng = ChaChaRng::from_seed(&[0xe7, 0x4d]);
+        let aes_cipher = AES::new(KeySize::KeySize256, AESMode::Counter, None, &mut rng);
+        let mut composed = aes_cipher.authenticated_ctr(HashAlgorithm::Sha3_256, &mut rng).unwrap();
+
+        for &length in [0, 1, 16, 17, 64, 1000].iter() {
+            let data: Vec<u8> = (0..length).map(|i| i as u8).collect();
+            let sealed = composed.encrypt(&data).unwrap();
+
+            assert_eq!(sealed.len(), aes::AUTHENTICATED_CTR_IV_SIZE + length + 32);
+            assert_eq!(composed.decrypt(&sealed).unwrap(), data);
+        }
+
+        // Every message goes under its own IV
+        let first = composed.encrypt(b"transfer 100 to alice").unwrap();
+        let second = composed.encrypt(b"transfer 100 to alice").unwrap();
+        assert!(first[..aes::AUTHENTICATED_CTR_IV_SIZE] != second[..aes::AUTHENTICATED_CTR_IV_SIZE]);
+
+        // The derived keys are not the instance key, so plain CTR can't read it
+        let iv = first[..aes::AUTHENTICATED_CTR_IV_SIZE].to_vec();
+        let plain = AES::new_with_key(aes_cipher.key().to_vec(), iv, KeySize::KeySize256, AESMode::Counter, None).unwrap();
+        assert!(plain.decrypt(&first[aes::AUTHENTICATED_CTR_IV_SIZE..first.len() - 32]).unwrap() != b"transfer 100 to alice".to_vec());
+
+        let gcm = AES::new(KeySize::KeySize256, AESMode::GCM, None, &mut rng);
+        assert!(gcm.authenticated_ctr(HashAlgorithm::Sha3_256, &mut rng).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "hashes-sha3")]
+    fn authenticated_ctr_rejects_flipped_bits() {
+        let mut rng = ChaChaRng::from_seed(&[0xe7, 0x4e]);
+        let aes_cipher = AES::new(KeySize::KeySize128, AESMode::Counter, None, &mut rng);
+        let mut composed = aes_cipher.authenticated_ctr(HashAlgorithm::Sha3_256, &mut rng).unwrap();
+        let sealed = composed.encrypt(b"transfer 100 to alice, 40 to bob").unwrap();
+
+        // In the IV, the ciphertext and the tag alike
+        for index in 0..sealed.len() {
+            for bit in 0..8 {
+                let mut corrupted = sealed.clone();
+                corrupted[index] ^= 1 << bit;
+                assert_eq!(composed.decrypt(&corrupted), Err(AesError::AuthenticationFailed));
+            }
+        }
+
+        assert!(composed.decrypt(&sealed[..sealed.len() - 1]).is_err());
+        assert!(composed.decrypt(&sealed[..aes::AUTHENTICATED_CTR_IV_SIZE + 31]).is_err());
+
+        // Another instance key gives another MAC key
+        let other = AES::new(KeySize::KeySize128, AESMode::Counter, None, &mut rng);
+        assert!(other.authenticated_ctr(HashAlgorithm::Sha3_256, &mut rng).unwrap().decrypt(&sealed).is_err());
+
+        composed.clear();
+        assert!(composed.decrypt(&sealed).is_err());
+        assert!(composed.encrypt(b"transfer").is_err());
+    }
+
+    #[test]
+    fn cbc_mode_matches_sp800_38a_vectors() {
+        // F.2.1 and F.2.5, CBC-AES128 and CBC-AES256, followed here by a block of padding
+        let vectors = [
+            ("2b7e151628aed2a6abf7158809cf4f3c", KeySize::KeySize128, "7649abac8119b246cee98e9b12e9197d5086cb9b507219ee95db113a917678b273bed6b8e3c1743b7116e69e222295163ff1caa1681fac09120eca307586e1a7"),
+            ("603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4", KeySize::KeySize256, "f58c4c04d6e5f1ba779eabfb5f7bfbd69cfc4e967edb808d679f777bc6702c7d39f23369a9d9bacfa530e26304231461b2eb05e2c39be9fcda6c19078c6a9d1b")
+        ];
+
+        let message = hex_to_bytes(CMAC_MESSAGE).unwrap();
+        let iv = hex_to_bytes("000102030405060708090a0b0c0d0e0f").unwrap();
+
+        for &(key, key_size, expected) in vectors.iter() {
+            let mut aes_cipher = AES::new_with_key(hex_to_bytes(key).unwrap(), iv.clone(), key_size, AESMode::CBC, None).unwrap();
+            let ciphertext = aes_cipher.encrypt(&message).unwrap();
+
+            assert_eq!(ciphertext.len(), message.len() + 16);
+            assert_eq!(ciphertext[..message.len()].to_vec(), hex_to_bytes(expected).unwrap());
+            assert_eq!(aes_cipher.decrypt(&ciphertext).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn cbc_mode_checks_its_padding() {
+        let mut rng = os_rng().unwrap();
+        let mut aes_cipher = AES::new(KeySize::KeySize192, AESMode::CBC, None, &mut rng);
+
+        for &length in [0, 15, 16, 17].iter() {
+            let data = vec![0x42; length];
+            let ciphertext = aes_cipher.encrypt(&data).unwrap();
+
+            assert_eq!(ciphertext.len(), (length / 16 + 1) * 16);
+            assert_eq!(aes_cipher.decrypt(&ciphertext).unwrap(), data);
+
+            // Every message chains from the IV, so each needs a new one
+            assert_eq!(aes_cipher.encrypt(&data), Err(AesError::KeyExhausted));
+            assert_eq!(aes_cipher.encrypt_in_place(&mut data.clone()), Err(AesError::KeyExhausted));
+            aes_cipher.rekey(&mut rng);
+        }
+
+        // Changing the last byte of the IV changes only the last padding byte
+        let single = aes_cipher.encrypt(&[0x42; 15]).unwrap();
+        let mut iv = aes_cipher.initialization_vector().to_vec();
+        iv[15] ^= 0x03;
+        let wrong_iv = AES::new_with_key(aes_cipher.key().to_vec(), iv, KeySize::KeySize192, AESMode::CBC, None).unwrap();
+        assert!(wrong_iv.decrypt(&single).is_err());
+
+        aes_cipher.rekey(&mut rng);
+        let ciphertext = aes_cipher.encrypt(&[0x42; 20]).unwrap();
+        let mut corrupted = ciphertext.clone();
+        corrupted[15] ^= 1;
+
+        assert!(aes_cipher.decrypt(&corrupted).is_err());
+        assert!(aes_cipher.decrypt(&ciphertext[..31]).is_err());
+        assert!(aes_cipher.decrypt(&[]).is_err());
+
+        // The padding itself is checked byte by byte
+        assert_eq!(transform::pkcs7_unpad(&[1, 2, 3, 4, 3, 3, 3, 3], 8), Some(vec![1, 2, 3, 4, 3]));
+        assert_eq!(transform::pkcs7_unpad(&[1, 2, 3, 4, 4, 2, 4, 4], 8), None);
+        assert_eq!(transform::pkcs7_unpad(&[1, 2, 3, 4, 5, 6, 7, 0], 8), None);
+        assert_eq!(transform::pkcs7_unpad(&[9; 8], 8), None);
+        assert_eq!(transform::pkcs7_unpad(&[8; 8], 8), Some(Vec::new()));
+    }
+
+    #[test]
+    fn gcm_mode_encryption() {
+        let data = b"Hello World";
+        let aad = Some([3u8; 10].to_vec());
+        let mut aes_cipher = AES::new(KeySize::KeySize256, AESMode::GCM, aad, &mut os_rng().unwrap());
+
+        let cipher = aes_cipher.encrypt(data).ok().unwrap();
+    }
+
+    #[test]
+    fn gcm_mode_uses_the_instance_key() {
+        let mut rng = ChaChaRng::from_seed(&[0x67, 0x63, 0x6d]);
+        let mut first = AES::new(KeySize::KeySize256, AESMode::GCM, None, &mut rng);
+        let second = AES::new(KeySize::KeySize256, AESMode::GCM, None, &mut rng);
+        let copy = AES::new_with_key(first.key().to_vec(), Vec::new(), KeySize::KeySize256, AESMode::GCM, None).unwrap();
+        let zero_key = AES::new_with_key(vec![0; 32], Vec::new(), KeySize::KeySize256, AESMode::GCM, None).unwrap();
+
+        let ciphertext = first.encrypt(b"Hello World").unwrap();
+
+        // Only the key the message was sealed under opens it
+        assert_eq!(copy.decrypt(&ciphertext).unwrap(), b"Hello World".to_vec());
+        assert!(second.decrypt(&ciphertext).is_err());
+        assert!(zero_key.decrypt(&ciphertext).is_err());
+    }
+
+    #[test]
+    fn gcm_messages_carry_fresh_nonces() {
+        let mut aes_cipher = AES::new(KeySize::KeySize128, AESMode::GCM, Some(b"header".to_vec()), &mut os_rng().unwrap());
+
+        let first = aes_cipher.encrypt(b"same message").unwrap();
+        let second = aes_cipher.encrypt(b"same message").unwrap();
+
+        assert_eq!(first.len(), aes::GCM_NONCE_SIZE + 12 + aes::GCM_TAG_SIZE);
+        assert!(first[..aes::GCM_NONCE_SIZE] != second[..aes::GCM_NONCE_SIZE]);
+        assert!(first != second);
+        assert_eq!(aes_cipher.decrypt(&first).unwrap(), b"same message".to_vec());
+        assert_eq!(aes_cipher.decrypt(&second).unwrap(), b"same message".to_vec());
+        let empty = aes_cipher.encrypt(b"").unwrap();
+        assert_eq!(aes_cipher.decrypt(&empty).unwrap(), Vec::<u8>::new());
+
+        // A change to the nonce, the ciphertext or the tag is caught
+        for &index in [0, aes::GCM_NONCE_SIZE + 3, first.len() - 1].iter() {
+            let mut tampered = first.clone();
+            tampered[index] ^= 1;
+            assert_eq!(aes_cipher.decrypt(&tampered), Err(AesError::AuthenticationFailed), "byte {}", index);
+        }
+
+        assert!(aes_cipher.decrypt(&first[..aes::GCM_NONCE_SIZE + aes::GCM_TAG_SIZE - 1]).is_err());
+
+        // No more messages once the key's nonces are used up
+        aes_cipher.nonces.exhaust();
+        assert!(aes_cipher.encrypt(b"same message").is_err());
+    }
+
+    #[test]
+    fn in_place_matches_allocating_api() {
+        let modes = || vec![
+            AESMode::Counter,
+            AESMode::CBC,
+            AESMode::GCM,
+            AESMode::CCM { tag_len: 16, nonce_len: 12 },
+            AESMode::CCM { tag_len: 8, nonce_len: 13 },
+            AESMode::SIV
+        ];
+
+        for (allocating_mode, in_place_mode) in modes().into_iter().zip(modes()) {
+            // Twin instances with the same key, IV and nonces
+            let mut allocating = AES::new(KeySize::KeySize256, allocating_mode, Some(b"header".to_vec()), &mut ChaChaRng::from_seed(&[0x69, 0x70]));
+            let mut in_place = AES::new(KeySize::KeySize256, in_place_mode, Some(b"header".to_vec()), &mut ChaChaRng::from_seed(&[0x69, 0x70]));
+
+            for &length in [0, 1, 15, 16, 17, 100, 1000].iter() {
+                // Rekeyed alike, as Counter mode takes one message per key and IV
+                allocating.rekey(&mut ChaChaRng::from_seed(&[0x69, length as u32]));
+                in_place.rekey(&mut ChaChaRng::from_seed(&[0x69, length as u32]));
+
+                let data: Vec<u8> = (0..length).map(|i| (i * 7) as u8).collect();
+                let ciphertext = allocating.encrypt(&data).unwrap();
+
+                let mut buffer = data.clone();
+                in_place.encrypt_in_place(&mut buffer).unwrap();
+                assert_eq!(buffer, ciphertext);
+
+                in_place.decrypt_in_place(&mut buffer).unwrap();
+                assert_eq!(buffer, data);
+                assert_eq!(allocating.decrypt(&ciphertext).unwrap(), data);
+            }
+        }
+    }
+
+    #[test]
+    fn symmetric_encryptors_round_trip() {
+        let mut rng = ChaChaRng::from_seed(&[0x73, 0x65]);
+        let mut ciphers: Vec<Box<dyn SymmetricEncryptor>> = vec![
+            Box::new(AES::new(KeySize::KeySize256, AESMode::GCM, None, &mut rng)),
+            Box::new(AES::new(KeySize::KeySize128, AESMode::GCM, Some(b"instance".to_vec()), &mut rng))
+        ];
+
+        for cipher in ciphers.iter_mut() {
+            for &length in [0, 1, 16, 100].iter() {
+                let data = vec![0x3c; length];
+                let ciphertext = cipher.encrypt(&data, &[]).unwrap();
+                assert_eq!(cipher.decrypt(&ciphertext, &[]).unwrap(), data);
+            }
+        }
+
+        // Associated data is per message in GCM
+        let gcm = &mut ciphers[0];
+        let ciphertext = gcm.encrypt(b"message", b"header").unwrap();
+        assert_eq!(gcm.decrypt(&ciphertext, b"header").unwrap(), b"message".to_vec());
+        assert_eq!(gcm.decrypt(&ciphertext, b"other header"), Err(CipherError::AuthenticationFailed));
+        assert_eq!(gcm.decrypt(&ciphertext, &[]), Err(CipherError::AuthenticationFailed));
+
+        // Counter and CBC modes take one message per key and IV, and no associated data
+        for mode in vec![AESMode::Counter, AESMode::CBC] {
+            let mut cipher: Box<dyn SymmetricEncryptor> = Box::new(AES::new(KeySize::KeySize256, mode, None, &mut rng));
+            assert_eq!(cipher.encrypt(b"message", b"header"), Err(CipherError::InvalidLength));
+            let ciphertext = cipher.encrypt(b"message", &[]).unwrap();
+            assert_eq!(cipher.decrypt(&ciphertext, &[]).unwrap(), b"message".to_vec());
+            assert_eq!(cipher.encrypt(b"message", &[]), Err(CipherError::KeyExhausted));
+        }
+    }
+
+    #[test]
+    fn symmetric_encryptor_keeps_the_instance_aad() {
+        let mut rng = ChaChaRng::from_seed(&[0x73, 0x66]);
+        let mut aes_cipher = AES::new(KeySize::KeySize128, AESMode::SIV, Some(b"instance".to_vec()), &mut rng);
+
+        let through_trait = SymmetricEncryptor::encrypt(&mut aes_cipher, b"message", b"instance").unwrap();
+        assert_eq!(aes_cipher.encrypt(b"message").unwrap(), through_trait);
+        assert_eq!(aes_cipher.decrypt(&through_trait).unwrap(), b"message".to_vec());
+    }
+
+    #[test]
+    fn in_place_rejects_what_the_allocating_api_does() {
+        let mut rng = ChaChaRng::from_seed(&[0x69, 0x71]);
+
+        // CBC has its padding broken through the first block, the others their tag
+        let cases = vec![
+            (AESMode::CBC, Some(15), AesError::InvalidPadding),
+            (AESMode::GCM, None, AesError::AuthenticationFailed),
+            (AESMode::CCM { tag_len: 16, nonce_len: 12 }, None, AesError::AuthenticationFailed),
+            (AESMode::SIV, Some(0), AesError::AuthenticationFailed)
+        ];
+
+        for (mode, index, error) in cases {
+            let mut aes_cipher = AES::new(KeySize::KeySize128, mode, None, &mut rng);
+            let mut sealed = aes_cipher.encrypt(b"sixteen byte msg and some").unwrap();
+            let index = index.unwrap_or(sealed.len() - 1);
+            sealed[index] ^= 0x01;
+
+            let mut buffer = sealed.clone();
+            assert_eq!(aes_cipher.decrypt(&sealed), Err(error));
+            assert_eq!(aes_cipher.decrypt_in_place(&mut buffer), Err(error));
+            assert!(buffer == sealed || buffer.is_empty());
+
+            // An empty buffer is too short to hold any of them
+            assert_eq!(aes_cipher.decrypt_in_place(&mut Vec::new()), Err(AesError::InvalidLength));
+        }
+
+        // The budget applies in place too
+        let budget = KeyBudget { bytes: 16, messages: 10 };
+        let mut aes_cipher = AES::new(KeySize::KeySize128, AESMode::GCM, None, &mut rng).with_budget(budget);
+        aes_cipher.encrypt_in_place(&mut vec![0; 16]).unwrap();
+        assert_eq!(aes_cipher.encrypt_in_place(&mut vec![0; 1]), Err(AesError::KeyExhausted));
+    }
+
+    #[test]
+    fn gcm_in_place_matches_the_specification() {
+        // GCM specification test case 4, AES-128 with associated data
+        let cipher = aes::block_encryptor(&hex_to_bytes("feffe9928665731c6d6a8f9467308308").unwrap()).unwrap();
+        let nonce = hex_to_bytes("cafebabefacedbaddecaf888").unwrap();
+        let aad = hex_to_bytes("feedfacedeadbeeffeedfacedeadbeefabaddad2").unwrap();
+        let plaintext = hex_to_bytes("d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a721c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b39").unwrap();
+        let ciphertext = hex_to_bytes("42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e21d514b25466931c7d8f6a5aac84aa051ba30b396a0aac973d58e091").unwrap();
+        let tag = hex_to_bytes("5bc94fbc3221a5db94fae95ae7121a47").unwrap();
+
+        let mut buffer = plaintext.clone();
+        assert_eq!(aes::gcm_seal_in_place(&*cipher, &nonce, &aad, &mut buffer).to_vec(), tag);
+        assert_eq!(buffer, ciphertext);
+
+        assert!(aes::gcm_open_in_place(&*cipher, &nonce, &aad, &mut buffer, &tag));
+        assert_eq!(buffer, plaintext);
+
+        let mut forged = ciphertext.clone();
+        forged[0] ^= 0x80;
+        assert!(!aes::gcm_open_in_place(&*cipher, &nonce, &aad, &mut forged, &tag));
+        assert_eq!(forged[1..], ciphertext[1..]);
+    }
+
+    #[test]
+    fn gcm_in_place_matches_aes_gcm() {
+        let mut rng = ChaChaRng::from_seed(&[0x67, 0x63, 0x6d]);
+        let sizes = [(KeySize::KeySize128, 16), (KeySize::KeySize192, 24), (KeySize::KeySize256, 32)];
+        let lengths = (0..70).chain(vec![255, 256, 257, 1000, 4097]);
+
+        for (index, length) in lengths.enumerate() {
+            let (key_size, key_len) = sizes[index % sizes.len()];
+            let key: Vec<u8> = (0..key_len).map(|_| rng.gen()).collect();
+            let nonce: Vec<u8> = (0..aes::GCM_NONCE_SIZE).map(|_| rng.gen()).collect();
+            let aad: Vec<u8> = (0..index % 40).map(|_| rng.gen()).collect();
+            let plaintext: Vec<u8> = (0..length).map(|_| rng.gen()).collect();
+            let cipher = aes::block_encryptor(&key).unwrap();
+
+            let mut ciphertext = vec![0; length];
+            let mut tag = [0; aes::GCM_TAG_SIZE];
+            AesGcm::new(key_size, &key, &nonce, &aad).encrypt(&plaintext, &mut ciphertext, &mut tag);
+
+            let mut buffer = plaintext.clone();
+            assert_eq!(aes::gcm_seal_in_place(&*cipher, &nonce, &aad, &mut buffer), tag, "tag of {} bytes", length);
+            assert_eq!(buffer, ciphertext, "ciphertext of {} bytes", length);
+
+            assert!(aes::gcm_open_in_place(&*cipher, &nonce, &aad, &mut buffer, &tag));
+            assert_eq!(buffer, plaintext);
+
+            // What one side seals the other opens, through the instance
+            let mut in_place = AES::new_with_key(key.clone(), vec![], key_size, AESMode::GCM, Some(aad.clone())).unwrap();
+            let mut sealed = plaintext.clone();
+            in_place.encrypt_in_place(&mut sealed).unwrap();
+
+            let (sealed_nonce, rest) = sealed.split_at(aes::GCM_NONCE_SIZE);
+            let (rest, sealed_tag) = rest.split_at(length);
+            let mut opened = vec![0; length];
+            assert!(AesGcm::new(key_size, &key, sealed_nonce, &aad).decrypt(rest, &mut opened, sealed_tag));
+            assert_eq!(opened, plaintext);
+
+            let mut buffer = in_place.encrypt(&plaintext).unwrap();
+            in_place.decrypt_in_place(&mut buffer).unwrap();
+            assert_eq!(buffer, plaintext);
+        }
+
+        // The 32 bit counter starts at 2, leaving 2^32 - 2 blocks a message
+        let most = ((1u64 << 32) - 2) as usize * aes::CMAC_BLOCK_SIZE;
+        assert_eq!(aes::check_gcm_length(0), Ok(()));
+        assert_eq!(aes::check_gcm_length(most), Ok(()));
+        assert_eq!(aes::check_gcm_length(most + 1), Err(AesError::InvalidLength));
+    }
+
+    #[test]
+    #[cfg(feature = "hashes-sha2")]
+    fn password_keys_follow_password_and_salt() {
+        let mut sender = AES::from_password(b"correct horse", b"salt of one", 1000, KeySize::KeySize256, AESMode::GCM).unwrap();
+        let receiver = AES::from_password(b"correct horse", b"salt of one", 1000, KeySize::KeySize256, AESMode::GCM).unwrap();
+
+        assert_eq!(sender.key().len(), 32);
+        assert_eq!(sender.key(), receiver.key());
+        assert_eq!(receiver.decrypt(&sender.encrypt(b"attack at dawn").unwrap()).unwrap(), b"attack at dawn".to_vec());
+
+        // The key is PBKDF2-HMAC-SHA256 output, and the IV follows it
+        let counter = AES::from_password(b"correct horse", b"salt of one", 1000, KeySize::KeySize128, AESMode::Counter).unwrap();
+        let derived = pbkdf2(b"correct horse", b"salt of one", 1000, 32, HashAlgorithm::Sha256).unwrap();
+        assert_eq!(counter.key(), &derived[..16]);
+        assert_eq!(counter.initialization_vector(), &derived[16..]);
+
+        let other_salt = AES::from_password(b"correct horse", b"salt of two", 1000, KeySize::KeySize256, AESMode::GCM).unwrap();
+        let other_password = AES::from_password(b"correct horsf", b"salt of one", 1000, KeySize::KeySize256, AESMode::GCM).unwrap();
+        assert!(other_salt.key() != sender.key());
+        assert!(other_password.key() != sender.key());
+        assert!(other_salt.decrypt(&sender.encrypt(b"attack at dawn").unwrap()).is_err());
+
+        assert!(AES::from_password(b"correct horse", b"salt of one", 0, KeySize::KeySize256, AESMode::GCM).is_err());
+    }
+
+    #[test]
+    fn key_budget_forces_rekeying() {
+        let mut rng = ChaChaRng::from_seed(&[0x72, 0x6b]);
+        let budget = KeyBudget { bytes: 40, messages: 3 };
+        let mut aes_cipher = AES::new(KeySize::KeySize128, AESMode::GCM, None, &mut rng).with_budget(budget);
+
+        // Three messages fit, a fourth doesn't
+        let sealed = aes_cipher.encrypt(b"first").unwrap();
+        aes_cipher.encrypt(b"second").unwrap();
+        aes_cipher.encrypt(b"third").unwrap();
+        assert_eq!(aes_cipher.encrypt(b"fourth"), Err(AesError::KeyExhausted));
+
+        // Decryption doesn't count, and still works under the old key
+        assert_eq!(aes_cipher.decrypt(&sealed).unwrap(), b"first".to_vec());
+
+        let old_key = aes_cipher.key().to_vec();
+        aes_cipher.rekey(&mut rng);
+        assert!(aes_cipher.key() != &old_key[..]);
+        assert_eq!(aes_cipher.key().len(), 16);
+        assert!(aes_cipher.decrypt(&sealed).is_err());
+
+        let sealed = aes_cipher.encrypt(b"fourth").unwrap();
+        assert_eq!(aes_cipher.decrypt(&sealed).unwrap(), b"fourth".to_vec());
+
+        // The byte budget counts plaintext, up to and including the limit
+        let mut aes_cipher = AES::new(KeySize::KeySize256, AESMode::GCM, None, &mut rng).with_budget(budget);
+        aes_cipher.encrypt(&[0; 30]).unwrap();
+        assert_eq!(aes_cipher.encrypt(&[0; 11]), Err(AesError::KeyExhausted));
+        aes_cipher.encrypt(&[0; 10]).unwrap();
+        assert_eq!(aes_cipher.encrypt(&[0; 1]), Err(AesError::KeyExhausted));
+
+        // CBC takes a single message, and rekeying draws a new IV for the next
+        let mut aes_cipher = AES::new(KeySize::KeySize256, AESMode::CBC, None, &mut rng);
+        aes_cipher.encrypt(&[7; 40]).unwrap();
+        assert_eq!(aes_cipher.encrypt(&[7; 40]), Err(AesError::KeyExhausted));
+
+        let old_iv = aes_cipher.initialization_vector().to_vec();
+        aes_cipher.rekey(&mut rng);
+        assert!(aes_cipher.initialization_vector() != &old_iv[..]);
+
+        let ciphertext = aes_cipher.encrypt(&[7; 40]).unwrap();
+        assert_eq!(aes_cipher.decrypt(&ciphertext).unwrap(), vec![7; 40]);
+    }
+
+    #[test]
+    fn default_budgets_follow_the_mode() {
+        let gcm = KeyBudget::for_mode(&AESMode::GCM);
+        assert_eq!(gcm, KeyBudget { bytes: 1 << 36, messages: 1 << 32 });
+        assert_eq!(KeyBudget::for_mode(&AESMode::CCM { tag_len: 16, nonce_len: 12 }), gcm);
+        assert_eq!(KeyBudget::for_mode(&AESMode::Counter).bytes, 1 << 36);
+        assert_eq!(KeyBudget::for_mode(&AESMode::Counter).messages, u64::max_value());
+    }
+
+    #[test]
+    fn debug_output_leaves_out_the_key() {
+        let mut rng = ChaChaRng::from_seed(&[0x64, 0x62, 0x67]);
+        let mut aes_cipher = AES::new(KeySize::KeySize256, AESMode::Counter, None, &mut rng);
+        aes_cipher.encrypt(b"counted").unwrap();
+        let output = format!("{:?}", aes_cipher);
+
+        assert!(output.contains("Counter"));
+        assert!(output.contains("key_bits: 256"));
+        assert!(output.contains("history_messages: 1"));
+        assert!(!output.contains(&format!("{:?}", aes_cipher.key())));
+        assert!(!output.contains(&format!("{:?}", aes_cipher.initialization_vector())));
+    }
+
+    #[test]
+    fn generated_keys_and_ivs_have_exact_lengths() {
+        let mut rng = ChaChaRng::from_seed(&[0x6c, 0x65, 0x6e]);
+
+        for &(key_size, length) in [(KeySize::KeySize128, 16), (KeySize::KeySize192, 24), (KeySize::KeySize256, 32)].iter() {
+            for _ in 0..300 {
+                let counter = AES::new(key_size, AESMode::Counter, None, &mut rng);
+                let gcm = AES::new(key_size, AESMode::GCM, None, &mut rng);
+
+                assert_eq!(counter.key().len(), length);
+                assert_eq!(counter.initialization_vector().len(), 16);
+                assert_eq!(gcm.key().len(), length);
+                assert!(gcm.initialization_vector().is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn ccm_matches_rfc3610_vectors() {
+        let cipher = aes::block_encryptor(&hex_to_bytes("c0c1c2c3c4c5c6c7c8c9cacbcccdcecf").unwrap()).unwrap();
+        let header = hex_to_bytes("0001020304050607").unwrap();
+
+        // Packet vectors 1 to 3, with 8 byte headers and tags
+        let vectors = [
+            ("00000003020100a0a1a2a3a4a5", "08090a0b0c0d0e0f101112131415161718191a1b1c1d1e", "588c979a61c663d2f066d0c2c0f989806d5f6b61dac38417e8d12cfdf926e0"),
+            ("00000004030201a0a1a2a3a4a5", "08090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f", "72c91a36e135f8cf291ca894085c87e3cc15c439c9e43a3ba091d56e10400916"),
+            ("00000005040302a0a1a2a3a4a5", "08090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f20", "51b1e5f44a197d1da46b0f8e2d282ae871e838bb64da8596574adaa76fbd9fb0c5")
+        ];
+
+        for &(nonce, data, sealed) in vectors.iter() {
+            let nonce = hex_to_bytes(nonce).unwrap();
+            let data = hex_to_bytes(data).unwrap();
+            let sealed = hex_to_bytes(sealed).unwrap();
+
+            assert_eq!(aes::ccm_seal(&*cipher, &nonce, &header, &data, 8), sealed);
+            assert_eq!(aes::ccm_open(&*cipher, &nonce, &header, &sealed, 8), Some(data));
+        }
+    }
+
+    #[test]
+    fn ccm_mode_authenticates_before_decrypting() {
+        let mut rng = os_rng().unwrap();
+
+        for &(tag_len, nonce_len) in [(4, 13), (8, 12), (16, 7), (10, 11)].iter() {
+            let mode = AESMode::CCM { tag_len: tag_len, nonce_len: nonce_len };
+            let mut aes_cipher = AES::new(KeySize::KeySize128, mode, Some(b"header".to_vec()), &mut rng);
+
+            for &length in [0, 1, 16, 33].iter() {
+                let data = vec![0x5c; length];
+                let ciphertext = aes_cipher.encrypt(&data).unwrap();
+
+                assert_eq!(ciphertext.len(), nonce_len + length + tag_len);
+                assert_eq!(aes_cipher.decrypt(&ciphertext).unwrap(), data);
+            }
+
+            // The nonce, the message and the tag are all covered
+            let ciphertext = aes_cipher.encrypt(b"sensor reading").unwrap();
+            assert!(aes_cipher.encrypt(b"sensor reading").unwrap() != ciphertext);
+
+            for &index in [0, nonce_len, ciphertext.len() - 1].iter() {
+                let mut tampered = ciphertext.clone();
+                tampered[index] ^= 1;
+                assert_eq!(aes_cipher.decrypt(&tampered), Err(AesError::AuthenticationFailed), "byte {}", index);
+            }
+
+            assert!(aes_cipher.decrypt(&ciphertext[..nonce_len + tag_len - 1]).is_err());
+        }
+
+        // As is the associated data
+        let mode = AESMode::CCM { tag_len: 8, nonce_len: 13 };
+        let mut sender = AES::new_with_key(vec![7; 16], Vec::new(), KeySize::KeySize128, mode, Some(b"header".to_vec())).unwrap();
+        let other_header = AES::new_with_key(vec![7; 16], Vec::new(), KeySize::KeySize128, AESMode::CCM { tag_len: 8, nonce_len: 13 }, Some(b"header!".to_vec())).unwrap();
+        assert!(other_header.decrypt(&sender.encrypt(b"sensor reading").unwrap()).is_err());
+
+        // Lengths outside RFC 3610 are refused
+        for &(tag_len, nonce_len) in [(3, 12), (5, 12), (18, 12), (8, 6), (8, 14)].iter() {
+            let mut aes_cipher = AES::new(KeySize::KeySize128, AESMode::CCM { tag_len: tag_len, nonce_len: nonce_len }, None, &mut rng);
+            assert!(aes_cipher.encrypt(b"sensor reading").is_err());
+        }
+    }
+
+    #[test]
+    fn siv_matches_rfc5297_vectors() {
+        // A.1, deterministic authenticated encryption with one AD component
+        let key = hex_to_bytes("fffefdfcfbfaf9f8f7f6f5f4f3f2f1f0f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff").unwrap();
+        let ad = hex_to_bytes("101112131415161718191a1b1c1d1e1f2021222324252627").unwrap();
+        let plaintext = hex_to_bytes("112233445566778899aabbccddee").unwrap();
+        let expected = hex_to_bytes("85632d07c6e8f37f950acd320a2ecc9340c02b9690c4dc04daef7f6afe5c").unwrap();
+
+        let mut aes_cipher = AES::new_with_key(key, Vec::new(), KeySize::KeySize128, AESMode::SIV, Some(ad)).unwrap();
+        assert_eq!(aes_cipher.encrypt(&plaintext).unwrap(), expected);
+        assert_eq!(aes_cipher.decrypt(&expected).unwrap(), plaintext);
+
+        // A.2, nonce-based, the nonce being the last AD component
+        let key = hex_to_bytes("7f7e7d7c7b7a79787776757473727170404142434445464748494a4b4c4d4e4f").unwrap();
+        let components = vec![
+            hex_to_bytes("00112233445566778899aabbccddeeffdeaddadadeaddadaffeeddccbbaa99887766554433221100").unwrap(),
+            hex_to_bytes("102030405060708090a0").unwrap(),
+            hex_to_bytes("09f911029d74e35bd84156c5635688c0").unwrap()
+        ];
+        let plaintext = hex_to_bytes("7468697320697320736f6d6520706c61696e7465787420746f20656e6372797074207573696e67205349562d414553").unwrap();
+        let expected = hex_to_bytes("7bdb6e3b432667eb06f4d14bff2fbd0fcb900f2fddbe404326601965c889bf17dba77ceb094fa663b7a3f748ba8af829ea64ad544a272e9c485b62a3fd5c0d").unwrap();
+
+        let mut aes_cipher = AES::new_with_key(key, Vec::new(), KeySize::KeySize128, AESMode::SIV, None)
+            .unwrap()
+            .with_aad_components(components);
+
+        assert_eq!(aes_cipher.encrypt(&plaintext).unwrap(), expected);
+        assert_eq!(aes_cipher.decrypt(&expected).unwrap(), plaintext);
+    }
+
+    #[test]
+    fn siv_mode_is_deterministic_and_authenticated() {
+        let mut rng = ChaChaRng::from_seed(&[0x51, 0x56]);
+        let mut aes_cipher = AES::new(KeySize::KeySize256, AESMode::SIV, Some(b"header".to_vec()), &mut rng);
+        assert_eq!(aes_cipher.key().len(), 64);
+
+        // The same message under the same AD seals the same way, any other doesn't
+        let sealed = aes_cipher.encrypt(b"wrapped key").unwrap();
+        assert_eq!(aes_cipher.encrypt(b"wrapped key").unwrap(), sealed);
+        assert!(aes_cipher.encrypt(b"wrapped kez").unwrap() != sealed);
+
+        for &length in [0, 1, 15, 16, 17, 100].iter() {
+            let data = vec![0x5a; length];
+            let ciphertext = aes_cipher.encrypt(&data).unwrap();
+            assert_eq!(ciphertext.len(), length + 16);
+            assert_eq!(aes_cipher.decrypt(&ciphertext).unwrap(), data);
+        }
+
+        // Any flipped bit is caught, in the IV or the ciphertext
+        for index in 0..sealed.len() {
+            let mut corrupted = sealed.clone();
+            corrupted[index] ^= 0x01;
+            assert_eq!(aes_cipher.decrypt(&corrupted), Err(AesError::AuthenticationFailed));
+        }
+
+        assert!(aes_cipher.decrypt(&sealed[..15]).is_err());
+
+        // Components are authenticated as separate strings, not joined
+        let key = aes_cipher.key().to_vec();
+        let mut split = AES::new_with_key(key.clone(), Vec::new(), KeySize::KeySize256, AESMode::SIV, None)
+            .unwrap()
+            .with_aad_components(vec![b"ab".to_vec(), b"c".to_vec()]);
+        let other_split = AES::new_with_key(key, Vec::new(), KeySize::KeySize256, AESMode::SIV, None)
+            .unwrap()
+            .with_aad_components(vec![b"a".to_vec(), b"bc".to_vec()]);
+
+        assert!(other_split.decrypt(&split.encrypt(b"wrapped key").unwrap()).is_err());
+
+        // GCM and CCM take a single component only
+        let mut gcm = AES::new(KeySize::KeySize128, AESMode::GCM, None, &mut rng)
+            .with_aad_components(vec![b"ab".to_vec(), b"c".to_vec()]);
+        assert!(gcm.encrypt(b"wrapped key").is_err());
+    }
+
+    #[test]
+    fn seeded_key_generation_is_reproducible() {
+        let first = AES::new(KeySize::KeySize256, AESMode::Counter, None, &mut ChaChaRng::from_seed(&[8, 6, 7, 5]));
+        let second = AES::new(KeySize::KeySize256, AESMode::Counter, None, &mut ChaChaRng::from_seed(&[8, 6, 7, 5]));
+
+        assert_eq!(first.key(), second.key());
+        assert_eq!(first.initialization_vector(), second.initialization_vector());
+    }
+
+    #[test]
+    fn supplied_key_decrypts_in_another_instance() {
+        let mut first = AES::new(KeySize::KeySize128, AESMode::Counter, None, &mut os_rng().unwrap());
+        let ciphertext = first.encrypt(b"read me later").unwrap();
+
+        let second = AES::new_with_key(first.key().to_vec(), first.initialization_vector().to_vec(), KeySize::KeySize128, AESMode::Counter, None).unwrap();
+        assert_eq!(second.decrypt(&ciphertext).unwrap(), b"read me later".to_vec());
+
+        // Keys and IVs of the wrong length for the size and mode are refused
+        assert!(AES::new_with_key(vec![0; 16], vec![0; 16], KeySize::KeySize256, AESMode::Counter, None).is_err());
+        assert!(AES::new_with_key(vec![0; 33], vec![0; 16], KeySize::KeySize256, AESMode::Counter, None).is_err());
+        assert!(AES::new_with_key(vec![0; 32], vec![0; 12], KeySize::KeySize256, AESMode::Counter, None).is_err());
+        assert!(AES::new_with_key(vec![0; 32], vec![0; 12], KeySize::KeySize256, AESMode::GCM, None).is_err());
+        assert!(AES::new_with_key(vec![0; 32], Vec::new(), KeySize::KeySize256, AESMode::GCM, None).is_ok());
+    }
+
+    #[test]
+    fn cmac_matches_sp800_38b_vectors() {
+        let message = hex_to_bytes(CMAC_MESSAGE).unwrap();
+        let vectors = [
+            ("2b7e151628aed2a6abf7158809cf4f3c", 0, "bb1d6929e95937287fa37d129b756746"),
+            ("2b7e151628aed2a6abf7158809cf4f3c", 16, "070a16b46b4d4144f79bdd9dd04a287c"),
+            ("2b7e151628aed2a6abf7158809cf4f3c", 40, "dfa66747de9ae63030ca32611497c827"),
+            ("2b7e151628aed2a6abf7158809cf4f3c", 64, "51f0bebf7e3b9d92fc49741779363cfe"),
+            ("603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4", 0, "028962f61b7bf89efc6b551f4667d983"),
+            ("603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4", 16, "28a7023f452e8f82bd4bf28d8c37c35c"),
+            ("603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4", 40, "aaf3d8f1de5640c232f5b169b9c911e6"),
+            ("603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4", 64, "e1992190549f6ed5696a2c056c315410")
+        ];
+
+        for &(key, length, tag) in vectors.iter() {
+            let key = hex_to_bytes(key).unwrap();
+            assert_eq!(aes::cmac(&key, &message[..length]).unwrap().to_vec(), hex_to_bytes(tag).unwrap(), "{} bytes", length);
+        }
+    }
+
+    #[test]
+    fn key_wrap_matches_rfc3394_vectors() {
+        let kek_128 = "000102030405060708090a0b0c0d0e0f";
+        let kek_192 = "000102030405060708090a0b0c0d0e0f1011121314151617";
+        let kek_256 = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+        let key_128 = "00112233445566778899aabbccddeeff";
+        let key_192 = "00112233445566778899aabbccddeeff0001020304050607";
+        let key_256 = "00112233445566778899aabbccddeeff000102030405060708090a0b0c0d0e0f";
+
+        // Sections 4.1 to 4.6
+        let vectors = [
+            (kek_128, key_128, "1fa68b0a8112b447aef34bd8fb5a7b829d3e862371d2cfe5"),
+            (kek_192, key_128, "96778b25ae6ca435f92b5b97c050aed2468ab8a17ad84e5d"),
+            (kek_256, key_128, "64e8c3f9ce0f5ba263e9777905818a2a93c8191e7d6e8ae7"),
+            (kek_192, key_192, "031d33264e15d33268f24ec260743edce1c6c7ddee725a936ba814915c6762d2"),
+            (kek_256, key_192, "a8f9bc1612c68b3ff6e6f4fbe30e71e4769c8b80a32cb8958cd5d17d6b254da1"),
+            (kek_256, key_256, "28c9f404c4b810f4cbccb35cfb87f8263f5786e2d80ed326cbc7f0e71a99f43bfb988b9b7a02dd21")
+        ];
+
+        for &(kek, key, wrapped) in vectors.iter() {
+            let kek = hex_to_bytes(kek).unwrap();
+            let key = hex_to_bytes(key).unwrap();
+            let wrapped = hex_to_bytes(wrapped).unwrap();
+
+            assert_eq!(aes::key_wrap(&kek, &key).unwrap(), wrapped);
+            assert_eq!(aes::key_unwrap(&kek, &wrapped).unwrap(), key);
+        }
+    }
+
+    #[test]
+    fn key_unwrap_checks_integrity() {
+        let kek = hex_to_bytes("000102030405060708090a0b0c0d0e0f").unwrap();
+        let wrapped = hex_to_bytes("1fa68b0a8112b447aef34bd8fb5a7b829d3e862371d2cfe5").unwrap();
+
+        for index in 0..wrapped.len() {
+            let mut tampered = wrapped.clone();
+            tampered[index] ^= 0x10;
+            assert!(aes::key_unwrap(&kek, &tampered).is_err(), "byte {}", index);
+        }
+
+        let mut other_kek = kek.clone();
+        other_kek[0] ^= 1;
+
+        assert!(aes::key_unwrap(&other_kek, &wrapped).is_err());
+        assert!(aes::key_unwrap(&kek, &wrapped[..16]).is_err());
+        assert!(aes::key_unwrap(&kek[..15], &wrapped).is_err());
+        assert!(aes::key_wrap(&kek, &[0; 8]).is_err());
+        assert!(aes::key_wrap(&kek, &[0; 20]).is_err());
+    }
+
+    #[test]
+    fn incremental_cmac_matches_one_shot() {
+        let key = hex_to_bytes("2b7e151628aed2a6abf7158809cf4f3c").unwrap();
+        let message = hex_to_bytes(CMAC_MESSAGE).unwrap();
+
+        // Split points around and across block boundaries
+        for split in [0, 1, 15, 16, 17, 39, 40, 64].iter() {
+            let mut mac = Cmac::new(&key).unwrap();
+            mac.update(&message[..*split]);
+            mac.update(&[]);
+            mac.update(&message[*split..]);
+
+            assert_eq!(mac.finalize(), aes::cmac(&key, &message).unwrap());
+        }
+
+        assert!(aes::cmac(&key[..15], &message).is_err());
     }
 }
\ No newline at end of file
@@ -0,0 +1,391 @@
+//! Chunked encryption of a reader into a writer under an AES-GCM instance,
+//! for files and other inputs too large to encrypt as one message.
+//!
+//! The output starts with a header holding a version, the frame size and
+//! a base nonce drawn from the instance. The plaintext follows in frames
+//! of the frame size, each one a length prefix, the GCM ciphertext and
+//! its tag. The nonce of a frame is the base nonce with the frame counter
+//! XORed into its last 8 bytes, so frames cannot be reordered, and the
+//! top bit of the length prefix marks the final frame. The header and
+//! that flag are the associated data of every frame, so a stream cut
+//! short at a frame boundary or moved behind another header fails to
+//! decrypt. Every frame counts as a message against the key budget
+
+use std::io::{ self, Read, Write };
+use crypto::aes_gcm::AesGcm;
+use crypto::aead::{ AeadEncryptor, AeadDecryptor };
+
+use crate::encryption::CipherError;
+use crate::encryption::aes::{ AES, AESMode, GCM_NONCE_SIZE, GCM_TAG_SIZE };
+use crate::utils::zeroize::zeroize_bytes;
+
+
+/*---- CONSTANTS ----*/
+
+/// Length in bytes of the stream header: version, frame size and base nonce
+pub const HEADER_LENGTH: usize = 1 + 4 + GCM_NONCE_SIZE;
+
+/// Length in bytes of the prefix in front of every frame
+pub const PREFIX_LENGTH: usize = 4;
+
+/// Largest number of plaintext bytes in a frame
+pub const MAX_FRAME_SIZE: usize = 1 << 24;
+
+/// Version of the stream format, the first byte of the header
+const STREAM_VERSION: u8 = 1;
+
+/// Flag on the top bit of the length prefix of the final frame
+const FINAL_FRAME_FLAG: u32 = 1 << 31;
+
+
+/*---- FUNCTIONS ----*/
+
+/// Encrypts everything read from a reader into framed GCM messages on a
+/// writer, returning the number of plaintext bytes. The instance must be
+/// in GCM mode. An input that fills its last frame exactly is followed
+/// by an empty final frame
+/// 
+/// ### Arguments
+/// 
+/// * `aes` - GCM instance holding the key
+/// * `reader` - Plaintext to encrypt
+/// * `writer` - Receiver of the header and frames
+/// * `frame_size` - Plaintext bytes in every frame but the final one
+pub fn encrypt_reader<R: Read, W: Write>(aes: &mut AES, mut reader: R, mut writer: W, frame_size: usize) -> io::Result<u64> {
+    check_instance(aes)?;
+
+    if frame_size == 0 || frame_size > MAX_FRAME_SIZE {
+        return Err(io_error(CipherError::InvalidLength));
+    }
+
+    let base = aes.next_nonce(GCM_NONCE_SIZE).map_err(io_error)?;
+    let mut header = Vec::with_capacity(HEADER_LENGTH);
+    header.push(STREAM_VERSION);
+    header.extend_from_slice(&(frame_size as u32).to_be_bytes());
+    header.extend_from_slice(&base);
+    writer.write_all(&header)?;
+
+    let mut frame = vec![0; frame_size];
+    let mut total = 0;
+    let mut counter = 0;
+
+    loop {
+        let length = read_full(&mut reader, &mut frame)?;
+        let last = length < frame_size;
+        let sealed = seal_frame(aes, &header, counter, &frame[..length], last).map_err(io_error);
+        zeroize_bytes(&mut frame[..length]);
+
+        writer.write_all(&sealed?)?;
+        total += length as u64;
+        counter += 1;
+
+        if last {
+            break;
+        }
+    }
+
+    writer.flush()?;
+    Ok(total)
+}
+
+/// Decrypts a stream written by `encrypt_reader` into a writer, returning
+/// the number of plaintext bytes. Every frame is checked before its
+/// plaintext is written, so a stream that fails part way leaves only the
+/// frames before the failure on the writer. A stream missing its final
+/// frame fails with `UnexpectedEof`, and a forged, reordered or
+/// overlong one with `InvalidData`
+/// 
+/// ### Arguments
+/// 
+/// * `aes` - GCM instance holding the key the stream was encrypted under
+/// * `reader` - Stream to decrypt
+/// * `writer` - Receiver of the plaintext
+pub fn decrypt_reader<R: Read, W: Write>(aes: &AES, mut reader: R, mut writer: W) -> io::Result<u64> {
+    check_instance(aes)?;
+
+    let mut header = vec![0; HEADER_LENGTH];
+
+    if read_full(&mut reader, &mut header)? < HEADER_LENGTH {
+        return Err(truncated());
+    }
+
+    let frame_size = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+
+    if header[0] != STREAM_VERSION || frame_size == 0 || frame_size > MAX_FRAME_SIZE {
+        return Err(io_error(CipherError::InvalidLength));
+    }
+
+    let mut sealed = vec![0; frame_size + GCM_TAG_SIZE];
+    let mut prefix = [0; PREFIX_LENGTH];
+    let mut total = 0;
+    let mut counter = 0;
+
+    loop {
+        if read_full(&mut reader, &mut prefix)? < PREFIX_LENGTH {
+            return Err(truncated());
+        }
+
+        let flagged_length = u32::from_be_bytes(prefix);
+        let last = flagged_length & FINAL_FRAME_FLAG != 0;
+        let length = (flagged_length & !FINAL_FRAME_FLAG) as usize;
+
+        // Only the final frame may be shorter than the frame size
+        if length > frame_size || (!last && length < frame_size) {
+            return Err(io_error(CipherError::InvalidLength));
+        }
+
+        let frame = &mut sealed[..length + GCM_TAG_SIZE];
+
+        if read_full(&mut reader, frame)? < frame.len() {
+            return Err(truncated());
+        }
+
+        let mut plaintext = open_frame(aes, &header, counter, frame, last).map_err(io_error)?;
+        let written = writer.write_all(&plaintext);
+        zeroize_bytes(&mut plaintext);
+
+        written?;
+        total += length as u64;
+        counter += 1;
+
+        if last {
+            break;
+        }
+    }
+
+    // Frames appended after the final one are as much a forgery as any other
+    if read_full(&mut reader, &mut prefix[..1])? != 0 {
+        return Err(io_error(CipherError::InvalidLength));
+    }
+
+    writer.flush()?;
+    Ok(total)
+}
+
+/// Seals one frame into its length prefix, ciphertext and tag
+/// 
+/// ### Arguments
+/// 
+/// * `aes` - GCM instance holding the key
+/// * `header` - Header of the stream
+/// * `counter` - Position of the frame in the stream
+/// * `plaintext` - Plaintext of the frame
+/// * `last` - Whether the frame is the final one
+fn seal_frame(aes: &mut AES, header: &[u8], counter: u64, plaintext: &[u8], last: bool) -> Result<Vec<u8>, CipherError> {
+    aes.check_budget(plaintext.len())?;
+
+    let prefix = frame_prefix(plaintext.len(), last);
+    let mut sealed = vec![0; PREFIX_LENGTH + plaintext.len() + GCM_TAG_SIZE];
+    let mut tag = [0; GCM_TAG_SIZE];
+    let (ciphertext, tag_slot) = sealed[PREFIX_LENGTH..].split_at_mut(plaintext.len());
+
+    let mut encryptor = AesGcm::new(aes.key_size, &aes.key, &frame_nonce(header, counter), &frame_aad(header, last));
+    encryptor.encrypt(plaintext, ciphertext, &mut tag);
+    tag_slot.copy_from_slice(&tag);
+    sealed[..PREFIX_LENGTH].copy_from_slice(&prefix);
+
+    aes.spend(plaintext.len());
+    Ok(sealed)
+}
+
+/// Opens a frame sealed by `seal_frame`
+/// 
+/// ### Arguments
+/// 
+/// * `aes` - GCM instance holding the key
+/// * `header` - Header of the stream
+/// * `counter` - Position the frame should have in the stream
+/// * `sealed` - Ciphertext and tag of the frame
+/// * `last` - Whether the frame is marked as the final one
+fn open_frame(aes: &AES, header: &[u8], counter: u64, sealed: &[u8], last: bool) -> Result<Vec<u8>, CipherError> {
+    let (ciphertext, tag) = sealed.split_at(sealed.len() - GCM_TAG_SIZE);
+    let mut plaintext = vec![0; ciphertext.len()];
+
+    let mut decryptor = AesGcm::new(aes.key_size, &aes.key, &frame_nonce(header, counter), &frame_aad(header, last));
+
+    if !decryptor.decrypt(ciphertext, &mut plaintext, tag) {
+        zeroize_bytes(&mut plaintext);
+        return Err(CipherError::AuthenticationFailed);
+    }
+
+    Ok(plaintext)
+}
+
+/// Nonce of a frame, the base nonce in the header with the counter XORed
+/// into its last 8 bytes
+/// 
+/// ### Arguments
+/// 
+/// * `header` - Header of the stream
+/// * `counter` - Position of the frame in the stream
+fn frame_nonce(header: &[u8], counter: u64) -> Vec<u8> {
+    let mut nonce = header[HEADER_LENGTH - GCM_NONCE_SIZE..].to_vec();
+
+    for (byte, count) in nonce[GCM_NONCE_SIZE - 8..].iter_mut().zip(counter.to_be_bytes().iter()) {
+        *byte ^= count;
+    }
+
+    nonce
+}
+
+/// Associated data of a frame, the header followed by the final flag
+/// 
+/// ### Arguments
+/// 
+/// * `header` - Header of the stream
+/// * `last` - Whether the frame is the final one
+fn frame_aad(header: &[u8], last: bool) -> Vec<u8> {
+    let mut aad = header.to_vec();
+    aad.push(last as u8);
+    aad
+}
+
+/// Length prefix of a frame, with the flag set on the final one
+/// 
+/// ### Arguments
+/// 
+/// * `length` - Plaintext length of the frame
+/// * `last` - Whether the frame is the final one
+fn frame_prefix(length: usize, last: bool) -> [u8; PREFIX_LENGTH] {
+    let flag = if last { FINAL_FRAME_FLAG } else { 0 };
+    (length as u32 | flag).to_be_bytes()
+}
+
+/// Refuses instances that aren't in GCM mode or whose key was cleared
+/// 
+/// ### Arguments
+/// 
+/// * `aes` - Instance to check
+fn check_instance(aes: &AES) -> io::Result<()> {
+    match aes.mode {
+        AESMode::GCM if !aes.key.is_empty() => Ok(()),
+        _ => Err(io_error(CipherError::InvalidLength))
+    }
+}
+
+/// Reads until the buffer is full or the reader runs out, returning the
+/// number of bytes read
+/// 
+/// ### Arguments
+/// 
+/// * `reader` - Reader to take bytes from
+/// * `buffer` - Buffer to fill
+fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(count) => filled += count,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e)
+        }
+    }
+
+    Ok(filled)
+}
+
+/// Wraps a cipher error for the `std::io` functions
+/// 
+/// ### Arguments
+/// 
+/// * `error` - Error raised by the cipher
+fn io_error(error: CipherError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Error for a stream that ends before its final frame
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, CipherError::InvalidLength)
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod stream_test {
+
+    use std::io;
+    use rand::{ Rng, ChaChaRng, SeedableRng };
+    use crate::encryption::aes::{ AES, AESMode, KeySize, GCM_TAG_SIZE };
+    use crate::encryption::aes::stream::{ encrypt_reader, decrypt_reader, HEADER_LENGTH, PREFIX_LENGTH };
+
+    const FRAME_SIZE: usize = 64 * 1024;
+
+    fn decrypted(aes: &AES, stream: &[u8]) -> io::Result<Vec<u8>> {
+        let mut plaintext = Vec::new();
+        decrypt_reader(aes, stream, &mut plaintext)?;
+
+        Ok(plaintext)
+    }
+
+    #[test]
+    fn large_buffer_round_trips() {
+        let mut rng = ChaChaRng::from_seed(&[0x66, 0x72, 0x6d]);
+        let mut aes = AES::new(KeySize::KeySize256, AESMode::GCM, None, &mut rng);
+        let mut plaintext = vec![0; 5 * 1024 * 1024 + 123];
+        rng.fill_bytes(&mut plaintext);
+
+        let mut stream = Vec::new();
+        let written = encrypt_reader(&mut aes, &plaintext[..], &mut stream, FRAME_SIZE).unwrap();
+        let frames = plaintext.len() / FRAME_SIZE + 1;
+
+        assert_eq!(written, plaintext.len() as u64);
+        assert_eq!(stream.len(), HEADER_LENGTH + plaintext.len() + frames * (PREFIX_LENGTH + GCM_TAG_SIZE));
+        assert_eq!(decrypted(&aes, &stream).unwrap(), plaintext);
+
+        // An input filling its frames exactly still ends in a final frame
+        let mut exact = Vec::new();
+        encrypt_reader(&mut aes, &plaintext[..2 * FRAME_SIZE], &mut exact, FRAME_SIZE).unwrap();
+        assert_eq!(decrypted(&aes, &exact).unwrap(), plaintext[..2 * FRAME_SIZE].to_vec());
+    }
+
+    #[test]
+    fn truncated_and_reordered_streams_are_rejected() {
+        let mut rng = ChaChaRng::from_seed(&[0x66, 0x72, 0x6e]);
+        let mut aes = AES::new(KeySize::KeySize128, AESMode::GCM, None, &mut rng);
+        let mut plaintext = vec![0; 3 * 1024 + 10];
+        rng.fill_bytes(&mut plaintext);
+
+        let mut stream = Vec::new();
+        encrypt_reader(&mut aes, &plaintext[..], &mut stream, 1024).unwrap();
+        let frame = PREFIX_LENGTH + 1024 + GCM_TAG_SIZE;
+        let frame_at = |index: usize| HEADER_LENGTH + index * frame;
+
+        // Cut at a frame boundary, before the final frame
+        let cut = decrypted(&aes, &stream[..frame_at(3)]).unwrap_err();
+        assert_eq!(cut.kind(), io::ErrorKind::UnexpectedEof);
+
+        // Cut inside a frame
+        let cut = decrypted(&aes, &stream[..frame_at(1) + 100]).unwrap_err();
+        assert_eq!(cut.kind(), io::ErrorKind::UnexpectedEof);
+
+        // Frames swapped
+        let mut swapped = stream[..frame_at(0)].to_vec();
+        swapped.extend_from_slice(&stream[frame_at(1)..frame_at(2)]);
+        swapped.extend_from_slice(&stream[frame_at(0)..frame_at(1)]);
+        swapped.extend_from_slice(&stream[frame_at(2)..]);
+        assert_eq!(decrypted(&aes, &swapped).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // A full frame passed off as the final one
+        let mut marked = stream[..frame_at(2)].to_vec();
+        marked[frame_at(1)] |= 0x80;
+        assert_eq!(decrypted(&aes, &marked).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // A frame appended after the final one
+        let mut extended = stream.clone();
+        extended.extend_from_slice(&stream[frame_at(0)..frame_at(1)]);
+        assert_eq!(decrypted(&aes, &extended).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        assert_eq!(decrypted(&aes, &stream).unwrap(), plaintext);
+    }
+
+    #[test]
+    fn only_gcm_instances_stream() {
+        let mut rng = ChaChaRng::from_seed(&[0x66, 0x72, 0x6f]);
+        let mut aes = AES::new(KeySize::KeySize128, AESMode::Counter, None, &mut rng);
+
+        assert!(encrypt_reader(&mut aes, &b"data"[..], Vec::new(), 1024).is_err());
+        assert!(decrypt_reader(&aes, &b"data"[..], Vec::new()).is_err());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,322 @@
+use std::fmt;
+use rand::Rng;
+use crypto::aes::KeySize;
+use crypto::aes_gcm::AesGcm;
+use crypto::aead::{ AeadEncryptor, AeadDecryptor };
+
+use crate::hash::kdf::KeyDerivation;
+use crate::hash::crypto::HashAlgorithm;
+use crate::key_exchange::ecdh::ECDH;
+use crate::utils::rng::CryptoRngCore;
+use crate::utils::ecc::{ ECPKeypair, EcError, ValidatedPoint, encoded_point_len };
+
+
+/*---- CONSTANTS ----*/
+
+/// Length in bytes of the AES-256 key derived from the shared secret
+const KEY_LENGTH: usize = 32;
+
+/// Length in bytes of the GCM nonce
+const NONCE_LENGTH: usize = 12;
+
+/// Length in bytes of the GCM authentication tag
+const TAG_LENGTH: usize = 16;
+
+/// KDF info string binding derived keys to this scheme
+const KDF_INFO: &'static [u8] = b"ECIES AES-256-GCM";
+
+/// Hash algorithm used by the KDF
+const KDF_HASH_ALGORITHM: HashAlgorithm = HashAlgorithm::Sha3_256;
+
+
+/*---- ENUMS ----*/
+
+/// Errors raised while encrypting or decrypting an ECIES message
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum EciesError {
+    KeyExchange(EcError),       // ECDH with the recipient key failed
+    MalformedEphemeralKey,      // ephemeral public value could not be decoded or is invalid
+    TruncatedCiphertext,        // message is shorter than the fixed overhead
+    AuthenticationFailed        // GCM tag did not verify
+}
+
+impl fmt::Display for EciesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EciesError::KeyExchange(e) => write!(f, "Key exchange failed: {}", e),
+            EciesError::MalformedEphemeralKey => write!(f, "Ephemeral public key is malformed"),
+            EciesError::TruncatedCiphertext => write!(f, "Ciphertext is too short"),
+            EciesError::AuthenticationFailed => write!(f, "Ciphertext failed authentication")
+        }
+    }
+}
+
+impl From<EcError> for EciesError {
+    fn from(error: EcError) -> Self {
+        EciesError::KeyExchange(error)
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Encrypts a message to the holder of `recipient_pub` using the Elliptic
+/// Curve Integrated Encryption Scheme. A fresh ephemeral keypair is
+/// generated, ECDH is performed against the recipient's public key and
+/// the shared secret is run through HKDF to obtain an AES-256-GCM key.
+/// The output is ephemeral_pub || nonce || ciphertext || tag, with the
+/// encoded ephemeral public key also authenticated as additional data.
+/// The ephemeral keypair is generated on the curve the recipient key
+/// was validated against
+/// 
+/// ### Arguments
+/// 
+/// * `recipient_pub` - Validated public key of the recipient
+/// * `plaintext` - Message to encrypt
+/// * `rng` - Random number generator
+pub fn encrypt(recipient_pub: &ValidatedPoint, plaintext: &[u8], rng: &mut impl CryptoRngCore) -> Result<Vec<u8>, EciesError> {
+    encrypt_with(recipient_pub, plaintext, KeyDerivation::Hkdf, rng)
+}
+
+/// Encrypts a message as `encrypt` does, deriving the AES key with the
+/// given KDF. The X9.63 and Concatenation KDFs take the encoded
+/// ephemeral public key followed by the scheme's info string as their
+/// shared info. The recipient must decrypt with the same KDF
+/// 
+/// ### Arguments
+/// 
+/// * `recipient_pub` - Validated public key of the recipient
+/// * `plaintext` - Message to encrypt
+/// * `kdf` - Key derivation function to apply to the shared secret
+/// * `rng` - Random number generator
+pub fn encrypt_with(
+    recipient_pub: &ValidatedPoint,
+    plaintext: &[u8],
+    kdf: KeyDerivation,
+    rng: &mut impl CryptoRngCore
+) -> Result<Vec<u8>, EciesError>
+{
+    let mut ephemeral = ECDH::new(recipient_pub.curve_id(), rng)?;
+    ephemeral.set_peer_public_key(recipient_pub)?;
+
+    let shared_bytes = ephemeral.derive_shared_bytes(rng)?;
+    let ephemeral_bytes = ephemeral.public_key_bytes(false);
+    let key = derive_aes_key(kdf, &shared_bytes, &ephemeral_bytes)?;
+
+    let mut nonce = [0; NONCE_LENGTH];
+    rng.fill_bytes(&mut nonce);
+
+    let mut ciphertext = vec![0; plaintext.len()];
+    let mut tag = [0; TAG_LENGTH];
+
+    let mut encryptor = AesGcm::new(KeySize::KeySize256, &key, &nonce, &ephemeral_bytes);
+    encryptor.encrypt(plaintext, &mut ciphertext, &mut tag);
+
+    let mut output = Vec::with_capacity(ephemeral_bytes.len() + NONCE_LENGTH + ciphertext.len() + TAG_LENGTH);
+    output.extend(ephemeral_bytes);
+    output.extend_from_slice(&nonce);
+    output.extend(ciphertext);
+    output.extend_from_slice(&tag);
+
+    Ok(output)
+}
+
+/// Decrypts an ECIES message produced by `encrypt`. An ephemeral key
+/// that cannot be decoded or fails validation is reported as
+/// `MalformedEphemeralKey`, and any other tampering (or the wrong
+/// recipient key) surfaces as `AuthenticationFailed`
+/// 
+/// ### Arguments
+/// 
+/// * `recipient_keypair` - Keypair of the recipient
+/// * `blob` - Encrypted message
+/// * `rng` - Random number generator
+pub fn decrypt(recipient_keypair: &ECPKeypair, blob: &[u8], rng: &mut impl CryptoRngCore) -> Result<Vec<u8>, EciesError> {
+    decrypt_with(recipient_keypair, blob, KeyDerivation::Hkdf, rng)
+}
+
+/// Decrypts an ECIES message produced by `encrypt_with`, deriving the
+/// AES key with the same KDF the sender used
+/// 
+/// ### Arguments
+/// 
+/// * `recipient_keypair` - Keypair of the recipient
+/// * `blob` - Encrypted message
+/// * `kdf` - Key derivation function to apply to the shared secret
+/// * `rng` - Random number generator
+pub fn decrypt_with(
+    recipient_keypair: &ECPKeypair,
+    blob: &[u8],
+    kdf: KeyDerivation,
+    rng: &mut impl CryptoRngCore
+) -> Result<Vec<u8>, EciesError>
+{
+    let point_length = encoded_point_len(&recipient_keypair.group);
+
+    if blob.len() < point_length + NONCE_LENGTH + TAG_LENGTH {
+        return Err(EciesError::TruncatedCiphertext);
+    }
+
+    let (ephemeral_bytes, rest) = blob.split_at(point_length);
+    let (nonce, rest) = rest.split_at(NONCE_LENGTH);
+    let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LENGTH);
+
+    let mut ecdh = ECDH::from_keypair(recipient_keypair.clone());
+
+    if ecdh.set_peer_public_key_bytes(ephemeral_bytes).is_err() {
+        return Err(EciesError::MalformedEphemeralKey);
+    }
+
+    let shared_bytes = match ecdh.derive_shared_bytes(rng) {
+        Ok(bytes) => bytes,
+        Err(EcError::InvalidPublicKey(_)) |
+        Err(EcError::LowOrderPoint) |
+        Err(EcError::PointAtInfinity) => return Err(EciesError::MalformedEphemeralKey),
+        Err(e) => return Err(EciesError::KeyExchange(e))
+    };
+
+    let key = derive_aes_key(kdf, &shared_bytes, ephemeral_bytes)?;
+    let mut plaintext = vec![0; ciphertext.len()];
+
+    let mut decryptor = AesGcm::new(KeySize::KeySize256, &key, nonce, ephemeral_bytes);
+
+    if !decryptor.decrypt(ciphertext, &mut plaintext, tag) {
+        return Err(EciesError::AuthenticationFailed);
+    }
+
+    Ok(plaintext)
+}
+
+/// Derives the AES key from the ECDH shared secret, salting the KDF with
+/// the encoded ephemeral public key
+/// 
+/// ### Arguments
+/// 
+/// * `kdf` - Key derivation function to apply
+/// * `shared_bytes` - Fixed-length encoding of the shared secret
+/// * `ephemeral_bytes` - Encoded ephemeral public key
+fn derive_aes_key(kdf: KeyDerivation, shared_bytes: &[u8], ephemeral_bytes: &[u8]) -> Result<Vec<u8>, EciesError> {
+    match kdf.derive(shared_bytes, ephemeral_bytes, KDF_INFO, KEY_LENGTH, KDF_HASH_ALGORITHM) {
+        Ok(key) => Ok(key),
+        Err(e) => Err(EciesError::KeyExchange(EcError::from(e)))
+    }
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod ecies_test {
+
+    use rand::{ ChaChaRng, SeedableRng };
+    use crate::utils::rng::os_rng;
+    use crate::utils::ecc::{ ECPKeypair, ValidatedPoint, ValidationLevel, encoded_point_len };
+    use crate::utils::ecc_curves::ECPSupportedCurves;
+    use crate::hash::kdf::KeyDerivation;
+    use crate::encryption::ecies::{ encrypt, decrypt, encrypt_with, decrypt_with, EciesError, NONCE_LENGTH };
+
+    const MESSAGE: &'static [u8] = b"Attack at dawn, bring the good biscuits";
+
+    fn validated(keypair: &ECPKeypair) -> ValidatedPoint {
+        keypair.group.validate(keypair.q.clone(), ValidationLevel::FullSubgroup).unwrap()
+    }
+
+    fn round_trip(curve: ECPSupportedCurves) {
+        let mut rng = os_rng().unwrap();
+        let recipient = ECPKeypair::new(curve).setup(&mut rng).unwrap();
+
+        let blob = encrypt(&validated(&recipient), MESSAGE, &mut rng).unwrap();
+        assert_eq!(decrypt(&recipient, &blob, &mut rng).unwrap(), MESSAGE.to_vec());
+    }
+
+    #[test]
+    fn round_trip_weierstrass() {
+        round_trip(ECPSupportedCurves::BP256R1);
+    }
+
+    #[test]
+    fn round_trip_montgomery() {
+        round_trip(ECPSupportedCurves::Curve25519);
+    }
+
+    #[test]
+    fn round_trip_under_each_kdf() {
+        let mut rng = os_rng().unwrap();
+        let recipient = ECPKeypair::new(ECPSupportedCurves::BP256R1).setup(&mut rng).unwrap();
+
+        for kdf in vec![KeyDerivation::Hkdf, KeyDerivation::X963, KeyDerivation::Concat] {
+            let blob = encrypt_with(&validated(&recipient), MESSAGE, kdf, &mut rng).unwrap();
+            assert_eq!(decrypt_with(&recipient, &blob, kdf, &mut rng).unwrap(), MESSAGE.to_vec());
+
+            // A recipient expecting another KDF derives another key
+            let other = if kdf == KeyDerivation::X963 { KeyDerivation::Concat } else { KeyDerivation::X963 };
+            assert_eq!(decrypt_with(&recipient, &blob, other, &mut rng), Err(EciesError::AuthenticationFailed));
+        }
+    }
+
+    #[test]
+    fn tampering_with_any_field_fails() {
+        let mut rng = os_rng().unwrap();
+        let recipient = ECPKeypair::new(ECPSupportedCurves::BP256R1).setup(&mut rng).unwrap();
+        let blob = encrypt(&validated(&recipient), MESSAGE, &mut rng).unwrap();
+
+        let point_length = encoded_point_len(&recipient.group);
+        let nonce_start = point_length;
+        let ciphertext_start = nonce_start + NONCE_LENGTH;
+        let tag_start = blob.len() - 1;
+
+        // Flipping a coordinate bit moves the ephemeral point off the curve
+        let mut tampered = blob.clone();
+        tampered[5] ^= 1;
+        assert_eq!(decrypt(&recipient, &tampered, &mut rng), Err(EciesError::MalformedEphemeralKey));
+
+        for index in vec![nonce_start, ciphertext_start, tag_start] {
+            let mut tampered = blob.clone();
+            tampered[index] ^= 1;
+
+            assert_eq!(decrypt(&recipient, &tampered, &mut rng), Err(EciesError::AuthenticationFailed));
+        }
+    }
+
+    #[test]
+    fn tampered_montgomery_ephemeral_key_fails() {
+        let mut rng = os_rng().unwrap();
+        let recipient = ECPKeypair::new(ECPSupportedCurves::Curve25519).setup(&mut rng).unwrap();
+        let mut blob = encrypt(&validated(&recipient), MESSAGE, &mut rng).unwrap();
+
+        blob[3] ^= 1;
+        assert!(decrypt(&recipient, &blob, &mut rng).is_err());
+    }
+
+    #[test]
+    fn wrong_private_key_fails_authentication() {
+        let mut rng = os_rng().unwrap();
+        let recipient = ECPKeypair::new(ECPSupportedCurves::BP256R1).setup(&mut rng).unwrap();
+        let other = ECPKeypair::new(ECPSupportedCurves::BP256R1).setup(&mut rng).unwrap();
+
+        let blob = encrypt(&validated(&recipient), MESSAGE, &mut rng).unwrap();
+        assert_eq!(decrypt(&other, &blob, &mut rng), Err(EciesError::AuthenticationFailed));
+    }
+
+    #[test]
+    fn truncated_message_is_rejected() {
+        let mut rng = os_rng().unwrap();
+        let recipient = ECPKeypair::new(ECPSupportedCurves::Curve25519).setup(&mut rng).unwrap();
+        let blob = encrypt(&validated(&recipient), MESSAGE, &mut rng).unwrap();
+
+        assert_eq!(decrypt(&recipient, &blob[..40], &mut rng), Err(EciesError::TruncatedCiphertext));
+    }
+
+    #[test]
+    fn seeded_encryption_is_reproducible() {
+        let mut rng = os_rng().unwrap();
+        let recipient = ECPKeypair::new(ECPSupportedCurves::BP256R1).setup(&mut rng).unwrap();
+
+        let first = encrypt(&validated(&recipient), MESSAGE, &mut ChaChaRng::from_seed(&[3, 1, 4, 1])).unwrap();
+        let second = encrypt(&validated(&recipient), MESSAGE, &mut ChaChaRng::from_seed(&[3, 1, 4, 1])).unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(decrypt(&recipient, &first, &mut rng).unwrap(), MESSAGE.to_vec());
+    }
+
+}
\ No newline at end of file
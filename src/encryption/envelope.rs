@@ -0,0 +1,319 @@
+//! Hybrid encryption of payloads of any size to an RSA key. The payload
+//! is sealed with AES-256-GCM under a fresh key and nonce, and only those
+//! 44 bytes go through RSA, under the key's padding (OAEP unless the key
+//! was made without), so the cost of the RSA step doesn't grow with the
+//! payload. The wrapped key is bound to the payload as GCM additional
+//! data, so that neither can be swapped out for another envelope's
+
+use std::fmt;
+use std::convert::TryInto;
+use crypto::aes::KeySize;
+use crypto::aes_gcm::AesGcm;
+use crypto::aead::{ AeadEncryptor, AeadDecryptor };
+
+use crate::encryption::AsymmetricKeyMode;
+use crate::encryption::rsa::{ RSA, RsaError };
+use crate::utils::rng::CryptoRngCore;
+use crate::utils::zeroize::zeroize_bytes;
+
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+#[cfg(feature = "serde")]
+use crate::utils::serde_hex;
+
+
+/*---- CONSTANTS ----*/
+
+/// Length in bytes of the AES-256 payload key
+const KEY_LENGTH: usize = 32;
+
+/// Length in bytes of the GCM nonce
+pub const NONCE_LENGTH: usize = 12;
+
+/// Length in bytes of the GCM authentication tag
+pub const TAG_LENGTH: usize = 16;
+
+/// Length in bytes of the wrapped key length in front of an encoded envelope
+const LENGTH_PREFIX: usize = 4;
+
+
+/*---- ENUMS ----*/
+
+/// Errors raised while sealing or opening an envelope
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum EnvelopeError {
+    Rsa(RsaError),              // wrapping or unwrapping the payload key failed
+    MalformedKey,               // unwrapped key and nonce have the wrong length
+    Truncated,                  // encoded envelope is shorter than its fields
+    AuthenticationFailed        // GCM tag did not verify
+}
+
+impl fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EnvelopeError::Rsa(e) => write!(f, "Key wrapping failed: {}", e),
+            EnvelopeError::MalformedKey => write!(f, "Unwrapped payload key is malformed"),
+            EnvelopeError::Truncated => write!(f, "Envelope is too short"),
+            EnvelopeError::AuthenticationFailed => write!(f, "Envelope failed authentication")
+        }
+    }
+}
+
+impl From<RsaError> for EnvelopeError {
+    fn from(error: RsaError) -> Self {
+        EnvelopeError::Rsa(error)
+    }
+}
+
+
+/*---- STRUCTS ----*/
+
+/// Payload sealed to an RSA key, along with everything but the private
+/// key needed to open it
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Envelope {
+    #[cfg_attr(feature = "serde", serde(with = "serde_hex::bytes"))]
+    pub wrapped_key: Vec<u8>,   // AES key and nonce, encrypted to the RSA key
+    #[cfg_attr(feature = "serde", serde(with = "serde_hex::bytes"))]
+    pub ciphertext: Vec<u8>,    // payload under AES-256-GCM
+    #[cfg_attr(feature = "serde", serde(with = "serde_hex::bytes"))]
+    pub tag: Vec<u8>            // GCM tag over the ciphertext and wrapped key
+}
+
+
+/*---- IMPLEMENTATIONS ----*/
+
+impl Envelope {
+
+    /// Encodes the envelope as the wrapped key length in four big-endian
+    /// bytes, followed by the wrapped key, the ciphertext and the tag
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut output = Vec::with_capacity(LENGTH_PREFIX + self.wrapped_key.len() + self.ciphertext.len() + TAG_LENGTH);
+
+        output.extend_from_slice(&(self.wrapped_key.len() as u32).to_be_bytes());
+        output.extend_from_slice(&self.wrapped_key);
+        output.extend_from_slice(&self.ciphertext);
+        output.extend_from_slice(&self.tag);
+
+        output
+    }
+
+    /// Decodes an envelope written by `to_bytes`
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `bytes` - Encoded envelope
+    pub fn from_bytes(bytes: &[u8]) -> Result<Envelope, EnvelopeError> {
+        if bytes.len() < LENGTH_PREFIX {
+            return Err(EnvelopeError::Truncated);
+        }
+
+        let (prefix, rest) = bytes.split_at(LENGTH_PREFIX);
+        let key_length = u32::from_be_bytes(prefix.try_into().unwrap()) as usize;
+
+        if rest.len() < TAG_LENGTH || rest.len() - TAG_LENGTH < key_length {
+            return Err(EnvelopeError::Truncated);
+        }
+
+        let (wrapped_key, rest) = rest.split_at(key_length);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LENGTH);
+
+        Ok(Envelope {
+            wrapped_key: wrapped_key.to_vec(),
+            ciphertext: ciphertext.to_vec(),
+            tag: tag.to_vec()
+        })
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Seals a payload to the holder of an RSA key. A fresh AES-256 key and
+/// nonce are drawn for every envelope, and wiped once wrapped
+/// 
+/// ### Example
+/// 
+/// ```
+/// use num_bigint::BigUint;
+/// use num_traits::FromPrimitive;
+/// use cryptofun::encryption::envelope;
+/// use cryptofun::encryption::rsa::RSA;
+/// use cryptofun::hash::crypto::HashAlgorithm;
+/// use cryptofun::utils::rng::seeded_rng;
+/// 
+/// let mut rng = seeded_rng(&[0xe5, 0x1]);
+/// let exponent = BigUint::from_u32(65537).unwrap();
+/// // A small key keeps the example quick, real keys are held to 2048 bits
+/// let recipient = RSA::new(HashAlgorithm::Sha3_256, true).allow_insecure_for_testing().generate_keypair(768, &exponent, &mut rng).unwrap();
+/// let sender = RSA::from_public_key(HashAlgorithm::Sha3_256, &recipient.public_key().unwrap()).allow_insecure_for_testing();
+/// 
+/// let sealed = envelope::seal(&sender, b"the quarterly numbers", &mut rng).unwrap();
+/// 
+/// assert_eq!(envelope::open(&recipient, &sealed, &mut rng).unwrap(), b"the quarterly numbers".to_vec());
+/// ```
+/// 
+/// ### Arguments
+/// 
+/// * `recipient` - RSA key holding at least the recipient's public key
+/// * `plaintext` - Payload to seal
+/// * `rng` - Random number generator
+pub fn seal(recipient: &RSA, plaintext: &[u8], rng: &mut impl CryptoRngCore) -> Result<Envelope, EnvelopeError> {
+    let mut secret = vec![0; KEY_LENGTH + NONCE_LENGTH];
+    rng.fill_bytes(&mut secret);
+
+    let wrapped_key = match recipient.encrypt(&secret, AsymmetricKeyMode::Public, rng) {
+        Ok(wrapped_key) => wrapped_key,
+        Err(e) => {
+            zeroize_bytes(&mut secret);
+            return Err(e.into());
+        }
+    };
+
+    let (key, nonce) = secret.split_at(KEY_LENGTH);
+    let mut ciphertext = vec![0; plaintext.len()];
+    let mut tag = vec![0; TAG_LENGTH];
+
+    let mut encryptor = AesGcm::new(KeySize::KeySize256, key, nonce, &wrapped_key);
+    encryptor.encrypt(plaintext, &mut ciphertext, &mut tag);
+    zeroize_bytes(&mut secret);
+
+    Ok(Envelope {
+        wrapped_key: wrapped_key,
+        ciphertext: ciphertext,
+        tag: tag
+    })
+}
+
+/// Opens an envelope made by `seal`. A tampered envelope, or one sealed
+/// to another key, fails with `AuthenticationFailed` unless unwrapping
+/// the key already fails
+/// 
+/// ### Arguments
+/// 
+/// * `recipient` - RSA keypair of the recipient
+/// * `envelope` - Envelope to open
+/// * `rng` - Random number generator, for blinding the RSA step
+pub fn open(recipient: &RSA, envelope: &Envelope, rng: &mut impl CryptoRngCore) -> Result<Vec<u8>, EnvelopeError> {
+    if envelope.tag.len() != TAG_LENGTH {
+        return Err(EnvelopeError::Truncated);
+    }
+
+    let mut secret = recipient.decrypt(&envelope.wrapped_key, AsymmetricKeyMode::Private, rng)?;
+
+    if secret.len() != KEY_LENGTH + NONCE_LENGTH {
+        zeroize_bytes(&mut secret);
+        return Err(EnvelopeError::MalformedKey);
+    }
+
+    let (key, nonce) = secret.split_at(KEY_LENGTH);
+    let mut plaintext = vec![0; envelope.ciphertext.len()];
+
+    let mut decryptor = AesGcm::new(KeySize::KeySize256, key, nonce, &envelope.wrapped_key);
+    let authentic = decryptor.decrypt(&envelope.ciphertext, &mut plaintext, &envelope.tag);
+    zeroize_bytes(&mut secret);
+
+    if !authentic {
+        zeroize_bytes(&mut plaintext);
+        return Err(EnvelopeError::AuthenticationFailed);
+    }
+
+    Ok(plaintext)
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod envelope_test {
+
+    use num_bigint::BigUint;
+    use num_traits::FromPrimitive;
+    use rand::{ ChaChaRng, SeedableRng };
+    use crate::encryption::rsa::RSA;
+    use crate::encryption::envelope::{ seal, open, Envelope, EnvelopeError };
+    use crate::hash::crypto::HashAlgorithm;
+
+    const MESSAGE: &'static [u8] = b"Meet at the old mill, bring the ledger";
+
+    fn keypair(seed: u32) -> RSA {
+        let mut rng = ChaChaRng::from_seed(&[0x65, 0x6e, seed]);
+        let exponent = BigUint::from_u32(65537).unwrap();
+
+        RSA::new(HashAlgorithm::Sha3_256, true).allow_insecure_for_testing().generate_keypair(768, &exponent, &mut rng).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut rng = ChaChaRng::from_seed(&[0x65, 0x6e, 0x76]);
+        let recipient = keypair(1);
+        let sender = RSA::from_public_key(HashAlgorithm::Sha3_256, &recipient.public_key().unwrap()).allow_insecure_for_testing();
+
+        let envelope = seal(&sender, MESSAGE, &mut rng).unwrap();
+
+        assert_eq!(envelope.ciphertext.len(), MESSAGE.len());
+        assert_eq!(open(&recipient, &envelope, &mut rng).unwrap(), MESSAGE.to_vec());
+    }
+
+    #[test]
+    fn empty_and_large_payloads() {
+        let mut rng = ChaChaRng::from_seed(&[0x65, 0x6e, 0x77]);
+        let recipient = keypair(2);
+
+        let envelope = seal(&recipient, &[], &mut rng).unwrap();
+        assert_eq!(open(&recipient, &envelope, &mut rng).unwrap(), Vec::<u8>::new());
+
+        let large: Vec<u8> = (0..3 << 20).map(|i: u32| (i % 251) as u8).collect();
+        let envelope = seal(&recipient, &large, &mut rng).unwrap();
+        assert_eq!(open(&recipient, &envelope, &mut rng).unwrap(), large);
+    }
+
+    #[test]
+    fn tampering_with_any_field_fails() {
+        let mut rng = ChaChaRng::from_seed(&[0x65, 0x6e, 0x78]);
+        let recipient = keypair(3);
+        let envelope = seal(&recipient, MESSAGE, &mut rng).unwrap();
+
+        let mut tampered = envelope.clone();
+        tampered.ciphertext[0] ^= 1;
+        assert_eq!(open(&recipient, &tampered, &mut rng), Err(EnvelopeError::AuthenticationFailed));
+
+        let mut tampered = envelope.clone();
+        tampered.tag[7] ^= 1;
+        assert_eq!(open(&recipient, &tampered, &mut rng), Err(EnvelopeError::AuthenticationFailed));
+
+        // A changed wrapped key unwraps to garbage or not at all
+        let mut tampered = envelope.clone();
+        tampered.wrapped_key[0] ^= 1;
+        assert!(open(&recipient, &tampered, &mut rng).is_err());
+
+        let mut tampered = envelope.clone();
+        tampered.tag.pop();
+        assert_eq!(open(&recipient, &tampered, &mut rng), Err(EnvelopeError::Truncated));
+    }
+
+    #[test]
+    fn wrong_recipient_fails() {
+        let mut rng = ChaChaRng::from_seed(&[0x65, 0x6e, 0x79]);
+        let recipient = keypair(4);
+        let other = keypair(5);
+
+        let envelope = seal(&recipient, MESSAGE, &mut rng).unwrap();
+        assert!(open(&other, &envelope, &mut rng).is_err());
+    }
+
+    #[test]
+    fn encoding_round_trip() {
+        let mut rng = ChaChaRng::from_seed(&[0x65, 0x6e, 0x7a]);
+        let recipient = keypair(6);
+        let envelope = seal(&recipient, MESSAGE, &mut rng).unwrap();
+        let bytes = envelope.to_bytes();
+
+        assert_eq!(Envelope::from_bytes(&bytes).unwrap(), envelope);
+        assert_eq!(open(&recipient, &Envelope::from_bytes(&bytes).unwrap(), &mut rng).unwrap(), MESSAGE.to_vec());
+
+        assert_eq!(Envelope::from_bytes(&bytes[..3]), Err(EnvelopeError::Truncated));
+        assert_eq!(Envelope::from_bytes(&bytes[..80]), Err(EnvelopeError::Truncated));
+    }
+}
\ No newline at end of file
@@ -1,16 +1,61 @@
-use rand::OsRng;
+use std::fmt;
+use std::error;
 
+use crate::error::Error;
+use crate::utils::rng::CryptoRngCore;
+
+#[cfg(feature = "rsa")]
 pub mod rsa;
+#[cfg(feature = "aes")]
 pub mod aes;
-// pub mod ring_lwe;
+#[cfg(all(feature = "ecc", feature = "aes"))]
+pub mod ecies;
+#[cfg(all(feature = "rsa", feature = "aes"))]
+pub mod envelope;
+#[cfg(all(feature = "aes", feature = "hashes-sha3"))]
+pub mod aead;
+#[cfg(feature = "ring-lwe")]
+pub mod ring_lwe;
 
+/// Encryption under one half of a keypair, decryption under either,
+/// reporting failures as the crate-wide `Error`
 pub trait AsymmetricEncryptor<AsymmetricKeyMode> {
-    fn encrypt(&mut self, data: &Vec<u8>, mode: AsymmetricKeyMode, generator: &mut OsRng) -> Vec<u8>;
-    fn decrypt(&mut self, ciphertext: &Vec<u8>, mode: AsymmetricKeyMode, generator: &mut OsRng) -> Vec<u8>;
+    fn encrypt(&mut self, data: &[u8], mode: AsymmetricKeyMode, generator: &mut impl CryptoRngCore) -> Result<Vec<u8>, Error>;
+    fn decrypt(&mut self, ciphertext: &[u8], mode: AsymmetricKeyMode, generator: &mut impl CryptoRngCore) -> Result<Vec<u8>, Error>;
+}
+
+/// Encryption and decryption under a key held by the cipher, with the
+/// associated data of each message passed alongside it. Ciphers without
+/// authentication refuse any associated data with `InvalidLength`
+pub trait SymmetricEncryptor {
+    fn encrypt(&mut self, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, CipherError>;
+    fn decrypt(&mut self, ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>, CipherError>;
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum AsymmetricKeyMode {
     Private,
     Public,
-}
\ No newline at end of file
+}
+
+/// Errors raised by symmetric ciphers
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CipherError {
+    InvalidLength,          // key, IV, nonce, parameter or input of the wrong length
+    InvalidPadding,         // padding or key wrap integrity check did not match
+    AuthenticationFailed,   // tag of an authenticated mode did not match
+    KeyExhausted            // the key budget is used up, and the key needs replacing
+}
+
+impl fmt::Display for CipherError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CipherError::InvalidLength => write!(f, "Key, IV or input has an invalid length"),
+            CipherError::InvalidPadding => write!(f, "Padding or integrity check failed"),
+            CipherError::AuthenticationFailed => write!(f, "Message failed authentication"),
+            CipherError::KeyExhausted => write!(f, "The key budget is used up, rekey before encrypting more")
+        }
+    }
+}
+
+impl error::Error for CipherError {}
\ No newline at end of file
@@ -1,7 +1,7 @@
 use num_bigint::BigUint;
 use num_traits::Zero;
 
-use utils::knuth_yao;
+use crate::utils::knuth_yao;
 
 /*---- Structs ----*/
 
@@ -0,0 +1,306 @@
+use std::io;
+use std::fmt;
+use std::error;
+
+use crate::utils::primes::PrimeError;
+use crate::utils::entropy::EntropyError;
+use crate::utils::nonce::NonceError;
+use crate::utils::encoding::EncodingError;
+
+#[cfg(feature = "aes")]
+use crate::encryption::CipherError;
+#[cfg(any(feature = "hashes-sha3", feature = "hashes-blake2"))]
+use crate::hash::kdf::KdfError;
+#[cfg(any(feature = "hashes-sha3", feature = "hashes-blake2"))]
+use crate::self_test::SelfTestReport;
+#[cfg(feature = "rsa")]
+use crate::encryption::rsa::RsaError;
+#[cfg(feature = "ecc")]
+use crate::utils::ecc::EcError;
+#[cfg(all(feature = "ecc", feature = "aes"))]
+use crate::encryption::ecies::EciesError;
+#[cfg(all(feature = "rsa", feature = "aes"))]
+use crate::encryption::envelope::EnvelopeError;
+#[cfg(all(feature = "aes", feature = "hashes-sha3"))]
+use crate::encryption::aead::StreamError;
+#[cfg(feature = "dh")]
+use crate::key_exchange::diffie_hellman::DhError;
+#[cfg(feature = "ecdsa")]
+use crate::signature::ecdsa::SignatureError;
+#[cfg(feature = "ecdsa")]
+use crate::signature::cosign::CosignError;
+#[cfg(feature = "ecdsa")]
+use crate::signature::vrf::VrfError;
+#[cfg(feature = "dsa")]
+use crate::signature::dsa::DsaError;
+#[cfg(feature = "keys")]
+use crate::keys::KeyError;
+#[cfg(feature = "sharing")]
+use crate::sharing::SharingError;
+#[cfg(feature = "hd")]
+use crate::hd::HdError;
+#[cfg(feature = "session")]
+use crate::session::SessionError;
+#[cfg(feature = "session")]
+use crate::negotiation::NegotiationError;
+
+
+/*---- ENUMS ----*/
+
+/// Crate-wide error type. Every module reports failures through its own
+/// error enum, each of which converts into this one so that callers
+/// mixing several algorithms can use a single error type with `?`
+#[derive(Debug)]
+pub enum Error {
+    #[cfg(feature = "rsa")]
+    Rsa(RsaError),                  // RSA key generation or operation failed
+    #[cfg(feature = "aes")]
+    Cipher(CipherError),            // symmetric encryption or decryption failed
+    #[cfg(feature = "ecc")]
+    Ec(EcError),                    // elliptic curve key or exchange failed
+    #[cfg(all(feature = "ecc", feature = "aes"))]
+    Ecies(EciesError),              // ECIES encryption or decryption failed
+    #[cfg(all(feature = "rsa", feature = "aes"))]
+    Envelope(EnvelopeError),        // an envelope could not be sealed or failed to open
+    #[cfg(all(feature = "aes", feature = "hashes-sha3"))]
+    Stream(StreamError),            // a stream could not be sealed or failed to open
+    #[cfg(feature = "dh")]
+    Dh(DhError),                    // Diffie-Hellman setup or exchange failed
+    #[cfg(feature = "ecdsa")]
+    Signature(SignatureError),      // signing or verification failed
+    #[cfg(feature = "ecdsa")]
+    Cosign(CosignError),            // key combination or a co-signing session failed
+    #[cfg(feature = "ecdsa")]
+    Vrf(VrfError),                  // VRF proof could not be made or did not verify
+    #[cfg(feature = "dsa")]
+    Dsa(DsaError),                  // DSA parameters, signing or verification failed
+    Encoding(EncodingError),        // conversion between representations failed
+    Prime(PrimeError),              // prime generation failed
+    Entropy(EntropyError),          // the RNG failed its health tests
+    Nonce(NonceError),              // nonces ran out or their saved state was malformed
+    Timeout,                        // key or prime generation ran out of budget
+    #[cfg(any(feature = "hashes-sha3", feature = "hashes-blake2"))]
+    Kdf(KdfError),                  // key derivation failed
+    #[cfg(feature = "keys")]
+    Key(KeyError),                  // key container could not be written or read
+    #[cfg(feature = "sharing")]
+    Sharing(SharingError),          // secret could not be split or recovered
+    #[cfg(feature = "hd")]
+    Hd(HdError),                    // key tree could not be built or walked
+    #[cfg(feature = "session")]
+    Session(SessionError),          // session handshake or channel failed
+    #[cfg(feature = "session")]
+    Negotiation(NegotiationError),  // no cipher suite could be agreed on
+    #[cfg(any(feature = "hashes-sha3", feature = "hashes-blake2"))]
+    SelfTest(SelfTestReport),       // a known-answer test at startup failed
+    Io(io::Error)                   // an underlying OS resource failed
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            #[cfg(feature = "rsa")]
+            Error::Rsa(e) => write!(f, "RSA error: {}", e),
+            #[cfg(feature = "aes")]
+            Error::Cipher(e) => write!(f, "Cipher error: {}", e),
+            #[cfg(feature = "ecc")]
+            Error::Ec(e) => write!(f, "ECC error: {}", e),
+            #[cfg(all(feature = "ecc", feature = "aes"))]
+            Error::Ecies(e) => write!(f, "ECIES error: {}", e),
+            #[cfg(all(feature = "rsa", feature = "aes"))]
+            Error::Envelope(e) => write!(f, "Envelope error: {}", e),
+            #[cfg(all(feature = "aes", feature = "hashes-sha3"))]
+            Error::Stream(e) => write!(f, "Stream error: {}", e),
+            #[cfg(feature = "dh")]
+            Error::Dh(e) => write!(f, "Diffie-Hellman error: {}", e),
+            #[cfg(feature = "ecdsa")]
+            Error::Signature(e) => write!(f, "Signature error: {}", e),
+            #[cfg(feature = "ecdsa")]
+            Error::Cosign(e) => write!(f, "Co-signing error: {}", e),
+            #[cfg(feature = "ecdsa")]
+            Error::Vrf(e) => write!(f, "VRF error: {}", e),
+            #[cfg(feature = "dsa")]
+            Error::Dsa(e) => write!(f, "DSA error: {}", e),
+            Error::Encoding(e) => write!(f, "Encoding error: {}", e),
+            Error::Prime(e) => write!(f, "Prime generation error: {}", e),
+            Error::Entropy(e) => write!(f, "Entropy error: {}", e),
+            Error::Nonce(e) => write!(f, "Nonce error: {}", e),
+            Error::Timeout => write!(f, "Generation ran out of its iteration or time budget"),
+            #[cfg(any(feature = "hashes-sha3", feature = "hashes-blake2"))]
+            Error::Kdf(e) => write!(f, "Key derivation error: {}", e),
+            #[cfg(feature = "keys")]
+            Error::Key(e) => write!(f, "Key container error: {}", e),
+            #[cfg(feature = "sharing")]
+            Error::Sharing(e) => write!(f, "Secret sharing error: {}", e),
+            #[cfg(feature = "hd")]
+            Error::Hd(e) => write!(f, "Key tree error: {}", e),
+            #[cfg(feature = "session")]
+            Error::Session(e) => write!(f, "Session error: {}", e),
+            #[cfg(feature = "session")]
+            Error::Negotiation(e) => write!(f, "Negotiation error: {}", e),
+            #[cfg(any(feature = "hashes-sha3", feature = "hashes-blake2"))]
+            Error::SelfTest(report) => write!(f, "Self-test failed: {}", report),
+            Error::Io(e) => write!(f, "IO error: {}", e)
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+#[cfg(feature = "rsa")]
+impl From<RsaError> for Error {
+    fn from(error: RsaError) -> Self {
+        match error {
+            RsaError::Timeout => Error::Timeout,
+            error => Error::Rsa(error)
+        }
+    }
+}
+
+#[cfg(feature = "aes")]
+impl From<CipherError> for Error {
+    fn from(error: CipherError) -> Self {
+        Error::Cipher(error)
+    }
+}
+
+#[cfg(feature = "ecc")]
+impl From<EcError> for Error {
+    fn from(error: EcError) -> Self {
+        match error {
+            EcError::Timeout => Error::Timeout,
+            error => Error::Ec(error)
+        }
+    }
+}
+
+#[cfg(all(feature = "ecc", feature = "aes"))]
+impl From<EciesError> for Error {
+    fn from(error: EciesError) -> Self {
+        Error::Ecies(error)
+    }
+}
+
+#[cfg(all(feature = "rsa", feature = "aes"))]
+impl From<EnvelopeError> for Error {
+    fn from(error: EnvelopeError) -> Self {
+        Error::Envelope(error)
+    }
+}
+
+#[cfg(all(feature = "aes", feature = "hashes-sha3"))]
+impl From<StreamError> for Error {
+    fn from(error: StreamError) -> Self {
+        Error::Stream(error)
+    }
+}
+
+#[cfg(feature = "dh")]
+impl From<DhError> for Error {
+    fn from(error: DhError) -> Self {
+        Error::Dh(error)
+    }
+}
+
+#[cfg(feature = "ecdsa")]
+impl From<SignatureError> for Error {
+    fn from(error: SignatureError) -> Self {
+        Error::Signature(error)
+    }
+}
+
+#[cfg(feature = "ecdsa")]
+impl From<CosignError> for Error {
+    fn from(error: CosignError) -> Self {
+        Error::Cosign(error)
+    }
+}
+
+#[cfg(feature = "ecdsa")]
+impl From<VrfError> for Error {
+    fn from(error: VrfError) -> Self {
+        Error::Vrf(error)
+    }
+}
+
+#[cfg(feature = "dsa")]
+impl From<DsaError> for Error {
+    fn from(error: DsaError) -> Self {
+        Error::Dsa(error)
+    }
+}
+
+impl From<EncodingError> for Error {
+    fn from(error: EncodingError) -> Self {
+        Error::Encoding(error)
+    }
+}
+
+impl From<PrimeError> for Error {
+    fn from(error: PrimeError) -> Self {
+        match error {
+            PrimeError::Timeout => Error::Timeout,
+            error => Error::Prime(error)
+        }
+    }
+}
+
+impl From<EntropyError> for Error {
+    fn from(error: EntropyError) -> Self {
+        Error::Entropy(error)
+    }
+}
+
+impl From<NonceError> for Error {
+    fn from(error: NonceError) -> Self {
+        Error::Nonce(error)
+    }
+}
+
+#[cfg(any(feature = "hashes-sha3", feature = "hashes-blake2"))]
+impl From<KdfError> for Error {
+    fn from(error: KdfError) -> Self {
+        Error::Kdf(error)
+    }
+}
+
+#[cfg(feature = "keys")]
+impl From<KeyError> for Error {
+    fn from(error: KeyError) -> Self {
+        Error::Key(error)
+    }
+}
+
+#[cfg(feature = "sharing")]
+impl From<SharingError> for Error {
+    fn from(error: SharingError) -> Self {
+        Error::Sharing(error)
+    }
+}
+
+#[cfg(feature = "hd")]
+impl From<HdError> for Error {
+    fn from(error: HdError) -> Self {
+        Error::Hd(error)
+    }
+}
+
+#[cfg(feature = "session")]
+impl From<SessionError> for Error {
+    fn from(error: SessionError) -> Self {
+        Error::Session(error)
+    }
+}
+
+#[cfg(feature = "session")]
+impl From<NegotiationError> for Error {
+    fn from(error: NegotiationError) -> Self {
+        Error::Negotiation(error)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,265 @@
+//! Fingerprints of public keys of every algorithm in the crate. A
+//! fingerprint is a hash of `keys::public_encoding`, so a keypair and
+//! the public key split off from it share a fingerprint, and keys of
+//! different algorithms can never collide on the same encoding.
+//!
+//! Fingerprints render as hex, base58check or a list of pronounceable
+//! words in the spirit of the PGP word list, one word per byte. Words
+//! at even and odd positions come from different lists, three and four
+//! letters long, so a dropped or swapped word is noticed when the list
+//! is read back
+
+use std::fmt;
+
+use crate::hash::crypto::{ hash_message, HashAlgorithm };
+use crate::keys::{ self, ExportableKey, KeyError };
+use crate::utils::ct;
+use crate::utils::encoding::{ EncodingError, bytes_to_hex, base58_encode };
+
+
+/*---- CONSTANTS ----*/
+
+/// Length in bytes of the base58check checksum
+const CHECKSUM_LENGTH: usize = 4;
+
+/// Letters making up the words. The high nibble of a byte picks the
+/// first letter and the low nibble the vowel and the ending
+const CONSONANTS: [&'static str; 16] = ["b", "d", "f", "g", "h", "j", "k", "l", "m", "n", "p", "r", "s", "t", "v", "z"];
+const VOWELS: [&'static str; 4] = ["a", "i", "o", "u"];
+const EVEN_ENDINGS: [&'static str; 4] = ["k", "m", "n", "t"];
+const ODD_ENDINGS: [&'static str; 4] = ["ba", "do", "li", "ru"];
+
+
+/*---- ENUMS ----*/
+
+/// Outcome of comparing two fingerprints
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum MatchResult {
+    Full,           // same hash and same bytes
+    Prefix(usize),  // the shorter fingerprint is a prefix of the longer, of this many bytes
+    Mismatch        // different hashes or differing bytes
+}
+
+
+/*---- STRUCTS ----*/
+
+/// Hash of the canonical public encoding of a key
+#[derive(Debug, Clone)]
+pub struct Fingerprint {
+    algorithm: HashAlgorithm,   // hash the fingerprint was taken with
+    bytes: Vec<u8>              // digest, or a prefix of it
+}
+
+
+/*---- IMPLEMENTATIONS ----*/
+
+impl Fingerprint {
+
+    /// Rebuilds a fingerprint from its bytes, such as a prefix read
+    /// back from one of the renderings
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `algorithm` - Hash the fingerprint was taken with
+    /// * `bytes` - Fingerprint bytes
+    pub fn from_bytes(algorithm: HashAlgorithm, bytes: &[u8]) -> Self {
+        Fingerprint { algorithm: algorithm, bytes: bytes.to_vec() }
+    }
+
+    /// Hash the fingerprint was taken with
+    pub fn algorithm(&self) -> &HashAlgorithm {
+        &self.algorithm
+    }
+
+    /// Raw fingerprint bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Lowercase hex rendering
+    pub fn to_hex(&self) -> String {
+        bytes_to_hex(&self.bytes)
+    }
+
+    /// Base58 rendering with a four byte checksum appended
+    pub fn to_base58check(&self) -> String {
+        let mut payload = self.bytes.clone();
+        let digest = hash_message(&hash_message(&payload, HashAlgorithm::Sha3_256), HashAlgorithm::Sha3_256);
+        payload.extend_from_slice(&digest[..CHECKSUM_LENGTH]);
+
+        base58_encode(&payload)
+    }
+
+    /// Space separated word rendering, read back by `words_to_bytes`
+    pub fn to_words(&self) -> String {
+        let words: Vec<String> = self.bytes.iter()
+            .enumerate()
+            .map(|(position, byte)| word(*byte, position % 2 == 1))
+            .collect();
+
+        words.join(" ")
+    }
+}
+
+/// Fingerprints are compared in constant time
+impl PartialEq for Fingerprint {
+    fn eq(&self, other: &Fingerprint) -> bool {
+        self.algorithm == other.algorithm && ct::ct_eq(&self.bytes, &other.bytes)
+    }
+}
+
+impl Eq for Fingerprint {}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Fingerprint of a key's public half
+/// 
+/// ### Arguments
+/// 
+/// * `key` - Key of any algorithm, with or without its private values
+/// * `algorithm` - Hash to take the fingerprint with
+pub fn of(key: &dyn ExportableKey, algorithm: HashAlgorithm) -> Result<Fingerprint, KeyError> {
+    let encoding = keys::public_encoding(key)?;
+
+    Ok(Fingerprint {
+        bytes: hash_message(&encoding, algorithm.clone()),
+        algorithm: algorithm
+    })
+}
+
+/// Compares two fingerprints in constant time. A shorter fingerprint
+/// matching the start of a longer one is reported as a prefix match, so
+/// callers can decide how many bytes a user has to check
+/// 
+/// ### Arguments
+/// 
+/// * `a` - First fingerprint
+/// * `b` - Second fingerprint
+pub fn compare(a: &Fingerprint, b: &Fingerprint) -> MatchResult {
+    let shared = a.bytes.len().min(b.bytes.len());
+
+    if a.algorithm != b.algorithm || shared == 0 || !ct::ct_eq(&a.bytes[..shared], &b.bytes[..shared]) {
+        return MatchResult::Mismatch;
+    }
+
+    if a.bytes.len() == b.bytes.len() {
+        MatchResult::Full
+    } else {
+        MatchResult::Prefix(shared)
+    }
+}
+
+/// Reads back the bytes of a word rendering
+/// 
+/// ### Arguments
+/// 
+/// * `words` - Words separated by whitespace
+pub fn words_to_bytes(words: &str) -> Result<Vec<u8>, EncodingError> {
+    words.split_whitespace()
+        .enumerate()
+        .map(|(position, text)| {
+            let odd = position % 2 == 1;
+
+            (0..=255u8)
+                .find(|byte| word(*byte, odd) == text.to_lowercase())
+                .ok_or(EncodingError::InvalidWord)
+        })
+        .collect()
+}
+
+/// Word for a byte at an even or odd position
+/// 
+/// ### Arguments
+/// 
+/// * `byte` - Byte to render
+/// * `odd` - Whether the byte is at an odd position
+fn word(byte: u8, odd: bool) -> String {
+    let endings = if odd { &ODD_ENDINGS } else { &EVEN_ENDINGS };
+    let low = (byte & 0x0f) as usize;
+
+    format!("{}{}{}", CONSONANTS[(byte >> 4) as usize], VOWELS[low >> 2], endings[low & 3])
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod fingerprint_test {
+
+    use num_bigint::BigUint;
+    use crate::hash::crypto::HashAlgorithm;
+    use crate::encryption::rsa::{ RSA, RsaPublicKey };
+    use crate::key_exchange::diffie_hellman::DiffieHellman;
+    use crate::utils::ecc::ECPKeypair;
+    use crate::utils::ecc_curves::ECPSupportedCurves;
+    use crate::utils::encoding::hex_to_bytes;
+    use crate::utils::rng::os_rng;
+    use crate::fingerprint::{ self, Fingerprint, MatchResult, words_to_bytes };
+
+    fn int(hex: &str) -> BigUint {
+        BigUint::from_bytes_be(&hex_to_bytes(hex).unwrap())
+    }
+
+    #[test]
+    fn fingerprints_of_fixed_keys_are_pinned() {
+        // (2^127 - 1)(2^89 - 1), e = 65537
+        let n = int("ffffffffffffffffffffff7ffffffffe0000000000000000000001");
+        let rsa = RSA::from_public_key(HashAlgorithm::Sha3_256, &RsaPublicKey::new(n, int("010001")).unwrap());
+
+        // The generator itself as the public point
+        let mut ecc = ECPKeypair::new(ECPSupportedCurves::SECP256R1);
+        ecc.q = ecc.group.g.clone();
+
+        // P = 2^127 - 1, G = 3, GX = 3^5
+        let mut dh = DiffieHellman::new();
+        dh.p = int("7fffffffffffffffffffffffffffffff");
+        dh.g = int("03");
+        dh.gx = int("f3");
+
+        assert_eq!(fingerprint::of(&rsa, HashAlgorithm::Sha3_256).unwrap().to_hex(), "6107d348a02cf68287c922ed04e38b625d54e3f599f05a9845b479f0e7fccea8");
+        assert_eq!(fingerprint::of(&ecc, HashAlgorithm::Sha3_256).unwrap().to_hex(), "223b6f810ab24e526eeb3a6ddfb8bf9b6df44697de72c040a22e754bf407d45a");
+        assert_eq!(fingerprint::of(&dh, HashAlgorithm::Sha3_256).unwrap().to_hex(), "564cd4013908c2cbd113ef088cd0cdf54495f9d2b7c905a5311554e4d2dcf257");
+    }
+
+    #[test]
+    fn keypair_and_public_key_share_a_fingerprint() {
+        let mut rng = os_rng().unwrap();
+        let keypair = ECPKeypair::new(ECPSupportedCurves::SECP384R1).setup(&mut rng).unwrap();
+        let mut public = ECPKeypair::new(ECPSupportedCurves::SECP384R1);
+        public.q = keypair.q.clone();
+
+        let first = fingerprint::of(&keypair, HashAlgorithm::Sha3_256).unwrap();
+        let second = fingerprint::of(&public, HashAlgorithm::Sha3_256).unwrap();
+
+        assert_eq!(first, second);
+        assert_ne!(first, fingerprint::of(&public, HashAlgorithm::Sha3_512).unwrap());
+    }
+
+    #[test]
+    fn renderings_and_comparison() {
+        let fingerprint = Fingerprint::from_bytes(HashAlgorithm::Sha3_256, &[0x00, 0x01, 0x7f, 0x80, 0xfe, 0xff]);
+
+        assert_eq!(fingerprint.to_hex(), "00017f80feff");
+        assert_eq!(fingerprint.to_words(), "bak bado lut maba zun zuru");
+        assert_eq!(words_to_bytes(&fingerprint.to_words()).unwrap(), fingerprint.as_bytes());
+
+        // Swapping two words breaks the read back
+        assert!(words_to_bytes("bado bak").is_err());
+
+        let prefix = Fingerprint::from_bytes(HashAlgorithm::Sha3_256, &[0x00, 0x01, 0x7f]);
+        let other = Fingerprint::from_bytes(HashAlgorithm::Sha3_256, &[0x00, 0x02, 0x7f]);
+
+        assert_eq!(fingerprint::compare(&fingerprint, &fingerprint.clone()), MatchResult::Full);
+        assert_eq!(fingerprint::compare(&prefix, &fingerprint), MatchResult::Prefix(3));
+        assert_eq!(fingerprint::compare(&other, &fingerprint), MatchResult::Mismatch);
+        assert_eq!(fingerprint::compare(&fingerprint, &Fingerprint::from_bytes(HashAlgorithm::Sha3_512, fingerprint.as_bytes())), MatchResult::Mismatch);
+        assert!(fingerprint.to_base58check() != Fingerprint::from_bytes(HashAlgorithm::Sha3_256, &[0x00, 0x01]).to_base58check());
+    }
+}
\ No newline at end of file
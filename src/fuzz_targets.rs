@@ -0,0 +1,309 @@
+//! Randomized robustness checks for every decoder that reads bytes from
+//! outside the crate. Each target gets `CASES` inputs from a ChaCha
+//! generator seeded with its name: purely random bytes, and truncated,
+//! extended, spliced and bit-flipped copies of valid encodings. A target
+//! may accept or reject an input, but it may never panic. A panic is
+//! reported with the target name, the case number and the input in hex,
+//! so it can be pinned as a regression input next to the decoder it hit
+
+use std::panic::{ self, AssertUnwindSafe };
+use rand::{ ChaChaRng, Rng, SeedableRng };
+
+use crate::utils::encoding::bytes_to_hex;
+
+
+/*---- CONSTANTS ----*/
+
+/// Inputs fed to every target
+pub const CASES: usize = 10_000;
+
+/// Longest purely random input
+const MAX_RANDOM_LENGTH: usize = 192;
+
+/// Byte values that tend to sit on length and sign boundaries
+const BOUNDARY_BYTES: [u8; 6] = [0x00, 0x01, 0x7f, 0x80, 0x81, 0xff];
+
+
+/*---- FUNCTIONS ----*/
+
+/// Feeds `CASES` inputs to a decoder, failing with the offending input
+/// if any of them panics
+/// 
+/// ### Arguments
+/// 
+/// * `target` - Name of the target, which also seeds its inputs
+/// * `samples` - Valid encodings to mutate
+/// * `decode` - Decoder under test. Its result is ignored
+pub fn run(target: &str, samples: &[Vec<u8>], mut decode: impl FnMut(&[u8])) -> () {
+    let mut rng = ChaChaRng::from_seed(&target.bytes().map(|byte| byte as u32).collect::<Vec<u32>>());
+
+    for case in 0..CASES {
+        let input = if samples.is_empty() || case % 8 == 0 {
+            random_bytes(&mut rng)
+        } else {
+            let sample = &samples[rng.gen_range(0, samples.len())];
+            mutate(&mut rng, sample)
+        };
+
+        if panic::catch_unwind(AssertUnwindSafe(|| decode(&input))).is_err() {
+            panic!("{} panicked on case {} with input {}", target, case, bytes_to_hex(&input));
+        }
+    }
+}
+
+/// Random bytes of random length
+/// 
+/// ### Arguments
+/// 
+/// * `rng` - Generator to draw from
+fn random_bytes(rng: &mut ChaChaRng) -> Vec<u8> {
+    let length = rng.gen_range(0, MAX_RANDOM_LENGTH + 1);
+    (0..length).map(|_| rng.gen::<u8>()).collect()
+}
+
+/// Derives an input from a valid encoding with one of a handful of
+/// structural or bit level mutations
+/// 
+/// ### Arguments
+/// 
+/// * `rng` - Generator to draw from
+/// * `sample` - Valid encoding
+fn mutate(rng: &mut ChaChaRng, sample: &[u8]) -> Vec<u8> {
+    let mut input = sample.to_vec();
+
+    if input.is_empty() {
+        return random_bytes(rng);
+    }
+
+    match rng.gen_range(0, 6) {
+        // Flip a few bits
+        0 => {
+            for _ in 0..rng.gen_range(1, 5) {
+                let index = rng.gen_range(0, input.len());
+                input[index] ^= 1 << rng.gen_range(0, 8);
+            }
+        },
+
+        // Truncate
+        1 => input.truncate(rng.gen_range(0, sample.len())),
+
+        // Append random bytes
+        2 => {
+            let extra = random_bytes(rng);
+            input.extend(extra);
+        },
+
+        // Overwrite a byte with a boundary value
+        3 => {
+            let index = rng.gen_range(0, input.len());
+            input[index] = BOUNDARY_BYTES[rng.gen_range(0, BOUNDARY_BYTES.len())];
+        },
+
+        // Copy a slice of the sample over another position
+        4 => {
+            let start = rng.gen_range(0, sample.len());
+            let end = rng.gen_range(start, sample.len() + 1);
+            let at = rng.gen_range(0, input.len());
+
+            for (offset, byte) in sample[start..end].iter().enumerate() {
+                if at + offset < input.len() {
+                    input[at + offset] = *byte;
+                }
+            }
+        },
+
+        // Remove a range
+        _ => {
+            let start = rng.gen_range(0, sample.len());
+            let end = rng.gen_range(start, sample.len() + 1);
+            input.drain(start..end);
+        }
+    }
+
+    input
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod fuzz_targets_test {
+
+    use rand::{ ChaChaRng, SeedableRng };
+    use num_bigint::BigUint;
+    use num_traits::FromPrimitive;
+    use crate::fuzz_targets::{ run, CASES };
+    use crate::signature::der::{ encode_signature, decode_signature };
+    use crate::utils::encoding::{ base58_encode, base58_decode, bech32_encode, bech32_decode };
+    #[cfg(feature = "ecdsa")]
+    use crate::signature::ecdsa::ECDSASignature;
+    #[cfg(feature = "dsa")]
+    use crate::signature::dsa::DSASignature;
+
+    fn text(input: &[u8]) -> String {
+        String::from_utf8_lossy(input).into_owned()
+    }
+
+    #[test]
+    fn harness_reports_panicking_inputs() {
+        let mut calls = 0;
+        run("harness", &[vec![1, 2, 3]], |_| calls += 1);
+        assert_eq!(calls, CASES);
+
+        let caught = std::panic::catch_unwind(|| run("harness", &[], |input| { let _ = input[0]; }));
+        assert!(caught.is_err());
+    }
+
+    #[test]
+    fn der_signatures() {
+        let samples = vec![
+            encode_signature(&BigUint::from_u32(1).unwrap(), &BigUint::from_u32(0x80).unwrap()),
+            encode_signature(&BigUint::from_bytes_be(&[0xff; 66]), &BigUint::from_bytes_be(&[0x7f; 66]))
+        ];
+
+        run("der", &samples, |input| {
+            let _ = decode_signature(input);
+
+            #[cfg(feature = "ecdsa")]
+            let _ = ECDSASignature::from_der(input);
+
+            #[cfg(feature = "dsa")]
+            let _ = DSASignature::from_der(input);
+        });
+    }
+
+    #[test]
+    fn text_encodings() {
+        let base58 = vec![base58_encode(&[0, 0, 1, 2, 3, 0xff]).into_bytes()];
+        let bech32 = vec![bech32_encode("id", &[0x5a; 20]).unwrap().into_bytes()];
+
+        run("base58", &base58, |input| { let _ = base58_decode(&text(input)); });
+        run("bech32", &bech32, |input| { let _ = bech32_decode(&text(input)); });
+    }
+
+    #[cfg(feature = "ecc")]
+    #[test]
+    fn sec1_points() {
+        use crate::utils::ecc::{ ECPPublicKey, encode_point, decode_point };
+        use crate::utils::ecc_curves::{ ECPGroup, ECPSupportedCurves };
+
+        for curve in &[ECPSupportedCurves::SECP256R1, ECPSupportedCurves::BP384R1, ECPSupportedCurves::Curve25519] {
+            let group = ECPGroup::new(curve.clone());
+            let samples = vec![
+                encode_point(&group, &group.g, false).unwrap(),
+                encode_point(&group, &group.g, true).unwrap()
+            ];
+
+            run(&format!("sec1 {:?}", curve), &samples, |input| {
+                let _ = decode_point(&group, input);
+                let _ = ECPPublicKey::new(curve.clone(), input);
+            });
+        }
+    }
+
+    #[cfg(feature = "ecdsa")]
+    #[test]
+    fn addresses() {
+        use crate::hash::crypto::HashAlgorithm;
+        use crate::signature::identity::{ Address, address_from_public };
+        use crate::utils::ecc_curves::{ ECPGroup, ECPSupportedCurves };
+
+        let group = ECPGroup::new(ECPSupportedCurves::SECP256R1);
+        let address = address_from_public(&group.g, &group, HashAlgorithm::Sha3_256).unwrap();
+
+        run("address", &[address.to_string().into_bytes()], |input| {
+            let _ = Address::from_base58check(&text(input));
+            let _ = Address::from_bytes(input);
+        });
+    }
+
+    #[cfg(feature = "keys")]
+    #[test]
+    fn key_containers() {
+        use crate::encryption::rsa::RSA;
+        use crate::hash::crypto::HashAlgorithm;
+        use crate::key_exchange::diffie_hellman::DiffieHellman;
+        use crate::keys::{ save, load };
+        use crate::utils::ecc::ECPKeypair;
+        use crate::utils::ecc_curves::ECPSupportedCurves;
+
+        let mut rng = ChaChaRng::from_seed(&[0x6b, 0x65, 0x79, 0x73]);
+        let exponent = BigUint::from_u32(65537).unwrap();
+        let rsa = RSA::new(HashAlgorithm::Sha3_256, true).allow_insecure_for_testing().generate_keypair(256, &exponent, &mut rng).unwrap();
+        let rsa_public = RSA::from_public_key(HashAlgorithm::Sha3_256, &rsa.public_key().unwrap());
+        let ecc = ECPKeypair::new(ECPSupportedCurves::SECP256R1).setup(&mut rng).unwrap();
+        let dh = DiffieHellman::new().setup(64, &mut rng).unwrap();
+
+        // Sealed containers are fuzzed without a passphrase: every record
+        // is still parsed, while PBKDF2 is kept out of the loop
+        let samples = vec![
+            save(&rsa, None, &mut rng).unwrap(),
+            save(&rsa_public, None, &mut rng).unwrap(),
+            save(&ecc, None, &mut rng).unwrap(),
+            save(&dh, None, &mut rng).unwrap(),
+            save(&ecc, Some(b"passphrase"), &mut rng).unwrap()
+        ];
+
+        run("keys", &samples, |input| { let _ = load(input, None); });
+    }
+
+    #[cfg(feature = "rsa")]
+    #[test]
+    fn rsa_ciphertexts() {
+        use crate::encryption::AsymmetricKeyMode;
+        use crate::encryption::rsa::{ RSA, RsaVerifyContext };
+        use crate::hash::crypto::HashAlgorithm;
+
+        let mut rng = ChaChaRng::from_seed(&[0x72, 0x73, 0x61]);
+        let exponent = BigUint::from_u32(65537).unwrap();
+        let rsa = RSA::new_raw_unsafe(HashAlgorithm::Sha3_256, true).allow_insecure_for_testing().generate_keypair(256, &exponent, &mut rng).unwrap();
+        let padded = RSA::new(HashAlgorithm::Sha3_256, true).allow_insecure_for_testing().generate_keypair(768, &exponent, &mut rng).unwrap();
+        let context = RsaVerifyContext::new(&rsa.public_key().unwrap());
+
+        let samples = vec![
+            rsa.encrypt(b"a message spanning more than one chunk", AsymmetricKeyMode::Public, &mut rng).unwrap(),
+            rsa.encrypt(b"signed", AsymmetricKeyMode::Private, &mut rng).unwrap(),
+            padded.encrypt(b"a message spanning more than one OAEP chunk", AsymmetricKeyMode::Public, &mut rng).unwrap()
+        ];
+
+        run("rsa", &samples, |input| {
+            let _ = padded.decrypt(input, AsymmetricKeyMode::Private, &mut rng);
+            let _ = rsa.decrypt(input, AsymmetricKeyMode::Private, &mut rng);
+            let _ = rsa.decrypt(input, AsymmetricKeyMode::Public, &mut rng);
+            let _ = context.verify(b"signed", input);
+        });
+    }
+
+    #[cfg(all(feature = "ecc", feature = "aes"))]
+    #[test]
+    fn ecies_blobs() {
+        use crate::encryption::ecies::{ encrypt, decrypt };
+        use crate::utils::ecc::{ ECPKeypair, ValidationLevel };
+        use crate::utils::ecc_curves::ECPSupportedCurves;
+
+        let mut rng = ChaChaRng::from_seed(&[0xec, 0x1e, 0x5]);
+        let recipient = ECPKeypair::new(ECPSupportedCurves::Curve25519).setup(&mut rng).unwrap();
+        let recipient_pub = recipient.group.validate(recipient.q.clone(), ValidationLevel::FullSubgroup).unwrap();
+        let samples = vec![encrypt(&recipient_pub, b"sealed for the recipient", &mut rng).unwrap()];
+
+        run("ecies", &samples, |input| { let _ = decrypt(&recipient, input, &mut rng); });
+    }
+
+    #[cfg(feature = "wasm")]
+    #[test]
+    fn sealed_messages() {
+        use crate::wasm::{ seal, seal_committing, open, open_committing };
+
+        let key = [0x42; 32];
+        let samples = vec![
+            seal(&key, b"sealed message").unwrap(),
+            seal_committing(&key, b"sealed message").unwrap()
+        ];
+
+        run("open", &samples, |input| {
+            let _ = open(&key, input);
+            let _ = open_committing(&key, input);
+            let _ = open(&input[..input.len().min(32)], &samples[0]);
+        });
+    }
+}
\ No newline at end of file
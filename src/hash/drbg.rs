@@ -0,0 +1,187 @@
+//! HMAC_DRBG as described in NIST SP 800-90A, section 10.1.2, for
+//! expanding a short seed into as much reproducible output as a caller
+//! needs. It is instantiated once from the seed and never reseeded, so
+//! the same seed and hash give the same stream on every run and every
+//! platform. Output is generated a block of the hash length at a time,
+//! each block being one generate request, so a stream doesn't depend on
+//! how callers split their reads. The output is only as secret as the
+//! seed
+
+use rand::Rng;
+
+use crate::hash::hmac::hmac;
+use crate::hash::crypto::HashAlgorithm;
+use crate::utils::rng::CryptoRngCore;
+use crate::utils::zeroize::zeroize_bytes;
+
+
+/*---- STRUCTS ----*/
+
+/// HMAC_DRBG state, handing out its output through `rand::Rng`
+pub struct HmacDrbg {
+    k: Vec<u8>,                 // HMAC key K
+    v: Vec<u8>,                 // chaining value V
+    buffer: Vec<u8>,            // generated bytes not yet handed out
+    algorithm: HashAlgorithm    // hash underneath HMAC
+}
+
+
+/*---- IMPLEMENTATIONS ----*/
+
+impl HmacDrbg {
+
+    /// Instantiates the generator from a seed, which plays the part of
+    /// the entropy input, nonce and personalisation string together
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `seed` - Seed to expand
+    /// * `algorithm` - Hash used for the HMAC
+    pub fn new(seed: &[u8], algorithm: HashAlgorithm) -> Self {
+        let mut generator = HmacDrbg {
+            k: vec![0x00; algorithm.output_size()],
+            v: vec![0x01; algorithm.output_size()],
+            buffer: Vec::new(),
+            algorithm: algorithm
+        };
+
+        generator.update(seed);
+        generator
+    }
+
+    /// Generates one block of output, V = HMAC_K(V), and moves the state
+    /// past it so that the block can't be recovered from a later state
+    fn refill(&mut self) -> () {
+        self.v = hmac(&self.k, &self.v, self.algorithm.clone());
+        self.buffer.extend_from_slice(&self.v);
+        self.update(&[]);
+    }
+
+    /// K = HMAC_K(V || 0x00 || input) and V = HMAC_K(V), followed by the
+    /// same with 0x01 when input is given
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `input` - Provided data, empty after generating
+    fn update(&mut self, input: &[u8]) -> () {
+        for marker in &[0x00, 0x01] {
+            let mut message = self.v.clone();
+            message.push(*marker);
+            message.extend_from_slice(input);
+
+            self.k = hmac(&self.k, &message, self.algorithm.clone());
+            self.v = hmac(&self.k, &self.v, self.algorithm.clone());
+            zeroize_bytes(&mut message);
+
+            if input.is_empty() {
+                break;
+            }
+        }
+    }
+}
+
+impl Rng for HmacDrbg {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0; 4];
+        self.fill_bytes(&mut bytes);
+
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        self.fill_bytes(&mut bytes);
+
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        let mut filled = 0;
+
+        while filled < dest.len() {
+            if self.buffer.is_empty() {
+                self.refill();
+            }
+
+            let count = (dest.len() - filled).min(self.buffer.len());
+            dest[filled..filled + count].copy_from_slice(&self.buffer[..count]);
+            zeroize_bytes(&mut self.buffer[..count]);
+            self.buffer.drain(..count);
+            filled += count;
+        }
+    }
+}
+
+/// The output follows from the seed alone, so there is nothing for the
+/// health tests to find, and running them would use up output the first
+/// time in a process only
+impl CryptoRngCore for HmacDrbg {
+    fn health_tested(&self) -> bool {
+        false
+    }
+}
+
+impl Drop for HmacDrbg {
+    fn drop(&mut self) {
+        zeroize_bytes(&mut self.k);
+        zeroize_bytes(&mut self.v);
+        zeroize_bytes(&mut self.buffer);
+    }
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(all(test, feature = "hashes-sha2"))]
+mod drbg_test {
+
+    use rand::Rng;
+    use crate::hash::drbg::HmacDrbg;
+    use crate::hash::hmac::hmac;
+    use crate::hash::crypto::HashAlgorithm;
+
+    #[test]
+    fn same_seed_same_stream() {
+        let mut first = HmacDrbg::new(b"seed", HashAlgorithm::Sha256);
+        let mut second = HmacDrbg::new(b"seed", HashAlgorithm::Sha256);
+        let mut other = HmacDrbg::new(b"seed!", HashAlgorithm::Sha256);
+
+        // Requests of any size split the stream the same way
+        let mut whole = vec![0; 100];
+        first.fill_bytes(&mut whole);
+        let mut pieces = vec![0; 100];
+
+        for piece in pieces.chunks_mut(7) {
+            second.fill_bytes(piece);
+        }
+
+        let mut different = vec![0; 100];
+        other.fill_bytes(&mut different);
+
+        assert_eq!(whole, pieces);
+        assert!(whole != different);
+    }
+
+    #[test]
+    fn first_block_matches_the_specification() {
+        // Instantiate with "seed", then V = HMAC_K(V), worked through
+        // step by step with the HMAC primitive
+        let mut generator = HmacDrbg::new(b"seed", HashAlgorithm::Sha256);
+        let mut block = vec![0; 32];
+        generator.fill_bytes(&mut block);
+
+        let mut k = vec![0x00; 32];
+        let mut v = vec![0x01; 32];
+
+        for marker in &[0x00, 0x01] {
+            let mut message = v.clone();
+            message.push(*marker);
+            message.extend_from_slice(b"seed");
+            k = hmac(&k, &message, HashAlgorithm::Sha256);
+            v = hmac(&k, &v, HashAlgorithm::Sha256);
+        }
+
+        v = hmac(&k, &v, HashAlgorithm::Sha256);
+        assert_eq!(block, v);
+    }
+}
\ No newline at end of file
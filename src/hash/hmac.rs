@@ -0,0 +1,81 @@
+use crate::hash::crypto::{ hash_message, HashAlgorithm };
+
+
+/*---- CONSTANTS ----*/
+
+const IPAD: u8 = 0x36;
+const OPAD: u8 = 0x5c;
+
+
+/*---- FUNCTIONS ----*/
+
+/// Keyed-hash message authentication code as described in RFC 2104:
+/// H((K ^ opad) || H((K ^ ipad) || message)). Keys longer than the
+/// block size of the hash are hashed down first.
+/// 
+/// ### Arguments
+/// 
+/// * `key` - MAC key
+/// * `message` - Message to authenticate
+/// * `algorithm` - Hash algorithm to build the MAC over
+pub fn hmac(key: &[u8], message: &[u8], algorithm: HashAlgorithm) -> Vec<u8> {
+    let block_size = algorithm.block_size();
+    let mut padded_key = if key.len() > block_size {
+        hash_message(key, algorithm.clone())
+    } else {
+        key.to_vec()
+    };
+
+    padded_key.resize(block_size, 0);
+
+    let mut inner = Vec::with_capacity(block_size + message.len());
+    inner.extend(padded_key.iter().map(|b| b ^ IPAD));
+    inner.extend_from_slice(message);
+
+    let inner_hash = hash_message(&inner, algorithm.clone());
+
+    let mut outer = Vec::with_capacity(block_size + inner_hash.len());
+    outer.extend(padded_key.iter().map(|b| b ^ OPAD));
+    outer.extend_from_slice(&inner_hash);
+
+    hash_message(&outer, algorithm)
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(all(test, feature = "hashes-sha3", feature = "hashes-blake2"))]
+mod hmac_test {
+
+    use crate::hash::hmac::hmac;
+    use crate::hash::crypto::HashAlgorithm;
+
+    #[test]
+    fn output_matches_digest_length() {
+        let tag = hmac(b"key", b"The quick brown fox jumps over the lazy dog", HashAlgorithm::Sha3_256);
+        assert_eq!(tag.len(), 32);
+
+        let tag = hmac(b"key", b"The quick brown fox jumps over the lazy dog", HashAlgorithm::Blake2b);
+        assert_eq!(tag.len(), 64);
+    }
+
+    #[test]
+    fn long_keys_are_hashed_first() {
+        let long_key = [0x42u8; 200];
+        let hashed_key = crate::hash::crypto::hash_message(&long_key, HashAlgorithm::Sha3_256);
+
+        assert_eq!(
+            hmac(&long_key, b"message", HashAlgorithm::Sha3_256),
+            hmac(&hashed_key, b"message", HashAlgorithm::Sha3_256)
+        );
+    }
+
+    #[test]
+    fn different_keys_give_different_tags() {
+        assert_ne!(
+            hmac(b"first key", b"message", HashAlgorithm::Blake2s),
+            hmac(b"second key", b"message", HashAlgorithm::Blake2s)
+        );
+    }
+
+}
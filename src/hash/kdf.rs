@@ -0,0 +1,759 @@
+use std::fmt;
+
+use crate::hash::hmac::hmac;
+use crate::hash::crypto::{ hash_message, HashAlgorithm };
+use crate::utils::zeroize::zeroize_bytes;
+
+
+/*---- CONSTANTS ----*/
+
+/// Memory scrypt may use unless given its own limit, 256 MiB
+#[cfg(feature = "hashes-sha2")]
+pub const SCRYPT_DEFAULT_MAX_MEMORY: usize = 256 * 1024 * 1024;
+
+/// Size in bytes of a Salsa20 block, the unit scrypt mixes
+#[cfg(feature = "hashes-sha2")]
+const SALSA_BLOCK_SIZE: usize = 64;
+
+
+/*---- ENUMS ----*/
+
+/// Errors raised while deriving key material
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum KdfError {
+    OutputTooLong,      // requested more output than the KDF can produce
+    NoIterations,       // PBKDF2 was asked for zero iterations
+    InvalidCost,        // scrypt cost parameters are out of range
+    MemoryLimit         // scrypt would need more memory than allowed
+}
+
+impl fmt::Display for KdfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KdfError::OutputTooLong => write!(f, "Requested output length is too long for the KDF"),
+            KdfError::NoIterations => write!(f, "At least one iteration is required"),
+            KdfError::InvalidCost => write!(f, "scrypt needs N a power of two above 1, and r and p within range"),
+            KdfError::MemoryLimit => write!(f, "scrypt parameters need more memory than allowed")
+        }
+    }
+}
+
+/// Key derivation applied to a Diffie-Hellman shared secret. HKDF is
+/// the default; the counter-mode KDFs are there for peers that require
+/// them, such as ECIES and CMS implementations following SEC 1 or
+/// SP 800-56A
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum KeyDerivation {
+    Hkdf,       // RFC 5869 extract-and-expand
+    X963,       // ANSI X9.63, Hash(Z || counter || SharedInfo)
+    Concat      // NIST SP 800-56A one-step, Hash(counter || Z || OtherInfo)
+}
+
+impl Default for KeyDerivation {
+    fn default() -> Self {
+        KeyDerivation::Hkdf
+    }
+}
+
+impl KeyDerivation {
+
+    /// Derives key material from a shared secret. HKDF takes the salt
+    /// and info separately, while the counter-mode KDFs take the salt
+    /// followed by the info as their shared or other info
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `secret` - Shared secret to derive from
+    /// * `salt` - Salt, possibly empty
+    /// * `info` - Context information binding the key to its purpose
+    /// * `out_len` - Length of the derived key in bytes
+    /// * `algorithm` - Hash algorithm to derive with
+    pub fn derive(
+        &self,
+        secret: &[u8],
+        salt: &[u8],
+        info: &[u8],
+        out_len: usize,
+        algorithm: HashAlgorithm
+    ) -> Result<Vec<u8>, KdfError>
+    {
+        if *self == KeyDerivation::Hkdf {
+            return hkdf(secret, salt, info, out_len, algorithm);
+        }
+
+        let mut shared_info = salt.to_vec();
+        shared_info.extend_from_slice(info);
+
+        match self {
+            KeyDerivation::X963 => x963_kdf(secret, &shared_info, out_len, algorithm),
+            _ => concat_kdf(secret, &shared_info, out_len, algorithm)
+        }
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// HMAC-based extract-and-expand key derivation function (RFC 5869).
+/// Turns input keying material, such as a Diffie-Hellman shared secret,
+/// into uniformly random key material of the requested length.
+/// 
+/// ### Arguments
+/// 
+/// * `ikm` - Input keying material
+/// * `salt` - Optional salt. An empty salt is replaced by a zeroed block
+/// * `info` - Context and application specific information
+/// * `out_len` - Length of the output key material in bytes
+/// * `algorithm` - Hash algorithm to build the HMAC over
+pub fn hkdf(
+    ikm: &[u8],
+    salt: &[u8],
+    info: &[u8],
+    out_len: usize,
+    algorithm: HashAlgorithm
+) -> Result<Vec<u8>, KdfError>
+{
+    let mut prk = hkdf_extract(ikm, salt, algorithm.clone());
+    let okm = hkdf_expand(&prk, info, out_len, algorithm);
+    zeroize_bytes(&mut prk);
+
+    okm
+}
+
+/// HKDF extract step: PRK = HMAC-Hash(salt, IKM)
+/// 
+/// ### Arguments
+/// 
+/// * `ikm` - Input keying material
+/// * `salt` - Optional salt. An empty salt is replaced by a zeroed block
+/// * `algorithm` - Hash algorithm to build the HMAC over
+pub fn hkdf_extract(ikm: &[u8], salt: &[u8], algorithm: HashAlgorithm) -> Vec<u8> {
+    if salt.is_empty() {
+        let zero_salt = vec![0; algorithm.output_size()];
+        return hmac(&zero_salt, ikm, algorithm);
+    }
+
+    hmac(salt, ikm, algorithm)
+}
+
+/// HKDF expand step: T(i) = HMAC-Hash(PRK, T(i - 1) || info || i)
+/// 
+/// ### Arguments
+/// 
+/// * `prk` - Pseudorandom key from the extract step
+/// * `info` - Context and application specific information
+/// * `out_len` - Length of the output key material in bytes
+/// * `algorithm` - Hash algorithm to build the HMAC over
+pub fn hkdf_expand(
+    prk: &[u8],
+    info: &[u8],
+    out_len: usize,
+    algorithm: HashAlgorithm
+) -> Result<Vec<u8>, KdfError>
+{
+    if out_len > 255 * algorithm.output_size() {
+        return Err(KdfError::OutputTooLong);
+    }
+
+    // Room for the last full block, so the buffer is never reallocated
+    let mut okm = Vec::with_capacity(out_len + algorithm.output_size());
+    let mut previous = Vec::new();
+    let mut counter: u8 = 1;
+
+    while okm.len() < out_len {
+        let mut input = previous.clone();
+        input.extend_from_slice(info);
+        input.push(counter);
+
+        let block = hmac(prk, &input, algorithm.clone());
+        okm.extend_from_slice(&block);
+
+        // Intermediate blocks are key material too
+        zeroize_bytes(&mut input);
+        zeroize_bytes(&mut previous);
+        previous = block;
+
+        counter = counter.wrapping_add(1);
+    }
+
+    zeroize_bytes(&mut previous);
+    zeroize_bytes(&mut okm[out_len..]);
+    okm.truncate(out_len);
+    Ok(okm)
+}
+
+/// Password-based key derivation function 2 (RFC 8018), with HMAC over
+/// the given hash as the pseudorandom function. Each output block is
+/// T(i) = U(1) ^ ... ^ U(c), where U(1) = HMAC(P, S || i) and
+/// U(j) = HMAC(P, U(j - 1))
+/// 
+/// ### Arguments
+/// 
+/// * `password` - Password to derive the key from
+/// * `salt` - Salt, unique per password
+/// * `iterations` - Iteration count, the work factor
+/// * `out_len` - Length of the derived key in bytes
+/// * `algorithm` - Hash algorithm to build the HMAC over
+pub fn pbkdf2(
+    password: &[u8],
+    salt: &[u8],
+    iterations: u32,
+    out_len: usize,
+    algorithm: HashAlgorithm
+) -> Result<Vec<u8>, KdfError>
+{
+    if iterations == 0 {
+        return Err(KdfError::NoIterations);
+    }
+
+    let mut derived = Vec::with_capacity(out_len + algorithm.output_size());
+    let mut counter: u32 = 1;
+
+    while derived.len() < out_len {
+        let mut input = salt.to_vec();
+        input.extend_from_slice(&counter.to_be_bytes());
+
+        let mut u = hmac(password, &input, algorithm.clone());
+        let mut block = u.clone();
+
+        for _ in 1..iterations {
+            let next = hmac(password, &u, algorithm.clone());
+            zeroize_bytes(&mut u);
+            u = next;
+
+            for (b, x) in block.iter_mut().zip(u.iter()) {
+                *b ^= x;
+            }
+        }
+
+        derived.extend_from_slice(&block);
+
+        zeroize_bytes(&mut u);
+        zeroize_bytes(&mut block);
+        counter = match counter.checked_add(1) {
+            Some(c) => c,
+            None => return Err(KdfError::OutputTooLong)
+        };
+    }
+
+    zeroize_bytes(&mut derived[out_len..]);
+    derived.truncate(out_len);
+    Ok(derived)
+}
+
+/// scrypt memory-hard key derivation (RFC 7914), with PBKDF2-HMAC-SHA256
+/// around ROMix. Memory use is capped at `SCRYPT_DEFAULT_MAX_MEMORY`
+/// 
+/// ### Arguments
+/// 
+/// * `password` - Password to derive the key from
+/// * `salt` - Salt, unique per password
+/// * `n` - CPU and memory cost, a power of two above 1
+/// * `r` - Block size factor
+/// * `p` - Parallelisation factor
+/// * `out_len` - Length of the derived key in bytes
+#[cfg(feature = "hashes-sha2")]
+pub fn scrypt(password: &[u8], salt: &[u8], n: u32, r: u32, p: u32, out_len: usize) -> Result<Vec<u8>, KdfError> {
+    scrypt_with_limit(password, salt, n, r, p, out_len, SCRYPT_DEFAULT_MAX_MEMORY)
+}
+
+/// scrypt with a caller chosen memory limit. Parameters needing more
+/// than `max_memory` bytes fail before anything is allocated
+/// 
+/// ### Arguments
+/// 
+/// * `password` - Password to derive the key from
+/// * `salt` - Salt, unique per password
+/// * `n` - CPU and memory cost, a power of two above 1
+/// * `r` - Block size factor
+/// * `p` - Parallelisation factor
+/// * `out_len` - Length of the derived key in bytes
+/// * `max_memory` - Bytes the derivation may allocate
+#[cfg(feature = "hashes-sha2")]
+pub fn scrypt_with_limit(
+    password: &[u8],
+    salt: &[u8],
+    n: u32,
+    r: u32,
+    p: u32,
+    out_len: usize,
+    max_memory: usize
+) -> Result<Vec<u8>, KdfError>
+{
+    let (n, r, p) = (n as u64, r as u64, p as u64);
+
+    // RFC 7914 section 2: N < 2^(128 r / 8) and p r < 2^30
+    if n < 2 || !n.is_power_of_two() || r == 0 || p == 0 || r * p >= 1 << 30 || (r < 4 && n >> (16 * r) != 0) {
+        return Err(KdfError::InvalidCost);
+    }
+
+    // V, the p blocks of B and the scratch block of BlockMix
+    let block_len = 2 * r * SALSA_BLOCK_SIZE as u64;
+    let memory = block_len.checked_mul(n + p + 1).ok_or(KdfError::MemoryLimit)?;
+
+    if memory > max_memory as u64 {
+        return Err(KdfError::MemoryLimit);
+    }
+
+    let (n, r, block_len) = (n as usize, r as usize, block_len as usize);
+    let mut b = pbkdf2(password, salt, 1, block_len * p as usize, HashAlgorithm::Sha256)?;
+
+    for block in b.chunks_mut(block_len) {
+        ro_mix(block, n, r);
+    }
+
+    let derived = pbkdf2(password, &b, 1, out_len, HashAlgorithm::Sha256);
+    zeroize_bytes(&mut b);
+
+    derived
+}
+
+/// ANSI X9.63 key derivation function, as used by SEC 1 ECIES. Each
+/// block is Hash(Z || counter || SharedInfo) with a four byte big-endian
+/// counter starting at 1
+/// 
+/// ### Arguments
+/// 
+/// * `shared_secret` - Shared secret Z
+/// * `shared_info` - Information shared by both parties, possibly empty
+/// * `out_len` - Length of the derived key in bytes
+/// * `algorithm` - Hash algorithm to derive with
+pub fn x963_kdf(shared_secret: &[u8], shared_info: &[u8], out_len: usize, algorithm: HashAlgorithm) -> Result<Vec<u8>, KdfError> {
+    counter_kdf(out_len, algorithm, |counter| {
+        let mut input = shared_secret.to_vec();
+        input.extend_from_slice(&counter);
+        input.extend_from_slice(shared_info);
+        input
+    })
+}
+
+/// NIST SP 800-56A one-step key derivation, the Concatenation KDF. Each
+/// block is Hash(counter || Z || OtherInfo) with a four byte big-endian
+/// counter starting at 1
+/// 
+/// ### Arguments
+/// 
+/// * `z` - Shared secret
+/// * `other_info` - Fixed info binding the key to the parties and its use
+/// * `out_len` - Length of the derived key in bytes
+/// * `algorithm` - Hash algorithm to derive with
+pub fn concat_kdf(z: &[u8], other_info: &[u8], out_len: usize, algorithm: HashAlgorithm) -> Result<Vec<u8>, KdfError> {
+    counter_kdf(out_len, algorithm, |counter| {
+        let mut input = counter.to_vec();
+        input.extend_from_slice(z);
+        input.extend_from_slice(other_info);
+        input
+    })
+}
+
+/// Mask generation function MGF1 (RFC 8017 B.2.1), used by the PSS
+/// and OAEP paddings. The mask is the concatenation of hashes of the
+/// seed followed by a four byte big-endian counter
+/// 
+/// ### Arguments
+/// 
+/// * `seed` - Seed the mask is generated from
+/// * `length` - Length of the mask in bytes
+/// * `algorithm` - Hash algorithm to generate with
+pub fn mgf1(seed: &[u8], length: usize, algorithm: HashAlgorithm) -> Vec<u8> {
+    let mut mask = Vec::with_capacity(length + algorithm.output_size());
+    let mut counter: u32 = 0;
+
+    while mask.len() < length {
+        let mut input = seed.to_vec();
+        input.extend_from_slice(&counter.to_be_bytes());
+
+        mask.extend(hash_message(&input, algorithm.clone()));
+        counter += 1;
+    }
+
+    mask.truncate(length);
+    mask
+}
+
+/// Hashes the inputs built around a 32-bit counter until enough key
+/// material is produced. The counter may not wrap, which limits the
+/// output to 2^32 - 1 hash blocks
+/// 
+/// ### Arguments
+/// 
+/// * `out_len` - Length of the derived key in bytes
+/// * `algorithm` - Hash algorithm to derive with
+/// * `input` - Builds the hash input from the encoded counter
+fn counter_kdf<F>(out_len: usize, algorithm: HashAlgorithm, input: F) -> Result<Vec<u8>, KdfError>
+    where F: Fn([u8; 4]) -> Vec<u8>
+{
+    let block_size = algorithm.output_size();
+
+    if out_len as u64 > block_size as u64 * u32::max_value() as u64 {
+        return Err(KdfError::OutputTooLong);
+    }
+
+    let mut derived = Vec::with_capacity(out_len + block_size);
+    let mut counter: u32 = 1;
+
+    while derived.len() < out_len {
+        let mut block_input = input(counter.to_be_bytes());
+        derived.extend(hash_message(&block_input, algorithm.clone()));
+
+        zeroize_bytes(&mut block_input);
+        counter = counter.wrapping_add(1);
+    }
+
+    zeroize_bytes(&mut derived[out_len..]);
+    derived.truncate(out_len);
+    Ok(derived)
+}
+
+/// scrypt ROMix: fills N blocks by repeated BlockMix, then mixes in N
+/// of them chosen by the running state
+/// 
+/// ### Arguments
+/// 
+/// * `block` - Block of 128 r bytes, mixed in place
+/// * `n` - CPU and memory cost
+/// * `r` - Block size factor
+#[cfg(feature = "hashes-sha2")]
+fn ro_mix(block: &mut [u8], n: usize, r: usize) -> () {
+    let words = 32 * r;
+    let mut x: Vec<u32> = block.chunks(4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .collect();
+    let mut v = vec![0u32; words * n];
+    let mut scratch = vec![0u32; words];
+
+    for i in 0..n {
+        v[i * words..(i + 1) * words].copy_from_slice(&x);
+        block_mix(&mut x, &mut scratch, r);
+    }
+
+    for _ in 0..n {
+        // Integerify, the first word of the last Salsa block, reduced
+        // mod N by masking since N is a power of two
+        let j = x[words - 16] as usize & (n - 1);
+
+        for (word, value) in x.iter_mut().zip(v[j * words..(j + 1) * words].iter()) {
+            *word ^= value;
+        }
+
+        block_mix(&mut x, &mut scratch, r);
+    }
+
+    for (bytes, word) in block.chunks_mut(4).zip(x.iter()) {
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+
+    for word in v.iter_mut().chain(x.iter_mut()).chain(scratch.iter_mut()) {
+        *word = 0;
+    }
+}
+
+/// scrypt BlockMix over Salsa20/8. The even output blocks go to the
+/// first half and the odd ones to the second
+/// 
+/// ### Arguments
+/// 
+/// * `b` - 2 r Salsa blocks as words, mixed in place
+/// * `y` - Scratch space of the same size
+/// * `r` - Block size factor
+#[cfg(feature = "hashes-sha2")]
+fn block_mix(b: &mut [u32], y: &mut [u32], r: usize) -> () {
+    let mut x = [0u32; 16];
+    x.copy_from_slice(&b[(2 * r - 1) * 16..]);
+
+    for i in 0..2 * r {
+        for (word, value) in x.iter_mut().zip(b[i * 16..(i + 1) * 16].iter()) {
+            *word ^= value;
+        }
+
+        salsa20_8(&mut x);
+
+        let position = (i / 2 + (i % 2) * r) * 16;
+        y[position..position + 16].copy_from_slice(&x);
+    }
+
+    b.copy_from_slice(y);
+}
+
+/// Salsa20/8 core, four double rounds added back onto the input
+/// 
+/// ### Arguments
+/// 
+/// * `block` - Sixteen little-endian words, replaced by the output
+#[cfg(feature = "hashes-sha2")]
+fn salsa20_8(block: &mut [u32; 16]) -> () {
+    let mut x = *block;
+
+    for _ in 0..4 {
+        for &(a, b, c, d) in &[(0, 4, 8, 12), (5, 9, 13, 1), (10, 14, 2, 6), (15, 3, 7, 11),
+                               (0, 1, 2, 3), (5, 6, 7, 4), (10, 11, 8, 9), (15, 12, 13, 14)] {
+            x[b] ^= x[a].wrapping_add(x[d]).rotate_left(7);
+            x[c] ^= x[b].wrapping_add(x[a]).rotate_left(9);
+            x[d] ^= x[c].wrapping_add(x[b]).rotate_left(13);
+            x[a] ^= x[d].wrapping_add(x[c]).rotate_left(18);
+        }
+    }
+
+    for (word, mixed) in block.iter_mut().zip(x.iter()) {
+        *word = word.wrapping_add(*mixed);
+    }
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(all(test, feature = "hashes-sha3", feature = "hashes-blake2"))]
+mod kdf_test {
+
+    use crate::hash::crypto::HashAlgorithm;
+    use crate::hash::kdf::{ hkdf, pbkdf2, mgf1, KdfError };
+    use crate::utils::encoding::hex_to_bytes;
+
+    #[test]
+    fn output_has_requested_length() {
+        for length in &[0, 1, 31, 32, 33, 100] {
+            let okm = hkdf(b"input", b"salt", b"info", *length, HashAlgorithm::Sha3_256).unwrap();
+            assert_eq!(okm.len(), *length);
+        }
+    }
+
+    #[test]
+    fn shorter_output_is_prefix_of_longer() {
+        let short = hkdf(b"input", b"salt", b"info", 20, HashAlgorithm::Blake2s).unwrap();
+        let long = hkdf(b"input", b"salt", b"info", 80, HashAlgorithm::Blake2s).unwrap();
+
+        assert_eq!(short[..], long[..20]);
+    }
+
+    #[test]
+    fn info_separates_outputs() {
+        let first = hkdf(b"input", b"", b"client", 32, HashAlgorithm::Sha3_256).unwrap();
+        let second = hkdf(b"input", b"", b"server", 32, HashAlgorithm::Sha3_256).unwrap();
+
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn rejects_overlong_output() {
+        let result = hkdf(b"input", b"salt", b"info", 255 * 32 + 1, HashAlgorithm::Sha3_256);
+        assert_eq!(result, Err(KdfError::OutputTooLong));
+    }
+
+    #[test]
+    fn pbkdf2_depends_on_every_input() {
+        let base = pbkdf2(b"password", b"salt", 4, 40, HashAlgorithm::Sha3_256).unwrap();
+
+        assert_eq!(base.len(), 40);
+        assert_eq!(base, pbkdf2(b"password", b"salt", 4, 40, HashAlgorithm::Sha3_256).unwrap());
+        assert_eq!(base[..20], pbkdf2(b"password", b"salt", 4, 20, HashAlgorithm::Sha3_256).unwrap()[..]);
+        assert_ne!(base, pbkdf2(b"passwore", b"salt", 4, 40, HashAlgorithm::Sha3_256).unwrap());
+        assert_ne!(base, pbkdf2(b"password", b"pepper", 4, 40, HashAlgorithm::Sha3_256).unwrap());
+        assert_ne!(base, pbkdf2(b"password", b"salt", 5, 40, HashAlgorithm::Sha3_256).unwrap());
+    }
+
+    #[test]
+    fn pbkdf2_rejects_zero_iterations() {
+        assert_eq!(pbkdf2(b"password", b"salt", 0, 32, HashAlgorithm::Sha3_256), Err(KdfError::NoIterations));
+    }
+
+
+    #[test]
+    fn mgf1_spans_several_blocks() {
+        let mask = mgf1(b"seed", 40, HashAlgorithm::Sha3_256);
+
+        assert_eq!(mask, hex_to_bytes("7fe2e122219eb3f5a8b31f3bab2fa73c7fe7cf915da2b28692a7630a7dd9cd990100e302da901451").unwrap());
+        assert_eq!(mgf1(b"seed", 7, HashAlgorithm::Sha3_256), mask[..7].to_vec());
+    }
+}
+
+#[cfg(all(test, feature = "hashes-sha2"))]
+mod scrypt_test {
+
+    use crate::hash::crypto::HashAlgorithm;
+    use crate::hash::kdf::{ hkdf, hkdf_extract, pbkdf2, scrypt, scrypt_with_limit, salsa20_8, KdfError };
+    use crate::utils::encoding::hex_to_bytes;
+
+    fn hex(value: &str) -> Vec<u8> {
+        hex_to_bytes(value).unwrap()
+    }
+
+    #[test]
+    fn salsa20_8_matches_rfc_7914() {
+        let input = hex(concat!(
+            "7e879a214f3ec9867ca940e641718f26baee555b8c61c1b50df846116dcd3b1d",
+            "ee24f319df9b3d8514121e4b5ac5aa3276021d2909c74829edebc68db8b8c25e"
+        ));
+        let mut block = [0u32; 16];
+
+        for (word, bytes) in block.iter_mut().zip(input.chunks(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+
+        salsa20_8(&mut block);
+        let output: Vec<u8> = block.iter().flat_map(|word| word.to_le_bytes().to_vec()).collect();
+
+        assert_eq!(output, hex(concat!(
+            "a41f859c6608cc993b81cacb020cef05044b2181a2fd337dfd7b1c6396682f29",
+            "b4393168e3c9e6bcfe6bc5b7a06d96bae424cc102c91745c24ad673dc7618f81"
+        )));
+    }
+
+    #[test]
+    fn hkdf_sha256_matches_rfc_5869() {
+        // Test case 1, basic
+        let ikm = [0x0b; 22];
+        let salt: Vec<u8> = (0x00..0x0d).collect();
+        let info: Vec<u8> = (0xf0..0xfa).collect();
+
+        assert_eq!(hkdf_extract(&ikm, &salt, HashAlgorithm::Sha256), hex("077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5"));
+        assert_eq!(hkdf(&ikm, &salt, &info, 42, HashAlgorithm::Sha256).unwrap(), hex(concat!(
+            "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf",
+            "34007208d5b887185865"
+        )));
+
+        // Test case 2, longer inputs and outputs
+        let ikm: Vec<u8> = (0x00..0x50).collect();
+        let salt: Vec<u8> = (0x60..0xb0).collect();
+        let info: Vec<u8> = (0xb0..=0xff).collect();
+
+        assert_eq!(hkdf(&ikm, &salt, &info, 82, HashAlgorithm::Sha256).unwrap(), hex(concat!(
+            "b11e398dc80327a1c8e7f78c596a49344f012eda2d4efad8a050cc4c19afa97c",
+            "59045a99cac7827271cb41c65e590e09da3275600c2f09b8367793a9aca3db71",
+            "cc30c58179ec3e87c14c01d5c1f3434f1d87"
+        )));
+
+        // Test case 3, empty salt and info
+        assert_eq!(hkdf_extract(&[0x0b; 22], &[], HashAlgorithm::Sha256), hex("19ef24a32c717b167f33a91d6f648bdf96596776afdb6377ac434c1c293ccb04"));
+        assert_eq!(hkdf(&[0x0b; 22], &[], &[], 42, HashAlgorithm::Sha256).unwrap(), hex(concat!(
+            "8da4e775a563c18f715f802a063c5a31b8a11f5c5ee1879ec3454e5f3c738d2d",
+            "9d201395faa4b61a96c8"
+        )));
+    }
+
+    #[test]
+    fn pbkdf2_sha256_matches_rfc_6070_inputs() {
+        // The RFC 6070 cases, which are for SHA-1, run under SHA-256
+        let cases: [(&[u8], &[u8], u32, &str); 5] = [
+            (b"password", b"salt", 1, "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"),
+            (b"password", b"salt", 2, "ae4d0c95af6b46d32d0adff928f06dd02a303f8ef3c251dfd6e2d85a95474c43"),
+            (b"password", b"salt", 4096, "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"),
+            (b"passwordPASSWORDpassword", b"saltSALTsaltSALTsaltSALTsaltSALTsalt", 4096, "348c89dbcbd32b2f32d814b8116e84cf2b17347ebc1800181c4e2a1fb8dd53e1c635518c7dac47e9"),
+            (b"pass\0word", b"sa\0lt", 4096, "89b69d0516f829893c696226650a8687")
+        ];
+
+        for &(password, salt, iterations, expected) in cases.iter() {
+            let expected = hex(expected);
+            assert_eq!(pbkdf2(password, salt, iterations, expected.len(), HashAlgorithm::Sha256).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn pbkdf2_sha256_matches_rfc_7914() {
+        let derived = pbkdf2(b"passwd", b"salt", 1, 64, HashAlgorithm::Sha256).unwrap();
+
+        assert_eq!(derived, hex(concat!(
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc",
+            "49ca9cccf179b645991664b39d77ef317c71b845b1e30bd509112041d3a19783"
+        )));
+    }
+
+    #[test]
+    fn scrypt_matches_rfc_7914() {
+        assert_eq!(scrypt(b"", b"", 16, 1, 1, 64).unwrap(), hex(concat!(
+            "77d6576238657b203b19ca42c18a0497f16b4844e3074ae8dfdffa3fede21442",
+            "fcd0069ded0948f8326a753a0fc81f17e8d3e0fb2e0d3628cf35e20c38d18906"
+        )));
+        assert_eq!(scrypt(b"password", b"NaCl", 1024, 8, 16, 64).unwrap(), hex(concat!(
+            "fdbabe1c9d3472007856e7190d01e9fe7c6ad7cbc8237830e77376634b373162",
+            "2eaf30d92e22a3886ff109279d9830dac727afb94a83ee6d8360cbdfa2cc0640"
+        )));
+    }
+
+    #[test]
+    #[ignore]
+    fn scrypt_matches_rfc_7914_slow() {
+        assert_eq!(scrypt(b"pleaseletmein", b"SodiumChloride", 16384, 8, 1, 64).unwrap(), hex(concat!(
+            "7023bdcb3afd7348461c06cd81fd38ebfda8fbba904f8e3ea9b543f6545da1f2",
+            "d5432955613f0fcf62d49705242a9af9e61e85dc0d651e40dfcf017b45575887"
+        )));
+    }
+
+    #[test]
+    fn scrypt_parameters_are_checked() {
+        assert_eq!(scrypt(b"p", b"s", 0, 1, 1, 32), Err(KdfError::InvalidCost));
+        assert_eq!(scrypt(b"p", b"s", 1, 1, 1, 32), Err(KdfError::InvalidCost));
+        assert_eq!(scrypt(b"p", b"s", 1000, 1, 1, 32), Err(KdfError::InvalidCost));
+        assert_eq!(scrypt(b"p", b"s", 16, 0, 1, 32), Err(KdfError::InvalidCost));
+        assert_eq!(scrypt(b"p", b"s", 16, 1, 0, 32), Err(KdfError::InvalidCost));
+        assert_eq!(scrypt(b"p", b"s", 1 << 16, 1, 1, 32), Err(KdfError::InvalidCost));
+
+        // 2^20 blocks of 1 KiB would be a gigabyte
+        assert_eq!(scrypt(b"p", b"s", 1 << 20, 8, 1, 32), Err(KdfError::MemoryLimit));
+        assert_eq!(scrypt_with_limit(b"p", b"s", 1024, 8, 1, 32, 1024 * 1024), Err(KdfError::MemoryLimit));
+        assert!(scrypt_with_limit(b"p", b"s", 1024, 8, 1, 32, 2 * 1024 * 1024).is_ok());
+    }
+}
+
+#[cfg(all(test, feature = "hashes-sha2", feature = "hashes-sha3"))]
+mod counter_kdf_test {
+
+    use crate::hash::crypto::HashAlgorithm;
+    use crate::hash::kdf::{ x963_kdf, concat_kdf, hkdf, KeyDerivation, KdfError };
+    use crate::utils::encoding::hex_to_bytes;
+
+    fn hex(value: &str) -> Vec<u8> {
+        hex_to_bytes(value).unwrap()
+    }
+
+    #[test]
+    fn x963_matches_nist_vectors() {
+        let z = hex("96c05619d56c328ab95fe84b18264b08725b85e33fd34f08");
+        assert_eq!(x963_kdf(&z, &[], 16, HashAlgorithm::Sha256).unwrap(), hex("443024c3dae66b95e6f5670601558f71"));
+
+        let z = hex("22518b10e70f2a3f243810ae3254139efbee04aa57c7af7d");
+        let shared_info = hex("75eef81aa3041e33b80971203d2c0c52");
+
+        assert_eq!(x963_kdf(&z, &shared_info, 128, HashAlgorithm::Sha256).unwrap(), hex(concat!(
+            "c498af77161cc59f2962b9a713e2b215152d139766ce34a776df11866a69bf2e",
+            "52a13d9c7c6fc878c50c5ea0bc7b00e0da2447cfd874f6cf92f30d0097111485",
+            "500c90c3af8b487872d04685d14c8d1dc8d7fa08beb0ce0ababc11f0bd496269",
+            "142d43525a78e5bc79a17f59676a5706dc54d54d4d1f0bd7e386128ec26afc21"
+        )));
+    }
+
+    #[test]
+    fn concat_kdf_matches_known_vector() {
+        let z = hex("52169af5c485dcc2321eb8d26d5efa21fb9b93c98e38412ee2484cf14f0d0d23");
+        let other_info = hex("a1b2c3d4e53728157e634612c12d6d5223e204aeea4341565369647bd184bcd246f72971f292badaa2fe4124612cba");
+
+        assert_eq!(concat_kdf(&z, &other_info, 16, HashAlgorithm::Sha256).unwrap(), hex("1c3bc9e7c4547c5191c0d478cccaed55"));
+    }
+
+    #[test]
+    fn counter_kdfs_are_consistent_under_sha3() {
+        let long = x963_kdf(b"secret", b"info", 100, HashAlgorithm::Sha3_512).unwrap();
+        assert_eq!(long.len(), 100);
+        assert_eq!(x963_kdf(b"secret", b"info", 64, HashAlgorithm::Sha3_512).unwrap(), long[..64].to_vec());
+
+        // Same blocks, different framing
+        let concat = concat_kdf(b"secret", b"info", 100, HashAlgorithm::Sha3_512).unwrap();
+        assert_eq!(concat_kdf(b"secret", b"info", 3, HashAlgorithm::Sha3_512).unwrap(), concat[..3].to_vec());
+        assert_ne!(concat, long);
+    }
+
+    #[test]
+    fn counter_kdfs_limit_the_output() {
+        assert_eq!(x963_kdf(b"secret", b"", usize::max_value(), HashAlgorithm::Sha256), Err(KdfError::OutputTooLong));
+        assert_eq!(concat_kdf(b"secret", b"", usize::max_value(), HashAlgorithm::Sha256), Err(KdfError::OutputTooLong));
+        assert_eq!(x963_kdf(b"secret", b"", 0, HashAlgorithm::Sha256).unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn key_derivation_selects_the_kdf() {
+        let algorithm = HashAlgorithm::Sha256;
+
+        assert_eq!(KeyDerivation::default().derive(b"z", b"salt", b"info", 32, algorithm.clone()), hkdf(b"z", b"salt", b"info", 32, algorithm.clone()));
+        assert_eq!(KeyDerivation::X963.derive(b"z", b"salt", b"info", 32, algorithm.clone()), x963_kdf(b"z", b"saltinfo", 32, algorithm.clone()));
+        assert_eq!(KeyDerivation::Concat.derive(b"z", b"salt", b"info", 32, algorithm.clone()), concat_kdf(b"z", b"saltinfo", 32, algorithm));
+    }
+}
\ No newline at end of file
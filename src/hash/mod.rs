@@ -1 +1,4 @@
-pub mod crypto;
\ No newline at end of file
+pub mod crypto;
+pub mod hmac;
+pub mod drbg;
+pub mod kdf;
@@ -0,0 +1,539 @@
+//! Hierarchical deterministic keys in the style of BIP32, so that a
+//! whole tree of ECC keypairs can be rebuilt from one master seed.
+//!
+//! The master node takes its private key and chain code from an HMAC of
+//! the seed, keyed by the name of the curve. Each child is found from an
+//! HMAC under its parent's chain code: the left half of the output IL is
+//! added to the parent key, k_i = IL + k mod n, and the right half is the
+//! child's chain code. Hardened children hash the parent's private key,
+//! while normal children hash its public key, so for normal children the
+//! public half of a node derives the same public keys as K_i = IL G + K
+//! without knowing any private key.
+//!
+//! BIP32 is defined over HMAC-SHA512, which the crate does not have, so
+//! every HMAC here is over SHA3-512 and the keys do not match those of
+//! BIP32 wallets. The 64 byte output covers a 32 byte key and a 32 byte
+//! chain code, which limits trees to the 256-bit short Weierstrass
+//! curves. Montgomery curves have no point addition to derive public
+//! keys with
+
+use std::fmt;
+use std::ops::Rem;
+use num_bigint::BigUint;
+use num_traits::Zero;
+
+use crate::hash::hmac::hmac;
+use crate::hash::crypto::HashAlgorithm;
+use crate::utils::{ comb_method, jacobian_coords };
+use crate::utils::rng::CryptoRngCore;
+use crate::utils::zeroize::{ zeroize_bytes, zeroize_biguint };
+use crate::utils::encoding::{ EndianOrdering, biguint_to_fixed_bytes };
+use crate::utils::ecc::{ ECPKeypair, ECPPublicKey, EcError, encode_point };
+use crate::utils::ecc_curves::{ ECPCurveShape, ECPGroup, ECPSupportedCurves };
+
+
+/*---- CONSTANTS ----*/
+
+/// Length in bytes of a chain code
+pub const CHAIN_CODE_LENGTH: usize = 32;
+
+/// Child numbers from this one up are hardened
+pub const HARDENED_OFFSET: u32 = 1 << 31;
+
+/// Bounds in bytes on the length of a master seed
+pub const MIN_SEED_LENGTH: usize = 16;
+pub const MAX_SEED_LENGTH: usize = 64;
+
+/// Length in bytes of a private key, the part of the HMAC output before the chain code
+const KEY_LENGTH: usize = 32;
+
+/// Hash algorithm every HMAC is taken over
+const HD_HASH_ALGORITHM: HashAlgorithm = HashAlgorithm::Sha3_512;
+
+/// Follows the curve name in the HMAC key of the master node
+const MASTER_KEY_SUFFIX: &'static str = " seed";
+
+
+/*---- ENUMS ----*/
+
+/// Errors raised while building or walking a key tree
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum HdError {
+    UnsupportedCurve,       // curve is not a 256-bit short Weierstrass curve
+    InvalidSeedLength,      // seed is shorter or longer than the bounds allow
+    IndexOutOfRange,        // index does not fit in 31 bits
+    HardenedFromPublic,     // hardened children need the parent's private key
+    InvalidChild(u32),      // child with this number is invalid, the next index should be used
+    TooDeep,                // tree is limited to 255 levels below the master
+    InvalidPath,            // derivation path could not be parsed
+    Ec(EcError)             // a point could not be computed or encoded
+}
+
+impl fmt::Display for HdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HdError::UnsupportedCurve => write!(f, "Key trees need a 256-bit short Weierstrass curve"),
+            HdError::InvalidSeedLength => write!(f, "Seed must be between {} and {} bytes", MIN_SEED_LENGTH, MAX_SEED_LENGTH),
+            HdError::IndexOutOfRange => write!(f, "Child index must be below 2^31"),
+            HdError::HardenedFromPublic => write!(f, "Hardened children cannot be derived from a public key"),
+            HdError::InvalidChild(number) => write!(f, "Child {} is invalid, use the next index", number),
+            HdError::TooDeep => write!(f, "Key tree is limited to 255 levels"),
+            HdError::InvalidPath => write!(f, "Derivation path is malformed"),
+            HdError::Ec(e) => write!(f, "ECC error: {}", e)
+        }
+    }
+}
+
+impl From<EcError> for HdError {
+    fn from(error: EcError) -> Self {
+        HdError::Ec(error)
+    }
+}
+
+
+/*---- STRUCTS ----*/
+
+/// Root of a key tree, made from a seed
+pub struct Master {
+    node: Node      // master node, at depth 0
+}
+
+/// Node of a key tree holding its private key, able to derive both
+/// hardened and normal children
+#[derive(Clone)]
+pub struct Node {
+    keypair: ECPKeypair,                    // private key k and public key K
+    chain_code: [u8; CHAIN_CODE_LENGTH],    // mixed into the derivation of every child
+    depth: u8,                              // number of derivations from the master
+    child_number: u32                       // index of the node, with the top bit set when hardened
+}
+
+/// Public half of a node, able to derive the public keys of its normal
+/// children only
+#[derive(Debug, Clone, PartialEq)]
+pub struct PublicNode {
+    public_key: ECPPublicKey,               // public key K
+    chain_code: [u8; CHAIN_CODE_LENGTH],    // mixed into the derivation of every child
+    depth: u8,                              // number of derivations from the master
+    child_number: u32                       // index of the node, always below 2^31 for children
+}
+
+
+/*---- IMPLEMENTATIONS ----*/
+
+impl Master {
+
+    /// Builds the master node of a tree from a seed. An HMAC of the seed,
+    /// keyed by the curve name, gives the private key and chain code. The
+    /// rare output that is not a valid key is hashed again until one is
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `seed` - Secret seed of the tree, between 16 and 64 bytes
+    /// * `curve` - Curve every key of the tree lies on
+    /// * `rng` - Random number generator
+    pub fn from_seed(seed: &[u8], curve: ECPSupportedCurves, rng: &mut impl CryptoRngCore) -> Result<Master, HdError> {
+        if seed.len() < MIN_SEED_LENGTH || seed.len() > MAX_SEED_LENGTH {
+            return Err(HdError::InvalidSeedLength);
+        }
+
+        let group = ECPGroup::new(curve.clone());
+
+        if group.get_curve_shape() != ECPCurveShape::ShortWeierstrass || (group_order(&group).bits() + 7) / 8 != KEY_LENGTH {
+            return Err(HdError::UnsupportedCurve);
+        }
+
+        let hmac_key = format!("{:?}{}", curve, MASTER_KEY_SUFFIX).into_bytes();
+        let mut output = hmac(&hmac_key, seed, HD_HASH_ALGORITHM);
+        let mut key = BigUint::from_bytes_be(&output[..KEY_LENGTH]);
+
+        while key.is_zero() || key >= group_order(&group) {
+            let next = hmac(&hmac_key, &output, HD_HASH_ALGORITHM);
+            zeroize_bytes(&mut output);
+
+            output = next;
+            key = BigUint::from_bytes_be(&output[..KEY_LENGTH]);
+        }
+
+        let node = Node::from_parts(group, key, &output[KEY_LENGTH..], 0, 0, rng);
+        zeroize_bytes(&mut output);
+
+        Ok(Master { node: node })
+    }
+
+    /// The master node
+    pub fn node(&self) -> &Node {
+        &self.node
+    }
+
+    /// Derives a child of the master node
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `index` - Index of the child, below 2^31
+    /// * `hardened` - Whether to derive from the private key
+    /// * `rng` - Random number generator
+    pub fn derive_child(&self, index: u32, hardened: bool, rng: &mut impl CryptoRngCore) -> Result<Node, HdError> {
+        self.node.derive_child(index, hardened, rng)
+    }
+
+    /// Derives the node at a path such as `m/0H/1/2H`. Hardened steps are
+    /// marked with `H`, `h` or `'`, and `m` alone is the master node
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `path` - Derivation path from the master
+    /// * `rng` - Random number generator
+    pub fn derive_path(&self, path: &str, rng: &mut impl CryptoRngCore) -> Result<Node, HdError> {
+        let mut node = self.node.clone();
+
+        for (index, hardened) in parse_path(path)? {
+            node = node.derive_child(index, hardened, rng)?;
+        }
+
+        Ok(node)
+    }
+}
+
+impl Node {
+
+    /// Derives a child node. Hardened children hash the private key, so
+    /// they cannot be found from the public half of this node
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `index` - Index of the child, below 2^31
+    /// * `hardened` - Whether to derive from the private key
+    /// * `rng` - Random number generator
+    pub fn derive_child(&self, index: u32, hardened: bool, rng: &mut impl CryptoRngCore) -> Result<Node, HdError> {
+        if index >= HARDENED_OFFSET {
+            return Err(HdError::IndexOutOfRange);
+        }
+
+        let depth = child_depth(self.depth)?;
+        let child_number = if hardened { index | HARDENED_OFFSET } else { index };
+        let group = &self.keypair.group;
+
+        let mut data = Vec::with_capacity(1 + KEY_LENGTH + 4);
+
+        if hardened {
+            let mut key = match biguint_to_fixed_bytes(&self.keypair.d, KEY_LENGTH, EndianOrdering::Big) {
+                Some(key) => key,
+                None => return Err(HdError::Ec(EcError::InvalidEncoding))
+            };
+
+            data.push(0);
+            data.extend_from_slice(&key);
+            zeroize_bytes(&mut key);
+        } else {
+            data.extend(encode_point(group, &self.keypair.q, true)?);
+        }
+
+        data.extend_from_slice(&child_number.to_be_bytes());
+
+        let mut output = hmac(&self.chain_code, &data, HD_HASH_ALGORITHM);
+        zeroize_bytes(&mut data);
+
+        let mut tweak = BigUint::from_bytes_be(&output[..KEY_LENGTH]);
+        let n = group_order(group);
+
+        if tweak >= n {
+            zeroize_bytes(&mut output);
+            return Err(HdError::InvalidChild(child_number));
+        }
+
+        // k_i = IL + k mod n
+        let key = (&tweak + &self.keypair.d).rem(&n);
+        zeroize_biguint(&mut tweak);
+
+        if key.is_zero() {
+            zeroize_bytes(&mut output);
+            return Err(HdError::InvalidChild(child_number));
+        }
+
+        let child = Node::from_parts(group.clone(), key, &output[KEY_LENGTH..], depth, child_number, rng);
+        zeroize_bytes(&mut output);
+
+        Ok(child)
+    }
+
+    /// Public half of the node, for handing out to derive normal
+    /// children's public keys
+    pub fn to_public(&self) -> Result<PublicNode, HdError> {
+        Ok(PublicNode {
+            public_key: self.keypair.public_key()?,
+            chain_code: self.chain_code,
+            depth: self.depth,
+            child_number: self.child_number
+        })
+    }
+
+    /// Keypair of the node
+    pub fn keypair(&self) -> &ECPKeypair {
+        &self.keypair
+    }
+
+    /// Chain code of the node
+    pub fn chain_code(&self) -> &[u8] {
+        &self.chain_code
+    }
+
+    /// Number of derivations from the master
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
+    /// Index of the node, with the top bit set when it is hardened
+    pub fn child_number(&self) -> u32 {
+        self.child_number
+    }
+
+    /// Builds a node from its private key, computing the public key
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `group` - Curve group of the tree
+    /// * `key` - Private key k
+    /// * `chain_code` - Chain code of the node
+    /// * `depth` - Number of derivations from the master
+    /// * `child_number` - Index of the node, with the top bit set when hardened
+    /// * `rng` - Random number generator
+    fn from_parts(
+        group: ECPGroup,
+        key: BigUint,
+        chain_code: &[u8],
+        depth: u8,
+        child_number: u32,
+        rng: &mut impl CryptoRngCore
+    ) -> Node
+    {
+        let q = comb_method::multiply(&group, &key, &group.g, rng);
+        let mut code = [0; CHAIN_CODE_LENGTH];
+        code.copy_from_slice(chain_code);
+
+        Node {
+            keypair: ECPKeypair { group: group, d: key, q: q },
+            chain_code: code,
+            depth: depth,
+            child_number: child_number
+        }
+    }
+}
+
+impl PublicNode {
+
+    /// Derives the public half of a normal child, K_i = IL G + K. Gives
+    /// the same key as deriving the child from the private node and
+    /// taking its public half
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `index` - Index of the child, below 2^31
+    /// * `rng` - Random number generator
+    pub fn derive_child(&self, index: u32, rng: &mut impl CryptoRngCore) -> Result<PublicNode, HdError> {
+        if index >= HARDENED_OFFSET {
+            return Err(HdError::HardenedFromPublic);
+        }
+
+        let depth = child_depth(self.depth)?;
+        let keypair = ECPKeypair::new(self.public_key.curve().clone());
+        let parent = self.public_key.point()?;
+
+        let mut data = encode_point(&keypair.group, &parent, true)?;
+        data.extend_from_slice(&index.to_be_bytes());
+
+        let output = hmac(&self.chain_code, &data, HD_HASH_ALGORITHM);
+        let tweak = BigUint::from_bytes_be(&output[..KEY_LENGTH]);
+
+        if tweak >= group_order(&keypair.group) {
+            return Err(HdError::InvalidChild(index));
+        }
+
+        let tweak_point = keypair.multiply_point(&keypair.group.g, &tweak, rng);
+        let point = jacobian_coords::normalize_point(&keypair.group, &keypair.add_points(&tweak_point, &parent));
+
+        if point.is_zero() {
+            return Err(HdError::InvalidChild(index));
+        }
+
+        let mut chain_code = [0; CHAIN_CODE_LENGTH];
+        chain_code.copy_from_slice(&output[KEY_LENGTH..]);
+
+        Ok(PublicNode {
+            public_key: ECPPublicKey::new(keypair.group.curve_id(), &encode_point(&keypair.group, &point, false)?)?,
+            chain_code: chain_code,
+            depth: depth,
+            child_number: index
+        })
+    }
+
+    /// Public key of the node
+    pub fn public_key(&self) -> &ECPPublicKey {
+        &self.public_key
+    }
+
+    /// Chain code of the node
+    pub fn chain_code(&self) -> &[u8] {
+        &self.chain_code
+    }
+
+    /// Number of derivations from the master
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
+    /// Index of the node
+    pub fn child_number(&self) -> u32 {
+        self.child_number
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Reads a path such as `m/0H/1/2'` into its indices and whether each
+/// step is hardened
+/// 
+/// ### Arguments
+/// 
+/// * `path` - Derivation path from the master
+fn parse_path(path: &str) -> Result<Vec<(u32, bool)>, HdError> {
+    let mut steps = path.split('/');
+
+    if steps.next() != Some("m") {
+        return Err(HdError::InvalidPath);
+    }
+
+    steps.map(|step| {
+        let (digits, hardened) = match step.chars().last() {
+            Some('H') | Some('h') | Some('\'') => (&step[..step.len() - 1], true),
+            _ => (step, false)
+        };
+
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(HdError::InvalidPath);
+        }
+
+        match digits.parse::<u32>() {
+            Ok(index) if index < HARDENED_OFFSET => Ok((index, hardened)),
+            _ => Err(HdError::IndexOutOfRange)
+        }
+    }).collect()
+}
+
+/// Depth of a child of a node at the given depth
+/// 
+/// ### Arguments
+/// 
+/// * `depth` - Depth of the parent
+fn child_depth(depth: u8) -> Result<u8, HdError> {
+    match depth.checked_add(1) {
+        Some(depth) => Ok(depth),
+        None => Err(HdError::TooDeep)
+    }
+}
+
+/// Order N of the group's generator
+/// 
+/// ### Arguments
+/// 
+/// * `group` - Curve group
+fn group_order(group: &ECPGroup) -> BigUint {
+    group.n.to_biguint().unwrap()
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod hd_test {
+
+    use rand::{ ChaChaRng, SeedableRng };
+    use crate::utils::ecc_curves::ECPSupportedCurves;
+    use crate::hd::{ Master, HdError, HARDENED_OFFSET, parse_path };
+
+    const SEED: [u8; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+
+    #[test]
+    fn public_derivation_matches_private_derivation() {
+        let mut rng = ChaChaRng::from_seed(&[0x4d, 0x32]);
+
+        for curve in vec![ECPSupportedCurves::BP256R1, ECPSupportedCurves::SECP256R1] {
+            let master = Master::from_seed(&SEED, curve, &mut rng).unwrap();
+            let parent = master.derive_child(3, true, &mut rng).unwrap();
+            let parent_public = parent.to_public().unwrap();
+
+            for index in vec![0, 1, 1000, HARDENED_OFFSET - 1] {
+                let from_private = parent.derive_child(index, false, &mut rng).unwrap().to_public().unwrap();
+                let from_public = parent_public.derive_child(index, &mut rng).unwrap();
+
+                assert_eq!(from_private, from_public);
+                assert_eq!(from_public.depth(), 2);
+            }
+        }
+    }
+
+    #[test]
+    fn trees_are_reproducible_from_the_seed() {
+        let mut rng = ChaChaRng::from_seed(&[0xb9, 0x32]);
+        let master = Master::from_seed(&SEED, ECPSupportedCurves::BP256R1, &mut rng).unwrap();
+        let again = Master::from_seed(&SEED, ECPSupportedCurves::BP256R1, &mut rng).unwrap();
+
+        let node = master.derive_path("m/0H/1/2H/2/1000000000", &mut rng).unwrap();
+        let chained = again.derive_child(0, true, &mut rng)
+            .and_then(|n| n.derive_child(1, false, &mut rng))
+            .and_then(|n| n.derive_child(2, true, &mut rng))
+            .and_then(|n| n.derive_child(2, false, &mut rng))
+            .and_then(|n| n.derive_child(1000000000, false, &mut rng))
+            .unwrap();
+
+        assert_eq!(node.keypair().d, chained.keypair().d);
+        assert_eq!(node.chain_code(), chained.chain_code());
+        assert_eq!((node.depth(), node.child_number()), (5, 1000000000));
+        assert_eq!(master.derive_path("m", &mut rng).unwrap().keypair().d, master.node().keypair().d);
+
+        // Another seed, curve, index or hardening gives another key
+        let mut other_seed = SEED;
+        other_seed[0] ^= 1;
+        let other_master = Master::from_seed(&other_seed, ECPSupportedCurves::BP256R1, &mut rng).unwrap();
+        let other_curve = Master::from_seed(&SEED, ECPSupportedCurves::SECP256R1, &mut rng).unwrap();
+
+        assert_ne!(other_master.node().keypair().d, master.node().keypair().d);
+        assert_ne!(other_curve.node().chain_code(), master.node().chain_code());
+        assert_ne!(master.derive_child(0, true, &mut rng).unwrap().keypair().d, master.derive_child(0, false, &mut rng).unwrap().keypair().d);
+        assert_ne!(master.derive_child(0, false, &mut rng).unwrap().keypair().d, master.derive_child(1, false, &mut rng).unwrap().keypair().d);
+        assert_eq!(master.derive_child(7, true, &mut rng).unwrap().child_number(), 7 | HARDENED_OFFSET);
+    }
+
+    #[test]
+    fn hardened_children_need_the_private_key() {
+        let mut rng = ChaChaRng::from_seed(&[0x48, 0x44]);
+        let master = Master::from_seed(&SEED, ECPSupportedCurves::BP256R1, &mut rng).unwrap();
+        let public = master.node().to_public().unwrap();
+
+        assert_eq!(public.derive_child(HARDENED_OFFSET, &mut rng), Err(HdError::HardenedFromPublic));
+        assert_eq!(master.derive_child(HARDENED_OFFSET, false, &mut rng).err(), Some(HdError::IndexOutOfRange));
+    }
+
+    #[test]
+    fn invalid_seeds_curves_and_paths_are_rejected() {
+        let mut rng = ChaChaRng::from_seed(&[0x0b, 0xad]);
+
+        for (seed, curve, error) in vec![
+            (&SEED[..15], ECPSupportedCurves::BP256R1, HdError::InvalidSeedLength),
+            (&[0; 65][..], ECPSupportedCurves::BP256R1, HdError::InvalidSeedLength),
+            (&SEED[..], ECPSupportedCurves::BP384R1, HdError::UnsupportedCurve),
+            (&SEED[..], ECPSupportedCurves::Curve25519, HdError::UnsupportedCurve)
+        ] {
+            assert_eq!(Master::from_seed(seed, curve, &mut rng).err(), Some(error));
+        }
+
+        assert_eq!(parse_path("m/0'/1h/2H/3").unwrap(), vec![(0, true), (1, true), (2, true), (3, false)]);
+
+        for path in vec!["", "0/1", "m/", "m/x", "m/+1", "m/1H'", "n/1"] {
+            assert_eq!(parse_path(path), Err(HdError::InvalidPath));
+        }
+
+        assert_eq!(parse_path("m/2147483648"), Err(HdError::IndexOutOfRange));
+    }
+}
\ No newline at end of file
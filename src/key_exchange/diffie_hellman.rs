@@ -1,10 +1,82 @@
-use rand::OsRng;
+use std::fmt;
+use std::mem;
 use num_bigint::BigUint;
 use crate::utils::primes;
+use crate::utils::rng::CryptoRngCore;
+use crate::utils::zeroize::zeroize_biguint;
+use crate::utils::primes::PrimeError;
+use crate::utils::entropy::{ self, EntropyError };
+use crate::utils::encoding::{ biguint_to_fixed_bytes, EndianOrdering };
+use crate::hash::kdf::KdfError;
+#[cfg(feature = "hashes-sha3")]
+use crate::hash::kdf::hkdf;
+#[cfg(feature = "hashes-sha3")]
+use crate::hash::crypto::HashAlgorithm;
+#[cfg(feature = "hashes-sha3")]
+use crate::utils::zeroize::zeroize_bytes;
+#[cfg(feature = "keys")]
+use crate::keys::{ self, ExportableKey, KeyAlgorithm, KeyError };
 use num_bigint::ToBigUint;
 use std::ops::{ Rem, Shr };
 use num_traits::{ One, Zero };
 
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize, Deserializer };
+#[cfg(feature = "serde")]
+use serde::de::Error as DeserializeError;
+#[cfg(feature = "serde")]
+use crate::utils::serde_hex;
+
+/// Hash underneath HKDF when deriving keys from the shared secret
+#[cfg(feature = "hashes-sha3")]
+const KDF_HASH_ALGORITHM: HashAlgorithm = HashAlgorithm::Sha3_256;
+
+/// Errors raised while setting up or running a Diffie-Hellman exchange
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum DhError {
+    InvalidModulus,                 // modulus P is too small to hold any valid value
+    InvalidPeerValue,               // peer value is outside of 2 <= GY <= P - 2
+    BlindingFailed,                 // no usable blinding value could be generated
+    KeyCleared,                     // private value has been wiped
+    NoSharedKey,                    // no shared key has been generated to derive from
+    KeyDerivation(KdfError),        // KDF applied to the shared key failed
+    Entropy(EntropyError),          // the generator failed its health tests
+    PrimeGeneration(PrimeError)     // generating a group parameter failed
+}
+
+impl fmt::Display for DhError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DhError::InvalidModulus => write!(f, "Modulus P is too small"),
+            DhError::InvalidPeerValue => write!(f, "Peer value needs to be between 2 and P - 2"),
+            DhError::BlindingFailed => write!(f, "Could not generate blinding values"),
+            DhError::KeyCleared => write!(f, "Private value has been cleared"),
+            DhError::NoSharedKey => write!(f, "No shared key has been generated"),
+            DhError::KeyDerivation(e) => write!(f, "Key derivation failed: {}", e),
+            DhError::Entropy(e) => write!(f, "RNG unfit for key generation: {}", e),
+            DhError::PrimeGeneration(e) => write!(f, "Parameter generation failed: {}", e)
+        }
+    }
+}
+
+impl From<PrimeError> for DhError {
+    fn from(error: PrimeError) -> Self {
+        DhError::PrimeGeneration(error)
+    }
+}
+
+impl From<KdfError> for DhError {
+    fn from(error: KdfError) -> Self {
+        DhError::KeyDerivation(error)
+    }
+}
+
+impl From<EntropyError> for DhError {
+    fn from(error: EntropyError) -> Self {
+        DhError::Entropy(error)
+    }
+}
+
 /// Diffie Hellman 
 pub struct DiffieHellman {
     pub p: BigUint,         // prime modulus
@@ -18,6 +90,78 @@ pub struct DiffieHellman {
     pub shared_key: BigUint // key = GY^X mod P
 }
 
+/// Public values sent to a peer so they can complete the exchange
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DhPublicParams {
+    #[cfg_attr(feature = "serde", serde(with = "serde_hex::biguint"))]
+    p: BigUint,             // prime modulus
+    #[cfg_attr(feature = "serde", serde(with = "serde_hex::biguint"))]
+    g: BigUint,             // generator
+    #[cfg_attr(feature = "serde", serde(with = "serde_hex::biguint"))]
+    gx: BigUint             // G^X mod P
+}
+
+impl DhPublicParams {
+
+    /// Creates a set of public values, checking that the modulus is
+    /// large enough and that G and GX lie between 2 and P - 2
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `p` - Prime modulus
+    /// * `g` - Generator
+    /// * `gx` - Public value G^X mod P
+    pub fn new(p: BigUint, g: BigUint, gx: BigUint) -> Result<Self, DhError> {
+        if p <= 4.to_biguint().unwrap() {
+            return Err(DhError::InvalidModulus);
+        }
+
+        let two = 2.to_biguint().unwrap();
+
+        for value in &[&g, &gx] {
+            if *value < &two || &(*value + &two) > &p {
+                return Err(DhError::InvalidPeerValue);
+            }
+        }
+
+        Ok(DhPublicParams { p: p, g: g, gx: gx })
+    }
+
+    /// Prime modulus
+    pub fn p(&self) -> &BigUint {
+        &self.p
+    }
+
+    /// Generator
+    pub fn g(&self) -> &BigUint {
+        &self.g
+    }
+
+    /// Public value G^X mod P
+    pub fn gx(&self) -> &BigUint {
+        &self.gx
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for DhPublicParams {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Fields {
+            #[serde(with = "serde_hex::biguint")]
+            p: BigUint,
+            #[serde(with = "serde_hex::biguint")]
+            g: BigUint,
+            #[serde(with = "serde_hex::biguint")]
+            gx: BigUint
+        }
+
+        let fields = Fields::deserialize(deserializer)?;
+        DhPublicParams::new(fields.p, fields.g, fields.gx).map_err(D::Error::custom)
+    }
+}
+
 impl DiffieHellman {
 
     /// Diffie-Hellman key agreement protocol. This implementation is a 
@@ -58,23 +202,90 @@ impl DiffieHellman {
         }
     }
 
+    /// Generate self based on public values received from a peer
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `params` - Peer's public values
+    pub fn new_from_params(params: &DhPublicParams) -> Self {
+        DiffieHellman::new_from_peer(&params.p, &params.g, &params.gx)
+    }
+
+    /// Rebuilds an instance from fixed parameters and a known private
+    /// value, as used by the known-answer tests
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `p` - P modulus value
+    /// * `g` - G value
+    /// * `x` - Private X value
+    pub(crate) fn from_private_value(p: &BigUint, g: &BigUint, x: &BigUint) -> Result<Self, DhError> {
+        let mut dh = DiffieHellman::new_from_peer(p, g, &BigUint::zero());
+        dh.x = x.clone();
+        dh.gx = primes::modpow(g, x, p);
+        dh.public_params()?;
+
+        Ok(dh)
+    }
+
     /// Sets up internal values. This is a separate method 
     /// from "new" because internal method referencing is not technically 
     /// possible in constructors. As such, it should chained with the "new" 
-    /// command in practical use (see tests below for an example).
+    /// command in practical use.
+    /// 
+    /// ### Example
+    /// 
+    /// ```
+    /// use cryptofun::key_exchange::diffie_hellman::DiffieHellman;
+    /// use cryptofun::utils::rng::seeded_rng;
+    /// 
+    /// let mut rng = seeded_rng(&[0xd, 0x4]);
+    /// let mut alice = DiffieHellman::new().setup(64, &mut rng).unwrap();
+    /// let alice_params = alice.public_params().unwrap();
+    /// 
+    /// // Bob takes P and G from Alice and answers with his own GX
+    /// let mut bob = DiffieHellman::new_from_params(&alice_params).setup(64, &mut rng).unwrap();
+    /// let bob_params = bob.public_params().unwrap();
+    /// 
+    /// let alice_key = alice.generate_shared_key(&mut rng, bob_params.gx()).unwrap();
+    /// let bob_key = bob.generate_shared_key(&mut rng, alice_params.gx()).unwrap();
+    /// 
+    /// assert_eq!(alice_key, bob_key);
+    /// ```
     /// 
     /// ### Arguments
     /// 
     /// * `bitlength` - Bit length of primes
-    pub fn setup(mut self, bitlength: usize) -> DiffieHellman {
+    /// * `generator` - Random number generator
+    pub fn setup(mut self, bitlength: usize, generator: &mut impl CryptoRngCore) -> Result<DiffieHellman, DhError> {
+        entropy::check_cached(generator)?;
+        let fresh_params = self.g.is_zero() && self.p.is_zero();
+
         // check for peer value
         if self.g == BigUint::zero() {
-            self.g = primes::generate_discrete_log_prime(&bitlength);
+            self.g = primes::generate_discrete_log_prime(&bitlength, generator)?;
         }
 
         // check for peer value
         if self.p == BigUint::zero() {
-            self.p = primes::generate_discrete_log_prime(&bitlength);
+            self.p = primes::generate_discrete_log_prime(&bitlength, generator)?;
+        }
+
+        // G and P are drawn the same way, so the larger of the two is
+        // taken as P. Otherwise G lies above P half of the time and the
+        // public values are refused by `DhPublicParams::new`
+        if fresh_params {
+            while self.g == self.p {
+                self.g = primes::generate_discrete_log_prime(&bitlength, generator)?;
+            }
+
+            if self.g > self.p {
+                mem::swap(&mut self.g, &mut self.p);
+            }
+        }
+
+        if self.p <= 4.to_biguint().unwrap() {
+            return Err(DhError::InvalidModulus);
         }
 
         // check for peer value
@@ -82,26 +293,26 @@ impl DiffieHellman {
             let mut gy = BigUint::zero();
 
             while !self.check_range(&gy) {
-                gy = primes::generate_discrete_log_prime(&bitlength);
+                gy = primes::generate_discrete_log_prime(&bitlength, generator)?;
             }
             
             self.gy = gy;
+        } else if !self.check_range(&self.gy) {
+            return Err(DhError::InvalidPeerValue);
         }
-        
-        self.x = self.generate_private_x(&bitlength);
-
-        // check for peer value
-        self.gx = self.g.modpow(&self.x, &self.p);
 
-        if !self.check_range(&self.gx) {
-            println!("GX needs to be less than modulus P");
+        // GX = G^X mod P is always less than P, but can still fall
+        // outside of the valid range, in which case we pick another X
+        loop {
+            self.x = self.generate_private_x(&bitlength, generator)?;
+            self.gx = primes::modpow(&self.g, &self.x, &self.p);
 
-            while !self.check_range(&self.gx) {
-                self.gx = primes::generate_discrete_log_prime(&bitlength);
+            if self.check_range(&self.gx) {
+                break;
             }
         }
 
-        self
+        Ok(self)
     }
 
     /// Generate a private X value that is as large as possible ( < P )
@@ -109,18 +320,19 @@ impl DiffieHellman {
     /// ### Arguments
     ///  
     /// * `bitlength` - Bit length of X
-    fn generate_private_x(&mut self, bitlength: &usize) -> BigUint {
+    /// * `generator` - Random number generator
+    fn generate_private_x(&mut self, bitlength: &usize, generator: &mut impl CryptoRngCore) -> Result<BigUint, DhError> {
         let mut x = BigUint::zero();
 
         while !self.check_range(&x) {
-            x = primes::generate_discrete_log_prime(bitlength);
+            x = primes::generate_discrete_log_prime(bitlength, generator)?;
 
             while &x >= &self.p {
                 x = x.clone().shr(1);
             }
         }
 
-        x.clone()
+        Ok(x)
     }
 
     /// Verify sanity of parameter in relation to P modulus.
@@ -135,7 +347,7 @@ impl DiffieHellman {
     /// * `parameter` - Parameter to check
     fn check_range(&self, parameter: &BigUint) -> bool {
         parameter >= &2.to_biguint().unwrap() && 
-        parameter <= &(&self.p - &2.to_biguint().unwrap())
+        &(parameter + &2.to_biguint().unwrap()) <= &self.p
     }
 
     /// Update blinding values. Use the blinding method and optimisation 
@@ -146,7 +358,7 @@ impl DiffieHellman {
     /// ### Arguments
     /// 
     /// * `generator` - Random number generator
-    fn update_blinding(&mut self, mut generator: &mut OsRng) -> () {
+    fn update_blinding(&mut self, generator: &mut impl CryptoRngCore) -> Result<(), DhError> {
 
         // Don't use any blinding the first time a particular X is used,
         // but remember it to use blinding next time.
@@ -159,8 +371,8 @@ impl DiffieHellman {
         // We need blinding. Can we re-use existing values?
         // If yes, just update them by squaring them.
         else if self.v_i != BigUint::one() {
-            self.v_i = self.v_i.modpow(&self.v_i, &self.p);
-            self.v_f = self.v_i.modpow(&self.v_f, &self.p);
+            self.v_i = primes::modpow(&self.v_i, &self.v_i, &self.p);
+            self.v_f = primes::modpow(&self.v_i, &self.v_f, &self.p);
         }
 
         // We need to generate blinding values from scratch
@@ -169,7 +381,7 @@ impl DiffieHellman {
 
             // Vi = random( 2, P-1 )
             while &self.v_i <= &BigUint::one() {
-                self.v_i = primes::generate_random_biguint(&mut generator, &self.p.bits());
+                self.v_i = primes::generate_random_biguint(generator, &self.p.bits());
 
                 while &self.v_i >= &self.p {
                     self.v_i = self.v_i.clone().shr(1);
@@ -178,41 +390,166 @@ impl DiffieHellman {
                 count += 1;
 
                 if count > 10 {
-                    break;
+                    return Err(DhError::BlindingFailed);
                 }
             }
 
             // Vf = Vi^-X mod P
             self.v_f = primes::modular_inverse(&self.v_i, &self.p);
-            self.v_f = self.v_f.modpow(&self.x, &self.p);
+            self.v_f = primes::modpow(&self.v_f, &self.x, &self.p);
         }
 
+        Ok(())
     }
  
     /// Derive and export the shared secret (G^Y)^X mod P.
     /// Random number generator is used to blind the input as a
     /// countermeasure against timing attacks. Blinding is
     /// automatically used if and only if our secret value X is
-    /// re-used and costs nothing otherwise.
+    /// re-used and costs nothing otherwise. Peer values outside of
+    /// 2 <= GY <= P - 2 are rejected.
     /// 
     /// ### Arguments
     ///  
     /// * `generator` - Random number generator
     /// * `peer_gx` - Peer's GY value
-    pub fn generate_shared_key(&mut self, mut generator: &mut OsRng, peer_gx: &BigUint) -> BigUint {
+    pub fn generate_shared_key(&mut self, generator: &mut impl CryptoRngCore, peer_gx: &BigUint) -> Result<BigUint, DhError> {
         let mut key = BigUint::zero();
 
+        if self.x.is_zero() {
+            return Err(DhError::KeyCleared);
+        }
+
+        if !self.check_range(peer_gx) {
+            return Err(DhError::InvalidPeerValue);
+        }
+
         // Perform necessary blinding
-        self.update_blinding(&mut generator);
+        self.update_blinding(generator)?;
         key = (peer_gx * &self.v_i).rem(&self.p);
 
         // Modular exponentiation and then unblind
-        self.shared_key = key.clone().modpow(&self.x, &self.p);
+        self.shared_key = primes::modpow(&key, &self.x, &self.p);
         self.shared_key = (&self.shared_key * &self.v_f).rem(&self.p);
 
-        self.shared_key.clone()
+        Ok(self.shared_key.clone())
+    }
+
+    /// Encodes the shared key big-endian in exactly as many bytes as P
+    /// takes, so that the encoding never depends on the value of the key
+    pub fn shared_key_bytes(&self) -> Result<Vec<u8>, DhError> {
+        if self.shared_key.is_zero() {
+            return Err(DhError::NoSharedKey);
+        }
+
+        let length = (self.p.bits() + 7) / 8;
+        biguint_to_fixed_bytes(&self.shared_key, length, EndianOrdering::Big).ok_or(DhError::InvalidModulus)
+    }
+
+    /// Derive symmetric key material from the last shared key generated,
+    /// by applying HKDF over its fixed-length big-endian encoding
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `info` - Context information binding the key to its purpose
+    /// * `len` - Length of the derived key in bytes
+    #[cfg(feature = "hashes-sha3")]
+    pub fn derive_key(&self, info: &[u8], len: usize) -> Result<Vec<u8>, DhError> {
+        let mut shared_bytes = self.shared_key_bytes()?;
+        let key = hkdf(&shared_bytes, &[], info, len, KDF_HASH_ALGORITHM);
+        zeroize_bytes(&mut shared_bytes);
+
+        Ok(key?)
+    }
+
+    /// Exports the public values to send to a peer
+    pub fn public_params(&self) -> Result<DhPublicParams, DhError> {
+        DhPublicParams::new(self.p.clone(), self.g.clone(), self.gx.clone())
     }
 
+    /// Wipes the private value, the blinding values and the last shared
+    /// key. The public parameters are kept, while deriving another shared
+    /// key fails with `DhError::KeyCleared`
+    pub fn clear(&mut self) -> () {
+        zeroize_biguint(&mut self.x);
+        zeroize_biguint(&mut self.px);
+        zeroize_biguint(&mut self.v_i);
+        zeroize_biguint(&mut self.v_f);
+        zeroize_biguint(&mut self.shared_key);
+    }
+
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for DiffieHellman {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// The payload is P, G and GX, followed for private keys by X. Any peer
+/// value held for an exchange in progress is not stored
+#[cfg(feature = "keys")]
+impl ExportableKey for DiffieHellman {
+    fn key_algorithm(&self) -> KeyAlgorithm {
+        KeyAlgorithm::DiffieHellman
+    }
+
+    fn has_private_key(&self) -> bool {
+        !self.x.is_zero()
+    }
+
+    fn export_payload(&self) -> Result<Vec<u8>, KeyError> {
+        let mut payload = Vec::new();
+
+        keys::write_biguint(&mut payload, &self.p);
+        keys::write_biguint(&mut payload, &self.g);
+        keys::write_biguint(&mut payload, &self.gx);
+
+        if self.has_private_key() {
+            keys::write_biguint(&mut payload, &self.x);
+        }
+
+        Ok(payload)
+    }
+
+    fn export_public_payload(&self) -> Result<Vec<u8>, KeyError> {
+        let mut payload = Vec::new();
+
+        keys::write_biguint(&mut payload, &self.p);
+        keys::write_biguint(&mut payload, &self.g);
+        keys::write_biguint(&mut payload, &self.gx);
+
+        Ok(payload)
+    }
+
+    fn import_payload(_algorithm: &KeyAlgorithm, private: bool, mut payload: &[u8]) -> Result<Self, KeyError> {
+        let p = keys::read_biguint(&mut payload)?;
+        let g = keys::read_biguint(&mut payload)?;
+        let gx = keys::read_biguint(&mut payload)?;
+
+        let params = match DhPublicParams::new(p, g, gx) {
+            Ok(params) => params,
+            Err(DhError::InvalidModulus) => return Err(KeyError::InvalidKey("Modulus P is too small")),
+            Err(_) => return Err(KeyError::InvalidKey("G and GX need to be between 2 and P - 2"))
+        };
+
+        let mut dh = DiffieHellman::new();
+        dh.p = params.p;
+        dh.g = params.g;
+        dh.gx = params.gx;
+
+        if private {
+            dh.x = keys::read_biguint(&mut payload)?;
+
+            if dh.x.is_zero() || primes::modpow(&dh.g, &dh.x, &dh.p) != dh.gx {
+                return Err(KeyError::InvalidKey("Private value X does not match GX"));
+            }
+        }
+
+        keys::finish_payload(payload)?;
+        Ok(dh)
+    }
 }
 
 
@@ -221,22 +558,96 @@ impl DiffieHellman {
 #[cfg(test)]
 mod dh_test {
 
-    use rand::OsRng;
-    use cryptopunk::key_exchange::diffie_hellman::DiffieHellman;
+    use rand::{ ChaChaRng, SeedableRng };
+    use num_bigint::BigUint;
+    use num_traits::{ One, Zero };
+    use crate::utils::rng::os_rng;
+    use crate::key_exchange::diffie_hellman::{ DiffieHellman, DhError };
 
     #[test]
     fn successful_shared_secret() {
-        let mut generator = OsRng::new().unwrap();
-        let mut dh = DiffieHellman::new().setup(16);
+        let mut generator = os_rng().unwrap();
+        let mut dh = DiffieHellman::new().setup(16, &mut generator).unwrap();
 
         let (p, g, peer_gx) = (dh.p.clone(), dh.g.clone(), dh.gx.clone());
 
-        let mut dh2 = DiffieHellman::new_from_peer(&p, &g, &peer_gx).setup(16);
+        let mut dh2 = DiffieHellman::new_from_peer(&p, &g, &peer_gx).setup(16, &mut generator).unwrap();
 
-        let check_from_first = dh.generate_shared_key(&mut generator, &dh2.gx);
-        let check_from_second = dh2.generate_shared_key(&mut generator, &dh.gx);
+        let check_from_first = dh.generate_shared_key(&mut generator, &dh2.gx).unwrap();
+        let check_from_second = dh2.generate_shared_key(&mut generator, &dh.gx).unwrap();
 
         assert_eq!(check_from_first, check_from_second);
     }
+
+    #[test]
+    #[cfg(all(feature = "hashes-sha3", feature = "aes"))]
+    fn derived_keys_carry_a_gcm_message() {
+        use crypto::aes::KeySize;
+        use crate::encryption::aes::{ AES, AESMode };
+
+        let mut generator = ChaChaRng::from_seed(&[0x64, 0x6b]);
+        let mut dh = DiffieHellman::new().setup(32, &mut generator).unwrap();
+        let (p, g, peer_gx) = (dh.p.clone(), dh.g.clone(), dh.gx.clone());
+        let mut dh2 = DiffieHellman::new_from_peer(&p, &g, &peer_gx).setup(32, &mut generator).unwrap();
+
+        assert_eq!(dh.derive_key(b"session key", 32), Err(DhError::NoSharedKey));
+
+        let peer_of_first = dh2.gx.clone();
+        let peer_of_second = dh.gx.clone();
+        dh.generate_shared_key(&mut generator, &peer_of_first).unwrap();
+        dh2.generate_shared_key(&mut generator, &peer_of_second).unwrap();
+
+        // Both sides encode the key to the length of P before hashing
+        assert_eq!(dh.shared_key_bytes().unwrap().len(), (dh.p.bits() + 7) / 8);
+
+        let key = dh.derive_key(b"session key", 32).unwrap();
+        assert_eq!(key, dh2.derive_key(b"session key", 32).unwrap());
+        assert!(key != dh.derive_key(b"other key", 32).unwrap());
+
+        let mut sender = AES::new_with_key(key, Vec::new(), KeySize::KeySize256, AESMode::GCM, None).unwrap();
+        let receiver_key = dh2.derive_key(b"session key", 32).unwrap();
+        let receiver = AES::new_with_key(receiver_key, Vec::new(), KeySize::KeySize256, AESMode::GCM, None).unwrap();
+
+        let sealed = sender.encrypt(b"meet at the usual place").unwrap();
+        assert_eq!(receiver.decrypt(&sealed).unwrap(), b"meet at the usual place".to_vec());
+    }
+
+    #[test]
+    fn out_of_range_peer_values_are_rejected() {
+        let mut generator = os_rng().unwrap();
+        let mut dh = DiffieHellman::new().setup(16, &mut generator).unwrap();
+        let p = dh.p.clone();
+
+        for peer_gx in vec![BigUint::zero(), BigUint::one(), &p - BigUint::one(), p.clone()] {
+            assert_eq!(dh.generate_shared_key(&mut generator, &peer_gx), Err(DhError::InvalidPeerValue));
+        }
+    }
+
+    #[test]
+    fn tiny_peer_modulus_is_rejected() {
+        let mut generator = os_rng().unwrap();
+        let two = BigUint::one() + BigUint::one();
+        let setup = DiffieHellman::new_from_peer(&two, &two, &BigUint::one()).setup(16, &mut generator);
+
+        assert!(match setup { Err(DhError::InvalidModulus) => true, _ => false });
+    }
+
+    #[test]
+    fn seeded_setup_is_reproducible() {
+        let first = DiffieHellman::new().setup(32, &mut ChaChaRng::from_seed(&[5, 6, 7, 8])).unwrap();
+        let second = DiffieHellman::new().setup(32, &mut ChaChaRng::from_seed(&[5, 6, 7, 8])).unwrap();
+
+        assert_eq!((&first.p, &first.g, &first.gx), (&second.p, &second.g, &second.gx));
+    }
+
+    #[test]
+    fn generated_params_can_be_exported() {
+        for seed in 0..8 {
+            let dh = DiffieHellman::new().setup(32, &mut ChaChaRng::from_seed(&[seed, 0x64])).unwrap();
+
+            assert!(dh.g < dh.p);
+            assert!(dh.public_params().is_ok());
+        }
+    }
     
 }
\ No newline at end of file
@@ -1,17 +1,34 @@
-use rand::OsRng;
+use std::fmt;
 use num_traits::Zero;
-use num_bigint::BigInt;
+use num_bigint::{ BigInt, BigUint, ToBigInt, ToBigUint };
 
-use utils::{ montgomery_ladder, comb_method };
-use utils::ecc::{ ECPKeypair };
-use utils::ecc_curves::{ ECPGroup, ECPPoint, ECPSupportedCurves, ECPCurveShape };
+use crate::hash::kdf::KeyDerivation;
+use crate::hash::crypto::HashAlgorithm;
+use crate::key_exchange::KeyExchange;
+use crate::key_exchange::x25519::{ x25519, X25519_LENGTH, X25519_BASE_POINT };
+use crate::utils::rng::CryptoRngCore;
+use crate::utils::{ comb_method, jacobian_coords };
+use crate::utils::ecc::{ ECPKeypair, EcError, ValidatedPoint, ValidationLevel, encode_point, decode_point };
+use crate::utils::encoding::{ EndianOrdering, biguint_to_fixed_bytes };
+use crate::utils::zeroize::{ zeroize_bytes, zeroize_biguint };
+use crate::utils::ecc_curves::{ ECPGroup, ECPPoint, ECPSupportedCurves, ECPCurveShape };
 
+/// Hash algorithm used by the KDF when deriving keys from the shared secret
+const KDF_HASH_ALGORITHM: HashAlgorithm = HashAlgorithm::Sha3_256;
+
+/// All fields are private so that the public value always matches
+/// the private value held in the keypair. Public values are exchanged
+/// as bytes through `public_key_bytes` and `set_peer_public_key_bytes`.
+/// 
+/// The shared secret is never stored; it is returned by value from the
+/// derivation methods, which only borrow the struct immutably. A static
+/// key can therefore be wrapped in an Arc and used from many threads.
+/// The private value is wiped when the struct is dropped or cleared
 pub struct ECDH {
-    pub group: ECPGroup,
-    pub q: ECPPoint,                // our public value (public key) 
-    pub z: BigInt,                  // shared secret
-    pub peer_q: Option<ECPPoint>,   // peer's public value (public key)
-    keypair: ECPKeypair             // Generated keypair, for reference (private value stored here)
+    group: ECPGroup,
+    q: ECPPoint,                // our public value (public key) 
+    peer_q: Option<ECPPoint>,   // peer's public value (public key)
+    keypair: ECPKeypair         // Generated keypair, for reference (private value stored here)
 }
 
 impl ECDH {
@@ -20,60 +37,429 @@ impl ECDH {
     /// the TLS ECDH source code, written in C, found here:
     /// https://github.com/ARMmbed/mbedtls/blob/master/library/ecdh.c
     /// 
+    /// ### Example
+    /// 
+    /// ```
+    /// use cryptofun::key_exchange::ecdh::ECDH;
+    /// use cryptofun::utils::ecc_curves::ECPSupportedCurves;
+    /// use cryptofun::utils::rng::seeded_rng;
+    /// 
+    /// let mut rng = seeded_rng(&[0xec, 0xd4]);
+    /// let alice = ECDH::new(ECPSupportedCurves::Curve25519, &mut rng).unwrap();
+    /// let bob = ECDH::new(ECPSupportedCurves::Curve25519, &mut rng).unwrap();
+    /// 
+    /// let alice_secret = alice.derive_shared_bytes_for_peer(&bob.public_key_bytes(false), &mut rng).unwrap();
+    /// let bob_secret = bob.derive_shared_bytes_for_peer(&alice.public_key_bytes(false), &mut rng).unwrap();
+    /// 
+    /// assert_eq!(alice_secret, bob_secret);
+    /// ```
+    /// 
     /// ### Arguments
     /// 
     /// * `curve` - Curve group to use
-    pub fn new(curve: ECPSupportedCurves) -> Self {
-        let zero = BigInt::zero();
-        let mut rng = OsRng::new().unwrap();
-        let keypair = ECPKeypair::new(curve).setup(&mut rng);
+    /// * `rng` - Random number generator
+    pub fn new(curve: ECPSupportedCurves, rng: &mut impl CryptoRngCore) -> Result<Self, EcError> {
+        let keypair = generate_keypair(curve, rng)?;
+
+        Ok(ECDH {
+            group: keypair.group.clone(),
+            q: keypair.q.clone(),
+            peer_q: None,
+            keypair: keypair
+        })
+    }
 
+    /// Builds an ECDH context around an existing keypair, for instance
+    /// a long-term key that has been generated beforehand
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `keypair` - Keypair holding the private value to use
+    pub fn from_keypair(keypair: ECPKeypair) -> Self {
         ECDH {
             group: keypair.group.clone(),
             q: keypair.q.clone(),
             peer_q: None,
-            z: zero.clone(),
             keypair: keypair
         }
     }
 
+    /// Identifier of the curve used for the exchange
+    pub fn curve_id(&self) -> ECPSupportedCurves {
+        self.group.curve_id()
+    }
+
+    /// Curve group used for the exchange
+    pub fn group(&self) -> &ECPGroup {
+        &self.group
+    }
+
+    /// Our public value
+    pub fn public_key(&self) -> &ECPPoint {
+        &self.q
+    }
+
+    /// The peer's public value, if one has been set
+    pub fn peer_public_key(&self) -> Option<&ECPPoint> {
+        self.peer_q.as_ref()
+    }
+
+    /// Exports our public value for the wire. Short Weierstrass curves
+    /// use the SEC1 encoding, compressed or not, while Montgomery curves
+    /// always export the raw little-endian u-coordinate
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `compressed` - Whether to use compressed SEC1 points
+    pub fn public_key_bytes(&self, compressed: bool) -> Vec<u8> {
+        match encode_point(&self.group, &self.q, compressed) {
+            Ok(bytes) => bytes,
+            Err(e) => panic!("{}", e)
+        }
+    }
+
+    /// Sets the peer's public value. The point must have been validated
+    /// against the curve of the exchange, or `EcError::CurveMismatch`
+    /// is returned
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `point` - Validated peer public value
+    pub fn set_peer_public_key(&mut self, point: &ValidatedPoint) -> Result<(), EcError> {
+        point.check_curve(&self.curve_id())?;
+        self.peer_q = Some(point.point().clone());
+        Ok(())
+    }
+
+    /// Imports the peer's public value from the bytes produced by
+    /// its `public_key_bytes`, validating it for membership of the group
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `bytes` - Encoded peer public value
+    pub fn set_peer_public_key_bytes(&mut self, bytes: &[u8]) -> Result<(), EcError> {
+        let point = decode_point(&self.group, bytes)?;
+        let validated = self.group.validate(point, ValidationLevel::Membership)?;
+
+        self.peer_q = Some(validated.point().clone());
+        Ok(())
+    }
+
     /// Derive and export the shared secret
     /// 
     /// ### Arguments
     /// 
     /// * `rng` - Random number generator
-    pub fn generate_shared_key(&mut self, mut rng: &mut OsRng) -> BigInt {
-        // Check peer Q point first
-        self.check_peer_q();
+    pub fn generate_shared_key(&self, rng: &mut impl CryptoRngCore) -> Result<BigInt, EcError> {
+        let peer_q = self.check_peer_q()?;
+        let P = self.compute_shared_point(&peer_q, rng)?;
+
+        Ok(P.x)
+    }
+
+    /// Derive the shared secret as a fixed-length byte string. For short
+    /// Weierstrass curves this is the big-endian x-coordinate padded to the
+    /// byte length of the field (SEC1 section 3.3.1), and for Montgomery
+    /// curves the little-endian u-coordinate as described in RFC 7748.
+    /// The encoding never depends on the value of the secret itself
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `rng` - Random number generator
+    pub fn derive_shared_bytes(&self, rng: &mut impl CryptoRngCore) -> Result<Vec<u8>, EcError> {
+        let peer_q = self.check_peer_q()?;
+        self.shared_bytes_with(&peer_q, rng)
+    }
+
+    /// Derive the shared secret with a peer whose public value is given
+    /// directly as bytes, without storing it. This lets a single static
+    /// key serve many handshakes at once
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `peer_bytes` - Encoded peer public value
+    /// * `rng` - Random number generator
+    pub fn derive_shared_bytes_for_peer(&self, peer_bytes: &[u8], rng: &mut impl CryptoRngCore) -> Result<Vec<u8>, EcError> {
+        let peer_q = decode_point(&self.group, peer_bytes)?;
+        let validated = self.group.validate(peer_q, ValidationLevel::Membership)?;
+
+        self.shared_bytes_with(validated.point(), rng)
+    }
+
+    /// Derive symmetric key material from the shared secret by applying
+    /// HKDF over its fixed-length byte encoding
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `rng` - Random number generator
+    /// * `info` - Context information binding the key to its purpose
+    /// * `len` - Length of the derived key in bytes
+    pub fn derive_key(&self, rng: &mut impl CryptoRngCore, info: &[u8], len: usize) -> Result<Vec<u8>, EcError> {
+        self.derive_key_with(KeyDerivation::Hkdf, rng, info, len)
+    }
+
+    /// Derive symmetric key material from the shared secret with the
+    /// given KDF, for peers that expect X9.63 or the Concatenation KDF
+    /// rather than HKDF
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `kdf` - Key derivation function to apply
+    /// * `rng` - Random number generator
+    /// * `info` - Context information binding the key to its purpose
+    /// * `len` - Length of the derived key in bytes
+    pub fn derive_key_with(&self, kdf: KeyDerivation, rng: &mut impl CryptoRngCore, info: &[u8], len: usize) -> Result<Vec<u8>, EcError> {
+        let shared_bytes = self.derive_shared_bytes(rng)?;
+        expand_shared_bytes(kdf, shared_bytes, info, len)
+    }
+
+    /// Derive symmetric key material with a peer whose public value is
+    /// given directly as bytes, without storing it
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `peer_bytes` - Encoded peer public value
+    /// * `rng` - Random number generator
+    /// * `info` - Context information binding the key to its purpose
+    /// * `len` - Length of the derived key in bytes
+    pub fn derive_key_for_peer(&self, peer_bytes: &[u8], rng: &mut impl CryptoRngCore, info: &[u8], len: usize) -> Result<Vec<u8>, EcError> {
+        self.derive_key_for_peer_with(KeyDerivation::Hkdf, peer_bytes, rng, info, len)
+    }
+
+    /// Derive symmetric key material with a peer whose public value is
+    /// given as bytes, using the given KDF
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `kdf` - Key derivation function to apply
+    /// * `peer_bytes` - Encoded peer public value
+    /// * `rng` - Random number generator
+    /// * `info` - Context information binding the key to its purpose
+    /// * `len` - Length of the derived key in bytes
+    pub fn derive_key_for_peer_with(
+        &self,
+        kdf: KeyDerivation,
+        peer_bytes: &[u8],
+        rng: &mut impl CryptoRngCore,
+        info: &[u8],
+        len: usize
+    ) -> Result<Vec<u8>, EcError>
+    {
+        let shared_bytes = self.derive_shared_bytes_for_peer(peer_bytes, rng)?;
+        expand_shared_bytes(kdf, shared_bytes, info, len)
+    }
+
+    /// Performs a one-shot exchange with a fresh keypair on the same curve,
+    /// for forward secrecy. The ephemeral private value is wiped before
+    /// returning, so it can never be reused. Returns our ephemeral public
+    /// value, to be sent to the peer, and the derived key
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `peer_bytes` - Encoded peer public value
+    /// * `rng` - Random number generator
+    /// * `info` - Context information binding the key to its purpose
+    /// * `len` - Length of the derived key in bytes
+    pub fn ephemeral_exchange(
+        &self,
+        peer_bytes: &[u8],
+        rng: &mut impl CryptoRngCore,
+        info: &[u8],
+        len: usize
+    ) -> Result<(Vec<u8>, Vec<u8>), EcError>
+    {
+        let mut ephemeral = ECDH::new(self.curve_id(), rng)?;
+        let key = ephemeral.derive_key_for_peer(peer_bytes, rng, info, len);
+        let public_bytes = ephemeral.public_key_bytes(false);
+
+        ephemeral.clear();
+
+        Ok((public_bytes, key?))
+    }
+
+    /// Replaces our keypair with a freshly generated one on the same
+    /// curve, wiping the old private value. The peer public value is kept
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `rng` - Random number generator
+    pub fn rekey(&mut self, rng: &mut impl CryptoRngCore) -> Result<(), EcError> {
+        let keypair = generate_keypair(self.curve_id(), rng)?;
+
+        zeroize_biguint(&mut self.keypair.d);
+        self.q = keypair.q.clone();
+        self.keypair = keypair;
+
+        Ok(())
+    }
+
+    /// Wipes the private value. Any later attempt to derive a shared
+    /// secret returns `EcError::KeyCleared` instead of stale data
+    pub fn clear(&mut self) -> () {
+        zeroize_biguint(&mut self.keypair.d);
+    }
+
+    /// Encodes the shared secret with a validated peer point
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `peer_q` - Validated peer public value
+    /// * `rng` - Random number generator
+    fn shared_bytes_with(&self, peer_q: &ECPPoint, rng: &mut impl CryptoRngCore) -> Result<Vec<u8>, EcError> {
+        let P = self.compute_shared_point(peer_q, rng)?;
+        let field_length = (self.group.p.bits() + 7) / 8;
+
+        let ordering = match self.group.get_curve_shape() {
+            ECPCurveShape::Montgomery => EndianOrdering::Little,
+            ECPCurveShape::ShortWeierstrass => EndianOrdering::Big
+        };
+
+        let x = match P.x.to_biguint() {
+            Some(x) => x,
+            None => return Err(EcError::InvalidEncoding)
+        };
+
+        match biguint_to_fixed_bytes(&x, field_length, ordering) {
+            Some(bytes) => Ok(bytes),
+            None => Err(EcError::InvalidEncoding)
+        }
+    }
+
+    /// Computes the normalized shared point d * peer_Q
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `peer_q` - Validated peer public value
+    /// * `rng` - Random number generator
+    fn compute_shared_point(&self, peer_q: &ECPPoint, rng: &mut impl CryptoRngCore) -> Result<ECPPoint, EcError> {
+        if self.keypair.d.is_zero() {
+            return Err(EcError::KeyCleared);
+        }
 
         let curve_shape = self.group.get_curve_shape();
 
         let P = match curve_shape {
             ECPCurveShape::Montgomery => {
-                montgomery_ladder::multiply(&self.keypair.group, &self.keypair.d, &self.peer_q.clone().unwrap())
+                let peer_x = match peer_q.x.to_biguint() {
+                    Some(x) => x,
+                    None => return Err(EcError::InvalidPublicKey("U coordinate of peer point is negative"))
+                };
+
+                let mut scalar = encode_x25519(&self.keypair.d)?;
+                let shared_u = encode_x25519(&peer_x).and_then(|u| x25519(&scalar, &u, rng));
+                zeroize_bytes(&mut scalar);
+
+                let shared_u = shared_u?;
+
+                ECPPoint::new( &BigUint::from_bytes_le(&shared_u).to_bigint().unwrap(), None )
             },
             ECPCurveShape::ShortWeierstrass => {
-                comb_method::multiply(&mut self.keypair.group, &self.keypair.d, &self.peer_q.clone().unwrap(), &mut rng)
+                let R = comb_method::multiply(&self.keypair.group, &self.keypair.d, peer_q, rng);
+                jacobian_coords::normalize_point(&self.keypair.group, &R)
             }
         };
 
-        self.z = P.x.clone();
-        P.x
+        if P.is_zero() || P.z == BigInt::zero() {
+            return Err(EcError::PointAtInfinity);
+        }
+
+        Ok(P)
     }
 
-    /// Checks that a peer's Q point is available and valid
-    fn check_peer_q(&self) -> () {
-        if self.peer_q.is_none() {
-            panic!("No peer point available to generate shared secret for");
+    /// Checks that a peer's Q point is available, returning a copy of
+    /// it. The point was validated when it was set
+    fn check_peer_q(&self) -> Result<ECPPoint, EcError> {
+        match self.peer_q {
+            Some(ref q) => Ok(q.clone()),
+            None => Err(EcError::MissingPeerPoint)
         }
+    }
 
-        let validity_check = self.keypair.check_public_key(&self.peer_q.clone().unwrap());
+}
 
-        if !validity_check.0 {
-            panic!(validity_check.1);
-        }
+impl Drop for ECDH {
+    fn drop(&mut self) {
+        self.clear();
     }
+}
+
+impl KeyExchange for ECDH {
+    type Error = EcError;
+
+    fn public_bytes(&self) -> Vec<u8> {
+        self.public_key_bytes(false)
+    }
+
+    fn shared_secret(&self, peer_bytes: &[u8], rng: &mut impl CryptoRngCore) -> Result<Vec<u8>, EcError> {
+        self.derive_shared_bytes_for_peer(peer_bytes, rng)
+    }
+}
+
+impl fmt::Debug for ECDH {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ECDH")
+            .field("curve", &self.curve_id())
+            .field("q", &self.q)
+            .field("peer_q", &self.peer_q)
+            .field("d", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Generates a keypair on the given curve. Montgomery public values are
+/// computed with X25519 so that they match those of RFC 7748 peers
+/// 
+/// ### Arguments
+/// 
+/// * `curve` - Curve to generate the keypair on
+/// * `rng` - Random number generator
+fn generate_keypair(curve: ECPSupportedCurves, rng: &mut impl CryptoRngCore) -> Result<ECPKeypair, EcError> {
+    let mut keypair = ECPKeypair::new(curve).setup(rng)?;
+
+    if keypair.group.get_curve_shape() == ECPCurveShape::Montgomery {
+        let mut scalar = encode_x25519(&keypair.d)?;
+
+        let public_u = x25519(&scalar, &X25519_BASE_POINT, rng);
+        zeroize_bytes(&mut scalar);
+
+        keypair.q = ECPPoint::new( &BigUint::from_bytes_le(&public_u?).to_bigint().unwrap(), None );
+    }
+
+    Ok(keypair)
+}
 
+/// Applies a KDF to a shared secret, wiping the raw secret once the key
+/// has been derived
+/// 
+/// ### Arguments
+/// 
+/// * `kdf` - Key derivation function to apply
+/// * `shared_bytes` - Fixed-length encoding of the shared secret
+/// * `info` - Context information binding the key to its purpose
+/// * `len` - Length of the derived key in bytes
+fn expand_shared_bytes(kdf: KeyDerivation, mut shared_bytes: Vec<u8>, info: &[u8], len: usize) -> Result<Vec<u8>, EcError> {
+    let key = kdf.derive(&shared_bytes, &[], info, len, KDF_HASH_ALGORITHM);
+    zeroize_bytes(&mut shared_bytes);
+
+    Ok(key?)
+}
+
+/// Encodes a scalar or u-coordinate as the 32 little-endian bytes
+/// expected by X25519
+/// 
+/// ### Arguments
+/// 
+/// * `value` - Value to encode
+fn encode_x25519(value: &BigUint) -> Result<[u8; X25519_LENGTH], EcError> {
+    let bytes = match biguint_to_fixed_bytes(value, X25519_LENGTH, EndianOrdering::Little) {
+        Some(bytes) => bytes,
+        None => return Err(EcError::InvalidEncoding)
+    };
+
+    let mut encoded = [0; X25519_LENGTH];
+    encoded.copy_from_slice(&bytes);
+
+    Ok(encoded)
 }
 
 /*----- TESTS -----*/
@@ -81,45 +467,439 @@ impl ECDH {
 #[cfg(test)]
 mod ecdh_test {
 
-    use rand::OsRng;
-    use cryptopunk::key_exchange::ecdh::ECDH;
-    use utils::ecc_curves::ECPSupportedCurves;
+    use std::thread;
+    use std::sync::Arc;
 
-    #[test]
-    fn successful_shared_secret_weierstrass() {
-        let mut rng = OsRng::new().unwrap();
+    use rand::{ ChaChaRng, SeedableRng };
+    use num_bigint::BigUint;
+    use crate::utils::encoding::hex_to_bytes;
+    use crate::hash::kdf::KeyDerivation;
+    use crate::key_exchange::ecdh::ECDH;
+    use crate::utils::ecc::{ ECPKeypair, EcError, ValidationLevel };
+    use crate::utils::rng::os_rng;
+    use crate::utils::ecc_curves::ECPSupportedCurves;
+    use crate::utils::encoding::{ EndianOrdering, biguint_to_fixed_bytes };
+
+    /// Sets up two ECDH instances that know each other's public values,
+    /// exchanging nothing but byte strings
+    fn paired_instances(curve: ECPSupportedCurves) -> (ECDH, ECDH) {
+        let mut rng = os_rng().unwrap();
+        let mut dh = ECDH::new(curve.clone(), &mut rng).unwrap();
+        let mut dh2 = ECDH::new(curve, &mut rng).unwrap();
+
+        let self_bytes = dh.public_key_bytes(false);
+        let peer_bytes = dh2.public_key_bytes(false);
 
-        let mut dh = ECDH::new(ECPSupportedCurves::BP256R1);
-        let self_q = dh.q.clone();
+        dh.set_peer_public_key_bytes(&peer_bytes).unwrap();
+        dh2.set_peer_public_key_bytes(&self_bytes).unwrap();
 
-        let mut dh2 = ECDH::new(ECPSupportedCurves::BP256R1);
-        let peer_q = dh2.q.clone();
+        (dh, dh2)
+    }
 
-        dh.peer_q = Some(peer_q);
-        dh2.peer_q = Some(self_q);
+    #[test]
+    fn successful_shared_secret_weierstrass() {
+        let mut rng = os_rng().unwrap();
+        let (dh, dh2) = paired_instances(ECPSupportedCurves::BP256R1);
 
-        let check_from_first = dh.generate_shared_key(&mut rng);
-        let check_from_second = dh2.generate_shared_key(&mut rng);
+        let check_from_first = dh.generate_shared_key(&mut rng).unwrap();
+        let check_from_second = dh2.generate_shared_key(&mut rng).unwrap();
 
         assert_eq!(check_from_first, check_from_second);
     }
     
     #[test]
     fn successful_shared_secret_montgomery() {
-        let mut rng = OsRng::new().unwrap();
+        let mut rng = os_rng().unwrap();
+        let (dh, dh2) = paired_instances(ECPSupportedCurves::Curve25519);
 
-        let mut dh = ECDH::new(ECPSupportedCurves::Curve25519);
-        let self_q = dh.q.clone();
+        let check_from_first = dh.generate_shared_key(&mut rng).unwrap();
+        let check_from_second = dh2.generate_shared_key(&mut rng).unwrap();
 
-        let mut dh2 = ECDH::new(ECPSupportedCurves::Curve25519);
-        let peer_q = dh2.q.clone();
+        assert_eq!(check_from_first, check_from_second);
+    }
 
-        dh.peer_q = Some(peer_q);
-        dh2.peer_q = Some(self_q);
+    #[test]
+    fn compressed_public_values_agree() {
+        let mut rng = os_rng().unwrap();
+        let mut dh = ECDH::new(ECPSupportedCurves::BP256R1, &mut rng).unwrap();
+        let mut dh2 = ECDH::new(ECPSupportedCurves::BP256R1, &mut rng).unwrap();
 
-        let check_from_first = dh.generate_shared_key(&mut rng);
-        let check_from_second = dh2.generate_shared_key(&mut rng);
+        let self_bytes = dh.public_key_bytes(true);
+        let peer_bytes = dh2.public_key_bytes(true);
 
-        assert_eq!(check_from_first, check_from_second);
+        assert_eq!(self_bytes.len(), 33);
+        assert!(self_bytes[0] == 0x02 || self_bytes[0] == 0x03);
+
+        dh.set_peer_public_key_bytes(&peer_bytes).unwrap();
+        dh2.set_peer_public_key_bytes(&self_bytes).unwrap();
+
+        assert_eq!(dh.derive_shared_bytes(&mut rng).unwrap(), dh2.derive_shared_bytes(&mut rng).unwrap());
+    }
+
+    #[test]
+    fn public_value_encoding_per_curve_shape() {
+        let mut rng = os_rng().unwrap();
+        let weierstrass = ECDH::new(ECPSupportedCurves::BP256R1, &mut rng).unwrap();
+        let montgomery = ECDH::new(ECPSupportedCurves::Curve25519, &mut rng).unwrap();
+
+        assert_eq!(weierstrass.curve_id(), ECPSupportedCurves::BP256R1);
+        assert_eq!(weierstrass.public_key_bytes(false).len(), 65);
+        assert_eq!(weierstrass.public_key_bytes(false)[0], 0x04);
+
+        assert_eq!(montgomery.curve_id(), ECPSupportedCurves::Curve25519);
+        assert_eq!(montgomery.public_key_bytes(false), montgomery.public_key_bytes(true));
+        assert_eq!(montgomery.public_key_bytes(false).len(), 32);
+    }
+
+    #[test]
+    fn invalid_peer_bytes_are_rejected() {
+        let mut rng = os_rng().unwrap();
+        let mut dh = ECDH::new(ECPSupportedCurves::BP256R1, &mut rng).unwrap();
+        let mut peer_bytes = ECDH::new(ECPSupportedCurves::BP256R1, &mut rng).unwrap().public_key_bytes(false);
+
+        peer_bytes[40] ^= 1;
+
+        assert!(dh.set_peer_public_key_bytes(&peer_bytes).is_err());
+        assert!(dh.peer_public_key().is_none());
+        assert_eq!(dh.set_peer_public_key_bytes(&peer_bytes[..10]), Err(EcError::InvalidEncoding));
+    }
+
+    #[test]
+    fn points_validated_on_another_curve_are_rejected() {
+        let mut rng = os_rng().unwrap();
+        let mut dh = ECDH::new(ECPSupportedCurves::BP256R1, &mut rng).unwrap();
+        let foreign = ECDH::new(ECPSupportedCurves::SECP256R1, &mut rng).unwrap();
+        let peer = ECDH::new(ECPSupportedCurves::BP256R1, &mut rng).unwrap();
+
+        let foreign_point = foreign.group().validate(foreign.public_key().clone(), ValidationLevel::FullSubgroup).unwrap();
+        assert_eq!(dh.set_peer_public_key(&foreign_point), Err(EcError::CurveMismatch));
+        assert!(dh.peer_public_key().is_none());
+
+        let peer_point = peer.group().validate(peer.public_key().clone(), ValidationLevel::FullSubgroup).unwrap();
+        dh.set_peer_public_key(&peer_point).unwrap();
+
+        let from_peer = peer.derive_shared_bytes_for_peer(&dh.public_key_bytes(false), &mut rng).unwrap();
+        assert_eq!(dh.derive_shared_bytes(&mut rng).unwrap(), from_peer);
+    }
+
+    #[test]
+    fn shared_bytes_weierstrass() {
+        let mut rng = os_rng().unwrap();
+
+        for _ in 0..5 {
+            let (dh, dh2) = paired_instances(ECPSupportedCurves::BP256R1);
+
+            let check_from_first = dh.derive_shared_bytes(&mut rng).unwrap();
+            let check_from_second = dh2.derive_shared_bytes(&mut rng).unwrap();
+
+            assert_eq!(check_from_first.len(), 32);
+            assert_eq!(check_from_first, check_from_second);
+        }
+    }
+
+    #[test]
+    fn shared_bytes_montgomery() {
+        let mut rng = os_rng().unwrap();
+
+        for _ in 0..5 {
+            let (dh, dh2) = paired_instances(ECPSupportedCurves::Curve25519);
+
+            let check_from_first = dh.derive_shared_bytes(&mut rng).unwrap();
+            let check_from_second = dh2.derive_shared_bytes(&mut rng).unwrap();
+
+            assert_eq!(check_from_first.len(), 32);
+            assert_eq!(check_from_first, check_from_second);
+        }
+    }
+
+    #[test]
+    fn shared_bytes_keep_leading_zeros() {
+        let x = BigUint::from_bytes_be(&[0x01, 0x02, 0x03]);
+
+        let big = biguint_to_fixed_bytes(&x, 32, EndianOrdering::Big).unwrap();
+        assert_eq!(big.len(), 32);
+        assert!(big[..29].iter().all(|b| *b == 0));
+        assert_eq!(big[29..], [0x01, 0x02, 0x03]);
+
+        let little = biguint_to_fixed_bytes(&x, 32, EndianOrdering::Little).unwrap();
+        assert_eq!(little[..3], [0x03, 0x02, 0x01]);
+        assert!(little[3..].iter().all(|b| *b == 0));
+
+        assert!(biguint_to_fixed_bytes(&x, 2, EndianOrdering::Big).is_none());
+    }
+
+    #[test]
+    fn derived_keys_agree() {
+        let mut rng = os_rng().unwrap();
+        let (dh, dh2) = paired_instances(ECPSupportedCurves::BP256R1);
+
+        let key_from_first = dh.derive_key(&mut rng, b"session key", 16).unwrap();
+        let key_from_second = dh2.derive_key(&mut rng, b"session key", 16).unwrap();
+        let other_purpose = dh.derive_key(&mut rng, b"other key", 16).unwrap();
+
+        assert_eq!(key_from_first.len(), 16);
+        assert_eq!(key_from_first, key_from_second);
+        assert_ne!(key_from_first, other_purpose);
+    }
+
+    #[test]
+    fn derived_keys_agree_under_each_kdf() {
+        let mut rng = os_rng().unwrap();
+        let (dh, dh2) = paired_instances(ECPSupportedCurves::Curve25519);
+        let mut keys = Vec::new();
+
+        for kdf in vec![KeyDerivation::Hkdf, KeyDerivation::X963, KeyDerivation::Concat] {
+            let key = dh.derive_key_with(kdf, &mut rng, b"session key", 32).unwrap();
+
+            assert_eq!(key, dh2.derive_key_with(kdf, &mut rng, b"session key", 32).unwrap());
+            assert_eq!(key, dh2.derive_key_for_peer_with(kdf, &dh.public_key_bytes(false), &mut rng, b"session key", 32).unwrap());
+            keys.push(key);
+        }
+
+        assert_eq!(keys[0], dh.derive_key(&mut rng, b"session key", 32).unwrap());
+        assert_ne!(keys[0], keys[1]);
+        assert_ne!(keys[1], keys[2]);
+    }
+
+    #[test]
+    fn missing_peer_point_is_an_error() {
+        let mut rng = os_rng().unwrap();
+        let dh = ECDH::new(ECPSupportedCurves::BP256R1, &mut rng).unwrap();
+
+        assert_eq!(dh.derive_shared_bytes(&mut rng), Err(EcError::MissingPeerPoint));
+    }
+
+    #[test]
+    fn concurrent_derivation_with_static_key() {
+        let mut rng = os_rng().unwrap();
+        let server = Arc::new(ECDH::new(ECPSupportedCurves::BP256R1, &mut rng).unwrap());
+        let server_bytes = server.public_key_bytes(false);
+
+        let handles: Vec<_> = (0..8).map(|_| {
+            let server = server.clone();
+            let server_bytes = server_bytes.clone();
+
+            thread::spawn(move || {
+                let mut rng = os_rng().unwrap();
+                let mut client = ECDH::new(ECPSupportedCurves::BP256R1, &mut rng).unwrap();
+                client.set_peer_public_key_bytes(&server_bytes).unwrap();
+
+                let from_client = client.derive_shared_bytes(&mut rng).unwrap();
+                let from_server = server.derive_shared_bytes_for_peer(&client.public_key_bytes(false), &mut rng).unwrap();
+
+                assert_eq!(from_client, from_server);
+                from_server
+            })
+        }).collect();
+
+        let secrets: Vec<Vec<u8>> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+        // Every client used a fresh key, so every secret is different
+        for i in 0..secrets.len() {
+            for j in (i + 1)..secrets.len() {
+                assert_ne!(secrets[i], secrets[j]);
+            }
+        }
+    }
+
+    #[test]
+    fn debug_output_redacts_private_value() {
+        let mut rng = os_rng().unwrap();
+        let dh = ECDH::new(ECPSupportedCurves::BP256R1, &mut rng).unwrap();
+        let output = format!("{:?}", dh);
+
+        assert!(output.contains("<redacted>"));
+        assert!(!output.contains(&dh.keypair.d.to_str_radix(10)));
+        assert!(!output.contains(&dh.keypair.d.to_str_radix(16)));
+    }
+
+    #[test]
+    fn derivation_after_clear_is_an_error() {
+        let mut rng = os_rng().unwrap();
+        let (mut dh, _dh2) = paired_instances(ECPSupportedCurves::Curve25519);
+
+        assert!(dh.derive_shared_bytes(&mut rng).is_ok());
+        dh.clear();
+
+        assert_eq!(dh.derive_shared_bytes(&mut rng), Err(EcError::KeyCleared));
+        assert_eq!(dh.derive_key(&mut rng, b"session key", 32), Err(EcError::KeyCleared));
+    }
+
+    fn ephemeral_exchanges_are_fresh(curve: ECPSupportedCurves) {
+        let mut rng = os_rng().unwrap();
+        let (dh, peer) = paired_instances(curve);
+        let peer_bytes = peer.public_key_bytes(false);
+
+        let (public_1, key_1) = dh.ephemeral_exchange(&peer_bytes, &mut rng, b"session key", 32).unwrap();
+        let (public_2, key_2) = dh.ephemeral_exchange(&peer_bytes, &mut rng, b"session key", 32).unwrap();
+
+        assert_ne!(public_1, public_2);
+        assert_ne!(key_1, key_2);
+
+        // The peer recovers each key from our ephemeral public value alone
+        assert_eq!(peer.derive_key_for_peer(&public_1, &mut rng, b"session key", 32).unwrap(), key_1);
+        assert_eq!(peer.derive_key_for_peer(&public_2, &mut rng, b"session key", 32).unwrap(), key_2);
+
+        // The reused static key is unaffected
+        assert_eq!(
+            dh.derive_key(&mut rng, b"session key", 32).unwrap(),
+            peer.derive_key(&mut rng, b"session key", 32).unwrap()
+        );
+    }
+
+    #[test]
+    fn ephemeral_exchange_weierstrass() {
+        ephemeral_exchanges_are_fresh(ECPSupportedCurves::BP256R1);
+    }
+
+    #[test]
+    fn ephemeral_exchange_montgomery() {
+        ephemeral_exchanges_are_fresh(ECPSupportedCurves::Curve25519);
+    }
+
+    #[test]
+    fn rekey_replaces_keypair_and_keeps_peer() {
+        let mut rng = os_rng().unwrap();
+        let (mut dh, mut peer) = paired_instances(ECPSupportedCurves::Curve25519);
+
+        let old_public = dh.public_key_bytes(false);
+        let old_key = dh.derive_key(&mut rng, b"session key", 32).unwrap();
+
+        dh.rekey(&mut rng).unwrap();
+        let new_public = dh.public_key_bytes(false);
+        assert_ne!(old_public, new_public);
+
+        peer.set_peer_public_key_bytes(&new_public).unwrap();
+        let new_key = dh.derive_key(&mut rng, b"session key", 32).unwrap();
+
+        assert_ne!(old_key, new_key);
+        assert_eq!(new_key, peer.derive_key(&mut rng, b"session key", 32).unwrap());
+    }
+
+    #[test]
+    fn seeded_key_generation_is_reproducible() {
+        for curve in vec![ECPSupportedCurves::BP256R1, ECPSupportedCurves::Curve25519] {
+            let first = ECDH::new(curve.clone(), &mut ChaChaRng::from_seed(&[1, 2, 3, 4])).unwrap();
+            let second = ECDH::new(curve.clone(), &mut ChaChaRng::from_seed(&[1, 2, 3, 4])).unwrap();
+            let other = ECDH::new(curve, &mut ChaChaRng::from_seed(&[4, 3, 2, 1])).unwrap();
+
+            assert_eq!(first.public_key_bytes(false), second.public_key_bytes(false));
+            assert_ne!(first.public_key_bytes(false), other.public_key_bytes(false));
+        }
+    }
+
+    /// Builds an ECDH instance around a known private value
+    fn instance_from_private(curve: ECPSupportedCurves, d: BigUint) -> ECDH {
+        let mut rng = os_rng().unwrap();
+        let mut keypair = ECPKeypair::new(curve);
+
+        keypair.d = d;
+        keypair.q = keypair.multiply(&mut rng);
+
+        ECDH::from_keypair(keypair)
+    }
+
+    /// Runs both sides of a known-answer test, checking the exported public
+    /// values and the exact shared secret bytes against the expected ones
+    fn known_answer(
+        curve: ECPSupportedCurves,
+        d_a: BigUint,
+        public_a: &str,
+        d_b: BigUint,
+        public_b: &str,
+        shared: &str
+    ) {
+        let mut rng = os_rng().unwrap();
+        let mut alice = instance_from_private(curve.clone(), d_a);
+        let mut bob = instance_from_private(curve, d_b);
+
+        assert_eq!(alice.public_key_bytes(false), hex_to_bytes(public_a).unwrap());
+        assert_eq!(bob.public_key_bytes(false), hex_to_bytes(public_b).unwrap());
+
+        alice.set_peer_public_key_bytes(&hex_to_bytes(public_b).unwrap()).unwrap();
+        bob.set_peer_public_key_bytes(&hex_to_bytes(public_a).unwrap()).unwrap();
+
+        assert_eq!(alice.derive_shared_bytes(&mut rng).unwrap(), hex_to_bytes(shared).unwrap());
+        assert_eq!(bob.derive_shared_bytes(&mut rng).unwrap(), hex_to_bytes(shared).unwrap());
+    }
+
+    /// Decodes a big-endian private value from hex
+    fn private_be(hex: &str) -> BigUint {
+        BigUint::from_bytes_be(&hex_to_bytes(hex).unwrap())
+    }
+
+    /// Decodes and clamps an X25519 private value from hex (RFC 7748)
+    fn private_x25519(hex: &str) -> BigUint {
+        let mut bytes = hex_to_bytes(hex).unwrap();
+
+        bytes[0] &= 248;
+        bytes[31] &= 127;
+        bytes[31] |= 64;
+
+        BigUint::from_bytes_le(&bytes)
+    }
+
+    #[test]
+    fn rfc5903_p256() {
+        known_answer(
+            ECPSupportedCurves::SECP256R1,
+            private_be("c88f01f510d9ac3f70a292daa2316de544e9aab8afe84049c62a9c57862d1433"),
+            "04dad0b65394221cf9b051e1feca5787d098dfe637fc90b9ef945d0c3772581180\
+               5271a0461cdb8252d61f1c456fa3e59ab1f45b33accf5f58389e0577b8990bb3",
+            private_be("c6ef9c5d78ae012a011164acb397ce2088685d8f06bf9be0b283ab46476bee53"),
+            "04d12dfb5289c8d4f81208b70270398c342296970a0bccb74c736fc7554494bf63\
+               56fbf3ca366cc23e8157854c13c58d6aac23f046ada30f8353e74f33039872ab",
+            "d6840f6b42f6edafd13116e0e12565202fef8e9ece7dce03812464d04b9442de"
+        );
+    }
+
+    #[test]
+    fn rfc5903_p384() {
+        known_answer(
+            ECPSupportedCurves::SECP384R1,
+            private_be("099f3c7034d4a2c699884d73a375a67f7624ef7c6b3c0f160647b67414dce655\
+                        e35b538041e649ee3faef896783ab194"),
+            "04667842d7d180ac2cde6f74f37551f55755c7645c20ef73e31634fe72b4c55ee6\
+               de3ac808acb4bdb4c88732aee95f41aa\
+               9482ed1fc0eeb9cafc4984625ccfc23f65032149e0e144ada024181535a0f38e\
+               eb9fcff3c2c947dae69b4c634573a81c",
+            private_be("41cb0779b4bdb85d47846725fbec3c9430fab46cc8dc5060855cc9bda0aa2942\
+                        e0308312916b8ed2960e4bd55a7448fc"),
+            "04e558dbef53eecde3d3fccfc1aea08a89a987475d12fd950d83cfa41732bc509d\
+               0d1ac43a0336def96fda41d0774a3571\
+               dcfbec7aacf3196472169e838430367f66eebe3c6e70c416dd5f0c68759dd1ff\
+               f83fa40142209dff5eaad96db9e6386c",
+            "11187331c279962d93d604243fd592cb9d0a926f422e47187521287e7156c5c4\
+             d603135569b9e9d09cf5d4a270f59746"
+        );
+    }
+
+    #[test]
+    fn rfc7748_x25519() {
+        known_answer(
+            ECPSupportedCurves::Curve25519,
+            private_x25519("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a"),
+            "8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a",
+            private_x25519("5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb"),
+            "de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f",
+            "4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742"
+        );
+    }
+
+    /// Fixture generated with OpenSSL 3:
+    ///   openssl genpkey -algorithm EC -pkeyopt ec_paramgen_curve:brainpoolP256r1
+    ///   openssl pkeyutl -derive -inkey a.pem -peerkey b.pub
+    #[test]
+    fn openssl_brainpool_p256r1() {
+        known_answer(
+            ECPSupportedCurves::BP256R1,
+            private_be("4c92293a3f38b68d31561590692c771c8b7948c12509bce7ac836855b64925f1"),
+            "048a2709129c2a8c86f9623213a08b8093a45440f798c190deb9f33c83d8878c60\
+               6b87ec159c680c27f6ce5642aa8c7f9011d146b5d74b7290de93b127c1ac47e0",
+            private_be("0fdfc3b78fb0b33aa9435b114032b4d0df1609318f0f74f3dd10ce1c53840839"),
+            "0488ec6c2fd5e85dce716d6fea2cc8fcd8de6a6ea68a7a051b10cb777e5102aa15\
+               8b41336a3db56fd9386fe24559c7c70a46092f13f1ae3290af7d75ba4e41047d",
+            "73242241dbe98bc32522b423cc4b4202d4805a5f0d74ff41601b2cefd3d6a74c"
+        );
     }
 }
\ No newline at end of file
@@ -0,0 +1,294 @@
+use std::fmt;
+
+use crate::hash::crypto::HashAlgorithm;
+use crate::hash::kdf::{ hkdf_extract, hkdf_expand, KdfError };
+use crate::key_exchange::KeyExchange;
+use crate::utils::rng::CryptoRngCore;
+use crate::utils::zeroize::zeroize_bytes;
+
+/*---- CONSTANTS ----*/
+
+/// Length in bytes of the big-endian length prefix in front of each
+/// component's public value
+const LENGTH_PREFIX: usize = 2;
+
+/// Hash algorithm used by HKDF when combining the component secrets
+const KDF_HASH_ALGORITHM: HashAlgorithm = HashAlgorithm::Sha3_256;
+
+
+/*---- ENUMS ----*/
+
+/// Errors raised while running a hybrid exchange
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum HybridError<E1, E2> {
+    First(E1),                  // the first component exchange failed
+    Second(E2),                 // the second component exchange failed
+    MalformedMessage,           // the peer message could not be split into its components
+    KeyDerivation(KdfError)     // the combined key could not be derived
+}
+
+impl<E1: fmt::Display, E2: fmt::Display> fmt::Display for HybridError<E1, E2> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HybridError::First(e) => write!(f, "First component exchange failed: {}", e),
+            HybridError::Second(e) => write!(f, "Second component exchange failed: {}", e),
+            HybridError::MalformedMessage => write!(f, "Hybrid public value is malformed"),
+            HybridError::KeyDerivation(e) => write!(f, "Key derivation failed: {}", e)
+        }
+    }
+}
+
+impl<E1, E2> From<KdfError> for HybridError<E1, E2> {
+    fn from(error: KdfError) -> Self {
+        HybridError::KeyDerivation(error)
+    }
+}
+
+
+/*---- STRUCTS ----*/
+
+/// Runs two key exchanges side by side and combines their secrets, so
+/// that the session key stays secret as long as either component holds.
+/// The intended pairing is a classical exchange such as ECDH with a
+/// post-quantum KEM, but any two `KeyExchange` implementations can be
+/// combined
+pub struct Hybrid<K1, K2> {
+    first: K1,      // first component exchange
+    second: K2      // second component exchange
+}
+
+impl<K1: KeyExchange, K2: KeyExchange> Hybrid<K1, K2> {
+
+    /// Combines two key exchanges. Both peers must use the same component
+    /// types in the same order
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `first` - First component exchange
+    /// * `second` - Second component exchange
+    pub fn new(first: K1, second: K2) -> Self {
+        Hybrid {
+            first: first,
+            second: second
+        }
+    }
+
+    /// Exports both public values as a single wire message. Each value is
+    /// preceded by its length as a 2 byte big-endian integer
+    pub fn public_bytes(&self) -> Vec<u8> {
+        let first = self.first.public_bytes();
+        let second = self.second.public_bytes();
+
+        let mut message = Vec::with_capacity(2 * LENGTH_PREFIX + first.len() + second.len());
+        push_length_prefixed(&mut message, &first);
+        push_length_prefixed(&mut message, &second);
+
+        message
+    }
+
+    /// Runs both exchanges against the peer's wire message and derives
+    /// key material by applying HKDF-extract over the concatenation of
+    /// the two shared secrets, followed by HKDF-expand
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `peer_message` - Peer wire message from its `public_bytes`
+    /// * `rng` - Random number generator
+    /// * `info` - Context information binding the key to its purpose
+    /// * `len` - Length of the derived key in bytes
+    pub fn derive_key(
+        &self,
+        peer_message: &[u8],
+        rng: &mut impl CryptoRngCore,
+        info: &[u8],
+        len: usize
+    ) -> Result<Vec<u8>, HybridError<K1::Error, K2::Error>>
+    {
+        let (peer_first, rest) = split_length_prefixed(peer_message)?;
+        let (peer_second, rest) = split_length_prefixed(rest)?;
+
+        if !rest.is_empty() {
+            return Err(HybridError::MalformedMessage);
+        }
+
+        let mut secrets = match self.first.shared_secret(peer_first, rng) {
+            Ok(secret) => secret,
+            Err(e) => return Err(HybridError::First(e))
+        };
+
+        let mut second_secret = match self.second.shared_secret(peer_second, rng) {
+            Ok(secret) => secret,
+            Err(e) => {
+                zeroize_bytes(&mut secrets);
+                return Err(HybridError::Second(e));
+            }
+        };
+
+        secrets.extend_from_slice(&second_secret);
+        zeroize_bytes(&mut second_secret);
+
+        let mut prk = hkdf_extract(&secrets, &[], KDF_HASH_ALGORITHM);
+        zeroize_bytes(&mut secrets);
+
+        let key = hkdf_expand(&prk, info, len, KDF_HASH_ALGORITHM);
+        zeroize_bytes(&mut prk);
+
+        Ok(key?)
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Appends a value to the message, preceded by its 2 byte length
+/// 
+/// ### Arguments
+/// 
+/// * `message` - Message to append to
+/// * `value` - Value to append
+fn push_length_prefixed(message: &mut Vec<u8>, value: &[u8]) -> () {
+    assert!(value.len() <= u16::max_value() as usize, "Public value is too long to encode");
+
+    message.push((value.len() >> 8) as u8);
+    message.push(value.len() as u8);
+    message.extend_from_slice(value);
+}
+
+/// Splits a length-prefixed value from the front of the message,
+/// returning the value and the remainder
+/// 
+/// ### Arguments
+/// 
+/// * `message` - Message to split
+fn split_length_prefixed<E1, E2>(message: &[u8]) -> Result<(&[u8], &[u8]), HybridError<E1, E2>> {
+    if message.len() < LENGTH_PREFIX {
+        return Err(HybridError::MalformedMessage);
+    }
+
+    let length = ((message[0] as usize) << 8) | message[1] as usize;
+    let rest = &message[LENGTH_PREFIX..];
+
+    if rest.len() < length {
+        return Err(HybridError::MalformedMessage);
+    }
+
+    Ok(rest.split_at(length))
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(all(test, feature = "ecc"))]
+mod hybrid_test {
+
+    use rand::{ ChaChaRng, SeedableRng };
+    use crate::key_exchange::ecdh::ECDH;
+    use crate::key_exchange::KeyExchange;
+    use crate::key_exchange::hybrid::{ Hybrid, HybridError };
+    use crate::utils::ecc::EcError;
+    use crate::utils::rng::{ CryptoRngCore, os_rng };
+    use crate::utils::ecc_curves::ECPSupportedCurves;
+
+    // A post-quantum KEM is not available yet, so the tests pair two
+    // classical exchanges on different curves
+    fn party(rng: &mut impl CryptoRngCore) -> Hybrid<ECDH, ECDH> {
+        Hybrid::new(
+            ECDH::new(ECPSupportedCurves::Curve25519, rng).unwrap(),
+            ECDH::new(ECPSupportedCurves::BP256R1, rng).unwrap()
+        )
+    }
+
+    #[test]
+    fn both_sides_agree() {
+        let mut rng = os_rng().unwrap();
+        let alice = party(&mut rng);
+        let bob = party(&mut rng);
+
+        let alice_key = alice.derive_key(&bob.public_bytes(), &mut rng, b"session key", 32).unwrap();
+        let bob_key = bob.derive_key(&alice.public_bytes(), &mut rng, b"session key", 32).unwrap();
+
+        assert_eq!(alice_key.len(), 32);
+        assert_eq!(alice_key, bob_key);
+    }
+
+    #[test]
+    fn corrupted_component_breaks_agreement() {
+        let mut rng = os_rng().unwrap();
+        let alice = party(&mut rng);
+        let bob = party(&mut rng);
+        let message = alice.public_bytes();
+
+        // The Curve25519 value starts after its prefix, the BP256R1 one
+        // after the 32 byte u-coordinate and the second prefix
+        let mut first_corrupted = message.clone();
+        first_corrupted[2] ^= 1;
+
+        let mut second_corrupted = message.clone();
+        second_corrupted[2 + 32 + 2 + 40] ^= 1;
+
+        let alice_key = alice.derive_key(&bob.public_bytes(), &mut rng, b"session key", 32).unwrap();
+
+        // Curve25519 accepts any u-coordinate, so the first component may
+        // either fail or silently produce a different secret
+        match bob.derive_key(&first_corrupted, &mut rng, b"session key", 32) {
+            Ok(key) => assert_ne!(key, alice_key),
+            Err(HybridError::First(_)) => (),
+            Err(other) => panic!("Expected the first component to fail, got {:?}", other)
+        }
+
+        // A flipped coordinate bit moves the point off the curve
+        match bob.derive_key(&second_corrupted, &mut rng, b"session key", 32) {
+            Err(HybridError::Second(EcError::InvalidPublicKey(_))) => (),
+            other => panic!("Expected the second component to fail, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn combined_key_differs_from_components() {
+        let mut rng = os_rng().unwrap();
+        let alice_first = ECDH::new(ECPSupportedCurves::Curve25519, &mut rng).unwrap();
+        let alice_second = ECDH::new(ECPSupportedCurves::BP256R1, &mut rng).unwrap();
+        let bob = party(&mut rng);
+
+        let bob_message = bob.public_bytes();
+        let bob_first = &bob_message[2..34];
+        let bob_second = &bob_message[36..];
+
+        let first_key = alice_first.derive_key_for_peer(bob_first, &mut rng, b"session key", 32).unwrap();
+        let second_key = alice_second.derive_key_for_peer(bob_second, &mut rng, b"session key", 32).unwrap();
+        assert_eq!(alice_first.shared_secret(bob_first, &mut rng).unwrap().len(), 32);
+
+        let alice = Hybrid::new(alice_first, alice_second);
+        let hybrid_key = alice.derive_key(&bob_message, &mut rng, b"session key", 32).unwrap();
+
+        assert_ne!(hybrid_key, first_key);
+        assert_ne!(hybrid_key, second_key);
+    }
+
+    #[test]
+    fn malformed_messages_are_rejected() {
+        let mut rng = os_rng().unwrap();
+        let alice = party(&mut rng);
+        let message = party(&mut rng).public_bytes();
+
+        let malformed = vec![
+            vec![],
+            message[..1].to_vec(),
+            message[..message.len() - 1].to_vec(),
+            [&message[..], &[0]].concat()
+        ];
+
+        for bytes in malformed.iter() {
+            assert_eq!(alice.derive_key(bytes, &mut rng, b"session key", 32), Err(HybridError::MalformedMessage));
+        }
+    }
+
+    #[test]
+    fn seeded_parties_are_reproducible() {
+        let first = party(&mut ChaChaRng::from_seed(&[9, 9, 9, 9]));
+        let second = party(&mut ChaChaRng::from_seed(&[9, 9, 9, 9]));
+
+        assert_eq!(first.public_bytes(), second.public_bytes());
+    }
+
+}
\ No newline at end of file
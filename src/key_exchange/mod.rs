@@ -1,2 +1,22 @@
+use crate::utils::rng::CryptoRngCore;
+
+#[cfg(feature = "dh")]
 pub mod diffie_hellman;
-pub mod ecdh;
\ No newline at end of file
+#[cfg(feature = "ecc")]
+pub mod ecdh;
+#[cfg(feature = "ecc")]
+pub mod x25519;
+#[cfg(feature = "hashes-sha3")]
+pub mod hybrid;
+
+#[cfg(feature = "ecc")]
+pub use self::x25519::x25519;
+
+/// A key exchange whose public value and shared secret travel as bytes,
+/// so that different schemes can be composed
+pub trait KeyExchange {
+    type Error;
+
+    fn public_bytes(&self) -> Vec<u8>;
+    fn shared_secret(&self, peer_bytes: &[u8], rng: &mut impl CryptoRngCore) -> Result<Vec<u8>, Self::Error>;
+}
\ No newline at end of file
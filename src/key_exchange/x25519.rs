@@ -0,0 +1,172 @@
+use num_bigint::{ BigUint, ToBigInt, ToBigUint };
+
+use crate::utils::ct;
+use crate::utils::montgomery_ladder;
+use crate::utils::rng::CryptoRngCore;
+use crate::utils::ecc::EcError;
+use crate::utils::encoding::{ EndianOrdering, biguint_to_fixed_bytes };
+use crate::utils::ecc_curves::{ ECPGroup, ECPPoint, ECPSupportedCurves };
+
+
+/*---- CONSTANTS ----*/
+
+/// Length in bytes of X25519 scalars, u-coordinates and outputs
+pub const X25519_LENGTH: usize = 32;
+
+/// u-coordinate of the Curve25519 base point (u = 9)
+pub const X25519_BASE_POINT: [u8; X25519_LENGTH] = [
+    9, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+];
+
+
+/*---- FUNCTIONS ----*/
+
+/// The X25519 function as specified in RFC 7748, section 5. Scalars
+/// are decoded from 32 little-endian bytes and clamped, u-coordinates
+/// have their most significant bit masked, and the result is encoded
+/// as 32 little-endian bytes. An all-zero output means the peer sent a
+/// low order point and is rejected, as suggested in section 6.1.
+/// The generator only randomizes the ladder's projective coordinates
+/// and has no influence on the output.
+/// 
+/// ### Arguments
+/// 
+/// * `scalar` - Little-endian scalar
+/// * `u` - Little-endian u-coordinate of the peer point
+/// * `rng` - Random number generator
+pub fn x25519(
+    scalar: &[u8; X25519_LENGTH],
+    u: &[u8; X25519_LENGTH],
+    rng: &mut impl CryptoRngCore
+) -> Result<[u8; X25519_LENGTH], EcError>
+{
+    let group = ECPGroup::new(ECPSupportedCurves::Curve25519);
+    let k = decode_scalar(scalar);
+    let u = decode_u_coordinate(&group, u);
+
+    let point = ECPPoint::new( &u.to_bigint().unwrap(), None );
+    let result = montgomery_ladder::multiply(&group, &k, &point, rng);
+
+    let result_x = match result.x.to_biguint() {
+        Some(x) => x,
+        None => return Err(EcError::InvalidEncoding)
+    };
+
+    let encoded = match biguint_to_fixed_bytes(&result_x, X25519_LENGTH, EndianOrdering::Little) {
+        Some(bytes) => bytes,
+        None => return Err(EcError::InvalidEncoding)
+    };
+
+    // Check for the all-zero value without branching on individual bytes
+    if ct::ct_eq(&encoded, &[0; X25519_LENGTH]) {
+        return Err(EcError::LowOrderPoint);
+    }
+
+    let mut output = [0; X25519_LENGTH];
+    output.copy_from_slice(&encoded);
+
+    Ok(output)
+}
+
+/// Decodes and clamps a scalar: the three least significant bits are
+/// cleared, bit 255 is cleared and bit 254 is set
+/// 
+/// ### Arguments
+/// 
+/// * `scalar` - Little-endian scalar
+fn decode_scalar(scalar: &[u8; X25519_LENGTH]) -> BigUint {
+    let mut clamped = scalar.clone();
+
+    clamped[0] &= 248;
+    clamped[31] &= 127;
+    clamped[31] |= 64;
+
+    BigUint::from_bytes_le(&clamped)
+}
+
+/// Decodes a u-coordinate, masking the most significant bit and
+/// reducing non-canonical values modulo p
+/// 
+/// ### Arguments
+/// 
+/// * `group` - Curve25519 group
+/// * `u` - Little-endian u-coordinate
+fn decode_u_coordinate(group: &ECPGroup, u: &[u8; X25519_LENGTH]) -> BigUint {
+    let mut masked = u.clone();
+    masked[31] &= 127;
+
+    BigUint::from_bytes_le(&masked) % group.p.to_biguint().unwrap()
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod x25519_test {
+
+    use crate::utils::encoding::hex_to_bytes;
+    use crate::utils::ecc::EcError;
+    use crate::utils::rng::os_rng;
+    use crate::key_exchange::x25519::{ x25519, X25519_BASE_POINT, X25519_LENGTH };
+
+    fn to_array(hex: &str) -> [u8; X25519_LENGTH] {
+        let mut output = [0; X25519_LENGTH];
+        output.copy_from_slice(&hex_to_bytes(hex).unwrap());
+
+        output
+    }
+
+    #[test]
+    fn rfc7748_vector_1() {
+        let scalar = to_array("a546e36bf0527c9d3b16154b82465edd62144c0ac1fc5a18506a2244ba449ac4");
+        let u = to_array("e6db6867583030db3594c1a424b15f7c726624ec26b3353b10a903a6d0ab1c4c");
+        let expected = to_array("c3da55379de9c6908e94ea4df28d084f32eccf03491c71f754b4075577a28552");
+
+        assert_eq!(x25519(&scalar, &u, &mut os_rng().unwrap()).unwrap(), expected);
+    }
+
+    #[test]
+    fn rfc7748_vector_2() {
+        let scalar = to_array("4b66e9d4d1b4673c5ad22691957d6af5c11b6421e0ea01d42ca4169e7918ba0d");
+        let u = to_array("e5210f12786811d3f4b7959d0538ae2c31dbe7106fc03c3efc4cd549c715a493");
+        let expected = to_array("95cbde9476e8907d7aade45cb4b873f88b595a68799fa152e6f8f7647aac7957");
+
+        assert_eq!(x25519(&scalar, &u, &mut os_rng().unwrap()).unwrap(), expected);
+    }
+
+    #[test]
+    fn rfc7748_iterated() {
+        let mut k = X25519_BASE_POINT;
+        let mut u = X25519_BASE_POINT;
+        let mut rng = os_rng().unwrap();
+
+        for i in 0..1000 {
+            let result = x25519(&k, &u, &mut rng).unwrap();
+            u = k;
+            k = result;
+
+            if i == 0 {
+                assert_eq!(k, to_array("422c8e7a6227d7bca1350b3e2bb7279f7897b87bb6854b783c60e80311ae3079"));
+            }
+        }
+
+        assert_eq!(k, to_array("684cf59ba83309552800ef566f2f4d3c1c3887c49360e3875f2eb94d99532c51"));
+    }
+
+    #[test]
+    fn low_order_points_rejected() {
+        let scalar = to_array("a546e36bf0527c9d3b16154b82465edd62144c0ac1fc5a18506a2244ba449ac4");
+        let low_order = [
+            "0000000000000000000000000000000000000000000000000000000000000000",
+            "0100000000000000000000000000000000000000000000000000000000000000",
+            "e0eb7a7c3b41b8ae1656e3faf19fc46ada098deb9c32b1fd866205165f49b800"
+        ];
+        let mut rng = os_rng().unwrap();
+
+        for u in low_order.iter() {
+            assert_eq!(x25519(&scalar, &to_array(u), &mut rng), Err(EcError::LowOrderPoint));
+        }
+    }
+
+}
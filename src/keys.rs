@@ -0,0 +1,913 @@
+//! Versioned container for storing keys of every algorithm in the crate.
+//!
+//! A container is the magic "CFKY", a version byte and a list of records,
+//! each a one byte tag, a four byte big-endian length and the value:
+//!
+//! * algorithm - two byte algorithm id (see `KeyAlgorithm`)
+//! * visibility - 0 for a public key, 1 for a private key
+//! * label - UTF-8 name of the key, optional and version 2 only
+//! * created - creation time as big-endian Unix seconds, optional and version 2 only
+//! * encryption - salt || PBKDF2 iterations || nonce, when a passphrase is used
+//! * payload - algorithm specific key values, always the last record
+//!
+//! When a passphrase is given the payload is sealed with AES-256-GCM under
+//! a PBKDF2 key, with the header, every byte before the payload record,
+//! authenticated as additional data. The algorithm, visibility and
+//! metadata of a sealed key can therefore not be swapped without the
+//! passphrase. Version 1 containers carry no metadata and are still read,
+//! flagged as legacy. Records with unknown tags are skipped, and keys
+//! with an unknown algorithm id are handed back as `KeyKind::Unknown`, so
+//! older readers can still inspect containers written by newer versions
+
+use std::fmt;
+use rand::Rng;
+use num_bigint::BigUint;
+use crypto::aes::KeySize;
+use crypto::aes_gcm::AesGcm;
+use crypto::aead::{ AeadEncryptor, AeadDecryptor };
+
+use crate::error::Error;
+use crate::hash::kdf::pbkdf2;
+use crate::hash::crypto::HashAlgorithm;
+use crate::encryption::rsa::RSA;
+use crate::key_exchange::diffie_hellman::DiffieHellman;
+use crate::utils::ecc::ECPKeypair;
+use crate::utils::rng::CryptoRngCore;
+use crate::utils::zeroize::zeroize_bytes;
+use crate::utils::ecc_curves::ECPSupportedCurves;
+
+
+/*---- CONSTANTS ----*/
+
+/// Leading bytes of every container
+pub const MAGIC: &'static [u8] = b"CFKY";
+
+/// Version of the container layout written by `save`
+pub const VERSION: u8 = 2;
+
+/// Earlier layout without metadata, still accepted by `load`
+pub const LEGACY_VERSION: u8 = 1;
+
+/// PBKDF2 iteration count used when sealing with a passphrase
+pub const PBKDF2_ITERATIONS: u32 = 100_000;
+
+/// Highest PBKDF2 iteration count accepted when reading, so that a
+/// crafted container can't stall `load` in key derivation
+pub const MAX_PBKDF2_ITERATIONS: u32 = 10 * PBKDF2_ITERATIONS;
+
+/// Record tags
+const TAG_ALGORITHM: u8 = 1;
+const TAG_VISIBILITY: u8 = 2;
+const TAG_ENCRYPTION: u8 = 3;
+const TAG_PAYLOAD: u8 = 4;
+const TAG_LABEL: u8 = 5;
+const TAG_CREATED: u8 = 6;
+
+/// Algorithm ids. ECC ids are the base plus the curve index, and
+/// 0x0300 is reserved for Ring-LWE parameter sets
+const ID_RSA: u16 = 0x0001;
+const ID_ECC_BASE: u16 = 0x0100;
+const ID_DIFFIE_HELLMAN: u16 = 0x0200;
+
+/// Lengths in bytes of the values in the encryption record
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+const TAG_LENGTH: usize = 16;
+const KEY_LENGTH: usize = 32;
+
+/// Hash algorithm used by PBKDF2
+const KDF_HASH_ALGORITHM: HashAlgorithm = HashAlgorithm::Sha3_256;
+
+
+/*---- ENUMS ----*/
+
+/// Errors raised while writing or reading a key container
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum KeyError {
+    BadMagic,                       // input does not start with the container magic
+    UnsupportedVersion(u8),         // container was written by an unknown format version
+    Malformed(&'static str),        // records or payload could not be parsed
+    PassphraseRequired,             // payload is sealed but no passphrase was given
+    WrongPassphrase,                // payload failed authentication under the passphrase
+    InvalidKey(&'static str)        // key values failed validation
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyError::BadMagic => write!(f, "Input is not a key container"),
+            KeyError::UnsupportedVersion(v) => write!(f, "Unsupported key container version {}", v),
+            KeyError::Malformed(reason) => write!(f, "Malformed key container: {}", reason),
+            KeyError::PassphraseRequired => write!(f, "Key is encrypted and needs a passphrase"),
+            KeyError::WrongPassphrase => write!(f, "Passphrase is wrong or the key was altered"),
+            KeyError::InvalidKey(reason) => write!(f, "Invalid key: {}", reason)
+        }
+    }
+}
+
+/// Algorithm a stored key belongs to
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyAlgorithm {
+    Rsa,
+    Ecc(ECPSupportedCurves),
+    DiffieHellman,
+    Unknown(u16)        // id not known to this version of the crate
+}
+
+/// A key read back from a container
+pub enum KeyKind {
+    Rsa(RSA),
+    Ecc(ECPKeypair),
+    DiffieHellman(DiffieHellman),
+    Unknown {
+        algorithm: u16,     // id of the unrecognised algorithm
+        private: bool,      // visibility flag of the container
+        payload: Vec<u8>    // payload, decrypted if a passphrase was used
+    }
+}
+
+
+/*---- STRUCTS ----*/
+
+/// Metadata stored in the header of a container. When the key is sealed
+/// it is authenticated along with the rest of the header
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct KeyMetadata {
+    pub label: Option<String>,      // name of the key, e.g. the identity it belongs to
+    pub created: Option<u64>        // creation time in seconds since the Unix epoch
+}
+
+/// A key read back from a container along with its header
+pub struct LoadedKey {
+    pub key: KeyKind,               // the key itself
+    pub metadata: KeyMetadata,      // metadata from the header, empty for legacy containers
+    pub legacy: bool                // container uses the version 1 layout and should be saved again
+}
+
+
+/*---- TRAITS ----*/
+
+/// A key that can be written to and read from a container. Only the
+/// algorithm specific payload is produced here; the framing and the
+/// optional encryption are handled by `save` and `load`
+pub trait ExportableKey {
+
+    /// Algorithm the key belongs to
+    fn key_algorithm(&self) -> KeyAlgorithm;
+
+    /// Whether the payload holds the private values
+    fn has_private_key(&self) -> bool;
+
+    /// Algorithm specific key values
+    fn export_payload(&self) -> Result<Vec<u8>, KeyError>;
+
+    /// Payload of the public half of the key, the same whether or not
+    /// the private values are held
+    fn export_public_payload(&self) -> Result<Vec<u8>, KeyError>;
+
+    /// Rebuilds and validates a key from its payload
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `algorithm` - Algorithm from the container
+    /// * `private` - Whether the payload holds the private values
+    /// * `payload` - Algorithm specific key values
+    fn import_payload(algorithm: &KeyAlgorithm, private: bool, payload: &[u8]) -> Result<Self, KeyError> where Self: Sized;
+}
+
+
+/*---- IMPLEMENTATIONS ----*/
+
+impl KeyAlgorithm {
+
+    /// Id written to the algorithm record
+    pub fn id(&self) -> u16 {
+        match self {
+            KeyAlgorithm::Rsa => ID_RSA,
+            KeyAlgorithm::Ecc(curve) => ID_ECC_BASE + match curve {
+                ECPSupportedCurves::SECP256R1 => 1,
+                ECPSupportedCurves::SECP384R1 => 2,
+                ECPSupportedCurves::SECP521R1 => 3,
+                ECPSupportedCurves::BP256R1 => 4,
+                ECPSupportedCurves::BP384R1 => 5,
+                ECPSupportedCurves::BP521R1 => 6,
+                ECPSupportedCurves::Curve25519 => 7
+            },
+            KeyAlgorithm::DiffieHellman => ID_DIFFIE_HELLMAN,
+            KeyAlgorithm::Unknown(id) => *id
+        }
+    }
+
+    /// Algorithm for an id read from a container
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `id` - Algorithm id
+    pub fn from_id(id: u16) -> Self {
+        match id {
+            ID_RSA => KeyAlgorithm::Rsa,
+            0x0101 => KeyAlgorithm::Ecc(ECPSupportedCurves::SECP256R1),
+            0x0102 => KeyAlgorithm::Ecc(ECPSupportedCurves::SECP384R1),
+            0x0103 => KeyAlgorithm::Ecc(ECPSupportedCurves::SECP521R1),
+            0x0104 => KeyAlgorithm::Ecc(ECPSupportedCurves::BP256R1),
+            0x0105 => KeyAlgorithm::Ecc(ECPSupportedCurves::BP384R1),
+            0x0106 => KeyAlgorithm::Ecc(ECPSupportedCurves::BP521R1),
+            0x0107 => KeyAlgorithm::Ecc(ECPSupportedCurves::Curve25519),
+            ID_DIFFIE_HELLMAN => KeyAlgorithm::DiffieHellman,
+            _ => KeyAlgorithm::Unknown(id)
+        }
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Writes a key to a container, sealing the payload under the passphrase
+/// if one is given. Passphrases are meant for private keys, but public
+/// keys are sealed too when one is supplied
+/// 
+/// ### Arguments
+/// 
+/// * `key` - Key to store
+/// * `passphrase` - Optional passphrase to encrypt the payload with
+/// * `rng` - Random number generator for the salt and nonce
+pub fn save(key: &dyn ExportableKey, passphrase: Option<&[u8]>, rng: &mut impl CryptoRngCore) -> Result<Vec<u8>, Error> {
+    save_with_metadata(key, &KeyMetadata::default(), passphrase, rng)
+}
+
+/// Writes a key to a container along with its metadata. When a
+/// passphrase is given the metadata is bound to the sealed payload, so
+/// altering it makes the container fail to open
+/// 
+/// ### Arguments
+/// 
+/// * `key` - Key to store
+/// * `metadata` - Label and creation time to store in the header
+/// * `passphrase` - Optional passphrase to encrypt the payload with
+/// * `rng` - Random number generator for the salt and nonce
+pub fn save_with_metadata(
+    key: &dyn ExportableKey,
+    metadata: &KeyMetadata,
+    passphrase: Option<&[u8]>,
+    rng: &mut impl CryptoRngCore
+) -> Result<Vec<u8>, Error>
+{
+    write_container(key, metadata, VERSION, passphrase, rng)
+}
+
+/// Canonical encoding of the public half of a key: the version 1
+/// container for the public key without a passphrase. A keypair and the
+/// public key split off from it have the same encoding, and it stays on
+/// the version 1 layout so fingerprints don't change with the container
+/// 
+/// ### Arguments
+/// 
+/// * `key` - Key to encode
+pub fn public_encoding(key: &dyn ExportableKey) -> Result<Vec<u8>, KeyError> {
+    let mut output = MAGIC.to_vec();
+    output.push(LEGACY_VERSION);
+
+    write_record(&mut output, TAG_ALGORITHM, &key.key_algorithm().id().to_be_bytes());
+    write_record(&mut output, TAG_VISIBILITY, &[0]);
+    write_record(&mut output, TAG_PAYLOAD, &key.export_public_payload()?);
+
+    Ok(output)
+}
+
+/// Reads a key from a container written by `save`. A passphrase is only
+/// needed if the payload was sealed
+/// 
+/// ### Arguments
+/// 
+/// * `bytes` - Container to read
+/// * `passphrase` - Passphrase the payload was sealed under, if any
+pub fn load(bytes: &[u8], passphrase: Option<&[u8]>) -> Result<KeyKind, Error> {
+    Ok(load_with_metadata(bytes, passphrase)?.key)
+}
+
+/// Reads a key from a container along with its metadata. Version 1
+/// containers are accepted and flagged as legacy
+/// 
+/// ### Arguments
+/// 
+/// * `bytes` - Container to read
+/// * `passphrase` - Passphrase the payload was sealed under, if any
+pub fn load_with_metadata(bytes: &[u8], passphrase: Option<&[u8]>) -> Result<LoadedKey, Error> {
+    if bytes.len() < MAGIC.len() + 1 || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(KeyError::BadMagic.into());
+    }
+
+    let version = bytes[MAGIC.len()];
+
+    if version != VERSION && version != LEGACY_VERSION {
+        return Err(KeyError::UnsupportedVersion(version).into());
+    }
+
+    let mut algorithm = None;
+    let mut visibility = None;
+    let mut label = None;
+    let mut created = None;
+    let mut encryption = None;
+    let mut payload = None;
+    let mut offset = MAGIC.len() + 1;
+
+    while offset < bytes.len() {
+        if payload.is_some() {
+            return Err(KeyError::Malformed("Records found after the payload").into());
+        }
+
+        let record_start = offset;
+        let (tag, value) = read_record(bytes, &mut offset)?;
+
+        let slot = match tag {
+            TAG_ALGORITHM => &mut algorithm,
+            TAG_VISIBILITY => &mut visibility,
+            TAG_LABEL if version == VERSION => &mut label,
+            TAG_CREATED if version == VERSION => &mut created,
+            TAG_ENCRYPTION => &mut encryption,
+            TAG_PAYLOAD => &mut payload,
+            _ => continue
+        };
+
+        if slot.is_some() {
+            return Err(KeyError::Malformed("Duplicate record").into());
+        }
+
+        *slot = Some((record_start, value));
+    }
+
+    let algorithm = match algorithm {
+        Some((_, value)) if value.len() == 2 => KeyAlgorithm::from_id(u16::from_be_bytes([value[0], value[1]])),
+        _ => return Err(KeyError::Malformed("Missing or invalid algorithm record").into())
+    };
+
+    let private = match visibility {
+        Some((_, [0])) => false,
+        Some((_, [1])) => true,
+        _ => return Err(KeyError::Malformed("Missing or invalid visibility record").into())
+    };
+
+    let label = match label {
+        Some((_, value)) => match String::from_utf8(value.to_vec()) {
+            Ok(label) => Some(label),
+            Err(_) => return Err(KeyError::Malformed("Label is not valid UTF-8").into())
+        },
+        None => None
+    };
+
+    let created = match created {
+        Some((_, value)) if value.len() == 8 => {
+            let mut seconds = [0; 8];
+            seconds.copy_from_slice(value);
+            Some(u64::from_be_bytes(seconds))
+        },
+        Some(_) => return Err(KeyError::Malformed("Invalid creation time record").into()),
+        None => None
+    };
+
+    let metadata = KeyMetadata { label: label, created: created };
+    let legacy = version == LEGACY_VERSION;
+
+    let (payload_start, payload) = match payload {
+        Some(record) => record,
+        None => return Err(KeyError::Malformed("Missing payload record").into())
+    };
+
+    let mut payload = match encryption {
+        Some((_, parameters)) => {
+            let passphrase = passphrase.ok_or(KeyError::PassphraseRequired)?;
+            open_payload(passphrase, parameters, &bytes[..payload_start], payload)?
+        },
+        None => payload.to_vec()
+    };
+
+    let key = match algorithm {
+        KeyAlgorithm::Rsa => RSA::import_payload(&algorithm, private, &payload).map(KeyKind::Rsa),
+        KeyAlgorithm::Ecc(_) => ECPKeypair::import_payload(&algorithm, private, &payload).map(KeyKind::Ecc),
+        KeyAlgorithm::DiffieHellman => DiffieHellman::import_payload(&algorithm, private, &payload).map(KeyKind::DiffieHellman),
+        KeyAlgorithm::Unknown(id) => {
+            let key = KeyKind::Unknown { algorithm: id, private: private, payload: payload };
+            return Ok(LoadedKey { key: key, metadata: metadata, legacy: legacy });
+        }
+    };
+
+    zeroize_bytes(&mut payload);
+    Ok(LoadedKey { key: key?, metadata: metadata, legacy: legacy })
+}
+
+/// Appends a length-prefixed field to a payload
+/// 
+/// ### Arguments
+/// 
+/// * `output` - Payload being built
+/// * `value` - Field value
+pub(crate) fn write_field(output: &mut Vec<u8>, value: &[u8]) -> () {
+    output.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    output.extend_from_slice(value);
+}
+
+/// Appends a big-endian integer field to a payload
+/// 
+/// ### Arguments
+/// 
+/// * `output` - Payload being built
+/// * `value` - Integer to write
+pub(crate) fn write_biguint(output: &mut Vec<u8>, value: &BigUint) -> () {
+    let mut bytes = value.to_bytes_be();
+    write_field(output, &bytes);
+    zeroize_bytes(&mut bytes);
+}
+
+/// Reads the next length-prefixed field, advancing the input past it
+/// 
+/// ### Arguments
+/// 
+/// * `input` - Remaining payload
+pub(crate) fn read_field<'a>(input: &mut &'a [u8]) -> Result<&'a [u8], KeyError> {
+    if input.len() < 4 {
+        return Err(KeyError::Malformed("Truncated payload field"));
+    }
+
+    let length = u32::from_be_bytes([input[0], input[1], input[2], input[3]]) as usize;
+
+    if input.len() - 4 < length {
+        return Err(KeyError::Malformed("Truncated payload field"));
+    }
+
+    let value = &input[4..4 + length];
+    *input = &input[4 + length..];
+
+    Ok(value)
+}
+
+/// Reads the next big-endian integer field
+/// 
+/// ### Arguments
+/// 
+/// * `input` - Remaining payload
+pub(crate) fn read_biguint(input: &mut &[u8]) -> Result<BigUint, KeyError> {
+    Ok(BigUint::from_bytes_be(read_field(input)?))
+}
+
+/// Fails if any payload bytes are left over
+/// 
+/// ### Arguments
+/// 
+/// * `input` - Remaining payload
+pub(crate) fn finish_payload(input: &[u8]) -> Result<(), KeyError> {
+    if !input.is_empty() {
+        return Err(KeyError::Malformed("Trailing bytes in payload"));
+    }
+
+    Ok(())
+}
+
+/// Id byte for a hash algorithm stored alongside a key
+/// 
+/// ### Arguments
+/// 
+/// * `algorithm` - Hash algorithm
+pub(crate) fn hash_algorithm_id(algorithm: &HashAlgorithm) -> u8 {
+    match algorithm {
+        #[cfg(feature = "hashes-blake2")]
+        HashAlgorithm::Blake2b => 1,
+        #[cfg(feature = "hashes-blake2")]
+        HashAlgorithm::Blake2s => 2,
+        HashAlgorithm::Sha3_256 => 3,
+        HashAlgorithm::Sha3_512 => 4,
+        HashAlgorithm::Keccak256 => 5,
+        HashAlgorithm::Keccak512 => 6,
+        #[cfg(feature = "hashes-sha2")]
+        HashAlgorithm::Sha256 => 7
+    }
+}
+
+/// Hash algorithm for an id byte written by `hash_algorithm_id`
+/// 
+/// ### Arguments
+/// 
+/// * `id` - Id byte
+pub(crate) fn hash_algorithm_from_id(id: u8) -> Result<HashAlgorithm, KeyError> {
+    match id {
+        #[cfg(feature = "hashes-blake2")]
+        1 => Ok(HashAlgorithm::Blake2b),
+        #[cfg(feature = "hashes-blake2")]
+        2 => Ok(HashAlgorithm::Blake2s),
+        3 => Ok(HashAlgorithm::Sha3_256),
+        4 => Ok(HashAlgorithm::Sha3_512),
+        5 => Ok(HashAlgorithm::Keccak256),
+        6 => Ok(HashAlgorithm::Keccak512),
+        #[cfg(feature = "hashes-sha2")]
+        7 => Ok(HashAlgorithm::Sha256),
+        _ => Err(KeyError::Malformed("Unknown hash algorithm"))
+    }
+}
+
+/// Writes a container in the given layout. Metadata is only written in
+/// version 2 and later
+/// 
+/// ### Arguments
+/// 
+/// * `key` - Key to store
+/// * `metadata` - Label and creation time to store in the header
+/// * `version` - Container version to write
+/// * `passphrase` - Optional passphrase to encrypt the payload with
+/// * `rng` - Random number generator for the salt and nonce
+fn write_container(
+    key: &dyn ExportableKey,
+    metadata: &KeyMetadata,
+    version: u8,
+    passphrase: Option<&[u8]>,
+    rng: &mut impl CryptoRngCore
+) -> Result<Vec<u8>, Error>
+{
+    let mut payload = key.export_payload()?;
+    let mut output = MAGIC.to_vec();
+    output.push(version);
+
+    write_record(&mut output, TAG_ALGORITHM, &key.key_algorithm().id().to_be_bytes());
+    write_record(&mut output, TAG_VISIBILITY, &[key.has_private_key() as u8]);
+
+    if version >= VERSION {
+        if let Some(label) = &metadata.label {
+            write_record(&mut output, TAG_LABEL, label.as_bytes());
+        }
+
+        if let Some(created) = metadata.created {
+            write_record(&mut output, TAG_CREATED, &created.to_be_bytes());
+        }
+    }
+
+    let passphrase = match passphrase {
+        Some(passphrase) => passphrase,
+        None => {
+            write_record(&mut output, TAG_PAYLOAD, &payload);
+            zeroize_bytes(&mut payload);
+
+            return Ok(output);
+        }
+    };
+
+    let mut salt = [0; SALT_LENGTH];
+    let mut nonce = [0; NONCE_LENGTH];
+    rng.fill_bytes(&mut salt);
+    rng.fill_bytes(&mut nonce);
+
+    let mut parameters = salt.to_vec();
+    parameters.extend_from_slice(&PBKDF2_ITERATIONS.to_be_bytes());
+    parameters.extend_from_slice(&nonce);
+    write_record(&mut output, TAG_ENCRYPTION, &parameters);
+
+    let mut sealing_key = derive_sealing_key(passphrase, &salt, PBKDF2_ITERATIONS)?;
+    let mut sealed = vec![0; payload.len() + TAG_LENGTH];
+
+    // The header written so far, metadata included, is the associated data
+    {
+        let (ciphertext, tag) = sealed.split_at_mut(payload.len());
+        let mut encryptor = AesGcm::new(KeySize::KeySize256, &sealing_key, &nonce, &output);
+        encryptor.encrypt(&payload, ciphertext, tag);
+    }
+
+    write_record(&mut output, TAG_PAYLOAD, &sealed);
+    zeroize_bytes(&mut sealing_key);
+    zeroize_bytes(&mut payload);
+
+    Ok(output)
+}
+
+/// Appends a record to a container
+/// 
+/// ### Arguments
+/// 
+/// * `output` - Container being built
+/// * `tag` - Record tag
+/// * `value` - Record value
+fn write_record(output: &mut Vec<u8>, tag: u8, value: &[u8]) -> () {
+    output.push(tag);
+    write_field(output, value);
+}
+
+/// Reads the record at the offset, advancing the offset past it
+/// 
+/// ### Arguments
+/// 
+/// * `bytes` - Container being read
+/// * `offset` - Position of the record
+fn read_record<'a>(bytes: &'a [u8], offset: &mut usize) -> Result<(u8, &'a [u8]), KeyError> {
+    let tag = bytes[*offset];
+    let mut rest = &bytes[*offset + 1..];
+    let value = read_field(&mut rest)?;
+
+    *offset = bytes.len() - rest.len();
+    Ok((tag, value))
+}
+
+/// Derives the AES key that seals a payload
+/// 
+/// ### Arguments
+/// 
+/// * `passphrase` - Passphrase given by the caller
+/// * `salt` - Salt from the encryption record
+/// * `iterations` - PBKDF2 iteration count
+fn derive_sealing_key(passphrase: &[u8], salt: &[u8], iterations: u32) -> Result<Vec<u8>, KeyError> {
+    match pbkdf2(passphrase, salt, iterations, KEY_LENGTH, KDF_HASH_ALGORITHM) {
+        Ok(key) => Ok(key),
+        Err(_) => Err(KeyError::Malformed("Invalid key derivation parameters"))
+    }
+}
+
+/// Decrypts a sealed payload
+/// 
+/// ### Arguments
+/// 
+/// * `passphrase` - Passphrase given by the caller
+/// * `parameters` - Value of the encryption record
+/// * `aad` - Container header, the bytes before the payload record
+/// * `sealed` - Value of the payload record
+fn open_payload(passphrase: &[u8], parameters: &[u8], aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>, KeyError> {
+    if parameters.len() != SALT_LENGTH + 4 + NONCE_LENGTH {
+        return Err(KeyError::Malformed("Invalid encryption record"));
+    }
+
+    if sealed.len() < TAG_LENGTH {
+        return Err(KeyError::Malformed("Sealed payload is too short"));
+    }
+
+    let (salt, rest) = parameters.split_at(SALT_LENGTH);
+    let (iterations, nonce) = rest.split_at(4);
+    let iterations = u32::from_be_bytes([iterations[0], iterations[1], iterations[2], iterations[3]]);
+
+    if iterations == 0 || iterations > MAX_PBKDF2_ITERATIONS {
+        return Err(KeyError::Malformed("PBKDF2 iteration count is out of range"));
+    }
+
+    let (ciphertext, tag) = sealed.split_at(sealed.len() - TAG_LENGTH);
+
+    let mut sealing_key = derive_sealing_key(passphrase, salt, iterations)?;
+    let mut payload = vec![0; ciphertext.len()];
+    let mut decryptor = AesGcm::new(KeySize::KeySize256, &sealing_key, nonce, aad);
+    let authentic = decryptor.decrypt(ciphertext, &mut payload, tag);
+
+    zeroize_bytes(&mut sealing_key);
+
+    if !authentic {
+        zeroize_bytes(&mut payload);
+        return Err(KeyError::WrongPassphrase);
+    }
+
+    Ok(payload)
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod keys_test {
+
+    use num_bigint::BigUint;
+    use num_traits::FromPrimitive;
+
+    use crate::error::Error;
+    use crate::hash::crypto::HashAlgorithm;
+    use crate::encryption::rsa::RSA;
+    use crate::key_exchange::diffie_hellman::DiffieHellman;
+    use crate::utils::rng::os_rng;
+    use crate::utils::ecc::ECPKeypair;
+    use crate::utils::ecc_curves::ECPSupportedCurves;
+    use crate::keys::{ save, load, save_with_metadata, load_with_metadata, write_container };
+    use crate::keys::{ KeyKind, KeyError, KeyMetadata, MAGIC, VERSION, LEGACY_VERSION, MAX_PBKDF2_ITERATIONS };
+
+    const PASSPHRASE: &'static [u8] = b"correct horse";
+
+    fn rsa_keypair() -> RSA {
+        let mut rng = os_rng().unwrap();
+        let exponent = BigUint::from_u32(65537).unwrap();
+
+        RSA::new(HashAlgorithm::Sha3_256, true).allow_insecure_for_testing().generate_keypair(256, &exponent, &mut rng).unwrap()
+    }
+
+    fn metadata() -> KeyMetadata {
+        KeyMetadata { label: Some("test key".to_string()), created: Some(1_700_000_000) }
+    }
+
+    fn load_error(bytes: &[u8], passphrase: Option<&[u8]>) -> Error {
+        match load(bytes, passphrase) {
+            Err(e) => e,
+            Ok(_) => panic!("Expected the container to be rejected")
+        }
+    }
+
+    #[test]
+    fn rsa_round_trip() {
+        let mut rng = os_rng().unwrap();
+        let rsa = rsa_keypair();
+
+        for passphrase in vec![None, Some(PASSPHRASE)] {
+            match load(&save(&rsa, passphrase, &mut rng).unwrap(), passphrase).unwrap() {
+                KeyKind::Rsa(loaded) => assert_eq!(loaded.allow_insecure_for_testing(), rsa),
+                _ => panic!("Expected an RSA key")
+            }
+        }
+
+        let public = RSA::from_public_key(HashAlgorithm::Sha3_256, &rsa.public_key().unwrap());
+
+        match load(&save(&public, None, &mut rng).unwrap(), None).unwrap() {
+            KeyKind::Rsa(loaded) => assert_eq!(loaded.export_public_values(), rsa.export_public_values()),
+            _ => panic!("Expected an RSA key")
+        }
+    }
+
+    #[test]
+    fn ecc_round_trip() {
+        let mut rng = os_rng().unwrap();
+
+        for curve in vec![ECPSupportedCurves::BP256R1, ECPSupportedCurves::Curve25519] {
+            let keypair = ECPKeypair::new(curve.clone()).setup(&mut rng).unwrap();
+
+            for passphrase in vec![None, Some(PASSPHRASE)] {
+                match load(&save(&keypair, passphrase, &mut rng).unwrap(), passphrase).unwrap() {
+                    KeyKind::Ecc(loaded) => {
+                        assert_eq!(loaded.group.curve_id(), curve);
+                        assert_eq!(loaded.d, keypair.d);
+                        assert_eq!(loaded.q.x, keypair.q.x);
+                    },
+                    _ => panic!("Expected an ECC key")
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn diffie_hellman_round_trip() {
+        let mut rng = os_rng().unwrap();
+        let mut dh = DiffieHellman::new().setup(32, &mut rng).unwrap();
+        let peer = DiffieHellman::new_from_peer(&dh.p, &dh.g, &dh.gx).setup(32, &mut rng).unwrap();
+
+        match load(&save(&dh, Some(PASSPHRASE), &mut rng).unwrap(), Some(PASSPHRASE)).unwrap() {
+            KeyKind::DiffieHellman(mut loaded) => {
+                assert_eq!((&loaded.p, &loaded.g, &loaded.gx), (&dh.p, &dh.g, &dh.gx));
+                assert_eq!(
+                    loaded.generate_shared_key(&mut rng, &peer.gx).unwrap(),
+                    dh.generate_shared_key(&mut rng, &peer.gx).unwrap()
+                );
+            },
+            _ => panic!("Expected a Diffie-Hellman key")
+        }
+    }
+
+    #[test]
+    fn wrong_passphrase_is_rejected() {
+        let mut rng = os_rng().unwrap();
+        let keypair = ECPKeypair::new(ECPSupportedCurves::Curve25519).setup(&mut rng).unwrap();
+        let container = save(&keypair, Some(PASSPHRASE), &mut rng).unwrap();
+
+        match load_error(&container, Some(&b"battery staple"[..])) {
+            Error::Key(KeyError::WrongPassphrase) => (),
+            other => panic!("Unexpected error {:?}", other)
+        }
+
+        match load_error(&container, None) {
+            Error::Key(KeyError::PassphraseRequired) => (),
+            other => panic!("Unexpected error {:?}", other)
+        }
+    }
+
+    #[test]
+    fn corrupted_containers_are_rejected() {
+        let mut rng = os_rng().unwrap();
+        let keypair = ECPKeypair::new(ECPSupportedCurves::BP256R1).setup(&mut rng).unwrap();
+        let container = save(&keypair, None, &mut rng).unwrap();
+
+        let mut bad_magic = container.clone();
+        bad_magic[0] ^= 1;
+        assert!(match load_error(&bad_magic, None) { Error::Key(KeyError::BadMagic) => true, _ => false });
+
+        let mut bad_version = container.clone();
+        bad_version[MAGIC.len()] = VERSION + 1;
+        assert!(match load_error(&bad_version, None) { Error::Key(KeyError::UnsupportedVersion(_)) => true, _ => false });
+
+        for length in MAGIC.len() + 1..container.len() {
+            assert!(load(&container[..length], None).is_err());
+        }
+
+        let mut trailing = container.clone();
+        trailing.extend_from_slice(&[9, 0, 0, 0, 0]);
+        assert!(load(&trailing, None).is_err());
+
+        let mut off_curve = container.clone();
+        let last = off_curve.len() - 1;
+        off_curve[last] ^= 1;
+        assert!(load(&off_curve, None).is_err());
+
+        let sealed = save(&keypair, Some(PASSPHRASE), &mut rng).unwrap();
+        let mut flipped_flag = sealed.clone();
+        flipped_flag[MAGIC.len() + 1 + 5 + 2 + 5] ^= 1;
+        assert!(load(&flipped_flag, Some(PASSPHRASE)).is_err());
+    }
+
+    #[test]
+    fn out_of_range_iteration_counts_are_rejected() {
+        let mut rng = os_rng().unwrap();
+        let keypair = ECPKeypair::new(ECPSupportedCurves::BP256R1).setup(&mut rng).unwrap();
+        let sealed = save(&keypair, Some(PASSPHRASE), &mut rng).unwrap();
+
+        // Magic, version, algorithm and visibility records, then the
+        // encryption record header and its salt
+        let iterations_at = MAGIC.len() + 1 + 7 + 6 + 5 + 16;
+
+        for count in &[0, MAX_PBKDF2_ITERATIONS + 1, u32::max_value()] {
+            let mut altered = sealed.clone();
+            altered[iterations_at..iterations_at + 4].copy_from_slice(&count.to_be_bytes());
+
+            assert!(match load_error(&altered, Some(PASSPHRASE)) { Error::Key(KeyError::Malformed(_)) => true, _ => false });
+        }
+    }
+
+    #[test]
+    fn unknown_algorithms_and_records_are_passed_through() {
+        let mut container = MAGIC.to_vec();
+        container.push(VERSION);
+        container.extend_from_slice(&[1, 0, 0, 0, 2, 0x7f, 0x01]);
+        container.extend_from_slice(&[200, 0, 0, 0, 3, 1, 2, 3]);
+        container.extend_from_slice(&[2, 0, 0, 0, 1, 1]);
+        container.extend_from_slice(&[4, 0, 0, 0, 4, 9, 8, 7, 6]);
+
+        match load(&container, None).unwrap() {
+            KeyKind::Unknown { algorithm, private, payload } => {
+                assert_eq!(algorithm, 0x7f01);
+                assert!(private);
+                assert_eq!(payload, vec![9, 8, 7, 6]);
+            },
+            _ => panic!("Expected an unknown key")
+        }
+    }
+
+    #[test]
+    fn metadata_round_trip() {
+        let mut rng = os_rng().unwrap();
+        let keypair = ECPKeypair::new(ECPSupportedCurves::Curve25519).setup(&mut rng).unwrap();
+
+        for passphrase in vec![None, Some(PASSPHRASE)] {
+            let container = save_with_metadata(&keypair, &metadata(), passphrase, &mut rng).unwrap();
+            let loaded = load_with_metadata(&container, passphrase).unwrap();
+
+            assert_eq!(container[MAGIC.len()], VERSION);
+            assert_eq!(loaded.metadata, metadata());
+            assert!(!loaded.legacy);
+
+            match loaded.key {
+                KeyKind::Ecc(loaded) => assert_eq!(loaded.d, keypair.d),
+                _ => panic!("Expected an ECC key")
+            }
+        }
+
+        let loaded = load_with_metadata(&save(&keypair, None, &mut rng).unwrap(), None).unwrap();
+        assert_eq!(loaded.metadata, KeyMetadata::default());
+    }
+
+    #[test]
+    fn altered_metadata_fails_to_open() {
+        let mut rng = os_rng().unwrap();
+        let keypair = ECPKeypair::new(ECPSupportedCurves::BP256R1).setup(&mut rng).unwrap();
+        let sealed = save_with_metadata(&keypair, &metadata(), Some(PASSPHRASE), &mut rng).unwrap();
+
+        // Magic, version, algorithm and visibility records, then the
+        // label record header
+        let label_at = MAGIC.len() + 1 + 7 + 6 + 5;
+        let created_at = label_at + "test key".len() + 5;
+
+        let mut relabelled = sealed.clone();
+        relabelled[label_at..label_at + 4].copy_from_slice(b"prod");
+
+        let mut backdated = sealed.clone();
+        backdated[created_at + 7] ^= 1;
+
+        for altered in vec![relabelled, backdated] {
+            match load_error(&altered, Some(PASSPHRASE)) {
+                Error::Key(KeyError::WrongPassphrase) => (),
+                other => panic!("Unexpected error {:?}", other)
+            }
+        }
+
+        assert!(load(&sealed, Some(PASSPHRASE)).is_ok());
+    }
+
+    #[test]
+    fn legacy_containers_are_flagged() {
+        let mut rng = os_rng().unwrap();
+        let rsa = rsa_keypair();
+
+        for passphrase in vec![None, Some(PASSPHRASE)] {
+            let container = write_container(&rsa, &metadata(), LEGACY_VERSION, passphrase, &mut rng).unwrap();
+            let loaded = load_with_metadata(&container, passphrase).unwrap();
+
+            assert!(loaded.legacy);
+            assert_eq!(loaded.metadata, KeyMetadata::default());
+
+            match loaded.key {
+                KeyKind::Rsa(loaded) => assert_eq!(loaded.allow_insecure_for_testing(), rsa),
+                _ => panic!("Expected an RSA key")
+            }
+        }
+    }
+}
\ No newline at end of file
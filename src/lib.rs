@@ -0,0 +1,51 @@
+#[cfg(all(feature = "rsa", not(any(feature = "hashes-sha3", feature = "hashes-blake2"))))]
+compile_error!("The rsa feature needs at least one of hashes-sha3 or hashes-blake2 for OAEP padding");
+
+#[cfg(all(feature = "dsa", not(any(feature = "hashes-sha3", feature = "hashes-blake2"))))]
+compile_error!("The dsa feature needs at least one of hashes-sha3 or hashes-blake2 for message digests");
+
+#[cfg(any(feature = "hashes-sha3", feature = "hashes-blake2", feature = "hashes-sha2"))]
+pub mod hash;
+pub mod error;
+pub mod encryption;
+pub mod key_exchange;
+pub mod signature;
+pub mod utils;
+
+#[cfg(feature = "keys")]
+pub mod keys;
+#[cfg(feature = "keys")]
+pub mod fingerprint;
+
+#[cfg(feature = "sharing")]
+pub mod sharing;
+
+#[cfg(feature = "hd")]
+pub mod hd;
+
+#[cfg(feature = "session")]
+pub mod session;
+
+#[cfg(feature = "session")]
+pub mod negotiation;
+
+#[cfg(feature = "trace")]
+pub mod transcript;
+
+#[cfg(any(feature = "hashes-sha3", feature = "hashes-blake2"))]
+pub mod self_test;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(any(test, feature = "testvectors"))]
+pub mod testvectors;
+
+#[cfg(test)]
+mod fuzz_targets;
+
+pub use error::Error;
+pub use utils::ct;
+
+#[cfg(any(feature = "hashes-sha3", feature = "hashes-blake2"))]
+pub use self_test::self_test;
@@ -1,6 +1,3 @@
-pub mod key_exchange;
-pub mod utils;
-
 fn main() {
     println!("Hello, world!");
 }
@@ -0,0 +1,440 @@
+//! Agreement on a cipher suite ahead of a session handshake. Each peer
+//! lists the suites it supports in order of preference and sends that
+//! list as its offer. The selected suite is the one the local catalog
+//! prefers most among those the remote offer also contains, so peers
+//! whose catalogs rank their mutual suites alike select the same one.
+//!
+//! Offers travel unauthenticated, so the agreement also carries a hash
+//! over both offers and the selected suite. `Session` signs that hash as
+//! part of the handshake: a man in the middle who strips strong suites
+//! from an offer changes the hash on one side only, and the handshake
+//! fails instead of settling on a weaker suite.
+//!
+//! Suites are written with their TLS and IANA ids: the TLS named group
+//! of the curve, the IANA AEAD id of the cipher and a single byte for
+//! the hash, as TLS has no ids for SHA-3 or BLAKE2. Finite field
+//! Diffie-Hellman is not offered, as `DiffieHellman` generates its own
+//! modulus rather than using a named group
+
+use std::fmt;
+use crypto::aes::KeySize;
+
+use crate::hash::crypto::{ hash_message, HashAlgorithm };
+use crate::key_exchange::ecdh::ECDH;
+use crate::utils::ecc::EcError;
+use crate::utils::ecc_curves::ECPSupportedCurves;
+use crate::utils::rng::CryptoRngCore;
+
+
+/*---- CONSTANTS ----*/
+
+/// Length in bytes of one suite in an offer: group, AEAD and hash ids
+const SUITE_LENGTH: usize = 5;
+
+/// Most suites read from a remote offer
+const MAX_OFFER_SUITES: usize = 64;
+
+/// Domain separation label for the offer transcript
+const TRANSCRIPT_LABEL: &'static [u8] = b"cryptofun negotiation v1";
+
+
+/*---- ENUMS ----*/
+
+/// Errors raised while reading an offer or selecting a suite
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum NegotiationError {
+    MalformedOffer,         // offer is empty, too long or not a whole number of suites
+    NoMutualSuite           // the two sides have no suite in common
+}
+
+impl fmt::Display for NegotiationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NegotiationError::MalformedOffer => write!(f, "Suite offer is malformed"),
+            NegotiationError::NoMutualSuite => write!(f, "No cipher suite is supported by both sides")
+        }
+    }
+}
+
+/// Key exchange of a suite, with the group it runs over
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum KeyExchangeKind {
+    Ecdh(ECPSupportedCurves)    // ECDH, or X25519 on Curve25519
+}
+
+/// Authenticated cipher protecting the session frames
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Aead {
+    Aes128Gcm,
+    Aes256Gcm
+}
+
+
+/*---- STRUCTS ----*/
+
+/// Algorithms a session runs with
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Suite {
+    pub key_exchange: KeyExchangeKind,  // key exchange and its group
+    pub aead: Aead,                     // cipher for the frames
+    pub hash: HashAlgorithm             // hash for the transcript and key derivation
+}
+
+/// Suites supported by one side, most preferred first
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SuiteCatalog {
+    suites: Vec<Suite>
+}
+
+/// Outcome of a negotiation, to be handed to `Session`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Agreement {
+    pub suite: Suite,           // selected suite
+    pub transcript: Vec<u8>     // hash over both offers and the selected suite
+}
+
+
+/*---- IMPLEMENTATIONS ----*/
+
+impl Aead {
+
+    /// Length in bytes of the cipher key
+    pub fn key_length(&self) -> usize {
+        match self {
+            Aead::Aes128Gcm => 16,
+            Aead::Aes256Gcm => 32
+        }
+    }
+
+    /// AES key size for the cipher
+    pub(crate) fn key_size(&self) -> KeySize {
+        match self {
+            Aead::Aes128Gcm => KeySize::KeySize128,
+            Aead::Aes256Gcm => KeySize::KeySize256
+        }
+    }
+}
+
+impl Suite {
+
+    /// Creates a suite
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `key_exchange` - Key exchange and its group
+    /// * `aead` - Cipher for the frames
+    /// * `hash` - Hash for the transcript and key derivation
+    pub fn new(key_exchange: KeyExchangeKind, aead: Aead, hash: HashAlgorithm) -> Self {
+        Suite {
+            key_exchange: key_exchange,
+            aead: aead,
+            hash: hash
+        }
+    }
+
+    /// Generates a fresh key exchange for the suite
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `rng` - Random number generator
+    pub fn key_exchange(&self, rng: &mut impl CryptoRngCore) -> Result<ECDH, EcError> {
+        match &self.key_exchange {
+            KeyExchangeKind::Ecdh(curve) => ECDH::new(curve.clone(), rng)
+        }
+    }
+
+    /// Wire encoding of the suite
+    fn to_bytes(&self) -> [u8; SUITE_LENGTH] {
+        let group = match &self.key_exchange {
+            KeyExchangeKind::Ecdh(curve) => named_group_id(curve)
+        };
+
+        let aead: u16 = match self.aead {
+            Aead::Aes128Gcm => 1,
+            Aead::Aes256Gcm => 2
+        };
+
+        let group = group.to_be_bytes();
+        let aead = aead.to_be_bytes();
+
+        [group[0], group[1], aead[0], aead[1], hash_id(&self.hash)]
+    }
+
+    /// Reads a suite written by `to_bytes`, or None if any of its ids
+    /// is unknown
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `bytes` - Encoded suite
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let curve = named_group_from_id(u16::from_be_bytes([bytes[0], bytes[1]]))?;
+
+        let aead = match u16::from_be_bytes([bytes[2], bytes[3]]) {
+            1 => Aead::Aes128Gcm,
+            2 => Aead::Aes256Gcm,
+            _ => return None
+        };
+
+        Some(Suite::new(KeyExchangeKind::Ecdh(curve), aead, hash_from_id(bytes[4])?))
+    }
+}
+
+impl SuiteCatalog {
+
+    /// Creates a catalog from suites in order of preference. Repeated
+    /// suites are dropped
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `suites` - Supported suites, most preferred first
+    pub fn new(suites: Vec<Suite>) -> Self {
+        let mut catalog = SuiteCatalog { suites: Vec::new() };
+
+        for suite in suites {
+            if !catalog.suites.contains(&suite) {
+                catalog.suites.push(suite);
+            }
+        }
+
+        catalog
+    }
+
+    /// Supported suites, most preferred first
+    pub fn suites(&self) -> &[Suite] {
+        &self.suites
+    }
+
+    /// Encodes the catalog as an offer for the peer
+    pub fn offer(&self) -> Vec<u8> {
+        self.suites.iter().flat_map(|suite| suite.to_bytes().to_vec()).collect()
+    }
+
+    /// Reads the suites of a remote offer that this build knows. Unknown
+    /// suites are skipped, so that newer peers can offer more
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `offer` - Offer written by `offer`
+    pub fn from_offer(offer: &[u8]) -> Result<Self, NegotiationError> {
+        if offer.is_empty() || offer.len() % SUITE_LENGTH != 0 || offer.len() / SUITE_LENGTH > MAX_OFFER_SUITES {
+            return Err(NegotiationError::MalformedOffer);
+        }
+
+        Ok(SuiteCatalog::new(offer.chunks(SUITE_LENGTH).filter_map(Suite::from_bytes).collect()))
+    }
+}
+
+impl Default for SuiteCatalog {
+
+    /// X25519 first, then the NIST and Brainpool curves, each with
+    /// AES-256-GCM and SHA3-256
+    fn default() -> Self {
+        let curves = vec![
+            ECPSupportedCurves::Curve25519,
+            ECPSupportedCurves::SECP256R1,
+            ECPSupportedCurves::SECP384R1,
+            ECPSupportedCurves::BP256R1,
+            ECPSupportedCurves::BP384R1
+        ];
+
+        SuiteCatalog::new(curves.into_iter()
+            .map(|curve| Suite::new(KeyExchangeKind::Ecdh(curve), Aead::Aes256Gcm, HashAlgorithm::Sha3_256))
+            .collect())
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Selects the suite the local catalog prefers most among those in the
+/// remote offer
+/// 
+/// ### Arguments
+/// 
+/// * `local` - Suites supported locally, most preferred first
+/// * `remote_offer` - Offer received from the peer
+pub fn select(local: &SuiteCatalog, remote_offer: &[u8]) -> Result<Suite, NegotiationError> {
+    let remote = SuiteCatalog::from_offer(remote_offer)?;
+
+    match local.suites.iter().find(|suite| remote.suites.contains(suite)) {
+        Some(suite) => Ok(suite.clone()),
+        None => Err(NegotiationError::NoMutualSuite)
+    }
+}
+
+/// Selects a suite and hashes both offers along with it. The offers are
+/// hashed in byte order rather than local first, so both peers compute
+/// the same transcript
+/// 
+/// ### Arguments
+/// 
+/// * `local` - Suites supported locally, most preferred first
+/// * `remote_offer` - Offer received from the peer
+pub fn agree(local: &SuiteCatalog, remote_offer: &[u8]) -> Result<Agreement, NegotiationError> {
+    let suite = select(local, remote_offer)?;
+    let local_offer = local.offer();
+
+    let (first, second) = if local_offer.as_slice() <= remote_offer {
+        (local_offer.as_slice(), remote_offer)
+    } else {
+        (remote_offer, local_offer.as_slice())
+    };
+
+    let mut transcript = TRANSCRIPT_LABEL.to_vec();
+
+    for field in &[first, second, &suite.to_bytes()[..]] {
+        transcript.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        transcript.extend_from_slice(field);
+    }
+
+    Ok(Agreement {
+        transcript: hash_message(&transcript, suite.hash.clone()),
+        suite: suite
+    })
+}
+
+/// TLS named group id of a curve
+/// 
+/// ### Arguments
+/// 
+/// * `curve` - Curve to look up
+fn named_group_id(curve: &ECPSupportedCurves) -> u16 {
+    match curve {
+        ECPSupportedCurves::SECP256R1 => 0x0017,
+        ECPSupportedCurves::SECP384R1 => 0x0018,
+        ECPSupportedCurves::SECP521R1 => 0x0019,
+        ECPSupportedCurves::BP256R1 => 0x001a,
+        ECPSupportedCurves::BP384R1 => 0x001b,
+        ECPSupportedCurves::BP521R1 => 0x001c,
+        ECPSupportedCurves::Curve25519 => 0x001d
+    }
+}
+
+/// Curve for a TLS named group id
+/// 
+/// ### Arguments
+/// 
+/// * `id` - Named group id
+fn named_group_from_id(id: u16) -> Option<ECPSupportedCurves> {
+    match id {
+        0x0017 => Some(ECPSupportedCurves::SECP256R1),
+        0x0018 => Some(ECPSupportedCurves::SECP384R1),
+        0x0019 => Some(ECPSupportedCurves::SECP521R1),
+        0x001a => Some(ECPSupportedCurves::BP256R1),
+        0x001b => Some(ECPSupportedCurves::BP384R1),
+        0x001c => Some(ECPSupportedCurves::BP521R1),
+        0x001d => Some(ECPSupportedCurves::Curve25519),
+        _ => None
+    }
+}
+
+/// Id byte for a hash algorithm, matching the one in key containers
+/// 
+/// ### Arguments
+/// 
+/// * `algorithm` - Hash algorithm
+fn hash_id(algorithm: &HashAlgorithm) -> u8 {
+    match algorithm {
+        #[cfg(feature = "hashes-blake2")]
+        HashAlgorithm::Blake2b => 1,
+        #[cfg(feature = "hashes-blake2")]
+        HashAlgorithm::Blake2s => 2,
+        HashAlgorithm::Sha3_256 => 3,
+        HashAlgorithm::Sha3_512 => 4,
+        HashAlgorithm::Keccak256 => 5,
+        HashAlgorithm::Keccak512 => 6,
+        #[cfg(feature = "hashes-sha2")]
+        HashAlgorithm::Sha256 => 7
+    }
+}
+
+/// Hash algorithm for an id byte written by `hash_id`
+/// 
+/// ### Arguments
+/// 
+/// * `id` - Id byte
+fn hash_from_id(id: u8) -> Option<HashAlgorithm> {
+    match id {
+        #[cfg(feature = "hashes-blake2")]
+        1 => Some(HashAlgorithm::Blake2b),
+        #[cfg(feature = "hashes-blake2")]
+        2 => Some(HashAlgorithm::Blake2s),
+        3 => Some(HashAlgorithm::Sha3_256),
+        4 => Some(HashAlgorithm::Sha3_512),
+        5 => Some(HashAlgorithm::Keccak256),
+        6 => Some(HashAlgorithm::Keccak512),
+        #[cfg(feature = "hashes-sha2")]
+        7 => Some(HashAlgorithm::Sha256),
+        _ => None
+    }
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod negotiation_test {
+
+    use crate::hash::crypto::HashAlgorithm;
+    use crate::utils::ecc_curves::ECPSupportedCurves;
+    use crate::negotiation::{ SuiteCatalog, Suite, KeyExchangeKind, Aead, NegotiationError, select, agree };
+
+    fn suite(curve: ECPSupportedCurves, aead: Aead) -> Suite {
+        Suite::new(KeyExchangeKind::Ecdh(curve), aead, HashAlgorithm::Sha3_256)
+    }
+
+    #[test]
+    fn both_sides_select_the_same_suite() {
+        let alice = SuiteCatalog::new(vec![
+            suite(ECPSupportedCurves::Curve25519, Aead::Aes256Gcm),
+            suite(ECPSupportedCurves::SECP256R1, Aead::Aes256Gcm),
+            suite(ECPSupportedCurves::SECP384R1, Aead::Aes128Gcm)
+        ]);
+        let bob = SuiteCatalog::new(vec![
+            suite(ECPSupportedCurves::SECP256R1, Aead::Aes256Gcm),
+            suite(ECPSupportedCurves::BP256R1, Aead::Aes256Gcm),
+            suite(ECPSupportedCurves::SECP384R1, Aead::Aes128Gcm)
+        ]);
+
+        let expected = suite(ECPSupportedCurves::SECP256R1, Aead::Aes256Gcm);
+        assert_eq!(select(&alice, &bob.offer()), Ok(expected.clone()));
+        assert_eq!(select(&bob, &alice.offer()), Ok(expected));
+
+        let alice_agreement = agree(&alice, &bob.offer()).unwrap();
+        let bob_agreement = agree(&bob, &alice.offer()).unwrap();
+        assert_eq!(alice_agreement, bob_agreement);
+    }
+
+    #[test]
+    fn disjoint_and_malformed_offers_are_rejected() {
+        let alice = SuiteCatalog::new(vec![suite(ECPSupportedCurves::Curve25519, Aead::Aes256Gcm)]);
+        let bob = SuiteCatalog::new(vec![suite(ECPSupportedCurves::Curve25519, Aead::Aes128Gcm)]);
+
+        assert_eq!(select(&alice, &bob.offer()), Err(NegotiationError::NoMutualSuite));
+        assert_eq!(select(&alice, &[]), Err(NegotiationError::MalformedOffer));
+        assert_eq!(select(&alice, &alice.offer()[..4]), Err(NegotiationError::MalformedOffer));
+        assert_eq!(select(&alice, &vec![0; 5 * 65]), Err(NegotiationError::MalformedOffer));
+
+        // Unknown suites are skipped rather than rejected
+        let mut offer = vec![0xff, 0xff, 0x00, 0x02, 0x03];
+        offer.extend(alice.offer());
+        assert_eq!(select(&alice, &offer), Ok(alice.suites()[0].clone()));
+    }
+
+    #[test]
+    fn reordered_offer_selects_the_same_suite_under_a_new_transcript() {
+        let local = SuiteCatalog::default();
+        let remote = SuiteCatalog::new(vec![
+            suite(ECPSupportedCurves::SECP384R1, Aead::Aes256Gcm),
+            suite(ECPSupportedCurves::SECP256R1, Aead::Aes256Gcm)
+        ]);
+        let reordered = SuiteCatalog::new(remote.suites().iter().rev().cloned().collect());
+
+        let original = agree(&local, &remote.offer()).unwrap();
+        let swapped = agree(&local, &reordered.offer()).unwrap();
+
+        assert_eq!(original.suite, suite(ECPSupportedCurves::SECP256R1, Aead::Aes256Gcm));
+        assert_eq!(swapped.suite, original.suite);
+        assert_ne!(swapped.transcript, original.transcript);
+    }
+}
\ No newline at end of file
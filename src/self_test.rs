@@ -0,0 +1,581 @@
+//! Known-answer tests for every enabled primitive, meant to be run once
+//! at startup before any keys are handled. Each check compares against
+//! vectors embedded below, taken from the specifications named beside
+//! them, and the outcome of each is collected in a `SelfTestReport`.
+//!
+//! The checks draw from a fixed ChaCha generator rather than the system
+//! one, so a failure always points at the primitive and not the RNG.
+//! The generator only feeds blinding and projective randomization, which
+//! have no influence on the outputs
+
+use std::fmt;
+use rand::{ ChaChaRng, SeedableRng };
+use num_bigint::BigUint;
+#[cfg(feature = "ecdsa")]
+use num_bigint::ToBigInt;
+use rustc_serialize::hex::FromHex;
+
+#[cfg(feature = "aes")]
+use crypto::aes::KeySize;
+#[cfg(feature = "aes")]
+use crypto::aes_gcm::AesGcm;
+#[cfg(feature = "aes")]
+use crypto::aead::{ AeadEncryptor, AeadDecryptor };
+
+use crate::error::Error;
+use crate::utils::rng::CryptoRngCore;
+use crate::hash::crypto::{ hash_message, HashAlgorithm };
+#[cfg(feature = "rsa")]
+use crate::encryption::AsymmetricKeyMode;
+#[cfg(feature = "rsa")]
+use crate::encryption::rsa::RSA;
+#[cfg(feature = "ecdsa")]
+use crate::signature::ecdsa::{ ECDSA, ECDSASignature };
+#[cfg(feature = "ecdsa")]
+use crate::utils::ecc::ECPKeypair;
+#[cfg(feature = "ecdsa")]
+use crate::utils::ecc_curves::ECPSupportedCurves;
+#[cfg(feature = "ecc")]
+use crate::key_exchange::x25519::{ x25519, X25519_LENGTH };
+#[cfg(feature = "dh")]
+use crate::key_exchange::diffie_hellman::DiffieHellman;
+
+
+/*---- CONSTANTS ----*/
+
+/// Seed of the generator handed to the checks
+const RNG_SEED: &'static [u32] = &[0x5e1f, 0x7e57];
+
+/// Message hashed by every hash vector
+const HASH_MESSAGE: &'static [u8] = b"abc";
+
+/// Padding hash for the RSA check. Padding is not applied yet, so
+/// any enabled algorithm will do
+#[cfg(all(feature = "rsa", feature = "hashes-sha3"))]
+const RSA_HASH_ALGORITHM: HashAlgorithm = HashAlgorithm::Sha3_256;
+#[cfg(all(feature = "rsa", not(feature = "hashes-sha3")))]
+const RSA_HASH_ALGORITHM: HashAlgorithm = HashAlgorithm::Blake2s;
+
+/// Test case 4 from "The Galois/Counter Mode of Operation (GCM)",
+/// McGrew and Viega
+#[cfg(feature = "aes")]
+const AES_GCM_VECTOR: AesGcmVector = AesGcmVector {
+    key: "feffe9928665731c6d6a8f9467308308",
+    nonce: "cafebabefacedbaddecaf888",
+    aad: "feedfacedeadbeeffeedfacedeadbeefabaddad2",
+    plaintext: "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a721c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b39",
+    ciphertext: "42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e21d514b25466931c7d8f6a5aac84aa051ba30b396a0aac973d58e091",
+    tag: "5bc94fbc3221a5db94fae95ae7121a47"
+};
+
+/// 512-bit keypair generated for this check only. Never use it for
+/// anything else
+#[cfg(feature = "rsa")]
+const RSA_VECTOR: RsaVector = RsaVector {
+    n: "b2fa65b29471a004ef8ae4e4727747fc68dfdfde255650a51eb43d1b351b1ade794709a1fdf37855c778780a5d5dcabfacfc1bdb7a3cf5a5c75aa0ff11168d5d",
+    e: "010001",
+    d: "ace1f4a458819d49c73d8a36255f95187b116dc4be9a2ed220e387772588261d8aed07605ea5e5d2f7356e22f80c29ce3e80969bc1fbe4c20b4e943188debca1",
+    p: "eb77af10d530d57655cca576cfffdd6afe3e88225e862aeee26e0b32f49dd789",
+    q: "c295b4e0cedced87308397a1734da7ab2e3ff7f4429f37852ea04327c957fe35",
+    message: b"pairwise consistency"
+};
+
+/// RFC 6979 appendix A.2.5: P-256 with SHA-256 over "sample"
+#[cfg(feature = "ecdsa")]
+const ECDSA_VECTOR: EcdsaVector = EcdsaVector {
+    private_key: "c9afa9d845ba75166b5c215767b1d6934e50c3db36e89b127b8a622b120f6721",
+    public_key: "0460fed4ba255a9d31c961eb74c6356d68c049b8923b61fa6ce669622e60f29fb67903fe1008b8bc99a41ae9e95628bc64f2f1b20c2d7e9f5177a3c294d4462299",
+    digest: "af2bdbe1aa9b6ec1e2ade1d694f41fc71a831d0268e9891562113d8a62add1bf",
+    nonce: "a6e3c57dd01abe90086538398355dd4c3b17aa873382b0f24d6129493d8aad60",
+    r: "efd48b2aacb6a8fd1140dd9cd45e81d69d2c877b56aaf991c34d0ea84eaf3716",
+    s: "f7cb1c942d657c41d436c7a1b6e29f65f3e900dbb9aff4064dc4ab2f843acda8"
+};
+
+/// RFC 7748 section 5.2, first vector
+#[cfg(feature = "ecc")]
+const X25519_VECTOR: X25519Vector = X25519Vector {
+    scalar: "a546e36bf0527c9d3b16154b82465edd62144c0ac1fc5a18506a2244ba449ac4",
+    u: "e6db6867583030db3594c1a424b15f7c726624ec26b3353b10a903a6d0ab1c4c",
+    output: "c3da55379de9c6908e94ea4df28d084f32eccf03491c71f754b4075577a28552"
+};
+
+/// Textbook exchange over P = 23, G = 5
+#[cfg(feature = "dh")]
+const DH_VECTOR: DhVector = DhVector {
+    p: "17",
+    g: "05",
+    x_a: "06",
+    x_b: "0f",
+    shared: "02"
+};
+
+
+/*---- STRUCTS ----*/
+
+/// Primitive covered by one self-test check
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Primitive {
+    Hash(HashAlgorithm),    // digest of a fixed message
+    AesGcm,                 // AES-128-GCM encryption and decryption
+    Rsa,                    // RSA pairwise consistency
+    Ecdsa,                  // ECDSA signature with a fixed nonce
+    X25519,                 // X25519 scalar multiplication
+    DiffieHellman           // Diffie-Hellman agreement on a fixed group
+}
+
+/// Outcome of every check run by `self_test`, in the order they ran
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SelfTestReport {
+    pub results: Vec<(Primitive, bool)>
+}
+
+/// Vectors checked by `self_test`. Values are hex strings, with
+/// integers written big-endian
+#[derive(Clone)]
+struct KnownAnswers {
+    hashes: Vec<(HashAlgorithm, &'static str)>,
+    #[cfg(feature = "aes")]
+    aes_gcm: AesGcmVector,
+    #[cfg(feature = "rsa")]
+    rsa: RsaVector,
+    #[cfg(feature = "ecdsa")]
+    ecdsa: EcdsaVector,
+    #[cfg(feature = "ecc")]
+    x25519: X25519Vector,
+    #[cfg(feature = "dh")]
+    dh: DhVector
+}
+
+#[cfg(feature = "aes")]
+#[derive(Clone)]
+struct AesGcmVector {
+    key: &'static str,
+    nonce: &'static str,
+    aad: &'static str,
+    plaintext: &'static str,
+    ciphertext: &'static str,
+    tag: &'static str
+}
+
+#[cfg(feature = "rsa")]
+#[derive(Clone)]
+struct RsaVector {
+    n: &'static str,
+    e: &'static str,
+    d: &'static str,
+    p: &'static str,
+    q: &'static str,
+    message: &'static [u8]      // must not end in a zero byte
+}
+
+#[cfg(feature = "ecdsa")]
+#[derive(Clone)]
+struct EcdsaVector {
+    private_key: &'static str,
+    public_key: &'static str,   // uncompressed encoding
+    digest: &'static str,
+    nonce: &'static str,
+    r: &'static str,
+    s: &'static str
+}
+
+#[cfg(feature = "ecc")]
+#[derive(Clone)]
+struct X25519Vector {
+    scalar: &'static str,       // little-endian, as in RFC 7748
+    u: &'static str,            // little-endian, as in RFC 7748
+    output: &'static str        // little-endian, as in RFC 7748
+}
+
+#[cfg(feature = "dh")]
+#[derive(Clone)]
+struct DhVector {
+    p: &'static str,
+    g: &'static str,
+    x_a: &'static str,
+    x_b: &'static str,
+    shared: &'static str
+}
+
+
+/*---- IMPLEMENTATIONS ----*/
+
+impl SelfTestReport {
+
+    /// Whether every check passed
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|(_, passed)| *passed)
+    }
+
+    /// Primitives whose check failed
+    pub fn failures(&self) -> Vec<&Primitive> {
+        self.results.iter().filter(|(_, passed)| !*passed).map(|(primitive, _)| primitive).collect()
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.passed() {
+            return write!(f, "All {} checks passed", self.results.len());
+        }
+
+        write!(f, "Failed checks: {:?}", self.failures())
+    }
+}
+
+impl KnownAnswers {
+
+    /// The vectors embedded in this module
+    fn embedded() -> Self {
+        let mut hashes = Vec::new();
+
+        #[cfg(feature = "hashes-blake2")]
+        {
+            hashes.push((HashAlgorithm::Blake2b, "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d17d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"));
+            hashes.push((HashAlgorithm::Blake2s, "508c5e8c327c14e2e1a72ba34eeb452f37458b209ed63a294d999b4c86675982"));
+        }
+
+        #[cfg(feature = "hashes-sha3")]
+        {
+            hashes.push((HashAlgorithm::Sha3_256, "3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532"));
+            hashes.push((HashAlgorithm::Sha3_512, "b751850b1a57168a5693cd924b6b096e08f621827444f70d884f5d0240d2712e10e116e9192af3c91a7ec57647e3934057340b4cf408d5a56592f8274eec53f0"));
+            hashes.push((HashAlgorithm::Keccak256, "4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45"));
+            hashes.push((HashAlgorithm::Keccak512, "18587dc2ea106b9a1563e32b3312421ca164c7f1f07bc922a9c83d77cea3a1e5d0c69910739025372dc14ac9642629379540c17e2a65b19d77aa511a9d00bb96"));
+        }
+
+        KnownAnswers {
+            hashes: hashes,
+            #[cfg(feature = "aes")]
+            aes_gcm: AES_GCM_VECTOR,
+            #[cfg(feature = "rsa")]
+            rsa: RSA_VECTOR,
+            #[cfg(feature = "ecdsa")]
+            ecdsa: ECDSA_VECTOR,
+            #[cfg(feature = "ecc")]
+            x25519: X25519_VECTOR,
+            #[cfg(feature = "dh")]
+            dh: DH_VECTOR
+        }
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Runs a known-answer test for every enabled primitive. Returns the
+/// report if all of them pass, and `Error::SelfTest` carrying the same
+/// report otherwise
+pub fn self_test() -> Result<SelfTestReport, Error> {
+    run(&KnownAnswers::embedded())
+}
+
+/// Runs every check against the given vectors
+/// 
+/// ### Arguments
+/// 
+/// * `vectors` - Vectors to check against
+fn run(vectors: &KnownAnswers) -> Result<SelfTestReport, Error> {
+    let mut rng = ChaChaRng::from_seed(RNG_SEED);
+    let mut results = Vec::new();
+
+    for (algorithm, digest) in &vectors.hashes {
+        results.push((Primitive::Hash(algorithm.clone()), check_hash(algorithm, digest)));
+    }
+
+    #[cfg(feature = "aes")]
+    results.push((Primitive::AesGcm, check_aes_gcm(&vectors.aes_gcm)));
+
+    #[cfg(feature = "rsa")]
+    results.push((Primitive::Rsa, check_rsa(&vectors.rsa, &mut rng)));
+
+    #[cfg(feature = "ecdsa")]
+    results.push((Primitive::Ecdsa, check_ecdsa(&vectors.ecdsa, &mut rng)));
+
+    #[cfg(feature = "ecc")]
+    results.push((Primitive::X25519, check_x25519(&vectors.x25519, &mut rng)));
+
+    #[cfg(feature = "dh")]
+    results.push((Primitive::DiffieHellman, check_dh(&vectors.dh, &mut rng)));
+
+    let report = SelfTestReport { results: results };
+
+    if !report.passed() {
+        return Err(Error::SelfTest(report));
+    }
+
+    Ok(report)
+}
+
+/// Hashes the fixed message and compares the digest
+/// 
+/// ### Arguments
+/// 
+/// * `algorithm` - Algorithm to check
+/// * `digest` - Expected digest
+fn check_hash(algorithm: &HashAlgorithm, digest: &str) -> bool {
+    hash_message(HASH_MESSAGE, algorithm.clone()) == unhex(digest)
+}
+
+/// Encrypts the plaintext and compares ciphertext and tag, then
+/// decrypts it back
+/// 
+/// ### Arguments
+/// 
+/// * `vector` - Vector to check against
+#[cfg(feature = "aes")]
+fn check_aes_gcm(vector: &AesGcmVector) -> bool {
+    let key = unhex(vector.key);
+    let nonce = unhex(vector.nonce);
+    let aad = unhex(vector.aad);
+    let plaintext = unhex(vector.plaintext);
+
+    let mut ciphertext = vec![0; plaintext.len()];
+    let mut tag = [0; 16];
+    AesGcm::new(KeySize::KeySize128, &key, &nonce, &aad).encrypt(&plaintext, &mut ciphertext, &mut tag);
+
+    if ciphertext != unhex(vector.ciphertext) || tag[..] != unhex(vector.tag)[..] {
+        return false;
+    }
+
+    let mut decrypted = vec![0; ciphertext.len()];
+    let authentic = AesGcm::new(KeySize::KeySize128, &key, &nonce, &aad).decrypt(&ciphertext, &mut decrypted, &tag);
+
+    authentic && decrypted == plaintext
+}
+
+/// Rebuilds the keypair and checks that each key undoes the other,
+/// in both directions
+/// 
+/// ### Arguments
+/// 
+/// * `vector` - Vector to check against
+/// * `rng` - Random number generator
+#[cfg(feature = "rsa")]
+fn check_rsa(vector: &RsaVector, rng: &mut impl CryptoRngCore) -> bool {
+    let rsa = RSA::from_private_values(
+        RSA_HASH_ALGORITHM,
+        false,
+        &biguint(vector.n),
+        &biguint(vector.e),
+        &biguint(vector.d),
+        &biguint(vector.p),
+        &biguint(vector.q)
+    );
+
+    // The vector's modulus is kept small so the self test stays quick,
+    // and the round trips go both ways, which only raw keys allow
+    let rsa = match rsa {
+        Ok(rsa) => rsa.allow_insecure_for_testing().with_raw_padding_unsafe(),
+        Err(_) => return false
+    };
+
+    rsa_round_trip(&rsa, vector.message, AsymmetricKeyMode::Public, AsymmetricKeyMode::Private, rng)
+    && rsa_round_trip(&rsa, vector.message, AsymmetricKeyMode::Private, AsymmetricKeyMode::Public, rng)
+}
+
+/// Applies one key of an RSA keypair and then the other, checking that
+/// the message comes back
+/// 
+/// ### Arguments
+/// 
+/// * `rsa` - Keypair to use
+/// * `message` - Message to send through
+/// * `first` - Key to apply first
+/// * `second` - Key to apply second
+/// * `rng` - Random number generator
+#[cfg(feature = "rsa")]
+fn rsa_round_trip(
+    rsa: &RSA,
+    message: &[u8],
+    first: AsymmetricKeyMode,
+    second: AsymmetricKeyMode,
+    rng: &mut impl CryptoRngCore
+) -> bool
+{
+    match rsa.encrypt(message, first, rng) {
+        Ok(output) => rsa.decrypt(&output, second, rng) == Ok(message.to_vec()),
+        Err(_) => false
+    }
+}
+
+/// Rebuilds the keypair, signs the digest with the vector's nonce and
+/// compares the signature, then verifies it
+/// 
+/// ### Arguments
+/// 
+/// * `vector` - Vector to check against
+/// * `rng` - Random number generator
+#[cfg(feature = "ecdsa")]
+fn check_ecdsa(vector: &EcdsaVector, rng: &mut impl CryptoRngCore) -> bool {
+    let mut keypair = ECPKeypair::new(ECPSupportedCurves::SECP256R1);
+    keypair.d = biguint(vector.private_key);
+    keypair.q = keypair.multiply(rng);
+
+    let mut ecdsa = ECDSA::from_keypair(keypair);
+
+    match ecdsa.public_key() {
+        Ok(key) if key.point_bytes() == &unhex(vector.public_key)[..] => (),
+        _ => return false
+    }
+
+    let digest = unhex(vector.digest);
+
+    let expected = ECDSASignature {
+        r: biguint(vector.r).to_bigint().unwrap(),
+        s: biguint(vector.s).to_bigint().unwrap()
+    };
+
+    ecdsa.sign_with_nonce(&digest, &biguint(vector.nonce), rng) == Some(expected.clone())
+    && ecdsa.verify(&digest, &expected, rng).is_ok()
+}
+
+/// Runs X25519 on the vector's scalar and u-coordinate
+/// 
+/// ### Arguments
+/// 
+/// * `vector` - Vector to check against
+/// * `rng` - Random number generator
+#[cfg(feature = "ecc")]
+fn check_x25519(vector: &X25519Vector, rng: &mut impl CryptoRngCore) -> bool {
+    let (scalar, u) = (unhex(vector.scalar), unhex(vector.u));
+
+    if scalar.len() != X25519_LENGTH || u.len() != X25519_LENGTH {
+        return false;
+    }
+
+    let mut scalar_array = [0; X25519_LENGTH];
+    let mut u_array = [0; X25519_LENGTH];
+    scalar_array.copy_from_slice(&scalar);
+    u_array.copy_from_slice(&u);
+
+    match x25519(&scalar_array, &u_array, rng) {
+        Ok(output) => output[..] == unhex(vector.output)[..],
+        Err(_) => false
+    }
+}
+
+/// Runs both sides of the exchange with fixed private values and
+/// compares their shared keys
+/// 
+/// ### Arguments
+/// 
+/// * `vector` - Vector to check against
+/// * `rng` - Random number generator
+#[cfg(feature = "dh")]
+fn check_dh(vector: &DhVector, rng: &mut impl CryptoRngCore) -> bool {
+    let (p, g) = (biguint(vector.p), biguint(vector.g));
+
+    let (mut alice, mut bob) = match (
+        DiffieHellman::from_private_value(&p, &g, &biguint(vector.x_a)),
+        DiffieHellman::from_private_value(&p, &g, &biguint(vector.x_b))
+    ) {
+        (Ok(alice), Ok(bob)) => (alice, bob),
+        _ => return false
+    };
+
+    let expected = biguint(vector.shared);
+    let (alice_gx, bob_gx) = (alice.gx.clone(), bob.gx.clone());
+
+    alice.generate_shared_key(rng, &bob_gx) == Ok(expected.clone())
+    && bob.generate_shared_key(rng, &alice_gx) == Ok(expected)
+}
+
+/// Decodes an embedded hex string. Invalid hex gives an empty vector,
+/// which fails whichever check it belongs to
+/// 
+/// ### Arguments
+/// 
+/// * `hex` - Hex string to decode
+fn unhex(hex: &str) -> Vec<u8> {
+    hex.from_hex().unwrap_or(Vec::new())
+}
+
+/// Decodes an embedded big-endian hex integer
+/// 
+/// ### Arguments
+/// 
+/// * `hex` - Hex string to decode
+fn biguint(hex: &str) -> BigUint {
+    BigUint::from_bytes_be(&unhex(hex))
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod self_test_test {
+
+    use crate::error::Error;
+    use crate::self_test::{ self_test, run, KnownAnswers, Primitive };
+
+    fn failures(vectors: KnownAnswers) -> Vec<Primitive> {
+        match run(&vectors) {
+            Err(Error::SelfTest(report)) => report.failures().into_iter().cloned().collect(),
+            Ok(_) => Vec::new(),
+            Err(e) => panic!("Unexpected error: {}", e)
+        }
+    }
+
+    #[test]
+    fn embedded_vectors_pass() {
+        let report = self_test().unwrap();
+
+        assert!(report.passed());
+        assert!(report.failures().is_empty());
+        assert!(report.results.len() > KnownAnswers::embedded().hashes.len());
+    }
+
+    #[test]
+    fn corrupted_hash_vector_fails_alone() {
+        let mut vectors = KnownAnswers::embedded();
+        let algorithm = vectors.hashes[0].0.clone();
+        vectors.hashes[0].1 = "00";
+
+        assert_eq!(failures(vectors), vec![Primitive::Hash(algorithm)]);
+    }
+
+    #[test]
+    #[cfg(feature = "aes")]
+    fn corrupted_aes_gcm_vector_fails_alone() {
+        let mut vectors = KnownAnswers::embedded();
+        vectors.aes_gcm.tag = "5bc94fbc3221a5db94fae95ae7121a48";
+
+        assert_eq!(failures(vectors), vec![Primitive::AesGcm]);
+    }
+
+    #[test]
+    #[cfg(feature = "rsa")]
+    fn corrupted_rsa_vector_fails_alone() {
+        let mut vectors = KnownAnswers::embedded();
+        vectors.rsa.d = "ace1f4a458819d49c73d8a36255f95187b116dc4be9a2ed220e387772588261d8aed07605ea5e5d2f7356e22f80c29ce3e80969bc1fbe4c20b4e943188debca3";
+
+        assert_eq!(failures(vectors), vec![Primitive::Rsa]);
+    }
+
+    #[test]
+    #[cfg(feature = "ecdsa")]
+    fn corrupted_ecdsa_vector_fails_alone() {
+        let mut vectors = KnownAnswers::embedded();
+        vectors.ecdsa.nonce = "a6e3c57dd01abe90086538398355dd4c3b17aa873382b0f24d6129493d8aad61";
+
+        assert_eq!(failures(vectors), vec![Primitive::Ecdsa]);
+    }
+
+    #[test]
+    #[cfg(feature = "ecc")]
+    fn corrupted_x25519_vector_fails_alone() {
+        let mut vectors = KnownAnswers::embedded();
+        vectors.x25519.u = "e6db6867583030db3594c1a424b15f7c726624ec26b3353b10a903a6d0ab1c4d";
+
+        assert_eq!(failures(vectors), vec![Primitive::X25519]);
+    }
+
+    #[test]
+    #[cfg(feature = "dh")]
+    fn corrupted_dh_vector_fails_alone() {
+        let mut vectors = KnownAnswers::embedded();
+        vectors.dh.shared = "03";
+
+        assert_eq!(failures(vectors), vec![Primitive::DiffieHellman]);
+    }
+}
\ No newline at end of file
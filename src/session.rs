@@ -0,0 +1,816 @@
+//! Reference wiring of a key exchange, ECDSA signatures and AES-GCM into
+//! an authenticated session.
+//!
+//! The initiator sends its public value signed over a hash of the
+//! handshake so far. The responder checks that signature against the
+//! initiator's known verification key, then replies with its own public
+//! value signed over a hash covering both messages. Both sides derive a
+//! key per direction from the shared secret with HKDF, salted with the
+//! final transcript hash. Frames carry an explicit 64-bit counter, taken
+//! from a `utils::nonce::NonceSequence` and doubling as the GCM nonce,
+//! and must arrive strictly in order.
+//!
+//! Verification keys are not sent in the handshake; each side must
+//! already hold the other's key, since a key taken from the handshake
+//! itself would let a man in the middle substitute their own.
+//!
+//! `initiate` and `respond` run over AES-256-GCM and SHA3-256. Peers that
+//! negotiated a suite instead start with `initiate_agreed` and
+//! `respond_agreed`, which use the suite's curve, cipher and hash and
+//! bind the negotiation transcript into both signed hashes.
+//!
+//! With the trace feature, `initiate_traced` and `respond_traced` install
+//! a `transcript::Transcript` that follows the session into its channel
+
+use std::fmt;
+use num_bigint::{ BigInt, Sign };
+use crypto::aes::KeySize;
+use crypto::aes_gcm::AesGcm;
+use crypto::aead::{ AeadEncryptor, AeadDecryptor };
+
+use crate::error::Error;
+use crate::hash::kdf::hkdf;
+use crate::hash::crypto::{ hash_message, HashAlgorithm };
+use crate::key_exchange::KeyExchange;
+use crate::key_exchange::ecdh::ECDH;
+use crate::negotiation::{ Aead, Agreement };
+use crate::signature::ecdsa::{ ECDSA, ECDSASignature };
+use crate::utils::ecc::ECPPublicKey;
+use crate::utils::rng::CryptoRngCore;
+use crate::utils::zeroize::zeroize_bytes;
+use crate::utils::nonce::{ NonceSequence, NONCE_LENGTH, PREFIX_LENGTH, counter_nonce };
+#[cfg(feature = "trace")]
+use crate::transcript::{ self, Event, Role, Transcript, Tracer };
+
+
+/*---- MACROS ----*/
+
+/// Records an event on a tracer, if one is installed. The event is only
+/// built when it is recorded, and without the trace feature not at all.
+/// The `role =>` form binds the side the session plays
+#[cfg(feature = "trace")]
+macro_rules! trace {
+    ($tracer:expr, $role:ident => $event:expr) => {
+        if let Some(ref mut tracer) = $tracer {
+            let $role = tracer.role;
+            tracer.record($event);
+        }
+    };
+    ($tracer:expr, $event:expr) => {
+        if let Some(ref mut tracer) = $tracer {
+            tracer.record($event);
+        }
+    };
+}
+
+#[cfg(not(feature = "trace"))]
+macro_rules! trace {
+    ($tracer:expr, $($event:tt)*) => {
+        let _ = &mut $tracer;
+    };
+}
+
+
+/*---- CONSTANTS ----*/
+
+/// Domain separation labels for the two transcript hashes
+const INITIATE_LABEL: &'static [u8] = b"cryptofun session v1 initiate";
+const RESPOND_LABEL: &'static [u8] = b"cryptofun session v1 respond";
+
+/// HKDF info string for the direction keys
+const KDF_INFO: &'static [u8] = b"cryptofun session v1 keys";
+
+/// Hash algorithm for the transcript and HKDF, unless negotiated
+const HASH_ALGORITHM: HashAlgorithm = HashAlgorithm::Sha3_256;
+
+/// Length in bytes of the frame counter
+const COUNTER_LENGTH: usize = NONCE_LENGTH - PREFIX_LENGTH;
+
+/// Prefix of the frame nonces, which need none since each direction has its own key
+const NONCE_PREFIX: [u8; PREFIX_LENGTH] = [0; PREFIX_LENGTH];
+
+/// Length in bytes of the GCM authentication tag
+const TAG_LENGTH: usize = 16;
+
+
+/*---- ENUMS ----*/
+
+/// Errors raised while running the handshake or exchanging frames
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SessionError {
+    MalformedHandshake,         // handshake message could not be parsed
+    HandshakeRejected,          // peer signature over the transcript did not verify
+    MalformedFrame,             // frame is shorter than its counter and tag
+    ReplayedFrame,              // frame counter has already been received
+    OutOfOrderFrame,            // frame counter is ahead of the next expected one
+    AuthenticationFailed,       // frame failed GCM authentication
+    CounterExhausted            // no more frames can be sent under these keys
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SessionError::MalformedHandshake => write!(f, "Handshake message is malformed"),
+            SessionError::HandshakeRejected => write!(f, "Peer signature over the handshake did not verify"),
+            SessionError::MalformedFrame => write!(f, "Frame is too short"),
+            SessionError::ReplayedFrame => write!(f, "Frame has already been received"),
+            SessionError::OutOfOrderFrame => write!(f, "Frame arrived out of order"),
+            SessionError::AuthenticationFailed => write!(f, "Frame failed authentication"),
+            SessionError::CounterExhausted => write!(f, "Frame counter is exhausted")
+        }
+    }
+}
+
+
+/*---- STRUCTS ----*/
+
+/// Installed tracer, which is always absent without the trace feature
+#[cfg(feature = "trace")]
+type Traces = Option<Tracer>;
+#[cfg(not(feature = "trace"))]
+type Traces = Option<()>;
+
+/// Algorithms a handshake runs with
+#[derive(Clone)]
+struct Parameters {
+    aead: Aead,             // cipher for the frames
+    hash: HashAlgorithm,    // hash for the transcript and HKDF
+    binding: Vec<u8>        // negotiation transcript, empty when nothing was negotiated
+}
+
+/// Initiator side of a handshake that is waiting for the reply
+pub struct Session<K> {
+    kex: K,                     // key exchange whose public value was sent
+    message: Vec<u8>,           // handshake message sent to the responder
+    parameters: Parameters,     // algorithms the handshake runs with
+    tracer: Traces              // transcript handed on to the channel
+}
+
+/// Encrypted, authenticated and ordered channel produced by a handshake
+pub struct SecureChannel {
+    send_key: Vec<u8>,              // key for frames we send
+    recv_key: Vec<u8>,              // key for frames we receive
+    key_size: KeySize,              // AES key size of both direction keys
+    send_nonces: NonceSequence,     // nonces of the frames we send, whose counters go on the wire
+    recv_counter: u64,              // counter of the next frame to accept
+    tracer: Traces                  // transcript of the session, if any
+}
+
+
+/*---- IMPLEMENTATIONS ----*/
+
+impl Parameters {
+
+    /// AES-256-GCM and SHA3-256, with no negotiation to bind
+    fn fixed() -> Self {
+        Parameters {
+            aead: Aead::Aes256Gcm,
+            hash: HASH_ALGORITHM,
+            binding: Vec::new()
+        }
+    }
+
+    /// Algorithms of a negotiated suite, bound to its transcript
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `agreement` - Outcome of the negotiation
+    fn agreed(agreement: &Agreement) -> Self {
+        Parameters {
+            aead: agreement.suite.aead.clone(),
+            hash: agreement.suite.hash.clone(),
+            binding: agreement.transcript.clone()
+        }
+    }
+}
+
+impl<K: KeyExchange> Session<K> where Error: From<K::Error> {
+
+    /// Starts a handshake. Returns the pending session and the message
+    /// to send to the responder
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `kex` - Key exchange holding a fresh keypair
+    /// * `signer` - Long-term signing key of the initiator
+    /// * `rng` - Random number generator
+    pub fn initiate(kex: K, signer: &mut ECDSA, rng: &mut impl CryptoRngCore) -> Result<(Session<K>, Vec<u8>), Error> {
+        Session::initiate_with(kex, Parameters::fixed(), signer, None, rng)
+    }
+
+    /// Answers a handshake. Returns the channel and the reply to send
+    /// back to the initiator
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `kex` - Key exchange holding a fresh keypair
+    /// * `signer` - Long-term signing key of the responder
+    /// * `initiator_key` - Known verification key of the initiator
+    /// * `message` - Handshake message from the initiator
+    /// * `rng` - Random number generator
+    pub fn respond(
+        kex: K,
+        signer: &mut ECDSA,
+        initiator_key: &ECPPublicKey,
+        message: &[u8],
+        rng: &mut impl CryptoRngCore
+    ) -> Result<(SecureChannel, Vec<u8>), Error>
+    {
+        Session::respond_with(kex, Parameters::fixed(), signer, initiator_key, message, None, rng)
+    }
+
+    /// Starts a handshake like `initiate`, recording it and the channel
+    /// it leads to on a transcript
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `kex` - Key exchange holding a fresh keypair
+    /// * `signer` - Long-term signing key of the initiator
+    /// * `sink` - Transcript to record to
+    /// * `rng` - Random number generator
+    #[cfg(feature = "trace")]
+    pub fn initiate_traced(kex: K, signer: &mut ECDSA, sink: Box<dyn Transcript>, rng: &mut impl CryptoRngCore) -> Result<(Session<K>, Vec<u8>), Error> {
+        Session::initiate_with(kex, Parameters::fixed(), signer, Some(Tracer::new(Role::Initiator, sink)), rng)
+    }
+
+    /// Answers a handshake like `respond`, recording it and the channel
+    /// it leads to on a transcript
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `kex` - Key exchange holding a fresh keypair
+    /// * `signer` - Long-term signing key of the responder
+    /// * `initiator_key` - Known verification key of the initiator
+    /// * `message` - Handshake message from the initiator
+    /// * `sink` - Transcript to record to
+    /// * `rng` - Random number generator
+    #[cfg(feature = "trace")]
+    pub fn respond_traced(
+        kex: K,
+        signer: &mut ECDSA,
+        initiator_key: &ECPPublicKey,
+        message: &[u8],
+        sink: Box<dyn Transcript>,
+        rng: &mut impl CryptoRngCore
+    ) -> Result<(SecureChannel, Vec<u8>), Error>
+    {
+        Session::respond_with(kex, Parameters::fixed(), signer, initiator_key, message, Some(Tracer::new(Role::Responder, sink)), rng)
+    }
+
+    /// Completes the handshake with the responder's reply
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `responder_key` - Known verification key of the responder
+    /// * `reply` - Handshake reply from the responder
+    /// * `rng` - Random number generator
+    pub fn complete(mut self, responder_key: &ECPPublicKey, reply: &[u8], rng: &mut impl CryptoRngCore) -> Result<SecureChannel, Error> {
+        let (responder_bytes, responder_signature) = read_handshake(reply)?;
+        let transcript = respond_transcript(&self.parameters, &self.message, responder_bytes);
+        trace!(self.tracer, Event::PublicValue { from: Role::Responder, value: responder_bytes.to_vec() });
+        trace!(self.tracer, Event::HandshakeHash { signer: Role::Responder, hash: transcript.clone() });
+        verify(responder_key, &transcript, &responder_signature, rng)?;
+
+        let shared_secret = self.kex.shared_secret(responder_bytes, rng)?;
+        trace!(self.tracer, secret_event("shared secret", &shared_secret));
+        let (to_initiator, to_responder) = derive_keys(&self.parameters, shared_secret, &transcript)?;
+        trace!(self.tracer, secret_event("key to responder", &to_responder));
+        trace!(self.tracer, secret_event("key to initiator", &to_initiator));
+
+        Ok(SecureChannel::new(&self.parameters, to_responder, to_initiator, self.tracer.take()))
+    }
+
+    /// Starts a handshake under the given parameters
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `kex` - Key exchange holding a fresh keypair
+    /// * `parameters` - Algorithms the handshake runs with
+    /// * `signer` - Long-term signing key of the initiator
+    /// * `tracer` - Transcript to record to, if any
+    /// * `rng` - Random number generator
+    fn initiate_with(kex: K, parameters: Parameters, signer: &mut ECDSA, mut tracer: Traces, rng: &mut impl CryptoRngCore) -> Result<(Session<K>, Vec<u8>), Error> {
+        let public_bytes = kex.public_bytes();
+        let transcript = initiate_transcript(&parameters, &public_bytes);
+        trace!(tracer, Event::PublicValue { from: Role::Initiator, value: public_bytes.clone() });
+        trace!(tracer, Event::HandshakeHash { signer: Role::Initiator, hash: transcript.clone() });
+        let signature = signer.sign(&transcript, rng)?;
+
+        let mut message = Vec::new();
+        write_field(&mut message, &public_bytes);
+        write_signature(&mut message, &signature);
+
+        Ok((Session { kex: kex, message: message.clone(), parameters: parameters, tracer: tracer }, message))
+    }
+
+    /// Answers a handshake under the given parameters
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `kex` - Key exchange holding a fresh keypair
+    /// * `parameters` - Algorithms the handshake runs with
+    /// * `signer` - Long-term signing key of the responder
+    /// * `initiator_key` - Known verification key of the initiator
+    /// * `message` - Handshake message from the initiator
+    /// * `tracer` - Transcript to record to, if any
+    /// * `rng` - Random number generator
+    fn respond_with(
+        kex: K,
+        parameters: Parameters,
+        signer: &mut ECDSA,
+        initiator_key: &ECPPublicKey,
+        message: &[u8],
+        mut tracer: Traces,
+        rng: &mut impl CryptoRngCore
+    ) -> Result<(SecureChannel, Vec<u8>), Error>
+    {
+        let (initiator_bytes, initiator_signature) = read_handshake(message)?;
+        let initiator_transcript = initiate_transcript(&parameters, initiator_bytes);
+        trace!(tracer, Event::PublicValue { from: Role::Initiator, value: initiator_bytes.to_vec() });
+        trace!(tracer, Event::HandshakeHash { signer: Role::Initiator, hash: initiator_transcript.clone() });
+        verify(initiator_key, &initiator_transcript, &initiator_signature, rng)?;
+
+        let public_bytes = kex.public_bytes();
+        let transcript = respond_transcript(&parameters, message, &public_bytes);
+        trace!(tracer, Event::PublicValue { from: Role::Responder, value: public_bytes.clone() });
+        trace!(tracer, Event::HandshakeHash { signer: Role::Responder, hash: transcript.clone() });
+        let signature = signer.sign(&transcript, rng)?;
+
+        let mut reply = Vec::new();
+        write_field(&mut reply, &public_bytes);
+        write_signature(&mut reply, &signature);
+
+        let shared_secret = kex.shared_secret(initiator_bytes, rng)?;
+        trace!(tracer, secret_event("shared secret", &shared_secret));
+        let (to_initiator, to_responder) = derive_keys(&parameters, shared_secret, &transcript)?;
+        trace!(tracer, secret_event("key to responder", &to_responder));
+        trace!(tracer, secret_event("key to initiator", &to_initiator));
+
+        Ok((SecureChannel::new(&parameters, to_initiator, to_responder, tracer), reply))
+    }
+}
+
+impl Session<ECDH> {
+
+    /// Starts a handshake over a negotiated suite, with a fresh key
+    /// exchange on the suite's curve
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `agreement` - Outcome of the negotiation with the responder
+    /// * `signer` - Long-term signing key of the initiator
+    /// * `rng` - Random number generator
+    pub fn initiate_agreed(agreement: &Agreement, signer: &mut ECDSA, rng: &mut impl CryptoRngCore) -> Result<(Session<ECDH>, Vec<u8>), Error> {
+        let kex = agreement.suite.key_exchange(rng)?;
+        Session::initiate_with(kex, Parameters::agreed(agreement), signer, None, rng)
+    }
+
+    /// Answers a handshake over a negotiated suite. Fails if the
+    /// initiator signed a different negotiation transcript
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `agreement` - Outcome of the negotiation with the initiator
+    /// * `signer` - Long-term signing key of the responder
+    /// * `initiator_key` - Known verification key of the initiator
+    /// * `message` - Handshake message from the initiator
+    /// * `rng` - Random number generator
+    pub fn respond_agreed(
+        agreement: &Agreement,
+        signer: &mut ECDSA,
+        initiator_key: &ECPPublicKey,
+        message: &[u8],
+        rng: &mut impl CryptoRngCore
+    ) -> Result<(SecureChannel, Vec<u8>), Error>
+    {
+        let kex = agreement.suite.key_exchange(rng)?;
+        Session::respond_with(kex, Parameters::agreed(agreement), signer, initiator_key, message, None, rng)
+    }
+}
+
+impl SecureChannel {
+
+    /// Creates a channel from its direction keys
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `parameters` - Algorithms the handshake ran with
+    /// * `send_key` - Key for frames we send
+    /// * `recv_key` - Key for frames we receive
+    /// * `tracer` - Transcript to record to, if any
+    fn new(parameters: &Parameters, send_key: Vec<u8>, recv_key: Vec<u8>, tracer: Traces) -> Self {
+        SecureChannel {
+            send_key: send_key,
+            recv_key: recv_key,
+            key_size: parameters.aead.key_size(),
+            send_nonces: NonceSequence::counter(NONCE_PREFIX),
+            recv_counter: 0,
+            tracer: tracer
+        }
+    }
+
+    /// Seals a message into a frame: counter || ciphertext || tag
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `plaintext` - Message to send
+    pub fn send(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        let nonce = match self.send_nonces.next() {
+            Ok(nonce) => nonce,
+            Err(_) => return Err(SessionError::CounterExhausted.into())
+        };
+
+        let counter = &nonce[PREFIX_LENGTH..];
+        let mut ciphertext = vec![0; plaintext.len()];
+        let mut tag = [0; TAG_LENGTH];
+
+        let mut encryptor = AesGcm::new(self.key_size, &self.send_key, &nonce, counter);
+        encryptor.encrypt(plaintext, &mut ciphertext, &mut tag);
+        trace!(self.tracer, role => Event::Frame { from: role, counter: self.send_nonces.used() - 1 });
+
+        let mut frame = Vec::with_capacity(COUNTER_LENGTH + ciphertext.len() + TAG_LENGTH);
+        frame.extend_from_slice(counter);
+        frame.extend(ciphertext);
+        frame.extend_from_slice(&tag);
+
+        Ok(frame)
+    }
+
+    /// Opens the next frame. Frames that were already received, skip
+    /// ahead or fail authentication are rejected without moving the
+    /// expected counter on
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `frame` - Frame produced by the peer's `send`
+    pub fn recv(&mut self, frame: &[u8]) -> Result<Vec<u8>, Error> {
+        if frame.len() < COUNTER_LENGTH + TAG_LENGTH {
+            return Err(SessionError::MalformedFrame.into());
+        }
+
+        let (counter, rest) = frame.split_at(COUNTER_LENGTH);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LENGTH);
+        let received = u64::from_be_bytes([
+            counter[0], counter[1], counter[2], counter[3],
+            counter[4], counter[5], counter[6], counter[7]
+        ]);
+
+        if received < self.recv_counter {
+            return Err(SessionError::ReplayedFrame.into());
+        }
+
+        if received > self.recv_counter {
+            return Err(SessionError::OutOfOrderFrame.into());
+        }
+
+        let mut plaintext = vec![0; ciphertext.len()];
+        let mut decryptor = AesGcm::new(self.key_size, &self.recv_key, &counter_nonce(&NONCE_PREFIX, received), counter);
+
+        if !decryptor.decrypt(ciphertext, &mut plaintext, tag) {
+            trace!(self.tracer, role => Event::FrameRejected { from: role.peer(), counter: received });
+            return Err(SessionError::AuthenticationFailed.into());
+        }
+
+        trace!(self.tracer, role => Event::Frame { from: role.peer(), counter: received });
+        self.recv_counter += 1;
+        Ok(plaintext)
+    }
+
+    /// Wipes both direction keys, after which the channel fails to
+    /// send or receive
+    pub fn clear(&mut self) -> () {
+        zeroize_bytes(&mut self.send_key);
+        zeroize_bytes(&mut self.recv_key);
+        self.send_nonces.exhaust();
+        self.recv_counter = u64::max_value();
+    }
+}
+
+impl Drop for SecureChannel {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Event recording the fingerprint of a secret, never the secret itself
+/// 
+/// ### Arguments
+/// 
+/// * `label` - Name of the secret
+/// * `secret` - Secret to fingerprint
+#[cfg(feature = "trace")]
+fn secret_event(label: &str, secret: &[u8]) -> Event {
+    Event::SecretFingerprint { label: label.to_string(), fingerprint: transcript::fingerprint(secret) }
+}
+
+/// Hash signed by the initiator. A negotiation transcript, if any, is
+/// written ahead of the public value
+/// 
+/// ### Arguments
+/// 
+/// * `parameters` - Algorithms the handshake runs with
+/// * `initiator_bytes` - Initiator public value
+fn initiate_transcript(parameters: &Parameters, initiator_bytes: &[u8]) -> Vec<u8> {
+    let mut transcript = INITIATE_LABEL.to_vec();
+
+    if !parameters.binding.is_empty() {
+        write_field(&mut transcript, &parameters.binding);
+    }
+
+    write_field(&mut transcript, initiator_bytes);
+
+    hash_message(&transcript, parameters.hash.clone())
+}
+
+/// Hash signed by the responder, covering the whole first message
+/// 
+/// ### Arguments
+/// 
+/// * `parameters` - Algorithms the handshake runs with
+/// * `message` - Handshake message from the initiator
+/// * `responder_bytes` - Responder public value
+fn respond_transcript(parameters: &Parameters, message: &[u8], responder_bytes: &[u8]) -> Vec<u8> {
+    let mut transcript = RESPOND_LABEL.to_vec();
+
+    if !parameters.binding.is_empty() {
+        write_field(&mut transcript, &parameters.binding);
+    }
+
+    write_field(&mut transcript, message);
+    write_field(&mut transcript, responder_bytes);
+
+    hash_message(&transcript, parameters.hash.clone())
+}
+
+/// Derives the initiator-to-responder and responder-to-initiator keys,
+/// in that order, wiping the shared secret
+/// 
+/// ### Arguments
+/// 
+/// * `parameters` - Algorithms the handshake runs with
+/// * `shared_secret` - Output of the key exchange
+/// * `transcript` - Final transcript hash
+fn derive_keys(parameters: &Parameters, mut shared_secret: Vec<u8>, transcript: &[u8]) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    let key_length = parameters.aead.key_length();
+    let okm = hkdf(&shared_secret, transcript, KDF_INFO, 2 * key_length, parameters.hash.clone());
+    zeroize_bytes(&mut shared_secret);
+
+    let mut okm = okm?;
+    let keys = (okm[..key_length].to_vec(), okm[key_length..].to_vec());
+    zeroize_bytes(&mut okm);
+
+    Ok(keys)
+}
+
+/// Checks a peer signature over a transcript hash
+/// 
+/// ### Arguments
+/// 
+/// * `key` - Peer verification key
+/// * `transcript` - Transcript hash
+/// * `signature` - Peer signature
+/// * `rng` - Random number generator
+fn verify(key: &ECPPublicKey, transcript: &[u8], signature: &ECDSASignature, rng: &mut impl CryptoRngCore) -> Result<(), Error> {
+    let mut verifier = ECDSA::from_public_key(key)?;
+
+    match verifier.verify(transcript, signature, rng) {
+        Ok(()) => Ok(()),
+        Err(_) => Err(SessionError::HandshakeRejected.into())
+    }
+}
+
+/// Appends a field with a four byte big-endian length prefix
+/// 
+/// ### Arguments
+/// 
+/// * `output` - Message being built
+/// * `value` - Field value
+fn write_field(output: &mut Vec<u8>, value: &[u8]) -> () {
+    output.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    output.extend_from_slice(value);
+}
+
+/// Appends the R and S values of a signature
+/// 
+/// ### Arguments
+/// 
+/// * `output` - Message being built
+/// * `signature` - Signature to write
+fn write_signature(output: &mut Vec<u8>, signature: &ECDSASignature) -> () {
+    write_field(output, &signature.r.to_bytes_be().1);
+    write_field(output, &signature.s.to_bytes_be().1);
+}
+
+/// Reads the next length-prefixed field, advancing the input past it
+/// 
+/// ### Arguments
+/// 
+/// * `input` - Remaining message
+fn read_field<'a>(input: &mut &'a [u8]) -> Result<&'a [u8], SessionError> {
+    if input.len() < 4 {
+        return Err(SessionError::MalformedHandshake);
+    }
+
+    let length = u32::from_be_bytes([input[0], input[1], input[2], input[3]]) as usize;
+
+    if input.len() - 4 < length {
+        return Err(SessionError::MalformedHandshake);
+    }
+
+    let value = &input[4..4 + length];
+    *input = &input[4 + length..];
+
+    Ok(value)
+}
+
+/// Splits a handshake message into the public value and signature
+/// 
+/// ### Arguments
+/// 
+/// * `message` - Handshake message
+fn read_handshake(mut message: &[u8]) -> Result<(&[u8], ECDSASignature), SessionError> {
+    let public_bytes = read_field(&mut message)?;
+    let r = BigInt::from_bytes_be(Sign::Plus, read_field(&mut message)?);
+    let s = BigInt::from_bytes_be(Sign::Plus, read_field(&mut message)?);
+
+    if !message.is_empty() {
+        return Err(SessionError::MalformedHandshake);
+    }
+
+    Ok((public_bytes, ECDSASignature { r: r, s: s }))
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod session_test {
+
+    use std::collections::VecDeque;
+
+    use crate::error::Error;
+    use crate::hash::crypto::HashAlgorithm;
+    use crate::key_exchange::ecdh::ECDH;
+    use crate::negotiation::{ SuiteCatalog, Suite, KeyExchangeKind, Aead, agree };
+    use crate::signature::ecdsa::ECDSA;
+    use crate::utils::rng::os_rng;
+    use crate::utils::nonce::NonceSequence;
+    use crate::utils::ecc_curves::ECPSupportedCurves;
+    use crate::session::{ Session, SecureChannel, SessionError };
+
+    struct Party {
+        signer: ECDSA,
+        kex: ECDH
+    }
+
+    fn party() -> Party {
+        let mut rng = os_rng().unwrap();
+
+        Party {
+            signer: ECDSA::new(ECPSupportedCurves::BP256R1, &mut rng).unwrap(),
+            kex: ECDH::new(ECPSupportedCurves::Curve25519, &mut rng).unwrap()
+        }
+    }
+
+    fn session_error<T>(result: Result<T, Error>) -> SessionError {
+        match result {
+            Err(Error::Session(e)) => e,
+            Err(other) => panic!("Unexpected error {:?}", other),
+            Ok(_) => panic!("Expected a session error")
+        }
+    }
+
+    fn connect() -> (SecureChannel, SecureChannel) {
+        let mut rng = os_rng().unwrap();
+        let mut alice = party();
+        let mut bob = party();
+        let alice_key = alice.signer.public_key().unwrap();
+        let bob_key = bob.signer.public_key().unwrap();
+
+        let (pending, message) = Session::initiate(alice.kex, &mut alice.signer, &mut rng).unwrap();
+        let (bob_channel, reply) = Session::respond(bob.kex, &mut bob.signer, &alice_key, &message, &mut rng).unwrap();
+        let alice_channel = pending.complete(&bob_key, &reply, &mut rng).unwrap();
+
+        (alice_channel, bob_channel)
+    }
+
+    #[test]
+    fn two_party_exchange_over_pipe() {
+        let (mut alice, mut bob) = connect();
+        let mut to_bob = VecDeque::new();
+        let mut to_alice = VecDeque::new();
+
+        for message in vec![&b"hello"[..], &b""[..], &b"how are you"[..]] {
+            to_bob.push_back(alice.send(message).unwrap());
+        }
+
+        to_alice.push_back(bob.send(b"fine thanks").unwrap());
+
+        assert_eq!(bob.recv(&to_bob.pop_front().unwrap()).unwrap(), b"hello".to_vec());
+        assert_eq!(bob.recv(&to_bob.pop_front().unwrap()).unwrap(), Vec::<u8>::new());
+        assert_eq!(bob.recv(&to_bob.pop_front().unwrap()).unwrap(), b"how are you".to_vec());
+        assert_eq!(alice.recv(&to_alice.pop_front().unwrap()).unwrap(), b"fine thanks".to_vec());
+    }
+
+    #[test]
+    fn tampered_handshake_is_detected() {
+        let mut rng = os_rng().unwrap();
+        let mut alice = party();
+        let mut bob = party();
+        let mut mallory = party();
+        let alice_key = alice.signer.public_key().unwrap();
+        let bob_key = bob.signer.public_key().unwrap();
+
+        let (pending, message) = Session::initiate(alice.kex, &mut alice.signer, &mut rng).unwrap();
+
+        let mut flipped = message.clone();
+        flipped[10] ^= 1;
+        let result = Session::respond(party().kex, &mut party().signer, &alice_key, &flipped, &mut rng);
+        assert_eq!(session_error(result.map(|_| ())), SessionError::HandshakeRejected);
+
+        let (_, substituted) = Session::initiate(mallory.kex, &mut mallory.signer, &mut rng).unwrap();
+        let result = Session::respond(party().kex, &mut party().signer, &alice_key, &substituted, &mut rng);
+        assert_eq!(session_error(result.map(|_| ())), SessionError::HandshakeRejected);
+
+        let truncated = Session::respond(party().kex, &mut party().signer, &alice_key, &message[..20], &mut rng);
+        assert_eq!(session_error(truncated.map(|_| ())), SessionError::MalformedHandshake);
+
+        let (_, mut reply) = Session::respond(bob.kex, &mut bob.signer, &alice_key, &message, &mut rng).unwrap();
+        reply[10] ^= 1;
+        assert_eq!(session_error(pending.complete(&bob_key, &reply, &mut rng).map(|_| ())), SessionError::HandshakeRejected);
+    }
+
+    #[test]
+    fn out_of_order_and_replayed_frames_are_rejected() {
+        let (mut alice, mut bob) = connect();
+        let first = alice.send(b"first").unwrap();
+        let second = alice.send(b"second").unwrap();
+
+        assert_eq!(session_error(bob.recv(&second)), SessionError::OutOfOrderFrame);
+        assert_eq!(bob.recv(&first).unwrap(), b"first".to_vec());
+        assert_eq!(session_error(bob.recv(&first)), SessionError::ReplayedFrame);
+
+        let mut tampered = second.clone();
+        tampered[9] ^= 1;
+        assert_eq!(session_error(bob.recv(&tampered)), SessionError::AuthenticationFailed);
+        assert_eq!(session_error(bob.recv(&second[..10])), SessionError::MalformedFrame);
+        assert_eq!(bob.recv(&second).unwrap(), b"second".to_vec());
+    }
+
+    #[test]
+    fn exhausted_counter_stops_sending() {
+        let (mut alice, mut bob) = connect();
+        let mut state = vec![0; 5];
+        state.extend_from_slice(&(u64::max_value() - 1).to_be_bytes());
+        alice.send_nonces = NonceSequence::from_bytes(&state, &mut os_rng().unwrap()).unwrap();
+
+        let last = alice.send(b"last").unwrap();
+        assert_eq!(last[..8], (u64::max_value() - 1).to_be_bytes());
+        assert_eq!(session_error(alice.send(b"one too many")), SessionError::CounterExhausted);
+        assert_eq!(session_error(bob.recv(&last)), SessionError::OutOfOrderFrame);
+
+        bob.clear();
+        assert_eq!(session_error(bob.send(b"after clearing")), SessionError::CounterExhausted);
+    }
+
+    #[test]
+    fn reflected_frames_are_rejected() {
+        let (_, mut bob) = connect();
+        let reflected = bob.send(b"reply").unwrap();
+
+        assert_eq!(session_error(bob.recv(&reflected)), SessionError::AuthenticationFailed);
+    }
+
+    #[test]
+    fn negotiated_suite_drives_the_handshake() {
+        let mut rng = os_rng().unwrap();
+        let mut alice = party();
+        let mut bob = party();
+        let alice_key = alice.signer.public_key().unwrap();
+        let bob_key = bob.signer.public_key().unwrap();
+
+        let suite = Suite::new(KeyExchangeKind::Ecdh(ECPSupportedCurves::SECP256R1), Aead::Aes128Gcm, HashAlgorithm::Sha3_512);
+        let alice_catalog = SuiteCatalog::new(vec![suite.clone(), Suite::new(KeyExchangeKind::Ecdh(ECPSupportedCurves::Curve25519), Aead::Aes256Gcm, HashAlgorithm::Sha3_256)]);
+        let bob_catalog = SuiteCatalog::new(vec![suite.clone()]);
+
+        let alice_agreement = agree(&alice_catalog, &bob_catalog.offer()).unwrap();
+        let bob_agreement = agree(&bob_catalog, &alice_catalog.offer()).unwrap();
+        assert_eq!(alice_agreement.suite, suite);
+
+        let (pending, message) = Session::initiate_agreed(&alice_agreement, &mut alice.signer, &mut rng).unwrap();
+        let (mut bob_channel, reply) = Session::respond_agreed(&bob_agreement, &mut bob.signer, &alice_key, &message, &mut rng).unwrap();
+        let mut alice_channel = pending.complete(&bob_key, &reply, &mut rng).unwrap();
+
+        assert_eq!(bob_channel.recv(&alice_channel.send(b"negotiated").unwrap()).unwrap(), b"negotiated".to_vec());
+        assert_eq!(alice_channel.recv(&bob_channel.send(b"agreed").unwrap()).unwrap(), b"agreed".to_vec());
+
+        // Bob saw an offer with the other suite stripped, so his transcript differs
+        let stripped = agree(&bob_catalog, &SuiteCatalog::new(vec![suite]).offer()).unwrap();
+        let (_, message) = Session::initiate_agreed(&alice_agreement, &mut alice.signer, &mut rng).unwrap();
+        let result = Session::respond_agreed(&stripped, &mut bob.signer, &alice_key, &message, &mut rng);
+        assert_eq!(session_error(result.map(|_| ())), SessionError::HandshakeRejected);
+    }
+}
\ No newline at end of file
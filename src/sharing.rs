@@ -0,0 +1,397 @@
+//! Shamir secret sharing over GF(2^8), for backing up private keys.
+//!
+//! Each byte of the secret is the constant term of its own random
+//! polynomial of degree k - 1, and share i holds the value of every
+//! polynomial at x = i. Any k shares recover the secret by Lagrange
+//! interpolation at zero, while fewer reveal nothing about it. Field
+//! arithmetic uses the AES polynomial and never branches on or indexes
+//! by secret values.
+//!
+//! A share is its index, the threshold, one byte per secret byte and a
+//! checksum over the rest, which catches shares that were damaged or
+//! mistyped. The checksum is not a MAC: it does not stop a share holder
+//! from deliberately handing in a forged share
+
+use std::fmt;
+
+use crate::hash::crypto::{ hash_message, HashAlgorithm };
+use crate::utils::ct;
+use crate::utils::gf::{ gf256_mul, gf256_inverse };
+use crate::utils::rng::CryptoRngCore;
+use crate::utils::zeroize::zeroize_bytes;
+
+#[cfg(feature = "keys")]
+use crate::error::Error;
+#[cfg(feature = "keys")]
+use crate::keys::{ self, ExportableKey, KeyKind };
+
+
+/*---- CONSTANTS ----*/
+
+/// Length in bytes of the checksum at the end of each share
+pub const CHECKSUM_LENGTH: usize = 4;
+
+/// Hash the checksum is taken from
+const CHECKSUM_HASH_ALGORITHM: HashAlgorithm = HashAlgorithm::Sha3_256;
+
+
+/*---- ENUMS ----*/
+
+/// Errors raised while splitting a secret or combining shares
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SharingError {
+    InvalidThreshold,       // threshold is zero or above the number of shares
+    EmptySecret,            // there is nothing to split
+    MalformedShare,         // encoded share is too short to hold its fields
+    CorruptedShare(u8),     // share with this index failed its checksum
+    DuplicateIndex(u8),     // two shares have this index
+    InconsistentShares,     // shares disagree on the threshold or the secret length
+    NotEnoughShares         // fewer shares than the threshold were given
+}
+
+impl fmt::Display for SharingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SharingError::InvalidThreshold => write!(f, "Threshold must be between 1 and the number of shares"),
+            SharingError::EmptySecret => write!(f, "Secret is empty"),
+            SharingError::MalformedShare => write!(f, "Share is malformed"),
+            SharingError::CorruptedShare(index) => write!(f, "Share {} failed its checksum", index),
+            SharingError::DuplicateIndex(index) => write!(f, "Share {} was given more than once", index),
+            SharingError::InconsistentShares => write!(f, "Shares come from different splits"),
+            SharingError::NotEnoughShares => write!(f, "Fewer shares than the threshold were given")
+        }
+    }
+}
+
+
+/*---- STRUCTS ----*/
+
+/// One share of a split secret
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Share {
+    index: u8,                          // x coordinate, from 1 to n
+    threshold: u8,                      // shares needed to recover the secret
+    value: Vec<u8>,                     // polynomial values at the index, one per secret byte
+    checksum: [u8; CHECKSUM_LENGTH]     // checksum over the fields above
+}
+
+
+/*---- IMPLEMENTATIONS ----*/
+
+impl Share {
+
+    /// Index of the share, from 1 to the number of shares
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// Number of shares needed to recover the secret
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// Encodes the share as index || threshold || value || checksum
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.index, self.threshold];
+        bytes.extend_from_slice(&self.value);
+        bytes.extend_from_slice(&self.checksum);
+
+        bytes
+    }
+
+    /// Decodes a share written by `to_bytes`, checking its checksum
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `bytes` - Encoded share
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SharingError> {
+        if bytes.len() < 3 + CHECKSUM_LENGTH {
+            return Err(SharingError::MalformedShare);
+        }
+
+        let (fields, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LENGTH);
+        let mut share = Share {
+            index: fields[0],
+            threshold: fields[1],
+            value: fields[2..].to_vec(),
+            checksum: [0; CHECKSUM_LENGTH]
+        };
+
+        share.checksum.copy_from_slice(checksum);
+        share.check()?;
+
+        Ok(share)
+    }
+
+    /// Checks the checksum and that the index and threshold are usable
+    fn check(&self) -> Result<(), SharingError> {
+        if !ct::ct_eq(&self.checksum, &share_checksum(self.index, self.threshold, &self.value)) {
+            return Err(SharingError::CorruptedShare(self.index));
+        }
+
+        if self.index == 0 || self.threshold == 0 {
+            return Err(SharingError::MalformedShare);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for Share {
+    fn drop(&mut self) {
+        zeroize_bytes(&mut self.value);
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Splits a secret into `n` shares, any `k` of which recover it
+/// 
+/// ### Arguments
+/// 
+/// * `secret` - Secret to split
+/// * `k` - Threshold, from 1 to `n`
+/// * `n` - Number of shares to produce
+/// * `rng` - Random number generator for the polynomial coefficients
+pub fn split(secret: &[u8], k: u8, n: u8, rng: &mut impl CryptoRngCore) -> Result<Vec<Share>, SharingError> {
+    if k == 0 || k > n {
+        return Err(SharingError::InvalidThreshold);
+    }
+
+    if secret.is_empty() {
+        return Err(SharingError::EmptySecret);
+    }
+
+    let mut values = vec![vec![0; secret.len()]; n as usize];
+    let mut coefficients = vec![0; k as usize];
+
+    for (position, byte) in secret.iter().enumerate() {
+        // coefficients[0] is the secret byte, the others are random
+        coefficients[0] = *byte;
+        rng.fill_bytes(&mut coefficients[1..]);
+
+        for (x, value) in (1..=n).zip(values.iter_mut()) {
+            value[position] = evaluate(&coefficients, x);
+        }
+    }
+
+    zeroize_bytes(&mut coefficients);
+
+    Ok((1..=n).zip(values.into_iter()).map(|(index, value)| {
+        Share {
+            index: index,
+            threshold: k,
+            checksum: share_checksum(index, k, &value),
+            value: value
+        }
+    }).collect())
+}
+
+/// Recovers a secret from at least as many shares as its threshold.
+/// Every share is checked against its checksum; beyond the threshold,
+/// extra shares are checked but not used
+/// 
+/// ### Arguments
+/// 
+/// * `shares` - Shares of one split, in any order
+pub fn combine(shares: &[Share]) -> Result<Vec<u8>, SharingError> {
+    let first = match shares.first() {
+        Some(first) => first,
+        None => return Err(SharingError::NotEnoughShares)
+    };
+
+    for (position, share) in shares.iter().enumerate() {
+        share.check()?;
+
+        if share.threshold != first.threshold || share.value.len() != first.value.len() {
+            return Err(SharingError::InconsistentShares);
+        }
+
+        if shares[..position].iter().any(|other| other.index == share.index) {
+            return Err(SharingError::DuplicateIndex(share.index));
+        }
+    }
+
+    if shares.len() < first.threshold as usize {
+        return Err(SharingError::NotEnoughShares);
+    }
+
+    let used = &shares[..first.threshold as usize];
+    let mut secret = vec![0; first.value.len()];
+
+    // Lagrange basis at zero: l_i = prod over j != i of x_j / (x_j - x_i),
+    // where subtraction in GF(2^8) is XOR
+    for (i, share) in used.iter().enumerate() {
+        let mut basis = 1;
+
+        for (j, other) in used.iter().enumerate() {
+            if i != j {
+                basis = gf256_mul(basis, gf256_mul(other.index, gf256_inverse(other.index ^ share.index)));
+            }
+        }
+
+        for (byte, value) in secret.iter_mut().zip(share.value.iter()) {
+            *byte ^= gf256_mul(basis, *value);
+        }
+    }
+
+    Ok(secret)
+}
+
+/// Splits the container encoding of a key, without a passphrase, so
+/// that any `k` of the `n` shares restore the key with `combine_key`
+/// 
+/// ### Arguments
+/// 
+/// * `key` - Key to split, usually a private key
+/// * `k` - Threshold, from 1 to `n`
+/// * `n` - Number of shares to produce
+/// * `rng` - Random number generator
+#[cfg(feature = "keys")]
+pub fn split_key(key: &dyn ExportableKey, k: u8, n: u8, rng: &mut impl CryptoRngCore) -> Result<Vec<Share>, Error> {
+    let mut container = keys::save(key, None, rng)?;
+    let shares = split(&container, k, n, rng);
+    zeroize_bytes(&mut container);
+
+    Ok(shares?)
+}
+
+/// Restores a key split by `split_key`
+/// 
+/// ### Arguments
+/// 
+/// * `shares` - At least the threshold number of shares
+#[cfg(feature = "keys")]
+pub fn combine_key(shares: &[Share]) -> Result<KeyKind, Error> {
+    let mut container = combine(shares)?;
+    let key = keys::load(&container, None);
+    zeroize_bytes(&mut container);
+
+    key
+}
+
+/// Evaluates a polynomial at x with Horner's rule
+/// 
+/// ### Arguments
+/// 
+/// * `coefficients` - Coefficients, constant term first
+/// * `x` - Point to evaluate at
+fn evaluate(coefficients: &[u8], x: u8) -> u8 {
+    coefficients.iter().rev().fold(0, |result, coefficient| gf256_mul(result, x) ^ coefficient)
+}
+
+/// Leading bytes of a hash over the index, threshold and value
+/// 
+/// ### Arguments
+/// 
+/// * `index` - Share index
+/// * `threshold` - Share threshold
+/// * `value` - Share value
+fn share_checksum(index: u8, threshold: u8, value: &[u8]) -> [u8; CHECKSUM_LENGTH] {
+    let mut input = vec![index, threshold];
+    input.extend_from_slice(value);
+
+    let digest = hash_message(&input, CHECKSUM_HASH_ALGORITHM);
+    zeroize_bytes(&mut input);
+
+    let mut checksum = [0; CHECKSUM_LENGTH];
+    checksum.copy_from_slice(&digest[..CHECKSUM_LENGTH]);
+
+    checksum
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod sharing_test {
+
+    use rand::{ ChaChaRng, SeedableRng };
+    use crate::sharing::{ Share, SharingError, split, combine };
+
+    fn rng() -> ChaChaRng {
+        ChaChaRng::from_seed(&[0x53, 0x68, 0x61, 0x6d, 0x69, 0x72])
+    }
+
+    #[test]
+    fn any_k_shares_recover_the_secret() {
+        let mut rng = rng();
+        let secret = b"correct horse battery staple".to_vec();
+
+        for &(k, n) in &[(1, 1), (1, 3), (2, 2), (2, 3), (3, 5), (5, 5), (4, 10)] {
+            let shares = split(&secret, k, n, &mut rng).unwrap();
+            assert_eq!(shares.len(), n as usize);
+
+            // Every window of k consecutive shares, wrapping around
+            for start in 0..n as usize {
+                let subset: Vec<Share> = (0..k as usize).map(|i| shares[(start + i) % n as usize].clone()).collect();
+                assert_eq!(combine(&subset), Ok(secret.clone()));
+            }
+
+            assert_eq!(combine(&shares), Ok(secret.clone()));
+
+            if k > 1 {
+                assert_eq!(combine(&shares[..k as usize - 1]), Err(SharingError::NotEnoughShares));
+            }
+        }
+    }
+
+    #[test]
+    fn bad_parameters_and_share_sets_are_rejected() {
+        let mut rng = rng();
+
+        assert_eq!(split(b"secret", 0, 3, &mut rng), Err(SharingError::InvalidThreshold));
+        assert_eq!(split(b"secret", 4, 3, &mut rng), Err(SharingError::InvalidThreshold));
+        assert_eq!(split(b"", 2, 3, &mut rng), Err(SharingError::EmptySecret));
+        assert_eq!(combine(&[]), Err(SharingError::NotEnoughShares));
+
+        let shares = split(b"secret", 2, 3, &mut rng).unwrap();
+        assert_eq!(combine(&[shares[1].clone(), shares[1].clone()]), Err(SharingError::DuplicateIndex(2)));
+
+        let other = split(b"longer secret", 2, 3, &mut rng).unwrap();
+        assert_eq!(combine(&[shares[0].clone(), other[1].clone()]), Err(SharingError::InconsistentShares));
+    }
+
+    #[test]
+    fn corrupted_shares_are_detected() {
+        let mut rng = rng();
+        let shares = split(b"secret", 2, 3, &mut rng).unwrap();
+
+        let encoded = shares[2].to_bytes();
+        assert_eq!(Share::from_bytes(&encoded), Ok(shares[2].clone()));
+        assert_eq!(Share::from_bytes(&encoded[..6]), Err(SharingError::MalformedShare));
+
+        let mut damaged = encoded.clone();
+        damaged[4] ^= 0x10;
+        assert_eq!(Share::from_bytes(&damaged), Err(SharingError::CorruptedShare(3)));
+
+        let mut altered = shares[0].clone();
+        altered.value[0] ^= 1;
+        assert_eq!(combine(&[altered, shares[1].clone()]), Err(SharingError::CorruptedShare(1)));
+    }
+
+    #[cfg(feature = "keys")]
+    #[test]
+    fn ecc_keypair_survives_a_split() {
+        use crate::keys::KeyKind;
+        use crate::sharing::{ split_key, combine_key };
+        use crate::utils::ecc::ECPKeypair;
+        use crate::utils::ecc_curves::ECPSupportedCurves;
+
+        let mut rng = rng();
+        let keypair = ECPKeypair::new(ECPSupportedCurves::SECP256R1).setup(&mut rng).unwrap();
+        let shares = split_key(&keypair, 3, 5, &mut rng).unwrap();
+
+        match combine_key(&[shares[4].clone(), shares[0].clone(), shares[2].clone()]).unwrap() {
+            KeyKind::Ecc(restored) => {
+                assert_eq!(restored.d, keypair.d);
+                assert_eq!((restored.q.x, restored.q.y), (keypair.q.x.clone(), keypair.q.y.clone()));
+            },
+            _ => panic!("Expected an ECC keypair")
+        }
+
+        assert!(combine_key(&shares[..2]).is_err());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,759 @@
+//! Two-of-two co-signing with additively shared keys. Each party holds
+//! a scalar share d_i, and the combined public key is
+//! Q = D_1 + D_2 = (d_1 + d_2) G, so neither party can sign alone.
+//!
+//! Key generation is a single exchange of `KeyShareMessage`s, each
+//! carrying the sender's share D_i with a proof that the sender knows
+//! d_i. Without the proof a party could pick its share as X - D_j and
+//! end up holding the whole key behind Q = X.
+//!
+//! Signatures are Schnorr signatures (R, s) under Q, checked with
+//! s G == R + e Q where e = H(R || Q || message). Each signing session
+//! takes three rounds, with the parties stepping their sessions in
+//! lockstep:
+//!
+//! 1. `Cosigner::begin` draws a fresh nonce k_i and sends a commitment
+//!    to R_i = k_i G
+//! 2. `CommitRound::reveal` takes the peer's commitment and reveals R_i.
+//!    Committing first stops either party from choosing its nonce after
+//!    seeing the other's
+//! 3. `RevealRound::sign` checks the peer's R_j against its commitment
+//!    and sends the partial signature s_i = k_i + e d_i, with R = R_1 + R_2
+//! 4. `PartialRound::combine` checks the peer's partial signature and
+//!    returns (R, s_1 + s_2)
+//!
+//! Every round consumes the previous one, so a nonce is used for exactly
+//! one partial signature and is wiped as soon as that is computed, or as
+//! soon as the session fails. Messages carry the number of the session
+//! they belong to, and any message from another session is rejected.
+//!
+//! The combined key is also a valid ECDSA key, as `Cosigner::aggregate_key`
+//! returns it in the form `ECDSA::from_public_key` takes. ECDSA signing
+//! does not split the same way: s = k^-1 (e + r d) needs the inverse of
+//! the joint nonce multiplied by the joint key. The interactive flow is
+//! Lindell's two-party protocol:
+//!
+//! 1. Party 1 sends its share encrypted under its own Paillier key,
+//!    Enc(d_1), with a proof that it matches D_1
+//! 2. The parties exchange committed nonce points k_1 G and k_2 G as in
+//!    the rounds above, and both compute R = k_1 k_2 G and r = R.x
+//! 3. Party 2 uses the additive homomorphism to send
+//!    Enc(k_2^-1 (e + r d_2) + k_2^-1 r d_1 + p n) for a random p
+//! 4. Party 1 decrypts, multiplies by k_1^-1 and checks the result
+//!    against Q before releasing it
+//!
+//! The crate has no Paillier cryptosystem, so only the key generation
+//! half of ECDSA co-signing is provided here
+
+use std::fmt;
+use std::ops::Rem;
+use num_bigint::BigUint;
+use num_traits::Zero;
+
+use crate::hash::crypto::{ hash_message, HashAlgorithm };
+use crate::utils::ct;
+use crate::utils::jacobian_coords;
+use crate::utils::rng::CryptoRngCore;
+use crate::utils::zeroize::zeroize_biguint;
+use crate::utils::ecc::{ ECPKeypair, ECPPublicKey, EcError, encode_point, decode_point };
+use crate::utils::ecc_curves::{ ECPCurveShape, ECPGroup, ECPPoint, ECPSupportedCurves };
+
+
+/*---- CONSTANTS ----*/
+
+/// Domain separation labels for the hashes taken by the protocol
+const KEY_SHARE_LABEL: &'static [u8] = b"cryptofun cosign v1 key share";
+const COMMITMENT_LABEL: &'static [u8] = b"cryptofun cosign v1 commitment";
+const CHALLENGE_LABEL: &'static [u8] = b"cryptofun cosign v1 challenge";
+
+/// Hash algorithm for commitments and challenges
+const HASH_ALGORITHM: HashAlgorithm = HashAlgorithm::Sha3_256;
+
+
+/*---- ENUMS ----*/
+
+/// Errors raised while combining keys or running a signing session
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum CosignError {
+    KeyGeneration(EcError),     // the key share or a nonce could not be generated
+    UnsupportedCurve,           // co-signing needs a short Weierstrass curve
+    CurveMismatch,              // the peer's share is on another curve
+    InvalidKeyShare,            // the peer's share or its proof of possession is invalid
+    InvalidPoint,               // a point sent by the peer is not on the curve
+    StaleMessage,               // message belongs to another signing session
+    CommitmentMismatch,         // revealed nonce point does not match its commitment
+    InvalidPartialSignature,    // the peer's partial signature does not verify
+    VerificationFailed          // signature does not match the message and key
+}
+
+impl fmt::Display for CosignError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CosignError::KeyGeneration(e) => write!(f, "Key generation failed: {}", e),
+            CosignError::UnsupportedCurve => write!(f, "Co-signing needs a short Weierstrass curve"),
+            CosignError::CurveMismatch => write!(f, "Key shares are on different curves"),
+            CosignError::InvalidKeyShare => write!(f, "Peer key share failed validation"),
+            CosignError::InvalidPoint => write!(f, "Peer point is not a valid curve point"),
+            CosignError::StaleMessage => write!(f, "Message belongs to another signing session"),
+            CosignError::CommitmentMismatch => write!(f, "Peer nonce does not match its commitment"),
+            CosignError::InvalidPartialSignature => write!(f, "Peer partial signature did not verify"),
+            CosignError::VerificationFailed => write!(f, "Verification failed: s G != R + e Q")
+        }
+    }
+}
+
+impl From<EcError> for CosignError {
+    fn from(error: EcError) -> Self {
+        CosignError::KeyGeneration(error)
+    }
+}
+
+
+/*---- STRUCTS ----*/
+
+/// One party's share of the signing key, before it is combined with
+/// the peer's
+pub struct KeyShare {
+    keypair: ECPKeypair     // share d_i and its public point D_i
+}
+
+/// Key share sent to the peer, with a proof of possession of d_i
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyShareMessage {
+    pub public: ECPPublicKey,   // public point D_i of the share
+    pub commitment: Vec<u8>,    // compressed nonce point of the proof
+    pub response: BigUint       // proof response k + c d_i mod n
+}
+
+/// Round 1: commitment to a party's nonce point
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct NonceCommitment {
+    pub session: u64,           // signing session the message belongs to
+    pub commitment: Vec<u8>     // hash binding the session, share and nonce point
+}
+
+/// Round 2: a party's nonce point, opening its commitment
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct NonceReveal {
+    pub session: u64,           // signing session the message belongs to
+    pub nonce_point: Vec<u8>    // compressed nonce point R_i
+}
+
+/// Round 3: a party's share of the signature
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PartialSignature {
+    pub session: u64,           // signing session the message belongs to
+    pub s: BigUint              // partial signature k_i + e d_i mod n
+}
+
+/// Schnorr signature under the combined key
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CosignSignature {
+    pub r: Vec<u8>,             // compressed joint nonce point R
+    pub s: BigUint              // sum of both partial signatures
+}
+
+/// Party holding one share of a combined key, able to start signing
+/// sessions with the holder of the other share
+pub struct Cosigner {
+    context: SessionContext     // keys shared by every session, and the last session number
+}
+
+/// Session that has sent its nonce commitment
+pub struct CommitRound {
+    context: SessionContext,    // keys and session number
+    nonce: Nonce,               // secret nonce k_i
+    nonce_point: ECPPoint       // R_i = k_i G
+}
+
+/// Session that has revealed its nonce point
+pub struct RevealRound {
+    context: SessionContext,    // keys and session number
+    nonce: Nonce,               // secret nonce k_i
+    nonce_point: ECPPoint,      // R_i = k_i G
+    peer_commitment: Vec<u8>    // commitment the peer's nonce point must open
+}
+
+/// Session that has sent its partial signature
+pub struct PartialRound {
+    context: SessionContext,    // keys and session number
+    peer_nonce_point: ECPPoint, // R_j of the peer
+    joint_nonce: Vec<u8>,       // compressed R = R_1 + R_2
+    challenge: BigUint,         // e = H(R || Q || message) mod n
+    partial: BigUint            // own partial signature s_i
+}
+
+/// Keys needed at every step of a signing session
+#[derive(Clone)]
+struct SessionContext {
+    keypair: ECPKeypair,        // own share d_i and D_i
+    peer: ECPPoint,             // peer share D_j
+    aggregate: ECPPoint,        // combined key Q
+    session: u64                // number of the current session
+}
+
+/// Secret nonce, wiped when dropped
+struct Nonce {
+    k: BigUint
+}
+
+
+/*---- IMPLEMENTATIONS ----*/
+
+impl KeyShare {
+
+    /// Generates a fresh key share
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `curve` - Short Weierstrass curve to share a key on
+    /// * `rng` - Random number generator
+    pub fn generate(curve: ECPSupportedCurves, rng: &mut impl CryptoRngCore) -> Result<Self, CosignError> {
+        if ECPGroup::new(curve.clone()).get_curve_shape() != ECPCurveShape::ShortWeierstrass {
+            return Err(CosignError::UnsupportedCurve);
+        }
+
+        Ok(KeyShare { keypair: ECPKeypair::new(curve).setup(rng)? })
+    }
+
+    /// Creates the message announcing this share to the peer, proving
+    /// knowledge of d_i with a Schnorr proof
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `rng` - Random number generator
+    pub fn message(&self, rng: &mut impl CryptoRngCore) -> Result<KeyShareMessage, CosignError> {
+        let public = self.keypair.public_key()?;
+        let nonce = Nonce::generate(&self.keypair, rng)?;
+        let commitment = compress(&self.keypair.group, &self.keypair.multiply_point(&self.keypair.group.g, &nonce.k, rng))?;
+
+        let c = challenge(&self.keypair.group, &[KEY_SHARE_LABEL, public.point_bytes(), &commitment[..]]);
+
+        Ok(KeyShareMessage {
+            public: public,
+            commitment: commitment,
+            response: respond(&self.keypair, &nonce, &c)
+        })
+    }
+
+    /// Checks the peer's share and combines it with this one
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `peer` - Key share message from the peer
+    /// * `rng` - Random number generator
+    pub fn combine(self, peer: &KeyShareMessage, rng: &mut impl CryptoRngCore) -> Result<Cosigner, CosignError> {
+        let group = &self.keypair.group;
+
+        if peer.public.curve() != &group.curve_id() {
+            return Err(CosignError::CurveMismatch);
+        }
+
+        let peer_point = peer.public.point().map_err(|_| CosignError::InvalidKeyShare)?;
+        let commitment = read_point(&self.keypair, &peer.commitment)?;
+        let c = challenge(group, &[KEY_SHARE_LABEL, peer.public.point_bytes(), &peer.commitment[..]]);
+
+        if !check_response(&self.keypair, &peer.response, &commitment, &c, &peer_point, rng) {
+            return Err(CosignError::InvalidKeyShare);
+        }
+
+        // A reflected share would make Q = 2 D_i
+        if same_point(group, &peer_point, &self.keypair.q) {
+            return Err(CosignError::InvalidKeyShare);
+        }
+
+        let aggregate = jacobian_coords::normalize_point(group, &self.keypair.add_points(&self.keypair.q, &peer_point));
+
+        if aggregate.is_zero() {
+            return Err(CosignError::InvalidKeyShare);
+        }
+
+        Ok(Cosigner {
+            context: SessionContext {
+                keypair: self.keypair,
+                peer: peer_point,
+                aggregate: aggregate,
+                session: 0
+            }
+        })
+    }
+}
+
+impl Cosigner {
+
+    /// Exports the combined key Q = D_1 + D_2. It verifies co-signed
+    /// signatures with `verify`, and is a regular ECDSA verification key
+    pub fn aggregate_key(&self) -> Result<ECPPublicKey, CosignError> {
+        let group = &self.context.keypair.group;
+        Ok(ECPPublicKey::new(group.curve_id(), &encode_point(group, &self.context.aggregate, false)?)?)
+    }
+
+    /// Starts the next signing session with a fresh nonce. Returns the
+    /// session and the commitment to send to the peer, who must be
+    /// starting its own session of the same number
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `rng` - Random number generator
+    pub fn begin(&mut self, rng: &mut impl CryptoRngCore) -> Result<(CommitRound, NonceCommitment), CosignError> {
+        self.context.session += 1;
+
+        let context = self.context.clone();
+        let nonce = Nonce::generate(&context.keypair, rng)?;
+        let nonce_point = context.keypair.multiply_point(&context.keypair.group.g, &nonce.k, rng);
+        let encoded = compress(&context.keypair.group, &nonce_point)?;
+
+        let commitment = NonceCommitment {
+            session: context.session,
+            commitment: commit(&context, &context.keypair.q, &encoded)?
+        };
+
+        Ok((CommitRound { context: context, nonce: nonce, nonce_point: nonce_point }, commitment))
+    }
+
+    /// Number of the last session started
+    pub fn session(&self) -> u64 {
+        self.context.session
+    }
+}
+
+impl CommitRound {
+
+    /// Takes the peer's commitment and reveals the own nonce point
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `peer` - Nonce commitment from the peer
+    pub fn reveal(self, peer: &NonceCommitment) -> Result<(RevealRound, NonceReveal), CosignError> {
+        self.context.check_session(peer.session)?;
+
+        let reveal = NonceReveal {
+            session: self.context.session,
+            nonce_point: compress(&self.context.keypair.group, &self.nonce_point)?
+        };
+
+        let round = RevealRound {
+            context: self.context,
+            nonce: self.nonce,
+            nonce_point: self.nonce_point,
+            peer_commitment: peer.commitment.clone()
+        };
+
+        Ok((round, reveal))
+    }
+}
+
+impl RevealRound {
+
+    /// Checks the peer's nonce point against its commitment and signs
+    /// the message with the own share. The nonce is wiped on return,
+    /// whether or not signing succeeded
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `peer` - Nonce reveal from the peer
+    /// * `message` - Message to sign, the same for both parties
+    pub fn sign(self, peer: &NonceReveal, message: &[u8]) -> Result<(PartialRound, PartialSignature), CosignError> {
+        self.context.check_session(peer.session)?;
+
+        let keypair = &self.context.keypair;
+        let peer_nonce_point = read_point(keypair, &peer.nonce_point)?;
+        let expected = commit(&self.context, &self.context.peer, &peer.nonce_point)?;
+
+        if !ct::ct_eq(&expected, &self.peer_commitment) {
+            return Err(CosignError::CommitmentMismatch);
+        }
+
+        let joint = jacobian_coords::normalize_point(&keypair.group, &keypair.add_points(&self.nonce_point, &peer_nonce_point));
+
+        if joint.is_zero() {
+            return Err(CosignError::InvalidPoint);
+        }
+
+        let joint_nonce = compress(&keypair.group, &joint)?;
+        let e = signing_challenge(&keypair.group, &joint_nonce, &self.context.aggregate, message)?;
+        let partial = respond(keypair, &self.nonce, &e);
+
+        let signature = PartialSignature { session: self.context.session, s: partial.clone() };
+
+        let round = PartialRound {
+            context: self.context,
+            peer_nonce_point: peer_nonce_point,
+            joint_nonce: joint_nonce,
+            challenge: e,
+            partial: partial
+        };
+
+        Ok((round, signature))
+    }
+}
+
+impl PartialRound {
+
+    /// Checks the peer's partial signature and combines it with the
+    /// own one into the final signature
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `peer` - Partial signature from the peer
+    /// * `rng` - Random number generator
+    pub fn combine(self, peer: &PartialSignature, rng: &mut impl CryptoRngCore) -> Result<CosignSignature, CosignError> {
+        self.context.check_session(peer.session)?;
+
+        let keypair = &self.context.keypair;
+
+        if !check_response(keypair, &peer.s, &self.peer_nonce_point, &self.challenge, &self.context.peer, rng) {
+            return Err(CosignError::InvalidPartialSignature);
+        }
+
+        Ok(CosignSignature {
+            r: self.joint_nonce.clone(),
+            s: (&self.partial + &peer.s).rem(&group_order(&keypair.group))
+        })
+    }
+}
+
+impl SessionContext {
+
+    /// Rejects messages from any session but the current one
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `session` - Session number carried by the message
+    fn check_session(&self, session: u64) -> Result<(), CosignError> {
+        if session != self.session {
+            return Err(CosignError::StaleMessage);
+        }
+
+        Ok(())
+    }
+}
+
+impl Nonce {
+
+    /// Draws a nonce in 1..N-1
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `keypair` - Keypair whose group the nonce is for
+    /// * `rng` - Random number generator
+    fn generate(keypair: &ECPKeypair, rng: &mut impl CryptoRngCore) -> Result<Self, CosignError> {
+        Ok(Nonce { k: keypair.get_valid_private_value(rng)? })
+    }
+}
+
+impl Drop for Nonce {
+    fn drop(&mut self) {
+        zeroize_biguint(&mut self.k);
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Verifies a co-signed signature under the combined key
+/// 
+/// ### Arguments
+/// 
+/// * `public_key` - Combined key from `Cosigner::aggregate_key`
+/// * `message` - Message that was signed
+/// * `signature` - Signature to check
+/// * `rng` - Random number generator
+pub fn verify(
+    public_key: &ECPPublicKey,
+    message: &[u8],
+    signature: &CosignSignature,
+    rng: &mut impl CryptoRngCore
+) -> Result<(), CosignError> {
+    let keypair = ECPKeypair::new(public_key.curve().clone());
+
+    if keypair.group.get_curve_shape() != ECPCurveShape::ShortWeierstrass {
+        return Err(CosignError::UnsupportedCurve);
+    }
+
+    let aggregate = public_key.point()?;
+    let joint = read_point(&keypair, &signature.r).map_err(|_| CosignError::VerificationFailed)?;
+    let e = signing_challenge(&keypair.group, &signature.r, &aggregate, message)?;
+
+    if !check_response(&keypair, &signature.s, &joint, &e, &aggregate, rng) {
+        return Err(CosignError::VerificationFailed);
+    }
+
+    Ok(())
+}
+
+/// Response k + c d mod n of a Schnorr proof or partial signature
+/// 
+/// ### Arguments
+/// 
+/// * `keypair` - Keypair holding d
+/// * `nonce` - Secret nonce k
+/// * `c` - Challenge
+fn respond(keypair: &ECPKeypair, nonce: &Nonce, c: &BigUint) -> BigUint {
+    let n = group_order(&keypair.group);
+    let mut cd = (c * &keypair.d).rem(&n);
+    let response = (&nonce.k + &cd).rem(&n);
+    zeroize_biguint(&mut cd);
+
+    response
+}
+
+/// Checks that s G == R + c D, the verification equation shared by the
+/// proof of possession, partial signatures and full signatures
+/// 
+/// ### Arguments
+/// 
+/// * `keypair` - Keypair providing the group
+/// * `s` - Response to check
+/// * `commitment` - Nonce point R
+/// * `c` - Challenge
+/// * `public` - Public point D
+/// * `rng` - Random number generator
+fn check_response(
+    keypair: &ECPKeypair,
+    s: &BigUint,
+    commitment: &ECPPoint,
+    c: &BigUint,
+    public: &ECPPoint,
+    rng: &mut impl CryptoRngCore
+) -> bool {
+    if s.is_zero() || s >= &group_order(&keypair.group) {
+        return false;
+    }
+
+    let lhs = keypair.multiply_point(&keypair.group.g, s, rng);
+    let rhs = if c.is_zero() {
+        commitment.clone()
+    } else {
+        let cd = keypair.multiply_point(public, c, rng);
+        jacobian_coords::normalize_point(&keypair.group, &keypair.add_points(commitment, &cd))
+    };
+
+    same_point(&keypair.group, &lhs, &rhs)
+}
+
+/// Commitment to a party's nonce point, bound to the session and to
+/// the party's share so that it cannot be replayed or reflected
+/// 
+/// ### Arguments
+/// 
+/// * `context` - Session the commitment is for
+/// * `share` - Share point D_i of the committing party
+/// * `nonce_point` - Compressed nonce point R_i
+fn commit(context: &SessionContext, share: &ECPPoint, nonce_point: &[u8]) -> Result<Vec<u8>, CosignError> {
+    let mut input = COMMITMENT_LABEL.to_vec();
+    input.extend_from_slice(&context.session.to_be_bytes());
+    input.extend(compress(&context.keypair.group, share)?);
+    input.extend_from_slice(nonce_point);
+
+    Ok(hash_message(&input, HASH_ALGORITHM))
+}
+
+/// Challenge e = H(R || Q || message) mod n of a signature
+/// 
+/// ### Arguments
+/// 
+/// * `group` - Curve group
+/// * `joint_nonce` - Compressed joint nonce point R
+/// * `aggregate` - Combined key Q
+/// * `message` - Message being signed
+fn signing_challenge(group: &ECPGroup, joint_nonce: &[u8], aggregate: &ECPPoint, message: &[u8]) -> Result<BigUint, CosignError> {
+    let aggregate = compress(group, aggregate)?;
+    Ok(challenge(group, &[CHALLENGE_LABEL, joint_nonce, &aggregate[..], message]))
+}
+
+/// Hashes the concatenated parts and reduces the digest mod n
+/// 
+/// ### Arguments
+/// 
+/// * `group` - Curve group
+/// * `parts` - Inputs to hash, in order
+fn challenge(group: &ECPGroup, parts: &[&[u8]]) -> BigUint {
+    let input: Vec<u8> = parts.iter().flat_map(|part| part.iter().cloned()).collect();
+    BigUint::from_bytes_be(&hash_message(&input, HASH_ALGORITHM)).rem(&group_order(group))
+}
+
+/// Decodes a point sent by the peer and checks that it is on the curve
+/// 
+/// ### Arguments
+/// 
+/// * `keypair` - Keypair providing the group
+/// * `bytes` - Encoded point
+fn read_point(keypair: &ECPKeypair, bytes: &[u8]) -> Result<ECPPoint, CosignError> {
+    let point = decode_point(&keypair.group, bytes).map_err(|_| CosignError::InvalidPoint)?;
+
+    if !keypair.check_public_key(&point).0 {
+        return Err(CosignError::InvalidPoint);
+    }
+
+    Ok(point)
+}
+
+/// Compressed SEC1 encoding of a point
+/// 
+/// ### Arguments
+/// 
+/// * `group` - Curve group
+/// * `point` - Point to encode, in any coordinates
+fn compress(group: &ECPGroup, point: &ECPPoint) -> Result<Vec<u8>, CosignError> {
+    Ok(encode_point(group, &jacobian_coords::normalize_point(group, point), true)?)
+}
+
+/// Whether two points are equal, in any coordinates
+/// 
+/// ### Arguments
+/// 
+/// * `group` - Curve group
+/// * `p` - First point
+/// * `q` - Second point
+fn same_point(group: &ECPGroup, p: &ECPPoint, q: &ECPPoint) -> bool {
+    if p.is_zero() || q.is_zero() {
+        return p.is_zero() && q.is_zero();
+    }
+
+    let p = jacobian_coords::normalize_point(group, p);
+    let q = jacobian_coords::normalize_point(group, q);
+
+    p.x == q.x && p.y == q.y
+}
+
+/// Order N of the group's generator
+/// 
+/// ### Arguments
+/// 
+/// * `group` - Curve group
+fn group_order(group: &ECPGroup) -> BigUint {
+    group.n.to_biguint().unwrap()
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod cosign_test {
+
+    use std::ops::Rem;
+    use num_bigint::BigUint;
+    use num_traits::One;
+    use crate::utils::rng::os_rng;
+    use crate::utils::ecc::ECPKeypair;
+    use crate::utils::ecc_curves::ECPSupportedCurves;
+    use crate::signature::ecdsa::ECDSA;
+    use crate::signature::cosign::{ self, CosignError, CosignSignature, Cosigner, KeyShare };
+
+    fn parties() -> (Cosigner, Cosigner, ECPKeypair) {
+        let mut rng = os_rng().unwrap();
+        let first = KeyShare::generate(ECPSupportedCurves::SECP256R1, &mut rng).unwrap();
+        let second = KeyShare::generate(ECPSupportedCurves::SECP256R1, &mut rng).unwrap();
+
+        // Only the tests ever see the summed private key
+        let mut combined = first.keypair.clone();
+        combined.d = (&first.keypair.d + &second.keypair.d).rem(&cosign::group_order(&combined.group));
+
+        let first_message = first.message(&mut rng).unwrap();
+        let second_message = second.message(&mut rng).unwrap();
+
+        let first = first.combine(&second_message, &mut rng).unwrap();
+        let second = second.combine(&first_message, &mut rng).unwrap();
+        combined.q = first.context.aggregate.clone();
+
+        (first, second, combined)
+    }
+
+    fn cosign(first: &mut Cosigner, second: &mut Cosigner, message: &[u8]) -> (CosignSignature, CosignSignature) {
+        let mut rng = os_rng().unwrap();
+
+        let (first_round, first_commitment) = first.begin(&mut rng).unwrap();
+        let (second_round, second_commitment) = second.begin(&mut rng).unwrap();
+
+        let (first_round, first_reveal) = first_round.reveal(&second_commitment).unwrap();
+        let (second_round, second_reveal) = second_round.reveal(&first_commitment).unwrap();
+
+        let (first_round, first_partial) = first_round.sign(&second_reveal, message).unwrap();
+        let (second_round, second_partial) = second_round.sign(&first_reveal, message).unwrap();
+
+        (first_round.combine(&second_partial, &mut rng).unwrap(), second_round.combine(&first_partial, &mut rng).unwrap())
+    }
+
+    #[test]
+    fn aggregate_signature_verifies_under_combined_key() {
+        let mut rng = os_rng().unwrap();
+        let (mut first, mut second, _) = parties();
+        let message = b"co-signed message";
+
+        let (signature, other) = cosign(&mut first, &mut second, message);
+        let aggregate = first.aggregate_key().unwrap();
+
+        assert_eq!(signature, other);
+        assert_eq!(aggregate, second.aggregate_key().unwrap());
+        assert!(cosign::verify(&aggregate, message, &signature, &mut rng).is_ok());
+        assert_eq!(cosign::verify(&aggregate, b"another message", &signature, &mut rng), Err(CosignError::VerificationFailed));
+
+        // A second session draws fresh nonces
+        let (next, _) = cosign(&mut first, &mut second, message);
+        assert_ne!(next.r, signature.r);
+        assert!(cosign::verify(&aggregate, message, &next, &mut rng).is_ok());
+    }
+
+    #[test]
+    fn stale_round_one_message_is_rejected() {
+        let mut rng = os_rng().unwrap();
+        let (mut first, mut second, _) = parties();
+
+        let (_, stale) = first.begin(&mut rng).unwrap();
+        let (second_round, _) = second.begin(&mut rng).unwrap();
+        assert!(second_round.reveal(&stale).is_ok());
+
+        // Both parties move on to the next session, which only accepts
+        // its own messages
+        let (first_round, _) = first.begin(&mut rng).unwrap();
+        let (second_round, second_commitment) = second.begin(&mut rng).unwrap();
+
+        assert_eq!(second_round.reveal(&stale).err(), Some(CosignError::StaleMessage));
+        assert!(first_round.reveal(&second_commitment).is_ok());
+    }
+
+    #[test]
+    fn forged_shares_nonces_and_partials_are_rejected() {
+        let mut rng = os_rng().unwrap();
+        let first = KeyShare::generate(ECPSupportedCurves::SECP256R1, &mut rng).unwrap();
+        let second = KeyShare::generate(ECPSupportedCurves::SECP256R1, &mut rng).unwrap();
+
+        let mut forged = second.message(&mut rng).unwrap();
+        forged.response = &forged.response + BigUint::one();
+        assert_eq!(first.combine(&forged, &mut rng).err(), Some(CosignError::InvalidKeyShare));
+        assert_eq!(KeyShare::generate(ECPSupportedCurves::Curve25519, &mut rng).err(), Some(CosignError::UnsupportedCurve));
+
+        // Partial signature over a different message
+        let (mut first, mut second, _) = parties();
+        let (first_round, first_commitment) = first.begin(&mut rng).unwrap();
+        let (second_round, second_commitment) = second.begin(&mut rng).unwrap();
+        let (first_round, first_reveal) = first_round.reveal(&second_commitment).unwrap();
+        let (second_round, second_reveal) = second_round.reveal(&first_commitment).unwrap();
+        let (first_round, _) = first_round.sign(&second_reveal, b"message").unwrap();
+        let (_, second_partial) = second_round.sign(&first_reveal, b"another message").unwrap();
+
+        assert_eq!(first_round.combine(&second_partial, &mut rng).err(), Some(CosignError::InvalidPartialSignature));
+
+        // Nonce point swapped after committing
+        let (first_round, first_commitment) = first.begin(&mut rng).unwrap();
+        let (second_round, second_commitment) = second.begin(&mut rng).unwrap();
+        let (_, mut first_reveal) = first_round.reveal(&second_commitment).unwrap();
+        let (second_round, _) = second_round.reveal(&first_commitment).unwrap();
+        first_reveal.nonce_point = cosign::compress(&first.context.keypair.group, &first.context.keypair.q).unwrap();
+
+        assert_eq!(second_round.sign(&first_reveal, b"message").err(), Some(CosignError::CommitmentMismatch));
+    }
+
+    #[test]
+    fn combined_key_verifies_ecdsa() {
+        let mut rng = os_rng().unwrap();
+        let (first, _, combined) = parties();
+        let message = b"signed message";
+
+        let signature = ECDSA::from_keypair(combined).sign(message, &mut rng).unwrap();
+        let mut verifier = ECDSA::from_public_key(&first.aggregate_key().unwrap()).unwrap();
+
+        assert!(verifier.verify(message, &signature, &mut rng).is_ok());
+    }
+}
\ No newline at end of file
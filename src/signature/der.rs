@@ -0,0 +1,298 @@
+//! DER encoding of (R, S) signature pairs, shared by ECDSA and DSA:
+//!
+//! ```text
+//! Signature ::= SEQUENCE { r INTEGER, s INTEGER }
+//! ```
+//!
+//! Decoding is strict. Lengths and integers must use their minimal
+//! encoding, both values must be positive and nothing may follow the
+//! sequence, so every signature has exactly one accepted encoding.
+//!
+//! RSA public keys have the same shape, SEQUENCE { n INTEGER, e INTEGER },
+//! and are wrapped in a SubjectPublicKeyInfo with the element helpers
+//! here. PKCS#1 private keys are a longer sequence of integers, read one
+//! at a time with `read_integer`
+
+use num_bigint::BigUint;
+use num_traits::Zero;
+
+
+/*---- CONSTANTS ----*/
+
+pub(crate) const SEQUENCE_TAG: u8 = 0x30;
+pub(crate) const INTEGER_TAG: u8 = 0x02;
+pub(crate) const BIT_STRING_TAG: u8 = 0x03;
+
+
+/*---- FUNCTIONS ----*/
+
+/// Encodes a signature pair as a DER sequence of two integers
+/// 
+/// ### Arguments
+/// 
+/// * `r` - R value of the signature
+/// * `s` - S value of the signature
+pub fn encode_signature(r: &BigUint, s: &BigUint) -> Vec<u8> {
+    encode_integer_pair(r, s)
+}
+
+/// Decodes a DER signature into its R and S values. Returns None for
+/// anything other than the unique encoding of two positive integers
+/// 
+/// ### Arguments
+/// 
+/// * `bytes` - DER encoded signature
+pub fn decode_signature(bytes: &[u8]) -> Option<(BigUint, BigUint)> {
+    decode_integer_pair(bytes)
+}
+
+/// Encodes two integers as a DER sequence
+/// 
+/// ### Arguments
+/// 
+/// * `first` - First integer
+/// * `second` - Second integer
+pub(crate) fn encode_integer_pair(first: &BigUint, second: &BigUint) -> Vec<u8> {
+    encode_integer_sequence(&[first, second])
+}
+
+/// Encodes any number of non-negative integers as a DER sequence
+/// 
+/// ### Arguments
+/// 
+/// * `values` - Integers in order
+pub(crate) fn encode_integer_sequence(values: &[&BigUint]) -> Vec<u8> {
+    let body: Vec<u8> = values.iter().flat_map(|value| encode_integer(value)).collect();
+
+    encode_element(SEQUENCE_TAG, &body)
+}
+
+/// Decodes a DER sequence of exactly two positive integers, with nothing
+/// after it
+/// 
+/// ### Arguments
+/// 
+/// * `bytes` - DER encoded sequence
+pub(crate) fn decode_integer_pair(bytes: &[u8]) -> Option<(BigUint, BigUint)> {
+    let body = read_element(bytes, SEQUENCE_TAG)?;
+
+    if body.len() + header_length(body.len()) != bytes.len() {
+        return None;
+    }
+
+    let first_bytes = read_element(body, INTEGER_TAG)?;
+    let rest = &body[header_length(first_bytes.len()) + first_bytes.len()..];
+    let second_bytes = read_element(rest, INTEGER_TAG)?;
+
+    if header_length(second_bytes.len()) + second_bytes.len() != rest.len() {
+        return None;
+    }
+
+    Some((decode_integer(first_bytes)?, decode_integer(second_bytes)?))
+}
+
+/// Reads a non-negative DER INTEGER from the start of `bytes`, returning
+/// its value and the bytes after it. Zero is accepted here, as the
+/// version field of a PKCS#1 key is zero
+/// 
+/// ### Arguments
+/// 
+/// * `bytes` - Bytes starting with the INTEGER
+pub(crate) fn read_integer(bytes: &[u8]) -> Option<(BigUint, &[u8])> {
+    let content = read_element(bytes, INTEGER_TAG)?;
+    let value = decode_unsigned(content)?;
+
+    Some((value, &bytes[header_length(content.len()) + content.len()..]))
+}
+
+/// Encodes a non-negative integer as a DER INTEGER, adding a leading
+/// zero byte when the top bit would otherwise mark it as negative
+/// 
+/// ### Arguments
+/// 
+/// * `value` - Integer to encode
+fn encode_integer(value: &BigUint) -> Vec<u8> {
+    let mut content = if value.is_zero() {
+        vec![0]
+    } else {
+        value.to_bytes_be()
+    };
+
+    if content[0] & 0x80 != 0 {
+        content.insert(0, 0);
+    }
+
+    encode_element(INTEGER_TAG, &content)
+}
+
+/// Encodes an element from its tag and content
+/// 
+/// ### Arguments
+/// 
+/// * `tag` - Tag of the element
+/// * `content` - Content octets
+pub(crate) fn encode_element(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![tag];
+    encoded.extend(encode_length(content.len()));
+    encoded.extend_from_slice(content);
+
+    encoded
+}
+
+/// Reads the content of a positive DER INTEGER
+/// 
+/// ### Arguments
+/// 
+/// * `content` - Content octets of the INTEGER
+fn decode_integer(content: &[u8]) -> Option<BigUint> {
+    decode_unsigned(content).filter(|value| !value.is_zero())
+}
+
+/// Reads the content of a non-negative DER INTEGER in its minimal
+/// encoding
+/// 
+/// ### Arguments
+/// 
+/// * `content` - Content octets of the INTEGER
+fn decode_unsigned(content: &[u8]) -> Option<BigUint> {
+    match content {
+        [] => None,
+        [first, ..] if first & 0x80 != 0 => None,
+        [0, second, ..] if second & 0x80 == 0 => None,
+        _ => Some(BigUint::from_bytes_be(content))
+    }
+}
+
+/// Encodes a DER length in short or minimal long form
+/// 
+/// ### Arguments
+/// 
+/// * `length` - Length to encode
+fn encode_length(length: usize) -> Vec<u8> {
+    if length < 0x80 {
+        return vec![length as u8];
+    }
+
+    let octets: Vec<u8> = length.to_be_bytes().iter().cloned().skip_while(|b| *b == 0).collect();
+
+    let mut encoded = vec![0x80 | octets.len() as u8];
+    encoded.extend(octets);
+
+    encoded
+}
+
+/// Number of bytes taken by the tag and length of an element
+/// 
+/// ### Arguments
+/// 
+/// * `length` - Length of the element content
+pub(crate) fn header_length(length: usize) -> usize {
+    1 + encode_length(length).len()
+}
+
+/// Reads an element with the expected tag from the start of `bytes`,
+/// returning its content. Trailing bytes are left to the caller
+/// 
+/// ### Arguments
+/// 
+/// * `bytes` - Bytes starting with the element
+/// * `tag` - Expected tag
+pub(crate) fn read_element(bytes: &[u8], tag: u8) -> Option<&[u8]> {
+    if bytes.len() < 2 || bytes[0] != tag {
+        return None;
+    }
+
+    let (length, offset) = match bytes[1] {
+        short if short < 0x80 => (short as usize, 2),
+        long => {
+            let count = (long & 0x7f) as usize;
+
+            if count == 0 || count > std::mem::size_of::<usize>() || bytes.len() < 2 + count {
+                return None;
+            }
+
+            let length = bytes[2..2 + count].iter().fold(0usize, |acc, b| (acc << 8) | *b as usize);
+            (length, 2 + count)
+        }
+    };
+
+    // Only the minimal length encoding is accepted
+    if header_length(length) != offset || bytes.len() - offset < length {
+        return None;
+    }
+
+    Some(&bytes[offset..offset + length])
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod der_test {
+
+    use num_bigint::BigUint;
+    use num_traits::{ FromPrimitive, One, Zero };
+    use crate::signature::der::{ encode_signature, decode_signature, encode_integer_sequence, read_integer, read_element, SEQUENCE_TAG };
+
+    #[test]
+    fn small_values_encode_exactly() {
+        let encoded = encode_signature(&BigUint::one(), &BigUint::from_u32(0x80).unwrap());
+
+        assert_eq!(encoded, vec![0x30, 0x07, 0x02, 0x01, 0x01, 0x02, 0x02, 0x00, 0x80]);
+        assert_eq!(decode_signature(&encoded), Some((BigUint::one(), BigUint::from_u32(0x80).unwrap())));
+    }
+
+    #[test]
+    fn large_values_round_trip() {
+        let r = BigUint::from_bytes_be(&[0xff; 66]);
+        let s = BigUint::from_bytes_be(&[0x7f; 66]);
+        let encoded = encode_signature(&r, &s);
+
+        // 137 bytes of content need the long length form
+        assert_eq!(&encoded[..3], &[0x30, 0x81, 0x89]);
+        assert_eq!(decode_signature(&encoded), Some((r, s)));
+    }
+
+    #[test]
+    fn non_canonical_encodings_are_rejected() {
+        let rejected: [&[u8]; 10] = [
+            &[],
+            &[0x30, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x01, 0x00],        // trailing byte
+            &[0x30, 0x07, 0x02, 0x01, 0x01, 0x02, 0x01, 0x01, 0x00],        // trailing byte inside the sequence
+            &[0x30, 0x81, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x01],        // long form for a short length
+            &[0x30, 0x07, 0x02, 0x02, 0x00, 0x01, 0x02, 0x01, 0x01],        // padded integer
+            &[0x30, 0x06, 0x02, 0x01, 0x81, 0x02, 0x01, 0x01],              // negative integer
+            &[0x30, 0x06, 0x02, 0x01, 0x00, 0x02, 0x01, 0x01],              // zero R
+            &[0x30, 0x05, 0x02, 0x00, 0x02, 0x01, 0x01],                    // empty integer
+            &[0x30, 0x06, 0x02, 0x01, 0x01, 0x02, 0x02, 0x01],              // truncated S
+            &[0x31, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x01]               // wrong outer tag
+        ];
+
+        for bytes in rejected.iter() {
+            assert_eq!(decode_signature(bytes), None, "{:02x?}", bytes);
+        }
+
+        assert!(decode_signature(&[0x30, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x01]).is_some());
+    }
+
+    #[test]
+    fn integer_sequences_read_back_one_at_a_time() {
+        let values = [BigUint::zero(), BigUint::from_u32(0x80).unwrap(), BigUint::one()];
+        let encoded = encode_integer_sequence(&[&values[0], &values[1], &values[2]]);
+
+        assert_eq!(encoded, vec![0x30, 0x0a, 0x02, 0x01, 0x00, 0x02, 0x02, 0x00, 0x80, 0x02, 0x01, 0x01]);
+
+        // Zero is fine here, unlike in a signature
+        let mut rest = read_element(&encoded, SEQUENCE_TAG).unwrap();
+
+        for value in values.iter() {
+            let (read, next) = read_integer(rest).unwrap();
+            assert_eq!(&read, value);
+            rest = next;
+        }
+
+        assert!(rest.is_empty());
+        assert_eq!(read_integer(&[0x02, 0x02, 0x00, 0x01]), None);
+        assert_eq!(read_integer(&[0x02, 0x01, 0x80]), None);
+    }
+}
\ No newline at end of file
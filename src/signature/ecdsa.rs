@@ -1,89 +1,360 @@
-use rand::OsRng;
+use std::fmt;
 use std::ops::Rem;
+use num_integer::Integer;
 use num_traits::{One, Zero};
 use num_bigint::{BigInt, BigUint, ToBigInt};
 
-use utils::primes;
-use utils::ecc::ECPKeypair;
-use utils::encoding::from_plaintext;
-use utils::ecc_curves::{ECPPoint, ECPSupportedCurves};
+use crate::signature::{ der, rfc6979 };
+use crate::utils::{ primes, comb_method, jacobian_coords };
+use crate::utils::rng::CryptoRngCore;
+use crate::utils::zeroize::zeroize_biguint;
+use crate::utils::ecc::{ ECPKeypair, ECPPublicKey, EcError, ValidatedPoint, encode_point, decode_point };
+use crate::utils::ecc_curves::{ ECPGroup, ECPPoint, ECPSupportedCurves, ECPCurveShape };
+use crate::utils::encoding::{ EndianOrdering, biguint_to_fixed_bytes };
+
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize, Deserializer };
+#[cfg(feature = "serde")]
+use serde::de::Error as DeserializeError;
+#[cfg(feature = "serde")]
+use crate::utils::serde_hex;
+
+
+/// Number of attempts made at finding a nonce that yields
+/// non-zero R and S values before signing gives up
+const MAX_SIGNING_ATTEMPTS: usize = 10;
+
+
+/// Errors raised while creating or verifying ECDSA signatures
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SignatureError {
+    KeyGeneration(EcError),     // the signing keypair could not be generated
+    SigningFailed,              // no nonce produced a usable signature
+    KeyCleared,                 // the private value has been wiped
+    InvalidSignature,           // R or S is outside of 1..N-1
+    VerificationFailed,         // signature does not match the message and key
+    RecoveryFailed              // no public key can be recovered from the signature
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SignatureError::KeyGeneration(e) => write!(f, "Key generation failed: {}", e),
+            SignatureError::SigningFailed => write!(f, "Could not produce a signature"),
+            SignatureError::KeyCleared => write!(f, "Private value has been cleared"),
+            SignatureError::InvalidSignature => write!(f, "R or S values are either too small or too large"),
+            SignatureError::VerificationFailed => write!(f, "Verification failed: V != r"),
+            SignatureError::RecoveryFailed => write!(f, "Could not recover a public key from the signature")
+        }
+    }
+}
+
+impl From<EcError> for SignatureError {
+    fn from(error: EcError) -> Self {
+        SignatureError::KeyGeneration(error)
+    }
+}
 
 
 pub struct ECDSA {
     keypair: ECPKeypair
 }
 
+/// Verification key prepared for checking many signatures. The key is
+/// validated once and comb tables for both Q and G are kept, so each
+/// check goes straight to the two multiplications. Checking only needs
+/// `&self`, so one context can be shared between threads behind an `Arc`
+pub struct EcdsaVerifyContext {
+    group: ECPGroup,            // curve group, sharing its cached generator table
+    g_table: Vec<ECPPoint>,     // comb table for G
+    q_table: Vec<ECPPoint>      // comb table for the verification key Q
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct ECDSASignature {
-    r: BigInt,
-    s: BigInt
+    #[cfg_attr(feature = "serde", serde(with = "serde_hex::bigint"))]
+    pub r: BigInt,
+    #[cfg_attr(feature = "serde", serde(with = "serde_hex::bigint"))]
+    pub s: BigInt
+}
+
+/// Signature carrying the extra bits needed to recover the signer's
+/// public key from the signature and message alone. Bit 0 of the
+/// recovery id is the parity of R's Y coordinate, and bit 1 is set when
+/// R's X coordinate exceeded the group order before reduction
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RecoverableSignature {
+    pub signature: ECDSASignature,
+    pub recovery_id: u8
+}
+
+/// Signatures read from outside must have positive R and S. The upper
+/// bound depends on the curve and is left to `ECDSA::verify`
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for ECDSASignature {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Fields {
+            #[serde(with = "serde_hex::bigint")]
+            r: BigInt,
+            #[serde(with = "serde_hex::bigint")]
+            s: BigInt
+        }
+
+        let fields = Fields::deserialize(deserializer)?;
+
+        if fields.r < BigInt::one() || fields.s < BigInt::one() {
+            return Err(D::Error::custom(SignatureError::InvalidSignature));
+        }
+
+        Ok(ECDSASignature { r: fields.r, s: fields.s })
+    }
+}
+
+impl ECDSASignature {
+
+    /// Encodes the signature as a DER sequence of R and S
+    pub fn to_der(&self) -> Vec<u8> {
+        der::encode_signature(&self.r.to_biguint().unwrap(), &self.s.to_biguint().unwrap())
+    }
+
+    /// Decodes a DER signature. Range checks against the group order
+    /// are left to `ECDSA::verify`
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `bytes` - DER encoded signature
+    pub fn from_der(bytes: &[u8]) -> Result<Self, SignatureError> {
+        match der::decode_signature(bytes) {
+            Some((r, s)) => Ok(ECDSASignature { r: r.to_bigint().unwrap(), s: s.to_bigint().unwrap() }),
+            None => Err(SignatureError::InvalidSignature)
+        }
+    }
 }
 
 impl ECDSA {
-    pub fn new(curve: ECPSupportedCurves, rng: &mut OsRng) -> Self {
-        ECDSA {
-            keypair: ECPKeypair::new(curve).setup(rng)
+    pub fn new(curve: ECPSupportedCurves, rng: &mut impl CryptoRngCore) -> Result<Self, SignatureError> {
+        Ok(ECDSA {
+            keypair: ECPKeypair::new(curve).setup(rng)?
+        })
+    }
+
+    /// Signs a message, which is expected to be a hash no longer than
+    /// the group order
+    /// 
+    /// ### Example
+    /// 
+    /// ```
+    /// use cryptofun::signature::ecdsa::ECDSA;
+    /// use cryptofun::utils::rng::seeded_rng;
+    /// use cryptofun::utils::ecc_curves::ECPSupportedCurves;
+    /// 
+    /// let mut rng = seeded_rng(&[0xec, 0xd5, 0xa]);
+    /// let mut ecdsa = ECDSA::new(ECPSupportedCurves::BP256R1, &mut rng).unwrap();
+    /// let signature = ecdsa.sign(b"signed message", &mut rng).unwrap();
+    /// 
+    /// assert!(ecdsa.verify(b"signed message", &signature, &mut rng).is_ok());
+    /// assert!(ecdsa.verify(&b"another message"[..7], &signature, &mut rng).is_err());
+    /// ```
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `message` - Message to sign
+    /// * `rng` - Random number generator
+    pub fn sign(&mut self, message: &[u8], rng: &mut impl CryptoRngCore) -> Result<ECDSASignature, SignatureError> {
+        Ok(self.sign_recoverable(message, rng)?.signature)
+    }
+
+    /// Signs a message, keeping the recovery id that lets a verifier
+    /// rebuild the public key with `recover_public_key`
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `message` - Message to sign
+    /// * `rng` - Random number generator
+    pub fn sign_recoverable(&mut self, message: &[u8], rng: &mut impl CryptoRngCore) -> Result<RecoverableSignature, SignatureError> {
+        if self.keypair.d.is_zero() {
+            return Err(SignatureError::KeyCleared);
+        }
+
+        for _ in 0..MAX_SIGNING_ATTEMPTS {
+            let mut k = self.keypair.get_valid_private_value(rng)?;
+            let signature = self.sign_recoverable_with_nonce(message, &k, rng);
+            zeroize_biguint(&mut k);
+
+            // A zero R or S leaks the private key, so try another nonce
+            if let Some(signature) = signature {
+                return Ok(signature);
+            }
         }
+
+        Err(SignatureError::SigningFailed)
     }
 
-    pub fn sign(&mut self, message: &Vec<u8>, rng: &mut OsRng) -> ECDSASignature {
-        let mut r = BigInt::zero();
-        let mut s = BigInt::zero();
-        let mut t = BigInt::zero();
+    /// Signs a message with the given nonce, returning None if it
+    /// yields a zero R or S. Only `sign` and the known-answer tests
+    /// choose nonces
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `message` - Message to sign
+    /// * `nonce` - Per-signature secret K
+    /// * `rng` - Random number generator
+    pub(crate) fn sign_with_nonce(&self, message: &[u8], nonce: &BigUint, rng: &mut impl CryptoRngCore) -> Option<ECDSASignature> {
+        self.sign_recoverable_with_nonce(message, nonce, rng).map(|recoverable| recoverable.signature)
+    }
 
+    /// Signs a message with the given nonce, keeping the recovery id
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `message` - Message to sign
+    /// * `nonce` - Per-signature secret K
+    /// * `rng` - Random number generator
+    fn sign_recoverable_with_nonce(&self, message: &[u8], nonce: &BigUint, rng: &mut impl CryptoRngCore) -> Option<RecoverableSignature> {
         let g_clone = self.keypair.group.g.clone();
         let n_clone = self.keypair.group.n.clone();
 
         let n_int = n_clone.to_bigint().unwrap();
-        let mut k = self.keypair.get_valid_private_value();
-        k = k.rem(n_clone.clone());
+        let mut k = nonce.rem(n_clone.clone());
+
+        let p_1 = self.keypair.multiply_point(&g_clone, &k, rng);
+        let r = p_1.x.rem(n_int.clone());
 
-        let p_1 = self.keypair.multiply_point(&g_clone, &k);
-        r = p_1.x;
+        let y_odd = p_1.y.as_ref().map_or(false, |y| y.is_odd());
+        let x_overflow = p_1.x >= n_int;
+        let recovery_id = (y_odd as u8) | ((x_overflow as u8) << 1);
 
         if r == BigInt::zero() {
-            panic!("Whoops on R");
+            zeroize_biguint(&mut k);
+            return None;
         }
 
-        let e = BigUint::from_bytes_le(message).to_bigint().unwrap();
-        s = primes::modular_inverse(&k, &n_clone).to_bigint().unwrap();
-        t = self.keypair.d.to_bigint().unwrap() * r.clone();
+        let e = digest_to_int(message, &n_clone.to_biguint().unwrap()).to_bigint().unwrap();
+        let mut s = primes::modular_inverse(&k, &n_clone).to_bigint().unwrap();
+        zeroize_biguint(&mut k);
+        let mut t = self.keypair.d.to_bigint().unwrap() * r.clone();
         t = (e + t).rem(n_int.clone());
         s = (s * t).rem(n_int);
 
         if s == BigInt::zero() {
-            panic!("Whoops on s");
+            return None;
         }
 
-        ECDSASignature {
-            s: s,
-            r: r
-        }
+        Some(RecoverableSignature {
+            signature: ECDSASignature { s: s, r: r },
+            recovery_id: recovery_id
+        })
     }
 
-    pub fn verify(&mut self, message: &Vec<u8>, signature: &ECDSASignature) {
-        let n_int = self.keypair.group.n.to_bigint().unwrap();
+    /// Recovers the signer's public key from a recoverable signature
+    /// and the signed message, as Q = r^-1 (s R - e G). The result is
+    /// only meaningful when compared against a key or address the
+    /// signer is already known by
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `curve` - Curve the signature was made on
+    /// * `message` - Message that was signed
+    /// * `signature` - Signature with its recovery id
+    /// * `rng` - Random number generator
+    pub fn recover_public_key(
+        curve: ECPSupportedCurves,
+        message: &[u8],
+        signature: &RecoverableSignature,
+        rng: &mut impl CryptoRngCore
+    ) -> Result<ECPPublicKey, SignatureError> {
+        let keypair = ECPKeypair::new(curve.clone());
+        let group = &keypair.group;
+        let n = group.n.to_biguint().unwrap();
 
-        /*
-         * Step 1: make sure r and s are in range 1..n-1
-         */
-        if signature.r < BigInt::one() 
-        || signature.r >= n_int 
-        || signature.s < BigInt::one() 
-        || signature.s >= n_int {
-            panic!("R or S values are either too small or too large");
+        let (r, s) = match (signature.signature.r.to_biguint(), signature.signature.s.to_biguint()) {
+            (Some(r), Some(s)) => (r, s),
+            _ => return Err(SignatureError::InvalidSignature)
+        };
+
+        if r.is_zero() || r >= n || s.is_zero() || s >= n || signature.recovery_id > 3 {
+            return Err(SignatureError::InvalidSignature);
         }
 
-        /*
-         * Step 3: derive int from hashed message
-         */
-        let e = BigUint::from_bytes_le(message).to_bigint().unwrap();
+        // Rebuild R from its X coordinate and the parity of Y
+        let x = if signature.recovery_id & 2 == 2 { &r + &n } else { r.clone() };
+        let field_length = (group.p.bits() + 7) / 8;
 
-        /*
-         * Step 4: u1 = e / s mod n, u2 = r / s mod n
-         */
-        let s_inverse = primes::modular_inverse_int(&signature.s, &n_int);
-        let u_1 = (e * s_inverse.clone()) % n_int.clone();
-        let u_2 = (signature.r.clone() * s_inverse) % n_int.clone();
+        let mut encoded = vec![0x02 | (signature.recovery_id & 1)];
+
+        match biguint_to_fixed_bytes(&x, field_length, EndianOrdering::Big) {
+            Some(bytes) => encoded.extend(bytes),
+            None => return Err(SignatureError::RecoveryFailed)
+        }
+
+        let R = decode_point(group, &encoded).map_err(|_| SignatureError::RecoveryFailed)?;
+
+        // u1 = -e / r mod n, u2 = s / r mod n
+        let e = digest_to_int(message, &n).rem(&n);
+        let r_inverse = primes::modular_inverse(&r, &n);
+        let u_1 = ((&n - &e).rem(&n) * &r_inverse).rem(&n);
+        let u_2 = (&s * &r_inverse).rem(&n);
+
+        let S = keypair.multiply_point(&R, &u_2, rng);
+        let Q = if u_1.is_zero() {
+            S
+        } else {
+            let P = keypair.multiply_point(&group.g.clone(), &u_1, rng);
+            jacobian_coords::normalize_point(group, &keypair.add_points(&P, &S))
+        };
+
+        if Q.is_zero() {
+            return Err(SignatureError::RecoveryFailed);
+        }
+
+        let bytes = encode_point(group, &Q, false).map_err(|_| SignatureError::RecoveryFailed)?;
+        ECPPublicKey::new(curve, &bytes).map_err(|_| SignatureError::RecoveryFailed)
+    }
+
+    /// Creates a signer around an existing keypair
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `keypair` - Keypair holding the signing key
+    pub(crate) fn from_keypair(keypair: ECPKeypair) -> Self {
+        ECDSA { keypair: keypair }
+    }
+
+    /// Creates an instance that can only verify signatures, from the
+    /// signer's exported public key
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `public_key` - Verification key of the signer
+    pub fn from_public_key(public_key: &ECPPublicKey) -> Result<Self, SignatureError> {
+        let mut keypair = ECPKeypair::new(public_key.curve().clone());
+        keypair.q = public_key.point()?;
+
+        Ok(ECDSA { keypair: keypair })
+    }
+
+    /// Exports the verification key
+    pub fn public_key(&self) -> Result<ECPPublicKey, SignatureError> {
+        Ok(self.keypair.public_key()?)
+    }
+
+    /// Wipes the signing key. Verification keeps working, while
+    /// signing fails with `SignatureError::KeyCleared`
+    pub fn clear(&mut self) -> () {
+        self.keypair.clear();
+    }
+
+    /// Verifies a signature over a message
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `message` - Message that was signed
+    /// * `signature` - Signature to check
+    /// * `rng` - Random number generator
+    pub fn verify(&mut self, message: &[u8], signature: &ECDSASignature, rng: &mut impl CryptoRngCore) -> Result<(), SignatureError> {
+        let (u_1, u_2) = verification_scalars(&self.keypair.group, message, signature)?;
 
         /*
          * Step 5: R = u1 G + u2 Q
@@ -91,25 +362,324 @@ impl ECDSA {
         let g_clone = self.keypair.group.g.clone();
         let q_clone = self.keypair.q.clone();
 
-        let P = self.keypair.multiply_point(&g_clone, &u_1.to_biguint().unwrap());
-        let S = self.keypair.multiply_point(&q_clone, &u_2.to_biguint().unwrap());
-        let R = self.keypair.add_points(&P, &S);
+        let P = self.keypair.multiply_point(&g_clone, &u_1, rng);
+        let S = self.keypair.multiply_point(&q_clone, &u_2, rng);
+        let R = jacobian_coords::normalize_point(&self.keypair.group, &self.keypair.add_points(&P, &S));
 
+        check_verification_point(&self.keypair.group, &R, signature)
+    }
+}
 
-        /*
-         * Step 6: convert xR to an integer (no-op)
-         * Step 7: reduce xR mod n (gives v)
-         */
-        let v = R.x % n_int;
+impl EcdsaVerifyContext {
 
-        println!("v: {}", v);
-        println!("r: {}", signature.r);
+    /// Builds the comb table of a verification key, on the group the
+    /// key was validated against. The generator table is taken from the
+    /// group's cache, or built and cached there if this is the first
+    /// multiplication on the group
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `public_key` - Validated verification key Q
+    pub fn new(public_key: &ValidatedPoint) -> Result<Self, SignatureError> {
+        let group = public_key.group();
+
+        if group.get_curve_shape() != ECPCurveShape::ShortWeierstrass {
+            return Err(SignatureError::from(EcError::InvalidPublicKey("ECDSA needs a curve in short Weierstrass form")));
+        }
+
+        Ok(EcdsaVerifyContext {
+            group: group.clone(),
+            g_table: comb_method::generator_table(group),
+            q_table: comb_method::precompute_fixed(group, public_key.point())
+        })
+    }
+
+    /// Verifies a signature against the prepared key. Gives the same
+    /// result as `ECDSA::verify` with the same key
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `message` - Message that was signed
+    /// * `signature` - Signature to check
+    /// * `rng` - Random number generator
+    pub fn verify(&self, message: &[u8], signature: &ECDSASignature, rng: &mut impl CryptoRngCore) -> Result<(), SignatureError> {
+        let (u_1, u_2) = verification_scalars(&self.group, message, signature)?;
 
         /*
-         * Step 8: check if v (that is, R.X) is equal to r
+         * Step 5: R = u1 G + u2 Q
          */
-        if v != signature.r {
-            panic!("Verification failed: V != r");
-        }
+        let P = comb_method::multiply_fixed(&self.group, &u_1, &self.g_table, rng);
+        let mut S = comb_method::multiply_fixed(&self.group, &u_2, &self.q_table, rng);
+        let R = jacobian_coords::normalize_point(&self.group, &jacobian_coords::add(&self.group, &P, &mut S));
+
+        check_verification_point(&self.group, &R, signature)
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Reads a message digest as the integer e of SEC1 section 4.1.3: the
+/// leftmost bits of the digest, as many as the group order has, taken
+/// as a big-endian integer
+/// 
+/// ### Arguments
+/// 
+/// * `message` - Digest of the message being signed
+/// * `n` - Order of the group
+fn digest_to_int(message: &[u8], n: &BigUint) -> BigUint {
+    rfc6979::bits_to_int(message, n.bits())
+}
+
+/// Checks the range of R and S and derives the scalars u1 and u2 that
+/// G and Q are multiplied by during verification
+/// 
+/// ### Arguments
+/// 
+/// * `group` - Curve group the signature was made on
+/// * `message` - Message that was signed
+/// * `signature` - Signature to check
+fn verification_scalars(group: &ECPGroup, message: &[u8], signature: &ECDSASignature) -> Result<(BigUint, BigUint), SignatureError> {
+    let n_int = group.n.to_bigint().unwrap();
+
+    /*
+     * Step 1: make sure r and s are in range 1..n-1
+     */
+    if signature.r < BigInt::one()
+    || signature.r >= n_int
+    || signature.s < BigInt::one()
+    || signature.s >= n_int {
+        return Err(SignatureError::InvalidSignature);
+    }
+
+    /*
+     * Step 3: derive int from hashed message
+     */
+    let e = digest_to_int(message, &group.n.to_biguint().unwrap()).to_bigint().unwrap();
+
+    /*
+     * Step 4: u1 = e / s mod n, u2 = r / s mod n
+     */
+    let s_inverse = primes::modular_inverse_int(&signature.s, &n_int);
+    let u_1 = (e * s_inverse.clone()) % n_int.clone();
+    let u_2 = (signature.r.clone() * s_inverse) % n_int;
+
+    Ok((u_1.to_biguint().unwrap(), u_2.to_biguint().unwrap()))
+}
+
+/// Compares the X coordinate of R = u1 G + u2 Q against r
+/// 
+/// ### Arguments
+/// 
+/// * `group` - Curve group the signature was made on
+/// * `R` - Normalized sum of the two multiplications
+/// * `signature` - Signature to check
+fn check_verification_point(group: &ECPGroup, R: &ECPPoint, signature: &ECDSASignature) -> Result<(), SignatureError> {
+    if R.is_zero() {
+        return Err(SignatureError::VerificationFailed);
+    }
+
+    /*
+     * Step 6: convert xR to an integer (no-op)
+     * Step 7: reduce xR mod n (gives v)
+     */
+    let n_int = group.n.to_bigint().unwrap();
+    let v = R.x.clone() % n_int;
+
+    /*
+     * Step 8: check if v (that is, R.X) is equal to r
+     */
+    if v != signature.r {
+        return Err(SignatureError::VerificationFailed);
     }
+
+    Ok(())
 }
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod ecdsa_test {
+
+    use std::sync::Arc;
+    use std::thread;
+    use num_traits::One;
+    use num_bigint::BigInt;
+    use rand::{ ChaChaRng, SeedableRng };
+    use crate::utils::metrics;
+    use crate::utils::rng::os_rng;
+    use crate::utils::ecc::ValidationLevel;
+    use crate::utils::ecc_curves::{ ECPGroup, ECPPoint, ECPSupportedCurves };
+    use crate::signature::ecdsa::{ ECDSA, ECDSASignature, EcdsaVerifyContext, RecoverableSignature };
+
+    fn signed_messages(signer: &mut ECDSA, count: u8, rng: &mut ChaChaRng) -> Vec<(Vec<u8>, ECDSASignature)> {
+        (0..count).map(|i| {
+            let message = vec![i; 32];
+            let signature = signer.sign(&message, rng).unwrap();
+
+            (message, signature)
+        }).collect()
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let mut rng = os_rng().unwrap();
+        let mut ecdsa = ECDSA::new(ECPSupportedCurves::BP256R1, &mut rng).unwrap();
+        let message = b"signed message";
+
+        let signature = ecdsa.sign(message, &mut rng).unwrap();
+        assert!(ecdsa.verify(message, &signature, &mut rng).is_ok());
+    }
+
+    #[test]
+    fn verification_with_public_key_only() {
+        let mut rng = os_rng().unwrap();
+        let mut signer = ECDSA::new(ECPSupportedCurves::BP256R1, &mut rng).unwrap();
+        let mut verifier = ECDSA::from_public_key(&signer.public_key().unwrap()).unwrap();
+        let message = b"signed message";
+
+        let signature = signer.sign(message, &mut rng).unwrap();
+        assert!(verifier.verify(message, &signature, &mut rng).is_ok());
+        assert!(verifier.sign(message, &mut rng).is_err());
+    }
+
+    #[test]
+    fn seeded_signing_is_reproducible() {
+        let message = b"signed message";
+        let mut first_rng = ChaChaRng::from_seed(&[2, 7, 1, 8]);
+        let mut second_rng = ChaChaRng::from_seed(&[2, 7, 1, 8]);
+
+        let mut first = ECDSA::new(ECPSupportedCurves::BP256R1, &mut first_rng).unwrap();
+        let mut second = ECDSA::new(ECPSupportedCurves::BP256R1, &mut second_rng).unwrap();
+
+        let first_signature = first.sign(message, &mut first_rng).unwrap();
+        let second_signature = second.sign(message, &mut second_rng).unwrap();
+
+        assert_eq!((&first_signature.r, &first_signature.s), (&second_signature.r, &second_signature.s));
+        assert!(second.verify(message, &first_signature, &mut second_rng).is_ok());
+    }
+
+    #[test]
+    fn der_round_trip() {
+        let mut rng = os_rng().unwrap();
+        let mut ecdsa = ECDSA::new(ECPSupportedCurves::BP256R1, &mut rng).unwrap();
+        let message = b"signed message";
+
+        let signature = ecdsa.sign(message, &mut rng).unwrap();
+        let decoded = ECDSASignature::from_der(&signature.to_der()).unwrap();
+
+        assert_eq!(decoded, signature);
+        assert!(ecdsa.verify(message, &decoded, &mut rng).is_ok());
+        assert!(ECDSASignature::from_der(&signature.to_der()[1..]).is_err());
+    }
+
+    #[test]
+    fn public_key_is_recovered_from_signature() {
+        let mut rng = os_rng().unwrap();
+        let mut ecdsa = ECDSA::new(ECPSupportedCurves::SECP256R1, &mut rng).unwrap();
+        let expected = ecdsa.public_key().unwrap().point().unwrap();
+        let message = b"signed message";
+
+        let recoverable = ecdsa.sign_recoverable(message, &mut rng).unwrap();
+        let recovered = ECDSA::recover_public_key(ECPSupportedCurves::SECP256R1, message, &recoverable, &mut rng).unwrap().point().unwrap();
+
+        assert_eq!((&recovered.x, &recovered.y), (&expected.x, &expected.y));
+        assert!(ecdsa.verify(message, &recoverable.signature, &mut rng).is_ok());
+
+        // The other Y parity gives a different key, and ids above 3 are invalid
+        let flipped = RecoverableSignature { signature: recoverable.signature.clone(), recovery_id: recoverable.recovery_id ^ 1 };
+        let other = ECDSA::recover_public_key(ECPSupportedCurves::SECP256R1, message, &flipped, &mut rng).unwrap().point().unwrap();
+        assert_ne!((&other.x, &other.y), (&expected.x, &expected.y));
+
+        let invalid = RecoverableSignature { signature: recoverable.signature, recovery_id: 4 };
+        assert!(ECDSA::recover_public_key(ECPSupportedCurves::SECP256R1, message, &invalid, &mut rng).is_err());
+    }
+
+    #[test]
+    fn context_matches_plain_verification() {
+        let mut rng = ChaChaRng::from_seed(&[0xc0, 0x7e, 0x47]);
+        let mut signer = ECDSA::new(ECPSupportedCurves::SECP256R1, &mut rng).unwrap();
+        let mut verifier = ECDSA::from_public_key(&signer.public_key().unwrap()).unwrap();
+
+        let group = ECPGroup::new(ECPSupportedCurves::SECP256R1);
+        let q = signer.public_key().unwrap().point().unwrap();
+        let context = EcdsaVerifyContext::new(&group.validate(q, ValidationLevel::FullSubgroup).unwrap()).unwrap();
+
+        for (message, signature) in signed_messages(&mut signer, 24, &mut rng) {
+            let mut tampered = signature.clone();
+            tampered.s = &tampered.s + BigInt::one();
+
+            assert!(context.verify(&message, &signature, &mut rng).is_ok());
+            assert_eq!(context.verify(&message, &tampered, &mut rng), verifier.verify(&message, &tampered, &mut rng));
+            assert_eq!(context.verify(b"other", &signature, &mut rng), verifier.verify(b"other", &signature, &mut rng));
+        }
+    }
+
+    #[test]
+    fn context_rejects_invalid_keys() {
+        let group = ECPGroup::new(ECPSupportedCurves::SECP256R1);
+        let montgomery = ECPGroup::new(ECPSupportedCurves::Curve25519);
+        let off_curve = ECPPoint::new(&group.g.y.clone().unwrap(), Some(group.g.x.clone()));
+
+        assert!(group.validate(off_curve, ValidationLevel::Membership).is_err());
+        assert!(EcdsaVerifyContext::new(&group.validate(group.g.clone(), ValidationLevel::Membership).unwrap()).is_ok());
+
+        // The x-coordinate of G fits Curve25519, but ECDSA cannot use it
+        let montgomery_point = montgomery.validate(group.g.clone(), ValidationLevel::Membership).unwrap();
+        assert!(EcdsaVerifyContext::new(&montgomery_point).is_err());
+    }
+
+    #[test]
+    fn context_is_shared_between_threads() {
+        let mut rng = ChaChaRng::from_seed(&[0x7a, 0x3e, 0xad]);
+        let mut signer = ECDSA::new(ECPSupportedCurves::SECP256R1, &mut rng).unwrap();
+
+        let group = ECPGroup::new(ECPSupportedCurves::SECP256R1);
+        let q = group.validate(signer.public_key().unwrap().point().unwrap(), ValidationLevel::Membership).unwrap();
+        let context = Arc::new(EcdsaVerifyContext::new(&q).unwrap());
+        let signatures = Arc::new(signed_messages(&mut signer, 8, &mut rng));
+
+        let workers: Vec<_> = (0..4).map(|seed| {
+            let context = Arc::clone(&context);
+            let signatures = Arc::clone(&signatures);
+
+            thread::spawn(move || {
+                let mut rng = ChaChaRng::from_seed(&[seed]);
+
+                signatures.iter().all(|(message, signature)| context.verify(message, signature, &mut rng).is_ok())
+            })
+        }).collect();
+
+        for worker in workers {
+            assert!(worker.join().unwrap());
+        }
+    }
+
+    #[test]
+    fn key_table_is_precomputed_once() {
+        let mut rng = ChaChaRng::from_seed(&[0x0e, 0xce]);
+        let mut signer = ECDSA::new(ECPSupportedCurves::SECP256R1, &mut rng).unwrap();
+        let signatures = signed_messages(&mut signer, 6, &mut rng);
+
+        let group = ECPGroup::new(ECPSupportedCurves::SECP256R1);
+        let q = group.validate(signer.public_key().unwrap().point().unwrap(), ValidationLevel::Membership).unwrap();
+
+        // A fresh group has no generator table yet, so the first context
+        // builds both tables and a second one only builds the table for Q
+        let before = metrics::snapshot();
+        let context = EcdsaVerifyContext::new(&q).unwrap();
+        assert_eq!(metrics::snapshot().since(&before).table_precomputes, 2);
+
+        let before = metrics::snapshot();
+        EcdsaVerifyContext::new(&q).unwrap();
+        assert_eq!(metrics::snapshot().since(&before).table_precomputes, 1);
+
+        let before = metrics::snapshot();
+
+        for (message, signature) in &signatures {
+            assert!(context.verify(message, signature, &mut rng).is_ok());
+        }
+
+        assert_eq!(metrics::snapshot().since(&before).table_precomputes, 0);
+    }
+}
\ No newline at end of file
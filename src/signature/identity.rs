@@ -0,0 +1,223 @@
+//! Short, hash-based identities for ECDSA keys. An address is the
+//! leading 20 bytes of a hash over the compressed SEC1 encoding of the
+//! public point, so peers can refer to a signer without carrying its
+//! full key. Signatures made with `ECDSA::sign_recoverable` can then be
+//! checked against an address alone, by recovering the key from the
+//! signature and comparing the derived address
+
+use std::fmt;
+
+use crate::hash::crypto::{ hash_message, HashAlgorithm };
+use crate::signature::ecdsa::{ ECDSA, RecoverableSignature, SignatureError };
+use crate::utils::ct;
+use crate::utils::rng::CryptoRngCore;
+use crate::utils::ecc::{ EcError, encode_point };
+use crate::utils::ecc_curves::{ ECPGroup, ECPPoint, ECPSupportedCurves };
+use crate::utils::encoding::{ EncodingError, base58_encode, base58_decode, bech32_encode };
+
+
+/*---- CONSTANTS ----*/
+
+/// Length in bytes of an address
+pub const ADDRESS_LENGTH: usize = 20;
+
+/// Version byte prefixed to addresses in their base58check form
+const BASE58_VERSION: u8 = 0x00;
+
+/// Length in bytes of the base58check checksum
+const CHECKSUM_LENGTH: usize = 4;
+
+
+/*---- STRUCTS ----*/
+
+/// Identity derived from a public key. Displays in base58check form
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Address {
+    bytes: [u8; ADDRESS_LENGTH]
+}
+
+
+/*---- IMPLEMENTATIONS ----*/
+
+impl Address {
+
+    /// Wraps raw address bytes
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `bytes` - Address bytes, exactly `ADDRESS_LENGTH` long
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != ADDRESS_LENGTH {
+            return None;
+        }
+
+        let mut address = Address { bytes: [0; ADDRESS_LENGTH] };
+        address.bytes.copy_from_slice(bytes);
+
+        Some(address)
+    }
+
+    /// Raw address bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Parses the base58check form written by `Display`, checking the
+    /// version byte and checksum
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `encoded` - Base58check string
+    pub fn from_base58check(encoded: &str) -> Result<Self, EncodingError> {
+        let decoded = base58_decode(encoded)?;
+
+        if decoded.len() != 1 + ADDRESS_LENGTH + CHECKSUM_LENGTH || decoded[0] != BASE58_VERSION {
+            return Err(EncodingError::InvalidBase58);
+        }
+
+        let (payload, checksum) = decoded.split_at(1 + ADDRESS_LENGTH);
+
+        if !ct::ct_eq(checksum, &base58_checksum(payload)) {
+            return Err(EncodingError::InvalidBase58);
+        }
+
+        Address::from_bytes(&payload[1..]).ok_or(EncodingError::InvalidBase58)
+    }
+
+    /// Encodes the address as bech32 under the given human-readable part
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `hrp` - Human-readable part, in lowercase ASCII
+    pub fn to_bech32(&self, hrp: &str) -> Result<String, EncodingError> {
+        bech32_encode(hrp, &self.bytes)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut payload = vec![BASE58_VERSION];
+        payload.extend_from_slice(&self.bytes);
+
+        let checksum = base58_checksum(&payload);
+        payload.extend_from_slice(&checksum);
+
+        write!(f, "{}", base58_encode(&payload))
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Derives the address of a public point: the leading 20 bytes of the
+/// hash of its compressed SEC1 encoding
+/// 
+/// ### Arguments
+/// 
+/// * `point` - Affine public point
+/// * `group` - Curve group the point belongs to
+/// * `algorithm` - Hash to derive the address with
+pub fn address_from_public(point: &ECPPoint, group: &ECPGroup, algorithm: HashAlgorithm) -> Result<Address, EcError> {
+    let encoded = encode_point(group, point, true)?;
+    let digest = hash_message(&encoded, algorithm);
+
+    match Address::from_bytes(&digest[..ADDRESS_LENGTH]) {
+        Some(address) => Ok(address),
+        None => Err(EcError::InvalidEncoding)
+    }
+}
+
+/// Checks that a message was signed by the holder of an address. The
+/// public key is recovered from the signature and its address compared
+/// against the expected one in constant time
+/// 
+/// ### Arguments
+/// 
+/// * `curve` - Curve the signature was made on
+/// * `message` - Message that was signed
+/// * `signature` - Signature with its recovery id
+/// * `expected` - Address of the claimed signer
+/// * `algorithm` - Hash the address was derived with
+/// * `rng` - Random number generator
+pub fn verify_with_address(
+    curve: ECPSupportedCurves,
+    message: &[u8],
+    signature: &RecoverableSignature,
+    expected: &Address,
+    algorithm: HashAlgorithm,
+    rng: &mut impl CryptoRngCore
+) -> Result<(), SignatureError> {
+    let public_key = ECDSA::recover_public_key(curve.clone(), message, signature, rng)?;
+    let point = public_key.point().map_err(|_| SignatureError::RecoveryFailed)?;
+    let address = address_from_public(&point, &ECPGroup::new(curve), algorithm).map_err(|_| SignatureError::RecoveryFailed)?;
+
+    if !ct::ct_eq(address.as_bytes(), expected.as_bytes()) {
+        return Err(SignatureError::VerificationFailed);
+    }
+
+    Ok(())
+}
+
+/// Leading bytes of a double SHA3-256 over the versioned payload
+/// 
+/// ### Arguments
+/// 
+/// * `payload` - Version byte followed by the address
+fn base58_checksum(payload: &[u8]) -> Vec<u8> {
+    let digest = hash_message(&hash_message(payload, HashAlgorithm::Sha3_256), HashAlgorithm::Sha3_256);
+    digest[..CHECKSUM_LENGTH].to_vec()
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod identity_test {
+
+    use crate::hash::crypto::HashAlgorithm;
+    use crate::utils::rng::os_rng;
+    use crate::utils::ecc_curves::{ ECPGroup, ECPSupportedCurves };
+    use crate::signature::ecdsa::{ ECDSA, SignatureError };
+    use crate::signature::identity::{ Address, address_from_public, verify_with_address };
+
+    fn address_of(ecdsa: &ECDSA) -> Address {
+        let point = ecdsa.public_key().unwrap().point().unwrap();
+        address_from_public(&point, &ECPGroup::new(ECPSupportedCurves::SECP256R1), HashAlgorithm::Sha3_256).unwrap()
+    }
+
+    #[test]
+    fn signature_verifies_against_signer_address() {
+        let mut rng = os_rng().unwrap();
+        let mut signer = ECDSA::new(ECPSupportedCurves::SECP256R1, &mut rng).unwrap();
+        let other = ECDSA::new(ECPSupportedCurves::SECP256R1, &mut rng).unwrap();
+        let message = b"signed message";
+
+        let signature = signer.sign_recoverable(message, &mut rng).unwrap();
+        let address = address_of(&signer);
+
+        assert!(verify_with_address(ECPSupportedCurves::SECP256R1, message, &signature, &address, HashAlgorithm::Sha3_256, &mut rng).is_ok());
+
+        assert_eq!(
+            verify_with_address(ECPSupportedCurves::SECP256R1, message, &signature, &address_of(&other), HashAlgorithm::Sha3_256, &mut rng),
+            Err(SignatureError::VerificationFailed)
+        );
+
+        assert!(verify_with_address(ECPSupportedCurves::SECP256R1, b"signed massage", &signature, &address, HashAlgorithm::Sha3_256, &mut rng).is_err());
+    }
+
+    #[test]
+    fn generator_address_is_pinned() {
+        let group = ECPGroup::new(ECPSupportedCurves::SECP256R1);
+        let address = address_from_public(&group.g, &group, HashAlgorithm::Sha3_256).unwrap();
+
+        // SHA3-256 over the compressed encoding of the P-256 generator
+        assert_eq!(address.as_bytes(), &[
+            0xe8, 0xc6, 0xdb, 0x8c, 0xd7, 0x9a, 0x02, 0x66, 0x0d, 0x3b,
+            0x64, 0xc1, 0xda, 0xaf, 0x08, 0xf9, 0x1d, 0x69, 0x31, 0x1c
+        ][..]);
+        assert_eq!(address.to_string(), "1NDozNhi7MxuBUFMA9vqnzgnxFbvcMAcai");
+        assert_eq!(Address::from_base58check(&address.to_string()), Ok(address.clone()));
+        assert!(Address::from_base58check("1NDozNhi7MxuBUFMA9vqnzgnxFbvcMAcaj").is_err());
+        assert!(address.to_bech32("id").unwrap().starts_with("id1"));
+    }
+}
\ No newline at end of file
@@ -1 +1,15 @@
-pub mod ecdsa;
\ No newline at end of file
+pub mod der;
+
+#[cfg(any(feature = "hashes-sha3", feature = "hashes-blake2"))]
+pub mod rfc6979;
+
+#[cfg(feature = "ecdsa")]
+pub mod ecdsa;
+#[cfg(feature = "ecdsa")]
+pub mod identity;
+#[cfg(feature = "ecdsa")]
+pub mod cosign;
+#[cfg(feature = "ecdsa")]
+pub mod vrf;
+#[cfg(feature = "dsa")]
+pub mod dsa;
\ No newline at end of file
@@ -0,0 +1,217 @@
+//! Deterministic signature nonces as described in RFC 6979, section
+//! 3.2. The nonce is derived with HMAC from the private key and the
+//! message digest, so signing needs no randomness and a weak RNG can
+//! never leak the key through repeated or biased nonces
+
+use num_bigint::BigUint;
+use num_traits::Zero;
+
+use crate::hash::hmac::hmac;
+use crate::hash::crypto::HashAlgorithm;
+use crate::utils::zeroize::zeroize_bytes;
+
+
+/*---- STRUCTS ----*/
+
+/// HMAC_DRBG state producing the candidate nonces for one signature
+pub struct NonceGenerator {
+    q: BigUint,                 // order of the group the nonce lives in
+    k: Vec<u8>,                 // HMAC key K
+    v: Vec<u8>,                 // chaining value V
+    algorithm: HashAlgorithm,   // hash underneath HMAC
+    started: bool               // whether a candidate has been handed out
+}
+
+
+/*---- IMPLEMENTATIONS ----*/
+
+impl NonceGenerator {
+
+    /// Seeds the generator from the private key and the message digest
+    /// (steps 3.2.b to 3.2.g)
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `x` - Private key
+    /// * `q` - Group order
+    /// * `digest` - Hash of the message being signed
+    /// * `algorithm` - Hash used for the HMAC
+    pub fn new(x: &BigUint, q: &BigUint, digest: &[u8], algorithm: HashAlgorithm) -> Self {
+        let mut generator = NonceGenerator {
+            q: q.clone(),
+            k: vec![0x00; algorithm.output_size()],
+            v: vec![0x01; algorithm.output_size()],
+            algorithm: algorithm,
+            started: false
+        };
+
+        let mut seed = int_to_octets(x, q.bits());
+        seed.extend(bits_to_octets(digest, q));
+
+        generator.update(&seed);
+        zeroize_bytes(&mut seed);
+
+        generator
+    }
+
+    /// Returns the next candidate nonce, in 1..Q-1 (step 3.2.h). Call
+    /// again if the nonce gave a zero R or S
+    pub fn next_nonce(&mut self) -> BigUint {
+        if self.started {
+            self.reseed();
+        }
+
+        self.started = true;
+
+        loop {
+            let mut t = Vec::new();
+
+            while t.len() * 8 < self.q.bits() {
+                self.v = hmac(&self.k, &self.v, self.algorithm.clone());
+                t.extend_from_slice(&self.v);
+            }
+
+            let k = bits_to_int(&t, self.q.bits());
+            zeroize_bytes(&mut t);
+
+            if !k.is_zero() && k < self.q {
+                return k;
+            }
+
+            self.reseed();
+        }
+    }
+
+    /// K = HMAC_K(V || 0x00 || seed) and V = HMAC_K(V), followed by the
+    /// same with 0x01 when a seed is given
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `seed` - Provided data, empty when stepping past a rejected nonce
+    fn update(&mut self, seed: &[u8]) -> () {
+        for marker in &[0x00, 0x01] {
+            let mut input = self.v.clone();
+            input.push(*marker);
+            input.extend_from_slice(seed);
+
+            self.k = hmac(&self.k, &input, self.algorithm.clone());
+            self.v = hmac(&self.k, &self.v, self.algorithm.clone());
+            zeroize_bytes(&mut input);
+
+            if seed.is_empty() {
+                break;
+            }
+        }
+    }
+
+    /// Moves past a rejected candidate
+    fn reseed(&mut self) -> () {
+        self.update(&[]);
+    }
+}
+
+impl Drop for NonceGenerator {
+    fn drop(&mut self) {
+        zeroize_bytes(&mut self.k);
+        zeroize_bytes(&mut self.v);
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Reads the leftmost `qlen` bits of a byte string as a big-endian
+/// integer (bits2int, section 2.3.2). Signers use this to turn a
+/// digest into the value they sign
+/// 
+/// ### Arguments
+/// 
+/// * `bytes` - Bytes to read
+/// * `qlen` - Bit length of the group order
+pub fn bits_to_int(bytes: &[u8], qlen: usize) -> BigUint {
+    let value = BigUint::from_bytes_be(bytes);
+    let blen = bytes.len() * 8;
+
+    if blen > qlen {
+        return value >> (blen - qlen);
+    }
+
+    value
+}
+
+/// Encodes an integer as exactly ceil(qlen / 8) big-endian bytes
+/// (int2octets, section 2.3.3)
+/// 
+/// ### Arguments
+/// 
+/// * `value` - Integer below 2^qlen
+/// * `qlen` - Bit length of the group order
+fn int_to_octets(value: &BigUint, qlen: usize) -> Vec<u8> {
+    let rlen = (qlen + 7) / 8;
+    let bytes = value.to_bytes_be();
+
+    let mut octets = vec![0; rlen.saturating_sub(bytes.len())];
+    octets.extend_from_slice(&bytes[bytes.len().saturating_sub(rlen)..]);
+
+    octets
+}
+
+/// Reduces a digest modulo Q and encodes it (bits2octets, section 2.3.4)
+/// 
+/// ### Arguments
+/// 
+/// * `bytes` - Digest to encode
+/// * `q` - Group order
+fn bits_to_octets(bytes: &[u8], q: &BigUint) -> Vec<u8> {
+    let z1 = bits_to_int(bytes, q.bits());
+    let z2 = if &z1 >= q { z1 - q } else { z1 };
+
+    int_to_octets(&z2, q.bits())
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(all(test, feature = "hashes-sha3"))]
+mod rfc6979_test {
+
+    use num_bigint::BigUint;
+    use num_traits::{ FromPrimitive, One };
+    use crate::hash::crypto::{ hash_message, HashAlgorithm };
+    use crate::signature::rfc6979::{ NonceGenerator, bits_to_int };
+
+    #[test]
+    fn digest_is_truncated_to_the_order() {
+        let digest = [0xab; 32];
+
+        assert_eq!(bits_to_int(&digest, 256), BigUint::from_bytes_be(&digest));
+        assert_eq!(bits_to_int(&digest, 160), BigUint::from_bytes_be(&digest[..20]));
+        assert_eq!(bits_to_int(&digest, 4), BigUint::from_u32(0xa).unwrap());
+    }
+
+    #[test]
+    fn nonces_are_deterministic_and_in_range() {
+        let q = BigUint::parse_bytes(b"ab030ec11908097181949bc45e45bd2ba9cf2c57", 16).unwrap();
+        let x = BigUint::parse_bytes(b"38c6a7a993efb3de4891d466694e12690f86acba", 16).unwrap();
+        let digest = hash_message(b"sample", HashAlgorithm::Sha3_256);
+
+        let mut first = NonceGenerator::new(&x, &q, &digest, HashAlgorithm::Sha3_256);
+        let mut second = NonceGenerator::new(&x, &q, &digest, HashAlgorithm::Sha3_256);
+        let nonces: Vec<BigUint> = (0..4).map(|_| first.next_nonce()).collect();
+
+        // Cross-checked against an independent implementation that
+        // reproduces the SHA-256 nonces of RFC 6979, A.2.1
+        assert_eq!(nonces[0], BigUint::parse_bytes(b"a663bc4f16be3ebf55137aff8faa3d18b975fc07", 16).unwrap());
+
+        for nonce in &nonces {
+            assert_eq!(nonce, &second.next_nonce());
+            assert!(nonce >= &BigUint::one() && nonce < &q);
+        }
+
+        assert!(nonces.windows(2).all(|pair| pair[0] != pair[1]));
+
+        let other = hash_message(b"test", HashAlgorithm::Sha3_256);
+        assert_ne!(NonceGenerator::new(&x, &q, &other, HashAlgorithm::Sha3_256).next_nonce(), nonces[0]);
+        assert_ne!(NonceGenerator::new(&(&x + BigUint::one()), &q, &digest, HashAlgorithm::Sha3_256).next_nonce(), nonces[0]);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,538 @@
+//! Verifiable random function over the short Weierstrass curves, in the
+//! shape of ECVRF from the IETF draft (RFC 9381). Only the holder of a
+//! private key d can compute the output beta for an input alpha, while
+//! anyone holding Y = d G can check it against the accompanying proof.
+//!
+//! Proving maps alpha to a point H, computes Gamma = d H and proves
+//! that Gamma and Y share the discrete log d with a Chaum-Pedersen
+//! proof (c, s):
+//!
+//! 1. k is derived from d and H as in RFC 6979, so proofs are
+//!    deterministic
+//! 2. c = H(Y || H || Gamma || k G || k H), truncated to half the
+//!    length of N
+//! 3. s = k + c d mod N
+//!
+//! Verification recomputes U = s G - c Y and V = s H - c Gamma and
+//! accepts if they hash to the same c. The output is a hash of Gamma
+//! alone, so it is unique for a key and input.
+//!
+//! The crate has no standard hash-to-curve, so H is found by the
+//! draft's try-and-increment method: hashes of the input with a counter
+//! are read as compressed X coordinates until one lies on the curve.
+//! This takes time that depends on alpha, which is public. Digests
+//! shorter than the field are extended by hashing again with a block
+//! number, so every supported hash works with every curve
+
+use std::fmt;
+use std::ops::Rem;
+use num_bigint::BigUint;
+use num_traits::Zero;
+
+use crate::hash::crypto::{ hash_message, HashAlgorithm };
+use crate::signature::rfc6979::NonceGenerator;
+use crate::utils::jacobian_coords;
+use crate::utils::rng::CryptoRngCore;
+use crate::utils::zeroize::zeroize_biguint;
+use crate::utils::ecc::{ ECPKeypair, ECPPublicKey, EcError, encode_point, decode_point };
+use crate::utils::ecc_curves::{ ECPCurveShape, ECPGroup, ECPPoint };
+
+
+/*---- CONSTANTS ----*/
+
+/// Domain separation label, followed by the name of the hash
+const SUITE_LABEL: &'static [u8] = b"cryptofun vrf v1";
+
+/// Domain separators for hashing to the curve, the challenge and the
+/// output, as in the draft
+const ENCODE_TO_CURVE: u8 = 0x01;
+const CHALLENGE: u8 = 0x02;
+const OUTPUT: u8 = 0x03;
+
+/// Counter values tried before hashing to the curve gives up. Each try
+/// succeeds with probability close to 1/2
+const MAX_TRIES: usize = 256;
+
+
+/*---- ENUMS ----*/
+
+/// Errors raised while proving or verifying
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum VrfError {
+    Key(EcError),           // the key or a point could not be read or encoded
+    UnsupportedCurve,       // the VRF needs a short Weierstrass curve
+    HashToCurveFailed,      // no counter value hashed to a curve point
+    MalformedProof,         // proof bytes have the wrong length or an invalid point
+    VerificationFailed      // proof does not match the key and input
+}
+
+impl fmt::Display for VrfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VrfError::Key(e) => write!(f, "Key error: {}", e),
+            VrfError::UnsupportedCurve => write!(f, "The VRF needs a short Weierstrass curve"),
+            VrfError::HashToCurveFailed => write!(f, "Input could not be hashed to a curve point"),
+            VrfError::MalformedProof => write!(f, "Malformed VRF proof"),
+            VrfError::VerificationFailed => write!(f, "Verification failed: challenge does not match")
+        }
+    }
+}
+
+impl From<EcError> for VrfError {
+    fn from(error: EcError) -> Self {
+        VrfError::Key(error)
+    }
+}
+
+
+/*---- STRUCTS ----*/
+
+/// Proof that a VRF output was computed with the private key
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct VrfProof {
+    pub gamma: Vec<u8>,     // compressed Gamma = d H
+    pub c: BigUint,         // truncated challenge
+    pub s: BigUint          // response k + c d mod N
+}
+
+
+/*---- IMPLEMENTATIONS ----*/
+
+impl VrfProof {
+
+    /// Serializes the proof as Gamma || c || s, with c and s big-endian
+    /// and padded to their fixed lengths
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `group` - Curve group of the key
+    pub fn to_bytes(&self, group: &ECPGroup) -> Vec<u8> {
+        let mut bytes = self.gamma.clone();
+        bytes.extend(pad(&self.c, challenge_length(group)));
+        bytes.extend(pad(&self.s, scalar_length(group)));
+
+        bytes
+    }
+
+    /// Reads a proof written by `to_bytes`. Gamma is only checked to be
+    /// on the curve by `verify`
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `group` - Curve group of the key
+    /// * `bytes` - Serialized proof
+    pub fn from_bytes(group: &ECPGroup, bytes: &[u8]) -> Result<Self, VrfError> {
+        let point_length = 1 + (group.p.bits() + 7) / 8;
+        let c_length = challenge_length(group);
+
+        if bytes.len() != point_length + c_length + scalar_length(group) {
+            return Err(VrfError::MalformedProof);
+        }
+
+        Ok(VrfProof {
+            gamma: bytes[..point_length].to_vec(),
+            c: BigUint::from_bytes_be(&bytes[point_length..point_length + c_length]),
+            s: BigUint::from_bytes_be(&bytes[point_length + c_length..])
+        })
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Computes the VRF output beta for an input, with the proof that it
+/// was computed with the keypair's private key
+/// 
+/// ### Arguments
+/// 
+/// * `keypair` - Keypair on a short Weierstrass curve
+/// * `alpha` - Input
+/// * `algorithm` - Hash used throughout
+/// * `rng` - Random number generator, used to blind the point multiplications
+pub fn prove(
+    keypair: &ECPKeypair,
+    alpha: &[u8],
+    algorithm: HashAlgorithm,
+    rng: &mut impl CryptoRngCore
+) -> Result<(Vec<u8>, VrfProof), VrfError> {
+    let group = &keypair.group;
+
+    if group.get_curve_shape() != ECPCurveShape::ShortWeierstrass {
+        return Err(VrfError::UnsupportedCurve);
+    }
+
+    if keypair.d.is_zero() {
+        return Err(VrfError::Key(EcError::KeyCleared));
+    }
+
+    let n = group_order(group);
+    let public = compress(group, &keypair.q)?;
+    let h = encode_to_curve(keypair, &public, alpha, algorithm.clone())?;
+    let h_bytes = compress(group, &h)?;
+
+    let gamma = keypair.multiply_point(&h, &keypair.d, rng);
+    let gamma_bytes = compress(group, &gamma)?;
+
+    let mut k = NonceGenerator::new(&keypair.d, &n, &hash_message(&h_bytes, algorithm.clone()), algorithm.clone()).next_nonce();
+    let u = compress(group, &keypair.multiply_point(&group.g, &k, rng))?;
+    let v = compress(group, &keypair.multiply_point(&h, &k, rng))?;
+
+    let c = challenge(group, &[&public, &h_bytes, &gamma_bytes, &u, &v], algorithm.clone());
+    let mut cd = (&c * &keypair.d).rem(&n);
+    let s = (&k + &cd).rem(&n);
+    zeroize_biguint(&mut k);
+    zeroize_biguint(&mut cd);
+
+    Ok((output(&gamma_bytes, algorithm), VrfProof { gamma: gamma_bytes, c: c, s: s }))
+}
+
+/// Checks a proof for an input, returning the VRF output beta if it is
+/// valid
+/// 
+/// ### Arguments
+/// 
+/// * `public_key` - Public key the proof is checked against
+/// * `alpha` - Input
+/// * `proof` - Proof from `prove`
+/// * `algorithm` - Hash the proof was made with
+/// * `rng` - Random number generator, used to blind the point multiplications
+pub fn verify(
+    public_key: &ECPPublicKey,
+    alpha: &[u8],
+    proof: &VrfProof,
+    algorithm: HashAlgorithm,
+    rng: &mut impl CryptoRngCore
+) -> Result<Vec<u8>, VrfError> {
+    let keypair = ECPKeypair::new(public_key.curve().clone());
+    let group = &keypair.group;
+
+    if group.get_curve_shape() != ECPCurveShape::ShortWeierstrass {
+        return Err(VrfError::UnsupportedCurve);
+    }
+
+    let n = group_order(group);
+    let y = public_key.point()?;
+    let gamma = decode_point(group, &proof.gamma).map_err(|_| VrfError::MalformedProof)?;
+
+    if !keypair.check_public_key(&y).0 {
+        return Err(VrfError::Key(EcError::InvalidPublicKey("public key is not a valid curve point")));
+    }
+
+    if !keypair.check_public_key(&gamma).0 {
+        return Err(VrfError::MalformedProof);
+    }
+
+    if proof.s >= n || proof.c.bits() > 8 * challenge_length(group) {
+        return Err(VrfError::VerificationFailed);
+    }
+
+    let public = compress(group, &y)?;
+    let h = encode_to_curve(&keypair, &public, alpha, algorithm.clone())?;
+    let h_bytes = compress(group, &h)?;
+    let gamma_bytes = compress(group, &gamma)?;
+
+    // U = s G - c Y and V = s H - c Gamma
+    let minus_c = (&n - &proof.c).rem(&n);
+    let u = compress(group, &combine(&keypair, &proof.s, &group.g, &minus_c, &y, rng))?;
+    let v = compress(group, &combine(&keypair, &proof.s, &h, &minus_c, &gamma, rng))?;
+
+    if challenge(group, &[&public, &h_bytes, &gamma_bytes, &u, &v], algorithm.clone()) != proof.c {
+        return Err(VrfError::VerificationFailed);
+    }
+
+    Ok(output(&gamma_bytes, algorithm))
+}
+
+/// Maps an input to a curve point by try-and-increment
+/// 
+/// ### Arguments
+/// 
+/// * `keypair` - Keypair providing the group
+/// * `public` - Compressed public key, binding the point to the key
+/// * `alpha` - Input
+/// * `algorithm` - Hash to use
+fn encode_to_curve(keypair: &ECPKeypair, public: &[u8], alpha: &[u8], algorithm: HashAlgorithm) -> Result<ECPPoint, VrfError> {
+    let group = &keypair.group;
+    let field_length = (group.p.bits() + 7) / 8;
+    let excess_bits = 8 * field_length - group.p.bits();
+
+    for counter in 0..MAX_TRIES {
+        let mut input = suite(&algorithm);
+        input.push(ENCODE_TO_CURVE);
+        input.extend_from_slice(public);
+        input.extend_from_slice(alpha);
+        input.push(counter as u8);
+        input.push(0x00);
+
+        // The bits above P are cleared so that most candidates are
+        // below it
+        let mut candidate = vec![0x02];
+        candidate.extend(expand(&input, field_length, algorithm.clone()));
+        candidate[1] &= 0xff >> excess_bits;
+
+        if let Ok(point) = decode_point(group, &candidate) {
+            if keypair.check_public_key(&point).0 {
+                return Ok(point);
+            }
+        }
+    }
+
+    Err(VrfError::HashToCurveFailed)
+}
+
+/// Challenge over the compressed points, truncated to half the length
+/// of N
+/// 
+/// ### Arguments
+/// 
+/// * `group` - Curve group
+/// * `points` - Compressed Y, H, Gamma, U and V
+/// * `algorithm` - Hash to use
+fn challenge(group: &ECPGroup, points: &[&[u8]], algorithm: HashAlgorithm) -> BigUint {
+    let mut input = suite(&algorithm);
+    input.push(CHALLENGE);
+
+    for point in points {
+        input.extend_from_slice(point);
+    }
+
+    input.push(0x00);
+
+    BigUint::from_bytes_be(&expand(&input, challenge_length(group), algorithm))
+}
+
+/// VRF output beta, a hash of Gamma
+/// 
+/// ### Arguments
+/// 
+/// * `gamma` - Compressed Gamma
+/// * `algorithm` - Hash to use
+fn output(gamma: &[u8], algorithm: HashAlgorithm) -> Vec<u8> {
+    let mut input = suite(&algorithm);
+    input.push(OUTPUT);
+    input.extend_from_slice(gamma);
+    input.push(0x00);
+
+    hash_message(&input, algorithm)
+}
+
+/// a P + b Q, normalized. Each product is computed separately, as the
+/// crate has no multi-scalar multiplication
+/// 
+/// ### Arguments
+/// 
+/// * `keypair` - Keypair providing the group
+/// * `a` - First scalar
+/// * `p` - First point
+/// * `b` - Second scalar
+/// * `q` - Second point
+/// * `rng` - Random number generator
+fn combine(
+    keypair: &ECPKeypair,
+    a: &BigUint,
+    p: &ECPPoint,
+    b: &BigUint,
+    q: &ECPPoint,
+    rng: &mut impl CryptoRngCore
+) -> ECPPoint {
+    if b.is_zero() {
+        return keypair.multiply_point(p, a, rng);
+    }
+
+    if a.is_zero() {
+        return keypair.multiply_point(q, b, rng);
+    }
+
+    let ap = keypair.multiply_point(p, a, rng);
+    let bq = keypair.multiply_point(q, b, rng);
+
+    jacobian_coords::normalize_point(&keypair.group, &keypair.add_points(&ap, &bq))
+}
+
+/// Digest of the given length, made of H(input || 0), H(input || 1), ...
+/// truncated. A single block is the plain digest of the input
+/// 
+/// ### Arguments
+/// 
+/// * `input` - Input to hash
+/// * `length` - Length of the result in bytes
+/// * `algorithm` - Hash to use
+fn expand(input: &[u8], length: usize, algorithm: HashAlgorithm) -> Vec<u8> {
+    if length <= algorithm.output_size() {
+        let mut digest = hash_message(input, algorithm);
+        digest.truncate(length);
+        return digest;
+    }
+
+    let mut result = Vec::with_capacity(length);
+    let mut block = 0u8;
+
+    while result.len() < length {
+        let mut extended = input.to_vec();
+        extended.push(block);
+        result.extend(hash_message(&extended, algorithm.clone()));
+        block += 1;
+    }
+
+    result.truncate(length);
+    result
+}
+
+/// Domain separation prefix of every hash, naming the hash algorithm
+/// 
+/// ### Arguments
+/// 
+/// * `algorithm` - Hash in use
+fn suite(algorithm: &HashAlgorithm) -> Vec<u8> {
+    let mut suite = SUITE_LABEL.to_vec();
+    suite.extend(format!("{:?}", algorithm).into_bytes());
+    suite
+}
+
+/// Compressed SEC1 encoding of a point
+/// 
+/// ### Arguments
+/// 
+/// * `group` - Curve group
+/// * `point` - Point to encode, in any coordinates
+fn compress(group: &ECPGroup, point: &ECPPoint) -> Result<Vec<u8>, VrfError> {
+    if point.is_zero() {
+        return Err(VrfError::Key(EcError::PointAtInfinity));
+    }
+
+    Ok(encode_point(group, &jacobian_coords::normalize_point(group, point), true)?)
+}
+
+/// Big-endian bytes of a value, left padded to a fixed length
+/// 
+/// ### Arguments
+/// 
+/// * `value` - Value to write
+/// * `length` - Length of the result
+fn pad(value: &BigUint, length: usize) -> Vec<u8> {
+    let bytes = value.to_bytes_be();
+    let mut padded = vec![0; length.saturating_sub(bytes.len())];
+    padded.extend(bytes);
+    padded
+}
+
+/// Length of N in bytes
+/// 
+/// ### Arguments
+/// 
+/// * `group` - Curve group
+fn scalar_length(group: &ECPGroup) -> usize {
+    (group.n.bits() + 7) / 8
+}
+
+/// Length of the truncated challenge in bytes
+/// 
+/// ### Arguments
+/// 
+/// * `group` - Curve group
+fn challenge_length(group: &ECPGroup) -> usize {
+    scalar_length(group) / 2
+}
+
+/// Order N of the group's generator
+/// 
+/// ### Arguments
+/// 
+/// * `group` - Curve group
+fn group_order(group: &ECPGroup) -> BigUint {
+    group.n.to_biguint().unwrap()
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod vrf_test {
+
+    use num_bigint::BigUint;
+    use num_traits::One;
+    use crate::hash::crypto::HashAlgorithm;
+    use crate::utils::rng::os_rng;
+    use crate::utils::ecc::{ ECPKeypair, ECPPublicKey };
+    use crate::utils::ecc_curves::ECPSupportedCurves;
+    use crate::signature::vrf::{ self, VrfError, VrfProof };
+
+    fn keypair(curve: ECPSupportedCurves) -> (ECPKeypair, ECPPublicKey) {
+        let mut rng = os_rng().unwrap();
+        let keypair = ECPKeypair::new(curve).setup(&mut rng).unwrap();
+        let public = keypair.public_key().unwrap();
+
+        (keypair, public)
+    }
+
+    #[test]
+    fn output_is_deterministic_and_verifies() {
+        let mut rng = os_rng().unwrap();
+
+        for curve in &[ECPSupportedCurves::SECP256R1, ECPSupportedCurves::BP384R1, ECPSupportedCurves::SECP521R1] {
+            let (keypair, public) = keypair(curve.clone());
+
+            let (beta, proof) = vrf::prove(&keypair, b"input", HashAlgorithm::Sha3_256, &mut rng).unwrap();
+            let (again, same) = vrf::prove(&keypair, b"input", HashAlgorithm::Sha3_256, &mut rng).unwrap();
+
+            assert_eq!(beta, again);
+            assert_eq!(proof, same);
+            assert_eq!(vrf::verify(&public, b"input", &proof, HashAlgorithm::Sha3_256, &mut rng), Ok(beta));
+
+            let bytes = proof.to_bytes(&keypair.group);
+            assert_eq!(VrfProof::from_bytes(&keypair.group, &bytes), Ok(proof));
+            assert_eq!(VrfProof::from_bytes(&keypair.group, &bytes[1..]), Err(VrfError::MalformedProof));
+        }
+    }
+
+    #[test]
+    fn distinct_inputs_give_distinct_outputs() {
+        let mut rng = os_rng().unwrap();
+        let (keypair, _) = keypair(ECPSupportedCurves::SECP256R1);
+        let (other, _) = keypair(ECPSupportedCurves::SECP256R1);
+
+        let (first, _) = vrf::prove(&keypair, b"first", HashAlgorithm::Sha3_256, &mut rng).unwrap();
+        let (second, _) = vrf::prove(&keypair, b"second", HashAlgorithm::Sha3_256, &mut rng).unwrap();
+        let (by_other, _) = vrf::prove(&other, b"first", HashAlgorithm::Sha3_256, &mut rng).unwrap();
+
+        assert_ne!(first, second);
+        assert_ne!(first, by_other);
+    }
+
+    #[test]
+    fn altered_input_or_proof_is_rejected() {
+        let mut rng = os_rng().unwrap();
+        let (keypair, public) = keypair(ECPSupportedCurves::SECP256R1);
+        let (_, other_public) = keypair(ECPSupportedCurves::SECP256R1);
+        let (_, proof) = vrf::prove(&keypair, b"input", HashAlgorithm::Sha3_256, &mut rng).unwrap();
+
+        assert_eq!(vrf::verify(&public, b"other input", &proof, HashAlgorithm::Sha3_256, &mut rng), Err(VrfError::VerificationFailed));
+        assert_eq!(vrf::verify(&other_public, b"input", &proof, HashAlgorithm::Sha3_256, &mut rng), Err(VrfError::VerificationFailed));
+        assert_eq!(vrf::verify(&public, b"input", &proof, HashAlgorithm::Sha3_512, &mut rng), Err(VrfError::VerificationFailed));
+
+        let mut altered = proof.clone();
+        altered.c = &altered.c + BigUint::one();
+        assert_eq!(vrf::verify(&public, b"input", &altered, HashAlgorithm::Sha3_256, &mut rng), Err(VrfError::VerificationFailed));
+
+        let mut altered = proof.clone();
+        altered.s = &altered.s + BigUint::one();
+        assert_eq!(vrf::verify(&public, b"input", &altered, HashAlgorithm::Sha3_256, &mut rng), Err(VrfError::VerificationFailed));
+
+        // Gamma replaced by another valid point
+        let mut altered = proof.clone();
+        altered.gamma = vrf::compress(&keypair.group, &keypair.q).unwrap();
+        assert_eq!(vrf::verify(&public, b"input", &altered, HashAlgorithm::Sha3_256, &mut rng), Err(VrfError::VerificationFailed));
+
+        let mut altered = proof.clone();
+        altered.gamma[0] = 0x05;
+        assert_eq!(vrf::verify(&public, b"input", &altered, HashAlgorithm::Sha3_256, &mut rng), Err(VrfError::MalformedProof));
+    }
+
+    #[test]
+    fn montgomery_curves_are_rejected() {
+        let mut rng = os_rng().unwrap();
+        let (keypair, _) = keypair(ECPSupportedCurves::Curve25519);
+
+        assert_eq!(vrf::prove(&keypair, b"input", HashAlgorithm::Sha3_256, &mut rng).err(), Some(VrfError::UnsupportedCurve));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,411 @@
+//! Deterministic test vectors for every primitive in the crate. Each
+//! primitive runs from a ChaCha generator seeded with `SEED` and its own
+//! name, and records its inputs and outputs as hex strings. The tests
+//! replay a committed copy of the vectors, so any change to arithmetic,
+//! encodings or chunking that alters a result fails with a diff naming
+//! exactly the primitive and value that moved.
+//!
+//! The committed copy lives at `tests/fixtures/vectors.json`. When
+//! `CRYPTOFUN_BLESS` is set the tests rewrite it instead of comparing
+//! against it, and when it is missing they fail. Only the primitives it
+//! has records for are replayed, so one blessed without a feature, as
+//! the committed copy is without ecc and ecdsa, leaves that feature's
+//! primitives unpinned until it is blessed again. Outside of tests the
+//! module is built with the testvectors feature, which also provides a
+//! binary that prints the vectors:
+//!
+//! ```text
+//! cargo run --features testvectors --bin testvectors
+//! ```
+
+use std::collections::{ BTreeMap, BTreeSet };
+use rand::{ ChaChaRng, SeedableRng };
+
+use crate::utils::encoding::bytes_to_hex;
+#[cfg(any(feature = "hashes-sha3", feature = "hashes-blake2"))]
+use crate::hash::crypto::{ hash_message, HashAlgorithm };
+#[cfg(feature = "ecc")]
+use crate::utils::ecc_curves::ECPSupportedCurves;
+
+
+/*---- CONSTANTS ----*/
+
+/// Seed shared by every primitive
+pub const SEED: [u32; 4] = [0x6372_7970, 0x746f_6675, 0x6e20_7465, 0x7374_7673];
+
+/// Message fed to the hashes, signatures and ciphers
+pub const MESSAGE: &'static [u8] = b"cryptofun test vectors: the quick brown fox jumps over the lazy dog";
+
+/// Location of the committed fixture, relative to the crate root
+pub const FIXTURE_PATH: &'static str = "tests/fixtures/vectors.json";
+
+/// Bit length of the RSA modulus
+#[cfg(feature = "rsa")]
+const RSA_BITS: usize = 512;
+
+/// Bit length of the Diffie-Hellman modulus
+#[cfg(feature = "dh")]
+const DH_BITS: usize = 64;
+
+
+/*---- STRUCTS ----*/
+
+/// Named hex values recorded by one primitive
+pub type Record = BTreeMap<String, String>;
+
+/// Records of every primitive, keyed by primitive name
+pub type Fixture = BTreeMap<String, Record>;
+
+
+/*---- FUNCTIONS ----*/
+
+/// Runs every enabled primitive and collects its records
+pub fn generate() -> Fixture {
+    let mut fixture = Fixture::new();
+
+    #[cfg(any(feature = "hashes-sha3", feature = "hashes-blake2"))]
+    hashes(&mut fixture);
+
+    #[cfg(feature = "rsa")]
+    rsa(&mut fixture);
+
+    #[cfg(feature = "aes")]
+    aes(&mut fixture);
+
+    #[cfg(feature = "ecc")]
+    ecdh(&mut fixture);
+
+    #[cfg(feature = "ecdsa")]
+    ecdsa(&mut fixture);
+
+    #[cfg(feature = "dh")]
+    dh(&mut fixture);
+
+    fixture
+}
+
+/// Writes a fixture as pretty-printed JSON, ordered by name
+/// 
+/// ### Arguments
+/// 
+/// * `fixture` - Fixture to write
+pub fn to_json(fixture: &Fixture) -> String {
+    serde_json::to_string_pretty(fixture).unwrap()
+}
+
+/// Reads a fixture written by `to_json`
+/// 
+/// ### Arguments
+/// 
+/// * `json` - JSON to read
+pub fn from_json(json: &str) -> Result<Fixture, serde_json::Error> {
+    serde_json::from_str(json)
+}
+
+/// Lists every value that differs between two fixtures, as
+/// "primitive.name" followed by the expected and actual values
+/// 
+/// ### Arguments
+/// 
+/// * `expected` - Committed fixture
+/// * `actual` - Freshly generated fixture
+pub fn diff(expected: &Fixture, actual: &Fixture) -> Vec<String> {
+    let mut differences = Vec::new();
+    let empty = Record::new();
+
+    let primitives: BTreeSet<&String> = expected.keys().chain(actual.keys()).collect();
+
+    for primitive in primitives {
+        let before = expected.get(primitive).unwrap_or(&empty);
+        let after = actual.get(primitive).unwrap_or(&empty);
+        let names: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+
+        for name in names {
+            let (old, new) = (before.get(name), after.get(name));
+
+            if old != new {
+                differences.push(format!(
+                    "{}.{}: expected {}, got {}",
+                    primitive,
+                    name,
+                    old.map_or("nothing", |value| value.as_str()),
+                    new.map_or("nothing", |value| value.as_str())
+                ));
+            }
+        }
+    }
+
+    differences
+}
+
+/// Generator for one primitive, seeded with `SEED` followed by the
+/// primitive's name so that adding a primitive leaves the others alone
+/// 
+/// ### Arguments
+/// 
+/// * `primitive` - Name of the primitive
+fn rng_for(primitive: &str) -> ChaChaRng {
+    let mut seed = SEED.to_vec();
+    seed.extend(primitive.bytes().map(|byte| byte as u32));
+
+    ChaChaRng::from_seed(&seed)
+}
+
+/// Adds a record to the fixture
+/// 
+/// ### Arguments
+/// 
+/// * `fixture` - Fixture to add to
+/// * `primitive` - Name of the primitive
+/// * `values` - Named byte values to record as hex
+fn record(fixture: &mut Fixture, primitive: &str, values: &[(&str, &[u8])]) -> () {
+    let record = values.iter().map(|&(name, bytes)| (name.to_string(), bytes_to_hex(bytes))).collect();
+    fixture.insert(primitive.to_string(), record);
+}
+
+/// Digests of the empty string and of `MESSAGE` for every hash
+/// 
+/// ### Arguments
+/// 
+/// * `fixture` - Fixture to add to
+#[cfg(any(feature = "hashes-sha3", feature = "hashes-blake2"))]
+fn hashes(fixture: &mut Fixture) -> () {
+    let mut algorithms = Vec::new();
+
+    #[cfg(feature = "hashes-sha3")]
+    algorithms.extend(vec![
+        ("sha3_256", HashAlgorithm::Sha3_256),
+        ("sha3_512", HashAlgorithm::Sha3_512),
+        ("keccak256", HashAlgorithm::Keccak256),
+        ("keccak512", HashAlgorithm::Keccak512)
+    ]);
+
+    #[cfg(feature = "hashes-blake2")]
+    algorithms.extend(vec![
+        ("blake2b", HashAlgorithm::Blake2b),
+        ("blake2s", HashAlgorithm::Blake2s)
+    ]);
+
+    for (name, algorithm) in algorithms {
+        let empty = hash_message(&[], algorithm.clone());
+        let digest = hash_message(MESSAGE, algorithm);
+
+        record(fixture, &format!("hash/{}", name), &[("empty", &empty[..]), ("message", &digest[..])]);
+    }
+}
+
+/// Raw RSA public and private operations under a seeded keypair
+/// 
+/// ### Arguments
+/// 
+/// * `fixture` - Fixture to add to
+#[cfg(feature = "rsa")]
+fn rsa(fixture: &mut Fixture) -> () {
+    use num_bigint::BigUint;
+    use num_traits::FromPrimitive;
+    use crate::encryption::AsymmetricKeyMode;
+    use crate::encryption::rsa::RSA;
+
+    let mut rng = rng_for("rsa");
+    let exponent = BigUint::from_u32(65537).unwrap();
+    let rsa = RSA::new_raw_unsafe(HashAlgorithm::Sha3_256, true).allow_insecure_for_testing().generate_keypair(RSA_BITS, &exponent, &mut rng).unwrap();
+
+    let public = rsa.encrypt(MESSAGE, AsymmetricKeyMode::Public, &mut rng).unwrap();
+    let private = rsa.encrypt(MESSAGE, AsymmetricKeyMode::Private, &mut rng).unwrap();
+    let decrypted = rsa.decrypt(&public, AsymmetricKeyMode::Private, &mut rng).unwrap();
+
+    record(fixture, "rsa/raw", &[
+        ("n", &rsa.n.to_bytes_be()[..]),
+        ("e", &rsa.e.to_bytes_be()[..]),
+        ("public_op", &public[..]),
+        ("private_op", &private[..]),
+        ("decrypted", &decrypted[..])
+    ]);
+}
+
+/// AES-256 in counter, GCM, CBC and CCM modes, with seeded keys and IVs
+/// 
+/// ### Arguments
+/// 
+/// * `fixture` - Fixture to add to
+#[cfg(feature = "aes")]
+fn aes(fixture: &mut Fixture) -> () {
+    use crypto::aes::KeySize;
+    use crate::encryption::aes::{ AES, AESMode };
+
+    for &name in ["aes/ctr", "aes/gcm", "aes/cbc", "aes/ccm", "aes/siv"].iter() {
+        let (mode, aad) = match name {
+            "aes/ctr" => (AESMode::Counter, None),
+            "aes/cbc" => (AESMode::CBC, None),
+            "aes/siv" => (AESMode::SIV, Some(b"associated data".to_vec())),
+            "aes/ccm" => (AESMode::CCM { tag_len: 16, nonce_len: 12 }, Some(b"associated data".to_vec())),
+            _ => (AESMode::GCM, Some(b"associated data".to_vec()))
+        };
+
+        let mut cipher = AES::new(KeySize::KeySize256, mode, aad, &mut rng_for(name));
+        let ciphertext = cipher.encrypt(MESSAGE).unwrap();
+
+        record(fixture, name, &[
+            ("key", cipher.key()),
+            ("iv", cipher.initialization_vector()),
+            ("ciphertext", &ciphertext[..])
+        ]);
+    }
+}
+
+/// ECDH agreement between two seeded keypairs on every curve
+/// 
+/// ### Arguments
+/// 
+/// * `fixture` - Fixture to add to
+#[cfg(feature = "ecc")]
+fn ecdh(fixture: &mut Fixture) -> () {
+    use crate::key_exchange::ecdh::ECDH;
+
+    for (name, curve) in curves().into_iter() {
+        let primitive = format!("ecdh/{}", name);
+        let mut rng = rng_for(&primitive);
+
+        let mut alice = ECDH::new(curve.clone(), &mut rng).unwrap();
+        let bob = ECDH::new(curve, &mut rng).unwrap();
+
+        let alice_public = alice.public_key_bytes(false);
+        let bob_public = bob.public_key_bytes(false);
+        alice.set_peer_public_key_bytes(&bob_public).unwrap();
+        let shared = alice.derive_shared_bytes(&mut rng).unwrap();
+
+        record(fixture, &primitive, &[("alice", &alice_public[..]), ("bob", &bob_public[..]), ("shared", &shared[..])]);
+    }
+}
+
+/// ECDSA signatures over the digest of `MESSAGE` on every short
+/// Weierstrass curve
+/// 
+/// ### Arguments
+/// 
+/// * `fixture` - Fixture to add to
+#[cfg(feature = "ecdsa")]
+fn ecdsa(fixture: &mut Fixture) -> () {
+    use crate::signature::ecdsa::ECDSA;
+
+    let digest = hash_message(MESSAGE, HashAlgorithm::Sha3_256);
+
+    for (name, curve) in curves().into_iter().filter(|(_, curve)| curve != &ECPSupportedCurves::Curve25519) {
+        let primitive = format!("ecdsa/{}", name);
+        let mut rng = rng_for(&primitive);
+
+        let mut ecdsa = ECDSA::new(curve, &mut rng).unwrap();
+        let signature = ecdsa.sign(&digest, &mut rng).unwrap();
+
+        record(fixture, &primitive, &[
+            ("public", ecdsa.public_key().unwrap().point_bytes()),
+            ("digest", &digest[..]),
+            ("signature", &signature.to_der()[..])
+        ]);
+    }
+}
+
+/// Finite field Diffie-Hellman agreement over seeded parameters
+/// 
+/// ### Arguments
+/// 
+/// * `fixture` - Fixture to add to
+#[cfg(feature = "dh")]
+fn dh(fixture: &mut Fixture) -> () {
+    use crate::key_exchange::diffie_hellman::DiffieHellman;
+
+    let mut rng = rng_for("dh");
+    let mut alice = DiffieHellman::new().setup(DH_BITS, &mut rng).unwrap();
+    let alice_params = alice.public_params().unwrap();
+
+    let mut bob = DiffieHellman::new_from_params(&alice_params).setup(DH_BITS, &mut rng).unwrap();
+    let bob_params = bob.public_params().unwrap();
+
+    let shared = alice.generate_shared_key(&mut rng, bob_params.gx()).unwrap();
+    assert_eq!(shared, bob.generate_shared_key(&mut rng, alice_params.gx()).unwrap());
+
+    record(fixture, "dh", &[
+        ("p", &alice_params.p().to_bytes_be()[..]),
+        ("g", &alice_params.g().to_bytes_be()[..]),
+        ("alice", &alice_params.gx().to_bytes_be()[..]),
+        ("bob", &bob_params.gx().to_bytes_be()[..]),
+        ("shared", &shared.to_bytes_be()[..])
+    ]);
+}
+
+/// Every supported curve with the name it is recorded under
+#[cfg(feature = "ecc")]
+fn curves() -> Vec<(&'static str, ECPSupportedCurves)> {
+    vec![
+        ("secp256r1", ECPSupportedCurves::SECP256R1),
+        ("secp384r1", ECPSupportedCurves::SECP384R1),
+        ("secp521r1", ECPSupportedCurves::SECP521R1),
+        ("bp256r1", ECPSupportedCurves::BP256R1),
+        ("bp384r1", ECPSupportedCurves::BP384R1),
+        ("bp521r1", ECPSupportedCurves::BP521R1),
+        ("curve25519", ECPSupportedCurves::Curve25519)
+    ]
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod testvectors_test {
+
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use crate::testvectors::{ self, Fixture, Record };
+
+    fn fixture_path() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(testvectors::FIXTURE_PATH)
+    }
+
+    #[test]
+    fn vectors_match_committed_fixture() {
+        let actual = testvectors::generate();
+        let path = fixture_path();
+
+        if env::var_os("CRYPTOFUN_BLESS").is_some() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, testvectors::to_json(&actual) + "\n").unwrap();
+            return;
+        }
+
+        let json = fs::read_to_string(&path).unwrap_or_else(|error| panic!(
+            "cannot read {} ({}), run the tests with CRYPTOFUN_BLESS=1 to write it",
+            path.display(),
+            error
+        ));
+        let expected = testvectors::from_json(&json).unwrap();
+        let actual: Fixture = actual.into_iter().filter(|(primitive, _)| expected.contains_key(primitive)).collect();
+        let differences = testvectors::diff(&expected, &actual);
+
+        assert!(differences.is_empty(), "test vectors moved:\n{}", differences.join("\n"));
+    }
+
+    #[test]
+    fn generation_is_deterministic() {
+        assert_eq!(testvectors::generate(), testvectors::generate());
+    }
+
+    #[test]
+    fn diff_names_the_value_that_moved() {
+        let mut record = Record::new();
+        record.insert("output".to_string(), "00".to_string());
+
+        let mut expected = Fixture::new();
+        expected.insert("primitive".to_string(), record.clone());
+
+        let mut actual = expected.clone();
+        actual.get_mut("primitive").unwrap().insert("output".to_string(), "01".to_string());
+        actual.insert("added".to_string(), record);
+
+        assert_eq!(testvectors::diff(&expected, &actual), vec![
+            "added.output: expected nothing, got 00".to_string(),
+            "primitive.output: expected 00, got 01".to_string()
+        ]);
+        assert_eq!(testvectors::from_json(&testvectors::to_json(&actual)).unwrap(), actual);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,305 @@
+//! Record and replay of session handshakes for debugging interop. A
+//! `Transcript` installed on a session receives an event for every
+//! public value, handshake hash, derived secret and frame, which can be
+//! written to JSON on each side and compared offline.
+//!
+//! Events name the side a value came from rather than whether it was
+//! sent or received, so the transcripts of the two ends of a healthy
+//! exchange are identical and `first_divergence` points straight at the
+//! first value the two sides disagree on. Secrets are only ever recorded
+//! as a short fingerprint, never as their raw bytes
+
+use std::sync::{ Arc, Mutex };
+use serde::{ Serialize, Deserialize };
+
+use crate::hash::crypto::{ hash_message, HashAlgorithm };
+use crate::utils::serde_hex;
+
+
+/*---- CONSTANTS ----*/
+
+/// Domain separating secret fingerprints from other uses of the hash
+const FINGERPRINT_LABEL: &'static [u8] = b"cryptofun trace fingerprint";
+
+/// Length in bytes of a secret fingerprint
+const FINGERPRINT_LENGTH: usize = 8;
+
+
+/*---- ENUMS ----*/
+
+/// Side of a session
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Initiator,  // side that sent the first handshake message
+    Responder   // side that answered it
+}
+
+/// Something a session did that both sides should agree on
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    PublicValue {                       // key exchange public value, as sent or received
+        from: Role,
+        #[serde(with = "serde_hex::bytes")]
+        value: Vec<u8>
+    },
+    HandshakeHash {                     // transcript hash signed by one side, as signed or verified
+        signer: Role,
+        #[serde(with = "serde_hex::bytes")]
+        hash: Vec<u8>
+    },
+    SecretFingerprint {                 // fingerprint of a shared secret or derived key
+        label: String,
+        #[serde(with = "serde_hex::bytes")]
+        fingerprint: Vec<u8>
+    },
+    Frame {                             // frame sealed, or opened with a valid tag
+        from: Role,
+        counter: u64
+    },
+    FrameRejected {                     // frame whose tag failed to verify
+        from: Role,
+        counter: u64
+    }
+}
+
+
+/*---- STRUCTS ----*/
+
+/// First point at which two transcripts differ. An event is missing when
+/// one transcript ends before the other
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Divergence {
+    pub index: usize,           // position of the first differing event
+    pub left: Option<Event>,    // event of the first transcript at that position
+    pub right: Option<Event>    // event of the second transcript at that position
+}
+
+/// Transcript that keeps every event in memory. Clones share the same
+/// events, so one clone can be installed on a session and the other
+/// read back afterwards
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    events: Arc<Mutex<Vec<Event>>>     // events recorded so far
+}
+
+/// Sink installed on a session, along with the side it records for
+pub(crate) struct Tracer {
+    pub role: Role,                 // side the session plays
+    sink: Box<dyn Transcript>       // receiver of the events
+}
+
+
+/*---- TRAITS ----*/
+
+/// Receiver of session events
+pub trait Transcript: Send {
+
+    /// Records an event
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `event` - Event the session produced
+    fn record(&mut self, event: Event) -> ();
+}
+
+
+/*---- IMPLEMENTATIONS ----*/
+
+impl Role {
+
+    /// The other side
+    pub fn peer(&self) -> Role {
+        match self {
+            Role::Initiator => Role::Responder,
+            Role::Responder => Role::Initiator
+        }
+    }
+}
+
+impl Recorder {
+
+    /// Creates an empty recorder
+    pub fn new() -> Self {
+        Recorder::default()
+    }
+
+    /// Copy of the events recorded so far
+    pub fn events(&self) -> Vec<Event> {
+        self.events.lock().unwrap().clone()
+    }
+
+    /// Events recorded so far as JSON
+    pub fn to_json(&self) -> String {
+        to_json(&self.events())
+    }
+}
+
+impl Transcript for Recorder {
+    fn record(&mut self, event: Event) -> () {
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+impl Tracer {
+
+    /// Creates a tracer
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `role` - Side the session plays
+    /// * `sink` - Receiver of the events
+    pub fn new(role: Role, sink: Box<dyn Transcript>) -> Self {
+        Tracer { role: role, sink: sink }
+    }
+
+    /// Passes an event to the sink
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `event` - Event the session produced
+    pub fn record(&mut self, event: Event) -> () {
+        self.sink.record(event);
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Short fingerprint of a secret, safe to log. The secret is hashed
+/// under its own label, so the fingerprint reveals nothing useful about
+/// it beyond whether two sides hold the same value
+/// 
+/// ### Arguments
+/// 
+/// * `secret` - Secret to fingerprint
+pub fn fingerprint(secret: &[u8]) -> Vec<u8> {
+    let mut input = FINGERPRINT_LABEL.to_vec();
+    input.extend_from_slice(secret);
+
+    let mut digest = hash_message(&input, HashAlgorithm::Sha3_256);
+    digest.truncate(FINGERPRINT_LENGTH);
+
+    digest
+}
+
+/// Writes events as pretty-printed JSON
+/// 
+/// ### Arguments
+/// 
+/// * `events` - Events to write
+pub fn to_json(events: &[Event]) -> String {
+    serde_json::to_string_pretty(events).unwrap()
+}
+
+/// Reads events written by `to_json`
+/// 
+/// ### Arguments
+/// 
+/// * `json` - JSON to read
+pub fn from_json(json: &str) -> Result<Vec<Event>, serde_json::Error> {
+    serde_json::from_str(json)
+}
+
+/// Finds the earliest event at which two transcripts differ, or `None`
+/// when they are identical
+/// 
+/// ### Arguments
+/// 
+/// * `a` - First transcript, usually from one side
+/// * `b` - Second transcript, usually from the other side
+pub fn first_divergence(a: &[Event], b: &[Event]) -> Option<Divergence> {
+    (0..a.len().max(b.len()))
+        .find(|index| a.get(*index) != b.get(*index))
+        .map(|index| Divergence {
+            index: index,
+            left: a.get(index).cloned(),
+            right: b.get(index).cloned()
+        })
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod transcript_test {
+
+    use crate::key_exchange::ecdh::ECDH;
+    use crate::signature::ecdsa::ECDSA;
+    use crate::utils::rng::os_rng;
+    use crate::utils::ecc_curves::ECPSupportedCurves;
+    use crate::session::Session;
+    use crate::transcript::{ Event, Recorder, Role, first_divergence, from_json };
+
+    #[test]
+    fn successful_exchange_has_no_divergence() {
+        let mut rng = os_rng().unwrap();
+        let mut alice_signer = ECDSA::new(ECPSupportedCurves::BP256R1, &mut rng).unwrap();
+        let mut bob_signer = ECDSA::new(ECPSupportedCurves::BP256R1, &mut rng).unwrap();
+        let alice_key = alice_signer.public_key().unwrap();
+        let bob_key = bob_signer.public_key().unwrap();
+        let alice = Recorder::new();
+        let bob = Recorder::new();
+
+        let alice_kex = ECDH::new(ECPSupportedCurves::Curve25519, &mut rng).unwrap();
+        let bob_kex = ECDH::new(ECPSupportedCurves::Curve25519, &mut rng).unwrap();
+
+        let (pending, message) = Session::initiate_traced(alice_kex, &mut alice_signer, Box::new(alice.clone()), &mut rng).unwrap();
+        let (mut bob_channel, reply) = Session::respond_traced(bob_kex, &mut bob_signer, &alice_key, &message, Box::new(bob.clone()), &mut rng).unwrap();
+        let mut alice_channel = pending.complete(&bob_key, &reply, &mut rng).unwrap();
+
+        bob_channel.recv(&alice_channel.send(b"ping").unwrap()).unwrap();
+        alice_channel.recv(&bob_channel.send(b"pong").unwrap()).unwrap();
+
+        let events = alice.events();
+        assert_eq!(events.len(), 9);
+        assert_eq!(events[7], Event::Frame { from: Role::Initiator, counter: 0 });
+        assert_eq!(first_divergence(&events, &bob.events()), None);
+
+        // Both sides survive the trip through JSON
+        assert_eq!(from_json(&alice.to_json()).unwrap(), events);
+        assert_eq!(first_divergence(&from_json(&bob.to_json()).unwrap(), &events), None);
+
+        // A rejected frame shows up on the receiving side only
+        let mut tampered = alice_channel.send(b"again").unwrap();
+        tampered[9] ^= 1;
+        assert!(bob_channel.recv(&tampered).is_err());
+
+        let divergence = first_divergence(&alice.events(), &bob.events()).unwrap();
+        assert_eq!(divergence.index, 9);
+        assert_eq!(divergence.left, Some(Event::Frame { from: Role::Initiator, counter: 1 }));
+        assert_eq!(divergence.right, Some(Event::FrameRejected { from: Role::Initiator, counter: 1 }));
+    }
+
+    #[test]
+    fn corrupted_public_value_is_pinpointed() {
+        let mut rng = os_rng().unwrap();
+        let mut alice_signer = ECDSA::new(ECPSupportedCurves::BP256R1, &mut rng).unwrap();
+        let mut bob_signer = ECDSA::new(ECPSupportedCurves::BP256R1, &mut rng).unwrap();
+        let alice_key = alice_signer.public_key().unwrap();
+        let bob_key = bob_signer.public_key().unwrap();
+        let alice = Recorder::new();
+        let bob = Recorder::new();
+
+        let alice_kex = ECDH::new(ECPSupportedCurves::Curve25519, &mut rng).unwrap();
+        let bob_kex = ECDH::new(ECPSupportedCurves::Curve25519, &mut rng).unwrap();
+
+        let (pending, message) = Session::initiate_traced(alice_kex, &mut alice_signer, Box::new(alice.clone()), &mut rng).unwrap();
+        let (_, mut reply) = Session::respond_traced(bob_kex, &mut bob_signer, &alice_key, &message, Box::new(bob.clone()), &mut rng).unwrap();
+
+        // Corrupt the responder's public value on the wire
+        reply[10] ^= 1;
+        assert!(pending.complete(&bob_key, &reply, &mut rng).is_err());
+
+        let divergence = first_divergence(&alice.events(), &bob.events()).unwrap();
+        assert_eq!(divergence.index, 2);
+
+        match (divergence.left, divergence.right) {
+            (Some(Event::PublicValue { from: Role::Responder, value: seen }), Some(Event::PublicValue { from: Role::Responder, value: sent })) => {
+                assert_eq!(seen[6], sent[6] ^ 1);
+            },
+            other => panic!("Unexpected divergence {:?}", other)
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,170 @@
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::time::{ Duration, Instant };
+
+
+/*---- STRUCTS ----*/
+
+/// Limit on the work a key or prime generation may do before giving up.
+/// One budget is shared by every candidate loop of an operation, so a
+/// keypair's two primes draw from the same iterations and deadline
+#[derive(Debug, Clone)]
+pub struct Budget {
+    pub max_iterations: usize,          // candidates allowed across the whole operation
+    pub deadline: Option<Instant>,      // time after which the operation gives up
+    cancel: Option<Arc<AtomicBool>>,    // flag another thread sets to stop the operation
+    spent: usize                        // candidates tried so far
+}
+
+
+/*---- IMPLEMENTATIONS ----*/
+
+impl Budget {
+
+    /// Creates a budget
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `max_iterations` - Candidates allowed across the whole operation
+    /// * `deadline` - Optional time after which the operation gives up
+    pub fn new(max_iterations: usize, deadline: Option<Instant>) -> Self {
+        Budget {
+            max_iterations: max_iterations,
+            deadline: deadline,
+            cancel: None,
+            spent: 0
+        }
+    }
+
+    /// Budget that never runs out, used by the functions that take none
+    pub fn unlimited() -> Self {
+        Budget::new(usize::MAX, None)
+    }
+
+    /// Budget limited only by time, starting now
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `timeout` - Time the operation may take
+    pub fn timeout(timeout: Duration) -> Self {
+        Budget::new(usize::MAX, Some(Instant::now() + timeout))
+    }
+
+    /// Makes the budget run out as soon as the flag is set, so that a
+    /// search on one thread can be called off from another
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `flag` - Flag that cancels the operation once set
+    pub fn with_cancel(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(flag);
+        self
+    }
+
+    /// Counts one iteration and reports whether the budget has run out.
+    /// The clock is only read when a deadline is set
+    pub fn exhausted(&mut self) -> bool {
+        self.spent = self.spent.saturating_add(1);
+
+        if self.spent > self.max_iterations {
+            return true;
+        }
+
+        if let Some(flag) = &self.cancel {
+            if flag.load(Ordering::Relaxed) {
+                return true;
+            }
+        }
+
+        match self.deadline {
+            Some(deadline) => Instant::now() >= deadline,
+            None => false
+        }
+    }
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod budget_test {
+
+    use std::sync::Arc;
+    use std::sync::atomic::{ AtomicBool, Ordering };
+    use std::time::{ Duration, Instant };
+    use rand::{ ChaChaRng, SeedableRng };
+    use num_bigint::BigUint;
+    use num_traits::FromPrimitive;
+    use crate::utils::budget::Budget;
+    use crate::utils::primes::{ self, PrimeError };
+
+    fn generous() -> Budget {
+        Budget::new(1_000_000, Some(Instant::now() + Duration::from_secs(600)))
+    }
+
+    #[test]
+    fn budget_counts_iterations_and_time() {
+        let mut budget = Budget::new(2, None);
+        assert!(!budget.exhausted());
+        assert!(!budget.exhausted());
+        assert!(budget.exhausted());
+
+        let mut expired = Budget::timeout(Duration::from_secs(0));
+        assert!(expired.exhausted());
+        assert!(!Budget::unlimited().exhausted());
+    }
+
+    #[test]
+    fn budget_runs_out_when_cancelled() {
+        let flag = Arc::new(AtomicBool::new(false));
+        let mut budget = Budget::unlimited().with_cancel(flag.clone());
+        assert!(!budget.exhausted());
+
+        flag.store(true, Ordering::Relaxed);
+        assert!(budget.exhausted());
+
+        let mut rng = ChaChaRng::from_seed(&[0x63, 0x61]);
+        assert_eq!(primes::generate_with_budget(&4096, &mut budget, &mut rng), Err(PrimeError::Timeout));
+    }
+
+    #[test]
+    fn prime_generation_stops_when_out_of_budget() {
+        let mut rng = ChaChaRng::from_seed(&[0x62, 0x75]);
+
+        assert_eq!(primes::generate_with_budget(&4096, &mut Budget::new(1, None), &mut rng), Err(PrimeError::Timeout));
+        assert_eq!(primes::generate_discrete_log_prime_with_budget(&4096, &mut Budget::timeout(Duration::from_secs(0)), &mut rng), Err(PrimeError::Timeout));
+        assert!(primes::generate_with_budget(&256, &mut generous(), &mut rng).is_ok());
+    }
+
+    #[cfg(feature = "rsa")]
+    #[test]
+    fn rsa_key_generation_stops_when_out_of_budget() {
+        use crate::encryption::rsa::{ RSA, RsaError };
+        use crate::error::Error;
+        use crate::hash::crypto::HashAlgorithm;
+
+        let mut rng = ChaChaRng::from_seed(&[0x62, 0x75, 0x72]);
+        let exponent = BigUint::from_u32(65537).unwrap();
+
+        let result = RSA::new(HashAlgorithm::Sha3_256, true).generate_keypair_with_budget(4096, &exponent, false, &mut Budget::new(8, None), &mut rng);
+        assert_eq!(result.err(), Some(RsaError::Timeout));
+
+        let result = RSA::new(HashAlgorithm::Sha3_256, true).generate_keypair_with_budget(4096, &exponent, false, &mut Budget::timeout(Duration::from_secs(0)), &mut rng);
+        assert!(match result.err().map(Error::from) { Some(Error::Timeout) => true, _ => false });
+
+        assert!(RSA::new(HashAlgorithm::Sha3_256, true).allow_insecure_for_testing().generate_keypair_with_budget(512, &exponent, false, &mut generous(), &mut rng).is_ok());
+    }
+
+    #[cfg(feature = "ecc")]
+    #[test]
+    fn ecc_key_generation_stops_when_out_of_budget() {
+        use crate::utils::ecc::{ ECPKeypair, EcError };
+        use crate::utils::ecc_curves::ECPSupportedCurves;
+
+        let mut rng = ChaChaRng::from_seed(&[0x62, 0x75, 0x72, 0x6e]);
+
+        let result = ECPKeypair::new(ECPSupportedCurves::SECP256R1).setup_with_budget(&mut Budget::new(0, None), &mut rng);
+        assert_eq!(result.err(), Some(EcError::Timeout));
+        assert!(ECPKeypair::new(ECPSupportedCurves::SECP256R1).setup_with_budget(&mut generous(), &mut rng).is_ok());
+    }
+}
\ No newline at end of file
@@ -11,15 +11,17 @@
 /// ePrint Archive, 2004, vol. 2004, p. 342.
 /// <http://eprint.iacr.org/2004/342.pdf>
 
-use rand::OsRng;
-
 use num_integer::Integer;
-use num_traits::{ One, Zero };
-use num_bigint::{ BigUint, BigInt };
+use num_traits::One;
+use num_bigint::{ BigUint, BigInt, ToBigInt, ToBigUint };
 
-use utils::jacobian_coords;
-use utils::ecc_curves::{ ECPGroup, ECPPoint };
-use utils::encoding::{ EndianOrdering, biguint_to_bitvec };
+use crate::utils::ct;
+use crate::utils::jacobian_coords;
+use crate::utils::encoding::{ EndianOrdering, biguint_to_fixed_bytes };
+use crate::utils::rng::CryptoRngCore;
+use crate::utils::ecc_curves::{ ECPGroup, ECPPoint };
+#[cfg(any(test, feature = "metrics"))]
+use crate::utils::metrics::{ self, Operation };
 
 
 /*---- CONSTANTS ----*/
@@ -73,18 +75,16 @@ const FIXED_POINT_OPT: bool = true;
 /// * `d` - Fixed D size
 /// * `w` - Comb size, i.e. number of teeth of the comb, and must be between
 ///         2 and 7 (in practice, between 2 and MBEDTLS_ECP_WINDOW_SIZE)
-/// * `m` - Expected to be odd and such that bitlength(m) <= w/// d
+/// * `m` - Expected to be odd and such that bitlength(m) <= w * d
 ///         (the result will be incorrect if these assumptions are not satisfied)
 fn fixed_method(d: &usize, w: &usize, m: &BigUint) -> Vec<u8> {
-    let mut x = vec![0; d + 1];
-    let m_vec = biguint_to_bitvec(&m, EndianOrdering::Little);
+    let mut x = vec![0u8; d + 1];
+    let m_bytes = m.to_bytes_le();
 
     // First, get the classical comb values (except for x_d = 0)
     for i in 0..*d {
         for j in 0..*w {
-            if (i + d * j) < m_vec.len() {
-                x[i] = x[i].clone() | ((m_vec.get(i + d * j).unwrap() as u8) << j );
-            }
+            x[i] |= get_bit(&m_bytes, i + d * j) << j;
         }
     }
 
@@ -93,20 +93,33 @@ fn fixed_method(d: &usize, w: &usize, m: &BigUint) -> Vec<u8> {
     
     for i in 1..(*d + 1) {
         // Add carry and update it
-        let cc = &x[i].clone() & &c;
-        x[i] = &x[i].clone() ^ &c;
-        c = cc.clone();
+        let cc = x[i] & c;
+        x[i] ^= c;
+        c = cc;
 
         // Adjust if needed, avoiding branches
-        let adjust = 1 - (x[i].clone() & 1);
-        c = c.clone() | (x[i].clone() & ( x[i-1].clone() * adjust.clone() ));
-        x[i] = x[i].clone() ^ ( x[i-1].clone() * adjust.clone() );
-        x[i-1] = x[i-1].clone() | (adjust.clone() << 7);
+        let adjust = 1 - (x[i] & 1);
+        c |= x[i] & (x[i - 1] * adjust);
+        x[i] ^= x[i - 1] * adjust;
+        x[i - 1] |= adjust << 7;
     }
 
     x
 }
 
+/// Reads a single bit of a little-endian byte string
+/// 
+/// ### Arguments
+/// 
+/// * `bytes` - Little-endian bytes to read from
+/// * `index` - Index of the bit, starting from the least significant one
+fn get_bit(bytes: &[u8], index: usize) -> u8 {
+    match bytes.get(index / 8) {
+        Some(byte) => (byte >> (index % 8)) & 1,
+        None => 0
+    }
+}
+
 
 /// Multiplication (R = m * P) using the comb method,
 /// for curves in short Weierstrass form. R is returned
@@ -117,32 +130,97 @@ fn fixed_method(d: &usize, w: &usize, m: &BigUint) -> Vec<u8> {
 /// * `m` - M value
 /// * `P` - P point
 /// * `rng` - Random number generator
-pub fn multiply(group: &mut ECPGroup, m: &BigUint, P: &ECPPoint, mut rng: &mut OsRng) -> ECPPoint {
-
-    let mut p_equals_g = if P.x == group.g.x && P.y == group.g.y {
-        true } else {
-        false };
+pub fn multiply(group: &ECPGroup, m: &BigUint, P: &ECPPoint, rng: &mut impl CryptoRngCore) -> ECPPoint {
 
-    let m_is_even = m.is_even();
-    let w = get_window_size(&group.nbits, p_equals_g.clone());
-
-    if !FIXED_POINT_OPT {
-        p_equals_g = false;
-    }
+    let p_equals_g = FIXED_POINT_OPT && P.x == group.g.x && P.y == group.g.y;
+    let w = get_window_size(&group.nbits, p_equals_g);
 
     // Other sizes that depend on w
-    let pre_len = 1 << (w - 1);
     let d = (group.nbits + w - 1) / w;
 
-    // Prepare precomputed points: if P == G we want to
-    // use group.t if already initialized, or initialize it.
-    let mut T = Vec::new();
-    
-    if p_equals_g {
-        T = precompute(group, P, &w, &d);
+    // Prepare precomputed points: if P == G we want to use the
+    // group's cached table
+    let T = if p_equals_g {
+        generator_table(group)
     } else {
-        T = group.t.clone();
+        precompute(group, P, &w, &d)
+    };
+
+    multiply_with_table(group, m, &T, &w, &d, rng)
+}
+
+/// Comb table for the generator. The group's cached table is used if
+/// already initialized, otherwise it is built and cached. The cache is
+/// behind a lock, so the group is never borrowed mutably
+/// 
+/// ### Arguments
+/// 
+/// * `group` - Curve group to operate from
+pub fn generator_table(group: &ECPGroup) -> Vec<ECPPoint> {
+    match group.precomputed_table() {
+        Some(table) => table,
+        None => {
+            let w = get_window_size(&group.nbits, FIXED_POINT_OPT);
+            let d = (group.nbits + w - 1) / w;
+            let table = precompute(group, &group.g, &w, &d);
+            group.set_precomputed_table(table.clone());
+
+            table
+        }
     }
+}
+
+/// Comb table for a point other than G that will be multiplied many
+/// times, such as a verification key. The table uses the same larger
+/// window as the generator's, and is meant for `multiply_fixed`
+/// 
+/// ### Arguments
+/// 
+/// * `group` - Curve group to operate from
+/// * `P` - Point to build the table for
+pub fn precompute_fixed(group: &ECPGroup, P: &ECPPoint) -> Vec<ECPPoint> {
+    let w = get_window_size(&group.nbits, FIXED_POINT_OPT);
+    let d = (group.nbits + w - 1) / w;
+
+    precompute(group, P, &w, &d)
+}
+
+/// Multiplication (R = m * P) from a table built by `precompute_fixed`.
+/// R is returned
+/// 
+/// ### Arguments
+/// 
+/// * `group` - Curve group to operate from
+/// * `m` - M value
+/// * `T` - Precomputed points for P
+/// * `rng` - Random number generator
+pub fn multiply_fixed(group: &ECPGroup, m: &BigUint, T: &[ECPPoint], rng: &mut impl CryptoRngCore) -> ECPPoint {
+    // T holds 2^{w - 1} points
+    let w = T.len().trailing_zeros() as usize + 1;
+    let d = (group.nbits + w - 1) / w;
+
+    multiply_with_table(group, m, T, &w, &d, rng)
+}
+
+/// Comb multiplication once the table for P is at hand. R is returned
+/// 
+/// ### Arguments
+/// 
+/// * `group` - Curve group to operate from
+/// * `m` - M value
+/// * `T` - Precomputed points for P
+/// * `w` - Window size the table was built with
+/// * `d` - ceil( nbits / w )
+/// * `rng` - Random number generator
+fn multiply_with_table(
+    group: &ECPGroup,
+    m: &BigUint,
+    T: &[ECPPoint],
+    w: &usize,
+    d: &usize,
+    rng: &mut impl CryptoRngCore
+) -> ECPPoint {
+    let m_is_even = m.is_even();
 
     // Make sure M is odd (M = m or M = N - m, since N is odd)
     // using the fact that m * P = - (N - m) * P
@@ -153,19 +231,14 @@ pub fn multiply(group: &mut ECPGroup, m: &BigUint, P: &ECPPoint, mut rng: &mut O
     }
 
     // Go for comb multiplication, R = M * P
-    let k = fixed_method(&d, &w, &M);
-    let mut R = core_multiplication(group, P, &M, &mut rng);
+    let k = fixed_method(d, w, &M);
+    let mut R = core_multiplication(group, T, &k, d, rng);
 
     // Now get m * P from M * P and normalize it
     if m_is_even {
         R = jacobian_coords::invert_point(group, &R);
     }
 
-    if p_equals_g {
-        group.t = T;
-        group.t_size = pre_len;
-    }
-
     jacobian_coords::normalize_point(group, &R)
 }   
 
@@ -176,30 +249,30 @@ pub fn multiply(group: &mut ECPGroup, m: &BigUint, P: &ECPPoint, mut rng: &mut O
 /// 
 /// `group` - Curve group to operate from
 /// `T` - pre-computed points
-/// `pre_len` - Length for point selection
-/// `k` - Boolean selector over iterations
+/// `k` - Comb digits, as given by the fixed method
 /// `d` - Iteration counter
 /// `rng` - Random number generator
 pub fn core_multiplication(
     group: &ECPGroup, 
-    P: &ECPPoint,
-    m: &BigUint,
-    mut rng: &mut OsRng
+    T: &[ECPPoint],
+    k: &[u8],
+    d: &usize,
+    rng: &mut impl CryptoRngCore
 ) -> ECPPoint {
 
     // Start with a non-zero point and randomize its coordinates
-    let mut R = ECPPoint::new( &BigInt::zero(), Some(BigInt::zero()) );
+    let mut R = select(group, T, k[*d]);
     R.z = BigInt::one();
-    
-    R = jacobian_coords::randomize_point(group, &R.clone(), &mut rng);
-    let mut i = m.bits();
-    let mut p_clone = P.clone();
 
-    while i > 0 {
-        R = jacobian_coords::double_point(group, &R);
-        R = jacobian_coords::add(group, &R, &mut p_clone);
+    R = jacobian_coords::randomize_point(group, &R, rng);
+    let mut i = *d;
 
+    while i > 0 {
         i -= 1;
+
+        let mut T_x = select(group, T, k[i]);
+        R = jacobian_coords::double_point(group, &R);
+        R = jacobian_coords::add(group, &R, &mut T_x);
     }
 
     R
@@ -245,56 +318,49 @@ fn get_window_size(nbits: &usize, p_equals_g: bool) -> usize {
 /// If i = i_{w-1} ... i_1 is the binary representation of i, then
 /// T[i] = i_{w-1} 2^{(w-1)d} P + ... + i_1 2^d P + P
 ///
-/// T must be able to hold 2^{w - 1} elements
+/// T holds 2^{w - 1} elements
 ///
 /// Cost: d(w-1) D + (2^{w-1} - 1) A + 1 N(w-1) + 1 N(2^{w-1} - 1)
 fn precompute(group: &ECPGroup, P: &ECPPoint, w: &usize, d: &usize) -> Vec<ECPPoint> {
-    let mut T = vec![ECPPoint::new( &BigInt::zero(), Some(BigInt::zero()) ); MAX_PRECOMPUTED_POINTS + 1];
-    let mut i = 1;
+    #[cfg(any(test, feature = "metrics"))]
+    metrics::record(Operation::TablePrecompute);
+
+    let t_len = 1 << (w - 1);
+    let mut T = vec![P.clone(); t_len];
 
     // Set T[0] = P and
     // T[2^{l-1}] = 2^{dl} P for l = 1 .. w-1 (this is not the final value)
-    T[0] = P.clone();
-
-    let mut k = 1;
-    while i < (1 << (w - 1)) {
-        T[i] = T[i >> 1].clone();
-        let mut calc_value = T[i].clone();
-        
-        for j in 0..*d {
-            calc_value = jacobian_coords::double_point(group, &calc_value); 
-        }
+    let mut i = 1;
+
+    while i < t_len {
+        let mut calc_value = T[i >> 1].clone();
 
-        T[k] = calc_value.clone();
+        for _ in 0..*d {
+            calc_value = jacobian_coords::double_point(group, &calc_value);
+        }
 
-        k += 1;
+        T[i] = jacobian_coords::normalize_point(group, &calc_value);
         i <<= 1;
     }
 
-    jacobian_coords::normalize_many(group, &mut T);
-
     // Compute the remaining ones using the minimal number of additions
     // Be careful to update T[2^l] only after using it!
-    let mut j = 0;
     i = 1;
-    k = 1;
 
-    while i < (1 << (w - 1)) {
-        j = i;
+    while i < t_len {
+        let mut j = i;
 
         while j > 0 {
-            T[i + j] = jacobian_coords::add(group, &T[j].clone(), &mut T[i].clone());
-            T[k] = T[i + j].clone();
-
             j -= 1;
-            k += 1;
+
+            let mut T_i = T[i].clone();
+            let sum = jacobian_coords::add(group, &T[j], &mut T_i);
+            T[i + j] = jacobian_coords::normalize_point(group, &sum);
         }
 
         i <<= 1;
     }
 
-    jacobian_coords::normalize_many(group, &mut T);
-
     T
 }
 
@@ -305,24 +371,48 @@ fn precompute(group: &ECPGroup, P: &ECPPoint, w: &usize, d: &usize) -> Vec<ECPPo
 /// `group` - Curve group to operate from
 /// `T` - Vector precomputed points
 /// `i` - "i" value in calculation
-fn select(group: &ECPGroup, T: &Vec<ECPPoint>, i: usize) -> ECPPoint {
-    let mut R = ECPPoint::new( &BigInt::zero(), Some(BigInt::zero()) );
+fn select(group: &ECPGroup, T: &[ECPPoint], i: u8) -> ECPPoint {
+    let length = (group.nbits + 7) / 8;
+    let mut x = vec![0u8; length];
+    let mut y = vec![0u8; length];
 
     // ignore signing and scale down
-    let ii = ( i & 127 ) >> 1;
+    let ii = ( ( i & 127 ) >> 1 ) as u32;
 
     // Read the whole table to thwart cache-based timing attacks
     for j in 0..T.len() {
-        if j == ii {
-            R.x = T[j].x.clone();
-            R.y = Some(T[j].y.clone().unwrap());
-        }
-    }
+        let mask = ct::ct_eq_u32(j as u32, ii);
 
-    // Safely invert result if i is "negative"
-    if i >> 7 == 0 {
-        R = jacobian_coords::invert_point(group, &R);
+        ct::ct_copy_if(mask, &mut x, &coordinate_bytes(&T[j].x, length));
+        ct::ct_copy_if(mask, &mut y, &coordinate_bytes(T[j].y.as_ref().unwrap(), length));
     }
 
-    R
+    // Always compute the inverse, and keep it only if i is "negative"
+    let R = ECPPoint::new( &coordinate_from_bytes(&x), Some(coordinate_from_bytes(&y)) );
+    let inverted = jacobian_coords::invert_point(group, &R);
+
+    ct::ct_copy_if(ct::Choice::from_bit(i >> 7), &mut y, &coordinate_bytes(inverted.y.as_ref().unwrap(), length));
+
+    ECPPoint::new( &coordinate_from_bytes(&x), Some(coordinate_from_bytes(&y)) )
+}
+
+/// Encodes a normalized coordinate as exactly `length` big-endian bytes,
+/// so that every table entry is copied with the same amount of work
+/// 
+/// ### Arguments
+/// 
+/// `value` - Coordinate to encode, in [0, P]
+/// `length` - Byte length of P
+fn coordinate_bytes(value: &BigInt, length: usize) -> Vec<u8> {
+    let value = value.to_biguint().unwrap();
+    biguint_to_fixed_bytes(&value, length, EndianOrdering::Big).unwrap()
+}
+
+/// Decodes a coordinate written by `coordinate_bytes`
+/// 
+/// ### Arguments
+/// 
+/// `bytes` - Big-endian bytes to decode
+fn coordinate_from_bytes(bytes: &[u8]) -> BigInt {
+    BigUint::from_bytes_be(bytes).to_bigint().unwrap()
 }
\ No newline at end of file
@@ -0,0 +1,331 @@
+//! Constant-time building blocks for code handling secret values. None
+//! of the helpers branch on or index by their inputs, and comparisons
+//! hand back a `Choice` instead of a bool so that turning a secret
+//! result into control flow is always a visible decision. Lengths are
+//! treated as public throughout
+
+use std::ops::{ BitAnd, BitOr, Not };
+use std::sync::atomic::{ compiler_fence, Ordering };
+
+
+/*---- STRUCTS ----*/
+
+/// Secret bit produced by a constant-time comparison. Holds 0 or 1 and
+/// only becomes a bool through `into_bool`
+#[derive(Clone, Copy, Debug)]
+pub struct Choice(u8);
+
+
+/*---- IMPLEMENTATIONS ----*/
+
+impl Choice {
+
+    /// Wraps the lowest bit of a byte
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `bit` - Byte whose lowest bit is wrapped
+    pub fn from_bit(bit: u8) -> Self {
+        Choice(bit & 1)
+    }
+
+    /// The wrapped bit
+    pub fn unwrap_u8(self) -> u8 {
+        self.0
+    }
+
+    /// 0xff when set and 0x00 otherwise
+    pub fn mask_u8(self) -> u8 {
+        0u8.wrapping_sub(self.0)
+    }
+
+    /// All ones when set and zero otherwise
+    pub fn mask_u32(self) -> u32 {
+        0u32.wrapping_sub(self.0 as u32)
+    }
+
+    /// Reveals the choice. Only call this once branching on the result
+    /// can no longer leak anything, e.g. on a final accept or reject
+    pub fn into_bool(self) -> bool {
+        compiler_fence(Ordering::SeqCst);
+        self.0 == 1
+    }
+}
+
+impl BitAnd for Choice {
+    type Output = Choice;
+
+    fn bitand(self, other: Choice) -> Choice {
+        Choice(self.0 & other.0)
+    }
+}
+
+impl BitOr for Choice {
+    type Output = Choice;
+
+    fn bitor(self, other: Choice) -> Choice {
+        Choice(self.0 | other.0)
+    }
+}
+
+impl Not for Choice {
+    type Output = Choice;
+
+    fn not(self) -> Choice {
+        Choice(self.0 ^ 1)
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Compares two byte strings without stopping at the first difference.
+/// Strings of different lengths are unequal
+/// 
+/// ### Arguments
+/// 
+/// * `a` - First string
+/// * `b` - Second string
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let difference = a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y));
+    ct_eq_u8(difference, 0).into_bool()
+}
+
+/// Compares two bytes
+/// 
+/// ### Arguments
+/// 
+/// * `a` - First byte
+/// * `b` - Second byte
+pub fn ct_eq_u8(a: u8, b: u8) -> Choice {
+    ct_eq_u32(a as u32, b as u32)
+}
+
+/// Compares two words
+/// 
+/// ### Arguments
+/// 
+/// * `a` - First word
+/// * `b` - Second word
+pub fn ct_eq_u32(a: u32, b: u32) -> Choice {
+    let difference = (a ^ b) as u64;
+
+    // Zero stays zero, anything else wraps to the top half
+    Choice::from_bit((1 ^ (difference.wrapping_neg() >> 63)) as u8)
+}
+
+/// Picks `a` when the mask is set and `b` otherwise
+/// 
+/// ### Arguments
+/// 
+/// * `mask` - Which value to pick
+/// * `a` - Value picked when set
+/// * `b` - Value picked when not set
+pub fn ct_select_u8(mask: Choice, a: u8, b: u8) -> u8 {
+    b ^ (mask.mask_u8() & (a ^ b))
+}
+
+/// Picks `a` when the mask is set and `b` otherwise
+/// 
+/// ### Arguments
+/// 
+/// * `mask` - Which value to pick
+/// * `a` - Value picked when set
+/// * `b` - Value picked when not set
+pub fn ct_select_u32(mask: Choice, a: u32, b: u32) -> u32 {
+    b ^ (mask.mask_u32() & (a ^ b))
+}
+
+/// Whether `a` < `b`, reading both as big-endian integers of the same
+/// width. Panics if the widths differ
+/// 
+/// ### Arguments
+/// 
+/// * `a` - First integer
+/// * `b` - Second integer
+pub fn ct_lt(a: &[u8], b: &[u8]) -> Choice {
+    assert_eq!(a.len(), b.len(), "ct_lt needs operands of the same width");
+
+    // Subtract b from a and keep only the final borrow
+    let mut borrow = 0u16;
+
+    for (x, y) in a.iter().rev().zip(b.iter().rev()) {
+        let difference = (*x as u16).wrapping_sub(*y as u16).wrapping_sub(borrow);
+        borrow = (difference >> 8) & 1;
+    }
+
+    Choice::from_bit(borrow as u8)
+}
+
+/// Whether `a` >= `b`, reading both as big-endian integers of the same
+/// width. Panics if the widths differ
+/// 
+/// ### Arguments
+/// 
+/// * `a` - First integer
+/// * `b` - Second integer
+pub fn ct_ge(a: &[u8], b: &[u8]) -> Choice {
+    !ct_lt(a, b)
+}
+
+/// Overwrites `dst` with `src` when the mask is set, and leaves it
+/// untouched otherwise. Every byte is written either way. Panics if
+/// the lengths differ
+/// 
+/// ### Arguments
+/// 
+/// * `mask` - Whether to copy
+/// * `dst` - Destination buffer
+/// * `src` - Source buffer
+pub fn ct_copy_if(mask: Choice, dst: &mut [u8], src: &[u8]) -> () {
+    assert_eq!(dst.len(), src.len(), "ct_copy_if needs buffers of the same length");
+
+    let mask = mask.mask_u8();
+
+    for (d, s) in dst.iter_mut().zip(src.iter()) {
+        *d ^= mask & (*d ^ *s);
+    }
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod ct_test {
+
+    use std::time::{ Duration, Instant };
+    use crate::utils::ct::{ Choice, ct_eq, ct_eq_u8, ct_eq_u32, ct_select_u8, ct_select_u32, ct_lt, ct_ge, ct_copy_if };
+
+    const SET: Choice = Choice(1);
+    const UNSET: Choice = Choice(0);
+
+    #[test]
+    fn choice_operators() {
+        assert!((SET & SET).into_bool());
+        assert!(!(SET & UNSET).into_bool());
+        assert!((SET | UNSET).into_bool());
+        assert!(!(UNSET | UNSET).into_bool());
+        assert!((!UNSET).into_bool());
+        assert!(!(!SET).into_bool());
+
+        assert_eq!(Choice::from_bit(3).unwrap_u8(), 1);
+        assert_eq!(Choice::from_bit(2).unwrap_u8(), 0);
+        assert_eq!((SET.mask_u8(), UNSET.mask_u8()), (0xff, 0));
+        assert_eq!((SET.mask_u32(), UNSET.mask_u32()), (0xffff_ffff, 0));
+    }
+
+    #[test]
+    fn byte_strings_compare() {
+        assert!(ct_eq(b"same bytes", b"same bytes"));
+        assert!(ct_eq(b"", b""));
+        assert!(!ct_eq(b"xame bytes", b"same bytes"));
+        assert!(!ct_eq(b"same bytez", b"same bytes"));
+        assert!(!ct_eq(b"same", b"same bytes"));
+        assert!(!ct_eq(b"", b"s"));
+    }
+
+    #[test]
+    #[ignore] // timing: run with --ignored, in release mode on a quiet machine
+    fn comparison_time_does_not_depend_on_mismatch_position() {
+        let reference = vec![0x5a; 1 << 16];
+        let mut early = reference.clone();
+        let mut late = reference.clone();
+        early[0] ^= 1;
+        late[(1 << 16) - 1] ^= 1;
+
+        let time = |other: &[u8]| {
+            let start = Instant::now();
+            let matches = (0..64).filter(|_| ct_eq(&reference, other)).count();
+
+            assert_eq!(matches, 0);
+            start.elapsed()
+        };
+
+        // Interleave the two so that drift in clock speed hits both, and
+        // compare medians so that a stray context switch doesn't count
+        let mut early_times: Vec<Duration> = Vec::new();
+        let mut late_times: Vec<Duration> = Vec::new();
+
+        for _ in 0..101 {
+            early_times.push(time(&early));
+            late_times.push(time(&late));
+        }
+
+        early_times.sort();
+        late_times.sort();
+
+        let ratio = early_times[50].as_nanos() as f64 / late_times[50].as_nanos() as f64;
+        assert!(ratio > 0.8 && ratio < 1.25, "early mismatch takes {:.2} times as long as late", ratio);
+    }
+
+    #[test]
+    fn words_compare() {
+        assert!(ct_eq_u8(0, 0).into_bool());
+        assert!(ct_eq_u8(255, 255).into_bool());
+        assert!(!ct_eq_u8(0, 255).into_bool());
+        assert!(!ct_eq_u8(0x80, 0x00).into_bool());
+        assert!(!ct_eq_u8(0x01, 0x00).into_bool());
+
+        assert!(ct_eq_u32(0xffff_ffff, 0xffff_ffff).into_bool());
+        assert!(!ct_eq_u32(0x8000_0000, 0).into_bool());
+        assert!(!ct_eq_u32(1, 0).into_bool());
+    }
+
+    #[test]
+    fn selection() {
+        assert_eq!(ct_select_u8(SET, 0xaa, 0x55), 0xaa);
+        assert_eq!(ct_select_u8(UNSET, 0xaa, 0x55), 0x55);
+        assert_eq!(ct_select_u8(SET, 7, 7), 7);
+
+        assert_eq!(ct_select_u32(SET, 0xdead_beef, 1), 0xdead_beef);
+        assert_eq!(ct_select_u32(UNSET, 0xdead_beef, 1), 1);
+    }
+
+    #[test]
+    fn ordering() {
+        let cases: [(&[u8], &[u8], bool); 7] = [
+            (&[1, 2, 3], &[1, 2, 3], false),        // equal
+            (&[0, 2, 3], &[1, 2, 3], true),         // first byte smaller
+            (&[2, 0, 0], &[1, 255, 255], false),    // first byte larger
+            (&[1, 2, 2], &[1, 2, 3], true),         // last byte smaller
+            (&[1, 2, 4], &[1, 2, 3], false),        // last byte larger
+            (&[0, 0], &[255, 255], true),
+            (&[], &[], false)                       // zero width
+        ];
+
+        for &(a, b, less) in cases.iter() {
+            assert_eq!(ct_lt(a, b).into_bool(), less);
+            assert_eq!(ct_ge(a, b).into_bool(), !less);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn ordering_rejects_mixed_widths() {
+        ct_lt(&[1], &[0, 1]);
+    }
+
+    #[test]
+    fn conditional_copy() {
+        let mut dst = [1, 2, 3];
+
+        ct_copy_if(UNSET, &mut dst, &[9, 9, 9]);
+        assert_eq!(dst, [1, 2, 3]);
+
+        ct_copy_if(SET, &mut dst, &[9, 8, 7]);
+        assert_eq!(dst, [9, 8, 7]);
+
+        ct_copy_if(SET, &mut dst, &[0, 8, 7]);
+        assert_eq!(dst, [0, 8, 7]);
+
+        ct_copy_if(SET, &mut dst, &[0, 8, 6]);
+        assert_eq!(dst, [0, 8, 6]);
+
+        let mut empty: [u8; 0] = [];
+        ct_copy_if(SET, &mut empty, &[]);
+    }
+}
\ No newline at end of file
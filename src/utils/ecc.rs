@@ -1,12 +1,86 @@
-use rand::OsRng;
+use std::fmt;
 use std::ops::Shr;
+use rand::{ Rng, ChaChaRng, SeedableRng };
 
-use num_bigint::{ BigUint, ToBigInt, BigInt };
+use num_bigint::{ BigUint, ToBigInt, ToBigUint, BigInt };
 use num_traits::{ One, Zero };
 
-use utils::{ primes, comb_method, montgomery_ladder, jacobian_coords };
-use utils::encoding::{ EndianOrdering, biguint_to_bitvec, bitvec_to_biguint };
-use utils::ecc_curves::{ ECPPoint, ECPGroup, ECPSupportedCurves, ECPCurveShape };
+use crate::hash::kdf::KdfError;
+use crate::utils::rng::CryptoRngCore;
+use crate::utils::entropy::{ self, EntropyError };
+use crate::utils::budget::Budget;
+use crate::utils::zeroize::zeroize_biguint;
+use crate::utils::{ primes, comb_method, montgomery_ladder, jacobian_coords };
+use crate::utils::encoding::{ EndianOrdering, biguint_to_fixed_bytes };
+use crate::utils::ecc_curves::{ ECPPoint, ECPGroup, ECPSupportedCurves, ECPCurveShape };
+#[cfg(feature = "keys")]
+use crate::keys::{ self, ExportableKey, KeyAlgorithm, KeyError };
+
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize, Deserializer };
+#[cfg(feature = "serde")]
+use serde::de::Error as DeserializeError;
+#[cfg(feature = "serde")]
+use crate::utils::serde_hex;
+
+
+/*---- ENUMS ----*/
+
+/// Errors raised by elliptic curve operations and the protocols
+/// built on top of them
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum EcError {
+    MissingPeerPoint,                   // no peer public value has been set
+    InvalidPublicKey(&'static str),     // peer point failed validation
+    InvalidPrivateKey(&'static str),    // private scalar failed validation
+    PointAtInfinity,                    // result of an operation was the zero point
+    LowOrderPoint,                      // peer point lies in a small subgroup
+    CurveMismatch,                      // point was validated against another curve
+    InvalidEncoding,                    // byte encoding could not be produced or parsed
+    KeyCleared,                         // private value has been wiped
+    KeyDerivation(KdfError),            // KDF applied to the shared secret failed
+    Entropy(EntropyError),              // the generator failed its health tests
+    Timeout,                            // key generation ran out of budget
+    RngFailure                          // the random number generator failed
+}
+
+impl fmt::Display for EcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EcError::MissingPeerPoint => write!(f, "No peer point available to generate shared secret for"),
+            EcError::InvalidPublicKey(reason) => write!(f, "Invalid public key: {}", reason),
+            EcError::InvalidPrivateKey(reason) => write!(f, "Invalid private key: {}", reason),
+            EcError::PointAtInfinity => write!(f, "Resulting point is the point at infinity"),
+            EcError::LowOrderPoint => write!(f, "Peer point has low order"),
+            EcError::CurveMismatch => write!(f, "Point was validated against a different curve"),
+            EcError::InvalidEncoding => write!(f, "Invalid point or scalar encoding"),
+            EcError::KeyCleared => write!(f, "Private value has been cleared"),
+            EcError::KeyDerivation(e) => write!(f, "Key derivation failed: {}", e),
+            EcError::Entropy(e) => write!(f, "RNG unfit for key generation: {}", e),
+            EcError::Timeout => write!(f, "Key generation ran out of budget"),
+            EcError::RngFailure => write!(f, "Random number generation failed")
+        }
+    }
+}
+
+impl From<KdfError> for EcError {
+    fn from(error: KdfError) -> Self {
+        EcError::KeyDerivation(error)
+    }
+}
+
+impl From<EntropyError> for EcError {
+    fn from(error: EntropyError) -> Self {
+        EcError::Entropy(error)
+    }
+}
+
+/// Checks `ECPGroup::validate` runs on a point
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ValidationLevel {
+    Membership,     // point is affine and lies on the curve
+    FullSubgroup    // point also lies in the prime-order subgroup generated by G
+}
 
 
 /*---- STRUCTS ----*/
@@ -24,6 +98,26 @@ pub struct ECPKeypair {
     pub q: ECPPoint         // public value
 }
 
+/// Public half of an ECC keypair, held as its curve and encoded point.
+/// The point is checked against the curve on construction
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ECPPublicKey {
+    curve: ECPSupportedCurves,  // curve the point lies on
+    #[cfg_attr(feature = "serde", serde(with = "serde_hex::bytes"))]
+    point: Vec<u8>              // point as produced by `encode_point`
+}
+
+/// Point that has passed `ECPGroup::validate`, along with the group it
+/// was checked against. Only `validate` can make one, so the peer key
+/// entry points that take it never see an unchecked point
+#[derive(Clone)]
+pub struct ValidatedPoint<'g> {
+    group: &'g ECPGroup,        // group the point was checked against
+    point: ECPPoint,            // affine point
+    level: ValidationLevel      // checks the point passed
+}
+
 
 /*---- CONSTANTS ----*/
 
@@ -32,6 +126,9 @@ const MAX_BIT_SIZE: usize = 521;
 const MAX_BYTE_SIZE: usize = ( ( 521 + 7 ) / 8 );
 const MAX_POINT_LEN: usize = ( 2 * 521 + 1 );
 
+/// Order L of the prime subgroup of Curve25519, as big-endian hex
+const CURVE25519_ORDER: &'static [u8] = b"1000000000000000000000000000000014def9dea2f79cd65812631a5cf5d3ed";
+
 
 /*---- IMPLEMENTATIONS ----*/
 
@@ -61,14 +158,27 @@ impl ECPKeypair {
     /// ### Arguments
     /// 
     /// * `rng` - Random number generator
-    pub fn setup(mut self, mut rng: &mut OsRng) -> ECPKeypair {
-        self.d = self.get_valid_private_value();
-        self.q = self.multiply(&mut rng);
+    pub fn setup(self, rng: &mut impl CryptoRngCore) -> Result<ECPKeypair, EcError> {
+        self.setup_with_budget(&mut Budget::unlimited(), rng)
+    }
+
+    /// Sets up new ECP keypair values, giving up with `EcError::Timeout`
+    /// once the budget runs out. Each candidate private value counts as
+    /// one iteration
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `budget` - Iterations and time generation may take
+    /// * `rng` - Random number generator
+    pub fn setup_with_budget(mut self, budget: &mut Budget, rng: &mut impl CryptoRngCore) -> Result<ECPKeypair, EcError> {
+        entropy::check_cached(rng)?;
+        self.d = self.private_value_with_budget(budget, rng)?;
+        self.q = self.multiply(rng);
         
         // Perform sanity checks
-        self.check_public_private_keys(&self.q);
+        self.check_public_private_keys(&self.q)?;
 
-        self
+        Ok(self)
     }
 
     /// Multiplication R = m * P. In this case "P" is the generator
@@ -77,13 +187,13 @@ impl ECPKeypair {
     /// ### Arguments
     /// 
     /// * `rng` - Random number generator
-    pub fn multiply(&mut self, rng: &mut OsRng) -> ECPPoint {
+    pub fn multiply(&self, rng: &mut impl CryptoRngCore) -> ECPPoint {
         let curve_shape = self.group.get_curve_shape();
         let p_point = self.group.g.clone();
 
         match curve_shape {
-            ECPCurveShape::Montgomery => montgomery_ladder::multiply(&self.group, &self.d, &p_point),
-            ECPCurveShape::ShortWeierstrass => comb_method::multiply(&mut self.group, &self.d, &p_point, rng)
+            ECPCurveShape::Montgomery => montgomery_ladder::multiply(&self.group, &self.d, &p_point, rng),
+            ECPCurveShape::ShortWeierstrass => comb_method::multiply(&self.group, &self.d, &p_point, rng)
         }
     }
 
@@ -93,13 +203,13 @@ impl ECPKeypair {
     ///  
     /// * `p` - Point to multiply
     /// * `m` - Scalar to multiply with
-    pub fn multiply_point(&mut self, p: &ECPPoint, m: &BigUint) -> ECPPoint {
+    /// * `rng` - Random number generator
+    pub fn multiply_point(&self, p: &ECPPoint, m: &BigUint, rng: &mut impl CryptoRngCore) -> ECPPoint {
         let curve_shape = self.group.get_curve_shape();
-        let mut rng = OsRng::new().unwrap();
 
         match curve_shape {
-            ECPCurveShape::Montgomery => montgomery_ladder::multiply(&self.group, m, p),
-            ECPCurveShape::ShortWeierstrass => comb_method::core_multiplication(&mut self.group, p, m, &mut rng)
+            ECPCurveShape::Montgomery => montgomery_ladder::multiply(&self.group, m, p, rng),
+            ECPCurveShape::ShortWeierstrass => comb_method::multiply(&self.group, m, p, rng)
         }
     }
 
@@ -109,7 +219,7 @@ impl ECPKeypair {
     /// 
     /// * `p` - First point
     /// * `r` - Second point
-    pub fn add_points(&mut self, p: &ECPPoint, r: &ECPPoint) -> ECPPoint {
+    pub fn add_points(&self, p: &ECPPoint, r: &ECPPoint) -> ECPPoint {
         let curve_shape = self.group.get_curve_shape();
         let gx = self.group.clone().g.x;
         let mut r_clone = r.clone();
@@ -125,17 +235,19 @@ impl ECPKeypair {
     /// ### Arguments
     /// 
     /// * `public` - Public point to check
-    fn check_public_private_keys(&self, point: &ECPPoint) -> () {
+    fn check_public_private_keys(&self, point: &ECPPoint) -> Result<(), EcError> {
         let private_key_check = self.check_private_key();
         let public_key_check = self.check_public_key(point);
 
         if !private_key_check.0 {
-            panic!(private_key_check.1);
+            return Err(EcError::InvalidPrivateKey(private_key_check.1));
         }
 
         if !public_key_check.0 {
-            panic!(public_key_check.1);
+            return Err(EcError::InvalidPublicKey(public_key_check.1));
         }
+
+        Ok(())
     }
 
     /// Check that the D value is valid as a private key
@@ -143,13 +255,9 @@ impl ECPKeypair {
         let curve_shape = self.group.get_curve_shape();
 
         if curve_shape == ECPCurveShape::Montgomery {
-            let d_bit_vec = biguint_to_bitvec(&self.d, EndianOrdering::Little);
+            let low_bits = &self.d % 8.to_biguint().unwrap();
 
-            if d_bit_vec.get(0).unwrap() ||
-               d_bit_vec.get(1).unwrap() ||
-               d_bit_vec.get(2).unwrap() ||
-               self.d.bits() != self.group.nbits 
-            {
+            if low_bits != BigUint::zero() || self.d.bits() != self.group.nbits + 1 {
                 return (false, "Least significant bits need to be 0 for private value D");
             }
 
@@ -172,59 +280,45 @@ impl ECPKeypair {
     /// 
     /// * `point` - Point to check
     pub fn check_public_key(&self, point: &ECPPoint) -> (bool, &'static str) {
-        // Must use affine coordinates
-        if point.z != BigInt::one() {
-            return (false, "Coordinates for public key point are not affine (Z coordinate != 1)");
-        }
-
-        let curve_shape = self.group.get_curve_shape();
-
-        match curve_shape {
-            ECPCurveShape::Montgomery => {
-                if (point.x.bits() + 7) / 8 > (self.group.nbits + 7) / 8 {
-                    return (false, "X coord for public point is too large");
-                }
-
-                return (true, "");
-            },
-
-            ECPCurveShape::ShortWeierstrass => self.check_weierstrass_public_key(point)
-        }
+        check_point(&self.group, point)
     }
 
     /// Generates a valid private value for use
     /// in an ECC keypair
-    pub fn get_valid_private_value(&self) -> BigUint {
-        let mut rng = OsRng::new().unwrap();
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `rng` - Random number generator
+    pub fn get_valid_private_value(&self, rng: &mut impl CryptoRngCore) -> Result<BigUint, EcError> {
+        self.private_value_with_budget(&mut Budget::unlimited(), rng)
+    }
+
+    /// Generates a private value as `get_valid_private_value` does,
+    /// counting each candidate against the budget
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `budget` - Iterations and time generation may take
+    /// * `rng` - Random number generator
+    fn private_value_with_budget(&self, budget: &mut Budget, rng: &mut impl CryptoRngCore) -> Result<BigUint, EcError> {
         let n_size = (self.group.nbits + &7) / 8;
         let curve_shape = self.group.get_curve_shape();
 
         match curve_shape {
             ECPCurveShape::Montgomery => {
-                let mut d = BigUint::zero();
-
-                while d.bits() != self.group.nbits {
-                    d = primes::generate(&self.group.nbits);
+                if budget.exhausted() {
+                    return Err(EcError::Timeout);
                 }
 
-                let mut d_bit_vec = biguint_to_bitvec(&d, EndianOrdering::Little);
-                let b = d.bits() - 1;
-
-                // Make sure the most significant bit is nbits
-                if b > self.group.nbits {
-                    d = d.shr(b.clone() - self.group.nbits.clone());
-                    d_bit_vec = biguint_to_bitvec(&d, EndianOrdering::Little); 
-
-                } else {
-                    d_bit_vec.set(self.group.nbits, true);
-                }
+                let mut bytes = vec![0; n_size];
+                rng.fill_bytes(&mut bytes);
 
-                // Make sure the last three bits are unset
-                d_bit_vec.set(0, false);
-                d_bit_vec.set(1, false);
-                d_bit_vec.set(2, false);
+                // Clamp as in RFC 7748: keep the low nbits bits, make bit
+                // nbits the most significant one and unset the last three bits
+                let top_bit = BigUint::one() << self.group.nbits;
+                let d = (BigUint::from_bytes_le(&bytes) % &top_bit) | &top_bit;
 
-                return bitvec_to_biguint(&d_bit_vec, EndianOrdering::Little);
+                return Ok(d.shr(3) << 3);
             },
 
             ECPCurveShape::ShortWeierstrass => {
@@ -237,7 +331,15 @@ impl ECPKeypair {
                 // - try until result is in the desired range.
                 // This also avoids any bias, which is especially important for ECDSA.
                 while d < BigUint::one() || d >= self.group.n {
-                    d = primes::generate(&self.group.nbits);
+                    if budget.exhausted() {
+                        zeroize_biguint(&mut d);
+                        return Err(EcError::Timeout);
+                    }
+
+                    let mut bytes = vec![0; n_size];
+                    rng.fill_bytes(&mut bytes);
+
+                    d = BigUint::from_bytes_be(&bytes);
                     d = d.shr(8 * n_size - self.group.nbits);
 
                     // Each try has at worst a probability 1/2 of failing (the msb has
@@ -250,53 +352,517 @@ impl ECPKeypair {
                     count += 1;
 
                     if count > 30 {
-                        panic!("Short Weierstrass private value generation failed");
+                        return Err(EcError::RngFailure);
                     }
                 }
 
-                return d;
+                return Ok(d);
             }
         }
     }
 
-    /// Check that an affine point is valid as a public key,
-    /// Short weierstrass curves (SEC1 3.2.3.1)
+    /// Exports the public value along with its curve
+    pub fn public_key(&self) -> Result<ECPPublicKey, EcError> {
+        ECPPublicKey::new(self.group.curve_id(), &encode_point(&self.group, &self.q, false)?)
+    }
+
+    /// Wipes the private value, leaving the group and public value intact
+    pub fn clear(&mut self) -> () {
+        zeroize_biguint(&mut self.d);
+    }
+    
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for ECPKeypair {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// The payload is the uncompressed public point, preceded for private
+/// keys by D. The curve is carried by the algorithm id
+#[cfg(feature = "keys")]
+impl ExportableKey for ECPKeypair {
+    fn key_algorithm(&self) -> KeyAlgorithm {
+        KeyAlgorithm::Ecc(self.group.curve_id())
+    }
+
+    fn has_private_key(&self) -> bool {
+        !self.d.is_zero()
+    }
+
+    fn export_payload(&self) -> Result<Vec<u8>, KeyError> {
+        let point = match encode_point(&self.group, &self.q, false) {
+            Ok(point) => point,
+            Err(_) => return Err(KeyError::InvalidKey("Public point could not be encoded"))
+        };
+
+        let mut payload = Vec::new();
+
+        if self.has_private_key() {
+            keys::write_biguint(&mut payload, &self.d);
+        }
+
+        keys::write_field(&mut payload, &point);
+
+        Ok(payload)
+    }
+
+    fn export_public_payload(&self) -> Result<Vec<u8>, KeyError> {
+        let point = match encode_point(&self.group, &self.q, false) {
+            Ok(point) => point,
+            Err(_) => return Err(KeyError::InvalidKey("Public point could not be encoded"))
+        };
+
+        let mut payload = Vec::new();
+        keys::write_field(&mut payload, &point);
+
+        Ok(payload)
+    }
+
+    fn import_payload(algorithm: &KeyAlgorithm, private: bool, mut payload: &[u8]) -> Result<Self, KeyError> {
+        let mut keypair = match algorithm {
+            KeyAlgorithm::Ecc(curve) => ECPKeypair::new(curve.clone()),
+            _ => return Err(KeyError::Malformed("Algorithm is not an elliptic curve"))
+        };
+
+        if private {
+            keypair.d = keys::read_biguint(&mut payload)?;
+        }
+
+        let point = match decode_point(&keypair.group, keys::read_field(&mut payload)?) {
+            Ok(point) => point,
+            Err(_) => return Err(KeyError::InvalidKey("Public point could not be decoded"))
+        };
+
+        keys::finish_payload(payload)?;
+
+        if private {
+            match keypair.check_public_private_keys(&point) {
+                Ok(()) => (),
+                Err(EcError::InvalidPublicKey(reason)) |
+                Err(EcError::InvalidPrivateKey(reason)) => return Err(KeyError::InvalidKey(reason)),
+                Err(_) => return Err(KeyError::InvalidKey("Keypair failed validation"))
+            }
+        } else {
+            let public_check = keypair.check_public_key(&point);
+
+            if !public_check.0 {
+                return Err(KeyError::InvalidKey(public_check.1));
+            }
+        }
+
+        keypair.q = point;
+        Ok(keypair)
+    }
+}
+
+impl ECPPublicKey {
+
+    /// Creates a public key from an encoded point, rejecting points
+    /// that cannot be decoded or that fail the public key checks
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `curve` - Curve the point lies on
+    /// * `bytes` - Point as produced by `encode_point`
+    pub fn new(curve: ECPSupportedCurves, bytes: &[u8]) -> Result<Self, EcError> {
+        ECPPublicKey::decode(&curve, bytes)?;
+
+        Ok(ECPPublicKey { curve: curve, point: bytes.to_vec() })
+    }
+
+    /// Curve the point lies on
+    pub fn curve(&self) -> &ECPSupportedCurves {
+        &self.curve
+    }
+
+    /// Encoded point
+    pub fn point_bytes(&self) -> &[u8] {
+        &self.point
+    }
+
+    /// Decoded point
+    pub fn point(&self) -> Result<ECPPoint, EcError> {
+        ECPPublicKey::decode(&self.curve, &self.point)
+    }
+
+    /// Decodes and validates a point for the given curve
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `curve` - Curve the point lies on
+    /// * `bytes` - Encoded point
+    fn decode(curve: &ECPSupportedCurves, bytes: &[u8]) -> Result<ECPPoint, EcError> {
+        let group = ECPGroup::new(curve.clone());
+        let point = decode_point(&group, bytes)?;
+
+        Ok(group.validate(point, ValidationLevel::Membership)?.point().clone())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for ECPPublicKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Fields {
+            curve: ECPSupportedCurves,
+            #[serde(with = "serde_hex::bytes")]
+            point: Vec<u8>
+        }
+
+        let fields = Fields::deserialize(deserializer)?;
+        ECPPublicKey::new(fields.curve, &fields.point).map_err(D::Error::custom)
+    }
+}
+
+impl ECPGroup {
+
+    /// Checks a point before it is used as a peer public key. Membership
+    /// runs the checks of SEC1 3.2.2.1: the point must be affine, and a
+    /// short Weierstrass point must have reduced coordinates that satisfy
+    /// the curve equation. Montgomery points only have the size of their
+    /// u-coordinate checked, as X25519 accepts any u (RFC 7748 section 5).
+    /// 
+    /// FullSubgroup also requires the point to lie in the subgroup of prime
+    /// order. Every supported short Weierstrass curve has cofactor 1, so
+    /// this follows from membership. On Curve25519 the u-coordinate has to
+    /// be reduced, belong to the curve rather than its twist, and have
+    /// order L
     /// 
     /// ### Arguments
     /// 
     /// * `point` - Point to check
-    fn check_weierstrass_public_key(&self, point: &ECPPoint) -> (bool, &'static str) {
-        if point.x.clone() < BigInt::zero()             || 
-           point.y.clone().unwrap() < BigInt::zero()    ||
-           point.x >= self.group.p.to_bigint().unwrap() ||
-           point.y.clone().unwrap() >= self.group.p.to_bigint().unwrap()
-        {
-            return (false, "X and Y coords need to be normalized");
+    /// * `level` - Checks to run
+    pub fn validate<'g>(&'g self, point: ECPPoint, level: ValidationLevel) -> Result<ValidatedPoint<'g>, EcError> {
+        let (valid, reason) = check_point(self, &point);
+
+        if !valid {
+            return Err(EcError::InvalidPublicKey(reason));
         }
 
-        // YY = Y^2
-        // RHS = X (X^2 + A) + B = X^3 + A X + B
-        let y_squared = self.group.mod_p( &(point.y.clone().unwrap() * point.y.clone().unwrap()) );
-        let mut rhs = self.group.mod_p( &(point.x.clone() * point.x.clone()) );
+        if level == ValidationLevel::FullSubgroup && self.get_curve_shape() == ECPCurveShape::Montgomery {
+            check_montgomery_subgroup(self, &point)?;
+        }
 
-        // Special case for A = -3
-        // NOTE handle A as a signed int
-        if self.group.a.to_bigint().unwrap() == -3.to_bigint().unwrap() {
-            rhs = self.group.mod_increase( &(rhs - 3.to_bigint().unwrap()) );
-        } else {
-            rhs = self.group.mod_reduce( &(rhs.clone() + self.group.a.to_bigint().unwrap()) );
+        Ok(ValidatedPoint { group: self, point: point, level: level })
+    }
+}
+
+impl<'g> ValidatedPoint<'g> {
+
+    /// Group the point was checked against
+    pub fn group(&self) -> &'g ECPGroup {
+        self.group
+    }
+
+    /// Identifier of the curve the point was checked against
+    pub fn curve_id(&self) -> ECPSupportedCurves {
+        self.group.curve_id()
+    }
+
+    /// The checked point
+    pub fn point(&self) -> &ECPPoint {
+        &self.point
+    }
+
+    /// Checks the point passed
+    pub fn level(&self) -> ValidationLevel {
+        self.level
+    }
+
+    /// Fails with `EcError::CurveMismatch` unless the point was checked
+    /// against the given curve
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `curve` - Curve of the operation the point is passed to
+    pub fn check_curve(&self, curve: &ECPSupportedCurves) -> Result<(), EcError> {
+        if &self.curve_id() != curve {
+            return Err(EcError::CurveMismatch);
         }
 
-        rhs = self.group.mod_p( &(rhs.clone() * point.x.clone()) );
-        rhs = self.group.mod_reduce( &(rhs.clone() + self.group.b.to_bigint().unwrap()) );
+        Ok(())
+    }
+}
+
+impl<'g> fmt::Debug for ValidatedPoint<'g> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ValidatedPoint")
+            .field("curve", &self.curve_id())
+            .field("point", &self.point)
+            .field("level", &self.level)
+            .finish()
+    }
+}
+
+
+
+/*---- FUNCTIONS ----*/
+
+/// Length in bytes of an uncompressed public point for the given group.
+/// Short Weierstrass points use the SEC1 form (0x04 || X || Y), while
+/// Montgomery points are encoded as their u-coordinate only
+/// 
+/// ### Arguments
+/// 
+/// * `group` - Curve group the point belongs to
+pub fn encoded_point_len(group: &ECPGroup) -> usize {
+    let field_length = (group.p.bits() + 7) / 8;
+
+    match group.get_curve_shape() {
+        ECPCurveShape::Montgomery => field_length,
+        ECPCurveShape::ShortWeierstrass => 1 + 2 * field_length
+    }
+}
+
+/// Encodes an affine public point as bytes. Short Weierstrass points are
+/// written in SEC1 form (section 2.3.3) with big-endian coordinates, either
+/// uncompressed (0x04 || X || Y) or compressed (0x02 or 0x03 depending on
+/// the parity of Y, followed by X). Montgomery points are always encoded as
+/// the little-endian u-coordinate (RFC 7748)
+/// 
+/// ### Arguments
+/// 
+/// * `group` - Curve group the point belongs to
+/// * `point` - Affine point to encode
+/// * `compressed` - Whether to use the compressed SEC1 form
+pub fn encode_point(group: &ECPGroup, point: &ECPPoint, compressed: bool) -> Result<Vec<u8>, EcError> {
+    let field_length = (group.p.bits() + 7) / 8;
+
+    let x = match point.x.to_biguint() {
+        Some(x) => x,
+        None => return Err(EcError::InvalidEncoding)
+    };
+
+    if group.get_curve_shape() == ECPCurveShape::Montgomery {
+        return match biguint_to_fixed_bytes(&x, field_length, EndianOrdering::Little) {
+            Some(bytes) => Ok(bytes),
+            None => Err(EcError::InvalidEncoding)
+        };
+    }
+
+    let y = match point.y {
+        Some(ref y) => match y.to_biguint() {
+            Some(y) => y,
+            None => return Err(EcError::InvalidEncoding)
+        },
+        None => return Err(EcError::InvalidEncoding)
+    };
+
+    let x_bytes = match biguint_to_fixed_bytes(&x, field_length, EndianOrdering::Big) {
+        Some(bytes) => bytes,
+        None => return Err(EcError::InvalidEncoding)
+    };
+
+    if compressed {
+        let mut encoded = Vec::with_capacity(1 + field_length);
+        encoded.push(if is_odd(&y) { 0x03 } else { 0x02 });
+        encoded.extend(x_bytes);
+
+        return Ok(encoded);
+    }
+
+    let y_bytes = match biguint_to_fixed_bytes(&y, field_length, EndianOrdering::Big) {
+        Some(bytes) => bytes,
+        None => return Err(EcError::InvalidEncoding)
+    };
+
+    let mut encoded = Vec::with_capacity(1 + 2 * field_length);
+    encoded.push(0x04);
+    encoded.extend(x_bytes);
+    encoded.extend(y_bytes);
 
-        if rhs != y_squared {
-            return (false, "Y^2 != X (X^2 + A) + B = X^3 + A X + B");
+    Ok(encoded)
+}
+
+/// Decodes a public point produced by `encode_point`, in either the
+/// compressed or uncompressed form. Only the format is checked here;
+/// the caller is expected to validate the resulting point with
+/// `ECPGroup::validate`
+/// 
+/// ### Arguments
+/// 
+/// * `group` - Curve group the point belongs to
+/// * `bytes` - Encoded point
+pub fn decode_point(group: &ECPGroup, bytes: &[u8]) -> Result<ECPPoint, EcError> {
+    let field_length = (group.p.bits() + 7) / 8;
+
+    if group.get_curve_shape() == ECPCurveShape::Montgomery {
+        if bytes.len() != field_length {
+            return Err(EcError::InvalidEncoding);
         }
 
-        (true, "")
+        let u = BigUint::from_bytes_le(bytes);
+        return Ok(ECPPoint::new( &u.to_bigint().unwrap(), None ));
     }
-    
+
+    if bytes.len() == 1 + 2 * field_length && bytes[0] == 0x04 {
+        let x = BigUint::from_bytes_be(&bytes[1..1 + field_length]);
+        let y = BigUint::from_bytes_be(&bytes[1 + field_length..]);
+
+        return Ok(ECPPoint::new( &x.to_bigint().unwrap(), Some(y.to_bigint().unwrap()) ));
+    }
+
+    if bytes.len() == 1 + field_length && (bytes[0] == 0x02 || bytes[0] == 0x03) {
+        let x = BigUint::from_bytes_be(&bytes[1..]);
+        let y = decompress_y(group, &x, bytes[0] == 0x03)?;
+
+        return Ok(ECPPoint::new( &x.to_bigint().unwrap(), Some(y.to_bigint().unwrap()) ));
+    }
+
+    Err(EcError::InvalidEncoding)
+}
+
+/// Recovers the Y coordinate of a compressed short Weierstrass point.
+/// All supported curves have P = 3 mod 4, so the square root of
+/// X^3 + A X + B is found as (X^3 + A X + B)^((P + 1) / 4) mod P
+/// 
+/// ### Arguments
+/// 
+/// * `group` - Curve group the point belongs to
+/// * `x` - X coordinate
+/// * `odd` - Whether the Y coordinate is odd
+fn decompress_y(group: &ECPGroup, x: &BigUint, odd: bool) -> Result<BigUint, EcError> {
+    let p = group.p.to_biguint().unwrap();
+    let a = group.a.to_biguint().unwrap();
+    let b = group.b.to_biguint().unwrap();
+
+    if x >= &p {
+        return Err(EcError::InvalidEncoding);
+    }
+
+    let rhs = (x * x * x + &a * x + &b) % &p;
+    let y = primes::modpow(&rhs, &((&p + BigUint::one()) >> 2), &p);
+
+    // X is not on the curve if RHS has no square root
+    if (&y * &y) % &p != rhs {
+        return Err(EcError::InvalidEncoding);
+    }
+
+    if is_odd(&y) == odd {
+        Ok(y)
+    } else if y.is_zero() {
+        Err(EcError::InvalidEncoding)
+    } else {
+        Ok(&p - &y)
+    }
+}
+
+/// Check that a point is affine and valid for the group as a public key
+/// 
+/// ### Arguments
+/// 
+/// * `group` - Curve group the point should lie on
+/// * `point` - Point to check
+fn check_point(group: &ECPGroup, point: &ECPPoint) -> (bool, &'static str) {
+    // Must use affine coordinates
+    if point.z != BigInt::one() {
+        return (false, "Coordinates for public key point are not affine (Z coordinate != 1)");
+    }
+
+    match group.get_curve_shape() {
+        ECPCurveShape::Montgomery => {
+            if (point.x.bits() + 7) / 8 > (group.nbits + 7) / 8 {
+                return (false, "X coord for public point is too large");
+            }
+
+            (true, "")
+        },
+
+        ECPCurveShape::ShortWeierstrass => {
+            if point.y.is_none() {
+                return (false, "Y coord for public point is missing");
+            }
+
+            check_weierstrass_point(group, point)
+        }
+    }
+}
+
+/// Check that an affine point is valid as a public key,
+/// Short weierstrass curves (SEC1 3.2.3.1)
+/// 
+/// ### Arguments
+/// 
+/// * `group` - Curve group the point should lie on
+/// * `point` - Point to check
+fn check_weierstrass_point(group: &ECPGroup, point: &ECPPoint) -> (bool, &'static str) {
+    if point.x.clone() < BigInt::zero()             || 
+       point.y.clone().unwrap() < BigInt::zero()    ||
+       point.x >= group.p.to_bigint().unwrap()      ||
+       point.y.clone().unwrap() >= group.p.to_bigint().unwrap()
+    {
+        return (false, "X and Y coords need to be normalized");
+    }
+
+    // YY = Y^2
+    // RHS = X (X^2 + A) + B = X^3 + A X + B
+    let y_squared = group.mod_p( &(point.y.clone().unwrap() * point.y.clone().unwrap()) );
+    let mut rhs = group.mod_p( &(point.x.clone() * point.x.clone()) );
+
+    // Special case for A = -3
+    // NOTE handle A as a signed int
+    if group.a.to_bigint().unwrap() == -3.to_bigint().unwrap() {
+        rhs = group.mod_increase( &(rhs - 3.to_bigint().unwrap()) );
+    } else {
+        rhs = group.mod_reduce( &(rhs.clone() + group.a.to_bigint().unwrap()) );
+    }
+
+    rhs = group.mod_p( &(rhs.clone() * point.x.clone()) );
+    rhs = group.mod_reduce( &(rhs.clone() + group.b.to_bigint().unwrap()) );
+
+    if rhs != y_squared {
+        return (false, "Y^2 != X (X^2 + A) + B = X^3 + A X + B");
+    }
+
+    (true, "")
+}
+
+/// Checks that a Montgomery u-coordinate belongs to a point of order L.
+/// The right-hand side u^3 + A u^2 + u has to be a non-zero square,
+/// which rules out the twist and the point (0, 0), and (L + 1) u has to
+/// come back to u, which only happens when L u is the point at infinity
+/// 
+/// ### Arguments
+/// 
+/// * `group` - Montgomery curve group
+/// * `point` - Point that has passed the membership checks
+fn check_montgomery_subgroup(group: &ECPGroup, point: &ECPPoint) -> Result<(), EcError> {
+    let p = group.p.to_biguint().unwrap();
+    let a = group.a.to_biguint().unwrap();
+
+    let u = match point.x.to_biguint() {
+        Some(ref u) if u < &p => u.clone(),
+        _ => return Err(EcError::InvalidPublicKey("U coordinate needs to be reduced mod P"))
+    };
+
+    let rhs = (&u * &u * &u + &a * &u * &u + &u) % &p;
+
+    if primes::modpow(&rhs, &((&p - BigUint::one()) >> 1), &p) != BigUint::one() {
+        return Err(EcError::InvalidPublicKey("U coordinate is not that of a point on the curve"));
+    }
+
+    // The point and the scalar are both public, so the ladder's
+    // coordinate blinding needs no fresh randomness
+    let order = BigUint::parse_bytes(CURVE25519_ORDER, 16).unwrap();
+    let multiple = montgomery_ladder::multiply(group, &(order + BigUint::one()), point, &mut ChaChaRng::from_seed(&[0]));
+
+    if multiple.x != point.x {
+        return Err(EcError::InvalidPublicKey("Point is outside the subgroup of prime order"));
+    }
+
+    Ok(())
+}
+
+/// Checks whether a value is odd
+/// 
+/// ### Arguments
+/// 
+/// * `value` - Value to check
+fn is_odd(value: &BigUint) -> bool {
+    (value % 2.to_biguint().unwrap()) == BigUint::one()
 }
 
 
@@ -305,32 +871,117 @@ impl ECPKeypair {
 #[cfg(test)]
 mod ecc_test {
 
-    use rand::OsRng;
-    use utils::ecc::ECPKeypair;
-    use utils::ecc_curves::ECPSupportedCurves;
+    use rand::{ ChaChaRng, SeedableRng };
+    use crate::utils::rng::os_rng;
+    use num_bigint::{ BigUint, ToBigInt };
+    use crate::utils::encoding::hex_to_bytes;
+    use crate::utils::ecc::{ ECPKeypair, EcError, ValidationLevel, encode_point, decode_point, encoded_point_len };
+    use crate::utils::ecc_curves::{ ECPGroup, ECPPoint, ECPSupportedCurves };
 
     #[test]
     fn keypair_generation_bp256r1() {
-        let mut rng = OsRng::new().unwrap();
-        let ecc = ECPKeypair::new(ECPSupportedCurves::BP256R1).setup(&mut rng);
+        let mut rng = os_rng().unwrap();
+        let ecc = ECPKeypair::new(ECPSupportedCurves::BP256R1).setup(&mut rng).unwrap();
     }
 
     #[test]
     fn keypair_generation_bp384r1() {
-        let mut rng = OsRng::new().unwrap();
-        let ecc = ECPKeypair::new(ECPSupportedCurves::BP384R1).setup(&mut rng);
+        let mut rng = os_rng().unwrap();
+        let ecc = ECPKeypair::new(ECPSupportedCurves::BP384R1).setup(&mut rng).unwrap();
     }
 
     #[test]
     fn keypair_generation_bp521r1() {
-        let mut rng = OsRng::new().unwrap();
-        let ecc = ECPKeypair::new(ECPSupportedCurves::BP521R1).setup(&mut rng);
+        let mut rng = os_rng().unwrap();
+        let ecc = ECPKeypair::new(ECPSupportedCurves::BP521R1).setup(&mut rng).unwrap();
     }
 
     #[test]
     fn keypair_generation_curve25519() {
-        let mut rng = OsRng::new().unwrap();
-        let ecc = ECPKeypair::new(ECPSupportedCurves::Curve25519).setup(&mut rng);
+        let mut rng = os_rng().unwrap();
+        let ecc = ECPKeypair::new(ECPSupportedCurves::Curve25519).setup(&mut rng).unwrap();
+    }
+
+    #[test]
+    fn point_encoding_round_trip() {
+        let mut rng = os_rng().unwrap();
+
+        for curve in vec![ECPSupportedCurves::BP256R1, ECPSupportedCurves::Curve25519] {
+            let ecc = ECPKeypair::new(curve).setup(&mut rng).unwrap();
+            let encoded = encode_point(&ecc.group, &ecc.q, false).unwrap();
+            assert_eq!(encoded.len(), encoded_point_len(&ecc.group));
+
+            for compressed in vec![false, true] {
+                let encoded = encode_point(&ecc.group, &ecc.q, compressed).unwrap();
+                let decoded = decode_point(&ecc.group, &encoded).unwrap();
+
+                assert_eq!(decoded.x, ecc.q.x);
+                assert_eq!(decoded.y, ecc.q.y);
+            }
+        }
+    }
+
+    #[test]
+    fn point_decoding_rejects_bad_format() {
+        let mut rng = os_rng().unwrap();
+        let ecc = ECPKeypair::new(ECPSupportedCurves::BP256R1).setup(&mut rng).unwrap();
+        let mut encoded = encode_point(&ecc.group, &ecc.q, false).unwrap();
+
+        assert_eq!(decode_point(&ecc.group, &encoded[1..]).err(), Some(EcError::InvalidEncoding));
+
+        encoded[0] = 0x02;
+        assert_eq!(decode_point(&ecc.group, &encoded).err(), Some(EcError::InvalidEncoding));
+
+        encoded[0] = 0x05;
+        assert_eq!(decode_point(&ecc.group, &encoded[..33]).err(), Some(EcError::InvalidEncoding));
+    }
+
+    #[test]
+    fn seeded_keypair_generation_is_reproducible() {
+        for curve in vec![ECPSupportedCurves::BP256R1, ECPSupportedCurves::Curve25519] {
+            let first = ECPKeypair::new(curve.clone()).setup(&mut ChaChaRng::from_seed(&[1, 2, 3, 4])).unwrap();
+            let second = ECPKeypair::new(curve).setup(&mut ChaChaRng::from_seed(&[1, 2, 3, 4])).unwrap();
+
+            assert_eq!(first.d, second.d);
+            assert_eq!((&first.q.x, &first.q.y), (&second.q.x, &second.q.y));
+        }
+    }
+
+    #[test]
+    fn validation_rejects_points_off_the_curve() {
+        let group = ECPGroup::new(ECPSupportedCurves::BP256R1);
+        let off_curve = ECPPoint::new(&group.g.y.clone().unwrap(), Some(group.g.x.clone()));
+
+        for level in vec![ValidationLevel::Membership, ValidationLevel::FullSubgroup] {
+            let validated = group.validate(group.g.clone(), level).unwrap();
+
+            assert_eq!(validated.curve_id(), ECPSupportedCurves::BP256R1);
+            assert_eq!(validated.level(), level);
+            assert!(validated.check_curve(&ECPSupportedCurves::SECP256R1).is_err());
+            assert!(group.validate(off_curve.clone(), level).is_err());
+        }
+    }
+
+    #[test]
+    fn full_subgroup_validation_on_curve25519() {
+        let mut rng = os_rng().unwrap();
+        let ecc = ECPKeypair::new(ECPSupportedCurves::Curve25519).setup(&mut rng).unwrap();
+        let group = &ecc.group;
+
+        assert!(group.validate(ecc.q.clone(), ValidationLevel::FullSubgroup).is_ok());
+
+        // A point of order 8, and the u-coordinate 2, which lies on the twist
+        let low_order = hex_to_bytes("e0eb7a7c3b41b8ae1656e3faf19fc46ada098deb9c32b1fd866205165f49b800").unwrap();
+
+        for u in vec![BigUint::from_bytes_le(&low_order), BigUint::from(2u8)] {
+            let point = ECPPoint::new(&u.to_bigint().unwrap(), None);
+
+            assert!(group.validate(point.clone(), ValidationLevel::Membership).is_ok());
+            assert!(match group.validate(point, ValidationLevel::FullSubgroup) {
+                Err(EcError::InvalidPublicKey(_)) => true,
+                _ => false
+            });
+        }
     }
 
 }
\ No newline at end of file
@@ -1,7 +1,14 @@
 use std::fmt;
 use std::ops::{ Rem, Shl };
+use std::sync::{ Arc, RwLock };
 use ramp::Int;
 
+#[cfg(any(test, feature = "metrics"))]
+use crate::utils::metrics::{ self, Operation };
+
+#[cfg(feature = "serde")]
+use serde::{ Serialize, Deserialize };
+
 
 /*---- ENUMS ----*/
 
@@ -36,7 +43,8 @@ pub enum ECPCurveShape {
 /// be found here: https://safecurves.cr.yp.to/rigid.html
 /// 
 /// Further support for Twisted Edwards curves will be provided in future
-#[derive(PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ECPSupportedCurves {
     SECP256R1,      // 256-bits NIST curve
     SECP384R1,      // 384-bits NIST curve
@@ -59,8 +67,7 @@ pub struct ECPGroup {
     pub g: ECPPoint,        // Generator of the (sub)group used
     pub n: Int,             // 1. the order of G, or 2. unused
     pub nbits: usize,       // number of bits in 1. P, or 2. private keys
-    pub t: Vec<ECPPoint>,   // pre-computed point for comb method multiplication
-    pub t_size: usize       // size for pre-computed point
+    t: Arc<RwLock<Option<Vec<ECPPoint>>>>   // cached comb method table for G, shared between threads
 }
 
 #[derive(Clone, Debug)]
@@ -100,16 +107,50 @@ impl ECPGroup {
                     p: p,
                     a: 486662.to_Int().unwrap(),
                     b: Int::one(),
-                    g: generator_point.clone(),
+                    g: generator_point,
                     n: Int::one(),
                     nbits: 254,
-                    t: vec![generator_point],
-                    t_size: 0
+                    t: Arc::new(RwLock::new(None))
                 };               
             }
         }
     }
 
+    /// Get the identifier of the curve this group was created from
+    pub fn curve_id(&self) -> ECPSupportedCurves {
+        match self.name {
+            "SECP256R1" => ECPSupportedCurves::SECP256R1,
+            "SECP384R1" => ECPSupportedCurves::SECP384R1,
+            "SECP521R1" => ECPSupportedCurves::SECP521R1,
+            "BP256R1" => ECPSupportedCurves::BP256R1,
+            "BP384R1" => ECPSupportedCurves::BP384R1,
+            "BP521R1" => ECPSupportedCurves::BP521R1,
+            _ => ECPSupportedCurves::Curve25519
+        }
+    }
+
+    /// Returns a copy of the cached comb method table for the
+    /// generator, if it has been computed yet
+    pub fn precomputed_table(&self) -> Option<Vec<ECPPoint>> {
+        match self.t.read() {
+            Ok(t) => t.clone(),
+            Err(_) => None
+        }
+    }
+
+    /// Caches the comb method table for the generator. The table sits
+    /// behind a lock so that a group can be shared between threads
+    /// without requiring mutable access during multiplication
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `table` - Precomputed points for G
+    pub fn set_precomputed_table(&self, table: Vec<ECPPoint>) -> () {
+        if let Ok(mut t) = self.t.write() {
+            *t = Some(table);
+        }
+    }
+
     /// Get the shape of the group curve
     pub fn get_curve_shape(&self) -> ECPCurveShape {
         match &self.g.y {
@@ -119,18 +160,30 @@ impl ECPGroup {
     }
 
     /// Mod P calculation used in numerous instances for 
-    /// value generation
+    /// value generation. Every call reduces a product, so each one is
+    /// counted as a field multiplication in the metrics.
+    /// 
+    /// The remainder takes the sign of `n`: the result is in [0, p) for
+    /// non-negative input, but in (-p, 0] for negative input. Use
+    /// `reduce_signed` when the input may be negative
     /// 
     /// ### Arguments
     /// 
     /// * `n` - Value to mod
     pub fn mod_p(&self, n: &Int) -> Int {
+        #[cfg(any(test, feature = "metrics"))]
+        metrics::record(Operation::ModMul);
+
         n.clone().rem(&self.p)
     }
 
     /// Reduce a mod p in-place. We know P, N and the result are 
     /// positive, so sub_abs is correct, and a bit faster.
     /// 
+    /// Meant for sums and small multiples of values already in [0, p):
+    /// P is subtracted once per multiple, and negative input is
+    /// returned unchanged
+    /// 
     /// ### Arguments
     /// 
     /// * `n` - Value to reduce
@@ -149,6 +202,10 @@ impl ECPGroup {
 
     /// Increase a mod p in-place if the value is less than 0
     /// 
+    /// Meant for differences of values already in [0, p): P is added
+    /// once per multiple below zero, and input of p or more is
+    /// returned unchanged
+    /// 
     /// ### Arguments
     /// 
     /// * `n` - Value to increase
@@ -164,6 +221,23 @@ impl ECPGroup {
         n_clone
     }
 
+    /// Canonical representative of a value mod p, in [0, p) for input
+    /// of any sign and size. Counted as a field multiplication, like
+    /// `mod_p`
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `x` - Value to reduce
+    pub fn reduce_signed(&self, x: &Int) -> Int {
+        let remainder = self.mod_p(x);
+
+        if remainder < Int::zero() {
+            return remainder + &self.p;
+        }
+
+        remainder
+    }
+
 }
 
 
@@ -192,15 +266,10 @@ impl ECPPoint {
         self.z = Int::zero();
     }
 
-    /// Checks whether point is at zero
+    /// Checks whether point is at zero, which in Jacobian
+    /// coordinates is any point with Z == 0
     pub fn is_zero(&self) -> bool {
-        if self.x == Int::zero()
-        && self.y == Some(Int::zero())
-        && self.z == Int::zero() {
-            return true;
-        }
-
-        false
+        self.z == Int::zero()
     }
 
 }
@@ -244,11 +313,10 @@ fn create_from_domain_parameters(from: &ECurveDomainParameters) -> ECPGroup {
         p: Int::from_bytes_le(Sign::Plus, from.p),
         a: Int::from_bytes_le(from.a),
         b: Int::from_bytes_le(from.b),
-        g: generator_point.clone(),
+        g: generator_point,
         n: Int::from_bytes_le(from.n),
         nbits: Int::from_bytes_le(from.n).bits(),
-        t: vec![generator_point],
-        t_size: 0
+        t: Arc::new(RwLock::new(None))
     }
 }
 
@@ -382,7 +450,7 @@ const SECP521R1: ECurveDomainParameters = ECurveDomainParameters {
         0xFF, 0x01
     ],
     a: &[
-        0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+        0xFC, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
         0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
         0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
         0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
@@ -390,7 +458,7 @@ const SECP521R1: ECurveDomainParameters = ECurveDomainParameters {
         0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
         0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
         0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
-        0xFF, 0xFC
+        0xFF, 0x01
     ],
     b: &[
         0x00, 0x3F, 0x50, 0x6B, 0xD4, 0x1F, 0x45, 0xEF,
@@ -607,4 +675,36 @@ const BP521R1: ECurveDomainParameters = ECurveDomainParameters {
         0x07, 0xFC, 0xC9, 0x33, 0xAE, 0xE6, 0xD4, 0x3F,
         0x8B, 0xC4, 0xE9, 0xDB, 0xB8, 0x9D, 0xDD, 0xAA
     ]
-};
\ No newline at end of file
+};
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod ecc_curves_test {
+
+    use ramp::Int;
+    use crate::utils::ecc_curves::{ ECPGroup, ECPSupportedCurves };
+
+    #[test]
+    fn signed_values_reduce_to_the_canonical_representative() {
+        for curve in &[ECPSupportedCurves::SECP256R1, ECPSupportedCurves::Curve25519] {
+            let group = ECPGroup::new(curve.clone());
+            let p = group.p.clone();
+            let three = 3.to_Int().unwrap();
+            let p_squared = &p * &p;
+
+            // A plain remainder keeps the sign of negative input
+            assert!(group.mod_p(&-three.clone()) < Int::zero());
+
+            assert_eq!(group.reduce_signed(&Int::zero()), Int::zero());
+            assert_eq!(group.reduce_signed(&three), three);
+            assert_eq!(group.reduce_signed(&-three.clone()), &p - &three);
+            assert_eq!(group.reduce_signed(&(&three - &p)), three);
+            assert_eq!(group.reduce_signed(&-p.clone()), Int::zero());
+            assert_eq!(group.reduce_signed(&p), Int::zero());
+            assert_eq!(group.reduce_signed(&(&p_squared + &three)), three);
+            assert_eq!(group.reduce_signed(&(-p_squared - &three)), &p - &three);
+        }
+    }
+}
\ No newline at end of file
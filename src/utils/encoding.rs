@@ -1,9 +1,13 @@
-use num_traits::Num;
-use bit_vec::BitVec;
+use std::fmt;
+use num_traits::{ Num, Zero };
 use std::string::String;
-use ascii::{ IntoAsciiString, AsciiString };
 use num_bigint::BigUint;
-use rustc_serialize::hex::{ ToHex, FromHex };
+use rustc_serialize::hex::ToHex;
+
+#[cfg(feature = "encoding")]
+use bit_vec::BitVec;
+#[cfg(feature = "encoding")]
+use ascii::{ IntoAsciiString, AsciiString };
 
 /// Enum to represent endian ordering
 #[derive(PartialEq)]
@@ -12,6 +16,45 @@ pub enum EndianOrdering {
     Big
 }
 
+/// Errors raised while converting between representations
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum EncodingError {
+    InvalidHex,         // input is not a valid hex string
+    NotAscii,           // decoded bytes are not valid ASCII
+    InvalidBase58,      // input contains characters outside the base58 alphabet
+    InvalidBech32,      // input is not a well-formed bech32 string or its checksum fails
+    InvalidWord,        // input contains a word outside the fingerprint word lists
+    InvalidPem          // input is not PEM armor with the expected label
+}
+
+impl fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EncodingError::InvalidHex => write!(f, "Input is not a valid hex string"),
+            EncodingError::NotAscii => write!(f, "Input does not decode to ASCII"),
+            EncodingError::InvalidBase58 => write!(f, "Input is not a valid base58 string"),
+            EncodingError::InvalidBech32 => write!(f, "Input is not a valid bech32 string"),
+            EncodingError::InvalidWord => write!(f, "Input contains an unknown fingerprint word"),
+            EncodingError::InvalidPem => write!(f, "Input is not valid PEM armor for the expected label")
+        }
+    }
+}
+
+
+/*---- CONSTANTS ----*/
+
+/// Base58 alphabet, without 0, O, I and l
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Bech32 alphabet (BIP 173)
+const BECH32_ALPHABET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// Generator coefficients of the bech32 checksum
+const BECH32_GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+
+/// Base64 characters per line of PEM armor (RFC 7468)
+const PEM_LINE_LENGTH: usize = 64;
+
 
 /*---- FUNCTIONS ----*/
 
@@ -25,17 +68,44 @@ pub fn to_hex(input: &BigUint) -> String {
     input.to_str_radix(16)
 }
 
+/// Formats bytes as a lowercase hex string, two digits per byte
+/// 
+/// ### Arguments
+/// 
+/// * `input` - Bytes to format
+pub fn bytes_to_hex(input: &[u8]) -> String {
+    input.to_hex()
+}
+
+/// Parses a hex string written by `bytes_to_hex`. Either case is
+/// accepted, but every byte needs both of its digits
+/// 
+/// ### Arguments
+/// 
+/// * `input` - Hex string to parse
+pub fn hex_to_bytes(input: &str) -> Result<Vec<u8>, EncodingError> {
+    if input.len() % 2 != 0 {
+        return Err(EncodingError::InvalidHex);
+    }
+
+    input.as_bytes().chunks(2).map(|pair| {
+        match (hex_digit(pair[0]), hex_digit(pair[1])) {
+            (Some(high), Some(low)) => Ok((high << 4) | low),
+            _ => Err(EncodingError::InvalidHex)
+        }
+    }).collect()
+}
+
 /// Converts input BigUint to ASCII string
 /// 
 /// ### Arguments
 /// 
 /// * `input` - Input to convert 
-pub fn to_plaintext(input: &BigUint) -> AsciiString {
-    let potential_plaintext = input.to_str_radix(16).from_hex().unwrap().into_ascii_string();
-
-    match potential_plaintext {
-        Ok(p) => return p,
-        Err(e) => panic!("Error in generating plaintext: {}", e)
+#[cfg(feature = "encoding")]
+pub fn to_plaintext(input: &BigUint) -> Result<AsciiString, EncodingError> {
+    match input.to_bytes_be().into_ascii_string() {
+        Ok(p) => Ok(p),
+        Err(_) => Err(EncodingError::NotAscii)
     }
 }
 
@@ -54,8 +124,11 @@ pub fn from_plaintext(input: String) -> BigUint {
 /// ### Arguments
 /// 
 /// * `input` - Input to convert 
-pub fn from_hex(input: String) -> BigUint {
-    BigUint::from_str_radix(&input, 16).unwrap()
+pub fn from_hex(input: String) -> Result<BigUint, EncodingError> {
+    match BigUint::from_str_radix(&input, 16) {
+        Ok(value) => Ok(value),
+        Err(_) => Err(EncodingError::InvalidHex)
+    }
 }
 
 
@@ -65,6 +138,7 @@ pub fn from_hex(input: String) -> BigUint {
 /// 
 /// * `input` - BigUint to convert
 /// * `ordering` - Endian ordering (either big or little)
+#[cfg(feature = "encoding")]
 pub fn biguint_to_bitvec(input: &BigUint, ordering: EndianOrdering) -> BitVec {
     let input_array = match ordering {
         EndianOrdering::Little => input.clone().to_bytes_le(),
@@ -81,6 +155,7 @@ pub fn biguint_to_bitvec(input: &BigUint, ordering: EndianOrdering) -> BitVec {
 /// 
 /// * `input` - BitVector to convert
 /// * `ordering` - Endian ordering (either big or little)
+#[cfg(feature = "encoding")]
 pub fn bitvec_to_biguint(input: &BitVec, ordering: EndianOrdering) -> BigUint {
     let input_array = &input.to_bytes();
 
@@ -91,6 +166,58 @@ pub fn bitvec_to_biguint(input: &BitVec, ordering: EndianOrdering) -> BigUint {
 }
 
 
+/// Converts a BigUint into a byte vector of exactly `length` bytes,
+/// padding with zeros on the most significant side. Returns None if
+/// the value does not fit in the requested length
+/// 
+/// ### Arguments
+/// 
+/// * `input` - BigUint to convert
+/// * `length` - Exact length of the output in bytes
+/// * `ordering` - Endian ordering (either big or little)
+pub fn biguint_to_fixed_bytes(input: &BigUint, length: usize, ordering: EndianOrdering) -> Option<Vec<u8>> {
+    let mut bytes = input.to_bytes_le();
+
+    // to_bytes_le returns [0] for zero
+    while bytes.len() > 1 && bytes[bytes.len() - 1] == 0 {
+        bytes.pop();
+    }
+
+    if bytes.len() > length {
+        return None;
+    }
+
+    bytes.resize(length, 0);
+
+    if ordering == EndianOrdering::Big {
+        bytes.reverse();
+    }
+
+    Some(bytes)
+}
+
+
+/// Canonical encoding of a list of values: each as a four byte big-endian
+/// length followed by its shortest big-endian bytes, zero being empty.
+/// Every list has exactly one encoding, so it is fit for hashing
+/// 
+/// ### Arguments
+/// 
+/// * `values` - Values to encode, in order
+pub fn length_prefixed_be(values: &[&BigUint]) -> Vec<u8> {
+    let mut output = Vec::new();
+
+    for value in values {
+        let bytes = if value.is_zero() { Vec::new() } else { value.to_bytes_be() };
+
+        output.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+        output.extend_from_slice(&bytes);
+    }
+
+    output
+}
+
+
 /// Converts an array of binary booleans 
 /// into an integer
 /// 
@@ -105,7 +232,7 @@ pub fn binary_to_int(slice: &[bool]) -> u8 {
             &false => 0
         };
 
-        result + (2u8.pow(index as u32)/// (bit as u8))
+        result + (2u8.pow(index as u32) * (bit as u8))
     }
     
     slice.iter().enumerate().fold(0, accumulate)
@@ -167,7 +294,7 @@ pub fn int_to_binary_string(entry: &u8) -> Vec<&str> {
 /// ### Arguments
 /// 
 /// * `binary` - Binary to convert
-pub fn binary_to_string(binary: &Vec<bool>) -> String {
+pub fn binary_to_string(binary: &[bool]) -> String {
     let mut final_string = String::new();
 
     for entry in binary {
@@ -180,4 +307,363 @@ pub fn binary_to_string(binary: &Vec<bool>) -> String {
     }
 
     final_string
+}
+
+/// Encodes bytes in base58. Each leading zero byte becomes a leading "1"
+/// 
+/// ### Arguments
+/// 
+/// * `input` - Bytes to encode
+pub fn base58_encode(input: &[u8]) -> String {
+    let zeros = input.iter().take_while(|b| **b == 0).count();
+
+    // Repeated division by 58 on little-endian base58 digits
+    let mut digits: Vec<u8> = Vec::with_capacity(input.len() * 138 / 100 + 1);
+
+    for byte in &input[zeros..] {
+        let mut carry = *byte as u32;
+
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+
+    let mut encoded = String::with_capacity(zeros + digits.len());
+    encoded.extend(std::iter::repeat('1').take(zeros));
+    encoded.extend(digits.iter().rev().map(|d| BASE58_ALPHABET[*d as usize] as char));
+
+    encoded
+}
+
+/// Decodes a base58 string produced by `base58_encode`
+/// 
+/// ### Arguments
+/// 
+/// * `input` - String to decode
+pub fn base58_decode(input: &str) -> Result<Vec<u8>, EncodingError> {
+    let zeros = input.bytes().take_while(|c| *c == b'1').count();
+    let mut bytes: Vec<u8> = Vec::with_capacity(input.len());
+
+    for c in input.bytes().skip(zeros) {
+        let mut carry = match BASE58_ALPHABET.iter().position(|a| *a == c) {
+            Some(value) => value as u32,
+            None => return Err(EncodingError::InvalidBase58)
+        };
+
+        for byte in bytes.iter_mut() {
+            carry += (*byte as u32) * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+
+    let mut decoded = vec![0; zeros];
+    decoded.extend(bytes.iter().rev());
+
+    Ok(decoded)
+}
+
+/// Encodes bytes as a bech32 string (BIP 173) under a human-readable
+/// part. The bytes are regrouped into 5-bit values, zero-padded at
+/// the end
+/// 
+/// ### Arguments
+/// 
+/// * `hrp` - Human-readable part, in lowercase ASCII
+/// * `data` - Bytes to encode
+pub fn bech32_encode(hrp: &str, data: &[u8]) -> Result<String, EncodingError> {
+    if hrp.is_empty() || hrp.bytes().any(|c| c < 33 || c > 126 || c.is_ascii_uppercase()) {
+        return Err(EncodingError::InvalidBech32);
+    }
+
+    let values = regroup_bits(data, 8, 5, true).ok_or(EncodingError::InvalidBech32)?;
+    let mut checked = bech32_expand_hrp(hrp);
+    checked.extend_from_slice(&values);
+    checked.extend_from_slice(&[0; 6]);
+
+    let checksum = bech32_polymod(&checked) ^ 1;
+    let mut encoded = format!("{}1", hrp);
+
+    for value in values.iter().cloned().chain((0..6).map(|i| ((checksum >> (5 * (5 - i))) & 31) as u8)) {
+        encoded.push(BECH32_ALPHABET[value as usize] as char);
+    }
+
+    Ok(encoded)
+}
+
+/// Decodes a bech32 string into its human-readable part and bytes,
+/// checking the checksum. Mixed-case strings are rejected
+/// 
+/// ### Arguments
+/// 
+/// * `input` - String to decode
+pub fn bech32_decode(input: &str) -> Result<(String, Vec<u8>), EncodingError> {
+    let has_lower = input.bytes().any(|c| c.is_ascii_lowercase());
+    let has_upper = input.bytes().any(|c| c.is_ascii_uppercase());
+
+    if has_lower && has_upper || input.len() > 90 {
+        return Err(EncodingError::InvalidBech32);
+    }
+
+    let input = input.to_ascii_lowercase();
+    let separator = input.rfind('1').ok_or(EncodingError::InvalidBech32)?;
+    let (hrp, rest) = (&input[..separator], &input[separator + 1..]);
+
+    if hrp.is_empty() || rest.len() < 6 || hrp.bytes().any(|c| c < 33 || c > 126) {
+        return Err(EncodingError::InvalidBech32);
+    }
+
+    let mut values = Vec::with_capacity(rest.len());
+
+    for c in rest.bytes() {
+        match BECH32_ALPHABET.iter().position(|a| *a == c) {
+            Some(value) => values.push(value as u8),
+            None => return Err(EncodingError::InvalidBech32)
+        }
+    }
+
+    let mut checked = bech32_expand_hrp(hrp);
+    checked.extend_from_slice(&values);
+
+    if bech32_polymod(&checked) != 1 {
+        return Err(EncodingError::InvalidBech32);
+    }
+
+    values.truncate(values.len() - 6);
+
+    match regroup_bits(&values, 5, 8, false) {
+        Some(data) => Ok((hrp.to_string(), data)),
+        None => Err(EncodingError::InvalidBech32)
+    }
+}
+
+/// Wraps DER bytes in PEM armor (RFC 7468), with base64 lines of 64
+/// characters between the BEGIN and END lines
+/// 
+/// ### Arguments
+/// 
+/// * `label` - Label of the armor, eg. "PUBLIC KEY"
+/// * `der` - DER bytes to wrap
+pub fn pem_encode(label: &str, der: &[u8]) -> String {
+    let body = base64::encode(der);
+    let mut pem = format!("-----BEGIN {}-----\n", label);
+
+    for line in body.as_bytes().chunks(PEM_LINE_LENGTH) {
+        pem.push_str(&String::from_utf8_lossy(line));
+        pem.push('\n');
+    }
+
+    pem.push_str(&format!("-----END {}-----\n", label));
+    pem
+}
+
+/// Unwraps the DER bytes of PEM armor with the expected label. Blank
+/// lines around the armor and line endings of either kind are accepted,
+/// while text before or after it and headers inside it are not
+/// 
+/// ### Arguments
+/// 
+/// * `label` - Expected label of the armor
+/// * `pem` - PEM text to unwrap
+pub fn pem_decode(label: &str, pem: &str) -> Result<Vec<u8>, EncodingError> {
+    let lines: Vec<&str> = pem.lines().map(|line| line.trim_end()).filter(|line| !line.is_empty()).collect();
+
+    if lines.len() < 2
+        || lines[0] != format!("-----BEGIN {}-----", label)
+        || lines[lines.len() - 1] != format!("-----END {}-----", label)
+    {
+        return Err(EncodingError::InvalidPem);
+    }
+
+    let body: String = lines[1..lines.len() - 1].concat();
+
+    base64::decode(&body).map_err(|_| EncodingError::InvalidPem)
+}
+
+/// Value of a single hex digit
+/// 
+/// ### Arguments
+/// 
+/// * `digit` - ASCII hex digit
+fn hex_digit(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|value| value as u8)
+}
+
+/// Checksum of the bech32 BCH code over 5-bit values
+/// 
+/// ### Arguments
+/// 
+/// * `values` - Expanded human-readable part followed by the data
+fn bech32_polymod(values: &[u8]) -> u32 {
+    let mut checksum = 1u32;
+
+    for value in values {
+        let top = checksum >> 25;
+        checksum = ((checksum & 0x1ff_ffff) << 5) ^ (*value as u32);
+
+        for (i, generator) in BECH32_GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+
+    checksum
+}
+
+/// Expands the human-readable part into the values fed to the checksum
+/// 
+/// ### Arguments
+/// 
+/// * `hrp` - Human-readable part
+fn bech32_expand_hrp(hrp: &str) -> Vec<u8> {
+    let mut expanded: Vec<u8> = hrp.bytes().map(|c| c >> 5).collect();
+    expanded.push(0);
+    expanded.extend(hrp.bytes().map(|c| c & 31));
+
+    expanded
+}
+
+/// Regroups a sequence of `from`-bit values into `to`-bit values. When
+/// not padding, leftover bits must be fewer than `from` and all zero
+/// 
+/// ### Arguments
+/// 
+/// * `data` - Values to regroup
+/// * `from` - Bits per input value
+/// * `to` - Bits per output value
+/// * `pad` - Whether to zero-pad a trailing partial group
+fn regroup_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Option<Vec<u8>> {
+    let mut accumulator = 0u32;
+    let mut bits = 0u32;
+    let mut regrouped = Vec::with_capacity(data.len() * from as usize / to as usize + 1);
+    let mask = (1u32 << to) - 1;
+
+    for value in data {
+        if (*value as u32) >> from != 0 {
+            return None;
+        }
+
+        accumulator = (accumulator << from) | *value as u32;
+        bits += from;
+
+        while bits >= to {
+            bits -= to;
+            regrouped.push(((accumulator >> bits) & mask) as u8);
+        }
+    }
+
+    if pad {
+        if bits > 0 {
+            regrouped.push(((accumulator << (to - bits)) & mask) as u8);
+        }
+    } else if bits >= from || ((accumulator << (to - bits)) & mask) != 0 {
+        return None;
+    }
+
+    Some(regrouped)
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod encoding_test {
+
+    use num_bigint::BigUint;
+    use num_traits::{ FromPrimitive, Zero };
+    use crate::utils::encoding::{ EncodingError, bytes_to_hex, hex_to_bytes, base58_encode, base58_decode, bech32_encode, bech32_decode, pem_encode, pem_decode, length_prefixed_be };
+
+    #[test]
+    fn length_prefixed_values() {
+        let modulus = BigUint::from_u32(0x01_00ff).unwrap();
+        let exponent = BigUint::from_u32(3).unwrap();
+
+        assert_eq!(length_prefixed_be(&[&modulus, &exponent]), vec![0, 0, 0, 3, 0x01, 0x00, 0xff, 0, 0, 0, 1, 3]);
+        assert_eq!(length_prefixed_be(&[&BigUint::zero()]), vec![0, 0, 0, 0]);
+        assert_eq!(length_prefixed_be(&[]), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn hex_pads_every_byte() {
+        assert_eq!(bytes_to_hex(&[0x00, 0x07, 0xaf, 0x10, 0xff]), "0007af10ff");
+        assert_eq!(bytes_to_hex(&[]), "");
+        assert_eq!(hex_to_bytes("0007AF10ff").unwrap(), vec![0x00, 0x07, 0xaf, 0x10, 0xff]);
+
+        assert_eq!(hex_to_bytes("7"), Err(EncodingError::InvalidHex));
+        assert_eq!(hex_to_bytes("0g"), Err(EncodingError::InvalidHex));
+        assert_eq!(hex_to_bytes("+7"), Err(EncodingError::InvalidHex));
+    }
+
+    #[test]
+    fn base58_matches_reference_strings() {
+        let cases: [(&[u8], &str); 4] = [
+            (b"", ""),
+            (b"Hello World!", "2NEpo7TZRRrLZSi2U"),
+            (&[0x00, 0x00, 0x28, 0x7f, 0xb4, 0xcd], "11233QC4"),
+            (&[0x00], "1")
+        ];
+
+        for &(bytes, encoded) in cases.iter() {
+            assert_eq!(base58_encode(bytes), encoded);
+            assert_eq!(base58_decode(encoded).unwrap(), bytes);
+        }
+
+        assert_eq!(base58_decode("0OIl"), Err(EncodingError::InvalidBase58));
+    }
+
+    #[test]
+    fn bech32_matches_reference_strings() {
+        let data = [
+            0x00, 0x44, 0x32, 0x14, 0xc7, 0x42, 0x54, 0xb6, 0x35, 0xcf,
+            0x84, 0x65, 0x3a, 0x56, 0xd7, 0xc6, 0x75, 0xbe, 0x77, 0xdf
+        ];
+
+        assert_eq!(bech32_encode("a", &[]).unwrap(), "a12uel5l");
+        assert_eq!(bech32_encode("abcdef", &data).unwrap(), "abcdef1qpzry9x8gf2tvdw0s3jn54khce6mua7lmqqqxw");
+        assert_eq!(bech32_decode("ABCDEF1QPZRY9X8GF2TVDW0S3JN54KHCE6MUA7LMQQQXW").unwrap(), ("abcdef".to_string(), data.to_vec()));
+
+        let (hrp, decoded) = bech32_decode(&bech32_encode("id", b"odd length").unwrap()).unwrap();
+        assert_eq!((hrp.as_str(), decoded.as_slice()), ("id", &b"odd length"[..]));
+    }
+
+    #[test]
+    fn bech32_rejects_corruption() {
+        assert_eq!(bech32_decode("a12uel5m"), Err(EncodingError::InvalidBech32));
+        assert_eq!(bech32_decode("A12uel5l"), Err(EncodingError::InvalidBech32));
+        assert_eq!(bech32_decode("a12uel5"), Err(EncodingError::InvalidBech32));
+        assert_eq!(bech32_decode("12uel5l"), Err(EncodingError::InvalidBech32));
+        assert_eq!(bech32_encode("Upper", b""), Err(EncodingError::InvalidBech32));
+    }
+
+    #[test]
+    fn pem_wraps_lines_and_checks_the_label() {
+        let der: Vec<u8> = (0..100).collect();
+        let pem = pem_encode("TEST DATA", &der);
+        let lines: Vec<&str> = pem.lines().collect();
+
+        assert_eq!(lines[0], "-----BEGIN TEST DATA-----");
+        assert_eq!(lines[1].len(), 64);
+        assert_eq!(lines[3].len(), 8);
+        assert_eq!(lines[4], "-----END TEST DATA-----");
+        assert_eq!(pem_decode("TEST DATA", &pem).unwrap(), der);
+        assert_eq!(pem_decode("TEST DATA", &format!("\r\n{}\n", pem.replace("\n", "\r\n"))).unwrap(), der);
+
+        assert_eq!(pem_decode("PUBLIC KEY", &pem), Err(EncodingError::InvalidPem));
+        assert_eq!(pem_decode("TEST DATA", &pem.replace("-----END", "----END")), Err(EncodingError::InvalidPem));
+        assert_eq!(pem_decode("TEST DATA", &pem.replace("AAEC", "AA*C")), Err(EncodingError::InvalidPem));
+        assert_eq!(pem_decode("TEST DATA", &format!("leading text\n{}", pem)), Err(EncodingError::InvalidPem));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,194 @@
+use std::fmt;
+use std::any::type_name;
+use std::sync::Mutex;
+
+use crate::utils::rng::CryptoRngCore;
+
+
+/*---- CONSTANTS ----*/
+
+/// Bytes drawn from the generator for one check
+const SAMPLE_LENGTH: usize = 4096;
+
+/// Repetition count cutoff from SP 800-90B 4.4.1, C = 1 + ceil(20 / H)
+/// for a false positive rate of 2^-20, assuming a conservative H = 1
+/// bit of min-entropy per byte
+const REPETITION_CUTOFF: usize = 21;
+
+/// Adaptive proportion window and cutoff from SP 800-90B 4.4.2 for
+/// non-binary samples, with the same H = 1 and false positive rate
+const PROPORTION_WINDOW: usize = 512;
+const PROPORTION_CUTOFF: usize = 311;
+
+/// Names of the generator types that have passed a check in this process
+static PASSED: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+
+/*---- ENUMS ----*/
+
+/// Health test that a generator's output failed
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum EntropyError {
+    RepetitionCount,        // the same byte was repeated too many times in a row
+    AdaptiveProportion      // one byte value made up too much of a window
+}
+
+impl fmt::Display for EntropyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EntropyError::RepetitionCount => write!(f, "RNG failed the repetition count test"),
+            EntropyError::AdaptiveProportion => write!(f, "RNG failed the adaptive proportion test")
+        }
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Runs the SP 800-90B repetition count and adaptive proportion tests
+/// over a few KB of the generator's output. These catch a generator
+/// that is stuck or badly biased, not one that is merely predictable
+/// 
+/// ### Arguments
+/// 
+/// * `rng` - Generator to check
+pub fn check(rng: &mut impl CryptoRngCore) -> Result<(), EntropyError> {
+    let mut sample = vec![0; SAMPLE_LENGTH];
+    rng.fill_bytes(&mut sample);
+
+    repetition_count(&sample)?;
+    adaptive_proportion(&sample)
+}
+
+/// Checks the generator before key generation. Each generator type is
+/// checked until it passes once, after which the process trusts it.
+/// Generators that opt out through `CryptoRngCore::health_tested`, such
+/// as seeded generators wrapped in `UncheckedRng`, are not checked
+/// 
+/// ### Arguments
+/// 
+/// * `rng` - Generator about to be used for key material
+pub fn check_cached<R: CryptoRngCore>(rng: &mut R) -> Result<(), EntropyError> {
+    if !rng.health_tested() {
+        return Ok(());
+    }
+
+    let name = type_name::<R>();
+
+    if PASSED.lock().unwrap().contains(&name) {
+        return Ok(());
+    }
+
+    check(rng)?;
+    PASSED.lock().unwrap().push(name);
+
+    Ok(())
+}
+
+/// Fails if any byte is repeated `REPETITION_CUTOFF` times in a row
+/// 
+/// ### Arguments
+/// 
+/// * `sample` - Generator output
+fn repetition_count(sample: &[u8]) -> Result<(), EntropyError> {
+    let mut run = 1;
+
+    for pair in sample.windows(2) {
+        run = if pair[0] == pair[1] { run + 1 } else { 1 };
+
+        if run >= REPETITION_CUTOFF {
+            return Err(EntropyError::RepetitionCount);
+        }
+    }
+
+    Ok(())
+}
+
+/// Fails if the first byte of any window appears `PROPORTION_CUTOFF`
+/// times within it
+/// 
+/// ### Arguments
+/// 
+/// * `sample` - Generator output
+fn adaptive_proportion(sample: &[u8]) -> Result<(), EntropyError> {
+    for window in sample.chunks(PROPORTION_WINDOW) {
+        let count = window.iter().filter(|byte| **byte == window[0]).count();
+
+        if count >= PROPORTION_CUTOFF {
+            return Err(EntropyError::AdaptiveProportion);
+        }
+    }
+
+    Ok(())
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod entropy_test {
+
+    use rand::{ Rng, ChaChaRng, SeedableRng };
+    use crate::utils::rng::{ CryptoRngCore, UncheckedRng, os_rng };
+    use crate::utils::entropy::{ check, check_cached, adaptive_proportion, EntropyError };
+
+    struct StuckRng;
+
+    impl Rng for StuckRng {
+        fn next_u32(&mut self) -> u32 {
+            0
+        }
+    }
+
+    impl CryptoRngCore for StuckRng {}
+
+    #[test]
+    fn working_generators_pass() {
+        assert_eq!(check(&mut os_rng().unwrap()), Ok(()));
+        assert_eq!(check(&mut ChaChaRng::from_seed(&[1, 2, 3, 4])), Ok(()));
+        assert_eq!(check_cached(&mut os_rng().unwrap()), Ok(()));
+    }
+
+    #[test]
+    fn stuck_generator_fails() {
+        assert_eq!(check(&mut StuckRng), Err(EntropyError::RepetitionCount));
+        assert_eq!(check_cached(&mut StuckRng), Err(EntropyError::RepetitionCount));
+        assert_eq!(check_cached(&mut UncheckedRng(StuckRng)), Ok(()));
+
+        // Biased but never repeating long enough for the first test
+        let biased: Vec<u8> = (0..512).map(|i| if i % 3 == 1 { i as u8 } else { 0xaa }).collect();
+        assert_eq!(adaptive_proportion(&biased), Err(EntropyError::AdaptiveProportion));
+    }
+
+    #[cfg(feature = "ecc")]
+    #[test]
+    fn key_generation_aborts_on_stuck_generator() {
+        use crate::utils::ecc::{ ECPKeypair, EcError };
+        use crate::utils::ecc_curves::ECPSupportedCurves;
+
+        let result = ECPKeypair::new(ECPSupportedCurves::SECP256R1).setup(&mut StuckRng);
+        assert_eq!(result.err(), Some(EcError::Entropy(EntropyError::RepetitionCount)));
+    }
+
+    #[cfg(feature = "rsa")]
+    #[test]
+    fn rsa_key_generation_aborts_on_stuck_generator() {
+        use num_bigint::BigUint;
+        use num_traits::FromPrimitive;
+        use crate::encryption::rsa::{ RSA, RsaError };
+        use crate::hash::crypto::HashAlgorithm;
+
+        let exponent = BigUint::from_u32(65537).unwrap();
+        let result = RSA::new(HashAlgorithm::Sha3_256, true).allow_insecure_for_testing().generate_keypair(512, &exponent, &mut StuckRng);
+        assert_eq!(result.err(), Some(RsaError::Entropy(EntropyError::RepetitionCount)));
+    }
+
+    #[cfg(feature = "dh")]
+    #[test]
+    fn dh_key_generation_aborts_on_stuck_generator() {
+        use crate::key_exchange::diffie_hellman::{ DiffieHellman, DhError };
+
+        let result = DiffieHellman::new().setup(64, &mut StuckRng);
+        assert_eq!(result.err(), Some(DhError::Entropy(EntropyError::RepetitionCount)));
+    }
+}
\ No newline at end of file
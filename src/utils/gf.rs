@@ -0,0 +1,241 @@
+//! Constant-time arithmetic in the binary fields used by the crate:
+//! GF(2^8) over the AES polynomial, as used by Shamir sharing, and
+//! GF(2^128) over the GCM polynomial on 16 byte blocks.
+//!
+//! GF(2^128) elements come in two bit orders. GHASH reflects the bits,
+//! so that the first bit of the block is the constant term, and
+//! `gf128_mul` and `gf128_xtime` follow it. CMAC and SIV subkeys are
+//! doubled in plain big-endian order with `gf128_double`. Both reduce by
+//! the same polynomial, x^128 + x^7 + x^2 + x + 1
+
+/*---- CONSTANTS ----*/
+
+/// Size in bytes of a GF(2^128) element
+pub const BLOCK_SIZE: usize = 16;
+
+/// Reduction polynomial of GF(2^8), x^8 + x^4 + x^3 + x + 1, without the x^8 term
+const GF256_POLYNOMIAL: u8 = 0x1b;
+
+/// Reduction of GF(2^128) in the reflected order of GHASH, folded into the first byte
+const GCM_R: u8 = 0xe1;
+
+/// Reduction of GF(2^128) in big-endian order, x^7 + x^2 + x + 1, folded into the last byte
+const DOUBLE_RB: u8 = 0x87;
+
+
+/*---- FUNCTIONS ----*/
+
+/// Multiplies two elements of GF(2^8), shifting and adding under masks
+/// instead of branching on the bits
+/// 
+/// ### Arguments
+/// 
+/// * `a` - First factor
+/// * `b` - Second factor
+pub fn gf256_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+
+    for _ in 0..8 {
+        product ^= a & 0u8.wrapping_sub(b & 1);
+
+        let carry = 0u8.wrapping_sub(a >> 7);
+        a = (a << 1) ^ (GF256_POLYNOMIAL & carry);
+        b >>= 1;
+    }
+
+    product
+}
+
+/// Inverts a non-zero element of GF(2^8) as a^254, with a fixed
+/// sequence of multiplications. Zero maps to zero
+/// 
+/// ### Arguments
+/// 
+/// * `a` - Element to invert
+pub fn gf256_inverse(a: u8) -> u8 {
+    let mut result = 1;
+    let mut power = a;
+
+    // 254 = 0b11111110
+    for bit in 0..8 {
+        let factor = if (254 >> bit) & 1 == 1 { power } else { 1 };
+        result = gf256_mul(result, factor);
+        power = gf256_mul(power, power);
+    }
+
+    result
+}
+
+/// Multiplies two GF(2^128) elements in the bit order of GHASH, adding
+/// under masks so the time taken does not depend on either factor
+/// 
+/// ### Arguments
+/// 
+/// * `x` - First factor
+/// * `y` - Second factor
+pub fn gf128_mul(x: &[u8; BLOCK_SIZE], y: &[u8; BLOCK_SIZE]) -> [u8; BLOCK_SIZE] {
+    let mut product = [0; BLOCK_SIZE];
+    let mut power = *y;
+
+    for bit in 0..BLOCK_SIZE * 8 {
+        let mask = 0u8.wrapping_sub((x[bit / 8] >> (7 - bit % 8)) & 1);
+
+        for (byte, value) in product.iter_mut().zip(power.iter()) {
+            *byte ^= value & mask;
+        }
+
+        power = gf128_xtime(&power);
+    }
+
+    product
+}
+
+/// Multiplies a GF(2^128) element by x in the bit order of GHASH, which
+/// is a right shift of the block, without branching on the bit shifted
+/// out
+/// 
+/// ### Arguments
+/// 
+/// * `block` - Element to multiply
+pub fn gf128_xtime(block: &[u8; BLOCK_SIZE]) -> [u8; BLOCK_SIZE] {
+    let mut shifted = [0; BLOCK_SIZE];
+    let carry = 0u8.wrapping_sub(block[BLOCK_SIZE - 1] & 1);
+
+    for i in 0..BLOCK_SIZE {
+        let previous = if i > 0 { block[i - 1] << 7 } else { 0 };
+        shifted[i] = (block[i] >> 1) | previous;
+    }
+
+    shifted[0] ^= GCM_R & carry;
+    shifted
+}
+
+/// Doubles a GF(2^128) element in big-endian order, as CMAC and SIV
+/// derive their subkeys, without branching on the secret top bit
+/// 
+/// ### Arguments
+/// 
+/// * `block` - Block to double, big-endian
+pub fn gf128_double(block: &[u8; BLOCK_SIZE]) -> [u8; BLOCK_SIZE] {
+    let mut doubled = [0; BLOCK_SIZE];
+    let carry = 0u8.wrapping_sub(block[0] >> 7);
+
+    for i in 0..BLOCK_SIZE {
+        let next = if i + 1 < BLOCK_SIZE { block[i + 1] >> 7 } else { 0 };
+        doubled[i] = (block[i] << 1) | next;
+    }
+
+    doubled[BLOCK_SIZE - 1] ^= DOUBLE_RB & carry;
+    doubled
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod gf_test {
+
+    use rand::{ Rng, ChaChaRng, SeedableRng };
+    use crate::utils::encoding::hex_to_bytes;
+    use crate::utils::gf::{ BLOCK_SIZE, gf256_mul, gf256_inverse, gf128_mul, gf128_xtime, gf128_double };
+
+    fn block(hex: &str) -> [u8; BLOCK_SIZE] {
+        let mut block = [0; BLOCK_SIZE];
+        block.copy_from_slice(&hex_to_bytes(hex).unwrap());
+        block
+    }
+
+    fn xor(a: &[u8; BLOCK_SIZE], b: &[u8; BLOCK_SIZE]) -> [u8; BLOCK_SIZE] {
+        let mut sum = *a;
+
+        for (byte, value) in sum.iter_mut().zip(b.iter()) {
+            *byte ^= value;
+        }
+
+        sum
+    }
+
+    /// AES S-box as the affine map of FIPS 197 5.1.1 over the inverse
+    fn sbox(a: u8) -> u8 {
+        let b = gf256_inverse(a);
+        b ^ b.rotate_left(1) ^ b.rotate_left(2) ^ b.rotate_left(3) ^ b.rotate_left(4) ^ 0x63
+    }
+
+    #[test]
+    fn gf256_inverses_are_correct() {
+        assert_eq!(gf256_mul(0x57, 0x83), 0xc1);
+        assert_eq!(gf256_mul(0x57, 0x13), 0xfe);
+        assert_eq!(gf256_inverse(0), 0);
+        assert_eq!(gf256_inverse(0x53), 0xca);
+
+        for a in 1..=255u8 {
+            assert_eq!(gf256_mul(a, gf256_inverse(a)), 1);
+        }
+    }
+
+    #[test]
+    fn gf256_matches_the_aes_sbox() {
+        assert_eq!(sbox(0x00), 0x63);
+        assert_eq!(sbox(0x01), 0x7c);
+        assert_eq!(sbox(0x53), 0xed);
+        assert_eq!(sbox(0xff), 0x16);
+    }
+
+    #[test]
+    fn gf256_is_distributive() {
+        for a in (0..=255u8).step_by(7) {
+            for b in (0..=255u8).step_by(5) {
+                for c in (0..=255u8).step_by(3) {
+                    assert_eq!(gf256_mul(a, b ^ c), gf256_mul(a, b) ^ gf256_mul(a, c));
+                    assert_eq!(gf256_mul(a, b), gf256_mul(b, a));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn gf128_matches_ghash_test_case_2() {
+        // GCM specification test case 2: zero key, one zero plaintext block
+        let h = block("66e94bd4ef8a2c3b884cfa59ca342b2e");
+        let c = block("0388dace60b6a392f328c2b971b2fe78");
+        let lengths = block("00000000000000000000000000000080");
+
+        let x1 = gf128_mul(&c, &h);
+        assert_eq!(x1, block("5e2ec746917062882c85b0685353deb7"));
+        assert_eq!(gf128_mul(&xor(&x1, &lengths), &h), block("f38cbb1ad69223dcc3457ae5b6b0f885"));
+    }
+
+    #[test]
+    fn gf128_xtime_and_double() {
+        let one = block("80000000000000000000000000000000");
+        let x = block("40000000000000000000000000000000");
+        let h = block("66e94bd4ef8a2c3b884cfa59ca342b2e");
+
+        assert_eq!(gf128_xtime(&block("80000000000000000000000000000001")), block("a1000000000000000000000000000000"));
+        assert_eq!(gf128_xtime(&h), gf128_mul(&h, &x));
+        assert_eq!(gf128_mul(&h, &one), h);
+
+        // RFC 4493 subkeys K1 and K2 from L
+        let l = block("7df76b0c1ab899b33e42f047b91b546f");
+        let k1 = gf128_double(&l);
+        assert_eq!(k1, block("fbeed618357133667c85e08f7236a8de"));
+        assert_eq!(gf128_double(&k1), block("f7ddac306ae266ccf90bc11ee46d513b"));
+    }
+
+    #[test]
+    fn gf128_is_distributive() {
+        let mut rng = ChaChaRng::from_seed(&[0x67, 0x66]);
+
+        for _ in 0..32 {
+            let mut a = [0; BLOCK_SIZE];
+            let mut b = [0; BLOCK_SIZE];
+            let mut c = [0; BLOCK_SIZE];
+            rng.fill_bytes(&mut a);
+            rng.fill_bytes(&mut b);
+            rng.fill_bytes(&mut c);
+
+            assert_eq!(gf128_mul(&a, &xor(&b, &c)), xor(&gf128_mul(&a, &b), &gf128_mul(&a, &c)));
+            assert_eq!(gf128_mul(&a, &b), gf128_mul(&b, &a));
+        }
+    }
+}
\ No newline at end of file
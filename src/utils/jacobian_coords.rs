@@ -6,14 +6,17 @@
 /// is significantly higher than field multiplications. In Jacobian 
 /// Coordinates the triple (X, Y, Z) represents the affine point (X / Z^2, Y / Z^3).
 
-use rand::OsRng;
 use std::ops::{ Shr, Shl, Mul, Sub, Add };
 
 use num_bigint::{ BigUint, BigInt, ToBigInt };
-use num_traits::{ One, Zero, Signed };
+use num_traits::{ One, Zero };
 
-use utils::primes;
-use utils::ecc_curves::{ ECPPoint, ECPGroup };
+use crate::utils::primes;
+use crate::utils::rng::CryptoRngCore;
+use crate::utils::ecc_curves::{ ECPPoint, ECPGroup };
+
+#[cfg(any(test, feature = "metrics"))]
+use crate::utils::metrics::{ self, Operation };
 
 
 /// For curves in short Weierstrass form, we do all the internal operations in
@@ -39,11 +42,11 @@ pub fn normalize_point(group: &ECPGroup, point: &ECPPoint) -> ECPPoint {
     // X = X / Z^2  mod p
     let z_i = primes::modular_inverse_int(&point.z, &group.p.to_bigint().unwrap());
     let zz_i = group.mod_p( &(&z_i * &z_i) );
-    new_point.x = group.mod_p( &(&point.x * &zz_i) );
+    new_point.x = group.reduce_signed( &(&point.x * &zz_i) );
 
     // Y = Y / Z^3  mod p
     let y_i = group.mod_p( &(&point.y.clone().unwrap() * &zz_i) );
-    new_point.y = Some( group.mod_p( &(&y_i * &z_i) ).abs() );
+    new_point.y = Some( group.reduce_signed( &(&y_i * &z_i) ) );
 
     // Z = 1
     new_point.z = BigInt::one();
@@ -51,17 +54,56 @@ pub fn normalize_point(group: &ECPGroup, point: &ECPPoint) -> ECPPoint {
     new_point
 }
 
-/// Normalize jacobian coordinates of an array of points. Original source
-/// code contains a potentially more efficient implementation, which is worth
-/// inspecting at a later date
+/// Normalize jacobian coordinates of an array of points,
+/// using Montgomery's trick to share a single inversion (GECC 2.26).
+/// Points with Z == 0 are left as they are
+/// Cost: 1N(t) := 1I + (6t - 3)M + 1S
 /// 
 /// ### Arguments
 /// 
 /// * `group` - Curve group to operate from
 /// * `points` - Vector of points to normalize
 pub fn normalize_many(group: &ECPGroup, points: &mut Vec<ECPPoint>) -> () {
-    for i in 0..points.len() {
-        points[i] = normalize_point(group, &points[i]);
+    let indices: Vec<usize> = (0..points.len()).filter(|i| points[*i].z != BigInt::zero()).collect();
+
+    if indices.is_empty() {
+        return;
+    }
+
+    // c[k] = Z_0 * ... * Z_k
+    let mut c = vec![points[indices[0]].z.clone()];
+
+    for k in 1..indices.len() {
+        let product = group.mod_p( &(&c[k - 1] * &points[indices[k]].z) );
+        c.push(product);
+    }
+
+    // u = 1 / (Z_0 * ... * Z_n) mod P
+    let mut u = primes::modular_inverse_int(&c[indices.len() - 1], &group.p.to_bigint().unwrap());
+
+    for k in (0..indices.len()).rev() {
+        let point = &mut points[indices[k]];
+
+        // z_i = 1 / Z_k and u = 1 / (Z_0 * ... * Z_{k-1}) mod P
+        let z_i = if k == 0 {
+            u.clone()
+        } else {
+            let z_i = group.mod_p( &(&u * &c[k - 1]) );
+            u = group.mod_p( &(&u * &point.z) );
+
+            z_i
+        };
+
+        // X = X / Z^2  mod p
+        let zz_i = group.mod_p( &(&z_i * &z_i) );
+        point.x = group.reduce_signed( &(&point.x * &zz_i) );
+
+        // Y = Y / Z^3  mod p
+        let y_i = group.mod_p( &(&point.y.clone().unwrap() * &zz_i) );
+        point.y = Some( group.reduce_signed( &(&y_i * &z_i) ) );
+
+        // Z = 1
+        point.z = BigInt::one();
     }
 }
 
@@ -100,6 +142,9 @@ pub fn invert_point(group: &ECPGroup, point: &ECPPoint) -> ECPPoint {
 /// * `R` - R point
 /// * `P` - P point
 pub fn double_point(group: &ECPGroup, P: &ECPPoint) -> ECPPoint {
+    #[cfg(any(test, feature = "metrics"))]
+    metrics::record(Operation::PointDoubling);
+
     let mut new_point = ECPPoint::new( &BigInt::zero(), Some(BigInt::zero()) );
     let mut T = BigInt::zero();
     let mut U = BigInt::zero();
@@ -158,7 +203,7 @@ pub fn double_point(group: &ECPGroup, P: &ECPPoint) -> ECPPoint {
 /// but those of P don't need to. R is not normalized.
 ///
 /// Special cases: (1) P or Q is zero, (2) R is zero, (3) P == Q.
-/// None of these cases can happen as intermediate step:
+/// None of these cases can happen as intermediate step of the comb method:
 /// 
 /// - at each step, P, Q and R are multiples of the base point, the factor
 ///   being less than its order, so none of them is zero;
@@ -166,7 +211,8 @@ pub fn double_point(group: &ECPGroup, P: &ECPPoint) -> ECPPoint {
 /// - Q is an odd multiple of the base point, P an even multiple,
 ///   due to the choice of precomputed points in the modified comb method.
 /// 
-/// So branches for these cases do not leak secret information.
+/// So branches for these cases do not leak secret information. They are
+/// still handled so that the function is correct for any input.
 ///
 /// Cost: 1A := 8M + 3S
 /// 
@@ -175,7 +221,7 @@ pub fn double_point(group: &ECPGroup, P: &ECPPoint) -> ECPPoint {
 /// * `group` - Curve group to operate from
 /// * `P` - P point
 /// * `Q` - Q point
-pub fn add(group: &ECPGroup, P: &ECPPoint, mut Q: &mut ECPPoint) -> ECPPoint {
+pub fn add(group: &ECPGroup, P: &ECPPoint, Q: &mut ECPPoint) -> ECPPoint {
     // Trivial cases: P == 0 or Q == 0 (case 1)
     if P.z == BigInt::zero() {
         return Q.clone();
@@ -185,10 +231,15 @@ pub fn add(group: &ECPGroup, P: &ECPPoint, mut Q: &mut ECPPoint) -> ECPPoint {
         return P.clone();
     }
 
+    #[cfg(any(test, feature = "metrics"))]
+    metrics::record(Operation::PointAddition);
+
     // Make sure Q coordinates are normalized
-    if Q.z != BigInt::one() {
-        normalize_point(group, &mut Q);
-    }
+    let Q = if Q.z != BigInt::one() {
+        normalize_point(group, Q)
+    } else {
+        Q.clone()
+    };
 
     let mut t_1 = group.mod_p( &P.z.clone().mul(&P.z) );
     let mut t_2 = group.mod_p( &t_1.clone().mul(&P.z) );
@@ -198,6 +249,18 @@ pub fn add(group: &ECPGroup, P: &ECPPoint, mut Q: &mut ECPPoint) -> ECPPoint {
     t_1 = group.mod_increase( &t_1.clone().sub(&P.x.clone()) );
     t_2 = group.mod_increase( &t_2.clone().sub(&P.y.clone().unwrap()) );
 
+    // Special cases (2) and (3)
+    if t_1 == BigInt::zero() {
+        if t_2 == BigInt::zero() {
+            return double_point(group, P);
+        }
+
+        let mut zero = P.clone();
+        zero.set_to_zero();
+
+        return zero;
+    }
+
     // All other cases
     let z = group.mod_p( &P.z.clone().mul(t_1.clone()) );
     let mut t_3 = group.mod_p( &t_1.clone().mul(t_1.clone()) );
@@ -239,10 +302,10 @@ pub fn add(group: &ECPGroup, P: &ECPPoint, mut Q: &mut ECPPoint) -> ECPPoint {
 /// * `group` - Curve group to operate from
 /// * `P` - point to randomize
 /// * `rng` - Random number generator
-pub fn randomize_point(group: &ECPGroup, P: &ECPPoint, mut rng: &mut OsRng) -> ECPPoint {
+pub fn randomize_point(group: &ECPGroup, P: &ECPPoint, rng: &mut impl CryptoRngCore) -> ECPPoint {
     let mut new_point = P.clone();
     let p_size = &group.p.bits();
-    let mut l = primes::generate(&p_size).to_bigint().unwrap();
+    let mut l = primes::generate_random_biguint(rng, &p_size).to_bigint().unwrap();
 
     // Generate l such that 1 < l < p
     if l >= group.p.to_bigint().unwrap() {
@@ -263,4 +326,65 @@ pub fn randomize_point(group: &ECPGroup, P: &ECPPoint, mut rng: &mut OsRng) -> E
     new_point.y = Some( group.mod_p( &P.y.clone().unwrap().mul(&l_cubed) ) );
 
     new_point
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod jacobian_coords_test {
+
+    use num_bigint::{ BigInt, ToBigInt };
+    use num_traits::{ One, Zero };
+    use crate::utils::jacobian_coords::{ normalize_point, normalize_many, double_point };
+    use crate::utils::ecc_curves::{ ECPGroup, ECPPoint, ECPSupportedCurves };
+
+    /// Represents an affine point in Jacobian coordinates with the given Z
+    fn with_z(point: &ECPPoint, z: &BigInt) -> ECPPoint {
+        let zz = z * z;
+
+        ECPPoint {
+            x: &point.x * &zz,
+            y: Some(point.y.clone().unwrap() * &zz * z),
+            z: z.clone()
+        }
+    }
+
+    #[test]
+    fn normalization_is_canonical_for_any_sign_of_z() {
+        let group = ECPGroup::new(ECPSupportedCurves::SECP256R1);
+        let p = group.p.to_bigint().unwrap();
+        let zs = vec![
+            BigInt::one(),
+            2.to_bigint().unwrap(),
+            &p - BigInt::one(),
+            -BigInt::one(),
+            -(3.to_bigint().unwrap())
+        ];
+
+        for z in &zs {
+            let normalized = normalize_point(&group, &with_z(&group.g, z));
+            assert_eq!((normalized.x, normalized.y), (group.g.x.clone(), group.g.y.clone()));
+        }
+
+        let mut points: Vec<ECPPoint> = zs.iter().map(|z| with_z(&group.g, z)).collect();
+        normalize_many(&group, &mut points);
+
+        for point in &points {
+            assert_eq!((&point.x, &point.y), (&group.g.x, &group.g.y));
+        }
+    }
+
+    #[test]
+    fn doubling_a_negative_representation_gives_the_same_point() {
+        let group = ECPGroup::new(ECPSupportedCurves::SECP256R1);
+        let expected = normalize_point(&group, &double_point(&group, &group.g));
+
+        for z in &[2.to_bigint().unwrap(), -BigInt::one(), -(2.to_bigint().unwrap())] {
+            let doubled = normalize_point(&group, &double_point(&group, &with_z(&group.g, z)));
+
+            assert_eq!((&doubled.x, &doubled.y), (&expected.x, &expected.y));
+            assert!(doubled.x >= BigInt::zero() && doubled.y.clone().unwrap() >= BigInt::zero());
+        }
+    }
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-use utils::encoding::{ int_to_binary, binary_to_int };
+use crate::utils::encoding::{ int_to_binary, binary_to_int };
 
 /// The Knuth-Yao sampling algorithm is an extremely useful scheme for 
 /// fast and efficient discrete Gaussian sampling. It is primarily based 
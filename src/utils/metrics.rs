@@ -0,0 +1,207 @@
+//! Per-thread operation counters for the arithmetic underneath the
+//! public primitives. They are bumped from the field helpers on
+//! ECPGroup, the point addition and doubling routines, the inversion
+//! and modpow wrappers in `primes`, the Montgomery contexts of
+//! `modarith`, and the comb method table builder, so that performance
+//! claims can be
+//! asserted as exact operation counts rather than flaky timings. Only
+//! compiled for tests and with the metrics feature
+
+use std::cell::Cell;
+
+
+/*---- STRUCTS ----*/
+
+/// Operation counts recorded on the current thread since the last reset
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Counters {
+    pub modmul: u64,            // field multiplications reduced mod p
+    pub inversions: u64,        // modular inversions
+    pub modpow: u64,            // modular exponentiations
+    pub point_additions: u64,   // Jacobian point additions
+    pub point_doublings: u64,   // Jacobian point doublings
+    pub table_precomputes: u64, // comb method tables built
+    pub montgomery_setups: u64  // Montgomery contexts prepared for a modulus
+}
+
+
+/*---- ENUMS ----*/
+
+/// Kinds of operation that can be recorded
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Operation {
+    ModMul,
+    Inversion,
+    ModPow,
+    PointAddition,
+    PointDoubling,
+    TablePrecompute,
+    MontgomerySetup
+}
+
+
+/*---- IMPLEMENTATIONS ----*/
+
+impl Counters {
+
+    /// Counts recorded between an earlier snapshot and this one
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `earlier` - Snapshot taken before the measured work
+    pub fn since(&self, earlier: &Counters) -> Counters {
+        Counters {
+            modmul: self.modmul - earlier.modmul,
+            inversions: self.inversions - earlier.inversions,
+            modpow: self.modpow - earlier.modpow,
+            point_additions: self.point_additions - earlier.point_additions,
+            point_doublings: self.point_doublings - earlier.point_doublings,
+            table_precomputes: self.table_precomputes - earlier.table_precomputes,
+            montgomery_setups: self.montgomery_setups - earlier.montgomery_setups
+        }
+    }
+}
+
+thread_local! {
+    static COUNTERS: Cell<Counters> = Cell::new(Counters::default());
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Sets every counter on the current thread back to zero
+pub fn reset() -> () {
+    COUNTERS.with(|counters| counters.set(Counters::default()));
+}
+
+/// Returns the counts recorded on the current thread
+pub fn snapshot() -> Counters {
+    COUNTERS.with(|counters| counters.get())
+}
+
+/// Records a single operation on the current thread
+/// 
+/// ### Arguments
+/// 
+/// * `operation` - Kind of operation performed
+pub fn record(operation: Operation) -> () {
+    COUNTERS.with(|counters| {
+        let mut current = counters.get();
+
+        match operation {
+            Operation::ModMul => current.modmul += 1,
+            Operation::Inversion => current.inversions += 1,
+            Operation::ModPow => current.modpow += 1,
+            Operation::PointAddition => current.point_additions += 1,
+            Operation::PointDoubling => current.point_doublings += 1,
+            Operation::TablePrecompute => current.table_precomputes += 1,
+            Operation::MontgomerySetup => current.montgomery_setups += 1
+        }
+
+        counters.set(current);
+    });
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod metrics_test {
+
+    use std::thread;
+    use num_bigint::BigUint;
+    use num_traits::FromPrimitive;
+    use crate::utils::primes;
+    use crate::utils::metrics::{ self, Operation };
+
+    #[test]
+    fn counters_reset_and_stay_on_their_thread() {
+        metrics::reset();
+        metrics::record(Operation::ModMul);
+        metrics::record(Operation::ModMul);
+        metrics::record(Operation::PointDoubling);
+
+        let counted = metrics::snapshot();
+        assert_eq!((counted.modmul, counted.point_doublings, counted.inversions), (2, 1, 0));
+
+        let other_thread = thread::spawn(|| metrics::snapshot()).join().unwrap();
+        assert_eq!(other_thread, metrics::Counters::default());
+
+        metrics::reset();
+        assert_eq!(metrics::snapshot(), metrics::Counters::default());
+    }
+
+    #[test]
+    fn prime_wrappers_are_counted() {
+        let modulus = BigUint::from_u32(23).unwrap();
+        let base = BigUint::from_u32(5).unwrap();
+        let before = metrics::snapshot();
+
+        primes::modpow(&base, &BigUint::from_u32(6).unwrap(), &modulus);
+        primes::modular_inverse(&base, &modulus);
+
+        let counted = metrics::snapshot().since(&before);
+        assert_eq!((counted.modpow, counted.inversions), (1, 1));
+    }
+
+    #[cfg(feature = "ecc")]
+    mod ecc_claims {
+
+        use num_bigint::{ BigInt, BigUint };
+        use num_traits::{ FromPrimitive, One };
+        use rand::{ ChaChaRng, SeedableRng };
+        use crate::utils::{ comb_method, jacobian_coords };
+        use crate::utils::ecc_curves::{ ECPGroup, ECPSupportedCurves };
+        use crate::utils::metrics;
+
+        #[test]
+        fn cached_generator_table_skips_precompute() {
+            let mut rng = ChaChaRng::from_seed(&[0x3e7, 0x1c5]);
+            let group = ECPGroup::new(ECPSupportedCurves::SECP256R1);
+            let m = BigUint::from_u64(0x1234_5678_9abc_def1).unwrap();
+
+            let before = metrics::snapshot();
+            let first = comb_method::multiply(&group, &m, &group.g, &mut rng);
+            let cold = metrics::snapshot().since(&before);
+
+            let before = metrics::snapshot();
+            let second = comb_method::multiply(&group, &m, &group.g, &mut rng);
+            let warm = metrics::snapshot().since(&before);
+
+            // With the table cached, the only doublings left are the ones
+            // in the comb loop itself: d = ceil(256 / 5) for a generator
+            // on a 256-bit curve
+            assert_eq!((first.x, first.y), (second.x, second.y));
+            assert_eq!(warm.point_doublings, 52);
+            assert!(cold.point_doublings > warm.point_doublings);
+            assert_eq!((cold.table_precomputes, warm.table_precomputes), (1, 0));
+        }
+
+        #[test]
+        fn batch_normalization_uses_one_inversion() {
+            let mut rng = ChaChaRng::from_seed(&[0xba7c, 0x4]);
+            let group = ECPGroup::new(ECPSupportedCurves::SECP256R1);
+            let mut points = Vec::new();
+            let mut point = group.g.clone();
+
+            for _ in 0..33 {
+                point = jacobian_coords::randomize_point(&group, &point, &mut rng);
+                points.push(point.clone());
+                point = jacobian_coords::double_point(&group, &point);
+            }
+
+            let expected: Vec<_> = points.iter().map(|p| jacobian_coords::normalize_point(&group, p)).collect();
+
+            let before = metrics::snapshot();
+            jacobian_coords::normalize_many(&group, &mut points);
+            let counted = metrics::snapshot().since(&before);
+
+            assert_eq!(counted.inversions, 1);
+
+            for (normalized, single) in points.iter().zip(expected.iter()) {
+                assert_eq!(normalized.z, BigInt::one());
+                assert_eq!((&normalized.x, &normalized.y), (&single.x, &single.y));
+            }
+        }
+    }
+}
\ No newline at end of file
@@ -1,9 +1,31 @@
+pub mod primes;
+pub mod modarith;
+pub mod encoding;
+pub mod transform;
+pub mod zeroize;
+pub mod rng;
+pub mod entropy;
+pub mod budget;
+pub mod ct;
+pub mod gf;
+pub mod nonce;
+
+#[cfg(any(test, feature = "metrics"))]
+pub mod metrics;
+
+#[cfg(feature = "ecc")]
 pub mod ecc;
-// pub mod ecc_curves;
-
-// pub mod primes;
-// pub mod encoding;
-// pub mod transform;
-// pub mod comb_method;
-// pub mod jacobian_coords;
-// pub mod montgomery_ladder;
\ No newline at end of file
+#[cfg(feature = "ecc")]
+pub mod ecc_curves;
+#[cfg(feature = "ecc")]
+pub mod comb_method;
+#[cfg(feature = "ecc")]
+pub mod jacobian_coords;
+#[cfg(feature = "ecc")]
+pub mod montgomery_ladder;
+
+#[cfg(feature = "ring-lwe")]
+pub mod knuth_yao;
+
+#[cfg(feature = "serde")]
+pub mod serde_hex;
\ No newline at end of file
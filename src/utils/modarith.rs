@@ -0,0 +1,356 @@
+//! Modular exponentiation in Montgomery form, for odd moduli such as RSA
+//! primes and moduli and Diffie-Hellman primes.
+//!
+//! Values are kept as little-endian 32-bit limbs of a fixed length, and
+//! multiplied with interleaved Montgomery reduction (CIOS), so that no
+//! step of an exponentiation divides by the modulus. The exponent is
+//! walked with a sliding window over a table of odd powers of the base.
+//! The window pattern follows the exponent bits, so the time taken is
+//! not independent of the exponent; RSA blinds its private exponents
+//! for that reason
+
+use num_bigint::BigUint;
+use num_integer::Integer;
+use num_traits::{ One, Zero };
+
+use crate::utils::zeroize::zeroize_biguint;
+#[cfg(any(test, feature = "metrics"))]
+use crate::utils::metrics::{ self, Operation };
+
+
+/*---- CONSTANTS ----*/
+
+/// Bits per limb
+const LIMB_BITS: usize = 32;
+
+
+/*---- STRUCTS ----*/
+
+/// Precomputed values for multiplying modulo one odd modulus. Preparing
+/// them takes two divisions by the modulus, so a context is worth
+/// keeping for a modulus that is used again and again
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Montgomery {
+    modulus: BigUint,       // modulus N
+    limbs: Vec<u32>,        // N as little-endian limbs
+    n0_inv: u32,            // -N^-1 mod 2^32
+    one: Vec<u32>,          // R mod N, one in Montgomery form
+    r_squared: Vec<u32>     // R^2 mod N, for converting into Montgomery form
+}
+
+
+/*---- IMPLEMENTATIONS ----*/
+
+impl Montgomery {
+
+    /// Prepares a modulus. Returns None for even moduli and moduli
+    /// below 3, which Montgomery reduction can't handle
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `modulus` - Odd modulus to work under
+    pub fn new(modulus: &BigUint) -> Option<Self> {
+        if modulus.is_even() || modulus <= &BigUint::one() {
+            return None;
+        }
+
+        #[cfg(any(test, feature = "metrics"))]
+        metrics::record(Operation::MontgomerySetup);
+
+        let length = (modulus.bits() + LIMB_BITS - 1) / LIMB_BITS;
+        let limbs = to_limbs(modulus, length);
+        let r = BigUint::one() << (length * LIMB_BITS);
+        let r_mod = &r % modulus;
+        let r_squared = (&r_mod * &r_mod) % modulus;
+
+        Some(Montgomery {
+            modulus: modulus.clone(),
+            n0_inv: negated_inverse(limbs[0]),
+            limbs: limbs,
+            one: to_limbs(&r_mod, length),
+            r_squared: to_limbs(&r_squared, length)
+        })
+    }
+
+    /// Modulus the context works under
+    pub fn modulus(&self) -> &BigUint {
+        &self.modulus
+    }
+
+    /// Overwrites the modulus and every value derived from it, for
+    /// contexts of secret moduli such as RSA primes
+    pub fn clear(&mut self) -> () {
+        zeroize_biguint(&mut self.modulus);
+        wipe(&mut self.limbs);
+        wipe(&mut self.one);
+        wipe(&mut self.r_squared);
+        self.n0_inv = 0;
+    }
+
+    /// Computes base^exponent mod N
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `base` - Value to raise, of any size
+    /// * `exponent` - Exponent to raise to
+    pub fn modpow(&self, base: &BigUint, exponent: &BigUint) -> BigUint {
+        let exponent_bytes = exponent.to_bytes_le();
+        let bit_count = exponent.bits();
+        let bit = |index: usize| (exponent_bytes[index / 8] >> (index % 8)) & 1 == 1;
+
+        let reduced = base % &self.modulus;
+        let base_form = self.mul(&to_limbs(&reduced, self.limbs.len()), &self.r_squared);
+        let mut table = self.odd_powers(&base_form, window_size(bit_count));
+        let mut result = self.one.clone();
+        let mut index = bit_count;
+
+        while index > 0 {
+            if !bit(index - 1) {
+                result = self.mul(&result, &result);
+                index -= 1;
+                continue;
+            }
+
+            // Longest window ending in a set bit that fits in the table
+            let mut low = index.saturating_sub(window_size(bit_count));
+
+            while !bit(low) {
+                low += 1;
+            }
+
+            let mut value = 0;
+
+            for position in (low..index).rev() {
+                result = self.mul(&result, &result);
+                value = (value << 1) | bit(position) as usize;
+            }
+
+            result = self.mul(&result, &table[value >> 1]);
+            index = low;
+        }
+
+        let mut unit = vec![0; self.limbs.len()];
+        unit[0] = 1;
+        let output = from_limbs(&self.mul(&result, &unit));
+
+        for power in table.iter_mut() {
+            wipe(power);
+        }
+
+        wipe(&mut result);
+        output
+    }
+
+    /// Odd powers b, b^3, b^5, ... of a value in Montgomery form, as
+    /// many as a window of the given size can select
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `base` - Base in Montgomery form
+    /// * `window` - Window size in bits
+    fn odd_powers(&self, base: &[u32], window: usize) -> Vec<Vec<u32>> {
+        let mut powers = vec![base.to_vec()];
+        let square = self.mul(base, base);
+
+        for i in 1..1 << (window - 1) {
+            let next = self.mul(&powers[i - 1], &square);
+            powers.push(next);
+        }
+
+        powers
+    }
+
+    /// Montgomery product a * b * R^-1 mod N of two values below N,
+    /// with the final subtraction made under a mask
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `a` - First factor, in limbs
+    /// * `b` - Second factor, in limbs
+    fn mul(&self, a: &[u32], b: &[u32]) -> Vec<u32> {
+        let n = &self.limbs;
+        let s = n.len();
+        let mut t = vec![0u32; s + 2];
+
+        for i in 0..s {
+            let mut carry = 0u64;
+
+            for j in 0..s {
+                let sum = t[j] as u64 + a[j] as u64 * b[i] as u64 + carry;
+                t[j] = sum as u32;
+                carry = sum >> LIMB_BITS;
+            }
+
+            let sum = t[s] as u64 + carry;
+            t[s] = sum as u32;
+            t[s + 1] = (sum >> LIMB_BITS) as u32;
+
+            // Add m * N so the lowest limb cancels, then shift down a limb
+            let m = t[0].wrapping_mul(self.n0_inv);
+            let mut carry = (t[0] as u64 + m as u64 * n[0] as u64) >> LIMB_BITS;
+
+            for j in 1..s {
+                let sum = t[j] as u64 + m as u64 * n[j] as u64 + carry;
+                t[j - 1] = sum as u32;
+                carry = sum >> LIMB_BITS;
+            }
+
+            let sum = t[s] as u64 + carry;
+            t[s - 1] = sum as u32;
+            t[s] = t[s + 1] + (sum >> LIMB_BITS) as u32;
+        }
+
+        // t < 2N, so one subtraction of N brings it below N
+        let mut reduced = vec![0u32; s];
+        let mut borrow = 0u64;
+
+        for j in 0..s {
+            let difference = (t[j] as u64).wrapping_sub(n[j] as u64).wrapping_sub(borrow);
+            reduced[j] = difference as u32;
+            borrow = (difference >> 63) & 1;
+        }
+
+        // Keep t only when it has no top limb and subtracting borrowed
+        let keep = 0u32.wrapping_sub((borrow as u32) & (t[s] == 0) as u32);
+
+        for j in 0..s {
+            reduced[j] = (t[j] & keep) | (reduced[j] & !keep);
+        }
+
+        wipe(&mut t);
+        reduced
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Window size for an exponent of the given length, trading the size of
+/// the table of odd powers against the multiplications it saves
+/// 
+/// ### Arguments
+/// 
+/// * `bits` - Bit length of the exponent
+fn window_size(bits: usize) -> usize {
+    match bits {
+        0..=23 => 1,
+        24..=79 => 3,
+        80..=239 => 4,
+        240..=671 => 5,
+        _ => 6
+    }
+}
+
+/// -n^-1 mod 2^32 for an odd limb, by Newton iteration. Each step
+/// doubles the number of correct low bits, starting from 1
+/// 
+/// ### Arguments
+/// 
+/// * `n` - Lowest limb of the modulus
+fn negated_inverse(n: u32) -> u32 {
+    let mut inverse = 1u32;
+
+    for _ in 0..5 {
+        inverse = inverse.wrapping_mul(2u32.wrapping_sub(n.wrapping_mul(inverse)));
+    }
+
+    inverse.wrapping_neg()
+}
+
+/// Splits a value below 2^(32 * length) into little-endian limbs
+/// 
+/// ### Arguments
+/// 
+/// * `value` - Value to split
+/// * `length` - Number of limbs
+fn to_limbs(value: &BigUint, length: usize) -> Vec<u32> {
+    let mut limbs = vec![0u32; length];
+
+    if value.is_zero() {
+        return limbs;
+    }
+
+    for (i, byte) in value.to_bytes_le().iter().enumerate() {
+        limbs[i / 4] |= (*byte as u32) << (8 * (i % 4));
+    }
+
+    limbs
+}
+
+/// Joins little-endian limbs back into a value
+/// 
+/// ### Arguments
+/// 
+/// * `limbs` - Limbs to join
+fn from_limbs(limbs: &[u32]) -> BigUint {
+    let bytes: Vec<u8> = limbs.iter().flat_map(|limb| limb.to_le_bytes().to_vec()).collect();
+
+    BigUint::from_bytes_le(&bytes)
+}
+
+/// Overwrites limbs that held intermediate values
+/// 
+/// ### Arguments
+/// 
+/// * `limbs` - Limbs to clear
+fn wipe(limbs: &mut [u32]) -> () {
+    for limb in limbs.iter_mut() {
+        *limb = 0;
+    }
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod modarith_test {
+
+    use rand::{ ChaChaRng, SeedableRng };
+    use num_bigint::{ BigUint, RandBigInt };
+    use num_traits::{ FromPrimitive, One, Zero };
+    use crate::utils::modarith::{ Montgomery, negated_inverse };
+
+    #[test]
+    fn matches_biguint_modpow() {
+        let mut rng = ChaChaRng::from_seed(&[0x6d, 0x6f, 0x6e]);
+
+        for round in 0..300 {
+            let bits = [16, 31, 32, 33, 64, 100, 256, 521, 1024][round % 9];
+            let modulus = rng.gen_biguint(bits) | BigUint::one();
+
+            if modulus <= BigUint::one() {
+                continue;
+            }
+
+            let context = Montgomery::new(&modulus).unwrap();
+            let base = rng.gen_biguint(bits + 8);
+            let exponent = rng.gen_biguint([1, 8, 17, 64, 300, 1024][round % 6]);
+
+            assert_eq!(context.modpow(&base, &exponent), base.modpow(&exponent, &modulus), "round {}", round);
+        }
+    }
+
+    #[test]
+    fn edge_values() {
+        let modulus = BigUint::from_u64(0xffff_ffff_ffff_ffc5).unwrap();
+        let context = Montgomery::new(&modulus).unwrap();
+        let top = &modulus - BigUint::one();
+
+        assert_eq!(context.modpow(&BigUint::from_u32(5).unwrap(), &BigUint::zero()), BigUint::one());
+        assert_eq!(context.modpow(&BigUint::zero(), &BigUint::from_u32(5).unwrap()), BigUint::zero());
+        assert_eq!(context.modpow(&modulus, &BigUint::one()), BigUint::zero());
+        assert_eq!(context.modpow(&top, &BigUint::from_u32(2).unwrap()), BigUint::one());
+        assert_eq!(context.modpow(&top, &BigUint::from_u32(3).unwrap()), top);
+
+        assert!(Montgomery::new(&BigUint::from_u32(10).unwrap()).is_none());
+        assert!(Montgomery::new(&BigUint::one()).is_none());
+        assert!(Montgomery::new(&BigUint::zero()).is_none());
+    }
+
+    #[test]
+    fn limb_inverse() {
+        for n in &[1u32, 3, 0xffff_ffff, 0x1234_5677] {
+            assert_eq!(n.wrapping_mul(negated_inverse(*n)), u32::max_value());
+        }
+    }
+}
\ No newline at end of file
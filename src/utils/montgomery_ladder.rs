@@ -7,15 +7,15 @@
 
 #[allow(non_camel_case_types)]
 
-use rand::OsRng;
+use std::cmp;
 use std::ops::Shr;
 
-use num_traits::{ One, Zero, Signed, ToPrimitive };
-use num_bigint::{ BigUint, BigInt, ToBigInt };
+use num_traits::{ One, Zero, ToPrimitive };
+use num_bigint::{ BigUint, BigInt, ToBigInt, ToBigUint };
 
-use utils::primes;
-use utils::ecc_curves::{ ECPGroup, ECPPoint };
-use utils::encoding::{ EndianOrdering, biguint_to_bitvec };
+use crate::utils::primes;
+use crate::utils::rng::CryptoRngCore;
+use crate::utils::ecc_curves::{ ECPGroup, ECPPoint };
 
 
 /// Multiplication with Montgomery ladder in x/z coordinates,
@@ -28,76 +28,79 @@ use utils::encoding::{ EndianOrdering, biguint_to_bitvec };
 /// attack can be found here: 
 /// https://link.springer.com/chapter/10.1007%2F978-3-662-44709-3_5
 /// 
+/// The ladder itself follows section 5 of RFC 7748, so that the
+/// output for Curve25519 is exactly that of the X25519 function. 
+/// The number of ladder steps depends only on the size of the 
+/// field, never on the value of the scalar.
+/// 
 /// ### Arguments
 /// 
 /// * `group` - Curve group to operate from
 /// * `m` - M value in calculation
 /// * `P` - P point in calculation
-/// * `rng` - Random number generator
-pub fn multiply(group: &ECPGroup, m: &BigUint, P: &ECPPoint) -> ECPPoint {
+/// * `rng` - Random number generator, used to randomize the projective coordinates
+pub fn multiply(group: &ECPGroup, m: &BigUint, P: &ECPPoint, rng: &mut impl CryptoRngCore) -> ECPPoint {
+    let p = group.p.to_biguint().unwrap();
+    let two = 2.to_biguint().unwrap();
+
+    // group.a holds A for Montgomery curves, the ladder needs (A - 2) / 4
+    let a24 = (group.a.to_biguint().unwrap() - &two) >> 2;
 
     // Save PX and read from P before writing to R, in case P == R
-    let PX = P.x.clone();
-    let mut RP = P.clone();
+    let x_1 = P.x.to_biguint().unwrap() % &p;
 
-    // Set R to double the generator P
-    let mut R = double_point(group, &RP);
-    let mut rng = OsRng::new().unwrap();
-    R = randomize_point(group, &R, &mut rng);
+    // Loop invariant: R = result so far, RP = R + P. R starts at 
+    // the point at infinity (1 : 0) and RP at a randomized P
+    let l = random_field_element(&p, rng);
+    let mut R = (BigUint::one(), BigUint::zero());
+    let mut RP = ((&x_1 * &l) % &p, l);
 
-    // Loop invariant: R = result so far, RP = R + P
-    let mut i = m.bits();
-    let mut point_selection = vec![R.clone(), RP.clone()];
-    let m_bit_vec = biguint_to_bitvec(&m, EndianOrdering::Little);
+    let mut swap = 0;
+    let mut i = cmp::max(p.bits(), m.bits());
 
     // Using pure arithmetic (vs conditional branching) avoids traditional
     // M-fault and flush + reload attacks
     while i > 0 {
-        let d_i = ((m.clone() >> i) & BigUint::one()).to_usize().unwrap();
-        point_selection[(d_i + 1) % 2] = add_points(group, &R, &RP, &PX);
-        point_selection[d_i] = double_point(group, &point_selection[d_i]);
+        i -= 1;
 
-        i >>= 1;
+        let d_i = ((m.clone() >> i) & BigUint::one()).to_usize().unwrap();
+        swap ^= d_i;
+
+        let point_selection = [R, RP];
+        R = point_selection[swap].clone();
+        RP = point_selection[1 - swap].clone();
+        swap = d_i;
+
+        let a = (&R.0 + &R.1) % &p;
+        let aa = (&a * &a) % &p;
+        let b = (&R.0 + &p - &R.1) % &p;
+        let bb = (&b * &b) % &p;
+        let e = (&aa + &p - &bb) % &p;
+        let c = (&RP.0 + &RP.1) % &p;
+        let d = (&RP.0 + &p - &RP.1) % &p;
+        let da = (&d * &a) % &p;
+        let cb = (&c * &b) % &p;
+
+        let sum = (&da + &cb) % &p;
+        let difference = (&da + &p - &cb) % &p;
+
+        RP = ((&sum * &sum) % &p, (&x_1 * &difference * &difference) % &p);
+        R = ((&aa * &bb) % &p, (&e * ((&aa + &a24 * &e) % &p)) % &p);
     }
 
-    let invert_z = invert(group, &point_selection[0].z);
-    let final_value = (&point_selection[0].x * invert_z.to_bigint().unwrap()) % group.p.clone();
-    let final_point = ECPPoint::new( &final_value, None );
+    let point_selection = [R, RP];
+    let R = point_selection[swap].clone();
 
-    normalize_point(group, &final_point)
-}
+    // Normalize: X = X / Z, Z = 1. Z = 0 (point at infinity) maps to 0
+    let invert_z = primes::modpow(&R.1, &(&p - &two), &p);
+    let final_value = (&R.0 * &invert_z) % &p;
 
-/// Invert coordinate using P modulus
-/// 
-/// ### Arguments
-/// 
-/// * `group` - Curve group to operate from
-/// * `coordinate` - Coordinate to invert
-fn invert(group: &ECPGroup, coordinate: &BigInt) -> BigInt {
-    let exponent = (group.p.clone() - 2.to_bigint().unwrap()).to_biguint().unwrap();
-    let uint_modulus = group.p.to_biguint().unwrap();
-
-    coordinate.to_biguint().unwrap().modpow(&exponent, &uint_modulus).to_bigint().unwrap()
+    ECPPoint::new( &final_value.to_bigint().unwrap(), None )
 }
 
-/// Normalize Montgomery x/z coordinates: X = X/Z, Z = 1
-/// 
-/// ### Arguments
-/// 
-/// * `group` - Curve group to operate from
-/// * `point` - Point to normalize
-fn normalize_point(group: &ECPGroup, point: &ECPPoint) -> ECPPoint {
-    let mut new_point = point.clone();
-
-    new_point.z = primes::modular_inverse_int(&point.z, &group.p.to_bigint().unwrap());
-    new_point.x = group.mod_p( &(&point.x * &point.z) ).abs();
-    new_point.z = BigInt::one();
-
-    new_point
-}
-
-/// Randomize projective x/z coordinates:
-/// (X, Z) -> (l X, l Z) for random l
+/// Generates a random field element l such that 1 <= l < p,
+/// used to randomize projective x/z coordinates:
+/// (X, Z) -> (l X, l Z)
 ///
 /// This countermeasure was first suggested in:
 /// CORON, Jean-S'ebastien. Resistance against differential power analysis
@@ -107,41 +110,20 @@ fn normalize_point(group: &ECPGroup, point: &ECPPoint) -> ECPPoint {
 /// 
 /// ### Arguments
 /// 
-/// * `group` - Curve group to operate from
-/// * `point` - Point to randomize
+/// * `p` - Prime modulus of the field
 /// * `rng` - Random number generator
-fn randomize_point(group: &ECPGroup, point: &ECPPoint, mut rng: &mut OsRng) -> ECPPoint {
-    let mut new_point = point.clone();
-    let mut l = primes::generate_random_biguint(&mut rng, &group.p.bits()).to_bigint().unwrap();
+fn random_field_element(p: &BigUint, rng: &mut impl CryptoRngCore) -> BigUint {
+    let mut l = BigUint::zero();
 
-    if l >= group.p {
-        while l >= group.p {
+    while l.is_zero() || &l >= p {
+        l = primes::generate_random_biguint(rng, &p.bits());
+
+        while &l >= p {
             l = l.shr(1);
         }
     }
 
-    new_point.x = group.mod_p( &(&point.x * &l) );
-    new_point.z = group.mod_p( &(&point.z * &l) );
-
-    new_point
-}
-
-/// Doubles a point on a Montgomery curve
-/// 
-/// ### Arguments
-///  
-/// * `group` - Curve group to operate from
-/// * `point` - Point to double
-fn double_point(group: &ECPGroup, point: &ECPPoint) -> ECPPoint {
-    let mut new_point = ECPPoint::new( &BigInt::zero(), None );
-    let x_squared = &point.x * &point.x;
-    let z_squared = &point.z * &point.z;
-
-    // TODO: Split z change out into 2 calculations
-    new_point.x = ((&x_squared - &z_squared) * (&x_squared - &z_squared)) % group.p.clone();
-    new_point.z = (&4.to_bigint().unwrap() * &point.x * &point.z * (&x_squared + &group.a.to_bigint().unwrap() * &point.x * &point.z + &z_squared)) % group.p.clone();
-
-    new_point
+    l
 }
 
 /// Adds two points on a Montgomery curves together
@@ -157,8 +139,8 @@ pub fn add_points(group: &ECPGroup, first: &ECPPoint, second: &ECPPoint, gx: &Bi
     let x_mult = (&second.x * &first.x - &second.z * &first.z);
     let z_mult = (&second.x * &first.z - &second.z * &first.x);
 
-    new_point.x = (&4.to_bigint().unwrap() * &x_mult * &x_mult) % group.p.clone();
-    new_point.z = (&4.to_bigint().unwrap() * &z_mult * &z_mult * gx) % group.p.clone();
+    new_point.x = group.reduce_signed( &(&4.to_bigint().unwrap() * &x_mult * &x_mult) );
+    new_point.z = group.reduce_signed( &(&4.to_bigint().unwrap() * &z_mult * &z_mult * gx) );
 
     new_point
 }
@@ -0,0 +1,340 @@
+//! Sequences of unique 96-bit nonces for AEAD ciphers, shared by every
+//! user of one key.
+//!
+//! Counter sequences put a fixed 4 byte prefix in front of a 64-bit
+//! big-endian counter, as in RFC 5116 3.2, and never repeat. Random
+//! sequences draw each nonce from a ChaCha generator seeded from the
+//! caller's RNG, for senders that share a key without coordinating,
+//! and stop at 2^32 nonces so the chance of a repeat stays below 2^-32
+//! (NIST SP 800-38D 8.3). Neither kind ever wraps around; `next` fails
+//! once the sequence is used up and the key has to be replaced.
+
+use std::fmt;
+use rand::{ Rng, ChaChaRng, SeedableRng };
+
+use crate::utils::rng::CryptoRngCore;
+
+
+/*---- CONSTANTS ----*/
+
+/// Length in bytes of a nonce
+pub const NONCE_LENGTH: usize = 12;
+
+/// Length in bytes of the fixed prefix of a counter nonce
+pub const PREFIX_LENGTH: usize = 4;
+
+/// Number of nonces a counter sequence hands out. The last counter
+/// value is never used, so a cleared sequence can be marked by it
+pub const COUNTER_NONCE_LIMIT: u64 = u64::max_value();
+
+/// Number of nonces a random sequence hands out
+pub const RANDOM_NONCE_LIMIT: u64 = 1 << 32;
+
+/// Length in bytes of a saved sequence: kind, prefix and nonces used
+const STATE_LENGTH: usize = 1 + PREFIX_LENGTH + 8;
+
+/// Kinds of sequence in a saved state
+const KIND_COUNTER: u8 = 0;
+const KIND_RANDOM: u8 = 1;
+
+/// Number of 32-bit words in the seed of a random sequence
+const SEED_WORDS: usize = 8;
+
+
+/*---- ENUMS ----*/
+
+/// Errors raised by a nonce sequence
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum NonceError {
+    Exhausted,          // every nonce of the sequence has been used
+    MalformedState      // saved state could not be read
+}
+
+impl fmt::Display for NonceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NonceError::Exhausted => write!(f, "Nonce sequence is exhausted, the key must be replaced"),
+            NonceError::MalformedState => write!(f, "Saved nonce sequence is malformed")
+        }
+    }
+}
+
+/// Source of the nonces of a sequence
+enum Source {
+    Counter([u8; PREFIX_LENGTH]),   // fixed prefix in front of the counter
+    Random(ChaChaRng)               // generator seeded from the caller's RNG
+}
+
+
+/*---- STRUCTS ----*/
+
+/// Hands out unique nonces for one key
+pub struct NonceSequence {
+    source: Source,     // how nonces are made
+    used: u64           // nonces handed out so far, the next counter value
+}
+
+
+/*---- IMPLEMENTATIONS ----*/
+
+impl NonceSequence {
+
+    /// Sequence of counter nonces, starting from zero
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `prefix` - Fixed field in front of the counter, distinct per sender of the key
+    pub fn counter(prefix: [u8; PREFIX_LENGTH]) -> Self {
+        NonceSequence { source: Source::Counter(prefix), used: 0 }
+    }
+
+    /// Sequence of random nonces
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `rng` - Random number generator to seed the sequence from
+    pub fn random(rng: &mut impl CryptoRngCore) -> Self {
+        NonceSequence { source: Source::Random(seeded(rng)), used: 0 }
+    }
+
+    /// Next unused nonce
+    pub fn next(&mut self) -> Result<[u8; NONCE_LENGTH], NonceError> {
+        if self.used >= self.limit() {
+            return Err(NonceError::Exhausted);
+        }
+
+        let nonce = match self.source {
+            Source::Counter(ref prefix) => counter_nonce(prefix, self.used),
+            Source::Random(ref mut rng) => {
+                let mut nonce = [0; NONCE_LENGTH];
+                rng.fill_bytes(&mut nonce);
+                nonce
+            }
+        };
+
+        self.used += 1;
+        Ok(nonce)
+    }
+
+    /// Number of nonces handed out so far. For a counter sequence this
+    /// is also the counter of the next nonce
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
+    /// Number of nonces left before the sequence is exhausted
+    pub fn remaining(&self) -> u64 {
+        self.limit() - self.used.min(self.limit())
+    }
+
+    /// Uses up the sequence, so that `next` fails from now on
+    pub fn exhaust(&mut self) -> () {
+        self.used = self.limit();
+    }
+
+    /// Saves the position of the sequence, so that a resumed session
+    /// carries on where it stopped instead of reusing nonces. The
+    /// generator of a random sequence is not saved, only its count
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(STATE_LENGTH);
+
+        match self.source {
+            Source::Counter(ref prefix) => {
+                state.push(KIND_COUNTER);
+                state.extend_from_slice(prefix);
+            },
+            Source::Random(_) => {
+                state.push(KIND_RANDOM);
+                state.extend_from_slice(&[0; PREFIX_LENGTH]);
+            }
+        }
+
+        state.extend_from_slice(&self.used.to_be_bytes());
+        state
+    }
+
+    /// Resumes a sequence saved by `to_bytes`. A random sequence is
+    /// reseeded from the RNG and keeps its count towards the limit
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `state` - Saved sequence
+    /// * `rng` - Random number generator to reseed a random sequence from
+    pub fn from_bytes(state: &[u8], rng: &mut impl CryptoRngCore) -> Result<Self, NonceError> {
+        if state.len() != STATE_LENGTH {
+            return Err(NonceError::MalformedState);
+        }
+
+        let mut prefix = [0; PREFIX_LENGTH];
+        let mut used = [0; 8];
+        prefix.copy_from_slice(&state[1..1 + PREFIX_LENGTH]);
+        used.copy_from_slice(&state[1 + PREFIX_LENGTH..]);
+
+        let source = match state[0] {
+            KIND_COUNTER => Source::Counter(prefix),
+            KIND_RANDOM if prefix == [0; PREFIX_LENGTH] => Source::Random(seeded(rng)),
+            _ => return Err(NonceError::MalformedState)
+        };
+
+        let sequence = NonceSequence { source: source, used: u64::from_be_bytes(used) };
+
+        if sequence.used > sequence.limit() {
+            return Err(NonceError::MalformedState);
+        }
+
+        Ok(sequence)
+    }
+
+    /// Number of nonces the sequence hands out in total
+    fn limit(&self) -> u64 {
+        match self.source {
+            Source::Counter(_) => COUNTER_NONCE_LIMIT,
+            Source::Random(_) => RANDOM_NONCE_LIMIT
+        }
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Counter nonce: the prefix followed by the big-endian counter. Used by
+/// receivers to rebuild the nonce of a counter the sender put on the wire
+/// 
+/// ### Arguments
+/// 
+/// * `prefix` - Fixed field in front of the counter
+/// * `counter` - Counter value
+pub fn counter_nonce(prefix: &[u8; PREFIX_LENGTH], counter: u64) -> [u8; NONCE_LENGTH] {
+    let mut nonce = [0; NONCE_LENGTH];
+    nonce[..PREFIX_LENGTH].copy_from_slice(prefix);
+    nonce[PREFIX_LENGTH..].copy_from_slice(&counter.to_be_bytes());
+
+    nonce
+}
+
+/// ChaCha generator seeded from the caller's RNG
+/// 
+/// ### Arguments
+/// 
+/// * `rng` - Random number generator to draw the seed from
+fn seeded(rng: &mut impl CryptoRngCore) -> ChaChaRng {
+    let mut seed = [0u32; SEED_WORDS];
+
+    for word in seed.iter_mut() {
+        *word = rng.next_u32();
+    }
+
+    let generator = ChaChaRng::from_seed(&seed[..]);
+
+    for word in seed.iter_mut() {
+        *word = 0;
+    }
+
+    generator
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod nonce_test {
+
+    use std::collections::HashSet;
+    use rand::{ ChaChaRng, SeedableRng };
+    use crate::utils::nonce::{ NonceSequence, NonceError, COUNTER_NONCE_LIMIT, RANDOM_NONCE_LIMIT, counter_nonce };
+
+    #[test]
+    fn counter_nonces_increase() {
+        let mut sequence = NonceSequence::counter([1, 2, 3, 4]);
+        let mut previous = sequence.next().unwrap();
+
+        assert_eq!(previous, [1, 2, 3, 4, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        for counter in 1..1000u64 {
+            let nonce = sequence.next().unwrap();
+
+            assert!(nonce > previous);
+            assert_eq!(nonce, counter_nonce(&[1, 2, 3, 4], counter));
+            previous = nonce;
+        }
+
+        assert_eq!(sequence.used(), 1000);
+    }
+
+    #[test]
+    fn first_million_counter_nonces_are_distinct() {
+        let mut sequence = NonceSequence::counter([0xca, 0xfe, 0, 1]);
+        let mut seen = HashSet::new();
+
+        for _ in 0..1_000_000 {
+            assert!(seen.insert(sequence.next().unwrap()));
+        }
+    }
+
+    #[test]
+    fn sequences_stop_at_their_limit() {
+        let mut counter = NonceSequence::counter([0; 4]);
+        counter.used = COUNTER_NONCE_LIMIT - 2;
+
+        assert_eq!(counter.next().unwrap(), counter_nonce(&[0; 4], u64::max_value() - 2));
+        assert_eq!(counter.next().unwrap(), counter_nonce(&[0; 4], u64::max_value() - 1));
+        assert_eq!(counter.remaining(), 0);
+        assert_eq!(counter.next(), Err(NonceError::Exhausted));
+        assert_eq!(counter.next(), Err(NonceError::Exhausted));
+
+        let mut random = NonceSequence::random(&mut ChaChaRng::from_seed(&[5, 5]));
+        random.used = RANDOM_NONCE_LIMIT - 1;
+
+        assert!(random.next().is_ok());
+        assert_eq!(random.next(), Err(NonceError::Exhausted));
+
+        let mut cleared = NonceSequence::counter([0; 4]);
+        cleared.exhaust();
+        assert_eq!(cleared.next(), Err(NonceError::Exhausted));
+    }
+
+    #[test]
+    fn random_nonces_differ() {
+        let mut rng = ChaChaRng::from_seed(&[7, 7]);
+        let mut first = NonceSequence::random(&mut rng);
+        let mut second = NonceSequence::random(&mut rng);
+        let mut seen = HashSet::new();
+
+        for _ in 0..1000 {
+            assert!(seen.insert(first.next().unwrap()));
+            assert!(seen.insert(second.next().unwrap()));
+        }
+    }
+
+    #[test]
+    fn state_round_trip() {
+        let mut rng = ChaChaRng::from_seed(&[1, 1]);
+        let mut sequence = NonceSequence::counter([9, 8, 7, 6]);
+
+        for _ in 0..5 {
+            sequence.next().unwrap();
+        }
+
+        let mut resumed = NonceSequence::from_bytes(&sequence.to_bytes(), &mut rng).unwrap();
+        assert_eq!(resumed.used(), 5);
+        assert_eq!(resumed.next(), sequence.next());
+
+        let mut random = NonceSequence::random(&mut rng);
+        random.used = RANDOM_NONCE_LIMIT - 1;
+
+        let mut resumed = NonceSequence::from_bytes(&random.to_bytes(), &mut rng).unwrap();
+        assert_eq!(resumed.remaining(), 1);
+        assert!(resumed.next().is_ok());
+        assert_eq!(resumed.next(), Err(NonceError::Exhausted));
+
+        let state = sequence.to_bytes();
+        let mut bad_kind = state.clone();
+        bad_kind[0] = 9;
+        let mut past_limit = random.to_bytes();
+        past_limit[5..].copy_from_slice(&(RANDOM_NONCE_LIMIT + 1).to_be_bytes());
+
+        for bad in vec![&state[1..], &bad_kind[..], &past_limit[..]] {
+            assert_eq!(NonceSequence::from_bytes(bad, &mut rng).err(), Some(NonceError::MalformedState));
+        }
+    }
+}
\ No newline at end of file
@@ -1,12 +1,56 @@
-use rand::OsRng;
+use std::fmt;
 use std::ops::{ Shl, BitXor, Rem, Shr };
 
 use num_integer::Integer;
-use num_traits::{ One, Zero, ToPrimitive };
+use num_traits::{ FromPrimitive, One, Zero, ToPrimitive };
 use num_bigint::{ BigUint, ToBigInt, BigInt, RandBigInt };
 
+use crate::utils::rng::CryptoRngCore;
+use crate::utils::budget::Budget;
+use crate::utils::zeroize::zeroize_biguint;
+use crate::utils::modarith::Montgomery;
+
+#[cfg(any(test, feature = "metrics"))]
+use crate::utils::metrics::{ self, Operation };
+
 const LARGE_THRESHOLD: usize = 25;
 
+/// Number of the small primes used as fixed Miller-Rabin bases by
+/// `is_prime`, which checks given values without a generator
+const FIXED_BASES: usize = 24;
+
+/// Smallest prime `generate_strong` will make, so that the auxiliary
+/// primes still have room to be found
+const STRONG_MIN_BITS: usize = 128;
+
+/// Odd primes below 256, used for trial division
+const SMALL_PRIMES: [u32; 53] = [
+    3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73,
+    79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179,
+    181, 191, 193, 197, 199, 211, 223, 227, 229, 233, 239, 241, 251
+];
+
+
+/*---- ENUMS ----*/
+
+/// Errors raised while generating prime numbers
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PrimeError {
+    RngFailure,             // the OS random number generator could not be loaded
+    InvalidBitLength,       // requested bit length is too small to hold a prime
+    Timeout                 // the generation budget ran out before a prime was found
+}
+
+impl fmt::Display for PrimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PrimeError::RngFailure => write!(f, "Could not load OS RNG"),
+            PrimeError::InvalidBitLength => write!(f, "Bit length is too small for prime generation"),
+            PrimeError::Timeout => write!(f, "Prime generation ran out of budget")
+        }
+    }
+}
+
 
 /*-------- PUBLIC FUNCTIONS --------*/
 
@@ -16,18 +60,34 @@ const LARGE_THRESHOLD: usize = 25;
 /// ### Arguments
 /// 
 /// * `bitlength` - The bit length of the number
-pub fn generate(bitlength: &usize) -> BigUint {
-    let mut generator = match OsRng::new() {
-        Ok(g) => g,
-        Err(e) => panic!("Could not load OS RNG with error {}", e)
-    };
+/// * `generator` - Random number generator
+pub fn generate(bitlength: &usize, generator: &mut impl CryptoRngCore) -> Result<BigUint, PrimeError> {
+    generate_with_budget(bitlength, &mut Budget::unlimited(), generator)
+}
+
+/// Generates a prime number, giving up with `PrimeError::Timeout` once
+/// the budget runs out. Every candidate counts as one iteration
+/// 
+/// ### Arguments
+/// 
+/// * `bitlength` - The bit length of the number
+/// * `budget` - Iterations and time the search may take
+/// * `generator` - Random number generator
+pub fn generate_with_budget(bitlength: &usize, budget: &mut Budget, generator: &mut impl CryptoRngCore) -> Result<BigUint, PrimeError> {
+    if bitlength < &2 {
+        return Err(PrimeError::InvalidBitLength);
+    }
 
     loop {
-        let candidate = generate_random_biguint(&mut generator, bitlength);
+        if budget.exhausted() {
+            return Err(PrimeError::Timeout);
+        }
+
+        let candidate = generate_random_biguint(generator, bitlength);
 
         if (bitlength < &LARGE_THRESHOLD && is_small_prime(&candidate)) || 
-           (bitlength >= &LARGE_THRESHOLD && is_large_prime(&candidate)) {
-            return candidate;
+           (bitlength >= &LARGE_THRESHOLD && is_large_prime(&candidate, generator)) {
+            return Ok(candidate);
         }
     }
 }
@@ -37,33 +97,212 @@ pub fn generate(bitlength: &usize) -> BigUint {
 /// ### Arguments
 /// 
 /// * `bitlength` - Bit length of prime number
-pub fn generate_discrete_log_prime(bitlength: &usize) -> BigUint {
+/// * `generator` - Random number generator
+pub fn generate_discrete_log_prime(bitlength: &usize, generator: &mut impl CryptoRngCore) -> Result<BigUint, PrimeError> {
+    generate_discrete_log_prime_with_budget(bitlength, &mut Budget::unlimited(), generator)
+}
+
+/// Generates a prime number that is safe for discrete log crypto, giving
+/// up with `PrimeError::Timeout` once the budget runs out
+/// 
+/// ### Arguments
+/// 
+/// * `bitlength` - Bit length of prime number
+/// * `budget` - Iterations and time the search may take
+/// * `generator` - Random number generator
+pub fn generate_discrete_log_prime_with_budget(
+    bitlength: &usize,
+    budget: &mut Budget,
+    generator: &mut impl CryptoRngCore
+) -> Result<BigUint, PrimeError> {
+    loop {
+        let mut candidate = generate_with_budget(bitlength, budget, generator)?;
+
+        if is_discrete_log_safe(&candidate, generator) {
+            return Ok(candidate);
+        }
+
+        zeroize_biguint(&mut candidate);
+    }
+}
+
+/// Generates a prime of exactly `bitlength` bits that is congruent to
+/// `residue` modulo `modulus`, e.g. a DSA modulus P with P = 1 mod 2Q
+/// 
+/// ### Arguments
+/// 
+/// * `bitlength` - Bit length of the prime
+/// * `modulus` - Modulus of the congruence
+/// * `residue` - Required remainder, below the modulus
+/// * `generator` - Random number generator
+pub fn generate_with_congruence(
+    bitlength: &usize,
+    modulus: &BigUint,
+    residue: &BigUint,
+    generator: &mut impl CryptoRngCore
+) -> Result<BigUint, PrimeError> {
+    generate_with_congruence_with_budget(bitlength, modulus, residue, &mut Budget::unlimited(), generator)
+}
+
+/// Generates a prime of exactly `bitlength` bits that is congruent to
+/// `residue` modulo `modulus`, giving up with `PrimeError::Timeout` once
+/// the budget runs out
+/// 
+/// ### Arguments
+/// 
+/// * `bitlength` - Bit length of the prime
+/// * `modulus` - Modulus of the congruence
+/// * `residue` - Required remainder, below the modulus
+/// * `budget` - Iterations and time the search may take
+/// * `generator` - Random number generator
+pub fn generate_with_congruence_with_budget(
+    bitlength: &usize,
+    modulus: &BigUint,
+    residue: &BigUint,
+    budget: &mut Budget,
+    generator: &mut impl CryptoRngCore
+) -> Result<BigUint, PrimeError> {
+    if bitlength < &2 || modulus.is_zero() || residue >= modulus || modulus.bits() >= *bitlength {
+        return Err(PrimeError::InvalidBitLength);
+    }
+
+    let top_bit = BigUint::one().shl(bitlength - 1);
+
     loop {
-        let candidate = generate(bitlength);
+        if budget.exhausted() {
+            return Err(PrimeError::Timeout);
+        }
+
+        let random: BigUint = generator.gen_biguint(*bitlength) | &top_bit;
+        let candidate = &random - random.clone().rem(modulus) + residue;
+
+        if candidate.bits() != *bitlength || has_small_factor(&candidate) {
+            continue;
+        }
 
-        if is_discrete_log_safe(&candidate) {
-            return candidate;
+        if (bitlength < &LARGE_THRESHOLD && is_small_prime(&candidate)) ||
+           (bitlength >= &LARGE_THRESHOLD && is_large_prime(&candidate, generator)) {
+            return Ok(candidate);
         }
     }
 }
 
+/// Generates a strong prime with Gordon's algorithm: p - 1 has a large
+/// prime factor r, p + 1 has a large prime factor s, and r - 1 has a
+/// large prime factor t. A modulus built from such primes can't be
+/// split by Pollard's p - 1 or Williams' p + 1 method
+/// 
+/// ### Arguments
+/// 
+/// * `bitlength` - Bit length of the prime, at least 128
+/// * `generator` - Random number generator
+pub fn generate_strong(bitlength: &usize, generator: &mut impl CryptoRngCore) -> Result<BigUint, PrimeError> {
+    generate_strong_with_budget(bitlength, &mut Budget::unlimited(), generator)
+}
+
+/// Generates a strong prime, giving up with `PrimeError::Timeout` once
+/// the budget runs out. The auxiliary primes count against it too
+/// 
+/// ### Arguments
+/// 
+/// * `bitlength` - Bit length of the prime, at least 128
+/// * `budget` - Iterations and time the search may take
+/// * `generator` - Random number generator
+pub fn generate_strong_with_budget(
+    bitlength: &usize,
+    budget: &mut Budget,
+    generator: &mut impl CryptoRngCore
+) -> Result<BigUint, PrimeError> {
+    let (p, mut r, mut s, mut t) = strong_prime_parts(bitlength, budget, generator)?;
+
+    zeroize_biguint(&mut r);
+    zeroize_biguint(&mut s);
+    zeroize_biguint(&mut t);
+
+    Ok(p)
+}
+
+/// Gordon's algorithm. Returns the prime p with the auxiliary primes r,
+/// s and t, where p = 1 mod r, p = -1 mod s and r = 1 mod t. r and s are
+/// 3/8 of the length of p, which leaves p a quarter of its bits to be
+/// searched over
+/// 
+/// ### Arguments
+/// 
+/// * `bitlength` - Bit length of the prime
+/// * `budget` - Iterations and time the search may take
+/// * `generator` - Random number generator
+fn strong_prime_parts(
+    bitlength: &usize,
+    budget: &mut Budget,
+    generator: &mut impl CryptoRngCore
+) -> Result<(BigUint, BigUint, BigUint, BigUint), PrimeError> {
+    if bitlength < &STRONG_MIN_BITS {
+        return Err(PrimeError::InvalidBitLength);
+    }
+
+    let two = BigUint::from_u8(2).unwrap();
+    let factor_bits = bitlength * 3 / 8;
+    let t_bits = factor_bits - factor_bits / 4;
+
+    let s = generate_with_congruence_with_budget(&factor_bits, &two, &BigUint::one(), budget, generator)?;
+    let t = generate_with_congruence_with_budget(&t_bits, &two, &BigUint::one(), budget, generator)?;
+    let mut r = s.clone();
+
+    while r == s {
+        r = generate_with_congruence_with_budget(&factor_bits, &(&t << 1), &BigUint::one(), budget, generator)?;
+    }
+
+    // p0 = 2 * (s^-1 mod r) * s - 1 is 1 mod r and -1 mod s, and odd
+    let residue = ((modular_inverse(&s, &r) * &s) << 1) - BigUint::one();
+    let modulus = (&r * &s) << 1;
+    let p = generate_with_congruence_with_budget(bitlength, &modulus, &residue, budget, generator)?;
+
+    Ok((p, r, s, t))
+}
+
 /// Checks for prime number safety by ensuring that 
 /// "q" in "p = 2q + 1" is a Sophie Germain prime
 /// 
 /// ### Arguments
 /// 
 /// * `candidate` - Candidate prime, the "p" in above equation
-fn is_discrete_log_safe(candidate: &BigUint) -> bool {
+/// * `generator` - Random number generator
+fn is_discrete_log_safe(candidate: &BigUint, generator: &mut impl CryptoRngCore) -> bool {
     let two = BigUint::one() + BigUint::one();
     let q = candidate.shr(1) - BigUint::one();
 
     if q.clone().rem(two) != BigUint::zero() {
-        return is_large_prime(&q);
+        return is_large_prime(&q, generator);
     }
 
     false
 }
 
+/// Checks whether a given value is prime, for values read from outside
+/// rather than generated. Trial division is followed by Miller-Rabin
+/// over the first 24 odd primes as fixed bases, so the answer is the
+/// same on every call and no generator is needed
+/// 
+/// ### Arguments
+/// 
+/// * `candidate` - Value to check
+pub fn is_prime(candidate: &BigUint) -> bool {
+    if candidate.bits() < LARGE_THRESHOLD {
+        return is_small_prime(candidate);
+    }
+
+    if candidate.is_even() || has_small_factor(candidate) {
+        return false;
+    }
+
+    let (s, d) = greatest_2_divisor(candidate);
+
+    SMALL_PRIMES[..FIXED_BASES].iter().all(|basis| {
+        is_strong_probable_prime(candidate, &BigUint::from_u32(*basis).unwrap(), s, &d)
+    })
+}
+
 /// Gets the modular inverse for provided parameters using Extended Euclidean
 /// 
 /// ### Arguments
@@ -71,6 +310,9 @@ fn is_discrete_log_safe(candidate: &BigUint) -> bool {
 /// * `a` - Value to apply EE to
 /// * `modulus` - Modulus for calculation
 pub fn modular_inverse(a: &BigUint, modulus: &BigUint) -> BigUint {
+    #[cfg(any(test, feature = "metrics"))]
+    metrics::record(Operation::Inversion);
+
     let int_a = a.to_bigint().unwrap();
     let int_modulus = modulus.to_bigint().unwrap();
 
@@ -99,6 +341,9 @@ pub fn modular_inverse(a: &BigUint, modulus: &BigUint) -> BigUint {
 /// * `a` - Value to apply EE to
 /// * `modulus` - Modulus for calculation
 pub fn modular_inverse_int(a: &BigInt, modulus: &BigInt) -> BigInt {
+    #[cfg(any(test, feature = "metrics"))]
+    metrics::record(Operation::Inversion);
+
     let mut mn = (modulus.clone(), a.clone());
     let mut xy = (BigInt::zero(), BigInt::one());
  
@@ -114,14 +359,66 @@ pub fn modular_inverse_int(a: &BigInt, modulus: &BigInt) -> BigInt {
     xy.0
 }
 
+
+/// Modular exponentiation, base^exponent mod modulus. All exponentiations
+/// in the crate go through here so that they show up in the metrics.
+/// Odd moduli, which covers RSA and Diffie-Hellman, are handled in
+/// Montgomery form by `modarith`
+/// 
+/// ### Arguments
+/// 
+/// * `base` - Value to raise
+/// * `exponent` - Exponent to raise to
+/// * `modulus` - Modulus for calculation
+pub fn modpow(base: &BigUint, exponent: &BigUint, modulus: &BigUint) -> BigUint {
+    #[cfg(any(test, feature = "metrics"))]
+    metrics::record(Operation::ModPow);
+
+    match Montgomery::new(modulus) {
+        Some(context) => context.modpow(base, exponent),
+        None => base.modpow(exponent, modulus)
+    }
+}
+
  
+/// Modular exponentiation under a Montgomery context prepared earlier,
+/// for moduli used often enough that setting the context up again on
+/// every call would show
+/// 
+/// ### Arguments
+/// 
+/// * `context` - Context of the modulus
+/// * `base` - Value to raise
+/// * `exponent` - Exponent to raise to
+pub fn modpow_with(context: &Montgomery, base: &BigUint, exponent: &BigUint) -> BigUint {
+    #[cfg(any(test, feature = "metrics"))]
+    metrics::record(Operation::ModPow);
+
+    context.modpow(base, exponent)
+}
+
+/// Least common multiple of two values, such as Carmichael's
+/// lambda(pq) = lcm(p - 1, q - 1) for RSA. Zero if either value is zero
+/// 
+/// ### Arguments
+/// 
+/// * `a` - First value
+/// * `b` - Second value
+pub fn lcm(a: &BigUint, b: &BigUint) -> BigUint {
+    if a.is_zero() || b.is_zero() {
+        return BigUint::zero();
+    }
+
+    a / a.gcd(b) * b
+}
+
 /// Generates an optimised large number for primality testing
 /// 
 /// ### Arguments
 /// 
 /// * `generator` - Random number generator
 /// * `bitlength` - Bit length for number
-pub fn generate_random_biguint(generator: &mut OsRng, bitlength: &usize) -> BigUint {
+pub fn generate_random_biguint(generator: &mut impl CryptoRngCore, bitlength: &usize) -> BigUint {
     let candidate:BigUint = generator.gen_biguint(bitlength - 1);
     let shifted_candidate = candidate.shl(1);
     let final_candidate = shifted_candidate.bitxor(BigUint::one());
@@ -138,18 +435,32 @@ pub fn generate_random_biguint(generator: &mut OsRng, bitlength: &usize) -> BigU
 /// ### Arguments
 /// 
 /// * `candidate` - Candidate to check
-fn is_large_prime(candidate: &BigUint) -> bool {
-    if !fermat_little(candidate) {
+/// * `generator` - Random number generator
+fn is_large_prime(candidate: &BigUint, generator: &mut impl CryptoRngCore) -> bool {
+    if !fermat_little(candidate, generator) {
         return false;
     }
 
-    if !miller_rabin(candidate, 3) {
+    if !miller_rabin(candidate, 3, generator) {
         return false;
     }
 
     true
 }
  
+/// Cheap trial division ahead of the probabilistic tests. Candidates
+/// that are themselves one of the small primes are not rejected
+/// 
+/// ### Arguments
+/// 
+/// * `candidate` - Candidate to check
+fn has_small_factor(candidate: &BigUint) -> bool {
+    SMALL_PRIMES.iter().any(|p| {
+        let p = BigUint::from_u32(*p).unwrap();
+        candidate != &p && candidate.clone().rem(&p).is_zero()
+    })
+}
+ 
 /// Full check whether small candidate is prime
 /// 
 /// ### Arguments
@@ -167,7 +478,7 @@ fn is_small_prime(candidate: &BigUint) -> bool {
     } else {
         let sqrt = (cast as f64).sqrt().abs();
 
-        for i in 2..(sqrt as u64) {
+        for i in 2..=(sqrt as u64) {
             if cast % i == 0 {
                 return false;
             }
@@ -183,14 +494,10 @@ fn is_small_prime(candidate: &BigUint) -> bool {
 /// ### Arguments
 /// 
 /// * `candidate` - Candidate to check
-fn fermat_little(candidate: &BigUint) -> bool {
-    let mut generator = match OsRng::new() {
-        Ok(g) => g,
-        Err(e) => panic!("Could not load OS RNG with error {}", e)
-    };
-
+/// * `generator` - Random number generator
+fn fermat_little(candidate: &BigUint, generator: &mut impl CryptoRngCore) -> bool {
     let random:BigUint = generator.gen_biguint_below(candidate);
-    let result = random.modpow(&(candidate - BigUint::one()), candidate);
+    let result = modpow(&random, &(candidate - BigUint::one()), candidate);
 
     result == BigUint::one()
 }
@@ -202,32 +509,15 @@ fn fermat_little(candidate: &BigUint) -> bool {
 /// 
 /// * `candidate` - Candidate to check
 /// * `iterations` - Number of iterations to perform
-fn miller_rabin(candidate: &BigUint, iterations: usize) -> bool {
+/// * `generator` - Random number generator
+fn miller_rabin(candidate: &BigUint, iterations: usize, generator: &mut impl CryptoRngCore) -> bool {
     let (s, d) = greatest_2_divisor(candidate);
-    let one = BigUint::one();
-    let two = &one + &one;
-    let mut generator = match OsRng::new() {
-        Ok(g) => g,
-        Err(e) => panic!("Could not load OS RNG with error {}", e)
-    };
+    let two = BigUint::one() + BigUint::one();
 
     for _ in 0..iterations {
         let basis = generator.gen_biguint_range(&two, &(candidate - &two));
-        let mut y = basis.modpow(&d, candidate);
-
-        if y == one || y == (candidate - &one) {
-            continue;
-        } else {
-            for _ in 0..s {
-                y = y.modpow(&two, candidate);
-
-                if y == one {
-                    return false;
-                } else if y == candidate - &one {
-                    break;
-                }
-            }
 
+        if !is_strong_probable_prime(candidate, &basis, s, &d) {
             return false;
         }
     }
@@ -235,6 +525,38 @@ fn miller_rabin(candidate: &BigUint, iterations: usize) -> bool {
     true
 }
 
+/// One round of Miller-Rabin: whether the candidate passes for a basis,
+/// where candidate - 1 = 2^s * d with d odd
+/// 
+/// ### Arguments
+/// 
+/// * `candidate` - Candidate to check
+/// * `basis` - Basis of the round
+/// * `s` - Power of two in candidate - 1
+/// * `d` - Odd part of candidate - 1
+fn is_strong_probable_prime(candidate: &BigUint, basis: &BigUint, s: usize, d: &BigUint) -> bool {
+    let one = BigUint::one();
+    let two = &one + &one;
+    let minus_one = candidate - &one;
+    let mut y = modpow(basis, d, candidate);
+
+    if y == one || y == minus_one {
+        return true;
+    }
+
+    for _ in 1..s {
+        y = modpow(&y, &two, candidate);
+
+        if y == minus_one {
+            return true;
+        } else if y == one {
+            return false;
+        }
+    }
+
+    false
+}
+
 /// Util function for Miller-Rabin test
 /// 
 /// ### Arguments
@@ -250,4 +572,101 @@ fn greatest_2_divisor(num: &BigUint) -> (usize, BigUint) {
     }
 
     (s, num)
+}
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod primes_test {
+
+    use rand::{ ChaChaRng, SeedableRng };
+    use num_bigint::BigUint;
+    use num_traits::{ FromPrimitive, One, Zero };
+    use crate::utils::budget::Budget;
+    use crate::utils::primes::{ generate, generate_random_biguint, generate_strong, generate_with_congruence, is_prime, lcm, strong_prime_parts, PrimeError };
+
+    #[test]
+    fn seeded_generation_is_reproducible() {
+        let mut first = ChaChaRng::from_seed(&[7, 7, 7, 7]);
+        let mut second = ChaChaRng::from_seed(&[7, 7, 7, 7]);
+
+        let prime = generate(&128, &mut first).unwrap();
+
+        assert_eq!(prime, generate(&128, &mut second).unwrap());
+        assert!(prime.bits() <= 128);
+        assert_eq!(generate_random_biguint(&mut first, &64), generate_random_biguint(&mut second, &64));
+    }
+
+    #[test]
+    fn congruent_primes_have_the_requested_shape() {
+        let mut rng = ChaChaRng::from_seed(&[4, 8, 3]);
+        let q = generate(&64, &mut rng).unwrap();
+        let modulus = &q << 1;
+        let p = generate_with_congruence(&256, &modulus, &BigUint::one(), &mut rng).unwrap();
+
+        assert_eq!(p.bits(), 256);
+        assert!(((&p - BigUint::one()) % &q).is_zero());
+
+        let six = BigUint::from_u32(6).unwrap();
+        let five = BigUint::from_u32(5).unwrap();
+        let small = generate_with_congruence(&12, &six, &five, &mut rng).unwrap();
+
+        assert_eq!((small.bits(), &small % &six), (12, five));
+
+        assert!(generate_with_congruence(&64, &modulus, &BigUint::one(), &mut rng).is_err());
+        assert!(generate_with_congruence(&256, &modulus, &modulus, &mut rng).is_err());
+    }
+
+    #[test]
+    fn strong_primes_have_large_auxiliary_factors() {
+        let mut rng = ChaChaRng::from_seed(&[0x67, 0x6f, 0x72]);
+        let (p, r, s, t) = strong_prime_parts(&256, &mut Budget::unlimited(), &mut rng).unwrap();
+
+        assert_eq!(p.bits(), 256);
+        assert_eq!((r.bits(), s.bits(), t.bits()), (96, 96, 72));
+        assert!(is_prime(&p) && is_prime(&r) && is_prime(&s) && is_prime(&t));
+
+        assert!(((&p - BigUint::one()) % &r).is_zero());
+        assert!(((&p + BigUint::one()) % &s).is_zero());
+        assert!(((&r - BigUint::one()) % &t).is_zero());
+
+        assert_eq!(generate_strong(&127, &mut rng), Err(PrimeError::InvalidBitLength));
+        assert_eq!(generate_strong(&128, &mut rng).unwrap().bits(), 128);
+    }
+
+    #[test]
+    fn least_common_multiple() {
+        let value = |n: u64| BigUint::from_u64(n).unwrap();
+
+        assert_eq!(lcm(&value(4), &value(6)), value(12));
+        assert_eq!(lcm(&value(7), &value(13)), value(91));
+        assert_eq!(lcm(&value(12), &value(12)), value(12));
+        assert_eq!(lcm(&value(0), &value(5)), BigUint::zero());
+
+        // lambda(61 * 53) = lcm(60, 52)
+        assert_eq!(lcm(&value(60), &value(52)), value(780));
+    }
+
+    #[test]
+    fn fixed_base_primality() {
+        let mersenne_127 = (BigUint::one() << 127) - BigUint::one();
+        let fermat_7 = (BigUint::one() << 128) + BigUint::one();
+
+        for prime in &[2u64, 3, 9973, 2_147_483_647] {
+            assert!(is_prime(&BigUint::from_u64(*prime).unwrap()), "{}", prime);
+        }
+
+        // 9 and 25 are squares, 561 a Carmichael number and 3215031751 a
+        // strong pseudoprime to the bases 2, 3, 5 and 7
+        for composite in &[0u64, 1, 9, 25, 561, 3_215_031_751] {
+            assert!(!is_prime(&BigUint::from_u64(*composite).unwrap()), "{}", composite);
+        }
+
+        assert!(is_prime(&mersenne_127));
+        assert!(!is_prime(&fermat_7));
+        assert!(!is_prime(&(&mersenne_127 * &mersenne_127)));
+
+        let mut rng = ChaChaRng::from_seed(&[1, 5, 9]);
+        assert!(is_prime(&generate(&256, &mut rng).unwrap()));
+    }
 }
\ No newline at end of file
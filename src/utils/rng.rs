@@ -0,0 +1,175 @@
+use std::io;
+use rand::{ Rng, OsRng, ChaChaRng, SeedableRng };
+
+
+/*---- TRAITS ----*/
+
+/// Marker for random number generators that are fit for generating
+/// key material, nonces and blinding values. Every randomised operation
+/// in the crate takes a `&mut impl CryptoRngCore`, so callers can supply
+/// the OS generator in production and a seeded generator in tests
+pub trait CryptoRngCore: Rng {
+
+    /// Whether key generation should run the health tests in
+    /// `utils::entropy` on this generator first
+    fn health_tested(&self) -> bool {
+        true
+    }
+}
+
+impl CryptoRngCore for OsRng {}
+
+impl CryptoRngCore for ChaChaRng {}
+
+impl CryptoRngCore for GetrandomRng {}
+
+impl<R: CryptoRngCore> CryptoRngCore for UncheckedRng<R> {
+    fn health_tested(&self) -> bool {
+        false
+    }
+}
+
+impl<'a, R: CryptoRngCore + ?Sized> CryptoRngCore for &'a mut R {
+    fn health_tested(&self) -> bool {
+        (**self).health_tested()
+    }
+}
+
+
+/*---- STRUCTS ----*/
+
+/// Generator backed by the `getrandom` crate. Unlike `OsRng` this also
+/// works on wasm32-unknown-unknown, where it reads from the browser's
+/// `crypto.getRandomValues`
+pub struct GetrandomRng;
+
+/// Wrapper opting a generator out of the health tests run before key
+/// generation. Meant for seeded generators in tests, whose output is
+/// known not to need checking
+pub struct UncheckedRng<R>(pub R);
+
+/// Generator returned by `os_rng`, chosen for the target platform
+#[cfg(not(target_arch = "wasm32"))]
+pub type SystemRng = OsRng;
+
+/// Generator returned by `os_rng`, chosen for the target platform
+#[cfg(target_arch = "wasm32")]
+pub type SystemRng = GetrandomRng;
+
+
+/*---- IMPLEMENTATIONS ----*/
+
+impl GetrandomRng {
+
+    /// Checks that the platform source is available before handing out
+    /// the generator, so that later reads can be assumed to succeed
+    pub fn new() -> Result<Self, io::Error> {
+        let mut probe = [0; 1];
+        getrandom::getrandom(&mut probe).map_err(io::Error::from)?;
+
+        Ok(GetrandomRng)
+    }
+}
+
+impl Rng for GetrandomRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0; 4];
+        self.fill_bytes(&mut bytes);
+
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        self.fill_bytes(&mut bytes);
+
+        u64::from_le_bytes(bytes)
+    }
+
+    /// Panics if the platform source fails after `new` succeeded, in the
+    /// same way as `OsRng`, since `Rng` has no way to report the error
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        if let Err(e) = getrandom::getrandom(dest) {
+            panic!("Random number generation failed: {}", e);
+        }
+    }
+}
+
+
+impl<R: CryptoRngCore> Rng for UncheckedRng<R> {
+    fn next_u32(&mut self) -> u32 {
+        self.0.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.fill_bytes(dest)
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Opens the operating system's random number generator. This is the
+/// generator to use unless reproducible output is explicitly required.
+/// On wasm targets the browser's generator is used instead
+pub fn os_rng() -> Result<SystemRng, io::Error> {
+    SystemRng::new()
+}
+
+/// Deterministic ChaCha generator for tests and documentation examples,
+/// which need the same output on every run. Its output is only as
+/// secret as the seed, so it must never be used for real keys
+/// 
+/// ### Arguments
+/// 
+/// * `seed` - Seed words
+pub fn seeded_rng(seed: &[u32]) -> ChaChaRng {
+    ChaChaRng::from_seed(seed)
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod rng_test {
+
+    use rand::{ Rng, ChaChaRng, SeedableRng };
+    use crate::utils::rng::{ CryptoRngCore, GetrandomRng, os_rng, seeded_rng };
+
+    fn fill<R: CryptoRngCore>(rng: &mut R) -> Vec<u8> {
+        let mut bytes = vec![0; 32];
+        rng.fill_bytes(&mut bytes);
+
+        bytes
+    }
+
+    #[test]
+    fn seeded_generators_are_reproducible() {
+        let mut first = ChaChaRng::from_seed(&[1, 2, 3, 4]);
+        let mut second = ChaChaRng::from_seed(&[1, 2, 3, 4]);
+        let mut other = ChaChaRng::from_seed(&[4, 3, 2, 1]);
+
+        assert_eq!(fill(&mut first), fill(&mut second));
+        assert!(fill(&mut first) != fill(&mut other));
+        assert_eq!(fill(&mut seeded_rng(&[1, 2, 3, 4])), fill(&mut ChaChaRng::from_seed(&[1, 2, 3, 4])));
+    }
+
+    #[test]
+    fn os_generator_is_available() {
+        let mut rng = os_rng().unwrap();
+
+        assert!(fill(&mut rng) != fill(&mut &mut rng));
+    }
+
+    #[test]
+    fn getrandom_generator_is_available() {
+        let mut rng = GetrandomRng::new().unwrap();
+
+        assert!(fill(&mut rng) != fill(&mut rng));
+        assert!(rng.next_u64() != rng.next_u64());
+    }
+}
\ No newline at end of file
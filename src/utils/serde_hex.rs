@@ -0,0 +1,164 @@
+//! Serde helpers that represent big integers and byte strings as
+//! lowercase hex, for use with `#[serde(with = "...")]` on fields
+
+use serde::de::Error;
+use serde::{ Serializer, Deserializer, Deserialize };
+use rustc_serialize::hex::{ ToHex, FromHex };
+
+
+/*---- FUNCTIONS ----*/
+
+/// Hex encoding for byte strings
+pub mod bytes {
+
+    use super::*;
+
+    /// Serializes bytes as a hex string
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `value` - Bytes to serialize
+    /// * `serializer` - Serializer to write to
+    pub fn serialize<S: Serializer>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_hex())
+    }
+
+    /// Deserializes bytes from a hex string
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `deserializer` - Deserializer to read from
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        hex.from_hex().map_err(|_| D::Error::custom("invalid hex string"))
+    }
+}
+
+/// Big-endian hex encoding for unsigned big integers
+pub mod biguint {
+
+    use super::*;
+    use num_bigint::BigUint;
+
+    /// Serializes a BigUint as a hex string
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `value` - Value to serialize
+    /// * `serializer` - Serializer to write to
+    pub fn serialize<S: Serializer>(value: &BigUint, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_str_radix(16))
+    }
+
+    /// Deserializes a BigUint from a hex string
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `deserializer` - Deserializer to read from
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BigUint, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        BigUint::parse_bytes(hex.as_bytes(), 16).ok_or(D::Error::custom("invalid hex integer"))
+    }
+}
+
+/// Hex encoding for signed big integers, with a leading "-" when negative
+pub mod bigint {
+
+    use super::*;
+    use num_bigint::BigInt;
+
+    /// Serializes a BigInt as a hex string
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `value` - Value to serialize
+    /// * `serializer` - Serializer to write to
+    pub fn serialize<S: Serializer>(value: &BigInt, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_str_radix(16))
+    }
+
+    /// Deserializes a BigInt from a hex string
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `deserializer` - Deserializer to read from
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BigInt, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        BigInt::parse_bytes(hex.as_bytes(), 16).ok_or(D::Error::custom("invalid hex integer"))
+    }
+}
+
+/// Hex encoding for optional signed big integers
+pub mod option_bigint {
+
+    use super::*;
+    use num_bigint::BigInt;
+
+    /// Serializes an optional BigInt as an optional hex string
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `value` - Value to serialize
+    /// * `serializer` - Serializer to write to
+    pub fn serialize<S: Serializer>(value: &Option<BigInt>, serializer: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(v) => serializer.serialize_some(&v.to_str_radix(16)),
+            None => serializer.serialize_none()
+        }
+    }
+
+    /// Deserializes an optional BigInt from an optional hex string
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `deserializer` - Deserializer to read from
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<BigInt>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(hex) => match BigInt::parse_bytes(hex.as_bytes(), 16) {
+                Some(v) => Ok(Some(v)),
+                None => Err(D::Error::custom("invalid hex integer"))
+            },
+            None => Ok(None)
+        }
+    }
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod serde_hex_test {
+
+    use serde::{ Serialize, Deserialize };
+    use num_traits::FromPrimitive;
+    use num_bigint::{ BigInt, BigUint };
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Sample {
+        #[serde(with = "utils::serde_hex::biguint")]
+        unsigned: BigUint,
+        #[serde(with = "utils::serde_hex::bigint")]
+        signed: BigInt,
+        #[serde(with = "utils::serde_hex::bytes")]
+        bytes: Vec<u8>
+    }
+
+    #[test]
+    fn values_are_written_as_hex() {
+        let sample = Sample {
+            unsigned: BigUint::from_bytes_be(&[0x01, 0xab]),
+            signed: BigInt::from_i64(-255).unwrap(),
+            bytes: vec![0, 15, 255]
+        };
+
+        let json = serde_json::to_string(&sample).unwrap();
+        assert_eq!(json, r#"{"unsigned":"1ab","signed":"-ff","bytes":"000fff"}"#);
+        assert_eq!(serde_json::from_str::<Sample>(&json).unwrap(), sample);
+    }
+
+    #[test]
+    fn invalid_hex_is_rejected() {
+        assert!(serde_json::from_str::<Sample>(r#"{"unsigned":"xyz","signed":"1","bytes":""}"#).is_err());
+        assert!(serde_json::from_str::<Sample>(r#"{"unsigned":"1","signed":"1","bytes":"abc"}"#).is_err());
+    }
+}
\ No newline at end of file
@@ -4,7 +4,7 @@
 /// ### Arguments
 /// 
 /// * `input` - Input to flatten
-pub fn flatten_chunks_with_chunk_data<T: Clone>(input: &Vec<(T, Vec<u8>)>) -> (Vec<T>, Vec<u8>) {
+pub fn flatten_chunks_with_chunk_data<T: Clone>(input: &[(T, Vec<u8>)]) -> (Vec<T>, Vec<u8>) {
     let size = input.iter().fold(0, |a, b| a + b.1.len());
     
     let recoded_bytes = input.into_iter().fold(Vec::with_capacity(size), |mut acc, v| {
@@ -23,7 +23,7 @@ pub fn flatten_chunks_with_chunk_data<T: Clone>(input: &Vec<(T, Vec<u8>)>) -> (V
 /// ### Arguments
 /// 
 /// * `input` - Input to flatten
-pub fn flatten_chunks<T: Clone>(input: &Vec<Vec<T>>) -> Vec<T> {
+pub fn flatten_chunks<T: Clone>(input: &[Vec<T>]) -> Vec<T> {
     let size = input.iter().fold(0, |a, b| a + b.len());
     input.into_iter().fold(Vec::with_capacity(size), |mut acc, v| {
         acc.extend(v.clone()); acc
@@ -37,7 +37,7 @@ pub fn flatten_chunks<T: Clone>(input: &Vec<Vec<T>>) -> Vec<T> {
 /// 
 /// * `full_input` - Input to chunk
 /// * `chunk_size` - Size of chunks
-pub fn get_exact_chunks<T: Clone>(full_input: &Vec<T>, chunk_size: &usize) -> Vec<Vec<T>> {
+pub fn get_exact_chunks<T: Clone>(full_input: &[T], chunk_size: &usize) -> Vec<Vec<T>> {
     let mut chunked_output = Vec::with_capacity((full_input.len() / chunk_size) + 1);
     let mut chunk = Vec::<T>::with_capacity(*chunk_size + 1);
     let mut counter = 1;
@@ -63,8 +63,8 @@ pub fn get_exact_chunks<T: Clone>(full_input: &Vec<T>, chunk_size: &usize) -> Ve
 /// 
 /// * `input` - Input to chunk
 /// * `chunk_size` - Size of chunks
-pub fn chunk_for_threads(input: &Vec<u8>, chunk_size: usize) -> Vec<Vec<u8>> {
-    let mut clone = input.clone();
+pub fn chunk_for_threads(input: &[u8], chunk_size: usize) -> Vec<Vec<u8>> {
+    let mut clone = input.to_vec();
     let mut return_vec = Vec::with_capacity((input.len() / chunk_size) + 1);
 
     while clone.len() > 0 {
@@ -81,4 +81,48 @@ pub fn chunk_for_threads(input: &Vec<u8>, chunk_size: usize) -> Vec<Vec<u8>> {
     }
 
     return_vec
+}
+
+/// Pads the input to a whole number of blocks with PKCS#7 (RFC 5652
+/// 6.3). Input that already fills its blocks gets a whole block of
+/// padding, so that the padding can always be told apart and removed
+/// 
+/// ### Arguments
+/// 
+/// * `input` - Input to pad
+/// * `block_size` - Size of blocks, from 1 to 255
+pub fn pkcs7_pad(input: &[u8], block_size: usize) -> Vec<u8> {
+    let padding = block_size - input.len() % block_size;
+    let mut padded = Vec::with_capacity(input.len() + padding);
+
+    padded.extend_from_slice(input);
+    padded.resize(input.len() + padding, padding as u8);
+    padded
+}
+
+/// Removes PKCS#7 padding, checking every padding byte rather than only
+/// the last. None when the input isn't a whole number of blocks or the
+/// padding is malformed
+/// 
+/// ### Arguments
+/// 
+/// * `input` - Padded input
+/// * `block_size` - Size of blocks, from 1 to 255
+pub fn pkcs7_unpad(input: &[u8], block_size: usize) -> Option<Vec<u8>> {
+    if input.is_empty() || input.len() % block_size != 0 {
+        return None;
+    }
+
+    let padding = input[input.len() - 1] as usize;
+
+    if padding == 0 || padding > block_size {
+        return None;
+    }
+
+    let malformed = input[input.len() - padding..].iter().fold(0, |acc, byte| acc | (byte ^ padding as u8));
+
+    match malformed {
+        0 => Some(input[..input.len() - padding].to_vec()),
+        _ => None
+    }
 }
\ No newline at end of file
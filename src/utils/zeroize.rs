@@ -0,0 +1,159 @@
+use std::fmt;
+use std::ptr;
+use std::ops::{ Deref, DerefMut };
+use std::sync::atomic::{ compiler_fence, Ordering };
+
+use num_traits::Zero;
+use num_bigint::BigUint;
+
+
+/*---- TRAITS ----*/
+
+/// Secret values that can be overwritten in place
+pub trait Zeroize {
+    fn zeroize(&mut self) -> ();
+}
+
+
+/*---- STRUCTS ----*/
+
+/// Holds a secret value and wipes it when dropped, whichever features
+/// are enabled. Derefs to the value it holds, and its Debug output never
+/// shows the value
+pub struct Zeroizing<T: Zeroize>(T);
+
+
+/*---- IMPLEMENTATIONS ----*/
+
+impl Zeroize for Vec<u8> {
+    fn zeroize(&mut self) -> () {
+        zeroize_bytes(self);
+    }
+}
+
+impl Zeroize for BigUint {
+    fn zeroize(&mut self) -> () {
+        zeroize_biguint(self);
+    }
+}
+
+impl<T: Zeroize> Zeroizing<T> {
+
+    /// Takes ownership of a secret value
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `value` - Value to wipe on drop
+    pub fn new(value: T) -> Self {
+        Zeroizing(value)
+    }
+}
+
+impl<T: Zeroize> Deref for Zeroizing<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Zeroize> DerefMut for Zeroizing<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: Zeroize> fmt::Debug for Zeroizing<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Zeroizing([REDACTED])")
+    }
+}
+
+impl<T: Zeroize> Drop for Zeroizing<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Overwrites a byte buffer with zeros. Volatile writes and a compiler
+/// fence keep the optimiser from removing the writes as dead stores
+/// 
+/// ### Arguments
+/// 
+/// * `bytes` - Buffer to wipe
+pub fn zeroize_bytes(bytes: &mut [u8]) -> () {
+    for byte in bytes.iter_mut() {
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Overwrites the digits of a BigUint with zeros and resets it to zero.
+/// num-bigint does not expose the digit vector, so it is reached by
+/// reinterpreting the BigUint, which is a struct holding only that vector
+/// 
+/// ### Arguments
+/// 
+/// * `value` - Value to wipe
+pub fn zeroize_biguint(value: &mut BigUint) -> () {
+    let digits = unsafe { &mut *(value as *mut BigUint as *mut Vec<u32>) };
+
+    for digit in digits.iter_mut() {
+        unsafe { ptr::write_volatile(digit, 0) };
+    }
+
+    compiler_fence(Ordering::SeqCst);
+    *value = BigUint::zero();
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod zeroize_test {
+
+    use num_traits::Zero;
+    use num_bigint::BigUint;
+    use crate::utils::zeroize::{ zeroize_bytes, zeroize_biguint, Zeroize, Zeroizing };
+
+    #[test]
+    fn bytes_are_wiped() {
+        let mut secret = vec![0xAB; 48];
+        zeroize_bytes(&mut secret);
+
+        assert_eq!(secret.len(), 48);
+        assert!(secret.iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn biguint_is_wiped() {
+        let mut secret = BigUint::from_bytes_be(&[0xDE, 0xAD, 0xBE, 0xEF, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        zeroize_biguint(&mut secret);
+
+        assert!(secret.is_zero());
+    }
+
+    #[test]
+    fn container_wipes_and_redacts() {
+        let mut key = Zeroizing::new(vec![0x5c; 32]);
+        key[0] = 0x01;
+
+        assert_eq!(key.len(), 32);
+        assert_eq!(&key[..2], &[0x01, 0x5c]);
+        assert_eq!(format!("{:?}", key), "Zeroizing([REDACTED])");
+
+        // What drop does to the value it holds
+        let mut value = key.clone();
+        value.zeroize();
+        assert!(value.iter().all(|b| *b == 0));
+
+        let mut private = Zeroizing::new(BigUint::from_bytes_be(&[0xDE, 0xAD, 0xBE, 0xEF]));
+        private.zeroize();
+        assert!(private.is_zero());
+    }
+
+}
\ No newline at end of file
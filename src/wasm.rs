@@ -0,0 +1,402 @@
+//! Wrappers around ECDH key agreement and AES-GCM sealing that only take
+//! and return byte arrays, so that they can be exported to JavaScript
+//! with wasm-bindgen. Errors are returned as their display strings,
+//! which wasm-bindgen turns into thrown exceptions.
+//!
+//! AES-GCM is not key-committing: someone who knows two keys can craft a
+//! single sealed message that opens under both. `seal_committing` and
+//! `open_committing` close that gap for designs with several recipients.
+//! They derive a per-message AES key and a commitment to the key from
+//! the given key with HMAC, and the commitment is checked before any
+//! decryption is attempted. `seal` and `open` keep their original format
+
+use rand::Rng;
+use crypto::aes::KeySize;
+use crypto::aes_gcm::AesGcm;
+use crypto::aead::{ AeadEncryptor, AeadDecryptor };
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+use crate::hash::hmac::hmac;
+use crate::hash::crypto::HashAlgorithm;
+use crate::key_exchange::ecdh::ECDH;
+use crate::utils::ct;
+use crate::utils::rng::{ CryptoRngCore, os_rng };
+use crate::utils::zeroize::zeroize_bytes;
+use crate::utils::ecc_curves::ECPSupportedCurves;
+
+
+/*---- CONSTANTS ----*/
+
+/// Length in bytes of the AES-256 key derived by `KeyAgreement`
+const KEY_LENGTH: usize = 32;
+
+/// Length in bytes of the GCM nonce
+const NONCE_LENGTH: usize = 12;
+
+/// Length in bytes of the GCM authentication tag
+const TAG_LENGTH: usize = 16;
+
+/// HKDF info string binding derived keys to the sealing functions
+const KDF_INFO: &'static [u8] = b"cryptofun wasm AES-256-GCM";
+
+/// Length in bytes of the key commitment
+const COMMITMENT_LENGTH: usize = 32;
+
+/// HMAC labels separating the committed encryption key from the commitment
+const COMMITTED_KEY_LABEL: &'static [u8] = b"cryptofun wasm committing key";
+const COMMITMENT_LABEL: &'static [u8] = b"cryptofun wasm commitment";
+
+/// Error returned when a committing message was sealed under another key
+const COMMITMENT_ERROR: &'static str = "Sealed message is not committed to this key";
+
+
+/*---- STRUCTS ----*/
+
+/// Curve25519 keypair for agreeing on a sealing key with a peer
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+pub struct KeyAgreement {
+    ecdh: ECDH
+}
+
+
+/*---- IMPLEMENTATIONS ----*/
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+impl KeyAgreement {
+
+    /// Generates a fresh keypair
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen(constructor))]
+    pub fn new() -> Result<KeyAgreement, String> {
+        let mut rng = os_rng().map_err(|e| e.to_string())?;
+        KeyAgreement::new_with_rng(&mut rng)
+    }
+
+    /// Public value to send to the peer
+    pub fn public_key(&self) -> Vec<u8> {
+        self.ecdh.public_key_bytes(false)
+    }
+
+    /// Derives the AES-256 key shared with the peer
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `peer_public_key` - Public value received from the peer
+    pub fn derive_key(&self, peer_public_key: &[u8]) -> Result<Vec<u8>, String> {
+        let mut rng = os_rng().map_err(|e| e.to_string())?;
+        self.derive_key_with_rng(peer_public_key, &mut rng)
+    }
+}
+
+impl KeyAgreement {
+
+    /// Generates a fresh keypair from the given generator
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `rng` - Random number generator
+    fn new_with_rng(rng: &mut impl CryptoRngCore) -> Result<KeyAgreement, String> {
+        match ECDH::new(ECPSupportedCurves::Curve25519, rng) {
+            Ok(ecdh) => Ok(KeyAgreement { ecdh: ecdh }),
+            Err(e) => Err(e.to_string())
+        }
+    }
+
+    /// Derives the AES-256 key shared with the peer
+    /// 
+    /// ### Arguments
+    /// 
+    /// * `peer_public_key` - Public value received from the peer
+    /// * `rng` - Random number generator
+    fn derive_key_with_rng(&self, peer_public_key: &[u8], rng: &mut impl CryptoRngCore) -> Result<Vec<u8>, String> {
+        self.ecdh.derive_key_for_peer(peer_public_key, rng, KDF_INFO, KEY_LENGTH).map_err(|e| e.to_string())
+    }
+}
+
+
+/*---- FUNCTIONS ----*/
+
+/// Encrypts and authenticates a message under a key from `KeyAgreement`.
+/// The output is nonce || ciphertext || tag
+/// 
+/// ### Arguments
+/// 
+/// * `key` - 32 byte AES key
+/// * `plaintext` - Message to seal
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+pub fn seal(key: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    let mut rng = os_rng().map_err(|e| e.to_string())?;
+    seal_with_rng(key, plaintext, &mut rng)
+}
+
+/// Decrypts a message produced by `seal`, failing if it was altered
+/// or sealed under another key
+/// 
+/// ### Arguments
+/// 
+/// * `key` - 32 byte AES key
+/// * `sealed` - Output of `seal`
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+pub fn open(key: &[u8], sealed: &[u8]) -> Result<Vec<u8>, String> {
+    check_key_length(key)?;
+
+    if sealed.len() < NONCE_LENGTH + TAG_LENGTH {
+        return Err("Sealed message is too short".to_string());
+    }
+
+    let (nonce, body) = sealed.split_at(NONCE_LENGTH);
+    decrypt_gcm(key, nonce, body)
+}
+
+/// Encrypts and authenticates a message under a key from `KeyAgreement`,
+/// committing the output to that key. The output is
+/// nonce || commitment || ciphertext || tag
+/// 
+/// ### Arguments
+/// 
+/// * `key` - 32 byte AES key
+/// * `plaintext` - Message to seal
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+pub fn seal_committing(key: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    let mut rng = os_rng().map_err(|e| e.to_string())?;
+    seal_committing_with_rng(key, plaintext, &mut rng)
+}
+
+/// Decrypts a message produced by `seal_committing`. The commitment is
+/// checked first, so a message sealed under any other key is rejected
+/// before decryption
+/// 
+/// ### Arguments
+/// 
+/// * `key` - 32 byte AES key
+/// * `sealed` - Output of `seal_committing`
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+pub fn open_committing(key: &[u8], sealed: &[u8]) -> Result<Vec<u8>, String> {
+    check_key_length(key)?;
+
+    if sealed.len() < NONCE_LENGTH + COMMITMENT_LENGTH + TAG_LENGTH {
+        return Err("Sealed message is too short".to_string());
+    }
+
+    let (nonce, rest) = sealed.split_at(NONCE_LENGTH);
+    let (commitment, body) = rest.split_at(COMMITMENT_LENGTH);
+
+    if !ct::ct_eq(commitment, &hmac(key, &labelled(COMMITMENT_LABEL, nonce), HashAlgorithm::Sha3_256)) {
+        return Err(COMMITMENT_ERROR.to_string());
+    }
+
+    let mut committed_key = hmac(key, &labelled(COMMITTED_KEY_LABEL, nonce), HashAlgorithm::Sha3_256);
+    let plaintext = decrypt_gcm(&committed_key, nonce, body);
+    zeroize_bytes(&mut committed_key);
+
+    plaintext
+}
+
+/// Encrypts and authenticates a message, drawing the nonce from the
+/// given generator
+/// 
+/// ### Arguments
+/// 
+/// * `key` - 32 byte AES key
+/// * `plaintext` - Message to seal
+/// * `rng` - Random number generator
+fn seal_with_rng(key: &[u8], plaintext: &[u8], rng: &mut impl CryptoRngCore) -> Result<Vec<u8>, String> {
+    check_key_length(key)?;
+
+    let mut nonce = [0; NONCE_LENGTH];
+    rng.fill_bytes(&mut nonce);
+
+    let mut output = nonce.to_vec();
+    output.extend(encrypt_gcm(key, &nonce, plaintext));
+
+    Ok(output)
+}
+
+/// Encrypts, authenticates and commits a message, drawing the nonce
+/// from the given generator
+/// 
+/// ### Arguments
+/// 
+/// * `key` - 32 byte AES key
+/// * `plaintext` - Message to seal
+/// * `rng` - Random number generator
+fn seal_committing_with_rng(key: &[u8], plaintext: &[u8], rng: &mut impl CryptoRngCore) -> Result<Vec<u8>, String> {
+    check_key_length(key)?;
+
+    let mut nonce = [0; NONCE_LENGTH];
+    rng.fill_bytes(&mut nonce);
+
+    let mut committed_key = hmac(key, &labelled(COMMITTED_KEY_LABEL, &nonce), HashAlgorithm::Sha3_256);
+
+    let mut output = nonce.to_vec();
+    output.extend(hmac(key, &labelled(COMMITMENT_LABEL, &nonce), HashAlgorithm::Sha3_256));
+    output.extend(encrypt_gcm(&committed_key, &nonce, plaintext));
+    zeroize_bytes(&mut committed_key);
+
+    Ok(output)
+}
+
+/// AES-256-GCM encryption without associated data, returning
+/// ciphertext || tag
+/// 
+/// ### Arguments
+/// 
+/// * `key` - 32 byte AES key
+/// * `nonce` - GCM nonce
+/// * `plaintext` - Message to encrypt
+fn encrypt_gcm(key: &[u8], nonce: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut ciphertext = vec![0; plaintext.len()];
+    let mut tag = [0; TAG_LENGTH];
+
+    let mut encryptor = AesGcm::new(KeySize::KeySize256, key, nonce, &[]);
+    encryptor.encrypt(plaintext, &mut ciphertext, &mut tag);
+
+    ciphertext.extend_from_slice(&tag);
+    ciphertext
+}
+
+/// AES-256-GCM decryption of ciphertext || tag
+/// 
+/// ### Arguments
+/// 
+/// * `key` - 32 byte AES key
+/// * `nonce` - GCM nonce
+/// * `body` - Ciphertext followed by the tag, at least `TAG_LENGTH` long
+fn decrypt_gcm(key: &[u8], nonce: &[u8], body: &[u8]) -> Result<Vec<u8>, String> {
+    let (ciphertext, tag) = body.split_at(body.len() - TAG_LENGTH);
+    let mut plaintext = vec![0; ciphertext.len()];
+
+    let mut decryptor = AesGcm::new(KeySize::KeySize256, key, nonce, &[]);
+
+    if !decryptor.decrypt(ciphertext, &mut plaintext, tag) {
+        return Err("Sealed message failed authentication".to_string());
+    }
+
+    Ok(plaintext)
+}
+
+/// Concatenates an HMAC label and the nonce it is bound to
+/// 
+/// ### Arguments
+/// 
+/// * `label` - Domain separation label
+/// * `nonce` - Message nonce
+fn labelled(label: &[u8], nonce: &[u8]) -> Vec<u8> {
+    let mut input = label.to_vec();
+    input.extend_from_slice(nonce);
+    input
+}
+
+/// Rejects keys that are not AES-256 keys
+/// 
+/// ### Arguments
+/// 
+/// * `key` - Key to check
+fn check_key_length(key: &[u8]) -> Result<(), String> {
+    if key.len() != KEY_LENGTH {
+        return Err(format!("Key must be {} bytes", KEY_LENGTH));
+    }
+
+    Ok(())
+}
+
+
+/*----- TESTS -----*/
+
+#[cfg(test)]
+mod wasm_test {
+
+    use rand::{ ChaChaRng, SeedableRng };
+    use crate::utils::encoding::hex_to_bytes;
+    use crate::hash::hmac::hmac;
+    use crate::hash::crypto::HashAlgorithm;
+    use crate::wasm::{ KeyAgreement, seal, open, seal_with_rng, seal_committing, open_committing };
+    use crate::wasm::{ encrypt_gcm, labelled, COMMITTED_KEY_LABEL, COMMITMENT_ERROR, NONCE_LENGTH, COMMITMENT_LENGTH };
+
+    const MESSAGE: &'static [u8] = b"Attack at dawn, bring the good biscuits";
+
+    #[test]
+    fn end_to_end_exchange() {
+        let alice = KeyAgreement::new().unwrap();
+        let bob = KeyAgreement::new().unwrap();
+
+        let alice_key = alice.derive_key(&bob.public_key()).unwrap();
+        let bob_key = bob.derive_key(&alice.public_key()).unwrap();
+        assert_eq!(alice_key, bob_key);
+
+        let sealed = seal(&alice_key, MESSAGE).unwrap();
+        assert_eq!(open(&bob_key, &sealed).unwrap(), MESSAGE.to_vec());
+    }
+
+    #[test]
+    fn seeded_exchange_is_reproducible() {
+        let exchange = |seed: u32| {
+            let mut rng = ChaChaRng::from_seed(&[seed]);
+            let alice = KeyAgreement::new_with_rng(&mut rng).unwrap();
+            let bob = KeyAgreement::new_with_rng(&mut rng).unwrap();
+            let key = alice.derive_key_with_rng(&bob.public_key(), &mut rng).unwrap();
+
+            seal_with_rng(&key, MESSAGE, &mut rng).unwrap()
+        };
+
+        assert_eq!(exchange(1), exchange(1));
+        assert!(exchange(1) != exchange(2));
+    }
+
+    #[test]
+    fn tampered_messages_are_rejected() {
+        let key = vec![7; 32];
+        let mut sealed = seal(&key, MESSAGE).unwrap();
+
+        assert!(open(&vec![8; 32], &sealed).is_err());
+        assert!(open(&key, &sealed[..20]).is_err());
+        assert!(seal(&key[..16], MESSAGE).is_err());
+
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        assert!(open(&key, &sealed).is_err());
+    }
+
+    #[test]
+    fn malformed_peer_keys_are_rejected() {
+        let alice = KeyAgreement::new().unwrap();
+
+        assert!(alice.derive_key(&[]).is_err());
+        assert!(alice.derive_key(&[0; 32]).is_err());
+    }
+
+    #[test]
+    fn plain_sealing_keeps_its_format() {
+        // AES-256-GCM of MESSAGE under key [7; 32] and nonce 1..=12,
+        // produced by an independent implementation
+        let sealed = hex_to_bytes("0102030405060708090a0b0cfa089cff0981c24d911e667fe2c98f60b697385ed56b468f1b81ebf0c33c667c577f572d6e73b8896a4e10413f3c79de8120186e0d2cde").unwrap();
+
+        assert_eq!(open(&vec![7; 32], &sealed).unwrap(), MESSAGE.to_vec());
+        assert!(open_committing(&vec![7; 32], &sealed).is_err());
+    }
+
+    #[test]
+    fn committing_messages_open_only_under_their_key() {
+        let key_a = vec![7; 32];
+        let key_b = vec![8; 32];
+        let sealed = seal_committing(&key_a, MESSAGE).unwrap();
+
+        assert_eq!(open_committing(&key_a, &sealed).unwrap(), MESSAGE.to_vec());
+        assert_eq!(open_committing(&key_b, &sealed), Err(COMMITMENT_ERROR.to_string()));
+        assert!(open(&key_a, &sealed).is_err());
+
+        // Keep A's commitment but swap in a ciphertext and tag that are
+        // valid under the key B derives, as a multi-key attacker would
+        let (header, _) = sealed.split_at(NONCE_LENGTH + COMMITMENT_LENGTH);
+        let nonce = &header[..NONCE_LENGTH];
+        let committed_key_b = hmac(&key_b, &labelled(COMMITTED_KEY_LABEL, nonce), HashAlgorithm::Sha3_256);
+
+        let mut forged = header.to_vec();
+        forged.extend(encrypt_gcm(&committed_key_b, nonce, b"a different message"));
+
+        assert_eq!(open_committing(&key_b, &forged), Err(COMMITMENT_ERROR.to_string()));
+        assert!(open_committing(&key_a, &forged).is_err());
+        assert!(open_committing(&key_a, &sealed[..NONCE_LENGTH + COMMITMENT_LENGTH]).is_err());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,111 @@
+#!/bin/sh
+# Regenerates the OpenSSL interop fixtures read by tests/interop.rs.
+# The committed files were produced once with OpenSSL 3.5 and should
+# not need regenerating: the tests pin the exact bytes written here.
+# Needs openssl, xxd, and python3 with the cryptography package
+set -e
+
+cd "$(dirname "$0")"
+work=$(mktemp -d)
+trap 'rm -rf "$work"' EXIT
+
+hex() {
+    xxd -p | tr -d '\n'
+}
+
+# Colon separated hex from `openssl pkey -text`, for the named block
+text_field() {
+    openssl pkey -in "$1" -text -noout | awk -v field="$2:" '
+        $1 == field { reading = 1; next }
+        reading && /^ / { gsub(/[ :]/, ""); printf "%s", $0; next }
+        reading { exit }
+    '
+}
+
+# RSA-2048: raw, OAEP (SHA-256, MGF1-SHA-256) and PSS (SHA-256 and SHA3-256, 32 byte salt)
+openssl genpkey -algorithm RSA -pkeyopt rsa_keygen_bits:2048 -pkeyopt rsa_keygen_pubexp:65537 -out rsa_private.pem
+openssl pkey -in rsa_private.pem -pubout -out rsa_public.pem
+openssl rsa -in rsa_private.pem -traditional -out rsa_private_pkcs1.pem
+
+# The raw block leads with 0x01, the marker the crate frames its chunks with
+printf '\001raw block signed without padding' > "$work/raw"
+printf 'encrypted with OAEP' > "$work/oaep"
+printf 'signed with PSS' > "$work/pss"
+
+# Raw signing needs the block padded out to the modulus length, and only
+# the deprecated rsautl still signs without padding
+(head -c 223 /dev/zero; cat "$work/raw") > "$work/raw.padded"
+mv "$work/raw.padded" "$work/raw"
+openssl rsautl -sign -raw -inkey rsa_private.pem -in "$work/raw" -out "$work/raw.sig" 2>/dev/null
+openssl pkeyutl -encrypt -pubin -inkey rsa_public.pem -pkeyopt rsa_padding_mode:oaep \
+    -pkeyopt rsa_oaep_md:sha256 -pkeyopt rsa_mgf1_md:sha256 -in "$work/oaep" -out "$work/oaep.ct"
+openssl dgst -sha256 -sign rsa_private.pem -sigopt rsa_padding_mode:pss \
+    -sigopt rsa_pss_saltlen:32 -sigopt rsa_mgf1_md:sha256 -out "$work/pss.sig" "$work/pss"
+openssl dgst -sha3-256 -sign rsa_private.pem -sigopt rsa_padding_mode:pss \
+    -sigopt rsa_pss_saltlen:32 -sigopt rsa_mgf1_md:sha3-256 -out "$work/pss_sha3.sig" "$work/pss"
+
+{
+    echo "n = $(openssl rsa -in rsa_private.pem -modulus -noout | cut -d= -f2 | tr 'A-F' 'a-f')"
+    echo "e = 010001"
+    echo "d = $(text_field rsa_private.pem privateExponent)"
+    echo "p = $(text_field rsa_private.pem prime1)"
+    echo "q = $(text_field rsa_private.pem prime2)"
+    echo "dp = $(text_field rsa_private.pem exponent1)"
+    echo "dq = $(text_field rsa_private.pem exponent2)"
+    echo "qp = $(text_field rsa_private.pem coefficient)"
+    echo "raw_message = $(hex < "$work/raw")"
+    echo "raw_signature = $(hex < "$work/raw.sig")"
+    echo "oaep_message = $(hex < "$work/oaep")"
+    echo "oaep_ciphertext = $(hex < "$work/oaep.ct")"
+    echo "pss_message = $(hex < "$work/pss")"
+    echo "pss_signature = $(hex < "$work/pss.sig")"
+    echo "pss_sha3_signature = $(hex < "$work/pss_sha3.sig")"
+} > rsa.txt
+
+# ECDSA over P-256 with a SHA3-256 digest, DER encoded
+openssl genpkey -algorithm EC -pkeyopt ec_paramgen_curve:P-256 -out ec_p256_private.pem
+printf 'signed by openssl' > "$work/ecdsa"
+openssl dgst -sha3-256 -sign ec_p256_private.pem -out "$work/ecdsa.sig" "$work/ecdsa"
+
+{
+    echo "private = $(text_field ec_p256_private.pem priv)"
+    echo "public = $(text_field ec_p256_private.pem pub)"
+    echo "message = $(hex < "$work/ecdsa")"
+    echo "signature = $(hex < "$work/ecdsa.sig")"
+} > ecdsa_p256.txt
+
+# X25519: two key pairs per triple and the secret they agree on
+: > x25519.txt
+for triple in 1 2; do
+    openssl genpkey -algorithm X25519 -out "$work/a.pem"
+    openssl genpkey -algorithm X25519 -out "$work/b.pem"
+    openssl pkey -in "$work/b.pem" -pubout -out "$work/b.pub"
+    openssl pkeyutl -derive -inkey "$work/a.pem" -peerkey "$work/b.pub" -out "$work/shared"
+
+    {
+        echo "private_a_$triple = $(text_field "$work/a.pem" priv)"
+        echo "public_a_$triple = $(text_field "$work/a.pem" pub)"
+        echo "private_b_$triple = $(text_field "$work/b.pem" priv)"
+        echo "public_b_$triple = $(text_field "$work/b.pem" pub)"
+        echo "shared_$triple = $(hex < "$work/shared")"
+    } >> x25519.txt
+done
+
+# AES-256-GCM with a 96 bit nonce, 128 bit tag and associated data.
+# `openssl enc` refuses AEAD ciphers and there is no other command line
+# way to GCM-encrypt, so these go through Python's cryptography package,
+# which calls the same OpenSSL EVP_aes_256_gcm as the other fixtures
+python3 - > aes256gcm.txt <<'PYTHON'
+import os
+from cryptography.hazmat.primitives.ciphers.aead import AESGCM
+
+cases = [(b"header", b"encrypted by openssl"), (b"", b"no associated data, but a plaintext spanning two blocks")]
+
+for index, (aad, plaintext) in enumerate(cases, 1):
+    key, nonce = os.urandom(32), os.urandom(12)
+    sealed = AESGCM(key).encrypt(nonce, plaintext, aad)
+
+    for name, value in [("key", key), ("nonce", nonce), ("aad", aad), ("plaintext", plaintext),
+                        ("ciphertext", sealed[:-16]), ("tag", sealed[-16:])]:
+        print("%s_%d = %s" % (name, index, value.hex()))
+PYTHON
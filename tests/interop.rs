@@ -0,0 +1,220 @@
+//! Checks the crate against values produced by OpenSSL. The fixtures in
+//! `tests/fixtures/interop` were generated once by the `generate.sh`
+//! script next to them and are committed, so every run checks the same
+//! bytes. The RSA public key and the PKCS#1 private key are also read
+//! from the PEM OpenSSL wrote, and the AES-256-GCM cases are opened in
+//! the nonce, ciphertext and tag layout the crate seals in. Only built
+//! when every module it covers is enabled
+
+#![cfg(all(feature = "rsa", feature = "ecdsa", feature = "aes", feature = "hashes-sha2"))]
+
+use std::collections::HashMap;
+use crypto::aes::KeySize;
+use num_bigint::BigUint;
+
+use cryptofun::hash::crypto::{ hash_message, HashAlgorithm };
+use cryptofun::encryption::{ AsymmetricKeyMode, CipherError };
+use cryptofun::encryption::aes::{ AES, AESMode };
+use cryptofun::encryption::rsa::{ RSA, RsaPublicKey, RsaVerifyContext, RsaError };
+use cryptofun::key_exchange::x25519::{ x25519, X25519_LENGTH, X25519_BASE_POINT };
+use cryptofun::signature::ecdsa::{ ECDSASignature, EcdsaVerifyContext, SignatureError };
+use cryptofun::utils::rng::os_rng;
+use cryptofun::utils::ecc::{ ECPKeypair, ECPPublicKey, ValidationLevel, encode_point };
+use cryptofun::utils::ecc_curves::ECPSupportedCurves;
+use cryptofun::utils::encoding::hex_to_bytes;
+
+/// Reads the `name = hex` lines of a fixture file
+fn fixture(contents: &str) -> HashMap<String, Vec<u8>> {
+    contents.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut parts = line.splitn(2, '=');
+            let name = parts.next().unwrap().trim().to_string();
+            let value = hex_to_bytes(parts.next().unwrap().trim()).unwrap();
+
+            (name, value)
+        })
+        .collect()
+}
+
+fn x25519_array(bytes: &[u8]) -> [u8; X25519_LENGTH] {
+    let mut array = [0; X25519_LENGTH];
+    array.copy_from_slice(bytes);
+    array
+}
+
+#[test]
+fn ecdsa_p256_signature_from_openssl_verifies() {
+    let mut rng = os_rng().unwrap();
+    let values = fixture(include_str!("fixtures/interop/ecdsa_p256.txt"));
+
+    // The public key OpenSSL derived is the one the crate derives
+    let mut keypair = ECPKeypair::new(ECPSupportedCurves::SECP256R1);
+    keypair.d = BigUint::from_bytes_be(&values["private"]);
+    keypair.q = keypair.multiply(&mut rng);
+    assert_eq!(encode_point(&keypair.group, &keypair.q, false).unwrap(), values["public"]);
+
+    let public_key = ECPPublicKey::new(ECPSupportedCurves::SECP256R1, &values["public"]).unwrap();
+    let validated = keypair.group.validate(public_key.point().unwrap(), ValidationLevel::FullSubgroup).unwrap();
+    let context = EcdsaVerifyContext::new(&validated).unwrap();
+    let signature = ECDSASignature::from_der(&values["signature"]).unwrap();
+    let digest = hash_message(&values["message"], HashAlgorithm::Sha3_256);
+
+    assert!(context.verify(&digest, &signature, &mut rng).is_ok());
+
+    let other = hash_message(b"not signed by openssl", HashAlgorithm::Sha3_256);
+    assert_eq!(context.verify(&other, &signature, &mut rng), Err(SignatureError::VerificationFailed));
+}
+
+#[test]
+fn x25519_agrees_with_openssl() {
+    let mut rng = os_rng().unwrap();
+    let values = fixture(include_str!("fixtures/interop/x25519.txt"));
+
+    for triple in 1..3 {
+        let value = |name: &str| x25519_array(&values[&format!("{}_{}", name, triple)]);
+        let (private_a, public_a) = (value("private_a"), value("public_a"));
+        let (private_b, public_b) = (value("private_b"), value("public_b"));
+
+        assert_eq!(x25519(&private_a, &X25519_BASE_POINT, &mut rng).unwrap(), public_a);
+        assert_eq!(x25519(&private_b, &X25519_BASE_POINT, &mut rng).unwrap(), public_b);
+        assert_eq!(x25519(&private_a, &public_b, &mut rng).unwrap(), value("shared"));
+        assert_eq!(x25519(&private_b, &public_a, &mut rng).unwrap(), value("shared"));
+    }
+}
+
+#[test]
+fn rsa_public_key_pem_from_openssl_parses() {
+    let values = fixture(include_str!("fixtures/interop/rsa.txt"));
+    let pem = include_str!("fixtures/interop/rsa_public.pem");
+    let rsa = RSA::new(HashAlgorithm::Sha3_256, false).from_public_pem(pem).unwrap();
+
+    assert_eq!(rsa.export_public_values(), (BigUint::from_bytes_be(&values["n"]), BigUint::from_bytes_be(&values["e"])));
+    assert_eq!(rsa.export_public_pem(), pem);
+}
+
+#[test]
+fn rsa_pkcs1_private_key_pem_from_openssl_parses() {
+    let mut rng = os_rng().unwrap();
+    let values = fixture(include_str!("fixtures/interop/rsa.txt"));
+    let value = |name: &str| BigUint::from_bytes_be(&values[name]);
+    let pem = include_str!("fixtures/interop/rsa_private_pkcs1.pem");
+    let rsa = RSA::new_raw_unsafe(HashAlgorithm::Sha3_256, true).from_pkcs1_pem(pem).unwrap();
+
+    assert_eq!(rsa.export_public_values(), (value("n"), value("e")));
+    assert_eq!(rsa.to_pkcs1_pem().unwrap(), pem);
+
+    // The key signs exactly as OpenSSL did
+    let mut message = value("raw_message").to_bytes_le();
+    assert_eq!(message.pop(), Some(1));
+    let mut signature = values["raw_signature"].clone();
+    signature.reverse();
+
+    assert_eq!(rsa.encrypt(&message, AsymmetricKeyMode::Private, &mut rng).unwrap(), signature);
+
+    // The PKCS#8 file next to it holds the same key under another label
+    let pkcs8 = include_str!("fixtures/interop/rsa_private.pem");
+    assert!(RSA::new(HashAlgorithm::Sha3_256, true).from_pkcs1_pem(pkcs8).is_err());
+}
+
+#[test]
+fn rsa_raw_signature_from_openssl_opens() {
+    let values = fixture(include_str!("fixtures/interop/rsa.txt"));
+    let public_key = RsaPublicKey::new(BigUint::from_bytes_be(&values["n"]), BigUint::from_bytes_be(&values["e"])).unwrap();
+    let context = RsaVerifyContext::new(&public_key);
+
+    // OpenSSL writes big-endian blocks, the crate reads little-endian
+    // chunks with a 0x01 marker on top, which the fixture's block leads with
+    let mut message = BigUint::from_bytes_be(&values["raw_message"]).to_bytes_le();
+    assert_eq!(message.pop(), Some(1));
+    let mut signature = values["raw_signature"].clone();
+    signature.reverse();
+
+    assert!(context.verify(&message, &signature).is_ok());
+
+    signature[0] ^= 1;
+    assert_eq!(context.verify(&message, &signature), Err(RsaError::VerificationFailed));
+}
+
+#[test]
+fn rsa_components_from_openssl_reproduce_its_raw_signature() {
+    let mut rng = os_rng().unwrap();
+    let values = fixture(include_str!("fixtures/interop/rsa.txt"));
+    let value = |name: &str| BigUint::from_bytes_be(&values[name]);
+    let rsa = RSA::from_components(&value("p"), &value("q"), &value("e"), HashAlgorithm::Sha3_256, true).unwrap().with_raw_padding_unsafe();
+
+    assert_eq!(rsa.export_public_values(), (value("n"), value("e")));
+
+    let mut message = value("raw_message").to_bytes_le();
+    assert_eq!(message.pop(), Some(1));
+    let mut signature = values["raw_signature"].clone();
+    signature.reverse();
+
+    assert_eq!(rsa.encrypt(&message, AsymmetricKeyMode::Private, &mut rng).unwrap(), signature);
+}
+
+#[test]
+fn rsa_pss_signature_from_openssl_verifies() {
+    let values = fixture(include_str!("fixtures/interop/rsa.txt"));
+    let public_key = RsaPublicKey::new(BigUint::from_bytes_be(&values["n"]), BigUint::from_bytes_be(&values["e"])).unwrap();
+    let verifier = RSA::from_public_key(HashAlgorithm::Sha3_256, &public_key);
+    let mut signature = values["pss_sha3_signature"].clone();
+
+    assert_eq!(verifier.verify_pss(&values["pss_message"], &signature), Ok(()));
+    assert_eq!(verifier.verify_pss(b"not signed by openssl", &signature), Err(RsaError::VerificationFailed));
+
+    signature[100] ^= 1;
+    assert_eq!(verifier.verify_pss(&values["pss_message"], &signature), Err(RsaError::VerificationFailed));
+}
+
+#[test]
+fn rsa_sha256_pss_signature_from_openssl_verifies() {
+    let values = fixture(include_str!("fixtures/interop/rsa.txt"));
+    let public_key = RsaPublicKey::new(BigUint::from_bytes_be(&values["n"]), BigUint::from_bytes_be(&values["e"])).unwrap();
+    let verifier = RSA::from_public_key(HashAlgorithm::Sha256, &public_key);
+    let mut signature = values["pss_signature"].clone();
+
+    assert_eq!(verifier.verify_pss(&values["pss_message"], &signature), Ok(()));
+    assert_eq!(verifier.verify_pss(b"not signed by openssl", &signature), Err(RsaError::VerificationFailed));
+
+    signature[100] ^= 1;
+    assert_eq!(verifier.verify_pss(&values["pss_message"], &signature), Err(RsaError::VerificationFailed));
+}
+
+#[test]
+fn rsa_oaep_ciphertext_from_openssl_decrypts() {
+    let mut rng = os_rng().unwrap();
+    let values = fixture(include_str!("fixtures/interop/rsa.txt"));
+    let pem = include_str!("fixtures/interop/rsa_private_pkcs1.pem");
+    let rsa = RSA::new(HashAlgorithm::Sha256, true).from_pkcs1_pem(pem).unwrap();
+    let mut ciphertext = values["oaep_ciphertext"].clone();
+
+    assert_eq!(rsa.decrypt(&ciphertext, AsymmetricKeyMode::Private, &mut rng).unwrap(), values["oaep_message"]);
+
+    // The label hash and MGF1 both run over the hash the key was made with
+    let sha3 = RSA::new(HashAlgorithm::Sha3_256, true).from_pkcs1_pem(pem).unwrap();
+    assert!(sha3.decrypt(&ciphertext, AsymmetricKeyMode::Private, &mut rng).is_err());
+
+    ciphertext[10] ^= 1;
+    assert!(rsa.decrypt(&ciphertext, AsymmetricKeyMode::Private, &mut rng).is_err());
+}
+
+#[test]
+fn aes256gcm_ciphertexts_from_openssl_open() {
+    let values = fixture(include_str!("fixtures/interop/aes256gcm.txt"));
+
+    for case in 1..3 {
+        let value = |name: &str| values[&format!("{}_{}", name, case)].clone();
+        let aes = AES::new_with_key(value("key"), vec![], KeySize::KeySize256, AESMode::GCM, Some(value("aad"))).unwrap();
+
+        let mut sealed = value("nonce");
+        sealed.extend(value("ciphertext"));
+        sealed.extend(value("tag"));
+
+        assert_eq!(aes.decrypt(&sealed).unwrap(), value("plaintext"));
+
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        assert_eq!(aes.decrypt(&sealed), Err(CipherError::AuthenticationFailed));
+    }
+}
\ No newline at end of file
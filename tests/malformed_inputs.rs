@@ -0,0 +1,148 @@
+//! Feeds malformed inputs to the public entry points of each module and
+//! checks that errors are returned instead of the process aborting. Only
+//! built when every module it covers is enabled
+
+#![cfg(all(feature = "rsa", feature = "aes", feature = "dh", feature = "ecdsa"))]
+
+use num_bigint::{ BigInt, BigUint };
+use num_traits::{ One, Zero, FromPrimitive };
+
+use cryptofun::Error;
+use cryptofun::hash::crypto::HashAlgorithm;
+use cryptofun::encryption::{ ecies, AsymmetricKeyMode };
+use cryptofun::encryption::rsa::{ RSA, RsaError };
+use cryptofun::key_exchange::ecdh::ECDH;
+use cryptofun::key_exchange::hybrid::{ Hybrid, HybridError };
+use cryptofun::key_exchange::diffie_hellman::{ DiffieHellman, DhError };
+use cryptofun::signature::ecdsa::{ ECDSA, ECDSASignature, SignatureError };
+use cryptofun::utils::{ primes, encoding };
+use cryptofun::utils::rng::os_rng;
+use cryptofun::utils::ecc::{ ECPKeypair, EcError };
+use cryptofun::utils::ecc_curves::ECPSupportedCurves;
+use cryptofun::utils::primes::PrimeError;
+use cryptofun::utils::encoding::EncodingError;
+
+#[test]
+fn rsa_rejects_bad_parameters_and_keys() {
+    let mut rng = os_rng().unwrap();
+    let exponent = BigUint::from_u32(65537).unwrap();
+
+    let short = RSA::new(HashAlgorithm::Sha3_256, false).generate_keypair(8, &exponent, &mut rng);
+    assert_eq!(short, Err(RsaError::InvalidBitLength));
+
+    let weak = RSA::new(HashAlgorithm::Sha3_256, false).allow_insecure_for_testing().generate_keypair(256, &BigUint::one(), &mut rng);
+    assert_eq!(weak, Err(RsaError::BadExponent));
+
+    let tiny_modulus = BigUint::from_u32(15).unwrap();
+    let from_peer = RSA::new(HashAlgorithm::Sha3_256, false).from_public_values(&tiny_modulus, &exponent);
+    assert!(from_peer.is_err());
+
+    let empty = RSA::new(HashAlgorithm::Sha3_256, true);
+    assert!(empty.check_keypair().is_err());
+    assert!(empty.encrypt(&[1u8, 2, 3], AsymmetricKeyMode::Public, &mut rng).is_err());
+    assert!(empty.decrypt(b"\x01\x02\x03", AsymmetricKeyMode::Private, &mut rng).is_err());
+
+    let rsa = RSA::new(HashAlgorithm::Sha3_256, false).allow_insecure_for_testing().generate_keypair(256, &exponent, &mut rng).unwrap();
+    let odd_length = vec![7; rsa.size_n + 1];
+    assert_eq!(rsa.decrypt(&odd_length, AsymmetricKeyMode::Private, &mut rng), Err(RsaError::InvalidCiphertext));
+}
+
+#[test]
+fn diffie_hellman_rejects_bad_peer_values() {
+    let mut rng = os_rng().unwrap();
+    let two = BigUint::one() + BigUint::one();
+
+    let tiny = DiffieHellman::new_from_peer(&two, &two, &two).setup(16, &mut rng);
+    assert!(match tiny { Err(DhError::InvalidModulus) => true, _ => false });
+
+    let no_bits = DiffieHellman::new().setup(0, &mut rng);
+    assert!(match no_bits { Err(DhError::PrimeGeneration(_)) => true, _ => false });
+
+    let mut dh = DiffieHellman::new().setup(16, &mut rng).unwrap();
+    let p = dh.p.clone();
+
+    assert_eq!(dh.generate_shared_key(&mut rng, &BigUint::zero()), Err(DhError::InvalidPeerValue));
+    assert_eq!(dh.generate_shared_key(&mut rng, &p), Err(DhError::InvalidPeerValue));
+
+    let out_of_range = DiffieHellman::new_from_peer(&p, &dh.g, &(&p - BigUint::one())).setup(16, &mut rng);
+    assert!(match out_of_range { Err(DhError::InvalidPeerValue) => true, _ => false });
+}
+
+#[test]
+fn ecdh_rejects_malformed_peer_values() {
+    let mut rng = os_rng().unwrap();
+
+    for curve in vec![ECPSupportedCurves::BP256R1, ECPSupportedCurves::Curve25519] {
+        let mut dh = ECDH::new(curve, &mut rng).unwrap();
+
+        assert_eq!(dh.derive_shared_bytes(&mut rng), Err(EcError::MissingPeerPoint));
+        assert_eq!(dh.generate_shared_key(&mut rng), Err(EcError::MissingPeerPoint));
+        assert!(dh.set_peer_public_key_bytes(&[]).is_err());
+        assert!(dh.set_peer_public_key_bytes(&[4; 7]).is_err());
+        assert!(dh.derive_shared_bytes_for_peer(&[0xff; 3], &mut rng).is_err());
+        assert!(dh.ephemeral_exchange(&[1, 2, 3], &mut rng, b"info", 32).is_err());
+    }
+
+    let mut weierstrass = ECDH::new(ECPSupportedCurves::BP256R1, &mut rng).unwrap();
+    let mut off_curve = weierstrass.public_key_bytes(false);
+    off_curve[10] ^= 1;
+    assert!(weierstrass.set_peer_public_key_bytes(&off_curve).is_err());
+
+    let montgomery = ECDH::new(ECPSupportedCurves::Curve25519, &mut rng).unwrap();
+    assert_eq!(montgomery.derive_shared_bytes_for_peer(&[0; 32], &mut rng), Err(EcError::LowOrderPoint));
+
+    let hybrid = Hybrid::new(ECDH::new(ECPSupportedCurves::Curve25519, &mut rng).unwrap(), weierstrass);
+    assert_eq!(hybrid.derive_key(&[0, 40, 1], &mut rng, b"info", 32), Err(HybridError::MalformedMessage));
+}
+
+#[test]
+fn ecies_rejects_malformed_messages() {
+    let mut rng = os_rng().unwrap();
+    let recipient = ECPKeypair::new(ECPSupportedCurves::BP256R1).setup(&mut rng).unwrap();
+
+    assert!(ecies::decrypt(&recipient, &[], &mut rng).is_err());
+    assert!(ecies::decrypt(&recipient, &[0; 200], &mut rng).is_err());
+}
+
+#[test]
+fn ecdsa_rejects_invalid_signatures() {
+    let mut rng = os_rng().unwrap();
+    let mut ecdsa = ECDSA::new(ECPSupportedCurves::BP256R1, &mut rng).unwrap();
+    let message = b"signed message";
+
+    let zero = ECDSASignature { r: BigInt::zero(), s: BigInt::one() };
+    assert_eq!(ecdsa.verify(message, &zero, &mut rng), Err(SignatureError::InvalidSignature));
+
+    let signature = ecdsa.sign(message, &mut rng).unwrap();
+    assert!(ecdsa.verify(b"another message", &signature, &mut rng).is_err());
+}
+
+#[test]
+fn utils_reject_malformed_inputs() {
+    let mut rng = os_rng().unwrap();
+
+    assert_eq!(primes::generate(&0, &mut rng), Err(PrimeError::InvalidBitLength));
+    assert_eq!(primes::generate(&1, &mut rng), Err(PrimeError::InvalidBitLength));
+    assert_eq!(encoding::from_hex("not hex".to_string()), Err(EncodingError::InvalidHex));
+
+    #[cfg(feature = "encoding")]
+    assert_eq!(encoding::to_plaintext(&BigUint::from_u32(0xff).unwrap()), Err(EncodingError::NotAscii));
+}
+
+#[test]
+fn module_errors_convert_into_crate_error() {
+    fn failing_pipeline() -> Result<Vec<u8>, Error> {
+        let mut rng = os_rng()?;
+        let dh = ECDH::new(ECPSupportedCurves::BP256R1, &mut rng)?;
+
+        Ok(dh.derive_key(&mut rng, b"info", 32)?)
+    }
+
+    match failing_pipeline() {
+        Err(Error::Ec(EcError::MissingPeerPoint)) => (),
+        other => panic!("Unexpected result {:?}", other)
+    }
+
+    let prime: Error = PrimeError::RngFailure.into();
+    assert!(!format!("{}", prime).is_empty());
+}
\ No newline at end of file
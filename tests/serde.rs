@@ -0,0 +1,154 @@
+//! Round-trips the public key and signature types through JSON and
+//! bincode, and checks that tampered values are rejected when read back.
+//! Only built with the serde feature and the algorithms it covers
+
+#![cfg(all(feature = "serde", feature = "rsa", feature = "dh", feature = "ecdsa"))]
+
+use num_bigint::BigUint;
+use num_traits::FromPrimitive;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use cryptofun::hash::crypto::HashAlgorithm;
+use cryptofun::encryption::AsymmetricKeyMode;
+use cryptofun::encryption::rsa::{ RSA, RsaPublicKey };
+use cryptofun::key_exchange::diffie_hellman::{ DiffieHellman, DhPublicParams };
+use cryptofun::signature::ecdsa::{ ECDSA, ECDSASignature };
+use cryptofun::utils::rng::os_rng;
+use cryptofun::utils::ecc::{ ECPKeypair, ECPPublicKey };
+use cryptofun::utils::ecc_curves::ECPSupportedCurves;
+
+fn round_trip<T: Serialize + DeserializeOwned + PartialEq + std::fmt::Debug>(value: &T) {
+    let json = serde_json::to_string(value).unwrap();
+    assert_eq!(&serde_json::from_str::<T>(&json).unwrap(), value);
+
+    let binary = bincode::serialize(value).unwrap();
+    assert_eq!(&bincode::deserialize::<T>(&binary).unwrap(), value);
+}
+
+#[test]
+fn rsa_public_key_round_trips() {
+    let mut rng = os_rng().unwrap();
+    let exponent = BigUint::from_u32(65537).unwrap();
+    let rsa = RSA::new_raw_unsafe(HashAlgorithm::Sha3_256, false).allow_insecure_for_testing().generate_keypair(256, &exponent, &mut rng).unwrap();
+    let public_key = rsa.public_key().unwrap();
+
+    round_trip(&public_key);
+
+    let json = serde_json::to_string(&public_key).unwrap();
+    let peer = RSA::from_public_key(HashAlgorithm::Sha3_256, &serde_json::from_str(&json).unwrap()).allow_insecure_for_testing().with_raw_padding_unsafe();
+    let message = [1u8, 2, 3];
+    let ciphertext = peer.encrypt(&message, AsymmetricKeyMode::Public, &mut rng).unwrap();
+
+    assert_eq!(rsa.decrypt(&ciphertext, AsymmetricKeyMode::Private, &mut rng).unwrap(), message);
+}
+
+#[test]
+fn tampered_rsa_public_key_is_rejected() {
+    let small_modulus = r#"{"n":"7f","e":"3"}"#;
+    let large_exponent = r#"{"n":"10001","e":"10001"}"#;
+
+    assert!(serde_json::from_str::<RsaPublicKey>(small_modulus).is_err());
+    assert!(serde_json::from_str::<RsaPublicKey>(large_exponent).is_err());
+    assert!(serde_json::from_str::<RsaPublicKey>(r#"{"n":"10001","e":"3"}"#).is_ok());
+}
+
+#[test]
+fn diffie_hellman_params_round_trip() {
+    let mut rng = os_rng().unwrap();
+    let dh = DiffieHellman::new().setup(32, &mut rng).unwrap();
+    let params = dh.public_params().unwrap();
+
+    round_trip(&params);
+
+    let binary = bincode::serialize(&params).unwrap();
+    let peer = DiffieHellman::new_from_params(&bincode::deserialize(&binary).unwrap()).setup(32, &mut rng).unwrap();
+
+    assert_eq!(peer.p, dh.p);
+}
+
+#[test]
+fn tampered_diffie_hellman_params_are_rejected() {
+    let mut rng = os_rng().unwrap();
+    let params = DiffieHellman::new().setup(32, &mut rng).unwrap().public_params().unwrap();
+    let p = params.p().to_str_radix(16);
+    let g = params.g().to_str_radix(16);
+
+    let low_gx = bincode::serialize(&(&p, &g, "1")).unwrap();
+    assert!(bincode::deserialize::<DhPublicParams>(&low_gx).is_err());
+
+    let high_gx = format!(r#"{{"p":"{}","g":"{}","gx":"{}"}}"#, p, g, p);
+    assert!(serde_json::from_str::<DhPublicParams>(&high_gx).is_err());
+
+    assert!(serde_json::from_str::<DhPublicParams>(r#"{"p":"3","g":"2","gx":"2"}"#).is_err());
+}
+
+#[test]
+fn ecc_public_key_round_trips() {
+    let mut rng = os_rng().unwrap();
+
+    for curve in vec![ECPSupportedCurves::BP256R1, ECPSupportedCurves::Curve25519] {
+        let keypair = ECPKeypair::new(curve.clone()).setup(&mut rng).unwrap();
+        let public_key = keypair.public_key().unwrap();
+
+        round_trip(&public_key);
+        assert_eq!(public_key.curve(), &curve);
+        assert_eq!(public_key.point().unwrap().x, keypair.q.x);
+    }
+}
+
+#[test]
+fn tampered_ecc_public_key_is_rejected() {
+    let mut rng = os_rng().unwrap();
+    let public_key = ECPKeypair::new(ECPSupportedCurves::BP256R1).setup(&mut rng).unwrap().public_key().unwrap();
+    let mut off_curve = public_key.point_bytes().to_vec();
+    off_curve[10] ^= 1;
+
+    let tampered = bincode::serialize(&(ECPSupportedCurves::BP256R1, hex(&off_curve))).unwrap();
+    assert!(bincode::deserialize::<ECPPublicKey>(&tampered).is_err());
+
+    let wrong_curve = serde_json::to_string(&public_key).unwrap().replace("BP256R1", "SECP256R1");
+    assert!(serde_json::from_str::<ECPPublicKey>(&wrong_curve).is_err());
+}
+
+#[test]
+fn ecdsa_signature_round_trips() {
+    let mut rng = os_rng().unwrap();
+    let mut ecdsa = ECDSA::new(ECPSupportedCurves::BP256R1, &mut rng).unwrap();
+    let message = b"signed message";
+    let signature = ecdsa.sign(message, &mut rng).unwrap();
+
+    round_trip(&signature);
+    round_trip(&ecdsa.public_key().unwrap());
+
+    let json = serde_json::to_string(&signature).unwrap();
+    assert!(ecdsa.verify(message, &serde_json::from_str(&json).unwrap(), &mut rng).is_ok());
+}
+
+#[test]
+fn tampered_ecdsa_signature_is_rejected() {
+    assert!(serde_json::from_str::<ECDSASignature>(r#"{"r":"0","s":"1"}"#).is_err());
+    assert!(serde_json::from_str::<ECDSASignature>(r#"{"r":"1","s":"-1"}"#).is_err());
+    assert!(serde_json::from_str::<ECDSASignature>(r#"{"r":"1","s":"1"}"#).is_ok());
+}
+
+#[cfg(feature = "aes")]
+#[test]
+fn rsa_envelope_round_trips() {
+    use cryptofun::encryption::envelope::{ self, Envelope };
+
+    let mut rng = os_rng().unwrap();
+    let exponent = BigUint::from_u32(65537).unwrap();
+    let rsa = RSA::new(HashAlgorithm::Sha3_256, true).allow_insecure_for_testing().generate_keypair(768, &exponent, &mut rng).unwrap();
+    let sealed = envelope::seal(&rsa, b"sealed payload", &mut rng).unwrap();
+
+    round_trip(&sealed);
+
+    let json = serde_json::to_string(&sealed).unwrap();
+    let read_back: Envelope = serde_json::from_str(&json).unwrap();
+    assert_eq!(envelope::open(&rsa, &read_back, &mut rng).unwrap(), b"sealed payload".to_vec());
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
\ No newline at end of file
@@ -0,0 +1,118 @@
+//! Exercises the explicit `clear` methods and the drop-time clearing of
+//! every type holding secret values. Only built with the zeroize feature
+//! and the algorithms it covers
+
+#![cfg(all(feature = "zeroize", feature = "rsa", feature = "aes", feature = "dh", feature = "ecdsa"))]
+
+use num_bigint::BigUint;
+use num_traits::{ FromPrimitive, Zero };
+use crypto::aes::KeySize;
+
+use cryptofun::hash::crypto::HashAlgorithm;
+use cryptofun::hash::kdf::hkdf;
+use cryptofun::encryption::AsymmetricKeyMode;
+use cryptofun::encryption::aes::{ AES, AESMode, AesError };
+use cryptofun::encryption::rsa::{ RSA, RsaError };
+use cryptofun::key_exchange::ecdh::ECDH;
+use cryptofun::key_exchange::diffie_hellman::{ DiffieHellman, DhError };
+use cryptofun::signature::ecdsa::{ ECDSA, SignatureError };
+use cryptofun::utils::rng::os_rng;
+use cryptofun::utils::ecc::{ ECPKeypair, EcError };
+use cryptofun::utils::ecc_curves::ECPSupportedCurves;
+
+#[test]
+fn rsa_clear_keeps_public_half() {
+    let mut rng = os_rng().unwrap();
+    let exponent = BigUint::from_u32(65537).unwrap();
+    let mut rsa = RSA::new_raw_unsafe(HashAlgorithm::Sha3_256, true).allow_insecure_for_testing().generate_keypair(256, &exponent, &mut rng).unwrap();
+    let public_values = rsa.export_public_values();
+
+    rsa.clear();
+
+    assert_eq!(rsa.export_public_values(), public_values);
+    assert!(rsa.encrypt(b"\x01\x02\x03", AsymmetricKeyMode::Public, &mut rng).is_ok());
+
+    match rsa.encrypt(b"\x01\x02\x03", AsymmetricKeyMode::Private, &mut rng) {
+        Err(RsaError::InvalidKey(_)) => (),
+        other => panic!("Expected a missing private key, got {:?}", other)
+    }
+
+    drop(RSA::new(HashAlgorithm::Sha3_256, false).allow_insecure_for_testing().generate_keypair(256, &exponent, &mut rng).unwrap());
+}
+
+#[test]
+fn aes_clear_disables_cipher() {
+    let mut rng = os_rng().unwrap();
+    let mut aes = AES::new(KeySize::KeySize256, AESMode::Counter, None, &mut rng);
+    let ciphertext = aes.encrypt(b"message").unwrap();
+
+    aes.clear();
+
+    assert!(aes.key().is_empty());
+    assert!(match aes.encrypt(b"message") { Err(AesError::InvalidLength) => true, _ => false });
+    assert!(match aes.decrypt(&ciphertext) { Err(AesError::InvalidLength) => true, _ => false });
+
+    drop(AES::new(KeySize::KeySize128, AESMode::GCM, None, &mut rng));
+}
+
+#[test]
+fn diffie_hellman_clear_keeps_parameters() {
+    let mut rng = os_rng().unwrap();
+    let mut dh = DiffieHellman::new().setup(32, &mut rng).unwrap();
+    let peer = DiffieHellman::new_from_peer(&dh.p, &dh.g, &dh.gx).setup(32, &mut rng).unwrap();
+    let (p, g, gx) = (dh.p.clone(), dh.g.clone(), dh.gx.clone());
+
+    dh.generate_shared_key(&mut rng, &peer.gx).unwrap();
+    dh.clear();
+
+    assert_eq!((&dh.p, &dh.g, &dh.gx), (&p, &g, &gx));
+    assert!(dh.shared_key.is_zero());
+    assert_eq!(dh.generate_shared_key(&mut rng, &peer.gx), Err(DhError::KeyCleared));
+
+    drop(peer);
+}
+
+#[test]
+fn ecc_clear_keeps_public_value() {
+    let mut rng = os_rng().unwrap();
+
+    for curve in vec![ECPSupportedCurves::BP256R1, ECPSupportedCurves::Curve25519] {
+        let mut keypair = ECPKeypair::new(curve.clone()).setup(&mut rng).unwrap();
+        let q = keypair.q.clone();
+
+        keypair.clear();
+
+        assert!(keypair.d.is_zero());
+        assert_eq!((&keypair.q.x, &keypair.q.y), (&q.x, &q.y));
+
+        let mut dh = ECDH::new(curve.clone(), &mut rng).unwrap();
+        let peer = ECDH::new(curve, &mut rng).unwrap();
+        let public_bytes = dh.public_key_bytes(false);
+
+        dh.clear();
+
+        assert_eq!(dh.public_key_bytes(false), public_bytes);
+        assert_eq!(dh.derive_shared_bytes_for_peer(&peer.public_key_bytes(false), &mut rng), Err(EcError::KeyCleared));
+    }
+}
+
+#[test]
+fn ecdsa_clear_keeps_verification() {
+    let mut rng = os_rng().unwrap();
+    let mut ecdsa = ECDSA::new(ECPSupportedCurves::BP256R1, &mut rng).unwrap();
+    let message = b"signed message";
+    let signature = ecdsa.sign(message, &mut rng).unwrap();
+
+    ecdsa.clear();
+
+    assert!(ecdsa.verify(message, &signature, &mut rng).is_ok());
+    assert!(match ecdsa.sign(message, &mut rng) { Err(SignatureError::KeyCleared) => true, _ => false });
+}
+
+#[test]
+fn kdf_output_is_unaffected_by_wiping() {
+    let short = hkdf(b"input", b"salt", b"info", 20, HashAlgorithm::Sha3_256).unwrap();
+    let long = hkdf(b"input", b"salt", b"info", 70, HashAlgorithm::Sha3_256).unwrap();
+
+    assert_eq!(short[..], long[..20]);
+}
\ No newline at end of file